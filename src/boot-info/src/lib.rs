@@ -0,0 +1,394 @@
+//! ブート情報（BootInfo）
+//! ブートローダーからカーネルへ渡す情報の構造体を定義する
+//!
+//! # なぜ独立したクレートなのか
+//! UEFIブートローダーとカーネルは別々のバイナリとしてビルドされる。
+//! 両者が同じメモリレイアウトで構造体を解釈できるように、
+//! 定義を1か所にまとめて両方から参照する。
+//!
+//! # MikanOSとの比較
+//! MikanOSでは `MemoryMap` と `FrameBufferConfig` を別々の引数で
+//! `KernelMain()` に渡していた。ここでは1つの `BootInfo` にまとめ、
+//! `_start(boot_info: &'static BootInfo)` の形で渡す。
+
+// テスト時は標準ライブラリを使用
+#![cfg_attr(not(test), no_std)]
+
+use core::marker::PhantomData;
+
+/// BootInfoが正しく渡されたか確認するためのマジックナンバー（"LEARNOS\0"）
+pub const BOOT_INFO_MAGIC: u64 = 0x004f_534e_5241_454c;
+
+/// 物理メモリ全体をマップする仮想アドレスのオフセット
+/// 物理アドレス `p` は仮想アドレス `PHYSICAL_MEMORY_OFFSET + p` でアクセスできる
+/// （PML4のエントリ256から始まる、いわゆる上位半分の先頭）
+pub const PHYSICAL_MEMORY_OFFSET: u64 = 0xffff_8000_0000_0000;
+
+/// カーネルがリンクされる仮想アドレス（-2GiB、code-model=kernel）
+/// `kernel/linker.ld` の値と一致させること
+pub const KERNEL_BASE: u64 = 0xffff_ffff_8000_0000;
+
+/// ブートローダーとカーネルの間で受け渡すスライス
+///
+/// `&[T]` はRustのABIに依存するため、`repr(C)` の (ポインタ, 長さ) で表現する
+#[derive(Debug)]
+#[repr(C)]
+pub struct FfiSlice<T> {
+    ptr: *const T,
+    len: usize,
+    _marker: PhantomData<T>,
+}
+
+// ポインタと長さだけなので、T が Copy でなくてもコピーできる
+impl<T> Clone for FfiSlice<T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T> Copy for FfiSlice<T> {}
+
+impl<T> FfiSlice<T> {
+    /// 空のスライスを作成
+    pub const fn empty() -> Self {
+        Self {
+            ptr: core::ptr::null(),
+            len: 0,
+            _marker: PhantomData,
+        }
+    }
+
+    /// 生ポインタと長さから作成
+    ///
+    /// # Safety
+    /// `ptr` から `len` 個の `T` が、カーネルが参照する間ずっと有効であること
+    pub const unsafe fn from_raw_parts(ptr: *const T, len: usize) -> Self {
+        Self {
+            ptr,
+            len,
+            _marker: PhantomData,
+        }
+    }
+
+    /// スライスとして取得
+    pub fn as_slice(&self) -> &[T] {
+        if self.ptr.is_null() || self.len == 0 {
+            &[]
+        } else {
+            // 安全性: from_raw_parts の契約で有効性が保証されている
+            unsafe { core::slice::from_raw_parts(self.ptr, self.len) }
+        }
+    }
+
+    /// 要素数
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// 空かどうか
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+}
+
+// 安全性: ブート後の FfiSlice は読み取り専用として扱う
+unsafe impl<T: Sync> Sync for FfiSlice<T> {}
+
+/// メモリ領域の種類
+/// UEFIの EFI_MEMORY_TYPE を、カーネルが必要とする粒度にまとめたもの
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u32)]
+pub enum MemoryRegionKind {
+    /// カーネルが自由に使える
+    Usable = 1,
+    /// ファームウェアやデバイスが使用中
+    Reserved = 2,
+    /// ACPIテーブル（読み終えたら解放可能）
+    AcpiReclaimable = 3,
+    /// ACPI NVS（解放不可）
+    AcpiNvs = 4,
+    /// 故障しているメモリ
+    Unusable = 5,
+    /// カーネルイメージ（ELFのPT_LOADセグメント）
+    Kernel = 6,
+    /// ブートローダーが確保したデータ（BootInfo、ページテーブル、初期スタック）
+    Bootloader = 7,
+}
+
+/// 物理メモリ領域 `[start, end)`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(C)]
+pub struct MemoryRegion {
+    /// 開始物理アドレス
+    pub start: u64,
+    /// 終了物理アドレス（この値は含まない）
+    pub end: u64,
+    /// 種類
+    pub kind: MemoryRegionKind,
+}
+
+impl MemoryRegion {
+    /// 新しいメモリ領域を作成
+    pub const fn new(start: u64, end: u64, kind: MemoryRegionKind) -> Self {
+        Self { start, end, kind }
+    }
+
+    /// 領域のサイズ（バイト）
+    pub fn len(&self) -> u64 {
+        self.end.saturating_sub(self.start)
+    }
+
+    /// 空の領域かどうか
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// フレームバッファのピクセル形式
+/// MikanOS: PixelFormat (kPixelRGBResv8BitPerColor / kPixelBGRResv8BitPerColor)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u32)]
+pub enum PixelFormat {
+    /// 1ピクセル4バイト: R, G, B, 予約
+    Rgb = 0,
+    /// 1ピクセル4バイト: B, G, R, 予約
+    Bgr = 1,
+    /// 上記以外（ビットマスク形式など、現時点では未対応）
+    Unknown = 2,
+}
+
+/// フレームバッファ情報（UEFI GOPから取得）
+#[derive(Debug, Clone, Copy)]
+#[repr(C)]
+pub struct FrameBufferInfo {
+    /// フレームバッファの物理アドレス（0ならフレームバッファなし）
+    pub base: u64,
+    /// フレームバッファのサイズ（バイト）
+    pub size: u64,
+    /// 横方向の画素数
+    pub width: u32,
+    /// 縦方向の画素数
+    pub height: u32,
+    /// 1行あたりの画素数（width以上）
+    pub stride: u32,
+    /// ピクセル形式
+    pub format: PixelFormat,
+}
+
+impl FrameBufferInfo {
+    /// フレームバッファなしを表す値
+    pub const fn none() -> Self {
+        Self {
+            base: 0,
+            size: 0,
+            width: 0,
+            height: 0,
+            stride: 0,
+            format: PixelFormat::Unknown,
+        }
+    }
+}
+
+/// ブートローダーが一緒に読み込んだファイル（initrdなど）
+/// Multiboot2のモジュールタグに相当
+#[derive(Debug, Clone, Copy)]
+#[repr(C)]
+pub struct BootModule {
+    /// 開始物理アドレス
+    pub start: u64,
+    /// 終了物理アドレス（この値は含まない）
+    pub end: u64,
+    /// モジュールの名前（コマンドライン文字列）
+    pub name: FfiSlice<u8>,
+}
+
+impl BootModule {
+    /// モジュールのサイズ（バイト）
+    pub fn len(&self) -> u64 {
+        self.end.saturating_sub(self.start)
+    }
+
+    /// 空のモジュールかどうか
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// 名前を文字列として取得（UTF-8でなければ空文字列）
+    pub fn name(&self) -> &str {
+        core::str::from_utf8(self.name.as_slice()).unwrap_or("")
+    }
+}
+
+/// ブートローダーからカーネルへ渡す情報
+///
+/// ブートローダーはこの構造体を物理メモリ上に置き、
+/// `PHYSICAL_MEMORY_OFFSET` 経由の仮想アドレスをカーネルに渡す
+#[derive(Debug)]
+#[repr(C)]
+pub struct BootInfo {
+    /// BOOT_INFO_MAGIC（カーネル側で正しく渡されたか確認する）
+    pub magic: u64,
+    /// 物理メモリマップ（開始アドレス順）
+    pub memory_regions: FfiSlice<MemoryRegion>,
+    /// フレームバッファ情報
+    pub framebuffer: FrameBufferInfo,
+    /// ACPI RSDPの物理アドレス（0なら見つからなかった）
+    pub rsdp_addr: u64,
+    /// 物理メモリ全体をマップした仮想アドレスのオフセット
+    pub physical_memory_offset: u64,
+    /// カーネルイメージの物理アドレス
+    pub kernel_addr: u64,
+    /// カーネルイメージのサイズ（バイト）
+    pub kernel_size: u64,
+    /// カーネルコマンドライン（NUL終端なし）
+    pub cmdline: FfiSlice<u8>,
+    /// 一緒に読み込まれたモジュール
+    pub modules: FfiSlice<BootModule>,
+}
+
+impl BootInfo {
+    /// 空のBootInfoを作成
+    pub const fn new() -> Self {
+        Self {
+            magic: BOOT_INFO_MAGIC,
+            memory_regions: FfiSlice::empty(),
+            framebuffer: FrameBufferInfo::none(),
+            rsdp_addr: 0,
+            physical_memory_offset: PHYSICAL_MEMORY_OFFSET,
+            kernel_addr: 0,
+            kernel_size: 0,
+            cmdline: FfiSlice::empty(),
+            modules: FfiSlice::empty(),
+        }
+    }
+
+    /// マジックナンバーが正しいか
+    pub fn is_valid(&self) -> bool {
+        self.magic == BOOT_INFO_MAGIC
+    }
+
+    /// メモリマップを取得
+    pub fn memory_regions(&self) -> &[MemoryRegion] {
+        self.memory_regions.as_slice()
+    }
+
+    /// フレームバッファを取得（なければNone）
+    pub fn framebuffer(&self) -> Option<&FrameBufferInfo> {
+        if self.framebuffer.base == 0 {
+            None
+        } else {
+            Some(&self.framebuffer)
+        }
+    }
+
+    /// ACPI RSDPの物理アドレスを取得（なければNone）
+    pub fn rsdp_addr(&self) -> Option<u64> {
+        if self.rsdp_addr == 0 {
+            None
+        } else {
+            Some(self.rsdp_addr)
+        }
+    }
+
+    /// コマンドラインを取得（UTF-8でなければ空文字列）
+    pub fn cmdline(&self) -> &str {
+        core::str::from_utf8(self.cmdline.as_slice()).unwrap_or("")
+    }
+
+    /// モジュールの一覧を取得
+    pub fn modules(&self) -> &[BootModule] {
+        self.modules.as_slice()
+    }
+
+    /// 物理アドレスを仮想アドレスに変換
+    pub fn phys_to_virt(&self, phys: u64) -> u64 {
+        self.physical_memory_offset + phys
+    }
+}
+
+impl Default for BootInfo {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// ===== テスト =====
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// FfiSliceのテスト
+    mod ffi_slice_tests {
+        use super::*;
+
+        #[test]
+        fn test_empty_slice() {
+            let slice: FfiSlice<u8> = FfiSlice::empty();
+            assert!(slice.is_empty(), "空のスライスであるべき");
+            assert_eq!(slice.as_slice(), &[] as &[u8], "空のスライスは[]を返すべき");
+        }
+
+        #[test]
+        fn test_roundtrip() {
+            static DATA: [u32; 3] = [1, 2, 3];
+            let slice = unsafe { FfiSlice::from_raw_parts(DATA.as_ptr(), DATA.len()) };
+            assert_eq!(slice.len(), 3, "長さは3であるべき");
+            assert_eq!(slice.as_slice(), &DATA, "元のデータと一致するべき");
+        }
+    }
+
+    /// BootInfoのテスト
+    mod boot_info_tests {
+        use super::*;
+
+        #[test]
+        fn test_new_boot_info_is_valid() {
+            let info = BootInfo::new();
+            assert!(info.is_valid(), "マジックナンバーが設定されているべき");
+            assert!(info.memory_regions().is_empty(), "メモリマップは空であるべき");
+        }
+
+        #[test]
+        fn test_missing_framebuffer_and_rsdp() {
+            let info = BootInfo::new();
+            assert!(info.framebuffer().is_none(), "base=0はフレームバッファなし");
+            assert!(info.rsdp_addr().is_none(), "rsdp_addr=0はRSDPなし");
+        }
+
+        #[test]
+        fn test_phys_to_virt() {
+            let info = BootInfo::new();
+            assert_eq!(
+                info.phys_to_virt(0xb8000),
+                PHYSICAL_MEMORY_OFFSET + 0xb8000,
+                "物理メモリオフセットが加算されるべき"
+            );
+        }
+
+        #[test]
+        fn test_cmdline_and_modules() {
+            static CMDLINE: &[u8] = b"loglevel=debug";
+            static NAME: &[u8] = b"initrd";
+            let mut info = BootInfo::new();
+            assert_eq!(info.cmdline(), "", "初期状態のコマンドラインは空");
+
+            info.cmdline = unsafe { FfiSlice::from_raw_parts(CMDLINE.as_ptr(), CMDLINE.len()) };
+            assert_eq!(info.cmdline(), "loglevel=debug");
+
+            let module = BootModule {
+                start: 0x10_0000,
+                end: 0x10_4000,
+                name: unsafe { FfiSlice::from_raw_parts(NAME.as_ptr(), NAME.len()) },
+            };
+            assert_eq!(module.len(), 0x4000, "モジュールのサイズ");
+            assert_eq!(module.name(), "initrd", "モジュール名");
+        }
+
+        #[test]
+        fn test_memory_region_len() {
+            let region = MemoryRegion::new(0x1000, 0x3000, MemoryRegionKind::Usable);
+            assert_eq!(region.len(), 0x2000, "サイズは0x2000であるべき");
+            assert!(!region.is_empty(), "空ではないべき");
+        }
+    }
+}
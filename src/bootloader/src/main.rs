@@ -0,0 +1,562 @@
+//! Learning OS UEFIブートローダー
+//!
+//! UEFIアプリケーションとして起動し、次の順にカーネルを起動する:
+//! 1. ESP（EFI System Partition）から `\kernel.elf` と（あれば）`\initrd` を読み込む
+//! 2. PT_LOADセグメントを物理メモリにコピーする
+//! 3. GOPのフレームバッファとACPI RSDPを探す
+//! 4. 初期ページテーブルを作る（恒等マップ・物理メモリマップ・カーネル）
+//! 5. メモリマップを取得してブートサービスを終了する
+//! 6. CR3を切り替えて `_start(boot_info)` へジャンプする
+//!
+//! MikanOS: MikanLoaderPkg/Main.c の UefiMain() に相当
+
+// テスト時は標準ライブラリを使用
+#![cfg_attr(not(test), no_std)]
+#![cfg_attr(not(test), no_main)]
+// テスト時はUEFIから呼ばれる起動処理を除外するので、そこからしか使わない項目が未使用になる
+#![cfg_attr(test, allow(dead_code))]
+
+mod memory_map;
+mod paging;
+mod uefi;
+
+#[cfg(not(test))]
+use boot_info::{BootInfo, BootModule, FfiSlice, FrameBufferInfo, MemoryRegion, PixelFormat, KERNEL_BASE, PHYSICAL_MEMORY_OFFSET};
+#[cfg(not(test))]
+use core::convert::Infallible;
+#[cfg(not(test))]
+use core::ffi::c_void;
+#[cfg(not(test))]
+use core::fmt::Write;
+#[cfg(not(test))]
+use core::panic::PanicInfo;
+#[cfg(not(test))]
+use core::sync::atomic::{AtomicBool, AtomicPtr, Ordering};
+#[cfg(not(test))]
+use elf64::{ElfFile, PF_W, PF_X};
+#[cfg(not(test))]
+use paging::{flags, FrameAllocator, PageTable, PageTableBuilder, PAGE_SIZE_2M, PAGE_SIZE_4K};
+#[cfg(not(test))]
+use uefi::{
+    memory_type, AllocateType, BootServices, Console, FileProtocol, GraphicsOutput, Handle,
+    LoadedImage, SimpleFileSystem, Status, SystemTable,
+};
+
+/// ESP上のカーネルのパス
+#[cfg(not(test))]
+const KERNEL_PATH: &str = "\\kernel.elf";
+
+/// ESP上の初期RAMディスクのパス（なくても起動する）
+#[cfg(not(test))]
+const INITRD_PATH: &str = "\\initrd";
+
+/// カーネルに渡すinitrdのモジュール名
+#[cfg(not(test))]
+const INITRD_NAME: &str = "initrd";
+
+/// カーネルの初期スタックのページ数（64KiB）
+#[cfg(not(test))]
+const KERNEL_STACK_PAGES: usize = 16;
+
+/// 最低限マップする物理メモリの大きさ
+/// メモリマップに載らないMMIO（Local APICなど）も4GiB未満にあるため
+#[cfg(not(test))]
+const MIN_PHYSICAL_MAP_SIZE: u64 = 4 << 30;
+
+/// UEFIのシステムテーブル（コンソール出力用）
+#[cfg(not(test))]
+static SYSTEM_TABLE: AtomicPtr<SystemTable> = AtomicPtr::new(core::ptr::null_mut());
+
+/// ブートサービスがまだ使えるか
+#[cfg(not(test))]
+static BOOT_SERVICES_ACTIVE: AtomicBool = AtomicBool::new(false);
+
+/// UEFIのコンソールに出力する（ブートサービス終了後は何もしない）
+#[cfg(not(test))]
+fn console_print(args: core::fmt::Arguments) {
+    if !BOOT_SERVICES_ACTIVE.load(Ordering::Relaxed) {
+        return;
+    }
+    let st = SYSTEM_TABLE.load(Ordering::Relaxed);
+    if st.is_null() {
+        return;
+    }
+    // 安全性: ブートサービス中はシステムテーブルとConOutが有効
+    let mut console = Console(unsafe { (*st).con_out });
+    let _ = console.write_fmt(args);
+}
+
+#[cfg(not(test))]
+macro_rules! println {
+    ($($arg:tt)*) => {
+        $crate::console_print(format_args!("{}\n", format_args!($($arg)*)))
+    };
+}
+
+/// パニックハンドラ
+/// ブートサービス中ならメッセージを表示して停止する
+#[cfg(not(test))]
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    println!("bootloader panic: {}", info);
+    loop {
+        unsafe { core::arch::asm!("hlt") };
+    }
+}
+
+/// UEFIアプリケーションのエントリポイント
+#[cfg(not(test))]
+#[no_mangle]
+pub extern "efiapi" fn efi_main(image: Handle, system_table: *mut SystemTable) -> Status {
+    SYSTEM_TABLE.store(system_table, Ordering::Relaxed);
+    BOOT_SERVICES_ACTIVE.store(true, Ordering::Relaxed);
+
+    match boot(image, system_table) {
+        Ok(never) => match never {},
+        Err(status) => {
+            println!("failed to boot kernel: {:?}", status);
+            status
+        }
+    }
+}
+
+/// カーネルを読み込んで起動する（成功すれば戻らない）
+#[cfg(not(test))]
+fn boot(image: Handle, system_table: *mut SystemTable) -> Result<Infallible, Status> {
+    // 安全性: ファームウェアから渡されたシステムテーブルは有効
+    let st = unsafe { &*system_table };
+    let bs = unsafe { &*st.boot_services };
+
+    // ウォッチドッグタイマー（5分でリセット）を止める
+    unsafe { (bs.set_watchdog_timer)(0, 0, 0, core::ptr::null()) };
+    println!("Learning OS bootloader");
+
+    // 1. カーネルファイルを読み込む
+    let kernel_file = read_file(bs, image, KERNEL_PATH)?;
+    let elf = ElfFile::parse(kernel_file).map_err(|err| {
+        println!("invalid kernel ELF: {:?}", err);
+        Status::LOAD_ERROR
+    })?;
+
+    // initrdはカーネルが最初のモジュールとして読む（置かれていなければ渡さない）
+    let initrd = read_file(bs, image, INITRD_PATH).ok();
+    if let Some(initrd) = initrd {
+        println!("initrd: phys={:#x} size={:#x}", initrd.as_ptr() as u64, initrd.len());
+    }
+
+    // 2. PT_LOADセグメントをコピー
+    let kernel = load_kernel(bs, &elf)?;
+    println!(
+        "kernel: phys={:#x} virt={:#x} size={:#x} entry={:#x}",
+        kernel.phys_base, kernel.virt_base, kernel.size, elf.entry
+    );
+
+    // 3. フレームバッファとRSDP、コマンドライン
+    let framebuffer = find_framebuffer(bs);
+    let rsdp_addr = find_rsdp(st);
+    let (cmdline_phys, cmdline_len) = read_cmdline(bs, image)?;
+
+    // 4. ページテーブル
+    let (map_size, descriptor_size) = memory_map_size(bs)?;
+    let map_buffer_size = map_size + 8 * descriptor_size;
+    let map_buffer = allocate_pool(bs, map_buffer_size)?;
+    let (_, map_len, _) = get_memory_map(bs, map_buffer, map_buffer_size)?;
+    let mut phys_end = memory_map::MemoryMapIter::new(
+        unsafe { core::slice::from_raw_parts(map_buffer, map_len) },
+        descriptor_size,
+    )
+    .map(|desc| desc.physical_start + desc.number_of_pages * uefi::PAGE_SIZE)
+    .max()
+    .unwrap_or(0)
+    .max(MIN_PHYSICAL_MAP_SIZE)
+    .max(framebuffer.base + framebuffer.size);
+    phys_end = (phys_end + PAGE_SIZE_2M - 1) & !(PAGE_SIZE_2M - 1);
+
+    let mut frames = UefiFrameAllocator { bs };
+    let pml4 = build_page_tables(&mut frames, &elf, &kernel, phys_end).map_err(|err| {
+        println!("failed to build page tables: {:?}", err);
+        Status::LOAD_ERROR
+    })?;
+
+    // 初期スタック
+    let stack_phys = allocate_pages(bs, memory_type::BOOT_DATA, KERNEL_STACK_PAGES)?;
+    let stack_top = PHYSICAL_MEMORY_OFFSET + stack_phys + (KERNEL_STACK_PAGES as u64) * PAGE_SIZE_4K;
+
+    // BootInfoとメモリマップ、モジュールの一覧（とその名前）の置き場所
+    // ここで確保した後にもメモリマップは少し増えるので余裕を持たせる
+    let (map_size, descriptor_size) = memory_map_size(bs)?;
+    let region_capacity = map_size / descriptor_size + 32;
+    let regions_offset = core::mem::size_of::<BootInfo>().next_multiple_of(16);
+    let modules_offset =
+        (regions_offset + region_capacity * core::mem::size_of::<MemoryRegion>()).next_multiple_of(16);
+    let boot_info_size = modules_offset + core::mem::size_of::<BootModule>() + INITRD_NAME.len();
+    let boot_info_pages = boot_info_size.div_ceil(PAGE_SIZE_4K as usize);
+    let boot_info_phys = allocate_pages(bs, memory_type::BOOT_DATA, boot_info_pages)?;
+
+    let map_buffer_size = map_size + 8 * descriptor_size;
+    let map_buffer = allocate_pool(bs, map_buffer_size)?;
+    let regions_phys = boot_info_phys + regions_offset as u64;
+    let regions = unsafe {
+        core::slice::from_raw_parts_mut(regions_phys as *mut MemoryRegion, region_capacity)
+    };
+
+    // ブートサービスを終えると表示できないので、今のマップが入りきるかをここで確かめておく
+    let (_, map_len, _) = get_memory_map(bs, map_buffer, map_buffer_size)?;
+    let map = unsafe { core::slice::from_raw_parts(map_buffer, map_len) };
+    let (_, dropped) = memory_map::convert(memory_map::MemoryMapIter::new(map, descriptor_size), regions);
+    if dropped > 0 {
+        println!("memory map too large: {} regions do not fit in {}", dropped, region_capacity);
+        return Err(Status::BUFFER_TOO_SMALL);
+    }
+
+    // 5. ブートサービスを終了
+    println!("exiting boot services");
+    let map_len = exit_boot_services(bs, image, map_buffer, map_buffer_size)?;
+    BOOT_SERVICES_ACTIVE.store(false, Ordering::Relaxed);
+
+    // ここから先はUEFIの機能を使えない
+    let map = unsafe { core::slice::from_raw_parts(map_buffer, map_len) };
+    // 確かめたあとに増えるのは数個なので、隣どうしの結合と region_capacity の余裕に収まる
+    let (region_count, _) = memory_map::convert(
+        memory_map::MemoryMapIter::new(map, descriptor_size),
+        regions,
+    );
+
+    let mut boot_info = BootInfo::new();
+    // カーネルからは物理メモリマップ経由で見える
+    boot_info.memory_regions = unsafe {
+        FfiSlice::from_raw_parts(
+            (PHYSICAL_MEMORY_OFFSET + regions_phys) as *const MemoryRegion,
+            region_count,
+        )
+    };
+    boot_info.framebuffer = framebuffer;
+    boot_info.rsdp_addr = rsdp_addr;
+    boot_info.kernel_addr = kernel.phys_base;
+    boot_info.kernel_size = kernel.size;
+    boot_info.cmdline = unsafe {
+        FfiSlice::from_raw_parts((PHYSICAL_MEMORY_OFFSET + cmdline_phys) as *const u8, cmdline_len)
+    };
+    if let Some(initrd) = initrd {
+        // 読み込んだバッファはUEFIの恒等マップの上にあったので、アドレスがそのまま物理アドレス
+        let modules_phys = boot_info_phys + modules_offset as u64;
+        let name_phys = modules_phys + core::mem::size_of::<BootModule>() as u64;
+        unsafe {
+            core::ptr::copy_nonoverlapping(INITRD_NAME.as_ptr(), name_phys as *mut u8, INITRD_NAME.len());
+            (modules_phys as *mut BootModule).write(BootModule {
+                start: initrd.as_ptr() as u64,
+                end: initrd.as_ptr() as u64 + initrd.len() as u64,
+                name: FfiSlice::from_raw_parts((PHYSICAL_MEMORY_OFFSET + name_phys) as *const u8, INITRD_NAME.len()),
+            });
+            boot_info.modules =
+                FfiSlice::from_raw_parts((PHYSICAL_MEMORY_OFFSET + modules_phys) as *const BootModule, 1);
+        }
+    }
+    unsafe { (boot_info_phys as *mut BootInfo).write(boot_info) };
+
+    // 6. カーネルへ
+    unsafe {
+        jump_to_kernel(pml4, stack_top, elf.entry, PHYSICAL_MEMORY_OFFSET + boot_info_phys)
+    }
+}
+
+/// 物理メモリ上に読み込んだカーネルの位置
+#[cfg(not(test))]
+struct LoadedKernel {
+    /// コピー先の物理アドレス
+    phys_base: u64,
+    /// 対応する仮想アドレス（ページ境界に切り下げたもの）
+    virt_base: u64,
+    /// 占有するバイト数（ページ単位）
+    size: u64,
+}
+
+/// PT_LOADセグメント全体を連続した物理ページにコピーする
+/// KERNEL_BASE より下のセグメント（Multiboot用のトランポリン）は読み込まない
+/// MikanOS: CopyLoadSegments()
+#[cfg(not(test))]
+fn load_kernel(bs: &BootServices, elf: &ElfFile) -> Result<LoadedKernel, Status> {
+    let (start, end) = elf.load_address_range(KERNEL_BASE).ok_or(Status::LOAD_ERROR)?;
+    let virt_base = start & !(PAGE_SIZE_4K - 1);
+    let size = (end - virt_base).next_multiple_of(PAGE_SIZE_4K);
+    let phys_base = allocate_pages(bs, memory_type::KERNEL, (size / PAGE_SIZE_4K) as usize)?;
+
+    // .bss のためにゼロクリアしてからコピー
+    unsafe { core::ptr::write_bytes(phys_base as *mut u8, 0, size as usize) };
+    for ph in elf.load_segments_above(KERNEL_BASE) {
+        let data = elf.segment_data(&ph).map_err(|_| Status::LOAD_ERROR)?;
+        let dest = (phys_base + (ph.p_vaddr - virt_base)) as *mut u8;
+        unsafe { core::ptr::copy_nonoverlapping(data.as_ptr(), dest, data.len()) };
+    }
+
+    Ok(LoadedKernel { phys_base, virt_base, size })
+}
+
+/// ページテーブル用フレームをUEFIから確保する
+#[cfg(not(test))]
+struct UefiFrameAllocator<'a> {
+    bs: &'a BootServices,
+}
+
+#[cfg(not(test))]
+impl FrameAllocator for UefiFrameAllocator<'_> {
+    fn allocate_frame(&mut self) -> Option<u64> {
+        let phys = allocate_pages(self.bs, memory_type::BOOT_DATA, 1).ok()?;
+        unsafe { core::ptr::write_bytes(phys as *mut u8, 0, PAGE_SIZE_4K as usize) };
+        Some(phys)
+    }
+
+    fn table_ptr(&mut self, phys: u64) -> *mut PageTable {
+        // ブートローダー実行中はUEFIが恒等マップしている
+        phys as *mut PageTable
+    }
+}
+
+/// カーネル用のページテーブルを作り、PML4の物理アドレスを返す
+#[cfg(not(test))]
+fn build_page_tables(
+    frames: &mut UefiFrameAllocator,
+    elf: &ElfFile,
+    kernel: &LoadedKernel,
+    phys_end: u64,
+) -> Result<u64, paging::MapError> {
+    let mut builder = PageTableBuilder::new(frames)?;
+
+    // 恒等マップ（CR3切り替え直後の命令を実行するため）
+    builder.map_physical_range(0, phys_end, flags::WRITABLE)?;
+    // 物理メモリ全体
+    builder.map_physical_range(PHYSICAL_MEMORY_OFFSET, phys_end, flags::WRITABLE | flags::NO_EXECUTE)?;
+
+    // カーネルのセグメント（書き込み・実行権限はELFのフラグに従う）
+    for ph in elf.load_segments_above(KERNEL_BASE) {
+        let mut page_flags = 0;
+        if ph.p_flags & PF_W != 0 {
+            page_flags |= flags::WRITABLE;
+        }
+        if ph.p_flags & PF_X == 0 {
+            page_flags |= flags::NO_EXECUTE;
+        }
+        let first = ph.p_vaddr & !(PAGE_SIZE_4K - 1);
+        let last = ph.p_vaddr + ph.p_memsz;
+        let mut virt = first;
+        while virt < last {
+            let phys = kernel.phys_base + (virt - kernel.virt_base);
+            builder.map_4k(virt, phys, page_flags | flags::GLOBAL)?;
+            virt += PAGE_SIZE_4K;
+        }
+    }
+
+    Ok(builder.pml4())
+}
+
+/// ページを確保する
+#[cfg(not(test))]
+fn allocate_pages(bs: &BootServices, ty: u32, pages: usize) -> Result<u64, Status> {
+    let mut addr = 0u64;
+    unsafe { (bs.allocate_pages)(AllocateType::AnyPages, ty, pages, &mut addr) }.to_result()?;
+    Ok(addr)
+}
+
+/// プールからメモリを確保する（LoaderData）
+#[cfg(not(test))]
+fn allocate_pool(bs: &BootServices, size: usize) -> Result<*mut u8, Status> {
+    let mut buffer = core::ptr::null_mut();
+    unsafe { (bs.allocate_pool)(memory_type::LOADER_DATA, size, &mut buffer) }.to_result()?;
+    Ok(buffer)
+}
+
+/// 現在のメモリマップに必要なバッファサイズと要素サイズを取得する
+#[cfg(not(test))]
+fn memory_map_size(bs: &BootServices) -> Result<(usize, usize), Status> {
+    let mut size = 0;
+    let mut key = 0;
+    let mut descriptor_size = 0;
+    let mut version = 0;
+    let status = unsafe {
+        (bs.get_memory_map)(&mut size, core::ptr::null_mut(), &mut key, &mut descriptor_size, &mut version)
+    };
+    if status != Status::BUFFER_TOO_SMALL {
+        status.to_result()?;
+    }
+    Ok((size, descriptor_size))
+}
+
+/// メモリマップを取得し、(map_key, 書き込まれたバイト数, descriptor_size) を返す
+#[cfg(not(test))]
+fn get_memory_map(bs: &BootServices, buffer: *mut u8, capacity: usize) -> Result<(usize, usize, usize), Status> {
+    let mut size = capacity;
+    let mut key = 0;
+    let mut descriptor_size = 0;
+    let mut version = 0;
+    unsafe {
+        (bs.get_memory_map)(&mut size, buffer.cast(), &mut key, &mut descriptor_size, &mut version)
+    }
+    .to_result()?;
+    Ok((key, size, descriptor_size))
+}
+
+/// 最新のメモリマップを取得してブートサービスを終了する
+/// 返り値はメモリマップのバイト数
+#[cfg(not(test))]
+fn exit_boot_services(bs: &BootServices, image: Handle, buffer: *mut u8, capacity: usize) -> Result<usize, Status> {
+    // GetMemoryMap と ExitBootServices の間にマップが変わると失敗するので1回だけ再試行する
+    let mut last_error = Status::LOAD_ERROR;
+    for _ in 0..2 {
+        let (key, size, _) = get_memory_map(bs, buffer, capacity)?;
+        let status = unsafe { (bs.exit_boot_services)(image, key) };
+        if !status.is_error() {
+            return Ok(size);
+        }
+        last_error = status;
+    }
+    Err(last_error)
+}
+
+/// ロードオプションをカーネルのコマンドラインとして1ページにコピーする
+/// 返り値は (物理アドレス, バイト数)
+#[cfg(not(test))]
+fn read_cmdline(bs: &BootServices, image: Handle) -> Result<(u64, usize), Status> {
+    let mut loaded_image: *mut c_void = core::ptr::null_mut();
+    unsafe { (bs.handle_protocol)(image, &uefi::LOADED_IMAGE_GUID, &mut loaded_image) }.to_result()?;
+    let loaded_image = unsafe { &*(loaded_image as *const LoadedImage) };
+
+    let phys = allocate_pages(bs, memory_type::BOOT_DATA, 1)?;
+    let buf = unsafe { core::slice::from_raw_parts_mut(phys as *mut u8, PAGE_SIZE_4K as usize) };
+    let len = if loaded_image.load_options.is_null() {
+        0
+    } else {
+        let options = unsafe {
+            core::slice::from_raw_parts(
+                loaded_image.load_options as *const u16,
+                loaded_image.load_options_size as usize / 2,
+            )
+        };
+        uefi::decode_load_options(options, buf)
+    };
+    if len > 0 {
+        println!("cmdline: {}", core::str::from_utf8(&buf[..len]).unwrap_or(""));
+    }
+    Ok((phys, len))
+}
+
+/// ブートデバイスのファイルを読み込む
+#[cfg(not(test))]
+fn read_file(bs: &BootServices, image: Handle, path: &str) -> Result<&'static [u8], Status> {
+    let mut loaded_image: *mut c_void = core::ptr::null_mut();
+    unsafe { (bs.handle_protocol)(image, &uefi::LOADED_IMAGE_GUID, &mut loaded_image) }.to_result()?;
+    let device = unsafe { (*(loaded_image as *mut LoadedImage)).device_handle };
+
+    let mut fs: *mut c_void = core::ptr::null_mut();
+    unsafe { (bs.handle_protocol)(device, &uefi::SIMPLE_FILE_SYSTEM_GUID, &mut fs) }.to_result()?;
+    let fs = fs as *mut SimpleFileSystem;
+
+    let mut root: *mut FileProtocol = core::ptr::null_mut();
+    unsafe { ((*fs).open_volume)(fs, &mut root) }.to_result()?;
+
+    let mut name = [0u16; 64];
+    uefi::encode_ucs2(path, &mut name).ok_or(Status::LOAD_ERROR)?;
+    let mut file: *mut FileProtocol = core::ptr::null_mut();
+    let status = unsafe { ((*root).open)(root, &mut file, name.as_ptr(), uefi::FILE_MODE_READ, 0) };
+    if status.is_error() {
+        println!("{} not found", path);
+        return Err(status);
+    }
+
+    // 末尾にシークしてファイルサイズを得る
+    let mut size = 0u64;
+    unsafe {
+        ((*file).set_position)(file, u64::MAX).to_result()?;
+        ((*file).get_position)(file, &mut size).to_result()?;
+        ((*file).set_position)(file, 0).to_result()?;
+    }
+
+    let buffer = allocate_pool(bs, size as usize)?;
+    let mut read = 0usize;
+    while read < size as usize {
+        let mut chunk = size as usize - read;
+        unsafe { ((*file).read)(file, &mut chunk, buffer.add(read).cast()) }.to_result()?;
+        if chunk == 0 {
+            break;
+        }
+        read += chunk;
+    }
+    unsafe {
+        ((*file).close)(file);
+        ((*root).close)(root);
+    }
+    Ok(unsafe { core::slice::from_raw_parts(buffer, read) })
+}
+
+/// GOPからフレームバッファ情報を取得する
+#[cfg(not(test))]
+fn find_framebuffer(bs: &BootServices) -> FrameBufferInfo {
+    let mut gop: *mut c_void = core::ptr::null_mut();
+    let status = unsafe {
+        (bs.locate_protocol)(&uefi::GRAPHICS_OUTPUT_GUID, core::ptr::null_mut(), &mut gop)
+    };
+    if status.is_error() || gop.is_null() {
+        println!("GOP not found");
+        return FrameBufferInfo::none();
+    }
+
+    let mode = unsafe { &*(*(gop as *mut GraphicsOutput)).mode };
+    let info = unsafe { &*mode.info };
+    let format = match info.pixel_format {
+        uefi::pixel_format::RGB_RESERVED_8BIT => PixelFormat::Rgb,
+        uefi::pixel_format::BGR_RESERVED_8BIT => PixelFormat::Bgr,
+        _ => PixelFormat::Unknown,
+    };
+    FrameBufferInfo {
+        base: mode.frame_buffer_base,
+        size: mode.frame_buffer_size as u64,
+        width: info.horizontal_resolution,
+        height: info.vertical_resolution,
+        stride: info.pixels_per_scan_line,
+        format,
+    }
+}
+
+/// 構成テーブルからACPI RSDPを探す（2.0を優先）
+#[cfg(not(test))]
+fn find_rsdp(st: &SystemTable) -> u64 {
+    let tables = st.configuration_tables();
+    tables
+        .iter()
+        .find(|t| t.vendor_guid == uefi::ACPI_20_TABLE_GUID)
+        .or_else(|| tables.iter().find(|t| t.vendor_guid == uefi::ACPI_10_TABLE_GUID))
+        .map(|t| t.vendor_table as u64)
+        .unwrap_or(0)
+}
+
+/// ページテーブルを切り替えてカーネルのエントリポイントへジャンプする
+///
+/// カーネルの `_start` はSystem V ABI（`extern "C"`）なので、第1引数はRDIで渡す。
+/// UEFIアプリ自身はMicrosoft ABIである点に注意
+#[cfg(not(test))]
+unsafe fn jump_to_kernel(pml4: u64, stack_top: u64, entry: u64, boot_info: u64) -> ! {
+    const IA32_EFER: u32 = 0xc000_0080;
+    const EFER_NXE: u64 = 1 << 11;
+
+    core::arch::asm!(
+        // ファームウェアの割り込みハンドラはもう使えない
+        "cli",
+        // NXビットを有効化（カーネルのデータページはNO_EXECUTE）
+        "mov ecx, {efer}",
+        "rdmsr",
+        "or eax, {nxe}",
+        "wrmsr",
+        "mov cr3, r8",
+        "mov rsp, r9",
+        // ダミーのリターンアドレス（関数入口のスタック境界を合わせる）
+        "push 0",
+        "jmp r10",
+        efer = const IA32_EFER,
+        nxe = const EFER_NXE,
+        // rdmsr/wrmsr が使う rax, rcx, rdx と重ならないレジスタで渡す
+        in("r8") pml4,
+        in("r9") stack_top,
+        in("r10") entry,
+        in("rdi") boot_info,
+        options(noreturn)
+    );
+}
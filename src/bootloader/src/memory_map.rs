@@ -0,0 +1,213 @@
+//! UEFIメモリマップからBootInfoのメモリマップへの変換
+//!
+//! UEFIは細かいメモリ種別（EFI_MEMORY_TYPE）を返すが、
+//! カーネルが知りたいのは「使えるか」「何に使われているか」だけなので
+//! MemoryRegionKind にまとめ、隣接する同種の領域を結合する。
+
+use boot_info::{MemoryRegion, MemoryRegionKind};
+
+use crate::uefi::{memory_type, MemoryDescriptor, PAGE_SIZE};
+
+/// GetMemoryMapが返すバッファを走査するイテレータ
+/// 要素サイズは `size_of::<MemoryDescriptor>()` ではなく descriptor_size を使う
+pub struct MemoryMapIter<'a> {
+    buffer: &'a [u8],
+    descriptor_size: usize,
+    offset: usize,
+}
+
+impl<'a> MemoryMapIter<'a> {
+    pub fn new(buffer: &'a [u8], descriptor_size: usize) -> Self {
+        Self { buffer, descriptor_size, offset: 0 }
+    }
+}
+
+impl Iterator for MemoryMapIter<'_> {
+    type Item = MemoryDescriptor;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let size = core::mem::size_of::<MemoryDescriptor>();
+        if self.descriptor_size < size || self.offset + size > self.buffer.len() {
+            return None;
+        }
+        let ptr = self.buffer[self.offset..].as_ptr() as *const MemoryDescriptor;
+        self.offset += self.descriptor_size;
+        // 安全性: 範囲チェック済み。アラインされている保証はないので read_unaligned
+        Some(unsafe { ptr.read_unaligned() })
+    }
+}
+
+/// EFI_MEMORY_TYPE を MemoryRegionKind に変換する
+pub fn region_kind(ty: u32) -> MemoryRegionKind {
+    match ty {
+        // ブートサービス終了後は自由に使える
+        memory_type::CONVENTIONAL
+        | memory_type::BOOT_SERVICES_CODE
+        | memory_type::BOOT_SERVICES_DATA => MemoryRegionKind::Usable,
+        // ローダー自身とローダーが確保したデータ
+        memory_type::LOADER_CODE
+        | memory_type::LOADER_DATA
+        | memory_type::BOOT_DATA => MemoryRegionKind::Bootloader,
+        memory_type::KERNEL => MemoryRegionKind::Kernel,
+        memory_type::ACPI_RECLAIM => MemoryRegionKind::AcpiReclaimable,
+        memory_type::ACPI_NVS => MemoryRegionKind::AcpiNvs,
+        memory_type::UNUSABLE => MemoryRegionKind::Unusable,
+        // ランタイムサービス、MMIOなどは触らない
+        _ => MemoryRegionKind::Reserved,
+    }
+}
+
+/// UEFIメモリマップを変換して `out` に書き込む
+///
+/// 開始アドレス順に並べ、隣接する同種の領域は1つにまとめる。
+/// 返り値は (書き込んだ要素数, 入りきらずに捨てた領域の数)。
+/// `out` が埋まったら、まず隣どうしを結合して空きを作る。
+pub fn convert<I>(descriptors: I, out: &mut [MemoryRegion]) -> (usize, usize)
+where
+    I: Iterator<Item = MemoryDescriptor>,
+{
+    let mut len = 0;
+    let mut dropped = 0;
+    for desc in descriptors {
+        if desc.number_of_pages == 0 {
+            continue;
+        }
+        let region = MemoryRegion::new(
+            desc.physical_start,
+            desc.physical_start + desc.number_of_pages * PAGE_SIZE,
+            region_kind(desc.ty),
+        );
+        if len == out.len() {
+            len = merge_adjacent(&mut out[..len]);
+        }
+        if len == out.len() {
+            dropped += 1;
+            continue;
+        }
+        // 挿入ソート（要素数は高々数百なので十分）
+        let mut i = len;
+        while i > 0 && out[i - 1].start > region.start {
+            out[i] = out[i - 1];
+            i -= 1;
+        }
+        out[i] = region;
+        len += 1;
+    }
+    (merge_adjacent(&mut out[..len]), dropped)
+}
+
+/// ソート済みの領域列の隣接する同種領域を結合し、新しい要素数を返す
+fn merge_adjacent(regions: &mut [MemoryRegion]) -> usize {
+    if regions.is_empty() {
+        return 0;
+    }
+    let mut write = 0;
+    for read in 1..regions.len() {
+        let current = regions[read];
+        let last = &mut regions[write];
+        if last.kind == current.kind && last.end == current.start {
+            last.end = current.end;
+        } else {
+            write += 1;
+            regions[write] = current;
+        }
+    }
+    write + 1
+}
+
+// ===== テスト =====
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn desc(ty: u32, start: u64, pages: u64) -> MemoryDescriptor {
+        MemoryDescriptor {
+            ty,
+            physical_start: start,
+            virtual_start: 0,
+            number_of_pages: pages,
+            attribute: 0,
+        }
+    }
+
+    fn empty_regions() -> [MemoryRegion; 8] {
+        [MemoryRegion::new(0, 0, MemoryRegionKind::Reserved); 8]
+    }
+
+    #[test]
+    fn test_region_kind() {
+        assert_eq!(region_kind(memory_type::CONVENTIONAL), MemoryRegionKind::Usable);
+        assert_eq!(region_kind(memory_type::BOOT_SERVICES_DATA), MemoryRegionKind::Usable);
+        assert_eq!(region_kind(memory_type::KERNEL), MemoryRegionKind::Kernel);
+        assert_eq!(region_kind(memory_type::BOOT_DATA), MemoryRegionKind::Bootloader);
+        assert_eq!(region_kind(memory_type::MMIO), MemoryRegionKind::Reserved);
+        assert_eq!(region_kind(memory_type::ACPI_NVS), MemoryRegionKind::AcpiNvs);
+    }
+
+    #[test]
+    fn test_convert_sorts_and_merges() {
+        // ブートサービス領域と空き領域は両方Usableなので結合される
+        let descs = [
+            desc(memory_type::CONVENTIONAL, 0x3000, 2),
+            desc(memory_type::BOOT_SERVICES_CODE, 0x1000, 2),
+            desc(memory_type::KERNEL, 0x5000, 1),
+        ];
+        let mut out = empty_regions();
+        let (len, dropped) = convert(descs.into_iter(), &mut out);
+
+        assert_eq!((len, dropped), (2, 0), "Usable 1つ + Kernel 1つ");
+        assert_eq!(out[0], MemoryRegion::new(0x1000, 0x5000, MemoryRegionKind::Usable));
+        assert_eq!(out[1], MemoryRegion::new(0x5000, 0x6000, MemoryRegionKind::Kernel));
+    }
+
+    #[test]
+    fn test_convert_keeps_gaps() {
+        // 間が空いている同種の領域は結合しない
+        let descs = [
+            desc(memory_type::CONVENTIONAL, 0x1000, 1),
+            desc(memory_type::CONVENTIONAL, 0x4000, 1),
+        ];
+        let mut out = empty_regions();
+        assert_eq!(convert(descs.into_iter(), &mut out), (2, 0), "隙間があれば別領域");
+    }
+
+    #[test]
+    fn test_convert_skips_empty_descriptors() {
+        let descs = [desc(memory_type::CONVENTIONAL, 0x1000, 0)];
+        let mut out = empty_regions();
+        assert_eq!(convert(descs.into_iter(), &mut out), (0, 0), "0ページの領域は無視");
+    }
+
+    #[test]
+    fn test_convert_counts_dropped_regions() {
+        // 隙間のある領域を10個（結合できない）、入れ物は8個
+        let descs = (0..10).map(|i| desc(memory_type::CONVENTIONAL, 0x1000 + i * 0x2000, 1));
+        let mut out = empty_regions();
+        assert_eq!(convert(descs, &mut out), (8, 2), "入りきらない分を数える");
+
+        // 結合できるなら、埋まっても捨てない
+        let descs = (0..10).map(|i| desc(memory_type::CONVENTIONAL, 0x1000 + i * 0x1000, 1));
+        let mut out = empty_regions();
+        assert_eq!(convert(descs, &mut out), (1, 0));
+        assert_eq!(out[0], MemoryRegion::new(0x1000, 0xb000, MemoryRegionKind::Usable));
+    }
+
+    #[test]
+    fn test_memory_map_iter_uses_descriptor_size() {
+        // ファームウェアは構造体より大きい descriptor_size を返すことがある
+        let descriptor_size = 48;
+        let mut buffer = vec![0u8; descriptor_size * 2];
+        for (i, d) in [desc(memory_type::CONVENTIONAL, 0x1000, 1), desc(memory_type::ACPI_NVS, 0x2000, 1)]
+            .iter()
+            .enumerate()
+        {
+            let ptr = buffer[i * descriptor_size..].as_mut_ptr() as *mut MemoryDescriptor;
+            unsafe { ptr.write_unaligned(*d) };
+        }
+
+        let descs: Vec<_> = MemoryMapIter::new(&buffer, descriptor_size).collect();
+        assert_eq!(descs.len(), 2, "2要素読めるべき");
+        assert_eq!(descs[1].physical_start, 0x2000);
+        assert_eq!(descs[1].ty, memory_type::ACPI_NVS);
+    }
+}
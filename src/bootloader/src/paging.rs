@@ -0,0 +1,259 @@
+//! カーネル起動用の初期ページテーブル作成
+//!
+//! ブートローダーは次の3つをマップした4段ページテーブルを作ってからカーネルに飛ぶ:
+//! 1. 恒等マップ（物理 = 仮想）: CR3を切り替えた直後もローダー自身が動けるように
+//! 2. 物理メモリ全体を `PHYSICAL_MEMORY_OFFSET` から: カーネルが物理メモリに触るため
+//! 3. カーネルのPT_LOADセグメントを `KERNEL_BASE` 付近に: ELFのリンク先アドレス
+//!
+//! MikanOS: カーネル側の SetupIdentityPageTable() で恒等マップだけを作っていた
+
+/// ページテーブルのエントリ数
+pub const ENTRY_COUNT: usize = 512;
+/// 4KiBページ
+pub const PAGE_SIZE_4K: u64 = 0x1000;
+/// 2MiBページ
+pub const PAGE_SIZE_2M: u64 = 0x20_0000;
+
+/// ページテーブルエントリのフラグ
+pub mod flags {
+    pub const PRESENT: u64 = 1 << 0;
+    pub const WRITABLE: u64 = 1 << 1;
+    pub const HUGE_PAGE: u64 = 1 << 7;
+    pub const GLOBAL: u64 = 1 << 8;
+    pub const NO_EXECUTE: u64 = 1 << 63;
+}
+
+/// エントリ中の物理アドレス部分
+const ADDR_MASK: u64 = 0x000f_ffff_ffff_f000;
+
+/// 4KiBアラインされたページテーブル1枚
+#[derive(Clone)]
+#[repr(C, align(4096))]
+pub struct PageTable {
+    pub entries: [u64; ENTRY_COUNT],
+}
+
+/// ページテーブル用の物理フレームを提供する
+pub trait FrameAllocator {
+    /// ゼロクリア済みの4KiBフレームを確保し、その物理アドレスを返す
+    fn allocate_frame(&mut self) -> Option<u64>;
+
+    /// 物理アドレスのページテーブルにアクセスするためのポインタ
+    /// （ローダー内では恒等マップなのでそのまま、テストではVec上の位置）
+    fn table_ptr(&mut self, phys: u64) -> *mut PageTable;
+}
+
+/// ページテーブル作成時のエラー
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MapError {
+    /// フレームが確保できなかった
+    OutOfFrames,
+    /// 既に別の物理アドレスがマップされている
+    AlreadyMapped,
+    /// アドレスがページ境界に揃っていない
+    Misaligned,
+}
+
+/// 仮想アドレスから各段のインデックスを取り出す
+/// [PML4, PDPT, PD, PT]
+pub fn table_indices(virt: u64) -> [usize; 4] {
+    [
+        ((virt >> 39) & 0x1ff) as usize,
+        ((virt >> 30) & 0x1ff) as usize,
+        ((virt >> 21) & 0x1ff) as usize,
+        ((virt >> 12) & 0x1ff) as usize,
+    ]
+}
+
+/// ページテーブルを組み立てる
+pub struct PageTableBuilder<'a, A: FrameAllocator> {
+    allocator: &'a mut A,
+    pml4: u64,
+}
+
+impl<'a, A: FrameAllocator> PageTableBuilder<'a, A> {
+    /// 空のPML4を確保して作成
+    pub fn new(allocator: &'a mut A) -> Result<Self, MapError> {
+        let pml4 = allocator.allocate_frame().ok_or(MapError::OutOfFrames)?;
+        Ok(Self { allocator, pml4 })
+    }
+
+    /// PML4の物理アドレス（CR3に設定する値）
+    pub fn pml4(&self) -> u64 {
+        self.pml4
+    }
+
+    /// `table` の `index` 番目のエントリが指す次段のテーブルを返す（なければ作る）
+    fn next_table(&mut self, table: u64, index: usize) -> Result<u64, MapError> {
+        let entry = unsafe { &mut (*self.allocator.table_ptr(table)).entries[index] };
+        if *entry & flags::PRESENT != 0 {
+            if *entry & flags::HUGE_PAGE != 0 {
+                return Err(MapError::AlreadyMapped);
+            }
+            return Ok(*entry & ADDR_MASK);
+        }
+        let frame = self.allocator.allocate_frame().ok_or(MapError::OutOfFrames)?;
+        // 中間テーブルは緩い権限にしておき、最終段で制限する
+        *entry = frame | flags::PRESENT | flags::WRITABLE;
+        Ok(frame)
+    }
+
+    /// 最終段のエントリを設定する
+    fn set_leaf(&mut self, table: u64, index: usize, value: u64) -> Result<(), MapError> {
+        let entry = unsafe { &mut (*self.allocator.table_ptr(table)).entries[index] };
+        if *entry & flags::PRESENT != 0 && *entry != value {
+            return Err(MapError::AlreadyMapped);
+        }
+        *entry = value;
+        Ok(())
+    }
+
+    /// 4KiBページを1つマップする
+    pub fn map_4k(&mut self, virt: u64, phys: u64, page_flags: u64) -> Result<(), MapError> {
+        if !virt.is_multiple_of(PAGE_SIZE_4K) || !phys.is_multiple_of(PAGE_SIZE_4K) {
+            return Err(MapError::Misaligned);
+        }
+        let [i4, i3, i2, i1] = table_indices(virt);
+        let pdpt = self.next_table(self.pml4, i4)?;
+        let pd = self.next_table(pdpt, i3)?;
+        let pt = self.next_table(pd, i2)?;
+        self.set_leaf(pt, i1, phys | page_flags | flags::PRESENT)
+    }
+
+    /// 2MiBページを1つマップする
+    pub fn map_2m(&mut self, virt: u64, phys: u64, page_flags: u64) -> Result<(), MapError> {
+        if !virt.is_multiple_of(PAGE_SIZE_2M) || !phys.is_multiple_of(PAGE_SIZE_2M) {
+            return Err(MapError::Misaligned);
+        }
+        let [i4, i3, i2, _] = table_indices(virt);
+        let pdpt = self.next_table(self.pml4, i4)?;
+        let pd = self.next_table(pdpt, i3)?;
+        self.set_leaf(pd, i2, phys | page_flags | flags::PRESENT | flags::HUGE_PAGE)
+    }
+
+    /// 物理アドレス `[0, size)` を `virt_base` から2MiBページでマップする
+    pub fn map_physical_range(&mut self, virt_base: u64, size: u64, page_flags: u64) -> Result<(), MapError> {
+        let mut phys = 0;
+        while phys < size {
+            self.map_2m(virt_base + phys, phys, page_flags)?;
+            phys += PAGE_SIZE_2M;
+        }
+        Ok(())
+    }
+
+    /// 仮想アドレスを物理アドレスに変換する（テストでの検証用）
+    #[cfg(test)]
+    pub fn translate(&mut self, virt: u64) -> Option<u64> {
+        let [i4, i3, i2, i1] = table_indices(virt);
+        let mut table = self.pml4;
+        for (level, index) in [i4, i3, i2, i1].into_iter().enumerate() {
+            let entry = unsafe { (*self.allocator.table_ptr(table)).entries[index] };
+            if entry & flags::PRESENT == 0 {
+                return None;
+            }
+            // PD段のHUGE_PAGEは2MiBページ
+            if level == 2 && entry & flags::HUGE_PAGE != 0 {
+                return Some((entry & ADDR_MASK & !(PAGE_SIZE_2M - 1)) + (virt & (PAGE_SIZE_2M - 1)));
+            }
+            table = entry & ADDR_MASK;
+        }
+        Some(table + (virt & (PAGE_SIZE_4K - 1)))
+    }
+}
+
+// ===== テスト =====
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Vec上にページテーブルを置くテスト用アロケータ
+    /// 物理アドレスは 0x1000 * (インデックス + 1) とみなす
+    struct VecAllocator {
+        tables: Vec<Box<PageTable>>,
+        limit: usize,
+    }
+
+    impl VecAllocator {
+        fn new(limit: usize) -> Self {
+            Self { tables: Vec::new(), limit }
+        }
+    }
+
+    impl FrameAllocator for VecAllocator {
+        fn allocate_frame(&mut self) -> Option<u64> {
+            if self.tables.len() == self.limit {
+                return None;
+            }
+            self.tables.push(Box::new(PageTable { entries: [0; ENTRY_COUNT] }));
+            Some(self.tables.len() as u64 * PAGE_SIZE_4K)
+        }
+
+        fn table_ptr(&mut self, phys: u64) -> *mut PageTable {
+            let index = (phys / PAGE_SIZE_4K) as usize - 1;
+            &mut *self.tables[index] as *mut PageTable
+        }
+    }
+
+    #[test]
+    fn test_table_indices() {
+        // -2GiB はPML4=511, PDPT=510
+        assert_eq!(table_indices(0xffff_ffff_8000_0000), [511, 510, 0, 0]);
+        assert_eq!(table_indices(0xffff_8000_0000_0000), [256, 0, 0, 0]);
+    }
+
+    #[test]
+    fn test_map_4k_and_translate() {
+        let mut alloc = VecAllocator::new(16);
+        let mut builder = PageTableBuilder::new(&mut alloc).unwrap();
+        builder.map_4k(0xffff_ffff_8000_1000, 0x20_0000, flags::WRITABLE).unwrap();
+
+        assert_eq!(builder.translate(0xffff_ffff_8000_1234), Some(0x20_0234), "オフセットも保存されるべき");
+        assert_eq!(builder.translate(0xffff_ffff_8000_2000), None, "未マップはNone");
+    }
+
+    #[test]
+    fn test_map_physical_range_with_2m_pages() {
+        let mut alloc = VecAllocator::new(16);
+        let mut builder = PageTableBuilder::new(&mut alloc).unwrap();
+        builder.map_physical_range(0, 0x40_0000, flags::WRITABLE).unwrap();
+        builder.map_physical_range(0xffff_8000_0000_0000, 0x40_0000, flags::WRITABLE).unwrap();
+
+        assert_eq!(builder.translate(0x30_0010), Some(0x30_0010), "恒等マップ");
+        assert_eq!(builder.translate(0xffff_8000_0030_0010), Some(0x30_0010), "物理メモリマップ");
+        assert_eq!(builder.translate(0x40_0000), None, "範囲外は未マップ");
+    }
+
+    #[test]
+    fn test_remap_conflict() {
+        let mut alloc = VecAllocator::new(16);
+        let mut builder = PageTableBuilder::new(&mut alloc).unwrap();
+        builder.map_4k(0x1000, 0x5000, 0).unwrap();
+
+        assert_eq!(builder.map_4k(0x1000, 0x6000, 0), Err(MapError::AlreadyMapped), "別の物理アドレスへの再マップは失敗");
+        assert_eq!(builder.map_4k(0x1000, 0x5000, 0), Ok(()), "同じ内容なら成功");
+    }
+
+    #[test]
+    fn test_4k_inside_2m_page_conflicts() {
+        let mut alloc = VecAllocator::new(16);
+        let mut builder = PageTableBuilder::new(&mut alloc).unwrap();
+        builder.map_2m(0, 0, 0).unwrap();
+
+        assert_eq!(builder.map_4k(0x1000, 0x1000, 0), Err(MapError::AlreadyMapped), "2MiBページの内側は4KiBでマップできない");
+    }
+
+    #[test]
+    fn test_misaligned() {
+        let mut alloc = VecAllocator::new(16);
+        let mut builder = PageTableBuilder::new(&mut alloc).unwrap();
+        assert_eq!(builder.map_2m(0x1000, 0, 0), Err(MapError::Misaligned));
+    }
+
+    #[test]
+    fn test_out_of_frames() {
+        // PML4しか確保できない
+        let mut alloc = VecAllocator::new(1);
+        let mut builder = PageTableBuilder::new(&mut alloc).unwrap();
+        assert_eq!(builder.map_4k(0, 0, 0), Err(MapError::OutOfFrames));
+    }
+}
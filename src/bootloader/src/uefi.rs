@@ -0,0 +1,503 @@
+//! UEFIの最小限のバインディング
+//! UEFI仕様（2.10）から、ブートローダーが使う部分だけを定義する
+//!
+//! # MikanOSとの比較
+//! MikanOSのMikanLoaderはEDK IIのヘッダ（Uefi.h）をそのまま使っていた。
+//! ここでは外部クレートに頼らず、必要な構造体だけを `repr(C)` で書き写す。
+//! 関数ポインタの並び順は仕様書の順番と完全に一致している必要がある。
+
+// 仕様の定義を写しているため、使っていない定数やフィールドもある
+#![allow(dead_code)]
+
+use core::ffi::c_void;
+use core::fmt;
+
+/// EFI_HANDLE
+pub type Handle = *mut c_void;
+
+/// EFI_STATUS
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(transparent)]
+pub struct Status(pub usize);
+
+impl Status {
+    /// EFI_SUCCESS
+    pub const SUCCESS: Status = Status(0);
+    /// エラーコードは最上位ビットが立っている
+    const ERROR_BIT: usize = 1 << (usize::BITS - 1);
+    /// EFI_LOAD_ERROR
+    pub const LOAD_ERROR: Status = Status(Self::ERROR_BIT | 1);
+    /// EFI_UNSUPPORTED
+    pub const UNSUPPORTED: Status = Status(Self::ERROR_BIT | 3);
+    /// EFI_BUFFER_TOO_SMALL
+    pub const BUFFER_TOO_SMALL: Status = Status(Self::ERROR_BIT | 5);
+    /// EFI_NOT_FOUND
+    pub const NOT_FOUND: Status = Status(Self::ERROR_BIT | 14);
+
+    /// エラーかどうか
+    pub fn is_error(self) -> bool {
+        self.0 & Self::ERROR_BIT != 0
+    }
+
+    /// Result に変換
+    pub fn to_result(self) -> Result<(), Status> {
+        if self.is_error() {
+            Err(self)
+        } else {
+            Ok(())
+        }
+    }
+}
+
+/// EFI_GUID
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(C)]
+pub struct Guid {
+    pub data1: u32,
+    pub data2: u16,
+    pub data3: u16,
+    pub data4: [u8; 8],
+}
+
+impl Guid {
+    pub const fn new(data1: u32, data2: u16, data3: u16, data4: [u8; 8]) -> Self {
+        Self { data1, data2, data3, data4 }
+    }
+}
+
+/// EFI_LOADED_IMAGE_PROTOCOL_GUID
+pub const LOADED_IMAGE_GUID: Guid = Guid::new(
+    0x5b1b31a1, 0x9562, 0x11d2,
+    [0x8e, 0x3f, 0x00, 0xa0, 0xc9, 0x69, 0x72, 0x3b],
+);
+
+/// EFI_SIMPLE_FILE_SYSTEM_PROTOCOL_GUID
+pub const SIMPLE_FILE_SYSTEM_GUID: Guid = Guid::new(
+    0x964e5b22, 0x6459, 0x11d2,
+    [0x8e, 0x39, 0x00, 0xa0, 0xc9, 0x69, 0x72, 0x3b],
+);
+
+/// EFI_GRAPHICS_OUTPUT_PROTOCOL_GUID
+pub const GRAPHICS_OUTPUT_GUID: Guid = Guid::new(
+    0x9042a9de, 0x23dc, 0x4a38,
+    [0x96, 0xfb, 0x7a, 0xde, 0xd0, 0x80, 0x51, 0x6a],
+);
+
+/// EFI_ACPI_20_TABLE_GUID（RSDP 2.0以降）
+pub const ACPI_20_TABLE_GUID: Guid = Guid::new(
+    0x8868e871, 0xe4f1, 0x11d3,
+    [0xbc, 0x22, 0x00, 0x80, 0xc7, 0x3c, 0x88, 0x81],
+);
+
+/// ACPI_TABLE_GUID（RSDP 1.0）
+pub const ACPI_10_TABLE_GUID: Guid = Guid::new(
+    0xeb9d2d30, 0x2d88, 0x11d3,
+    [0x9a, 0x16, 0x00, 0x90, 0x27, 0x3f, 0xc1, 0x4d],
+);
+
+/// EFI_TABLE_HEADER
+#[repr(C)]
+pub struct TableHeader {
+    pub signature: u64,
+    pub revision: u32,
+    pub header_size: u32,
+    pub crc32: u32,
+    pub reserved: u32,
+}
+
+/// EFI_SIMPLE_TEXT_OUTPUT_PROTOCOL
+#[repr(C)]
+pub struct SimpleTextOutput {
+    pub reset: unsafe extern "efiapi" fn(*mut SimpleTextOutput, bool) -> Status,
+    pub output_string: unsafe extern "efiapi" fn(*mut SimpleTextOutput, *const u16) -> Status,
+    // 以下は使わないので型を省略
+    test_string: usize,
+    query_mode: usize,
+    set_mode: usize,
+    set_attribute: usize,
+    clear_screen: usize,
+    set_cursor_position: usize,
+    enable_cursor: usize,
+    mode: usize,
+}
+
+/// EFI_CONFIGURATION_TABLE
+#[repr(C)]
+pub struct ConfigurationTable {
+    pub vendor_guid: Guid,
+    pub vendor_table: *mut c_void,
+}
+
+/// EFI_SYSTEM_TABLE
+#[repr(C)]
+pub struct SystemTable {
+    pub hdr: TableHeader,
+    pub firmware_vendor: *const u16,
+    pub firmware_revision: u32,
+    pub console_in_handle: Handle,
+    pub con_in: *mut c_void,
+    pub console_out_handle: Handle,
+    pub con_out: *mut SimpleTextOutput,
+    pub standard_error_handle: Handle,
+    pub std_err: *mut SimpleTextOutput,
+    pub runtime_services: *mut c_void,
+    pub boot_services: *mut BootServices,
+    pub number_of_table_entries: usize,
+    pub configuration_table: *const ConfigurationTable,
+}
+
+impl SystemTable {
+    /// 構成テーブル（ACPIなど）の一覧
+    pub fn configuration_tables(&self) -> &[ConfigurationTable] {
+        if self.configuration_table.is_null() {
+            return &[];
+        }
+        // 安全性: ファームウェアが number_of_table_entries 個の要素を保証する
+        unsafe {
+            core::slice::from_raw_parts(self.configuration_table, self.number_of_table_entries)
+        }
+    }
+}
+
+/// EFI_ALLOCATE_TYPE
+#[derive(Debug, Clone, Copy)]
+#[repr(u32)]
+pub enum AllocateType {
+    AnyPages = 0,
+    MaxAddress = 1,
+    Address = 2,
+}
+
+/// EFI_MEMORY_TYPE（仕様で定義されている値）
+pub mod memory_type {
+    pub const RESERVED: u32 = 0;
+    pub const LOADER_CODE: u32 = 1;
+    pub const LOADER_DATA: u32 = 2;
+    pub const BOOT_SERVICES_CODE: u32 = 3;
+    pub const BOOT_SERVICES_DATA: u32 = 4;
+    pub const RUNTIME_SERVICES_CODE: u32 = 5;
+    pub const RUNTIME_SERVICES_DATA: u32 = 6;
+    pub const CONVENTIONAL: u32 = 7;
+    pub const UNUSABLE: u32 = 8;
+    pub const ACPI_RECLAIM: u32 = 9;
+    pub const ACPI_NVS: u32 = 10;
+    pub const MMIO: u32 = 11;
+    pub const MMIO_PORT_SPACE: u32 = 12;
+    pub const PAL_CODE: u32 = 13;
+    pub const PERSISTENT: u32 = 14;
+
+    /// 0x80000000以上はOSローダーが自由に使える
+    /// カーネルイメージ用
+    pub const KERNEL: u32 = 0x8000_0000;
+    /// BootInfo・ページテーブル・初期スタック用
+    pub const BOOT_DATA: u32 = 0x8000_0001;
+}
+
+/// EFI_MEMORY_DESCRIPTOR
+/// 実際の要素サイズは GetMemoryMap が返す descriptor_size を使うこと
+#[derive(Debug, Clone, Copy)]
+#[repr(C)]
+pub struct MemoryDescriptor {
+    pub ty: u32,
+    pub physical_start: u64,
+    pub virtual_start: u64,
+    pub number_of_pages: u64,
+    pub attribute: u64,
+}
+
+/// UEFIのページサイズ（常に4KiB）
+pub const PAGE_SIZE: u64 = 4096;
+
+/// EFI_BOOT_SERVICES
+#[repr(C)]
+pub struct BootServices {
+    pub hdr: TableHeader,
+    raise_tpl: usize,
+    restore_tpl: usize,
+    pub allocate_pages:
+        unsafe extern "efiapi" fn(AllocateType, u32, usize, *mut u64) -> Status,
+    pub free_pages: unsafe extern "efiapi" fn(u64, usize) -> Status,
+    pub get_memory_map: unsafe extern "efiapi" fn(
+        *mut usize,
+        *mut MemoryDescriptor,
+        *mut usize,
+        *mut usize,
+        *mut u32,
+    ) -> Status,
+    pub allocate_pool: unsafe extern "efiapi" fn(u32, usize, *mut *mut u8) -> Status,
+    pub free_pool: unsafe extern "efiapi" fn(*mut u8) -> Status,
+    create_event: usize,
+    set_timer: usize,
+    wait_for_event: usize,
+    signal_event: usize,
+    close_event: usize,
+    check_event: usize,
+    install_protocol_interface: usize,
+    reinstall_protocol_interface: usize,
+    uninstall_protocol_interface: usize,
+    pub handle_protocol:
+        unsafe extern "efiapi" fn(Handle, *const Guid, *mut *mut c_void) -> Status,
+    reserved: usize,
+    register_protocol_notify: usize,
+    locate_handle: usize,
+    locate_device_path: usize,
+    install_configuration_table: usize,
+    load_image: usize,
+    start_image: usize,
+    exit: usize,
+    unload_image: usize,
+    pub exit_boot_services: unsafe extern "efiapi" fn(Handle, usize) -> Status,
+    get_next_monotonic_count: usize,
+    stall: usize,
+    pub set_watchdog_timer:
+        unsafe extern "efiapi" fn(usize, u64, usize, *const u16) -> Status,
+    connect_controller: usize,
+    disconnect_controller: usize,
+    open_protocol: usize,
+    close_protocol: usize,
+    open_protocol_information: usize,
+    protocols_per_handle: usize,
+    locate_handle_buffer: usize,
+    pub locate_protocol:
+        unsafe extern "efiapi" fn(*const Guid, *mut c_void, *mut *mut c_void) -> Status,
+    install_multiple_protocol_interfaces: usize,
+    uninstall_multiple_protocol_interfaces: usize,
+    calculate_crc32: usize,
+    copy_mem: usize,
+    set_mem: usize,
+    create_event_ex: usize,
+}
+
+/// EFI_LOADED_IMAGE_PROTOCOL
+#[repr(C)]
+pub struct LoadedImage {
+    pub revision: u32,
+    pub parent_handle: Handle,
+    pub system_table: *mut SystemTable,
+    pub device_handle: Handle,
+    pub file_path: *mut c_void,
+    reserved: *mut c_void,
+    pub load_options_size: u32,
+    pub load_options: *mut c_void,
+    pub image_base: *mut c_void,
+    pub image_size: u64,
+    pub image_code_type: u32,
+    pub image_data_type: u32,
+    unload: usize,
+}
+
+/// EFI_SIMPLE_FILE_SYSTEM_PROTOCOL
+#[repr(C)]
+pub struct SimpleFileSystem {
+    pub revision: u64,
+    pub open_volume:
+        unsafe extern "efiapi" fn(*mut SimpleFileSystem, *mut *mut FileProtocol) -> Status,
+}
+
+/// EFI_FILE_MODE_READ
+pub const FILE_MODE_READ: u64 = 0x0000_0000_0000_0001;
+
+/// EFI_FILE_PROTOCOL
+#[repr(C)]
+pub struct FileProtocol {
+    pub revision: u64,
+    pub open: unsafe extern "efiapi" fn(
+        *mut FileProtocol,
+        *mut *mut FileProtocol,
+        *const u16,
+        u64,
+        u64,
+    ) -> Status,
+    pub close: unsafe extern "efiapi" fn(*mut FileProtocol) -> Status,
+    delete: usize,
+    pub read: unsafe extern "efiapi" fn(*mut FileProtocol, *mut usize, *mut c_void) -> Status,
+    write: usize,
+    pub get_position: unsafe extern "efiapi" fn(*mut FileProtocol, *mut u64) -> Status,
+    pub set_position: unsafe extern "efiapi" fn(*mut FileProtocol, u64) -> Status,
+    get_info: usize,
+    set_info: usize,
+    flush: usize,
+}
+
+/// EFI_GRAPHICS_PIXEL_FORMAT
+pub mod pixel_format {
+    pub const RGB_RESERVED_8BIT: u32 = 0;
+    pub const BGR_RESERVED_8BIT: u32 = 1;
+    pub const BIT_MASK: u32 = 2;
+    pub const BLT_ONLY: u32 = 3;
+}
+
+/// EFI_GRAPHICS_OUTPUT_MODE_INFORMATION
+#[repr(C)]
+pub struct GraphicsModeInfo {
+    pub version: u32,
+    pub horizontal_resolution: u32,
+    pub vertical_resolution: u32,
+    pub pixel_format: u32,
+    pub pixel_information: [u32; 4],
+    pub pixels_per_scan_line: u32,
+}
+
+/// EFI_GRAPHICS_OUTPUT_PROTOCOL_MODE
+#[repr(C)]
+pub struct GraphicsMode {
+    pub max_mode: u32,
+    pub mode: u32,
+    pub info: *mut GraphicsModeInfo,
+    pub size_of_info: usize,
+    pub frame_buffer_base: u64,
+    pub frame_buffer_size: usize,
+}
+
+/// EFI_GRAPHICS_OUTPUT_PROTOCOL
+#[repr(C)]
+pub struct GraphicsOutput {
+    query_mode: usize,
+    set_mode: usize,
+    blt: usize,
+    pub mode: *mut GraphicsMode,
+}
+
+/// UEFIのテキスト出力（ConOut）に書き込むための構造体
+/// `core::fmt::Write` を実装しているので `write!` が使える
+pub struct Console(pub *mut SimpleTextOutput);
+
+impl fmt::Write for Console {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        // UCS-2に変換しながら、小さなバッファ単位で出力する
+        let mut buf = [0u16; 64];
+        let mut i = 0;
+        let flush = |buf: &mut [u16; 64], i: &mut usize| {
+            buf[*i] = 0;
+            // 安全性: ConOutはブートサービス終了前まで有効
+            unsafe { ((*self.0).output_string)(self.0, buf.as_ptr()) };
+            *i = 0;
+        };
+        for c in s.chars() {
+            // UEFIのコンソールは改行にCRLFを要求する
+            if c == '\n' {
+                buf[i] = b'\r' as u16;
+                i += 1;
+            }
+            let mut units = [0u16; 2];
+            for &unit in c.encode_utf16(&mut units).iter() {
+                buf[i] = unit;
+                i += 1;
+            }
+            if i >= buf.len() - 4 {
+                flush(&mut buf, &mut i);
+            }
+        }
+        if i > 0 {
+            flush(&mut buf, &mut i);
+        }
+        Ok(())
+    }
+}
+
+/// `&str` をNUL終端のUCS-2文字列に変換する
+/// 返り値は書き込んだ要素数（NUL含む）。バッファが足りなければNone
+pub fn encode_ucs2(s: &str, buf: &mut [u16]) -> Option<usize> {
+    let mut i = 0;
+    for c in s.chars() {
+        // UCS-2はBMPの文字だけ
+        let code = u16::try_from(u32::from(c)).ok()?;
+        *buf.get_mut(i)? = code;
+        i += 1;
+    }
+    *buf.get_mut(i)? = 0;
+    Some(i + 1)
+}
+
+/// LoadedImageのロードオプション（UCS-2）をカーネルのコマンドライン（ASCII）に変換する
+/// 返り値は書き込んだバイト数
+///
+/// UEFIシェルから起動すると先頭に実行ファイル名（`BOOTX64.EFI` など）が付くので取り除く。
+/// ブートマネージャー（Boot####変数）から起動した場合は付かない。
+pub fn decode_load_options(options: &[u16], buf: &mut [u8]) -> usize {
+    let end = options.iter().position(|&c| c == 0).unwrap_or(options.len());
+    let mut options = &options[..end];
+
+    let is_space = |c: &u16| *c == b' ' as u16 || *c == b'\t' as u16;
+    let first_len = options.iter().position(is_space).unwrap_or(options.len());
+    let first = &options[..first_len];
+    let is_efi = first.len() >= 4
+        && first[first.len() - 4..]
+            .iter()
+            .map(|&c| u8::try_from(c).map_or(0, |b| b.to_ascii_lowercase()))
+            .eq(b".efi".iter().copied());
+    if is_efi {
+        options = &options[first_len..];
+    }
+    while options.first().is_some_and(is_space) {
+        options = &options[1..];
+    }
+
+    let mut len = 0;
+    for (dst, &c) in buf.iter_mut().zip(options) {
+        // カーネルのパーサはASCIIだけを扱う
+        *dst = if c < 0x80 { c as u8 } else { b'?' };
+        len += 1;
+    }
+    len
+}
+
+// ===== テスト =====
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ucs2(s: &str) -> Vec<u16> {
+        s.encode_utf16().chain(core::iter::once(0)).collect()
+    }
+
+    #[test]
+    fn test_decode_load_options() {
+        let mut buf = [0u8; 64];
+        let len = decode_load_options(&ucs2("loglevel=debug sched=rr"), &mut buf);
+        assert_eq!(&buf[..len], b"loglevel=debug sched=rr", "そのままASCIIに変換");
+
+        let len = decode_load_options(&ucs2("\\EFI\\BOOT\\BOOTX64.EFI  quantum=4"), &mut buf);
+        assert_eq!(&buf[..len], b"quantum=4", "シェルが付けた実行ファイル名は除く");
+
+        let len = decode_load_options(&ucs2("init=/sbin/\u{3042}"), &mut buf);
+        assert_eq!(&buf[..len], b"init=/sbin/?", "ASCII以外は?に置き換える");
+
+        assert_eq!(decode_load_options(&[], &mut buf), 0, "オプションなし");
+    }
+
+    #[test]
+    fn test_decode_load_options_truncates() {
+        let mut buf = [0u8; 4];
+        assert_eq!(decode_load_options(&ucs2("console=serial"), &mut buf), 4, "バッファの長さで切る");
+        assert_eq!(&buf, b"cons");
+    }
+
+    #[test]
+    fn test_status_is_error() {
+        assert!(!Status::SUCCESS.is_error(), "SUCCESSはエラーではない");
+        assert!(Status::BUFFER_TOO_SMALL.is_error(), "BUFFER_TOO_SMALLはエラー");
+        assert_eq!(Status::NOT_FOUND.to_result(), Err(Status::NOT_FOUND));
+    }
+
+    #[test]
+    fn test_encode_ucs2() {
+        let mut buf = [0xffffu16; 16];
+        let len = encode_ucs2("\\kernel.elf", &mut buf).unwrap();
+        assert_eq!(len, 12, "11文字 + NUL");
+        assert_eq!(buf[0], b'\\' as u16);
+        assert_eq!(buf[11], 0, "NUL終端されるべき");
+    }
+
+    #[test]
+    fn test_encode_ucs2_too_small() {
+        let mut buf = [0u16; 4];
+        assert!(encode_ucs2("kernel", &mut buf).is_none(), "バッファ不足はNone");
+    }
+
+    #[test]
+    fn test_memory_descriptor_layout() {
+        // UEFI仕様: Type(4) + パディング(4) + 8バイト×4 = 40バイト
+        assert_eq!(core::mem::size_of::<MemoryDescriptor>(), 40);
+    }
+}
@@ -0,0 +1,331 @@
+//! 静的リンクしたx86_64実行ファイルを読み込むための最小限のELF64パーサ
+//!
+//! ヘッダの検証と PT_LOAD セグメントの列挙だけを行う。
+//! ブートローダーはカーネルを、カーネルはユーザープログラムをこれで読む。
+//! セグメントをどこに置いてよいか（カーネルなら上位半分、ユーザーならユーザー空間）は
+//! 使う側で確かめる。
+//!
+//! MikanOS: MikanLoaderの CalcLoadAddressRange() / CopyLoadSegments() に相当
+
+// テスト時は標準ライブラリを使用
+#![cfg_attr(not(test), no_std)]
+
+/// ELFのマジックナンバー
+const ELF_MAGIC: [u8; 4] = [0x7f, b'E', b'L', b'F'];
+/// ELFCLASS64
+const ELFCLASS64: u8 = 2;
+/// ELFDATA2LSB（リトルエンディアン）
+const ELFDATA2LSB: u8 = 1;
+/// EV_CURRENT
+const EV_CURRENT: u8 = 1;
+/// ET_EXEC（実行可能ファイル）
+const ET_EXEC: u16 = 2;
+/// EM_X86_64
+const EM_X86_64: u16 = 0x3e;
+/// ELF64ヘッダのサイズ
+pub const EHDR_SIZE: usize = 64;
+/// プログラムヘッダ1つのサイズ
+pub const PHDR_SIZE: usize = 56;
+
+/// PT_LOAD（メモリに読み込むセグメント）
+pub const PT_LOAD: u32 = 1;
+/// 実行可能
+pub const PF_X: u32 = 0x1;
+/// 書き込み可能
+pub const PF_W: u32 = 0x2;
+
+/// ELFの解析エラー
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ElfError {
+    /// ファイルが短すぎる（ヘッダやセグメントがファイルの外を指す）
+    Truncated,
+    /// マジックナンバーが違う
+    BadMagic,
+    /// 64bitリトルエンディアンのx86_64実行ファイル（ET_EXEC）ではない
+    Unsupported,
+}
+
+/// プログラムヘッダ（Elf64_Phdr）
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ProgramHeader {
+    pub p_type: u32,
+    pub p_flags: u32,
+    pub p_offset: u64,
+    pub p_vaddr: u64,
+    pub p_paddr: u64,
+    pub p_filesz: u64,
+    pub p_memsz: u64,
+    pub p_align: u64,
+}
+
+impl ProgramHeader {
+    /// `addr` がセグメント（メモリ上の範囲）の中にあるか
+    pub fn contains(&self, addr: u64) -> bool {
+        (self.p_vaddr..self.p_vaddr.saturating_add(self.p_memsz)).contains(&addr)
+    }
+}
+
+/// 解析済みのELFファイル
+pub struct ElfFile<'a> {
+    data: &'a [u8],
+    /// エントリポイント（e_entry）
+    pub entry: u64,
+    phoff: usize,
+    phentsize: usize,
+    phnum: usize,
+}
+
+fn read_u16(data: &[u8], off: usize) -> Result<u16, ElfError> {
+    let bytes = data.get(off..off + 2).ok_or(ElfError::Truncated)?;
+    Ok(u16::from_le_bytes([bytes[0], bytes[1]]))
+}
+
+fn read_u32(data: &[u8], off: usize) -> Result<u32, ElfError> {
+    let bytes = data.get(off..off + 4).ok_or(ElfError::Truncated)?;
+    let mut buf = [0u8; 4];
+    buf.copy_from_slice(bytes);
+    Ok(u32::from_le_bytes(buf))
+}
+
+fn read_u64(data: &[u8], off: usize) -> Result<u64, ElfError> {
+    let bytes = data.get(off..off + 8).ok_or(ElfError::Truncated)?;
+    let mut buf = [0u8; 8];
+    buf.copy_from_slice(bytes);
+    Ok(u64::from_le_bytes(buf))
+}
+
+impl<'a> ElfFile<'a> {
+    /// ELFヘッダを検証して解析する
+    pub fn parse(data: &'a [u8]) -> Result<Self, ElfError> {
+        if data.len() < EHDR_SIZE {
+            return Err(ElfError::Truncated);
+        }
+        if data[0..4] != ELF_MAGIC {
+            return Err(ElfError::BadMagic);
+        }
+        if data[4] != ELFCLASS64
+            || data[5] != ELFDATA2LSB
+            || data[6] != EV_CURRENT
+            || read_u16(data, 16)? != ET_EXEC
+            || read_u16(data, 18)? != EM_X86_64
+        {
+            return Err(ElfError::Unsupported);
+        }
+
+        let entry = read_u64(data, 24)?;
+        let phoff = read_u64(data, 32)? as usize;
+        let phentsize = read_u16(data, 54)? as usize;
+        let phnum = read_u16(data, 56)? as usize;
+        if phentsize < PHDR_SIZE {
+            return Err(ElfError::Unsupported);
+        }
+        // プログラムヘッダ表全体がファイル内に収まっているか
+        let table_end = phnum
+            .checked_mul(phentsize)
+            .and_then(|size| size.checked_add(phoff))
+            .ok_or(ElfError::Truncated)?;
+        if table_end > data.len() {
+            return Err(ElfError::Truncated);
+        }
+
+        Ok(Self { data, entry, phoff, phentsize, phnum })
+    }
+
+    /// i番目のプログラムヘッダ
+    fn program_header(&self, i: usize) -> Result<ProgramHeader, ElfError> {
+        let off = self.phoff + i * self.phentsize;
+        Ok(ProgramHeader {
+            p_type: read_u32(self.data, off)?,
+            p_flags: read_u32(self.data, off + 4)?,
+            p_offset: read_u64(self.data, off + 8)?,
+            p_vaddr: read_u64(self.data, off + 16)?,
+            p_paddr: read_u64(self.data, off + 24)?,
+            p_filesz: read_u64(self.data, off + 32)?,
+            p_memsz: read_u64(self.data, off + 40)?,
+            p_align: read_u64(self.data, off + 48)?,
+        })
+    }
+
+    /// プログラムヘッダ1つの大きさ（e_phentsize）
+    pub fn phentsize(&self) -> usize {
+        self.phentsize
+    }
+
+    /// プログラムヘッダの数（e_phnum、PT_LOAD 以外も数える）
+    pub fn phnum(&self) -> usize {
+        self.phnum
+    }
+
+    /// PT_LOADセグメントを列挙する
+    pub fn load_segments(&self) -> impl Iterator<Item = ProgramHeader> + '_ {
+        (0..self.phnum)
+            .filter_map(move |i| self.program_header(i).ok())
+            .filter(|ph| ph.p_type == PT_LOAD)
+    }
+
+    /// 仮想アドレスが `min_vaddr` 以上のPT_LOADセグメントだけを列挙する
+    /// カーネルのMultiboot用トランポリン（物理アドレスにリンクされた .boot）を除くのに使う
+    pub fn load_segments_above(&self, min_vaddr: u64) -> impl Iterator<Item = ProgramHeader> + '_ {
+        self.load_segments().filter(move |ph| ph.p_vaddr >= min_vaddr)
+    }
+
+    /// 仮想アドレスが `min_vaddr` 以上のPT_LOADセグメントを含む仮想アドレス範囲 `[start, end)`
+    /// MikanOS: CalcLoadAddressRange()
+    pub fn load_address_range(&self, min_vaddr: u64) -> Option<(u64, u64)> {
+        let mut range: Option<(u64, u64)> = None;
+        for ph in self.load_segments_above(min_vaddr) {
+            let start = ph.p_vaddr;
+            let end = ph.p_vaddr + ph.p_memsz;
+            range = Some(match range {
+                None => (start, end),
+                Some((s, e)) => (s.min(start), e.max(end)),
+            });
+        }
+        range
+    }
+
+    /// セグメントのファイル上のデータ
+    pub fn segment_data(&self, ph: &ProgramHeader) -> Result<&'a [u8], ElfError> {
+        let start = ph.p_offset as usize;
+        let end = start
+            .checked_add(ph.p_filesz as usize)
+            .ok_or(ElfError::Truncated)?;
+        self.data.get(start..end).ok_or(ElfError::Truncated)
+    }
+
+    /// プログラムヘッダ表を読み込む仮想アドレス（どのセグメントにも含まれなければNone）
+    pub fn phdr_address(&self) -> Option<u64> {
+        let (start, end) = (self.phoff as u64, (self.phoff + self.phnum * self.phentsize) as u64);
+        self.load_segments()
+            .find(|ph| ph.p_offset <= start && end <= ph.p_offset + ph.p_filesz)
+            .map(|ph| ph.p_vaddr + (start - ph.p_offset))
+    }
+}
+
+// ===== テスト =====
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// テスト用のELFイメージを組み立てる
+    /// segments: (p_flags, p_vaddr, ファイル上のデータ, p_memsz)
+    fn build_elf(entry: u64, segments: &[(u32, u64, &[u8], u64)]) -> Vec<u8> {
+        let phoff = EHDR_SIZE;
+        let data_start = phoff + segments.len() * PHDR_SIZE;
+        let mut elf = vec![0u8; data_start];
+        elf[0..4].copy_from_slice(&ELF_MAGIC);
+        elf[4] = ELFCLASS64;
+        elf[5] = ELFDATA2LSB;
+        elf[6] = EV_CURRENT;
+        elf[16..18].copy_from_slice(&ET_EXEC.to_le_bytes());
+        elf[18..20].copy_from_slice(&EM_X86_64.to_le_bytes());
+        elf[24..32].copy_from_slice(&entry.to_le_bytes());
+        elf[32..40].copy_from_slice(&(phoff as u64).to_le_bytes());
+        elf[52..54].copy_from_slice(&(EHDR_SIZE as u16).to_le_bytes());
+        elf[54..56].copy_from_slice(&(PHDR_SIZE as u16).to_le_bytes());
+        elf[56..58].copy_from_slice(&(segments.len() as u16).to_le_bytes());
+
+        for (i, &(flags, vaddr, bytes, memsz)) in segments.iter().enumerate() {
+            let offset = elf.len() as u64;
+            elf.extend_from_slice(bytes);
+            let ph = phoff + i * PHDR_SIZE;
+            elf[ph..ph + 4].copy_from_slice(&PT_LOAD.to_le_bytes());
+            elf[ph + 4..ph + 8].copy_from_slice(&flags.to_le_bytes());
+            elf[ph + 8..ph + 16].copy_from_slice(&offset.to_le_bytes());
+            elf[ph + 16..ph + 24].copy_from_slice(&vaddr.to_le_bytes());
+            elf[ph + 24..ph + 32].copy_from_slice(&vaddr.to_le_bytes());
+            elf[ph + 32..ph + 40].copy_from_slice(&(bytes.len() as u64).to_le_bytes());
+            elf[ph + 40..ph + 48].copy_from_slice(&memsz.to_le_bytes());
+            elf[ph + 48..ph + 56].copy_from_slice(&4096u64.to_le_bytes());
+        }
+        elf
+    }
+
+    #[test]
+    fn test_parse_valid_elf() {
+        let image = build_elf(0xffff_ffff_8000_0000, &[(PF_X, 0xffff_ffff_8000_0000, &[0x90; 16], 16)]);
+        let elf = ElfFile::parse(&image).unwrap();
+        assert_eq!(elf.entry, 0xffff_ffff_8000_0000, "エントリポイントが読めるべき");
+        assert_eq!(elf.load_segments().count(), 1, "PT_LOADは1つ");
+    }
+
+    #[test]
+    fn test_bad_magic() {
+        let mut image = build_elf(0, &[]);
+        image[0] = 0;
+        assert_eq!(ElfFile::parse(&image).err(), Some(ElfError::BadMagic));
+    }
+
+    #[test]
+    fn test_truncated() {
+        assert_eq!(ElfFile::parse(&[0x7f, b'E']).err(), Some(ElfError::Truncated));
+    }
+
+    #[test]
+    fn test_reject_32bit() {
+        let mut image = build_elf(0, &[]);
+        image[4] = 1; // ELFCLASS32
+        assert_eq!(ElfFile::parse(&image).err(), Some(ElfError::Unsupported));
+    }
+
+    #[test]
+    fn test_reject_unknown_version() {
+        let mut image = build_elf(0, &[]);
+        image[6] = 0; // EV_NONE
+        assert_eq!(ElfFile::parse(&image).err(), Some(ElfError::Unsupported));
+    }
+
+    #[test]
+    fn test_load_address_range() {
+        // .text と .bss 付きの .data
+        let image = build_elf(
+            0x1000,
+            &[(PF_X, 0x1000, &[1; 0x100], 0x100), (PF_W, 0x3000, &[2; 0x10], 0x2000)],
+        );
+        let elf = ElfFile::parse(&image).unwrap();
+        assert_eq!(elf.load_address_range(0), Some((0x1000, 0x5000)), "memszまで含むべき");
+    }
+
+    #[test]
+    fn test_load_address_range_skips_low_segments() {
+        // 物理アドレスにリンクされたトランポリンと、上位半分のカーネル本体
+        let image = build_elf(
+            0xffff_ffff_8000_1000,
+            &[
+                (PF_X, 0x10_0000, &[1; 0x100], 0x100),
+                (PF_X, 0xffff_ffff_8000_1000, &[2; 0x100], 0x1000),
+            ],
+        );
+        let elf = ElfFile::parse(&image).unwrap();
+        assert_eq!(
+            elf.load_address_range(0xffff_ffff_8000_0000),
+            Some((0xffff_ffff_8000_1000, 0xffff_ffff_8000_2000)),
+            "下位のセグメントは範囲に含めない"
+        );
+        assert_eq!(elf.load_segments_above(0xffff_ffff_8000_0000).count(), 1, "上位のセグメントだけ");
+    }
+
+    #[test]
+    fn test_segment_data() {
+        let image = build_elf(0, &[(PF_X, 0x1000, &[0xaa, 0xbb], 0x10)]);
+        let elf = ElfFile::parse(&image).unwrap();
+        let ph = elf.load_segments().next().unwrap();
+        assert_eq!(elf.segment_data(&ph).unwrap(), &[0xaa, 0xbb]);
+        assert!(ph.contains(0x100f) && !ph.contains(0x1010), "memszまでがセグメント");
+    }
+
+    #[test]
+    fn test_phdr_address() {
+        // プログラムヘッダ表はファイルの先頭にあるので、オフセット0から読むセグメントに含まれる
+        let mut image = build_elf(0x1000, &[(PF_X, 0x1000, &[0; 0x10], 0x10)]);
+        let elf = ElfFile::parse(&image).unwrap();
+        assert_eq!(elf.phdr_address(), None, "セグメントはヘッダの後ろから");
+        assert_eq!((elf.phentsize(), elf.phnum()), (PHDR_SIZE, 1));
+
+        let len = image.len() as u64;
+        image[EHDR_SIZE + 8..EHDR_SIZE + 16].copy_from_slice(&0u64.to_le_bytes());
+        image[EHDR_SIZE + 32..EHDR_SIZE + 40].copy_from_slice(&len.to_le_bytes());
+        let elf = ElfFile::parse(&image).unwrap();
+        assert_eq!(elf.phdr_address(), Some(0x1000 + EHDR_SIZE as u64));
+    }
+}
@@ -0,0 +1,17 @@
+//! カーネルのビルドスクリプト
+//! ベアメタル向けビルドのときだけリンカスクリプトを渡す
+//! （ホストでの `cargo test` には影響させない）
+
+use std::env;
+
+fn main() {
+    let manifest_dir = env::var("CARGO_MANIFEST_DIR").unwrap();
+    let target_os = env::var("CARGO_CFG_TARGET_OS").unwrap_or_default();
+
+    if target_os == "none" {
+        println!("cargo:rustc-link-arg-bins=-T{}/linker.ld", manifest_dir);
+        // 旧Multibootのa.out kludgeのため、ファイル内の配置を物理アドレスの配置と揃える
+        println!("cargo:rustc-link-arg-bins=-zmax-page-size=0x1000");
+    }
+    println!("cargo:rerun-if-changed=linker.ld");
+}
@@ -15,11 +15,24 @@
 //! ├── mod.rs           // このファイル（共通トレイト定義）
 //! ├── x86_64/          // Intel/AMD 64bit
 //! │   ├── mod.rs
-//! │   └── context.rs
+//! │   ├── backtrace.rs // フレームポインタのバックトレース
+//! │   ├── context.rs
+//! │   ├── gdt.rs       // GDTとTSS（ユーザーモードのセグメント）
+//! │   ├── interrupts.rs // IDTと割り込みの入口
+//! │   ├── paging.rs    // 4段ページテーブル
+//! │   ├── pic.rs       // 8259 PIC
+//! │   ├── pit.rs       // 8254 タイマー
+//! │   ├── port.rs      // I/Oポート（in/out命令）
+//! │   ├── qemu.rs      // QEMUの終了（isa-debug-exit）
+//! │   └── syscall.rs   // SYSCALL/SYSRETの入口とring 3への出入り
 //! ├── aarch64/         // ARM 64bit（Android対応）
 //! └── riscv64/         // RISC-V 64bit（将来）
 //! ```
 
+use core::fmt;
+
+use crate::memory::Frame;
+
 #[cfg(target_arch = "x86_64")]
 mod x86_64;
 
@@ -89,4 +102,112 @@ pub trait CpuOps {
     
     /// 現在のCPU IDを取得（マルチコア対応）
     fn cpu_id() -> u32;
-}
\ No newline at end of file
+}
+
+/// ページの大きさ
+///
+/// x86_64の4段ページング、AArch64の4KiBグラニュール、RISC-VのSv48で共通の3種類
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PageSize {
+    /// 4KiB（最下段のエントリ）
+    Size4K,
+    /// 2MiB（1つ上の段のエントリ）
+    Size2M,
+    /// 1GiB（2つ上の段のエントリ）
+    Size1G,
+}
+
+impl PageSize {
+    /// ページのバイト数
+    pub const fn bytes(self) -> u64 {
+        match self {
+            Self::Size4K => 0x1000,
+            Self::Size2M => 0x20_0000,
+            Self::Size1G => 0x4000_0000,
+        }
+    }
+}
+
+/// マップするページの属性（各アーキテクチャのエントリのビットに変換する）
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MapFlags(u8);
+
+impl MapFlags {
+    /// 書き込みできる
+    pub const WRITABLE: u8 = 0x01;
+    /// ユーザーモード（ring 3 / EL0 / Uモード）からアクセスできる
+    pub const USER: u8 = 0x02;
+    /// 命令を実行できる（なければ実行禁止にする）
+    pub const EXECUTABLE: u8 = 0x04;
+    /// アドレス空間を切り替えてもTLBから消さない（カーネルの領域用）
+    pub const GLOBAL: u8 = 0x08;
+
+    /// フラグの組み合わせから作成（`MapFlags::WRITABLE | MapFlags::USER` など）
+    pub const fn from_bits(bits: u8) -> Self {
+        Self(bits)
+    }
+
+    /// フラグが設定されているか確認
+    pub fn is_set(&self, flag: u8) -> bool {
+        (self.0 & flag) != 0
+    }
+
+    /// `flags` のすべてが設定されているか確認
+    pub fn contains(&self, flags: u8) -> bool {
+        (self.0 & flags) == flags
+    }
+
+    /// `flags` を外したもの（コピーオンライトで書き込みを禁止するときなど）
+    pub const fn without(self, flags: u8) -> Self {
+        Self(self.0 & !flags)
+    }
+}
+
+/// マップの操作に失敗した理由
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MapError {
+    /// ページテーブル用のフレームが確保できなかった
+    OutOfFrames,
+    /// すでにマップされている（大きなページの内側も含む）
+    AlreadyMapped,
+    /// マップされていない
+    NotMapped,
+    /// 仮想アドレスか物理アドレスがページの大きさにそろっていない
+    Misaligned,
+    /// 正規形（canonical）でない仮想アドレス
+    NonCanonical,
+}
+
+impl fmt::Display for MapError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::OutOfFrames => write!(f, "out of page table frames"),
+            Self::AlreadyMapped => write!(f, "page already mapped"),
+            Self::NotMapped => write!(f, "page not mapped"),
+            Self::Misaligned => write!(f, "address not aligned to page size"),
+            Self::NonCanonical => write!(f, "non-canonical address"),
+        }
+    }
+}
+
+/// アーキテクチャ共通のアドレス空間インターフェース
+///
+/// 仮想アドレスと物理フレームの対応を管理する。ページテーブルの形式は
+/// アーキテクチャごとに違うが、カーネルの残りの部分はこのトレイトだけを使う。
+///
+/// MINIX 3: servers/vm/pagetable.c の pt_writemap()
+pub trait AddressSpace {
+    /// 仮想アドレス `page` から `size` のページを物理フレーム `frame` にマップする
+    fn map(&mut self, page: u64, frame: Frame, size: PageSize, flags: MapFlags) -> Result<(), MapError>;
+
+    /// `page` から始まるページのマップを外し、マップしていたフレームと大きさを返す
+    fn unmap(&mut self, page: u64) -> Result<(Frame, PageSize), MapError>;
+
+    /// 仮想アドレスを物理アドレスに変換する（マップされていなければNone）
+    fn translate(&self, virt: u64) -> Option<u64>;
+
+    /// `page` から始まるページの属性を `flags` に変える（マップしているフレームはそのまま）
+    ///
+    /// MINIX 3: pt_writemap() に WMF_WRITEFLAGSONLY を付けて呼ぶ
+    fn protect(&mut self, page: u64, flags: MapFlags) -> Result<(), MapError>;
+}
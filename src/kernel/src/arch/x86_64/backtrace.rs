@@ -0,0 +1,145 @@
+//! フレームポインタをたどるバックトレース
+//!
+//! 関数の先頭で `push rbp; mov rbp, rsp` しているので、rbp から
+//! [rbp] = 呼び出し元の rbp、[rbp + 8] = 戻りアドレス
+//! という連結リストをたどれる（ターゲット定義で frame-pointer を always にしている）。
+//!
+//! MINIX 3: kernel/arch/i386/arch_system.c の proc_stacktrace()
+//! MikanOS: なし（パニック時はそのまま停止していた）
+
+/// たどるフレームの上限（壊れたスタックで止まらないように）
+pub const MAX_DEPTH: usize = 32;
+
+/// 戻りアドレスを新しい順に返すイテレータ
+///
+/// メモリの読み出しは `read` に任せる（テストでは偽のスタックを渡す）。
+pub struct Backtrace<R: Fn(u64) -> Option<u64>> {
+    rbp: u64,
+    read: R,
+    depth: usize,
+}
+
+impl<R: Fn(u64) -> Option<u64>> Backtrace<R> {
+    pub fn new(rbp: u64, read: R) -> Self {
+        Self { rbp, read, depth: 0 }
+    }
+}
+
+impl<R: Fn(u64) -> Option<u64>> Iterator for Backtrace<R> {
+    type Item = u64;
+
+    fn next(&mut self) -> Option<u64> {
+        if self.depth >= MAX_DEPTH || self.rbp == 0 || !self.rbp.is_multiple_of(8) {
+            return None;
+        }
+        let return_address = (self.read)(self.rbp + 8)?;
+        let caller_rbp = (self.read)(self.rbp)?;
+        if return_address == 0 {
+            return None;
+        }
+        // スタックは下に伸びるので、呼び出し元のフレームは必ず上にある
+        self.rbp = if caller_rbp > self.rbp { caller_rbp } else { 0 };
+        self.depth += 1;
+        Some(return_address)
+    }
+}
+
+/// 正規形（canonical）のアドレスか
+/// 上位17ビットがすべて0かすべて1でないアドレスを読むと#GPになる
+pub fn is_canonical(address: u64) -> bool {
+    matches!(address >> 47, 0 | 0x1_ffff)
+}
+
+/// 現在のスタックを読んでバックトレースを作る
+///
+/// # Safety
+/// `rbp` からのフレームポインタの連結がマップ済みのメモリを指していること
+/// （壊れたスタックではページフォルトになりうる）
+pub unsafe fn from_rbp(rbp: u64) -> Backtrace<impl Fn(u64) -> Option<u64>> {
+    Backtrace::new(rbp, |address| {
+        if address < 0x1000 || !is_canonical(address) {
+            return None;
+        }
+        Some(unsafe { core::ptr::read_volatile(address as *const u64) })
+    })
+}
+
+/// 現在の rbp
+#[inline(always)]
+pub fn current_rbp() -> u64 {
+    let rbp: u64;
+    unsafe { core::arch::asm!("mov {}, rbp", out(reg) rbp, options(nomem, nostack, preserves_flags)) };
+    rbp
+}
+
+// ===== テスト =====
+#[cfg(all(test, not(target_os = "none")))]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    /// (rbp, 呼び出し元のrbp, 戻りアドレス) から偽のスタックを作る
+    fn fake_stack(frames: &[(u64, u64, u64)]) -> HashMap<u64, u64> {
+        let mut memory = HashMap::new();
+        for &(rbp, caller, ret) in frames {
+            memory.insert(rbp, caller);
+            memory.insert(rbp + 8, ret);
+        }
+        memory
+    }
+
+    #[test]
+    fn test_walks_frame_chain() {
+        let memory = fake_stack(&[(0x1000, 0x1040, 0xa1), (0x1040, 0x1100, 0xa2), (0x1100, 0, 0xa3)]);
+        let addresses: Vec<_> = Backtrace::new(0x1000, |a| memory.get(&a).copied()).collect();
+        assert_eq!(addresses, [0xa1, 0xa2, 0xa3], "呼び出し元のrbpが0で終わる");
+    }
+
+    #[test]
+    fn test_stops_on_loop() {
+        let memory = fake_stack(&[(0x1000, 0x1040, 0xa1), (0x1040, 0x1000, 0xa2)]);
+        let addresses: Vec<_> = Backtrace::new(0x1000, |a| memory.get(&a).copied()).collect();
+        assert_eq!(addresses, [0xa1, 0xa2], "下に戻るrbpは壊れているので止める");
+    }
+
+    #[test]
+    fn test_stops_on_unreadable_or_misaligned() {
+        let memory = fake_stack(&[(0x1000, 0x2000, 0xa1)]);
+        let addresses: Vec<_> = Backtrace::new(0x1000, |a| memory.get(&a).copied()).collect();
+        assert_eq!(addresses, [0xa1], "読めないアドレスで止める");
+        assert_eq!(Backtrace::new(0x1003, |_| Some(1)).count(), 0, "8の倍数でないrbpは使わない");
+    }
+
+    #[test]
+    fn test_depth_limit() {
+        // すべてのフレームが自分の少し上を指す無限のスタック
+        let addresses = Backtrace::new(0x1000, |a| Some(if a.is_multiple_of(16) { a + 16 } else { 0xdead })).count();
+        assert_eq!(addresses, MAX_DEPTH, "上限でやめる");
+    }
+
+    #[test]
+    fn test_is_canonical() {
+        assert!(is_canonical(0x0000_7fff_ffff_ffff));
+        assert!(is_canonical(0xffff_8000_0000_0000));
+        assert!(!is_canonical(0x0000_8000_0000_0000), "穴の部分は正規形でない");
+        assert!(!is_canonical(0xdead_beef_dead_beef));
+    }
+}
+
+// ===== カーネル内テスト =====
+#[cfg(all(test, target_os = "none"))]
+mod kernel_tests {
+    use super::*;
+
+    #[test_case]
+    fn test_walk_current_stack() {
+        let trace = unsafe { from_rbp(current_rbp()) };
+        let mut count = 0;
+        for address in trace {
+            assert!(address >= boot_info::KERNEL_BASE, "戻りアドレスはカーネルの中: {:#x}", address);
+            count += 1;
+        }
+        // テストランナーと kernel_main からここまでの呼び出しがある
+        assert!(count >= 2, "{} frames", count);
+    }
+}
@@ -0,0 +1,156 @@
+//! x86_64のCPUコンテキスト
+//!
+//! MINIX 3: kernel/arch/i386/include/archtypes.h の stackframe_s に相当
+//! MikanOS: task.hpp の TaskContext に相当
+
+use core::fmt;
+
+/// コンテキストスイッチ用のレジスタ保存領域
+/// x86_64の呼び出し規約に従って保存するレジスタ
+#[derive(Debug, Clone, Copy)]
+#[repr(C)]
+pub struct StackFrame {
+    /// 汎用レジスタ
+    pub rax: u64,
+    pub rbx: u64,
+    pub rcx: u64,
+    pub rdx: u64,
+    pub rsi: u64,
+    pub rdi: u64,
+    pub rbp: u64,
+    pub r8: u64,
+    pub r9: u64,
+    pub r10: u64,
+    pub r11: u64,
+    pub r12: u64,
+    pub r13: u64,
+    pub r14: u64,
+    pub r15: u64,
+    /// 割り込み発生時のRFLAGS
+    pub rflags: u64,
+    /// 割り込み発生時のRIP（リターンアドレス）
+    pub rip: u64,
+    /// 割り込み発生時のRSP
+    pub rsp: u64,
+}
+
+impl StackFrame {
+    /// ゼロ初期化されたStackFrameを作成（const fn対応）
+    pub const fn new() -> Self {
+        Self {
+            rax: 0, rbx: 0, rcx: 0, rdx: 0,
+            rsi: 0, rdi: 0, rbp: 0,
+            r8: 0, r9: 0, r10: 0, r11: 0,
+            r12: 0, r13: 0, r14: 0, r15: 0,
+            rflags: 0, rip: 0, rsp: 0,
+        }
+    }
+}
+
+impl Default for StackFrame {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// レジスタダンプ（パニック時やデバッグ用）
+/// 1行に4つずつ、16進数16桁で表示する
+impl fmt::Display for StackFrame {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let registers = [
+            ("rax", self.rax), ("rbx", self.rbx), ("rcx", self.rcx), ("rdx", self.rdx),
+            ("rsi", self.rsi), ("rdi", self.rdi), ("rbp", self.rbp), ("rsp", self.rsp),
+            ("r8", self.r8), ("r9", self.r9), ("r10", self.r10), ("r11", self.r11),
+            ("r12", self.r12), ("r13", self.r13), ("r14", self.r14), ("r15", self.r15),
+            ("rip", self.rip), ("rflags", self.rflags),
+        ];
+        for (i, line) in registers.chunks(4).enumerate() {
+            if i > 0 {
+                writeln!(f)?;
+            }
+            for (j, (name, value)) in line.iter().enumerate() {
+                if j > 0 {
+                    f.write_str(" ")?;
+                }
+                write!(f, "{:<3}={:016x}", name, value)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// RFLAGSの割り込み許可フラグ（IF）
+const RFLAGS_IF: u64 = 1 << 9;
+/// RFLAGSのビット1（常に1）
+const RFLAGS_RESERVED: u64 = 1 << 1;
+
+/// x86_64のコンテキスト（arch::Context の実装）
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Context {
+    pub frame: StackFrame,
+}
+
+impl crate::arch::Context for Context {
+    /// 割り込み許可の状態で `entry_point` から始まるコンテキスト
+    fn new(entry_point: u64, stack_pointer: u64) -> Self {
+        let mut frame = StackFrame::new();
+        frame.rip = entry_point;
+        frame.rsp = stack_pointer;
+        frame.rflags = RFLAGS_IF | RFLAGS_RESERVED;
+        Self { frame }
+    }
+
+    fn set_instruction_pointer(&mut self, addr: u64) {
+        self.frame.rip = addr;
+    }
+
+    fn set_stack_pointer(&mut self, addr: u64) {
+        self.frame.rsp = addr;
+    }
+
+    fn instruction_pointer(&self) -> u64 {
+        self.frame.rip
+    }
+
+    fn stack_pointer(&self) -> u64 {
+        self.frame.rsp
+    }
+}
+
+// ===== テスト =====
+#[cfg(all(test, not(target_os = "none")))]
+mod tests {
+    use super::*;
+    use crate::arch::Context as _;
+
+    #[test]
+    fn test_new_context() {
+        let context = Context::new(0x40_1000, 0x7fff_f000);
+        assert_eq!(context.instruction_pointer(), 0x40_1000, "ripはエントリポイント");
+        assert_eq!(context.stack_pointer(), 0x7fff_f000, "rspはスタックの先頭");
+        assert_ne!(context.frame.rflags & RFLAGS_IF, 0, "割り込みは許可しておくべき");
+    }
+
+    #[test]
+    fn test_stack_frame_display() {
+        let mut frame = StackFrame::new();
+        frame.rax = 0x1234;
+        frame.rip = 0xffff_ffff_8010_0000;
+        frame.rflags = 0x202;
+        let text = frame.to_string();
+        let lines: Vec<_> = text.lines().collect();
+        assert_eq!(lines.len(), 5, "18個のレジスタを4つずつ並べる");
+        assert_eq!(lines[0], "rax=0000000000001234 rbx=0000000000000000 rcx=0000000000000000 rdx=0000000000000000");
+        assert_eq!(lines[2], "r8 =0000000000000000 r9 =0000000000000000 r10=0000000000000000 r11=0000000000000000");
+        assert_eq!(lines[4], "rip=ffffffff80100000 rflags=0000000000000202");
+    }
+
+    #[test]
+    fn test_set_registers() {
+        let mut context = Context::default();
+        context.set_instruction_pointer(0x1234);
+        context.set_stack_pointer(0x5678);
+        assert_eq!(context.frame.rip, 0x1234);
+        assert_eq!(context.frame.rsp, 0x5678);
+    }
+}
@@ -0,0 +1,229 @@
+//! GDT（グローバルディスクリプタテーブル）とTSS
+//!
+//! ブートローダーのGDTにはカーネルのセグメントしかないので、起動時にカーネル自身のGDTに切り替え、
+//! ユーザーモード（ring 3）のセグメントとTSSを足す。
+//! ロングモードではセグメントのベースと上限は使われず、特権レベル（DPL）と
+//! 64ビットコード（Lビット）の区別だけが意味を持つ。
+//!
+//! TSSはring 3から割り込みが来たときに切り替えるカーネルスタック（RSP0）を持つ。
+//! プロセスを切り替えるたびに、そのプロセスのカーネルスタックを書き込む。
+//!
+//! ```text
+//! 0x00  ヌル
+//! 0x08  カーネルコード（DPL 0）
+//! 0x10  カーネルデータ（DPL 0）
+//! 0x18  ユーザーデータ（DPL 3）   ← SYSRETは STAR[63:48] + 8 をSSにする
+//! 0x20  ユーザーコード（DPL 3）   ← SYSRETは STAR[63:48] + 16 をCSにする
+//! 0x28  TSS（16バイト）
+//! ```
+//!
+//! MINIX 3: kernel/arch/i386/protect.c の prot_init() と tss_init()
+//! MikanOS: segment.cpp の SetupSegments() と InitializeTSS()
+
+use crate::sync::SpinLock;
+
+/// カーネルのコードセグメント
+pub const KERNEL_CODE: u16 = 0x08;
+/// カーネルのデータセグメント
+pub const KERNEL_DATA: u16 = 0x10;
+/// ユーザーのデータセグメント（RPL 3）
+pub const USER_DATA: u16 = 0x18 | 3;
+/// ユーザーのコードセグメント（RPL 3）
+pub const USER_CODE: u16 = 0x20 | 3;
+/// TSSのセレクタ
+pub const TSS_SELECTOR: u16 = 0x28;
+
+/// セグメントディスクリプタ（ベース0、上限4GiB、ページ単位）
+const fn segment(access: u8, flags: u8) -> u64 {
+    0xffff | (access as u64) << 40 | ((flags as u64) << 4 | 0xf) << 48
+}
+
+/// Present、S（コード/データ）、DPLの付いたアクセスバイト
+const PRESENT: u8 = 0x90;
+const DPL3: u8 = 0x60;
+const CODE: u8 = 0x0a;
+const DATA: u8 = 0x02;
+/// Gビット（上限をページ単位にする）とLビット（64ビットコード）
+const GRANULARITY: u8 = 0x8;
+const LONG_MODE: u8 = 0x2;
+/// 32ビットのデータセグメント（D/B）
+const DEFAULT_SIZE: u8 = 0x4;
+
+/// TSS（64ビット、104バイト）
+/// Intel SDM Vol.3 8.7
+#[derive(Debug, Clone, Copy)]
+#[repr(C, packed(4))]
+pub struct TaskStateSegment {
+    reserved0: u32,
+    /// 特権レベル0〜2に入るときのスタック
+    pub rsp: [u64; 3],
+    reserved1: u64,
+    /// 割り込みスタックテーブル（IDTのISTで選ぶスタック）
+    pub ist: [u64; 7],
+    reserved2: u64,
+    reserved3: u16,
+    /// I/O許可ビットマップの位置（TSSの大きさ以上なら、ring 3からI/Oポートを使えない）
+    iomap_base: u16,
+}
+
+impl TaskStateSegment {
+    pub const fn new() -> Self {
+        Self {
+            reserved0: 0,
+            rsp: [0; 3],
+            reserved1: 0,
+            ist: [0; 7],
+            reserved2: 0,
+            reserved3: 0,
+            iomap_base: core::mem::size_of::<Self>() as u16,
+        }
+    }
+}
+
+/// TSSディスクリプタ（ロングモードでは16バイト、GDTの2エントリ分）
+pub const fn tss_descriptor(base: u64, limit: u32) -> [u64; 2] {
+    // Present、DPL 0、タイプ9（使用可能な64ビットTSS）
+    const TSS_AVAILABLE: u64 = 0x89;
+    let low = (limit as u64 & 0xffff)
+        | (base & 0xff_ffff) << 16
+        | TSS_AVAILABLE << 40
+        | ((limit as u64 >> 16) & 0xf) << 48
+        | ((base >> 24) & 0xff) << 56;
+    [low, base >> 32]
+}
+
+/// GDTのエントリ数（TSSは2エントリ使う）
+const GDT_ENTRIES: usize = 7;
+
+/// GDT本体
+#[repr(C, align(16))]
+pub struct Gdt {
+    entries: [u64; GDT_ENTRIES],
+}
+
+impl Gdt {
+    /// TSSのディスクリプタ以外を埋めたGDT
+    pub const fn new() -> Self {
+        Self {
+            entries: [
+                0,
+                segment(PRESENT | CODE, GRANULARITY | LONG_MODE),
+                segment(PRESENT | DATA, GRANULARITY | DEFAULT_SIZE),
+                segment(PRESENT | DPL3 | DATA, GRANULARITY | DEFAULT_SIZE),
+                segment(PRESENT | DPL3 | CODE, GRANULARITY | LONG_MODE),
+                0,
+                0,
+            ],
+        }
+    }
+
+    /// セレクタのエントリ
+    pub fn entry(&self, selector: u16) -> u64 {
+        self.entries[usize::from(selector >> 3)]
+    }
+
+    /// TSSのディスクリプタを設定する
+    pub fn set_tss(&mut self, tss: &'static TaskStateSegment) {
+        let limit = core::mem::size_of::<TaskStateSegment>() as u32 - 1;
+        let [low, high] = tss_descriptor(tss as *const TaskStateSegment as u64, limit);
+        let index = usize::from(TSS_SELECTOR >> 3);
+        self.entries[index] = low;
+        self.entries[index + 1] = high;
+    }
+}
+
+impl Default for Gdt {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// CPUに登録するGDT
+static GDT: SpinLock<Gdt> = SpinLock::new(Gdt::new());
+
+/// CPUに登録するTSS（CPUが直接読むのでアドレスを変えない）
+static TSS: SpinLock<TaskStateSegment> = SpinLock::new(TaskStateSegment::new());
+
+/// ring 3 から割り込みやSYSCALLで入ったときに使うカーネルスタックを設定する
+pub fn set_kernel_stack(top: u64) {
+    TSS.lock().rsp[0] = top;
+}
+
+/// カーネルのGDTに切り替えて、セグメントレジスタとタスクレジスタを読み込み直す
+///
+/// # Safety
+/// 起動時に一度だけ、割り込み禁止中に呼ぶこと（IDTはこの後のコードセグメントで作る）
+#[cfg(any(not(test), target_os = "none"))]
+pub unsafe fn init() {
+    #[repr(C, packed)]
+    struct Pointer {
+        limit: u16,
+        base: u64,
+    }
+
+    // 安全性: GDT と TSS は static なのでアドレスは変わらない
+    let tss: &'static TaskStateSegment = unsafe { &*(&*TSS.lock() as *const TaskStateSegment) };
+    let mut gdt = GDT.lock();
+    gdt.set_tss(tss);
+    let pointer = Pointer { limit: (core::mem::size_of::<Gdt>() - 1) as u16, base: &*gdt as *const Gdt as u64 };
+    unsafe {
+        core::arch::asm!(
+            "lgdt [{pointer}]",
+            // CSはmovで変えられないので、far returnで読み込む
+            "push {code}",
+            "lea {tmp}, [rip + 2f]",
+            "push {tmp}",
+            "retfq",
+            "2:",
+            "mov ds, {data:x}",
+            "mov es, {data:x}",
+            "mov ss, {data:x}",
+            "xor {tmp:e}, {tmp:e}",
+            "mov fs, {tmp:x}",
+            "mov gs, {tmp:x}",
+            "ltr {tss:x}",
+            pointer = in(reg) &pointer,
+            code = const KERNEL_CODE as u64,
+            data = in(reg) u64::from(KERNEL_DATA),
+            tss = in(reg) u64::from(TSS_SELECTOR),
+            tmp = out(reg) _,
+        );
+    }
+}
+
+// ===== テスト =====
+#[cfg(all(test, not(target_os = "none")))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_segments() {
+        let gdt = Gdt::new();
+        // 他のOSやブートローダー（Multibootのトランポリン）と同じ値
+        assert_eq!(gdt.entry(KERNEL_CODE), 0x00af_9a00_0000_ffff);
+        assert_eq!(gdt.entry(KERNEL_DATA), 0x00cf_9200_0000_ffff);
+        assert_eq!(gdt.entry(USER_DATA), 0x00cf_f200_0000_ffff, "DPL 3のデータ");
+        assert_eq!(gdt.entry(USER_CODE), 0x00af_fa00_0000_ffff, "DPL 3の64ビットコード");
+        // SYSRETは1つのベースからSSとCSを決めるので、この並びでなければならない
+        assert_eq!(USER_DATA + 8, USER_CODE);
+    }
+
+    #[test]
+    fn test_tss_layout() {
+        assert_eq!(core::mem::size_of::<TaskStateSegment>(), 104);
+        assert_eq!(core::mem::offset_of!(TaskStateSegment, rsp), 4, "RSP0はオフセット4");
+        assert_eq!(core::mem::offset_of!(TaskStateSegment, ist), 36);
+        let tss = TaskStateSegment::new();
+        assert_eq!({ tss.iomap_base }, 104, "I/O許可ビットマップなし");
+    }
+
+    #[test]
+    fn test_tss_descriptor() {
+        let [low, high] = tss_descriptor(0xffff_ffff_8012_3456, 103);
+        assert_eq!(low & 0xffff, 103, "上限の下位16ビット");
+        assert_eq!((low >> 16) & 0xff_ffff, 0x12_3456, "ベースの0〜23ビット");
+        assert_eq!((low >> 40) & 0xff, 0x89, "Present、64ビットTSS");
+        assert_eq!(low >> 56, 0x80, "ベースの24〜31ビット");
+        assert_eq!(high, 0xffff_ffff, "ベースの上位32ビット");
+    }
+}
@@ -0,0 +1,608 @@
+//! IDT（割り込み記述子テーブル）と割り込みの入口
+//!
+//! 各ベクタの入口（スタブ）はアセンブリで書き、レジスタを InterruptFrame の形で
+//! スタックに積んでから Rust の interrupt_dispatch() を呼ぶ。
+//! extern "x86-interrupt" を使わないのは、保存したレジスタをそのままプロセスの
+//! StackFrame として扱えるようにするため（MINIX 3 と同じ方式）。
+//!
+//! ベクタの配置は MINIX 3 に合わせ、CPU例外が 0〜31、IPCトラップが 0x21（DPL=3）、
+//! PICのIRQが 0x50〜0x5F。
+//!
+//! MINIX 3: kernel/arch/i386/mpx.S の hwint_master / exception_entry、
+//!          kernel/arch/i386/protect.c の idt_init()
+//! MikanOS: interrupt.cpp の SetIDTEntry() と InterruptFrame
+
+use core::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+
+use libipc::IPC_VECTOR;
+
+#[cfg(any(not(test), target_os = "none"))]
+use super::pic;
+use super::pic::{Pic8259, IRQ_BASE, IRQ_COUNT};
+use super::StackFrame;
+use crate::sync::SpinLock;
+
+/// CPU例外の数（ベクタ 0〜31）
+pub const EXCEPTION_COUNT: u8 = 32;
+
+/// ページフォルト（#PF）のベクタ
+pub const PAGE_FAULT: u8 = 14;
+
+/// ページフォルトのエラーコード: 存在するページへのアクセス（0ならマップされていない）
+pub const PF_PRESENT: u64 = 1 << 0;
+/// ページフォルトのエラーコード: 書き込み
+pub const PF_WRITE: u64 = 1 << 1;
+
+/// スタブを用意するベクタの数（CPU例外32個 + PICのIRQ 16個、IPCトラップは別）
+pub const STUB_COUNT: usize = EXCEPTION_COUNT as usize + IRQ_COUNT as usize;
+
+/// 割り込みゲート（割り込み禁止で入る）、DPL=0、Present
+const GATE_INTERRUPT: u8 = 0x8e;
+
+/// ring 3 から `int` で呼べる割り込みゲート（DPL=3）
+const GATE_USER: u8 = 0xee;
+
+/// CPU例外の名前（Intel SDM Vol.3 6.15）
+const EXCEPTION_NAMES: [&str; 32] = [
+    "Divide Error", "Debug", "NMI", "Breakpoint",
+    "Overflow", "BOUND Range Exceeded", "Invalid Opcode", "Device Not Available",
+    "Double Fault", "Coprocessor Segment Overrun", "Invalid TSS", "Segment Not Present",
+    "Stack-Segment Fault", "General Protection", "Page Fault", "Reserved",
+    "x87 Floating-Point", "Alignment Check", "Machine Check", "SIMD Floating-Point",
+    "Virtualization", "Control Protection", "Reserved", "Reserved",
+    "Reserved", "Reserved", "Reserved", "Reserved",
+    "Hypervisor Injection", "VMM Communication", "Security", "Reserved",
+];
+
+/// 例外ベクタの名前
+pub fn exception_name(vector: u8) -> &'static str {
+    EXCEPTION_NAMES.get(usize::from(vector)).copied().unwrap_or("Unknown")
+}
+
+/// IDTの1エントリ（16バイト）
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(C)]
+pub struct IdtEntry {
+    offset_low: u16,
+    selector: u16,
+    ist: u8,
+    attributes: u8,
+    offset_middle: u16,
+    offset_high: u32,
+    reserved: u32,
+}
+
+impl IdtEntry {
+    /// 使わないベクタ（Present=0）
+    pub const MISSING: Self = Self {
+        offset_low: 0,
+        selector: 0,
+        ist: 0,
+        attributes: 0,
+        offset_middle: 0,
+        offset_high: 0,
+        reserved: 0,
+    };
+
+    /// `handler` に飛ぶ割り込みゲート
+    pub const fn new(handler: u64, selector: u16) -> Self {
+        Self {
+            offset_low: handler as u16,
+            selector,
+            ist: 0,
+            attributes: GATE_INTERRUPT,
+            offset_middle: (handler >> 16) as u16,
+            offset_high: (handler >> 32) as u32,
+            reserved: 0,
+        }
+    }
+
+    /// ユーザーモードから `int` 命令で呼べる割り込みゲート（DPL=0のゲートを呼ぶと #GP）
+    pub const fn user(handler: u64, selector: u16) -> Self {
+        let mut entry = Self::new(handler, selector);
+        entry.attributes = GATE_USER;
+        entry
+    }
+
+    /// ユーザーモードから呼べるか
+    pub fn is_user(&self) -> bool {
+        self.attributes & 0x60 == 0x60
+    }
+
+    /// ハンドラのアドレス
+    pub fn handler(&self) -> u64 {
+        u64::from(self.offset_low) | u64::from(self.offset_middle) << 16 | u64::from(self.offset_high) << 32
+    }
+
+    pub fn is_present(&self) -> bool {
+        self.attributes & 0x80 != 0
+    }
+}
+
+/// IDT本体（256エントリ）
+#[repr(C, align(16))]
+pub struct Idt {
+    entries: [IdtEntry; 256],
+}
+
+impl Idt {
+    pub const fn new() -> Self {
+        Self { entries: [IdtEntry::MISSING; 256] }
+    }
+
+    pub fn set(&mut self, vector: u8, entry: IdtEntry) {
+        self.entries[usize::from(vector)] = entry;
+    }
+
+    pub fn get(&self, vector: u8) -> &IdtEntry {
+        &self.entries[usize::from(vector)]
+    }
+
+    /// lidtでCPUに登録する
+    ///
+    /// # Safety
+    /// 登録したエントリはすべて有効なスタブを指していること
+    #[cfg(any(not(test), target_os = "none"))]
+    unsafe fn load(&'static self) {
+        #[repr(C, packed)]
+        struct Pointer {
+            limit: u16,
+            base: u64,
+        }
+        let pointer = Pointer {
+            limit: (core::mem::size_of::<Self>() - 1) as u16,
+            base: self as *const Self as u64,
+        };
+        core::arch::asm!("lidt [{}]", in(reg) &pointer, options(readonly, nostack, preserves_flags));
+    }
+}
+
+impl Default for Idt {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// 割り込みの入口で積まれるレジスタ
+///
+/// スタブが積んだ汎用レジスタ・ベクタ番号・エラーコードと、
+/// CPUが積んだ rip/cs/rflags/rsp/ss がこの順で並ぶ。
+#[derive(Debug, Clone, Copy, Default)]
+#[repr(C)]
+pub struct InterruptFrame {
+    pub r15: u64,
+    pub r14: u64,
+    pub r13: u64,
+    pub r12: u64,
+    pub r11: u64,
+    pub r10: u64,
+    pub r9: u64,
+    pub r8: u64,
+    pub rbp: u64,
+    pub rdi: u64,
+    pub rsi: u64,
+    pub rdx: u64,
+    pub rcx: u64,
+    pub rbx: u64,
+    pub rax: u64,
+    /// ベクタ番号（スタブが積む）
+    pub vector: u64,
+    /// エラーコード（CPUが積まない例外ではスタブが0を積む）
+    pub error_code: u64,
+    pub rip: u64,
+    pub cs: u64,
+    pub rflags: u64,
+    pub rsp: u64,
+    pub ss: u64,
+}
+
+impl From<&InterruptFrame> for StackFrame {
+    fn from(frame: &InterruptFrame) -> Self {
+        Self {
+            rax: frame.rax, rbx: frame.rbx, rcx: frame.rcx, rdx: frame.rdx,
+            rsi: frame.rsi, rdi: frame.rdi, rbp: frame.rbp,
+            r8: frame.r8, r9: frame.r9, r10: frame.r10, r11: frame.r11,
+            r12: frame.r12, r13: frame.r13, r14: frame.r14, r15: frame.r15,
+            rflags: frame.rflags, rip: frame.rip, rsp: frame.rsp,
+        }
+    }
+}
+
+impl InterruptFrame {
+    /// iretqで戻るときのレジスタを書き換える（デバッガが変更した値を反映する）
+    pub fn set_registers(&mut self, regs: &StackFrame) {
+        self.rax = regs.rax;
+        self.rbx = regs.rbx;
+        self.rcx = regs.rcx;
+        self.rdx = regs.rdx;
+        self.rsi = regs.rsi;
+        self.rdi = regs.rdi;
+        self.rbp = regs.rbp;
+        self.r8 = regs.r8;
+        self.r9 = regs.r9;
+        self.r10 = regs.r10;
+        self.r11 = regs.r11;
+        self.r12 = regs.r12;
+        self.r13 = regs.r13;
+        self.r14 = regs.r14;
+        self.r15 = regs.r15;
+        self.rflags = regs.rflags;
+        self.rip = regs.rip;
+        self.rsp = regs.rsp;
+    }
+}
+
+/// 最後に起きたCPU例外のレジスタ（パニックハンドラがダンプする）
+static EXCEPTION_FRAME: SpinLock<Option<InterruptFrame>> = SpinLock::new(None);
+
+/// 最後に起きたCPU例外のレジスタ
+pub fn exception_frame() -> Option<InterruptFrame> {
+    EXCEPTION_FRAME.try_lock().and_then(|frame| *frame)
+}
+
+/// 保存した例外のレジスタを捨てる（カーネル内テストで例外の後に次のテストへ進むとき）
+#[cfg(all(test, target_os = "none"))]
+pub fn clear_exception_frame() {
+    if let Some(mut frame) = EXCEPTION_FRAME.try_lock() {
+        *frame = None;
+    }
+}
+
+/// IRQごとのハンドラ（0は未登録）
+static IRQ_HANDLERS: [AtomicUsize; IRQ_COUNT as usize] = [const { AtomicUsize::new(0) }; IRQ_COUNT as usize];
+
+/// IRQごとの受け付けた回数（スプリアスを除く）
+static IRQ_COUNTS: [AtomicU64; IRQ_COUNT as usize] = [const { AtomicU64::new(0) }; IRQ_COUNT as usize];
+
+/// CPUに登録するIDT
+static IDT: SpinLock<Idt> = SpinLock::new(Idt::new());
+
+/// 8259 PIC
+pub static PIC: SpinLock<Pic8259> = SpinLock::new(Pic8259::new());
+
+/// IRQハンドラを登録する（割り込みコンテキストで呼ばれるので短く済ませること）
+pub fn set_irq_handler(irq: u8, handler: fn()) {
+    IRQ_HANDLERS[usize::from(irq)].store(handler as usize, Ordering::Release);
+}
+
+/// IRQごとの統計（モニタの `irq` コマンド用）
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct IrqStat {
+    /// 受け付けた回数
+    pub count: u64,
+    /// ハンドラが登録されているか
+    pub has_handler: bool,
+}
+
+/// すべてのIRQの統計
+pub fn irq_stats() -> [IrqStat; IRQ_COUNT as usize] {
+    core::array::from_fn(|irq| IrqStat {
+        count: IRQ_COUNTS[irq].load(Ordering::Relaxed),
+        has_handler: IRQ_HANDLERS[irq].load(Ordering::Relaxed) != 0,
+    })
+}
+
+/// IRQハンドラを呼ぶ。登録されていなければ何もしない
+fn call_irq_handler(irq: u8) -> bool {
+    IRQ_COUNTS[usize::from(irq)].fetch_add(1, Ordering::Relaxed);
+    let raw = IRQ_HANDLERS[usize::from(irq)].load(Ordering::Acquire);
+    if raw == 0 {
+        return false;
+    }
+    // 安全性: set_irq_handler() で fn() から変換した値だけが入っている
+    let handler = unsafe { core::mem::transmute::<usize, fn()>(raw) };
+    handler();
+    true
+}
+
+/// IDTとPICを初期化してCPUに登録する（割り込みはまだ禁止のまま）
+///
+/// # Safety
+/// 起動時に一度だけ、割り込み禁止中に gdt::init() の後で呼ぶこと
+#[cfg(any(not(test), target_os = "none"))]
+pub unsafe fn init() {
+    extern "C" {
+        static __isr_stub_table: [u64; STUB_COUNT];
+        fn __ipc_stub();
+    }
+
+    // gdt::init() で読み込んだカーネルのコードセグメントに飛ぶ
+    let mut idt = IDT.lock();
+    let stubs = &*core::ptr::addr_of!(__isr_stub_table);
+    for (index, &stub) in stubs.iter().enumerate() {
+        idt.set(stub_vector(index), IdtEntry::new(stub, super::gdt::KERNEL_CODE));
+    }
+    idt.set(IPC_VECTOR, IdtEntry::user(__ipc_stub as *const () as u64, super::gdt::KERNEL_CODE));
+    // 安全性: IDT は static なのでアドレスは変わらない
+    let idt: &'static Idt = &*(&*idt as *const Idt);
+    idt.load();
+
+    PIC.lock().init();
+}
+
+/// スタブの表の `index` 番目のベクタ（例外はそのまま、IRQは IRQ_BASE から）
+fn stub_vector(index: usize) -> u8 {
+    match u8::try_from(index) {
+        Ok(index) if index < EXCEPTION_COUNT => index,
+        _ => IRQ_BASE + (index - usize::from(EXCEPTION_COUNT)) as u8,
+    }
+}
+
+/// すべての割り込みの共通処理（スタブから呼ばれる）
+#[cfg(any(not(test), target_os = "none"))]
+extern "C" fn interrupt_dispatch(frame: &mut InterruptFrame) {
+    let vector = frame.vector as u8;
+    if vector < EXCEPTION_COUNT {
+        // コピーオンライトのページへの書き込みなら、コピーしてからもう一度実行する
+        // （システムコールの中でカーネルがユーザーのバッファに書いたときも同じ。CR0.WP を立ててある）
+        if vector == PAGE_FAULT && crate::user::copy_on_write(frame.error_code, super::paging::read_cr2()) {
+            return;
+        }
+        // ユーザープロセスの例外はカーネルの故障ではないので、そのプロセスだけを止める
+        if frame.cs & 3 == 3 {
+            crate::user::fault(frame);
+        }
+        // GDBスタブが有効なら #DB と #BP はデバッガに渡して、そのまま再開する
+        if crate::gdb::handle_exception(frame) {
+            return;
+        }
+        if let Some(mut saved) = EXCEPTION_FRAME.try_lock() {
+            *saved = Some(*frame);
+        }
+        panic!(
+            "CPU exception: {} (vector {}) error={:#x} rip={} rsp={:#x}",
+            exception_name(vector),
+            vector,
+            frame.error_code,
+            crate::symbols::Symbolized(frame.rip),
+            frame.rsp
+        );
+    }
+
+    // IPCトラップ（rax: トラップ番号、rdi: 相手、rsi: メッセージ）
+    if vector == IPC_VECTOR {
+        let result = crate::ipc::do_ipc(frame.rax, frame.rdi as i32, frame.rsi);
+        crate::user::finish_trap(frame, result);
+        return;
+    }
+
+    let irq = vector - IRQ_BASE;
+    if pic::is_spurious(irq) {
+        return;
+    }
+    call_irq_handler(irq);
+    pic::end_of_interrupt(irq);
+    // ユーザーモードで時間量子を使い切ったら、ディスパッチャに戻ってほかのプロセスに譲る
+    if irq == super::pit::TIMER_IRQ && frame.cs & 3 == 3 {
+        crate::user::tick(frame);
+    }
+}
+
+// 割り込みの入口（ベクタごとのスタブ）
+//
+// CPUがエラーコードを積むのは 8, 10〜14, 17, 21, 29, 30 番の例外だけなので、
+// それ以外の例外とIRQでは0を積んで、スタックの形をそろえる。
+// CPUは割り込み時にRSPを16バイト境界にそろえてから5語を積むので、
+// ここで17語積むと合計22語（176バイト）になり、callの直前で16バイト境界になる。
+#[cfg(any(not(test), target_os = "none"))]
+core::arch::global_asm!(
+    ".macro isr_stub_noerr n",
+    "__isr_stub_\\n:",
+    "    push 0",
+    "    push \\n",
+    "    jmp __isr_common",
+    ".endm",
+    ".macro isr_stub_err n",
+    "__isr_stub_\\n:",
+    "    push \\n",
+    "    jmp __isr_common",
+    ".endm",
+    "",
+    ".section .text",
+    ".irp n, 0,1,2,3,4,5,6,7,9,15,16,18,19,20,22,23,24,25,26,27,28,31",
+    "    isr_stub_noerr \\n",
+    ".endr",
+    ".irp n, 8,10,11,12,13,14,17,21,29,30",
+    "    isr_stub_err \\n",
+    ".endr",
+    ".irp n, 0,1,2,3,4,5,6,7,8,9,10,11,12,13,14,15",
+    "__irq_stub_\\n:",
+    "    push 0",
+    "    push {irq_base} + \\n",
+    "    jmp __isr_common",
+    ".endr",
+    "",
+    // IPCトラップ（ring 3 から int 0x21 で入る）
+    ".global __ipc_stub",
+    "__ipc_stub:",
+    "    push 0",
+    "    push {ipc_vector}",
+    "    jmp __isr_common",
+    "",
+    "__isr_common:",
+    "    push rax",
+    "    push rbx",
+    "    push rcx",
+    "    push rdx",
+    "    push rsi",
+    "    push rdi",
+    "    push rbp",
+    "    push r8",
+    "    push r9",
+    "    push r10",
+    "    push r11",
+    "    push r12",
+    "    push r13",
+    "    push r14",
+    "    push r15",
+    "    mov rdi, rsp",
+    "    cld",
+    "    call {dispatch}",
+    "    pop r15",
+    "    pop r14",
+    "    pop r13",
+    "    pop r12",
+    "    pop r11",
+    "    pop r10",
+    "    pop r9",
+    "    pop r8",
+    "    pop rbp",
+    "    pop rdi",
+    "    pop rsi",
+    "    pop rdx",
+    "    pop rcx",
+    "    pop rbx",
+    "    pop rax",
+    "    add rsp, 16",
+    "    iretq",
+    "",
+    ".section .rodata",
+    ".balign 8",
+    ".global __isr_stub_table",
+    "__isr_stub_table:",
+    ".irp n, 0,1,2,3,4,5,6,7,8,9,10,11,12,13,14,15,16,17,18,19,20,21,22,23,24,25,26,27,28,29,30,31",
+    "    .quad __isr_stub_\\n",
+    ".endr",
+    ".irp n, 0,1,2,3,4,5,6,7,8,9,10,11,12,13,14,15",
+    "    .quad __irq_stub_\\n",
+    ".endr",
+    ".section .text",
+    dispatch = sym interrupt_dispatch,
+    irq_base = const IRQ_BASE,
+    ipc_vector = const IPC_VECTOR,
+);
+
+// ===== テスト =====
+#[cfg(all(test, not(target_os = "none")))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_idt_entry_layout() {
+        assert_eq!(core::mem::size_of::<IdtEntry>(), 16, "IDTエントリは16バイト");
+        assert_eq!(core::mem::size_of::<Idt>(), 16 * 256);
+    }
+
+    #[test]
+    fn test_idt_entry_splits_address() {
+        let entry = IdtEntry::new(0xffff_ffff_8012_3456, 0x38);
+        assert_eq!(entry.offset_low, 0x3456);
+        assert_eq!(entry.offset_middle, 0x8012);
+        assert_eq!(entry.offset_high, 0xffff_ffff);
+        assert_eq!(entry.handler(), 0xffff_ffff_8012_3456, "分割したアドレスを復元できる");
+        assert_eq!(entry.selector, 0x38);
+        assert!(entry.is_present(), "登録したエントリはPresent");
+        assert!(!IdtEntry::MISSING.is_present());
+        assert!(!entry.is_user(), "カーネルのゲートはDPL=0");
+        let user = IdtEntry::user(0x1000, 8);
+        assert!(user.is_present() && user.is_user(), "IPCトラップのゲートはDPL=3");
+        assert_eq!(user.handler(), 0x1000);
+    }
+
+    #[test]
+    fn test_stub_vectors() {
+        assert_eq!(stub_vector(0), 0);
+        assert_eq!(stub_vector(31), 31);
+        assert_eq!(stub_vector(32), IRQ_BASE, "IRQ 0");
+        assert_eq!(stub_vector(STUB_COUNT - 1), IRQ_BASE + IRQ_COUNT - 1);
+        let vectors: Vec<u8> = (0..STUB_COUNT).map(stub_vector).collect();
+        assert!(!vectors.contains(&IPC_VECTOR), "IPCトラップのベクタはIRQと重ならない");
+    }
+
+    #[test]
+    fn test_idt_set_and_get() {
+        let mut idt = Idt::new();
+        idt.set(0x24, IdtEntry::new(0x1000, 8));
+        assert_eq!(idt.get(0x24).handler(), 0x1000);
+        assert!(!idt.get(0x25).is_present(), "登録していないベクタは空");
+    }
+
+    #[test]
+    fn test_interrupt_frame_layout() {
+        // スタブは汎用レジスタ15個 + ベクタ + エラーコード、CPUは5語を積む
+        assert_eq!(core::mem::size_of::<InterruptFrame>(), 22 * 8);
+        assert_eq!(core::mem::offset_of!(InterruptFrame, vector), 15 * 8);
+        assert_eq!(core::mem::offset_of!(InterruptFrame, rip), 17 * 8);
+        assert_eq!(core::mem::size_of::<InterruptFrame>() % 16, 0, "callの直前で16バイト境界");
+    }
+
+    #[test]
+    fn test_interrupt_frame_to_stack_frame() {
+        let frame = InterruptFrame { rax: 1, r15: 15, rip: 0x1000, rsp: 0x2000, rflags: 0x202, ..Default::default() };
+        let stack_frame = StackFrame::from(&frame);
+        assert_eq!(stack_frame.rax, 1);
+        assert_eq!(stack_frame.r15, 15);
+        assert_eq!((stack_frame.rip, stack_frame.rsp, stack_frame.rflags), (0x1000, 0x2000, 0x202), "CPUが積んだ値も写す");
+    }
+
+    #[test]
+    fn test_set_registers() {
+        let mut frame = InterruptFrame { vector: 3, cs: 8, ss: 0x10, ..Default::default() };
+        let mut regs = StackFrame::new();
+        regs.rbx = 2;
+        regs.r12 = 12;
+        regs.rip = 0x1001;
+        regs.rflags = 0x302;
+        frame.set_registers(&regs);
+        assert_eq!((frame.rbx, frame.r12, frame.rip, frame.rflags), (2, 12, 0x1001, 0x302));
+        assert_eq!((frame.vector, frame.cs, frame.ss), (3, 8, 0x10), "セグメントとベクタはそのまま");
+        assert_eq!(StackFrame::from(&frame).to_string(), regs.to_string(), "StackFrameに戻すと同じ値");
+    }
+
+    #[test]
+    fn test_exception_names() {
+        assert_eq!(exception_name(13), "General Protection");
+        assert_eq!(exception_name(14), "Page Fault");
+        assert_eq!(exception_name(200), "Unknown");
+    }
+
+    #[test]
+    fn test_irq_handler_registry() {
+        use core::sync::atomic::AtomicBool;
+        static CALLED: AtomicBool = AtomicBool::new(false);
+        assert!(!call_irq_handler(9), "未登録のIRQは何もしない");
+        set_irq_handler(9, || CALLED.store(true, Ordering::SeqCst));
+        assert!(call_irq_handler(9));
+        assert!(CALLED.load(Ordering::SeqCst), "登録したハンドラが呼ばれる");
+    }
+
+    #[test]
+    fn test_irq_stats() {
+        let before = irq_stats()[10];
+        assert!(!before.has_handler);
+        call_irq_handler(10);
+        set_irq_handler(10, || {});
+        call_irq_handler(10);
+        let after = irq_stats()[10];
+        assert_eq!(after.count, before.count + 2, "ハンドラがなくても受け付けた回数は数える");
+        assert!(after.has_handler);
+    }
+}
+
+// ===== カーネル内テスト =====
+#[cfg(all(test, target_os = "none"))]
+mod kernel_tests {
+    use super::*;
+    use crate::testing::ShouldPanic;
+
+    #[test_case]
+    fn test_idt_is_loaded() {
+        #[repr(C, packed)]
+        struct Pointer {
+            limit: u16,
+            base: u64,
+        }
+        let mut pointer = Pointer { limit: 0, base: 0 };
+        unsafe { core::arch::asm!("sidt [{}]", in(reg) &mut pointer, options(nostack, preserves_flags)) };
+        let (limit, base) = (pointer.limit, pointer.base);
+        assert_eq!(base, &*IDT.lock() as *const Idt as u64, "IDTRは自分のIDTを指す");
+        assert_eq!(usize::from(limit) + 1, core::mem::size_of::<Idt>());
+    }
+
+    #[test_case]
+    fn test_breakpoint_exception_panics() -> ShouldPanic {
+        // GDBスタブ（gdb=）が無効なら、int3 はほかの例外と同じくパニックになる
+        unsafe { core::arch::asm!("int3", options(nomem, nostack)) };
+        ShouldPanic
+    }
+}
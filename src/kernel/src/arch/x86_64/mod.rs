@@ -2,7 +2,16 @@
 //! 
 //! Intel/AMD 64bitプロセッサ用の実装
 
+pub mod backtrace;
 mod context;
+pub mod gdt;
+pub mod interrupts;
+pub mod paging;
+pub mod pic;
+pub mod pit;
+pub mod port;
+pub mod qemu;
+pub mod syscall;
 
 pub use context::Context;
 pub use context::StackFrame;
@@ -38,4 +47,112 @@ impl CpuOps for X86_64 {
         // TODO: ローカルAPICからCPU IDを取得
         0
     }
-}
\ No newline at end of file
+}
+
+/// RFLAGSのIF（割り込み許可）ビットが立っているか
+#[inline]
+pub fn interrupts_enabled() -> bool {
+    let rflags: u64;
+    unsafe {
+        core::arch::asm!("pushfq", "pop {}", out(reg) rflags, options(nomem, preserves_flags));
+    }
+    rflags & (1 << 9) != 0
+}
+
+/// 次の割り込みが来るまでCPUを止める（割り込みは許可しておくこと）
+#[inline]
+pub fn halt() {
+    unsafe { core::arch::asm!("hlt", options(nomem, nostack, preserves_flags)) };
+}
+
+/// 割り込みを禁止して止まる（パニック時など、二度と戻らないとき）
+pub fn halt_forever() -> ! {
+    loop {
+        unsafe { core::arch::asm!("cli", "hlt", options(nomem, nostack)) };
+    }
+}
+
+/// 現在のページテーブルで `addr` がマップされているか
+///
+/// デバッガが任意のアドレスを読み書きしてもページフォルトを起こさないように、
+/// 物理メモリマップ（`PHYSICAL_MEMORY_OFFSET`）経由でCR3からたどる。
+#[cfg(any(not(test), target_os = "none"))]
+pub fn is_mapped(addr: u64) -> bool {
+    use crate::arch::AddressSpace;
+
+    paging::Mapper::active(paging::KernelFrames).translate(addr).is_some()
+}
+
+/// MSR（モデル固有レジスタ）を読む
+///
+/// # Safety
+/// CPUにないMSRを読むと #GP になる
+pub unsafe fn read_msr(msr: u32) -> u64 {
+    let (low, high): (u32, u32);
+    unsafe {
+        core::arch::asm!("rdmsr", in("ecx") msr, out("eax") low, out("edx") high, options(nomem, nostack, preserves_flags));
+    }
+    (u64::from(high) << 32) | u64::from(low)
+}
+
+/// MSR（モデル固有レジスタ）に書く
+///
+/// # Safety
+/// CPUにないMSRに書くと #GP になる。書いた値でCPUの動作が変わる
+pub unsafe fn write_msr(msr: u32, value: u64) {
+    unsafe {
+        core::arch::asm!(
+            "wrmsr",
+            in("ecx") msr,
+            in("eax") value as u32,
+            in("edx") (value >> 32) as u32,
+            options(nostack, preserves_flags),
+        );
+    }
+}
+
+/// ring 3 から割り込みやシステムコールで入ったときのカーネルスタックを設定する
+///
+/// コンテキストスイッチで、次に動くプロセスのカーネルスタックの先頭（一番上のアドレス）を渡す。
+/// Linux: update_task_stack()（TSSのsp0を書き換える）
+pub fn set_kernel_stack(top: u64) {
+    gdt::set_kernel_stack(top);
+    syscall::set_kernel_stack(top);
+}
+
+/// CR0.WP: ring 0 からの書き込みも、ページの書き込み禁止に従わせる
+const CR0_WP: u64 = 1 << 16;
+
+/// CR0.WP（書き込み保護）を立てる
+///
+/// 立っていないと、システムコールの中でカーネルがユーザーのバッファに書いても #PF にならず、
+/// fork した親子がコピーオンライトで共有しているフレームをそのまま書き換えてしまう。
+/// Multibootの入口（trampoline.rs）は立てるが、UEFIのファームウェアが立てているとは限らない。
+///
+/// # Safety
+/// カーネル自身が読み取り専用のページに書いていないこと
+pub unsafe fn enable_write_protect() {
+    let cr0: u64;
+    unsafe {
+        core::arch::asm!("mov {}, cr0", out(reg) cr0, options(nomem, nostack, preserves_flags));
+        core::arch::asm!("mov cr0, {}", in(reg) cr0 | CR0_WP, options(nostack, preserves_flags));
+    }
+}
+
+/// CR0.WP（書き込み保護）を一時的に外して `f` を実行する
+///
+/// 読み取り専用でマップした .text にブレークポイント（int3）を書き込むときに使う。
+///
+/// # Safety
+/// `f` の間はカーネルが読み取り専用のページにも書けてしまうので、
+/// 割り込みを禁止した状態で、必要な書き込みだけをすること
+pub unsafe fn without_write_protect<R>(f: impl FnOnce() -> R) -> R {
+    let cr0: u64;
+    unsafe {
+        core::arch::asm!("mov {}, cr0", out(reg) cr0, options(nomem, nostack, preserves_flags));
+        core::arch::asm!("mov cr0, {}", in(reg) cr0 & !CR0_WP, options(nostack, preserves_flags));
+    }
+    let result = f();
+    unsafe { core::arch::asm!("mov cr0, {}", in(reg) cr0, options(nostack, preserves_flags)) };
+    result
+}
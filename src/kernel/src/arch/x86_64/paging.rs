@@ -0,0 +1,678 @@
+//! 4段ページテーブル（PML4 → PDPT → PD → PT）
+//!
+//! ブートローダー（またはMultibootのトランポリン）が作ったページテーブルを引き継ぎ、
+//! カーネルが実行中にページをマップ・アンマップできるようにする。
+//! ページテーブル用のフレームは物理フレームアロケータから取り、
+//! 物理メモリマップ（`PHYSICAL_MEMORY_OFFSET`）経由で読み書きする。
+//!
+//! MINIX 3: servers/vm/pagetable.c（pt_writemap(), pt_ptalloc()）
+//! Linux: arch/x86/mm/pgtable.c
+//! MikanOS: paging.cpp（SetupIdentityPageTable() で恒等マップを作るだけだった）
+
+use crate::arch::{AddressSpace, MapError, MapFlags, PageSize};
+use crate::memory::slab::ObjectCache;
+use crate::memory::Frame;
+
+/// ページテーブル1枚のエントリ数
+pub const ENTRY_COUNT: usize = 512;
+
+/// カーネルの半分が始まるPML4のインデックス（0xffff_8000_0000_0000 から上）
+pub const KERNEL_HALF: usize = 256;
+
+/// ページテーブルエントリ
+///
+/// 物理アドレス（ビット12〜51）とフラグを1つの64ビット値に詰めたもの
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(transparent)]
+pub struct PageTableEntry(u64);
+
+impl PageTableEntry {
+    /// ビット0: 有効なエントリ
+    pub const PRESENT: u64 = 1 << 0;
+    /// ビット1: 書き込み可能
+    pub const WRITABLE: u64 = 1 << 1;
+    /// ビット2: ring 3からアクセス可能
+    pub const USER: u64 = 1 << 2;
+    /// ビット3: ライトスルー
+    pub const WRITE_THROUGH: u64 = 1 << 3;
+    /// ビット4: キャッシュ無効（MMIO用）
+    pub const NO_CACHE: u64 = 1 << 4;
+    /// ビット5: アクセスされた（CPUが立てる）
+    pub const ACCESSED: u64 = 1 << 5;
+    /// ビット6: 書き込まれた（CPUが立てる）
+    pub const DIRTY: u64 = 1 << 6;
+    /// ビット7: PDPT/PDのエントリで1GiB/2MiBページ
+    pub const HUGE_PAGE: u64 = 1 << 7;
+    /// ビット8: CR3を切り替えてもTLBから消さない
+    pub const GLOBAL: u64 = 1 << 8;
+    /// ビット63: 実行禁止（EFER.NXE が有効なときだけ使える）
+    pub const NO_EXECUTE: u64 = 1 << 63;
+
+    /// 物理アドレスの部分
+    const ADDRESS_MASK: u64 = 0x000f_ffff_ffff_f000;
+
+    /// 空の（無効な）エントリ
+    pub const fn empty() -> Self {
+        Self(0)
+    }
+
+    /// 物理アドレスとフラグから作成
+    pub const fn new(addr: u64, flags: u64) -> Self {
+        Self((addr & Self::ADDRESS_MASK) | flags)
+    }
+
+    /// 有効なエントリか
+    pub fn is_present(&self) -> bool {
+        self.is_set(Self::PRESENT)
+    }
+
+    /// 1GiB/2MiBページのエントリか
+    pub fn is_huge(&self) -> bool {
+        self.is_set(Self::HUGE_PAGE)
+    }
+
+    /// フラグが設定されているか確認
+    pub fn is_set(&self, flag: u64) -> bool {
+        (self.0 & flag) != 0
+    }
+
+    /// フラグを設定
+    pub fn set(&mut self, flag: u64) {
+        self.0 |= flag;
+    }
+
+    /// 指している物理アドレス（次の段のテーブル、またはページ）
+    pub fn address(&self) -> u64 {
+        self.0 & Self::ADDRESS_MASK
+    }
+
+    /// 物理アドレス以外の部分
+    pub fn flags(&self) -> u64 {
+        self.0 & !Self::ADDRESS_MASK
+    }
+}
+
+/// ページテーブル1枚（4KiB）
+#[repr(C, align(4096))]
+pub struct PageTable {
+    pub entries: [PageTableEntry; ENTRY_COUNT],
+}
+
+impl PageTable {
+    /// すべて無効なページテーブル
+    pub const fn new() -> Self {
+        Self { entries: [PageTableEntry::empty(); ENTRY_COUNT] }
+    }
+}
+
+/// 仮想アドレスから各段のインデックスを取り出す
+/// [PML4, PDPT, PD, PT]
+pub fn table_indices(virt: u64) -> [usize; 4] {
+    [
+        ((virt >> 39) & 0x1ff) as usize,
+        ((virt >> 30) & 0x1ff) as usize,
+        ((virt >> 21) & 0x1ff) as usize,
+        ((virt >> 12) & 0x1ff) as usize,
+    ]
+}
+
+/// 上位16ビットがビット47の符号拡張になっているか（そうでなければ #GP）
+pub fn is_canonical(virt: u64) -> bool {
+    ((virt as i64) << 16 >> 16) as u64 == virt
+}
+
+/// ページテーブル用のフレームの確保と、物理アドレスからのアクセス
+pub trait TableFrames {
+    /// ゼロクリアしたフレームを確保する
+    fn alloc_table(&mut self) -> Option<Frame>;
+
+    /// フレームにあるページテーブルへのポインタ
+    /// （カーネルでは物理メモリマップ経由、テストではVec上の位置）
+    fn table_ptr(&self, frame: Frame) -> *mut PageTable;
+
+    /// `alloc_table()` で確保したフレームを返す
+    fn free_table(&mut self, frame: Frame);
+}
+
+/// 1つのアドレス空間（PML4）のページをマップ・アンマップする
+pub struct Mapper<F: TableFrames> {
+    pml4: Frame,
+    frames: F,
+    /// 今のCR3のアドレス空間か（そうならアンマップのときにTLBを消す）
+    active: bool,
+    /// 実行禁止ビットを使えるか
+    no_execute: bool,
+}
+
+impl<F: TableFrames> Mapper<F> {
+    /// PML4が `pml4` にあるアドレス空間を操作する
+    pub fn new(pml4: Frame, frames: F) -> Self {
+        Self { pml4, frames, active: false, no_execute: no_execute_enabled() }
+    }
+
+    /// 上半分（`KERNEL_HALF` 以降）のエントリを `kernel` のPML4からコピーした、新しいアドレス空間
+    ///
+    /// カーネルの半分の下の段のテーブルは共有するので、カーネルの領域はどのアドレス空間でも同じに見える。
+    /// ただし、後からカーネルのPML4に足したエントリはコピーしたアドレス空間には現れない。
+    /// MINIX 3: servers/vm/pagetable.c の pt_new() と pt_mapkernel()
+    pub fn with_kernel_half(mut frames: F, kernel: Frame) -> Result<Self, MapError> {
+        let pml4 = frames.alloc_table().ok_or(MapError::OutOfFrames)?;
+        let mut mapper = Self::new(pml4, frames);
+        for index in KERNEL_HALF..ENTRY_COUNT {
+            let entry = mapper.entry(kernel, index);
+            mapper.set_entry(pml4, index, entry);
+        }
+        Ok(mapper)
+    }
+
+    /// 下半分のページテーブルとPML4をすべて返す（マップしていたページのフレームは返さない）
+    ///
+    /// 共有しているカーネルの半分には触らない。使っていた `TableFrames` を返す。
+    /// MINIX 3: servers/vm/pagetable.c の pt_free()
+    pub fn destroy(mut self) -> F {
+        for index in 0..KERNEL_HALF {
+            self.free_tables(self.pml4, index, 0);
+        }
+        self.frames.free_table(self.pml4);
+        self.frames
+    }
+
+    /// `table`（`level` 段目）の `index` 番目のエントリから下のテーブルを返す
+    fn free_tables(&mut self, table: Frame, index: usize, level: usize) {
+        let entry = self.entry(table, index);
+        // 最終段（PT）のエントリと、大きなページはページそのもの
+        if !entry.is_present() || entry.is_huge() || level == 3 {
+            return;
+        }
+        let next = Frame::containing_address(entry.address());
+        for child in 0..ENTRY_COUNT {
+            self.free_tables(next, child, level + 1);
+        }
+        self.set_entry(table, index, PageTableEntry::empty());
+        self.frames.free_table(next);
+    }
+
+    /// 今のCR3のアドレス空間を操作する
+    #[cfg(any(not(test), target_os = "none"))]
+    pub fn active(frames: F) -> Self {
+        Self { active: true, ..Self::new(Frame::containing_address(read_cr3()), frames) }
+    }
+
+    /// PML4のフレーム（CR3に設定する値）
+    pub fn pml4(&self) -> Frame {
+        self.pml4
+    }
+
+    /// `table` の `index` 番目のエントリ
+    fn entry(&self, table: Frame, index: usize) -> PageTableEntry {
+        unsafe { (*self.frames.table_ptr(table)).entries[index] }
+    }
+
+    fn set_entry(&mut self, table: Frame, index: usize, entry: PageTableEntry) {
+        unsafe { (*self.frames.table_ptr(table)).entries[index] = entry };
+    }
+
+    /// `table` の `index` 番目のエントリが指す次段のテーブルを返す（なければ作る）
+    fn next_table(&mut self, table: Frame, index: usize, user: bool) -> Result<Frame, MapError> {
+        let mut entry = self.entry(table, index);
+        if !entry.is_present() {
+            let frame = self.frames.alloc_table().ok_or(MapError::OutOfFrames)?;
+            // 中間の段は緩い権限にしておき、最終段で制限する
+            entry = PageTableEntry::new(frame.start_address(), PageTableEntry::PRESENT | PageTableEntry::WRITABLE);
+        } else if entry.is_huge() {
+            return Err(MapError::AlreadyMapped);
+        }
+        // ユーザーのページは、途中のすべての段でもUSERが必要
+        if user {
+            entry.set(PageTableEntry::USER);
+        }
+        self.set_entry(table, index, entry);
+        Ok(Frame::containing_address(entry.address()))
+    }
+
+    /// 仮想アドレスをマップしている最終段のエントリを探す
+    /// （エントリのあるテーブル、インデックス、ページの大きさ）
+    fn find_leaf(&self, virt: u64) -> Option<(Frame, usize, PageSize)> {
+        if !is_canonical(virt) {
+            return None;
+        }
+        let indices = table_indices(virt);
+        let mut table = self.pml4;
+        for (level, index) in indices.into_iter().enumerate() {
+            let entry = self.entry(table, index);
+            if !entry.is_present() {
+                return None;
+            }
+            match level {
+                1 if entry.is_huge() => return Some((table, index, PageSize::Size1G)),
+                2 if entry.is_huge() => return Some((table, index, PageSize::Size2M)),
+                3 => return Some((table, index, PageSize::Size4K)),
+                _ => table = Frame::containing_address(entry.address()),
+            }
+        }
+        None
+    }
+
+    /// 仮想アドレスをマップしているエントリとページの大きさ
+    pub fn lookup(&self, virt: u64) -> Option<(PageTableEntry, PageSize)> {
+        self.find_leaf(virt).map(|(table, index, size)| (self.entry(table, index), size))
+    }
+
+    /// `MapFlags` を最終段のエントリのフラグにする
+    fn leaf_flags(&self, flags: MapFlags, size: PageSize) -> u64 {
+        let mut bits = PageTableEntry::PRESENT;
+        if flags.is_set(MapFlags::WRITABLE) {
+            bits |= PageTableEntry::WRITABLE;
+        }
+        if flags.is_set(MapFlags::USER) {
+            bits |= PageTableEntry::USER;
+        }
+        if flags.is_set(MapFlags::GLOBAL) {
+            bits |= PageTableEntry::GLOBAL;
+        }
+        if !flags.is_set(MapFlags::EXECUTABLE) && self.no_execute {
+            bits |= PageTableEntry::NO_EXECUTE;
+        }
+        if size != PageSize::Size4K {
+            bits |= PageTableEntry::HUGE_PAGE;
+        }
+        bits
+    }
+
+    /// TLBから `virt` のエントリを消す
+    fn flush(&self, virt: u64) {
+        if self.active {
+            unsafe { invlpg(virt) };
+        }
+    }
+}
+
+impl<F: TableFrames> AddressSpace for Mapper<F> {
+    fn map(&mut self, page: u64, frame: Frame, size: PageSize, flags: MapFlags) -> Result<(), MapError> {
+        if !is_canonical(page) {
+            return Err(MapError::NonCanonical);
+        }
+        if !page.is_multiple_of(size.bytes()) || !frame.start_address().is_multiple_of(size.bytes()) {
+            return Err(MapError::Misaligned);
+        }
+        let [i4, i3, i2, i1] = table_indices(page);
+        let user = flags.is_set(MapFlags::USER);
+        let pdpt = self.next_table(self.pml4, i4, user)?;
+        let (table, index) = match size {
+            PageSize::Size1G => (pdpt, i3),
+            PageSize::Size2M => (self.next_table(pdpt, i3, user)?, i2),
+            PageSize::Size4K => {
+                let pd = self.next_table(pdpt, i3, user)?;
+                (self.next_table(pd, i2, user)?, i1)
+            }
+        };
+        // 大きなページを置く場所に、下の段のテーブルがあっても失敗にする
+        if self.entry(table, index).is_present() {
+            return Err(MapError::AlreadyMapped);
+        }
+        // 無効だったエントリはTLBに載らないので、マップではTLBを消さなくてよい
+        let entry = PageTableEntry::new(frame.start_address(), self.leaf_flags(flags, size));
+        self.set_entry(table, index, entry);
+        Ok(())
+    }
+
+    fn unmap(&mut self, page: u64) -> Result<(Frame, PageSize), MapError> {
+        if !is_canonical(page) {
+            return Err(MapError::NonCanonical);
+        }
+        let (table, index, size) = self.find_leaf(page).ok_or(MapError::NotMapped)?;
+        if !page.is_multiple_of(size.bytes()) {
+            return Err(MapError::Misaligned);
+        }
+        let entry = self.entry(table, index);
+        self.set_entry(table, index, PageTableEntry::empty());
+        self.flush(page);
+        Ok((Frame::containing_address(entry.address()), size))
+    }
+
+    fn translate(&self, virt: u64) -> Option<u64> {
+        let (entry, size) = self.lookup(virt)?;
+        Some(entry.address() + (virt & (size.bytes() - 1)))
+    }
+
+    fn protect(&mut self, page: u64, flags: MapFlags) -> Result<(), MapError> {
+        if !is_canonical(page) {
+            return Err(MapError::NonCanonical);
+        }
+        let (table, index, size) = self.find_leaf(page).ok_or(MapError::NotMapped)?;
+        if !page.is_multiple_of(size.bytes()) {
+            return Err(MapError::Misaligned);
+        }
+        let entry = PageTableEntry::new(self.entry(table, index).address(), self.leaf_flags(flags, size));
+        self.set_entry(table, index, entry);
+        // 書き込みを禁止したときは、古い（書ける）エントリがTLBに残っていてはいけない
+        self.flush(page);
+        Ok(())
+    }
+}
+
+/// ページテーブルのキャッシュ（物理メモリマップ上にあるので、物理アドレスもすぐわかる）
+pub static PAGE_TABLE_CACHE: ObjectCache<PageTable> = ObjectCache::new("page_table");
+
+/// ページテーブルのキャッシュと物理メモリマップを使う `TableFrames`
+#[cfg(any(not(test), target_os = "none"))]
+pub struct KernelFrames;
+
+#[cfg(any(not(test), target_os = "none"))]
+impl TableFrames for KernelFrames {
+    fn alloc_table(&mut self) -> Option<Frame> {
+        let table = PAGE_TABLE_CACHE.alloc_uninit()?;
+        unsafe { table.write(PageTable::new()) };
+        Some(Frame::containing_address(table.as_ptr() as u64 - boot_info::PHYSICAL_MEMORY_OFFSET))
+    }
+
+    fn table_ptr(&self, frame: Frame) -> *mut PageTable {
+        (boot_info::PHYSICAL_MEMORY_OFFSET + frame.start_address()) as *mut PageTable
+    }
+
+    fn free_table(&mut self, frame: Frame) {
+        let table = core::ptr::NonNull::new(self.table_ptr(frame)).expect("page table in physical map");
+        unsafe { PAGE_TABLE_CACHE.free_uninit(table) };
+    }
+}
+
+/// 今のページテーブル（PML4）の物理アドレス
+#[cfg(any(not(test), target_os = "none"))]
+pub fn read_cr3() -> u64 {
+    let cr3: u64;
+    unsafe { core::arch::asm!("mov {}, cr3", out(reg) cr3, options(nomem, nostack, preserves_flags)) };
+    cr3 & PageTableEntry::ADDRESS_MASK
+}
+
+/// 最後のページフォルトを起こした仮想アドレス（CR2）
+#[cfg(any(not(test), target_os = "none"))]
+pub fn read_cr2() -> u64 {
+    let cr2: u64;
+    unsafe { core::arch::asm!("mov {}, cr2", out(reg) cr2, options(nomem, nostack, preserves_flags)) };
+    cr2
+}
+
+/// CR3を `pml4` に切り替える（同じなら何もしない、GLOBALでないTLBのエントリはすべて消える）
+///
+/// # Safety
+/// `pml4` はカーネルの半分をマップしたPML4であること
+#[cfg(any(not(test), target_os = "none"))]
+pub unsafe fn switch_pml4(pml4: Frame) {
+    if read_cr3() != pml4.start_address() {
+        unsafe { core::arch::asm!("mov cr3, {}", in(reg) pml4.start_address(), options(nostack, preserves_flags)) };
+    }
+}
+
+/// TLBから1ページ分のエントリを消す
+///
+/// # Safety
+/// 特権命令なので ring 0 で呼ぶこと
+#[inline]
+unsafe fn invlpg(virt: u64) {
+    unsafe { core::arch::asm!("invlpg [{}]", in(reg) virt, options(nostack, preserves_flags)) };
+}
+
+/// EFER.NXE が有効か（無効なCPUで実行禁止ビットを立てると、予約ビット違反のページフォルトになる）
+fn no_execute_enabled() -> bool {
+    #[cfg(any(not(test), target_os = "none"))]
+    {
+        const IA32_EFER: u32 = 0xc000_0080;
+        const EFER_NXE: u64 = 1 << 11;
+        unsafe { super::read_msr(IA32_EFER) & EFER_NXE != 0 }
+    }
+    #[cfg(all(test, not(target_os = "none")))]
+    true
+}
+
+// ===== テスト =====
+#[cfg(all(test, not(target_os = "none")))]
+mod tests {
+    use super::*;
+
+    /// Vec上にページテーブルを置くテスト用の `TableFrames`
+    /// 物理アドレスは 0x1000 * (インデックス + 1) とみなす
+    struct VecFrames {
+        tables: Vec<Box<PageTable>>,
+        limit: usize,
+        /// 返されたフレーム（Vecからは消さない）
+        freed: Vec<Frame>,
+    }
+
+    impl TableFrames for VecFrames {
+        fn alloc_table(&mut self) -> Option<Frame> {
+            if self.tables.len() == self.limit {
+                return None;
+            }
+            self.tables.push(Box::new(PageTable::new()));
+            Some(Frame::from_number(self.tables.len() as u64))
+        }
+
+        fn table_ptr(&self, frame: Frame) -> *mut PageTable {
+            let index = frame.number() as usize - 1;
+            &*self.tables[index] as *const PageTable as *mut PageTable
+        }
+
+        fn free_table(&mut self, frame: Frame) {
+            self.freed.push(frame);
+        }
+    }
+
+    /// PML4だけのアドレス空間（ページテーブルは `limit` 枚まで）
+    fn mapper(limit: usize) -> Mapper<VecFrames> {
+        let mut frames = VecFrames { tables: Vec::new(), limit, freed: Vec::new() };
+        let pml4 = frames.alloc_table().unwrap();
+        Mapper::new(pml4, frames)
+    }
+
+    const KERNEL: MapFlags = MapFlags::from_bits(MapFlags::WRITABLE);
+    const PHYS: Frame = Frame::containing_address(0x4000_0000);
+
+    mod entry_tests {
+        use super::*;
+
+        #[test]
+        fn test_entry_address_and_flags() {
+            let entry = PageTableEntry::new(0x1234_5000, PageTableEntry::PRESENT | PageTableEntry::NO_EXECUTE);
+            assert!(entry.is_present());
+            assert!(!entry.is_huge());
+            assert_eq!(entry.address(), 0x1234_5000);
+            assert_eq!(entry.flags(), PageTableEntry::PRESENT | PageTableEntry::NO_EXECUTE);
+            assert_eq!(PageTableEntry::new(0x1234_5678, 0).address(), 0x1234_5000, "下位12ビットはフラグの場所");
+        }
+
+        #[test]
+        fn test_table_indices() {
+            // -2GiB はPML4=511, PDPT=510
+            assert_eq!(table_indices(0xffff_ffff_8000_0000), [511, 510, 0, 0]);
+            assert_eq!(table_indices(0x0000_0080_4020_1000), [1, 1, 1, 1]);
+        }
+
+        #[test]
+        fn test_canonical() {
+            assert!(is_canonical(0x0000_7fff_ffff_ffff));
+            assert!(is_canonical(0xffff_8000_0000_0000));
+            assert!(!is_canonical(0x0000_8000_0000_0000), "ビット47が1なら上位も1");
+        }
+    }
+
+    mod map_tests {
+        use super::*;
+
+        #[test]
+        fn test_map_each_size() {
+            let mut mapper = mapper(16);
+            mapper.map(0x1000, PHYS, PageSize::Size4K, KERNEL).unwrap();
+            mapper.map(0x20_0000, PHYS, PageSize::Size2M, KERNEL).unwrap();
+            mapper.map(0x40_0000_0000, PHYS, PageSize::Size1G, KERNEL).unwrap();
+
+            assert_eq!(mapper.translate(0x1234), Some(0x4000_0234), "4KiB");
+            assert_eq!(mapper.translate(0x2f_1234), Some(0x400f_1234), "2MiB");
+            assert_eq!(mapper.translate(0x40_3fff_fff0), Some(0x7fff_fff0), "1GiB");
+            assert_eq!(mapper.translate(0x2000), None, "未マップはNone");
+            assert_eq!(mapper.lookup(0x20_0000).map(|(_, size)| size), Some(PageSize::Size2M));
+        }
+
+        #[test]
+        fn test_leaf_flags() {
+            let mut mapper = mapper(16);
+            mapper.map(0x1000, PHYS, PageSize::Size4K, KERNEL).unwrap();
+            let code = MapFlags::from_bits(MapFlags::EXECUTABLE | MapFlags::GLOBAL);
+            mapper.map(0x2000, PHYS, PageSize::Size4K, code).unwrap();
+            mapper.map(0x20_0000, PHYS, PageSize::Size2M, KERNEL).unwrap();
+
+            let (data, _) = mapper.lookup(0x1000).unwrap();
+            assert!(data.is_set(PageTableEntry::WRITABLE) && data.is_set(PageTableEntry::NO_EXECUTE), "データは実行禁止");
+            assert!(!data.is_set(PageTableEntry::USER));
+            let (text, _) = mapper.lookup(0x2000).unwrap();
+            assert!(!text.is_set(PageTableEntry::WRITABLE) && !text.is_set(PageTableEntry::NO_EXECUTE));
+            assert!(text.is_set(PageTableEntry::GLOBAL));
+            let (huge, _) = mapper.lookup(0x20_0000).unwrap();
+            assert!(huge.is_huge(), "2MiBページはHUGE_PAGE");
+        }
+
+        #[test]
+        fn test_user_sets_intermediate_tables() {
+            let mut mapper = mapper(16);
+            mapper.map(0x1000, PHYS, PageSize::Size4K, KERNEL).unwrap();
+            let pml4e = mapper.entry(mapper.pml4(), 0);
+            assert!(!pml4e.is_set(PageTableEntry::USER), "カーネルのページだけならUSERなし");
+
+            let user = MapFlags::from_bits(MapFlags::USER | MapFlags::WRITABLE);
+            mapper.map(0x2000, PHYS, PageSize::Size4K, user).unwrap();
+            let pml4e = mapper.entry(mapper.pml4(), 0);
+            let pdpte = mapper.entry(Frame::containing_address(pml4e.address()), 0);
+            let pde = mapper.entry(Frame::containing_address(pdpte.address()), 0);
+            for entry in [pml4e, pdpte, pde] {
+                assert!(entry.is_set(PageTableEntry::USER), "途中の段にもUSERが必要");
+                assert!(!entry.is_set(PageTableEntry::NO_EXECUTE), "途中の段では制限しない");
+            }
+            assert!(mapper.lookup(0x2000).unwrap().0.is_set(PageTableEntry::USER));
+        }
+
+        #[test]
+        fn test_already_mapped() {
+            let mut mapper = mapper(16);
+            mapper.map(0x1000, PHYS, PageSize::Size4K, KERNEL).unwrap();
+            assert_eq!(mapper.map(0x1000, PHYS, PageSize::Size4K, KERNEL), Err(MapError::AlreadyMapped));
+            assert_eq!(mapper.map(0, PHYS, PageSize::Size2M, KERNEL), Err(MapError::AlreadyMapped), "4KiBページのあるPTの上");
+
+            mapper.map(0x4000_0000, PHYS, PageSize::Size1G, KERNEL).unwrap();
+            assert_eq!(
+                mapper.map(0x4020_0000, PHYS, PageSize::Size2M, KERNEL),
+                Err(MapError::AlreadyMapped),
+                "1GiBページの内側"
+            );
+            assert_eq!(mapper.map(0x4000_1000, PHYS, PageSize::Size4K, KERNEL), Err(MapError::AlreadyMapped));
+        }
+
+        #[test]
+        fn test_invalid_addresses() {
+            let mut mapper = mapper(16);
+            assert_eq!(mapper.map(0x1000, PHYS, PageSize::Size2M, KERNEL), Err(MapError::Misaligned));
+            let frame = Frame::containing_address(0x1000);
+            assert_eq!(mapper.map(0x20_0000, frame, PageSize::Size2M, KERNEL), Err(MapError::Misaligned), "物理側もそろえる");
+            assert_eq!(mapper.map(0x8000_0000_0000, PHYS, PageSize::Size4K, KERNEL), Err(MapError::NonCanonical));
+            assert_eq!(mapper.translate(0x8000_0000_0000), None);
+        }
+
+        #[test]
+        fn test_out_of_frames() {
+            // PML4とPDPTしか確保できない
+            let mut mapper = mapper(2);
+            assert_eq!(mapper.map(0x1000, PHYS, PageSize::Size4K, KERNEL), Err(MapError::OutOfFrames));
+            assert_eq!(mapper.map(0x4000_0000, PHYS, PageSize::Size1G, KERNEL), Ok(()), "1GiBならPDPTまでで足りる");
+        }
+    }
+
+    mod unmap_tests {
+        use super::*;
+
+        #[test]
+        fn test_unmap() {
+            let mut mapper = mapper(16);
+            mapper.map(0x1000, PHYS, PageSize::Size4K, KERNEL).unwrap();
+            mapper.map(0x20_0000, PHYS, PageSize::Size2M, KERNEL).unwrap();
+
+            assert_eq!(mapper.unmap(0x1000), Ok((PHYS, PageSize::Size4K)));
+            assert_eq!(mapper.translate(0x1000), None);
+            assert_eq!(mapper.unmap(0x1000), Err(MapError::NotMapped));
+            assert_eq!(mapper.unmap(0x20_1000), Err(MapError::Misaligned), "2MiBページの途中");
+            assert_eq!(mapper.unmap(0x20_0000), Ok((PHYS, PageSize::Size2M)));
+
+            // 外したところには別の大きさでマップし直せる
+            mapper.map(0x1000, Frame::from_number(7), PageSize::Size4K, KERNEL).unwrap();
+            assert_eq!(mapper.translate(0x1010), Some(0x7010));
+        }
+
+        #[test]
+        fn test_protect() {
+            let mut mapper = mapper(16);
+            let data = MapFlags::from_bits(MapFlags::USER | MapFlags::WRITABLE);
+            mapper.map(0x1000, PHYS, PageSize::Size4K, data).unwrap();
+            mapper.map(0x20_0000, PHYS, PageSize::Size2M, data).unwrap();
+
+            mapper.protect(0x1000, data.without(MapFlags::WRITABLE)).unwrap();
+            let (entry, _) = mapper.lookup(0x1000).unwrap();
+            assert!(!entry.is_set(PageTableEntry::WRITABLE), "書き込み禁止になる");
+            assert!(entry.is_set(PageTableEntry::USER));
+            assert_eq!(mapper.translate(0x1234), Some(0x4000_0234), "フレームは変わらない");
+
+            mapper.protect(0x20_0000, data.without(MapFlags::WRITABLE)).unwrap();
+            assert!(mapper.lookup(0x20_0000).unwrap().0.is_huge(), "大きなページのまま");
+            assert_eq!(mapper.protect(0x20_1000, data), Err(MapError::Misaligned));
+            assert_eq!(mapper.protect(0x2000, data), Err(MapError::NotMapped));
+        }
+
+        #[test]
+        fn test_kernel_half_is_shared() {
+            let mut kernel = mapper(16);
+            kernel.map(0xffff_ffff_8000_0000, PHYS, PageSize::Size2M, KERNEL).unwrap();
+            let (kernel_pml4, tables) = (kernel.pml4(), kernel.frames.tables.len());
+
+            let mut user = Mapper::with_kernel_half(kernel.frames, kernel_pml4).unwrap();
+            assert_eq!(user.frames.tables.len(), tables + 1, "新しく作るのはPML4だけ");
+            assert_eq!(user.translate(0xffff_ffff_8000_1234), Some(0x4000_1234), "カーネルの半分が見える");
+            user.map(0x40_0000, PHYS, PageSize::Size4K, MapFlags::from_bits(MapFlags::USER)).unwrap();
+            assert_eq!(user.translate(0x40_0000), Some(0x4000_0000));
+
+            // 下半分のテーブル（PDPT, PD, PT）とPML4を返し、共有しているカーネルのテーブルは返さない
+            let user_pml4 = user.pml4();
+            let frames = user.destroy();
+            assert_eq!(frames.freed.len(), 4);
+            assert_eq!(frames.freed.last(), Some(&user_pml4), "PML4は最後");
+            assert!(frames.freed.iter().all(|frame| frame.number() as usize > tables), "{:?}", frames.freed);
+        }
+
+        #[test]
+        fn test_error_display() {
+            assert_eq!(MapError::AlreadyMapped.to_string(), "page already mapped");
+            assert_eq!(MapError::Misaligned.to_string(), "address not aligned to page size");
+        }
+    }
+}
+
+#[cfg(all(test, target_os = "none"))]
+mod kernel_tests {
+    use super::*;
+
+    #[test_case]
+    fn test_map_and_unmap_kernel_page() {
+        // ブートローダーが使っていないPML4[384]の領域
+        const PAGE: u64 = 0xffff_c000_0000_0000;
+
+        let frame = crate::memory::alloc_frame().unwrap();
+        let mut mapper = Mapper::active(KernelFrames);
+        mapper.map(PAGE, frame, PageSize::Size4K, MapFlags::from_bits(MapFlags::WRITABLE)).unwrap();
+        assert_eq!(mapper.translate(PAGE + 8), Some(frame.start_address() + 8));
+        assert!(crate::arch::is_mapped(PAGE));
+
+        // 新しくマップしたアドレスと物理メモリマップは同じフレームを指す
+        unsafe { core::ptr::write_volatile((PAGE + 8) as *mut u64, 0xfeed) };
+        let phys = (boot_info::PHYSICAL_MEMORY_OFFSET + frame.start_address() + 8) as *const u64;
+        assert_eq!(unsafe { core::ptr::read_volatile(phys) }, 0xfeed);
+
+        assert_eq!(mapper.unmap(PAGE), Ok((frame, PageSize::Size4K)));
+        assert!(!crate::arch::is_mapped(PAGE), "ページテーブルから消えている");
+        crate::memory::free_frame(frame).unwrap();
+    }
+}
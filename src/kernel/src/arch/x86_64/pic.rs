@@ -0,0 +1,160 @@
+//! 8259 PIC（Programmable Interrupt Controller）
+//!
+//! マスター/スレーブの2つをカスケード接続した、PC互換機の古典的な割り込みコントローラ。
+//! IRQ 0〜15 を CPU の例外（0〜31）と重ならないよう 0x50〜0x5F に付け替えて使う。
+//! 0x20〜0x2F を空けておくのは、IPCトラップ（`int 0x21`）のベクタと重ならないようにするため（MINIX 3と同じ配置）。
+//!
+//! MINIX 3: kernel/arch/i386/i8259.c の intr_init() / irq_8259_unmask() / irq_8259_eoi()
+//! MikanOS: 8259は使わずLocal APICを使っている（ここは学習用に8259から始める）
+
+use super::port::{inb, outb};
+use crate::arch::InterruptController;
+
+/// マスターPICのIRQ 0 に割り当てるベクタ番号
+/// MINIX 3: IRQ0_VECTOR（0x50）
+pub const IRQ_BASE: u8 = 0x50;
+// CPU例外（0〜31）と重ならず、8259の制約で8の倍数であること
+const _: () = assert!(IRQ_BASE >= 32 && IRQ_BASE.is_multiple_of(8));
+/// IRQの数（マスター8本 + スレーブ8本）
+pub const IRQ_COUNT: u8 = 16;
+/// スレーブがつながっているマスターのIRQ
+const CASCADE_IRQ: u8 = 2;
+
+const MASTER_COMMAND: u16 = 0x20;
+const MASTER_DATA: u16 = 0x21;
+const SLAVE_COMMAND: u16 = 0xa0;
+const SLAVE_DATA: u16 = 0xa1;
+
+/// ICW1: エッジトリガ、カスケード、ICW4あり
+const ICW1_INIT: u8 = 0x11;
+/// ICW4: 8086モード
+const ICW4_8086: u8 = 0x01;
+/// OCW2: 非特定EOI
+const EOI: u8 = 0x20;
+/// OCW3: 次の読み込みでIRR（要求中の割り込み）を返す
+const READ_IRR: u8 = 0x0a;
+/// OCW3: 次の読み込みでISR（処理中の割り込み）を返す
+const READ_ISR: u8 = 0x0b;
+
+/// IRQ番号に対応する (データポート, ビット)
+fn mask_bit(irq: u8) -> (u16, u8) {
+    if irq < 8 {
+        (MASTER_DATA, 1 << irq)
+    } else {
+        (SLAVE_DATA, 1 << (irq - 8))
+    }
+}
+
+/// カスケード接続された2つの8259
+pub struct Pic8259 {
+    /// 現在のマスク（ビットが立っているIRQは無効）
+    masks: [u8; 2],
+}
+
+impl Pic8259 {
+    pub const fn new() -> Self {
+        Self { masks: [0xff; 2] }
+    }
+
+    /// 初期化してベクタを IRQ_BASE から割り当てる（すべてのIRQはマスクした状態）
+    ///
+    /// # Safety
+    /// PICの状態を変更する。割り込み禁止中に呼ぶこと
+    pub unsafe fn init(&mut self) {
+        outb(MASTER_COMMAND, ICW1_INIT);
+        outb(SLAVE_COMMAND, ICW1_INIT);
+        outb(MASTER_DATA, IRQ_BASE);
+        outb(SLAVE_DATA, IRQ_BASE + 8);
+        outb(MASTER_DATA, 1 << CASCADE_IRQ);
+        outb(SLAVE_DATA, CASCADE_IRQ);
+        outb(MASTER_DATA, ICW4_8086);
+        outb(SLAVE_DATA, ICW4_8086);
+
+        // スレーブへのカスケードだけは常に通す
+        self.masks = [!(1 << CASCADE_IRQ), 0xff];
+        self.write_masks();
+    }
+
+    fn write_masks(&self) {
+        unsafe {
+            outb(MASTER_DATA, self.masks[0]);
+            outb(SLAVE_DATA, self.masks[1]);
+        }
+    }
+
+    fn read_register(command: u8) -> u16 {
+        unsafe {
+            outb(MASTER_COMMAND, command);
+            outb(SLAVE_COMMAND, command);
+            u16::from(inb(MASTER_COMMAND)) | u16::from(inb(SLAVE_COMMAND)) << 8
+        }
+    }
+}
+
+impl Default for Pic8259 {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl InterruptController for Pic8259 {
+    fn enable(&mut self, irq: u32) {
+        let (port, bit) = mask_bit(irq as u8);
+        self.masks[usize::from(port == SLAVE_DATA)] &= !bit;
+        self.write_masks();
+    }
+
+    fn disable(&mut self, irq: u32) {
+        let (port, bit) = mask_bit(irq as u8);
+        self.masks[usize::from(port == SLAVE_DATA)] |= bit;
+        self.write_masks();
+    }
+
+    fn is_pending(&self, irq: u32) -> bool {
+        Self::read_register(READ_IRR) & (1 << irq) != 0
+    }
+
+    /// EOIを送る
+    fn clear(&mut self, irq: u32) {
+        end_of_interrupt(irq as u8);
+    }
+}
+
+/// 割り込み処理の終了をPICに伝える（スレーブのIRQは両方に送る）
+pub fn end_of_interrupt(irq: u8) {
+    unsafe {
+        if irq >= 8 {
+            outb(SLAVE_COMMAND, EOI);
+        }
+        outb(MASTER_COMMAND, EOI);
+    }
+}
+
+/// スプリアス割り込み（IRQ 7/15 で実際にはISRが立っていないもの）か
+///
+/// スプリアスのときはそのPICにEOIを送ってはいけない。
+/// IRQ 15 の場合はマスター側のカスケードにだけEOIを送る。
+pub fn is_spurious(irq: u8) -> bool {
+    if irq != 7 && irq != 15 {
+        return false;
+    }
+    let spurious = Pic8259::read_register(READ_ISR) & (1 << irq) == 0;
+    if spurious && irq == 15 {
+        unsafe { outb(MASTER_COMMAND, EOI) };
+    }
+    spurious
+}
+
+// ===== テスト =====
+#[cfg(all(test, not(target_os = "none")))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mask_bit() {
+        assert_eq!(mask_bit(0), (MASTER_DATA, 0x01), "IRQ 0はマスターのビット0");
+        assert_eq!(mask_bit(4), (MASTER_DATA, 0x10), "COM1（IRQ 4）");
+        assert_eq!(mask_bit(8), (SLAVE_DATA, 0x01), "IRQ 8はスレーブのビット0");
+        assert_eq!(mask_bit(15), (SLAVE_DATA, 0x80));
+    }
+}
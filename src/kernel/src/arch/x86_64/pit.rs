@@ -0,0 +1,49 @@
+//! 8253/8254 PIT（Programmable Interval Timer）
+//!
+//! チャンネル0を周期モードで動かし、IRQ 0 でタイマー割り込みを起こす。
+//!
+//! MINIX 3: kernel/arch/i386/arch_clock.c の init_8253A_timer()
+//! MikanOS: timer.cpp（MikanOSはLocal APICタイマーを使っていた）
+
+use super::port::outb;
+
+/// タイマー割り込みのIRQ
+pub const TIMER_IRQ: u8 = 0;
+/// PITの入力クロック（Hz）
+const PIT_FREQUENCY: u32 = 1_193_182;
+
+const CHANNEL0: u16 = 0x40;
+const COMMAND: u16 = 0x43;
+/// チャンネル0、下位→上位バイトの順、モード3（方形波）
+const SQUARE_WAVE: u8 = 0x36;
+
+/// `hz` 回/秒で割り込むための分周比
+pub fn divisor(hz: u32) -> u16 {
+    (PIT_FREQUENCY / hz.max(1)).clamp(1, u32::from(u16::MAX)) as u16
+}
+
+/// チャンネル0を `hz` 回/秒に設定する
+///
+/// # Safety
+/// PITの設定を変更する。割り込み禁止中に呼ぶこと
+pub unsafe fn init(hz: u32) {
+    let divisor = divisor(hz);
+    outb(COMMAND, SQUARE_WAVE);
+    outb(CHANNEL0, divisor as u8);
+    outb(CHANNEL0, (divisor >> 8) as u8);
+}
+
+// ===== テスト =====
+#[cfg(all(test, not(target_os = "none")))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_divisor() {
+        assert_eq!(divisor(100), 11_931, "100Hzなら約11932分周");
+        assert_eq!(divisor(1_000), 1_193);
+        assert_eq!(divisor(10), u16::MAX, "遅すぎる周期は最大値に丸める");
+        assert_eq!(divisor(0), u16::MAX, "0Hzは指定できない");
+        assert_eq!(divisor(2_000_000), 1, "速すぎる周期は1に丸める");
+    }
+}
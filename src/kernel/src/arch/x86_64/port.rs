@@ -0,0 +1,24 @@
+//! I/Oポートへのアクセス（in/out命令）
+//!
+//! MINIX 3: kernel/arch/i386/klib386.S の inb/outb に相当
+//! MikanOS: asmfunc.asm の IoIn32/IoOut32 に相当
+
+/// 1バイト書き込む
+///
+/// # Safety
+/// デバイスの状態を変更するため、ポートの意味を理解して呼ぶこと
+#[inline]
+pub unsafe fn outb(port: u16, value: u8) {
+    core::arch::asm!("out dx, al", in("dx") port, in("al") value, options(nomem, nostack, preserves_flags));
+}
+
+/// 1バイト読み込む
+///
+/// # Safety
+/// 読み込みで状態が変わるデバイスもある（受信バッファなど）
+#[inline]
+pub unsafe fn inb(port: u16) -> u8 {
+    let value: u8;
+    core::arch::asm!("in al, dx", out("al") value, in("dx") port, options(nomem, nostack, preserves_flags));
+    value
+}
@@ -0,0 +1,45 @@
+//! QEMUの終了（isa-debug-exit デバイス）
+//!
+//! `-device isa-debug-exit,iobase=0xf4,iosize=0x04` を付けて起動すると、
+//! ポート 0xf4 に書いた値 v に対して QEMU が終了コード (v << 1) | 1 で終わる。
+//! テストの成否をQEMUの終了コードで返すのに使う。
+
+use super::port::outb;
+
+/// isa-debug-exit のI/Oポート
+const ISA_DEBUG_EXIT_PORT: u16 = 0xf4;
+
+/// QEMUに返す値（終了コードは 0x21 / 0x23 になる）
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum QemuExitCode {
+    Success = 0x10,
+    Failed = 0x11,
+}
+
+impl QemuExitCode {
+    /// QEMUプロセスの終了コード
+    pub fn process_status(self) -> i32 {
+        (i32::from(self as u8) << 1) | 1
+    }
+}
+
+/// QEMUを終了させる
+///
+/// デバイスがない（実機や通常のQEMU）ときは何も起きずに戻ってくる。
+pub fn exit(code: QemuExitCode) {
+    unsafe { outb(ISA_DEBUG_EXIT_PORT, code as u8) };
+}
+
+// ===== テスト =====
+#[cfg(all(test, not(target_os = "none")))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_process_status() {
+        assert_eq!(QemuExitCode::Success.process_status(), 0x21);
+        assert_eq!(QemuExitCode::Failed.process_status(), 0x23);
+        assert_ne!(QemuExitCode::Success.process_status(), 1, "QEMU自身のエラー（1）と区別できる");
+    }
+}
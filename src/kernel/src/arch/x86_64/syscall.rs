@@ -0,0 +1,254 @@
+//! SYSCALL/SYSRET によるシステムコールの入口と、ユーザーモード（ring 3）への出入り
+//!
+//! ユーザープロセスが `syscall` 命令を実行すると、CPUは LSTAR のアドレスにring 0で飛ぶ。
+//! このときスタックは切り替わらないので、入口で自分でカーネルスタックに移り、
+//! 割り込みと同じ InterruptFrame の形でレジスタを積んでから syscall_dispatch() を呼ぶ。
+//!
+//! 呼び出し規約（Linuxと同じ）:
+//! - rax: 番号、rdi, rsi, rdx, r10, r8, r9: 引数、rax: 戻り値
+//! - rcx と r11 は CPU が rip と rflags の保存に使うので壊れる
+//!
+//! FMASK でIFを落とすので、システムコールの処理中は割り込み禁止のまま動く（MINIX 3のカーネルと同じ）。
+//!
+//! MINIX 3: kernel/arch/i386/mpx.S の ipc_entry_syscall_cpu0 と
+//!          kernel/arch/i386/protect.c の init_syscalls（STARとLSTARの設定）
+//! Linux: arch/x86/entry/entry_64.S の entry_SYSCALL_64 と syscall_init()
+
+use core::sync::atomic::{AtomicU64, Ordering};
+
+use super::gdt::{KERNEL_CODE, USER_CODE, USER_DATA};
+#[cfg(any(not(test), target_os = "none"))]
+use super::{interrupts::InterruptFrame, read_msr, write_msr, StackFrame};
+
+/// SYSCALL で入ったフレームのベクタ番号（IDTのベクタと区別するため256以上にする）
+pub const SYSCALL_VECTOR: u64 = 0x100;
+
+/// EFER（拡張機能の有効化）
+const MSR_EFER: u32 = 0xc000_0080;
+/// EFER.SCE（SYSCALL/SYSRET を使う）
+const EFER_SCE: u64 = 1 << 0;
+/// STAR（SYSCALL と SYSRET で読み込むセグメント）
+const MSR_STAR: u32 = 0xc000_0081;
+/// LSTAR（64ビットモードの SYSCALL の飛び先）
+const MSR_LSTAR: u32 = 0xc000_0082;
+/// FMASK（SYSCALL で落とす RFLAGS のビット）
+const MSR_FMASK: u32 = 0xc000_0084;
+
+/// SYSCALL で落とす RFLAGS のビット（TF, IF, DF, AC）
+pub const SYSCALL_FLAGS_MASK: u64 = (1 << 8) | (1 << 9) | (1 << 10) | (1 << 18);
+
+/// ユーザープロセスを始めるときの RFLAGS（IF と、常に1のビット1）
+pub const USER_RFLAGS: u64 = 0x202;
+
+/// STARの値
+///
+/// SYSCALL は STAR[47:32] をCS、+8 をSSにする。
+/// SYSRET は STAR[63:48] + 16 をCS、+8 をSSにする（どちらもRPL 3にして読み込む）。
+pub const fn star() -> u64 {
+    (((USER_DATA - 8) as u64) << 48) | ((KERNEL_CODE as u64) << 32)
+}
+
+/// SYSCALL で入ったときに使うカーネルスタック（実行中のプロセスのもの）
+static KERNEL_RSP: AtomicU64 = AtomicU64::new(0);
+
+/// SYSCALL の入口で退避するユーザーのRSP
+#[cfg(any(not(test), target_os = "none"))]
+static USER_RSP: AtomicU64 = AtomicU64::new(0);
+
+/// SYSCALL で入ったときに使うカーネルスタックを設定する
+pub fn set_kernel_stack(top: u64) {
+    KERNEL_RSP.store(top, Ordering::Relaxed);
+}
+
+/// SYSCALL/SYSRET を有効にして、入口を登録する
+///
+/// # Safety
+/// 起動時に一度だけ、gdt::init() の後に呼ぶこと
+#[cfg(any(not(test), target_os = "none"))]
+pub unsafe fn init() {
+    extern "C" {
+        fn __syscall_entry();
+    }
+
+    unsafe {
+        write_msr(MSR_EFER, read_msr(MSR_EFER) | EFER_SCE);
+        write_msr(MSR_STAR, star());
+        write_msr(MSR_LSTAR, __syscall_entry as *const () as u64);
+        write_msr(MSR_FMASK, SYSCALL_FLAGS_MASK);
+    }
+}
+
+/// システムコールの共通処理（入口のアセンブリから呼ばれる）
+#[cfg(any(not(test), target_os = "none"))]
+extern "C" fn syscall_dispatch(frame: &mut InterruptFrame) {
+    let args = [frame.rdi, frame.rsi, frame.rdx, frame.r10, frame.r8, frame.r9];
+    let result = crate::system::dispatch(frame.rax, args);
+    crate::user::finish_trap(frame, result);
+}
+
+#[cfg(any(not(test), target_os = "none"))]
+extern "C" {
+    /// `regs` のレジスタ（rip, rsp, rflags を含む）で ring 3 の実行を始める（または再開する）
+    ///
+    /// 呼び出し側の callee-saved レジスタと RFLAGS をカーネルスタックに積み、そのRSPを `saved` に書いてから
+    /// iretq する。`__leave_user(*saved, value)` が呼ばれると、ここから `value` を返す。
+    pub fn __enter_user(regs: *const StackFrame, saved: *mut u64) -> u64;
+
+    /// `__enter_user()` の呼び出し元に `value` を返して戻る（今のスタックは捨てる）
+    pub fn __leave_user(saved: u64, value: u64) -> !;
+}
+
+// SYSCALL の入口
+//
+// rcx にユーザーのrip、r11 にユーザーのrflags が入っている。rsp はユーザーのまま。
+// 割り込みの入口と同じ22語を積むので、callの直前で16バイト境界になる。
+//
+// 戻るときは rcx と r11 を積んだフレームから読み直して sysretq する。
+// rip は syscall 命令の次なので正規形（非正規形のまま sysretq するとring 0で #GP になる）。
+#[cfg(any(not(test), target_os = "none"))]
+core::arch::global_asm!(
+    ".section .text",
+    ".global __syscall_entry",
+    "__syscall_entry:",
+    "    mov [rip + {user_rsp}], rsp",
+    "    mov rsp, [rip + {kernel_rsp}]",
+    "    push {user_data}",
+    "    push qword ptr [rip + {user_rsp}]",
+    "    push r11",
+    "    push {user_code}",
+    "    push rcx",
+    "    push 0",
+    "    push {vector}",
+    "    push rax",
+    "    push rbx",
+    "    push rcx",
+    "    push rdx",
+    "    push rsi",
+    "    push rdi",
+    "    push rbp",
+    "    push r8",
+    "    push r9",
+    "    push r10",
+    "    push r11",
+    "    push r12",
+    "    push r13",
+    "    push r14",
+    "    push r15",
+    "    mov rdi, rsp",
+    "    call {dispatch}",
+    "    pop r15",
+    "    pop r14",
+    "    pop r13",
+    "    pop r12",
+    "    pop r11",
+    "    pop r10",
+    "    pop r9",
+    "    pop r8",
+    "    pop rbp",
+    "    pop rdi",
+    "    pop rsi",
+    "    pop rdx",
+    "    pop rcx",
+    "    pop rbx",
+    "    pop rax",
+    "    add rsp, 16",
+    "    pop rcx",
+    "    add rsp, 8",
+    "    pop r11",
+    "    pop rsp",
+    "    sysretq",
+    "",
+    // rdi = regs, rsi = saved
+    // 汎用レジスタはすべて regs の値で上書きするので、カーネルの値はユーザーに見えない
+    ".global __enter_user",
+    "__enter_user:",
+    "    push rbx",
+    "    push rbp",
+    "    push r12",
+    "    push r13",
+    "    push r14",
+    "    push r15",
+    "    pushfq",
+    "    mov [rsi], rsp",
+    "    push {user_data}",
+    "    push qword ptr [rdi + {off_rsp}]",
+    "    push qword ptr [rdi + {off_rflags}]",
+    "    push {user_code}",
+    "    push qword ptr [rdi + {off_rip}]",
+    "    mov rax, [rdi + {off_rax}]",
+    "    mov rbx, [rdi + {off_rbx}]",
+    "    mov rcx, [rdi + {off_rcx}]",
+    "    mov rdx, [rdi + {off_rdx}]",
+    "    mov rsi, [rdi + {off_rsi}]",
+    "    mov rbp, [rdi + {off_rbp}]",
+    "    mov r8, [rdi + {off_r8}]",
+    "    mov r9, [rdi + {off_r9}]",
+    "    mov r10, [rdi + {off_r10}]",
+    "    mov r11, [rdi + {off_r11}]",
+    "    mov r12, [rdi + {off_r12}]",
+    "    mov r13, [rdi + {off_r13}]",
+    "    mov r14, [rdi + {off_r14}]",
+    "    mov r15, [rdi + {off_r15}]",
+    "    mov rdi, [rdi + {off_rdi}]",
+    "    iretq",
+    "",
+    // rdi = saved, rsi = value
+    ".global __leave_user",
+    "__leave_user:",
+    "    mov rsp, rdi",
+    "    mov rax, rsi",
+    "    popfq",
+    "    pop r15",
+    "    pop r14",
+    "    pop r13",
+    "    pop r12",
+    "    pop rbp",
+    "    pop rbx",
+    "    ret",
+    user_rsp = sym USER_RSP,
+    kernel_rsp = sym KERNEL_RSP,
+    user_data = const USER_DATA as u64,
+    user_code = const USER_CODE as u64,
+    vector = const SYSCALL_VECTOR,
+    dispatch = sym syscall_dispatch,
+    off_rax = const core::mem::offset_of!(StackFrame, rax),
+    off_rbx = const core::mem::offset_of!(StackFrame, rbx),
+    off_rcx = const core::mem::offset_of!(StackFrame, rcx),
+    off_rdx = const core::mem::offset_of!(StackFrame, rdx),
+    off_rsi = const core::mem::offset_of!(StackFrame, rsi),
+    off_rdi = const core::mem::offset_of!(StackFrame, rdi),
+    off_rbp = const core::mem::offset_of!(StackFrame, rbp),
+    off_r8 = const core::mem::offset_of!(StackFrame, r8),
+    off_r9 = const core::mem::offset_of!(StackFrame, r9),
+    off_r10 = const core::mem::offset_of!(StackFrame, r10),
+    off_r11 = const core::mem::offset_of!(StackFrame, r11),
+    off_r12 = const core::mem::offset_of!(StackFrame, r12),
+    off_r13 = const core::mem::offset_of!(StackFrame, r13),
+    off_r14 = const core::mem::offset_of!(StackFrame, r14),
+    off_r15 = const core::mem::offset_of!(StackFrame, r15),
+    off_rflags = const core::mem::offset_of!(StackFrame, rflags),
+    off_rip = const core::mem::offset_of!(StackFrame, rip),
+    off_rsp = const core::mem::offset_of!(StackFrame, rsp),
+);
+
+// ===== テスト =====
+#[cfg(all(test, not(target_os = "none")))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_star() {
+        // SYSCALL: CS=0x08, SS=0x10
+        assert_eq!((star() >> 32) & 0xffff, u64::from(KERNEL_CODE));
+        // SYSRET: CS=base+16, SS=base+8（RPL 3）
+        let base = star() >> 48;
+        assert_eq!(base + 8, u64::from(USER_DATA));
+        assert_eq!(base + 16, u64::from(USER_CODE));
+    }
+
+    #[test]
+    fn test_flags_mask() {
+        assert_ne!(SYSCALL_FLAGS_MASK & (1 << 9), 0, "割り込み禁止で入る");
+        assert_eq!(SYSCALL_FLAGS_MASK & USER_RFLAGS & !(1 << 9), 0);
+    }
+}
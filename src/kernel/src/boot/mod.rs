@@ -0,0 +1,381 @@
+//! ブートプロトコル対応モジュール
+//!
+//! カーネルは2通りの方法で起動できる:
+//! - UEFIブートローダー（`src/bootloader`）: `_start(boot_info)` が直接呼ばれる
+//! - Multiboot2/Multiboot（GRUB、QEMUの `-kernel`）: 32bitのトランポリンから
+//!   `multiboot_main()` が呼ばれ、ここで同じ `BootInfo` を組み立てる
+//!
+//! どちらの経路でも、最後は `kernel_main(&BootInfo)` に合流する。
+
+pub mod multiboot1;
+pub mod multiboot2;
+mod trampoline;
+
+use boot_info::{BootInfo, BootModule, FfiSlice, FrameBufferInfo, MemoryRegion, MemoryRegionKind};
+
+/// Multibootで起動したときのカーネルの物理アドレス（`linker.ld` の KERNEL_PHYS）
+pub const KERNEL_PHYS: u64 = 0x10_0000;
+
+/// BootInfoBuilderが保持できるメモリ領域の最大数
+pub const MAX_MEMORY_REGIONS: usize = 128;
+
+/// BootInfoBuilderが保持できるモジュールの最大数
+pub const MAX_MODULES: usize = 16;
+
+/// ブート情報の解析エラー
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ParseError {
+    /// 情報構造体が途中で切れている
+    Truncated,
+    /// タグやエントリのサイズが不正
+    InvalidSize,
+}
+
+/// ブート情報を組み立てる
+///
+/// Multibootの情報構造体を解析しながら、メモリ領域やモジュールを固定長配列に溜め込み、
+/// 最後に `finish()` でそれらを指す `BootInfo` を作る。
+/// （ヒープがまだないので、配列は静的に確保しておく）
+pub struct BootInfoBuilder {
+    regions: [MemoryRegion; MAX_MEMORY_REGIONS],
+    region_count: usize,
+    modules: [BootModule; MAX_MODULES],
+    module_count: usize,
+    info: BootInfo,
+}
+
+impl BootInfoBuilder {
+    /// 空のビルダーを作成
+    pub const fn new() -> Self {
+        const EMPTY_REGION: MemoryRegion = MemoryRegion::new(0, 0, MemoryRegionKind::Reserved);
+        const EMPTY_MODULE: BootModule = BootModule {
+            start: 0,
+            end: 0,
+            name: FfiSlice::empty(),
+        };
+        Self {
+            regions: [EMPTY_REGION; MAX_MEMORY_REGIONS],
+            region_count: 0,
+            modules: [EMPTY_MODULE; MAX_MODULES],
+            module_count: 0,
+            info: BootInfo::new(),
+        }
+    }
+
+    /// 登録済みのメモリ領域（開始アドレス順）
+    #[cfg(all(test, not(target_os = "none")))]
+    pub fn regions(&self) -> &[MemoryRegion] {
+        &self.regions[..self.region_count]
+    }
+
+    /// 登録済みのモジュール
+    pub fn modules(&self) -> &[BootModule] {
+        &self.modules[..self.module_count]
+    }
+
+    /// 組み立て中のBootInfo
+    #[cfg(all(test, not(target_os = "none")))]
+    pub fn info(&self) -> &BootInfo {
+        &self.info
+    }
+
+    /// メモリ領域を追加する（開始アドレス順に挿入、空の領域は無視）
+    /// 返り値は追加できたかどうか
+    pub fn add_region(&mut self, start: u64, end: u64, kind: MemoryRegionKind) -> bool {
+        if start >= end {
+            return true;
+        }
+        if self.region_count == MAX_MEMORY_REGIONS {
+            return false;
+        }
+        let mut i = self.region_count;
+        while i > 0 && self.regions[i - 1].start > start {
+            self.regions[i] = self.regions[i - 1];
+            i -= 1;
+        }
+        self.regions[i] = MemoryRegion::new(start, end, kind);
+        self.region_count += 1;
+        true
+    }
+
+    /// `[start, end)` に重なるUsable領域を `kind` に置き換える
+    ///
+    /// Multibootのメモリマップはカーネル自身や情報構造体の位置も「使用可能」として返すので、
+    /// フレームアロケータに渡す前にここで除外しておく
+    pub fn reserve(&mut self, start: u64, end: u64, kind: MemoryRegionKind) -> bool {
+        if start >= end {
+            return true;
+        }
+        let old_regions = self.regions;
+        let old_count = self.region_count;
+        self.region_count = 0;
+        let mut ok = true;
+        for r in &old_regions[..old_count] {
+            if r.kind != MemoryRegionKind::Usable || r.end <= start || end <= r.start {
+                ok &= self.add_region(r.start, r.end, r.kind);
+                continue;
+            }
+            // 重なる部分で最大3つに分割する
+            let mid_start = r.start.max(start);
+            let mid_end = r.end.min(end);
+            ok &= self.add_region(r.start, mid_start, MemoryRegionKind::Usable);
+            ok &= self.add_region(mid_start, mid_end, kind);
+            ok &= self.add_region(mid_end, r.end, MemoryRegionKind::Usable);
+        }
+        ok
+    }
+
+    /// モジュールを追加する
+    pub fn add_module(&mut self, start: u64, end: u64, name: &'static [u8]) -> bool {
+        if self.module_count == MAX_MODULES {
+            return false;
+        }
+        self.modules[self.module_count] = BootModule {
+            start,
+            end,
+            name: unsafe { FfiSlice::from_raw_parts(name.as_ptr(), name.len()) },
+        };
+        self.module_count += 1;
+        true
+    }
+
+    /// コマンドラインを設定する
+    pub fn set_cmdline(&mut self, cmdline: &'static [u8]) {
+        self.info.cmdline = unsafe { FfiSlice::from_raw_parts(cmdline.as_ptr(), cmdline.len()) };
+    }
+
+    /// フレームバッファを設定する
+    pub fn set_framebuffer(&mut self, framebuffer: FrameBufferInfo) {
+        self.info.framebuffer = framebuffer;
+    }
+
+    /// ACPI RSDPの物理アドレスを設定する
+    pub fn set_rsdp_addr(&mut self, addr: u64) {
+        self.info.rsdp_addr = addr;
+    }
+
+    /// カーネルイメージの物理アドレスとサイズを設定する
+    pub fn set_kernel(&mut self, addr: u64, size: u64) {
+        self.info.kernel_addr = addr;
+        self.info.kernel_size = size;
+    }
+
+    /// 隣接する同種の領域を結合する
+    fn merge_regions(&mut self) {
+        if self.region_count == 0 {
+            return;
+        }
+        let mut write = 0;
+        for read in 1..self.region_count {
+            let current = self.regions[read];
+            let last = &mut self.regions[write];
+            if last.kind == current.kind && last.end == current.start {
+                last.end = current.end;
+            } else {
+                write += 1;
+                self.regions[write] = current;
+            }
+        }
+        self.region_count = write + 1;
+    }
+
+    /// BootInfoを完成させる
+    ///
+    /// BootInfo内のスライスはビルダーの配列を指すので、ビルダーは `'static` である必要がある
+    pub fn finish(&'static mut self) -> &'static BootInfo {
+        self.merge_regions();
+        self.info.memory_regions =
+            unsafe { FfiSlice::from_raw_parts(self.regions.as_ptr(), self.region_count) };
+        self.info.modules =
+            unsafe { FfiSlice::from_raw_parts(self.modules.as_ptr(), self.module_count) };
+        &self.info
+    }
+}
+
+impl Default for BootInfoBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Multiboot起動時のBootInfoの置き場所
+#[cfg(any(not(test), target_os = "none"))]
+struct BootInfoStorage(core::cell::UnsafeCell<BootInfoBuilder>);
+
+// 安全性: 起動直後のシングルスレッド環境で一度だけ使用される
+#[cfg(any(not(test), target_os = "none"))]
+unsafe impl Sync for BootInfoStorage {}
+
+#[cfg(any(not(test), target_os = "none"))]
+static MULTIBOOT_BOOT_INFO: BootInfoStorage =
+    BootInfoStorage(core::cell::UnsafeCell::new(BootInfoBuilder::new()));
+
+/// トランポリンが物理メモリマップに載せている範囲（先頭4GiB）
+#[cfg(any(not(test), target_os = "none"))]
+const TRAMPOLINE_MAPPED_SIZE: u64 = 4 << 30;
+
+/// 物理アドレスのバイト列を物理メモリマップ経由で参照する
+/// マップされていない範囲ならNone
+#[cfg(any(not(test), target_os = "none"))]
+fn phys_slice(phys: u64, len: usize) -> Option<&'static [u8]> {
+    if phys.checked_add(len as u64)? > TRAMPOLINE_MAPPED_SIZE {
+        return None;
+    }
+    let virt = boot_info::PHYSICAL_MEMORY_OFFSET + phys;
+    // 安全性: 範囲はトランポリンがマップした中に収まっている
+    Some(unsafe { core::slice::from_raw_parts(virt as *const u8, len) })
+}
+
+/// カーネルイメージ（.bssを含む）の物理的な終端
+#[cfg(any(not(test), target_os = "none"))]
+fn kernel_phys_end() -> u64 {
+    extern "C" {
+        /// linker.ld で定義（.bssの終わりの仮想アドレス）
+        static __kernel_end: u8;
+    }
+    let end = core::ptr::addr_of!(__kernel_end) as u64;
+    end - boot_info::KERNEL_BASE
+}
+
+/// Multibootトランポリンから呼ばれる64bitのエントリポイント
+///
+/// # 引数
+/// - `magic`: ブートローダーがEAXに入れた値（Multiboot2か旧Multibootかの判定に使う）
+/// - `info_phys`: 情報構造体の物理アドレス（EBX）
+#[cfg(any(not(test), target_os = "none"))]
+#[no_mangle]
+extern "C" fn multiboot_main(magic: u32, info_phys: u64) -> ! {
+    // 安全性: ここに来るのは起動時に一度だけ
+    let builder = unsafe { &mut *MULTIBOOT_BOOT_INFO.0.get() };
+
+    let info_end = match magic {
+        multiboot2::BOOTLOADER_MAGIC => {
+            let Some(header) = phys_slice(info_phys, 4) else { halt() };
+            let total_size = u32::from_le_bytes([header[0], header[1], header[2], header[3]]);
+            let Some(info) = phys_slice(info_phys, total_size as usize) else { halt() };
+            if multiboot2::parse(info, info_phys, builder).is_err() {
+                halt();
+            }
+            info_phys + total_size as u64
+        }
+        multiboot1::BOOTLOADER_MAGIC => {
+            if multiboot1::parse(info_phys, &phys_slice, builder).is_err() {
+                halt();
+            }
+            info_phys + multiboot1::INFO_SIZE as u64
+        }
+        // Multiboot以外から呼ばれた
+        _ => halt(),
+    };
+
+    // メモリマップ上は「使用可能」になっているカーネルや情報構造体を除外する
+    let kernel_end = kernel_phys_end();
+    builder.set_kernel(KERNEL_PHYS, kernel_end - KERNEL_PHYS);
+    builder.reserve(KERNEL_PHYS, kernel_end, MemoryRegionKind::Kernel);
+    builder.reserve(info_phys, info_end, MemoryRegionKind::Bootloader);
+    for i in 0..builder.modules().len() {
+        let module = builder.modules()[i];
+        builder.reserve(module.start, module.end, MemoryRegionKind::Bootloader);
+    }
+
+    crate::kernel_main(builder.finish())
+}
+
+/// 先に進めないときに停止する
+#[cfg(any(not(test), target_os = "none"))]
+fn halt() -> ! {
+    loop {
+        unsafe { core::arch::asm!("cli; hlt") };
+    }
+}
+
+// ===== テスト =====
+#[cfg(all(test, not(target_os = "none")))]
+mod tests {
+    use super::*;
+
+    /// BootInfoBuilderのテスト
+    mod builder_tests {
+        use super::*;
+
+        #[test]
+        fn test_add_region_sorted() {
+            let mut builder = BootInfoBuilder::new();
+            builder.add_region(0x10_0000, 0x20_0000, MemoryRegionKind::Usable);
+            builder.add_region(0, 0x9_f000, MemoryRegionKind::Usable);
+
+            let regions = builder.regions();
+            assert_eq!(regions.len(), 2, "2つの領域が登録されるべき");
+            assert_eq!(regions[0].start, 0, "開始アドレス順に並ぶべき");
+        }
+
+        #[test]
+        fn test_add_empty_region_ignored() {
+            let mut builder = BootInfoBuilder::new();
+            builder.add_region(0x1000, 0x1000, MemoryRegionKind::Usable);
+            assert!(builder.regions().is_empty(), "空の領域は無視されるべき");
+        }
+
+        #[test]
+        fn test_reserve_splits_usable_region() {
+            // カーネルが使用可能領域の真ん中にある
+            let mut builder = BootInfoBuilder::new();
+            builder.add_region(0x10_0000, 0x800_0000, MemoryRegionKind::Usable);
+            builder.reserve(0x20_0000, 0x30_0000, MemoryRegionKind::Kernel);
+
+            assert_eq!(
+                builder.regions(),
+                &[
+                    MemoryRegion::new(0x10_0000, 0x20_0000, MemoryRegionKind::Usable),
+                    MemoryRegion::new(0x20_0000, 0x30_0000, MemoryRegionKind::Kernel),
+                    MemoryRegion::new(0x30_0000, 0x800_0000, MemoryRegionKind::Usable),
+                ],
+                "前・予約・後の3つに分割されるべき"
+            );
+        }
+
+        #[test]
+        fn test_reserve_does_not_touch_reserved_region() {
+            let mut builder = BootInfoBuilder::new();
+            builder.add_region(0xf_0000, 0x10_0000, MemoryRegionKind::Reserved);
+            builder.reserve(0xf_0000, 0x10_0000, MemoryRegionKind::Kernel);
+
+            assert_eq!(builder.regions()[0].kind, MemoryRegionKind::Reserved, "Reservedはそのまま");
+        }
+
+        #[test]
+        fn test_reserve_at_region_edge() {
+            let mut builder = BootInfoBuilder::new();
+            builder.add_region(0x10_0000, 0x20_0000, MemoryRegionKind::Usable);
+            builder.reserve(0x10_0000, 0x18_0000, MemoryRegionKind::Kernel);
+
+            assert_eq!(builder.regions().len(), 2, "先頭を予約すると2つに分割");
+            assert_eq!(builder.regions()[0].kind, MemoryRegionKind::Kernel);
+        }
+
+        #[test]
+        fn test_region_capacity() {
+            let mut builder = BootInfoBuilder::new();
+            for i in 0..MAX_MEMORY_REGIONS as u64 {
+                assert!(builder.add_region(i * 0x2000, i * 0x2000 + 0x1000, MemoryRegionKind::Usable));
+            }
+            assert!(!builder.add_region(0xffff_0000, 0xffff_1000, MemoryRegionKind::Usable), "上限を超えたら失敗");
+        }
+
+        #[test]
+        fn test_finish_merges_and_publishes() {
+            let builder = Box::leak(Box::new(BootInfoBuilder::new()));
+            builder.add_region(0, 0x1000, MemoryRegionKind::Usable);
+            builder.add_region(0x1000, 0x2000, MemoryRegionKind::Usable);
+            builder.add_module(0x5000, 0x6000, b"initrd");
+            builder.set_cmdline(b"quantum=4");
+
+            let info = builder.finish();
+            assert!(info.is_valid(), "マジックナンバーが設定されているべき");
+            assert_eq!(info.memory_regions().len(), 1, "隣接する同種の領域は結合されるべき");
+            assert_eq!(info.memory_regions()[0].end, 0x2000);
+            assert_eq!(info.modules()[0].name(), "initrd");
+            assert_eq!(info.cmdline(), "quantum=4");
+        }
+    }
+}
@@ -0,0 +1,189 @@
+//! 旧Multiboot（バージョン1）の情報構造体の解析
+//!
+//! QEMUの `-kernel` オプションはMultiboot2に対応しておらず、旧Multibootだけを理解する。
+//! そのためカーネルには両方のヘッダを入れておき、QEMUから直接起動されたときは
+//! こちらの形式の情報構造体を受け取る。
+//!
+//! Multiboot2と違いタグ列ではなく固定レイアウトの構造体で、
+//! コマンドラインやメモリマップは物理アドレスで別の場所を指している。
+
+use super::{multiboot2, BootInfoBuilder, ParseError};
+
+/// ブートローダーがEAXに入れるマジックナンバー
+pub const BOOTLOADER_MAGIC: u32 = 0x2bad_b002;
+
+/// 情報構造体（multiboot_info）のサイズ
+pub const INFO_SIZE: usize = 116;
+
+/// flags のビット
+mod info_flags {
+    pub const CMDLINE: u32 = 1 << 2;
+    pub const MODULES: u32 = 1 << 3;
+    pub const MEMORY_MAP: u32 = 1 << 6;
+}
+
+/// コマンドラインとして読む最大バイト数
+const MAX_STRING_LEN: usize = 4096;
+
+fn read_u32(data: &[u8], off: usize) -> Result<u32, ParseError> {
+    let bytes = data.get(off..off + 4).ok_or(ParseError::Truncated)?;
+    Ok(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+}
+
+fn read_u64(data: &[u8], off: usize) -> Result<u64, ParseError> {
+    Ok(read_u32(data, off)? as u64 | (read_u32(data, off + 4)? as u64) << 32)
+}
+
+/// 物理アドレスのNUL終端文字列を読む（NULは含まない）
+fn read_cstr<F>(mem: &F, phys: u64) -> Result<&'static [u8], ParseError>
+where
+    F: Fn(u64, usize) -> Option<&'static [u8]>,
+{
+    for len in 0..MAX_STRING_LEN {
+        let byte = mem(phys + len as u64, 1).ok_or(ParseError::Truncated)?;
+        if byte[0] == 0 {
+            return mem(phys, len).ok_or(ParseError::Truncated);
+        }
+    }
+    Err(ParseError::InvalidSize)
+}
+
+/// 情報構造体を解析してBootInfoBuilderに書き込む
+///
+/// # 引数
+/// - `info_phys`: 情報構造体の物理アドレス
+/// - `mem`: 物理アドレスと長さからバイト列を得る関数（範囲外ならNone）
+/// - `builder`: 書き込み先
+pub fn parse<F>(info_phys: u64, mem: &F, builder: &mut BootInfoBuilder) -> Result<(), ParseError>
+where
+    F: Fn(u64, usize) -> Option<&'static [u8]>,
+{
+    let info = mem(info_phys, INFO_SIZE).ok_or(ParseError::Truncated)?;
+    let flags = read_u32(info, 0)?;
+
+    if flags & info_flags::CMDLINE != 0 {
+        let cmdline = read_u32(info, 16)? as u64;
+        builder.set_cmdline(read_cstr(mem, cmdline)?);
+    }
+
+    if flags & info_flags::MODULES != 0 {
+        let count = read_u32(info, 20)? as usize;
+        let addr = read_u32(info, 24)? as u64;
+        // mod_start, mod_end, string, reserved（各u32）
+        let modules = mem(addr, count * 16).ok_or(ParseError::Truncated)?;
+        for i in 0..count {
+            let start = read_u32(modules, i * 16)? as u64;
+            let end = read_u32(modules, i * 16 + 4)? as u64;
+            let name = match read_u32(modules, i * 16 + 8)? {
+                0 => &[][..],
+                string => read_cstr(mem, string as u64)?,
+            };
+            builder.add_module(start, end, name);
+        }
+    }
+
+    if flags & info_flags::MEMORY_MAP != 0 {
+        let length = read_u32(info, 44)? as usize;
+        let addr = read_u32(info, 48)? as u64;
+        let map = mem(addr, length).ok_or(ParseError::Truncated)?;
+        // 各エントリの先頭の size はそれ自身を含まない
+        let mut offset = 0;
+        while offset + 24 <= map.len() {
+            let size = read_u32(map, offset)? as usize;
+            if size < 20 {
+                return Err(ParseError::InvalidSize);
+            }
+            let base = read_u64(map, offset + 4)?;
+            let len = read_u64(map, offset + 12)?;
+            let ty = read_u32(map, offset + 20)?;
+            // メモリ種別の番号はMultiboot2と同じ
+            builder.add_region(base, base.saturating_add(len), multiboot2::region_kind(ty));
+            offset += size + 4;
+        }
+    }
+
+    Ok(())
+}
+
+// ===== テスト =====
+#[cfg(all(test, not(target_os = "none")))]
+mod tests {
+    use super::*;
+    use boot_info::MemoryRegionKind;
+
+    /// 物理アドレス 0x1000 から始まるテスト用の物理メモリ
+    const BASE: u64 = 0x1000;
+
+    fn memory(bytes: Vec<u8>) -> impl Fn(u64, usize) -> Option<&'static [u8]> {
+        let mem: &'static [u8] = Box::leak(bytes.into_boxed_slice());
+        move |phys, len| {
+            let start = phys.checked_sub(BASE)? as usize;
+            mem.get(start..start + len)
+        }
+    }
+
+    fn put_u32(bytes: &mut [u8], off: usize, value: u32) {
+        bytes[off..off + 4].copy_from_slice(&value.to_le_bytes());
+    }
+
+    fn put_u64(bytes: &mut [u8], off: usize, value: u64) {
+        bytes[off..off + 8].copy_from_slice(&value.to_le_bytes());
+    }
+
+    #[test]
+    fn test_parse_cmdline_modules_and_memory_map() {
+        // 0x1000: 情報構造体, 0x1100: コマンドライン, 0x1200: モジュール, 0x1300: メモリマップ
+        let mut bytes = vec![0u8; 0x400];
+        put_u32(&mut bytes, 0, info_flags::CMDLINE | info_flags::MODULES | info_flags::MEMORY_MAP);
+        put_u32(&mut bytes, 16, 0x1100);
+        put_u32(&mut bytes, 20, 1);
+        put_u32(&mut bytes, 24, 0x1200);
+        put_u32(&mut bytes, 44, 48);
+        put_u32(&mut bytes, 48, 0x1300);
+
+        bytes[0x100..0x10b].copy_from_slice(b"sched=fifo\0");
+
+        put_u32(&mut bytes, 0x200, 0x20_0000);
+        put_u32(&mut bytes, 0x204, 0x20_4000);
+        put_u32(&mut bytes, 0x208, 0x1180);
+        bytes[0x180..0x187].copy_from_slice(b"initrd\0");
+
+        for (i, &(base, len, ty)) in [(0u64, 0x9_fc00u64, 1u32), (0x10_0000, 0x700_0000, 1)].iter().enumerate() {
+            let off = 0x300 + i * 24;
+            put_u32(&mut bytes, off, 20);
+            put_u64(&mut bytes, off + 4, base);
+            put_u64(&mut bytes, off + 12, len);
+            put_u32(&mut bytes, off + 20, ty);
+        }
+
+        let mem = memory(bytes);
+        let mut builder = BootInfoBuilder::new();
+        parse(BASE, &mem, &mut builder).unwrap();
+
+        assert_eq!(builder.info().cmdline(), "sched=fifo", "コマンドライン");
+        assert_eq!(builder.modules()[0].name(), "initrd", "モジュール名");
+        assert_eq!(builder.modules()[0].end, 0x20_4000);
+        assert_eq!(builder.regions().len(), 2, "メモリマップは2エントリ");
+        assert_eq!(builder.regions()[1].kind, MemoryRegionKind::Usable);
+    }
+
+    #[test]
+    fn test_parse_without_flags() {
+        let mem = memory(vec![0u8; INFO_SIZE]);
+        let mut builder = BootInfoBuilder::new();
+        parse(BASE, &mem, &mut builder).unwrap();
+
+        assert_eq!(builder.info().cmdline(), "", "フラグがなければ何も設定しない");
+        assert!(builder.regions().is_empty());
+    }
+
+    #[test]
+    fn test_parse_out_of_range() {
+        let mut bytes = vec![0u8; INFO_SIZE];
+        put_u32(&mut bytes, 0, info_flags::CMDLINE);
+        put_u32(&mut bytes, 16, 0xdead_0000);
+        let mem = memory(bytes);
+        let mut builder = BootInfoBuilder::new();
+        assert_eq!(parse(BASE, &mem, &mut builder), Err(ParseError::Truncated));
+    }
+}
@@ -0,0 +1,329 @@
+//! Multiboot2の情報構造体の解析
+//!
+//! GRUBは起動時にEBXへ情報構造体の物理アドレスを渡す。
+//! 構造体は (total_size, reserved) の後に8バイト境界で並んだタグの列で、
+//! type=0 のタグで終わる。
+//!
+//! ```text
+//! +0  total_size (u32)
+//! +4  reserved   (u32)
+//! +8  tag: type (u32), size (u32), データ...
+//!     (8バイト境界に揃えて次のタグ)
+//! ```
+
+use boot_info::{FrameBufferInfo, MemoryRegionKind, PixelFormat};
+
+use super::{BootInfoBuilder, ParseError};
+
+/// ブートローダーがEAXに入れるマジックナンバー
+pub const BOOTLOADER_MAGIC: u32 = 0x36d7_6289;
+
+/// タグの種類
+pub mod tag {
+    pub const END: u32 = 0;
+    pub const CMDLINE: u32 = 1;
+    pub const MODULE: u32 = 3;
+    pub const MEMORY_MAP: u32 = 6;
+    pub const FRAMEBUFFER: u32 = 8;
+    pub const ACPI_OLD: u32 = 14;
+    pub const ACPI_NEW: u32 = 15;
+}
+
+/// メモリマップエントリの種類
+mod memory_type {
+    pub const AVAILABLE: u32 = 1;
+    pub const ACPI_RECLAIMABLE: u32 = 3;
+    pub const NVS: u32 = 4;
+    pub const BAD: u32 = 5;
+}
+
+/// フレームバッファの種類（type=1 が直接色指定のRGB）
+const FRAMEBUFFER_TYPE_RGB: u8 = 1;
+
+fn read_u32(data: &[u8], off: usize) -> Result<u32, ParseError> {
+    let bytes = data.get(off..off + 4).ok_or(ParseError::Truncated)?;
+    Ok(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+}
+
+fn read_u64(data: &[u8], off: usize) -> Result<u64, ParseError> {
+    Ok(read_u32(data, off)? as u64 | (read_u32(data, off + 4)? as u64) << 32)
+}
+
+/// NUL終端文字列の中身（NULより前）
+fn until_nul(bytes: &[u8]) -> &[u8] {
+    let end = bytes.iter().position(|&b| b == 0).unwrap_or(bytes.len());
+    &bytes[..end]
+}
+
+/// Multiboot2のメモリ種別を MemoryRegionKind に変換する
+pub fn region_kind(ty: u32) -> MemoryRegionKind {
+    match ty {
+        memory_type::AVAILABLE => MemoryRegionKind::Usable,
+        memory_type::ACPI_RECLAIMABLE => MemoryRegionKind::AcpiReclaimable,
+        memory_type::NVS => MemoryRegionKind::AcpiNvs,
+        memory_type::BAD => MemoryRegionKind::Unusable,
+        _ => MemoryRegionKind::Reserved,
+    }
+}
+
+/// 情報構造体を解析してBootInfoBuilderに書き込む
+///
+/// # 引数
+/// - `info`: 情報構造体全体（total_sizeバイト）
+/// - `info_phys`: 情報構造体の物理アドレス（RSDPの物理アドレス計算に使う）
+/// - `builder`: 書き込み先
+pub fn parse(info: &'static [u8], info_phys: u64, builder: &mut BootInfoBuilder) -> Result<(), ParseError> {
+    let total_size = read_u32(info, 0)? as usize;
+    if total_size < 8 || total_size > info.len() {
+        return Err(ParseError::Truncated);
+    }
+    let info = &info[..total_size];
+
+    let mut rsdp_new = None;
+    let mut rsdp_old = None;
+    let mut offset = 8;
+    loop {
+        let ty = read_u32(info, offset)?;
+        let size = read_u32(info, offset + 4)? as usize;
+        if ty == tag::END {
+            break;
+        }
+        if size < 8 {
+            return Err(ParseError::InvalidSize);
+        }
+        let body = info.get(offset + 8..offset + size).ok_or(ParseError::Truncated)?;
+
+        match ty {
+            tag::CMDLINE => builder.set_cmdline(until_nul(body)),
+            tag::MODULE => {
+                let start = read_u32(body, 0)? as u64;
+                let end = read_u32(body, 4)? as u64;
+                builder.add_module(start, end, until_nul(&body[8..]));
+            }
+            tag::MEMORY_MAP => parse_memory_map(body, builder)?,
+            tag::FRAMEBUFFER => builder.set_framebuffer(parse_framebuffer(body)?),
+            // タグの本体がRSDPのコピーになっている
+            tag::ACPI_NEW => rsdp_new = Some(info_phys + offset as u64 + 8),
+            tag::ACPI_OLD => rsdp_old = Some(info_phys + offset as u64 + 8),
+            // ブートローダー名、ELFセクションなどは使わない
+            _ => {}
+        }
+
+        // 次のタグは8バイト境界から始まる
+        offset += size.next_multiple_of(8);
+    }
+
+    if let Some(rsdp) = rsdp_new.or(rsdp_old) {
+        builder.set_rsdp_addr(rsdp);
+    }
+    Ok(())
+}
+
+/// メモリマップタグ（type=6）
+/// entry_size, entry_version の後に (base_addr, length, type, reserved) が並ぶ
+fn parse_memory_map(body: &[u8], builder: &mut BootInfoBuilder) -> Result<(), ParseError> {
+    let entry_size = read_u32(body, 0)? as usize;
+    if entry_size < 24 {
+        return Err(ParseError::InvalidSize);
+    }
+    let mut offset = 8;
+    while offset + entry_size <= body.len() {
+        let base = read_u64(body, offset)?;
+        let length = read_u64(body, offset + 8)?;
+        let ty = read_u32(body, offset + 16)?;
+        builder.add_region(base, base.saturating_add(length), region_kind(ty));
+        offset += entry_size;
+    }
+    Ok(())
+}
+
+/// フレームバッファタグ（type=8）
+fn parse_framebuffer(body: &[u8]) -> Result<FrameBufferInfo, ParseError> {
+    let base = read_u64(body, 0)?;
+    let pitch = read_u32(body, 8)?;
+    let width = read_u32(body, 12)?;
+    let height = read_u32(body, 16)?;
+    let bpp = *body.get(20).ok_or(ParseError::Truncated)?;
+    let fb_type = *body.get(21).ok_or(ParseError::Truncated)?;
+
+    // EGAテキストモード（type=2）はピクセル単位のフレームバッファではない
+    if fb_type != FRAMEBUFFER_TYPE_RGB || bpp == 0 {
+        return Ok(FrameBufferInfo::none());
+    }
+
+    // 色情報: red_position, red_mask_size, green_position, green_mask_size, blue_position, blue_mask_size
+    let red_position = *body.get(24).ok_or(ParseError::Truncated)?;
+    let blue_position = *body.get(28).ok_or(ParseError::Truncated)?;
+    let format = match (bpp, red_position, blue_position) {
+        (32, 0, 16) => PixelFormat::Rgb,
+        (32, 16, 0) => PixelFormat::Bgr,
+        _ => PixelFormat::Unknown,
+    };
+
+    let bytes_per_pixel = (bpp as u32).div_ceil(8);
+    Ok(FrameBufferInfo {
+        base,
+        size: pitch as u64 * height as u64,
+        width,
+        height,
+        stride: pitch / bytes_per_pixel,
+        format,
+    })
+}
+
+// ===== テスト =====
+#[cfg(all(test, not(target_os = "none")))]
+mod tests {
+    use super::*;
+
+    /// テスト用の情報構造体を組み立てる
+    struct InfoWriter {
+        bytes: Vec<u8>,
+    }
+
+    impl InfoWriter {
+        fn new() -> Self {
+            Self { bytes: vec![0; 8] }
+        }
+
+        fn tag(mut self, ty: u32, body: &[u8]) -> Self {
+            let size = 8 + body.len() as u32;
+            self.bytes.extend_from_slice(&ty.to_le_bytes());
+            self.bytes.extend_from_slice(&size.to_le_bytes());
+            self.bytes.extend_from_slice(body);
+            while !self.bytes.len().is_multiple_of(8) {
+                self.bytes.push(0);
+            }
+            self
+        }
+
+        fn finish(self) -> &'static [u8] {
+            let mut info = self.tag(tag::END, &[]);
+            let total = info.bytes.len() as u32;
+            info.bytes[0..4].copy_from_slice(&total.to_le_bytes());
+            Box::leak(info.bytes.into_boxed_slice())
+        }
+    }
+
+    fn memory_map_body(entries: &[(u64, u64, u32)]) -> Vec<u8> {
+        let mut body = Vec::new();
+        body.extend_from_slice(&24u32.to_le_bytes());
+        body.extend_from_slice(&0u32.to_le_bytes());
+        for &(base, len, ty) in entries {
+            body.extend_from_slice(&base.to_le_bytes());
+            body.extend_from_slice(&len.to_le_bytes());
+            body.extend_from_slice(&ty.to_le_bytes());
+            body.extend_from_slice(&0u32.to_le_bytes());
+        }
+        body
+    }
+
+    fn module_body(start: u32, end: u32, name: &str) -> Vec<u8> {
+        let mut body = Vec::new();
+        body.extend_from_slice(&start.to_le_bytes());
+        body.extend_from_slice(&end.to_le_bytes());
+        body.extend_from_slice(name.as_bytes());
+        body.push(0);
+        body
+    }
+
+    #[test]
+    fn test_parse_memory_map() {
+        let info = InfoWriter::new()
+            .tag(tag::MEMORY_MAP, &memory_map_body(&[
+                (0, 0x9_fc00, 1),
+                (0xf_0000, 0x1_0000, 2),
+                (0x10_0000, 0x7ee_0000, 1),
+            ]))
+            .finish();
+        let mut builder = BootInfoBuilder::new();
+        parse(info, 0x1_0000, &mut builder).unwrap();
+
+        let regions = builder.regions();
+        assert_eq!(regions.len(), 3, "3つのエントリ");
+        assert_eq!(regions[1].kind, MemoryRegionKind::Reserved, "type=2は予約");
+        assert_eq!(regions[2].end, 0x7fe_0000, "base + length");
+    }
+
+    #[test]
+    fn test_parse_cmdline_and_modules() {
+        let info = InfoWriter::new()
+            .tag(tag::CMDLINE, b"loglevel=debug console=serial\0")
+            .tag(tag::MODULE, &module_body(0x20_0000, 0x20_8000, "/boot/initrd"))
+            .finish();
+        let mut builder = BootInfoBuilder::new();
+        parse(info, 0, &mut builder).unwrap();
+
+        assert_eq!(builder.info().cmdline(), "loglevel=debug console=serial", "NULは含まない");
+        assert_eq!(builder.modules().len(), 1, "モジュールは1つ");
+        assert_eq!(builder.modules()[0].start, 0x20_0000);
+        assert_eq!(builder.modules()[0].name(), "/boot/initrd");
+    }
+
+    #[test]
+    fn test_parse_rsdp_prefers_new() {
+        let info = InfoWriter::new()
+            .tag(tag::ACPI_OLD, &[0; 20])
+            .tag(tag::ACPI_NEW, &[0; 36])
+            .finish();
+        let mut builder = BootInfoBuilder::new();
+        parse(info, 0x1_0000, &mut builder).unwrap();
+
+        // ACPI_OLDタグ: offset 8, サイズ28 → 32へ揃える。ACPI_NEWタグは offset 8+32=40
+        assert_eq!(builder.info().rsdp_addr(), Some(0x1_0000 + 40 + 8), "新しいRSDPのコピーを指すべき");
+    }
+
+    #[test]
+    fn test_parse_framebuffer() {
+        let mut body = Vec::new();
+        body.extend_from_slice(&0xfd00_0000u64.to_le_bytes());
+        body.extend_from_slice(&(1024u32 * 4).to_le_bytes()); // pitch
+        body.extend_from_slice(&1024u32.to_le_bytes());
+        body.extend_from_slice(&768u32.to_le_bytes());
+        body.push(32); // bpp
+        body.push(FRAMEBUFFER_TYPE_RGB);
+        body.extend_from_slice(&0u16.to_le_bytes());
+        body.extend_from_slice(&[16, 8, 8, 8, 0, 8]); // BGR
+        let info = InfoWriter::new().tag(tag::FRAMEBUFFER, &body).finish();
+        let mut builder = BootInfoBuilder::new();
+        parse(info, 0, &mut builder).unwrap();
+
+        let fb = builder.info().framebuffer().expect("フレームバッファがあるべき");
+        assert_eq!(fb.width, 1024);
+        assert_eq!(fb.stride, 1024, "pitch / 4");
+        assert_eq!(fb.format, PixelFormat::Bgr, "赤が16ビット目ならBGR");
+    }
+
+    #[test]
+    fn test_parse_ega_text_framebuffer_is_none() {
+        let mut body = vec![0u8; 24];
+        body[0..8].copy_from_slice(&0xb8000u64.to_le_bytes());
+        body[20] = 16;
+        body[21] = 2; // EGAテキスト
+        let info = InfoWriter::new().tag(tag::FRAMEBUFFER, &body).finish();
+        let mut builder = BootInfoBuilder::new();
+        parse(info, 0, &mut builder).unwrap();
+
+        assert!(builder.info().framebuffer().is_none(), "テキストモードはフレームバッファなし");
+    }
+
+    #[test]
+    fn test_parse_truncated() {
+        let mut bytes = InfoWriter::new().tag(tag::CMDLINE, b"abc\0").finish().to_vec();
+        // total_size を実際より大きくする
+        bytes[0..4].copy_from_slice(&1000u32.to_le_bytes());
+        let info = Box::leak(bytes.into_boxed_slice());
+        let mut builder = BootInfoBuilder::new();
+        assert_eq!(parse(info, 0, &mut builder), Err(ParseError::Truncated));
+    }
+
+    #[test]
+    fn test_parse_invalid_tag_size() {
+        let mut bytes = InfoWriter::new().tag(tag::CMDLINE, b"abc\0").finish().to_vec();
+        // 最初のタグのサイズを壊す
+        bytes[12..16].copy_from_slice(&4u32.to_le_bytes());
+        let info = Box::leak(bytes.into_boxed_slice());
+        let mut builder = BootInfoBuilder::new();
+        assert_eq!(parse(info, 0, &mut builder), Err(ParseError::InvalidSize));
+    }
+}
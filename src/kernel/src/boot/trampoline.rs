@@ -0,0 +1,208 @@
+//! Multiboot用のヘッダと、32bitプロテクトモードから64bitロングモードへのトランポリン
+//!
+//! GRUBやQEMUはカーネルを32bitプロテクトモード・ページング無効の状態で起動する。
+//! UEFIブートローダーが用意してくれていたもの（ページテーブル、ロングモード）を
+//! ここで自前で用意してから、上位半分の64bitコードへジャンプする。
+//!
+//! 1. CPUIDでロングモード対応を確認
+//! 2. ページテーブルを作成
+//!    - PML4[0]   → 物理0〜4GiBの恒等マップ（切り替え直後の命令実行用）
+//!    - PML4[256] → 同じPDPT（PHYSICAL_MEMORY_OFFSETからの物理メモリマップ）
+//!    - PML4[511] → PDPT[510] で物理0〜1GiBを KERNEL_BASE にマップ
+//! 3. CR4.PAE → CR3 → EFER.LME(+NXE) → CR0.PG の順に有効化
+//! 4. 64bitコードセグメントへfar jumpし、上位半分の `multiboot_main()` を呼ぶ
+//!
+//! MINIX 3: boot/ のブートモニタがプロテクトモードへの切り替えを担当していた。
+//! MikanOS: UEFIがロングモードにしてくれるので、この処理は不要だった。
+//!
+//! このコードは物理アドレス（KERNEL_PHYS = 1MiB付近）にリンクされる `.boot` セクションに置き、
+//! ページテーブルとスタックは上位半分の `.bss` に置いて `シンボル - KERNEL_VMA` で物理アドレスを得る。
+
+// ホストでのテストには含めない（32bitの絶対アドレス参照はPIEとしてリンクできない）
+#[cfg(any(not(test), target_os = "none"))]
+core::arch::global_asm!(
+    r#"
+    .set KERNEL_VMA, 0xffffffff80000000
+    .set MULTIBOOT2_MAGIC, 0xe85250d6
+    .set MULTIBOOT1_MAGIC, 0x1badb002
+    /* bit0: モジュールをページ境界に, bit1: メモリ情報を要求, bit16: アドレス情報あり（ELFとして解釈させない） */
+    .set MULTIBOOT1_FLAGS, 0x00010003
+
+    /* ===== Multibootヘッダ（ファイル先頭付近に置く必要がある） ===== */
+    .section .multiboot_header, "a"
+
+    /* Multiboot2: 先頭32KiB以内、8バイト境界 */
+    .balign 8
+multiboot2_header:
+    .long MULTIBOOT2_MAGIC
+    .long 0                                   /* アーキテクチャ: i386（32bitプロテクトモード） */
+    .long multiboot2_header_end - multiboot2_header
+    .long 0x100000000 - (MULTIBOOT2_MAGIC + (multiboot2_header_end - multiboot2_header))
+    /* エントリアドレスタグ: ELFのe_entry（64bitの_start）ではなくトランポリンから始める */
+    .balign 8
+    .short 3
+    .short 0
+    .long 12
+    .long _multiboot_entry
+    /* 終端タグ */
+    .balign 8
+    .short 0
+    .short 0
+    .long 8
+multiboot2_header_end:
+
+    /* 旧Multiboot（QEMUの -kernel 用）: 先頭8KiB以内、4バイト境界 */
+    /* QEMUは64bit ELFを拒否するので、bit16のアドレス情報でフラットなイメージとして読ませる */
+    .balign 4
+multiboot1_header:
+    .long MULTIBOOT1_MAGIC
+    .long MULTIBOOT1_FLAGS
+    .long 0x100000000 - (MULTIBOOT1_MAGIC + MULTIBOOT1_FLAGS)
+    .long multiboot1_header                   /* header_addr */
+    .long __kernel_phys_start                 /* load_addr */
+    .long __kernel_load_end                   /* load_end_addr */
+    .long __kernel_bss_end                    /* bss_end_addr */
+    .long _multiboot_entry                    /* entry_addr */
+
+    /* ===== 32bitトランポリン ===== */
+    .section .boot.text, "ax"
+    .code32
+    .global _multiboot_entry
+_multiboot_entry:
+    cli
+    cld
+    /* EAX = マジックナンバー, EBX = 情報構造体の物理アドレス。CPUIDで壊れるので退避 */
+    movl %eax, %edi
+    movl %ebx, %esi
+
+    /* ロングモード対応の確認 */
+    movl $0x80000000, %eax
+    cpuid
+    cmpl $0x80000001, %eax
+    jb no_long_mode
+    movl $0x80000001, %eax
+    cpuid
+    testl $(1 << 29), %edx
+    jz no_long_mode
+    /* NX対応かどうかは後でEFERを設定するときに使う */
+    movl %edx, %ebp
+
+    /* PML4 */
+    movl $(boot_pdpt_low - KERNEL_VMA + 0x3), %eax
+    movl %eax, (boot_pml4 - KERNEL_VMA)
+    movl %eax, (boot_pml4 - KERNEL_VMA + 256 * 8)
+    movl $(boot_pdpt_high - KERNEL_VMA + 0x3), %eax
+    movl %eax, (boot_pml4 - KERNEL_VMA + 511 * 8)
+
+    /* PDPT（下位）: 4つのPDで0〜4GiB */
+    movl $(boot_pd - KERNEL_VMA + 0x3), %eax
+    movl $(boot_pdpt_low - KERNEL_VMA), %ebx
+    movl $4, %ecx
+1:
+    movl %eax, (%ebx)
+    addl $0x1000, %eax
+    addl $8, %ebx
+    loop 1b
+
+    /* PDPT（上位）: -2GiB から物理0〜1GiB */
+    movl $(boot_pd - KERNEL_VMA + 0x3), %eax
+    movl %eax, (boot_pdpt_high - KERNEL_VMA + 510 * 8)
+
+    /* PD: 2048エントリの2MiBページ（PRESENT | WRITABLE | HUGE_PAGE） */
+    movl $(boot_pd - KERNEL_VMA), %ebx
+    xorl %ecx, %ecx
+2:
+    movl %ecx, %eax
+    shll $21, %eax
+    orl $0x83, %eax
+    movl %eax, (%ebx, %ecx, 8)
+    incl %ecx
+    cmpl $2048, %ecx
+    jne 2b
+
+    /* CR4.PAE */
+    movl %cr4, %eax
+    orl $(1 << 5), %eax
+    movl %eax, %cr4
+
+    movl $(boot_pml4 - KERNEL_VMA), %eax
+    movl %eax, %cr3
+
+    /* EFER.LME（対応していればEFER.NXEも） */
+    movl $0xc0000080, %ecx
+    rdmsr
+    orl $(1 << 8), %eax
+    testl $(1 << 20), %ebp
+    jz 3f
+    orl $(1 << 11), %eax
+3:
+    wrmsr
+
+    /* CR0.PG | CR0.WP */
+    movl %cr0, %eax
+    orl $0x80010000, %eax
+    movl %eax, %cr0
+
+    lgdt (boot_gdt_pointer)
+    ljmp $0x08, $long_mode_entry
+
+no_long_mode:
+    hlt
+    jmp no_long_mode
+
+    /* ===== 64bit（まだ恒等マップ上） ===== */
+    .code64
+long_mode_entry:
+    movw $0x10, %ax
+    movw %ax, %ds
+    movw %ax, %es
+    movw %ax, %ss
+    xorw %ax, %ax
+    movw %ax, %fs
+    movw %ax, %gs
+    /* 32bitモードで書いた値の上位32bitは不定なので、ゼロ拡張し直す */
+    movl %edi, %edi
+    movl %esi, %esi
+    movabsq $multiboot_higher_half, %rax
+    jmp *%rax
+
+    /* ===== GDT（ロングモード用の最小構成） ===== */
+    .section .boot.rodata, "a"
+    .balign 8
+boot_gdt:
+    .quad 0                                   /* ヌルディスクリプタ */
+    .quad 0x00af9a000000ffff                  /* 0x08: 64bitコード */
+    .quad 0x00cf92000000ffff                  /* 0x10: データ */
+boot_gdt_end:
+boot_gdt_pointer:
+    .short boot_gdt_end - boot_gdt - 1
+    .long boot_gdt
+
+    /* ===== 上位半分 ===== */
+    .section .text.multiboot, "ax"
+multiboot_higher_half:
+    movabsq $boot_stack_top, %rsp
+    xorl %ebp, %ebp
+    /* multiboot_main(magic: u32 = EDI, info_phys: u64 = RSI) */
+    call {main}
+4:
+    hlt
+    jmp 4b
+
+    .section .bss.multiboot, "aw", @nobits
+    .balign 4096
+boot_pml4:
+    .skip 4096
+boot_pdpt_low:
+    .skip 4096
+boot_pdpt_high:
+    .skip 4096
+boot_pd:
+    .skip 4096 * 4
+boot_stack:
+    .skip 64 * 1024
+boot_stack_top:
+"#,
+    main = sym super::multiboot_main,
+    options(att_syntax)
+);
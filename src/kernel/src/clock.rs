@@ -0,0 +1,58 @@
+//! クロック（タイマー割り込みとティック数）
+//!
+//! 起動してからのティック数を数える。ログのタイムスタンプや、
+//! 将来のスケジューラの時間量子（Quantum）の基準になる。
+//!
+//! MINIX 3: kernel/clock.c の clock_handler() と get_monotonic()
+//! MikanOS: timer.cpp の TimerManager::Tick()
+
+use core::sync::atomic::{AtomicU64, Ordering};
+
+/// 1秒あたりのティック数
+/// MINIX 3: DEFAULT_HZ（60）。ここでは計算しやすい100にする
+pub const HZ: u32 = 100;
+
+/// 起動してからのティック数
+static TICKS: AtomicU64 = AtomicU64::new(0);
+
+/// 起動してからのティック数
+pub fn ticks() -> u64 {
+    TICKS.load(Ordering::Relaxed)
+}
+
+/// タイマーを HZ で動かし、IRQ 0 を登録する
+#[cfg(any(not(test), target_os = "none"))]
+pub fn init() {
+    use crate::arch::interrupts::{set_irq_handler, PIC};
+    use crate::arch::pit::{self, TIMER_IRQ};
+    use crate::arch::InterruptController;
+
+    unsafe { pit::init(HZ) };
+    set_irq_handler(TIMER_IRQ, clock_handler);
+    PIC.lock().enable(u32::from(TIMER_IRQ));
+}
+
+/// IRQ 0 のハンドラ
+#[cfg(any(not(test), target_os = "none"))]
+fn clock_handler() {
+    TICKS.fetch_add(1, Ordering::Relaxed);
+}
+
+// ===== カーネル内テスト =====
+#[cfg(all(test, target_os = "none"))]
+mod kernel_tests {
+    use super::*;
+
+    #[test_case]
+    fn test_timer_ticks() {
+        let start = ticks();
+        // 1秒（HZティック）待っても進まなければ、IRQ 0 が届いていない
+        for _ in 0..HZ * 2 {
+            if ticks() > start {
+                return;
+            }
+            unsafe { core::arch::asm!("hlt", options(nomem, nostack)) };
+        }
+        panic!("timer interrupt did not arrive");
+    }
+}
@@ -0,0 +1,400 @@
+//! カーネルコマンドラインの解析
+//!
+//! ブートローダーから `BootInfo::cmdline()` として渡される文字列
+//! （UEFIのロードオプション、またはMultibootのコマンドラインタグ）を
+//! 型付きの起動オプションに変換する。
+//!
+//! ```text
+//! loglevel=debug sched=rr quantum=4 procs=256 init=/sbin/init console=serial gdb=wait
+//! ```
+//!
+//! - 空白区切りの `key=value`。値は `"..."` で囲めば空白を含められる
+//! - 同じキーが複数回あれば後のものが有効（Linuxと同じ）
+//! - 知らないキーや不正な値は無視し、警告として記録する（起動は止めない）
+//!
+//! MINIX 3: ブートモニタの環境変数（`boot_param`）を `env_get()` で読んでいた。
+
+use core::fmt;
+
+use crate::process::{Quantum, SchedPolicy, BOOT_PROCESSES, PROCESS_LIMIT};
+
+/// 記録する警告の最大数（これを超えた分は数だけ数える）
+pub const MAX_WARNINGS: usize = 8;
+
+/// デフォルトで起動する最初のユーザープロセス
+pub const DEFAULT_INIT: &str = "/sbin/init";
+
+/// ログレベル（logクレートの `Level` と同じ並び、数値が大きいほど詳細）
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum LogLevel {
+    Error = 1,
+    Warn,
+    Info,
+    Debug,
+    Trace,
+}
+
+impl LogLevel {
+    /// `error` / `warn` / `info` / `debug` / `trace`、または 1〜5 の数値
+    pub fn parse(value: &str) -> Option<Self> {
+        Some(match value {
+            "error" | "1" => LogLevel::Error,
+            "warn" | "2" => LogLevel::Warn,
+            "info" | "3" => LogLevel::Info,
+            "debug" | "4" => LogLevel::Debug,
+            "trace" | "5" => LogLevel::Trace,
+            _ => return None,
+        })
+    }
+}
+
+/// 出力先のコンソール
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Console {
+    /// COM1のシリアルポート
+    Serial,
+    /// VGAテキスト画面
+    Vga,
+}
+
+/// COM2のGDBスタブ
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GdbMode {
+    /// ブレークポイント（int3）やシングルステップで止まったらGDBに報告する
+    On,
+    /// さらに起動中に止まって、GDBがつなぐのを待つ（Linuxの kgdbwait）
+    Wait,
+}
+
+/// コマンドラインの問題（起動は続ける）
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Warning<'a> {
+    /// 知らないオプション
+    UnknownOption(&'a str),
+    /// 値が不正
+    InvalidValue { key: &'a str, value: &'a str },
+    /// `=` がない（値が必要なオプション）
+    MissingValue(&'a str),
+}
+
+impl fmt::Display for Warning<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Warning::UnknownOption(key) => write!(f, "unknown option `{}`", key),
+            Warning::InvalidValue { key, value } => write!(f, "invalid value `{}` for `{}`", value, key),
+            Warning::MissingValue(key) => write!(f, "option `{}` requires a value", key),
+        }
+    }
+}
+
+/// 起動オプション
+///
+/// 文字列はコマンドラインを借用する（BootInfoのコマンドラインは `'static`）。
+#[derive(Debug, Clone)]
+pub struct BootOptions<'a> {
+    /// `loglevel=`: ログの出力レベル
+    pub loglevel: LogLevel,
+    /// `sched=`: スケジューリング方式（`priority` / `rr`）
+    pub sched: SchedPolicy,
+    /// `quantum=`: デフォルトの時間量子（1〜255ティック）
+    pub quantum: u8,
+    /// `procs=`: プロセステーブルのスロット数（指定しなければ物理メモリ量から決める）
+    pub procs: Option<usize>,
+    /// `init=`: 最初に起動するユーザープログラムのパス
+    pub init: &'a str,
+    /// `console=`: 出力先（指定しなければシリアルとVGAの両方）
+    pub console: Option<Console>,
+    /// `gdb=`: COM2でGDBスタブを動かす（`on` / `wait`、指定しなければ無効）
+    pub gdb: Option<GdbMode>,
+    warnings: [Option<Warning<'a>>; MAX_WARNINGS],
+    warning_count: usize,
+    dropped_warnings: usize,
+}
+
+impl Default for BootOptions<'_> {
+    fn default() -> Self {
+        Self {
+            loglevel: LogLevel::Info,
+            sched: SchedPolicy::Priority,
+            quantum: Quantum::DEFAULT,
+            procs: None,
+            init: DEFAULT_INIT,
+            console: None,
+            gdb: None,
+            warnings: [None; MAX_WARNINGS],
+            warning_count: 0,
+            dropped_warnings: 0,
+        }
+    }
+}
+
+impl<'a> BootOptions<'a> {
+    /// 記録された警告
+    pub fn warnings(&self) -> impl Iterator<Item = &Warning<'a>> {
+        self.warnings[..self.warning_count].iter().flatten()
+    }
+
+    /// MAX_WARNINGSを超えて記録できなかった警告の数
+    pub fn dropped_warnings(&self) -> usize {
+        self.dropped_warnings
+    }
+
+    /// 指定されたコンソールに出力するか
+    pub fn uses_console(&self, console: Console) -> bool {
+        self.console.is_none_or(|c| c == console)
+    }
+
+    fn warn(&mut self, warning: Warning<'a>) {
+        if self.warning_count < MAX_WARNINGS {
+            self.warnings[self.warning_count] = Some(warning);
+            self.warning_count += 1;
+        } else {
+            self.dropped_warnings += 1;
+        }
+    }
+
+    /// 1つの `key=value` を適用する
+    fn apply(&mut self, key: &'a str, value: Option<&'a str>) {
+        let known = matches!(key, "loglevel" | "sched" | "quantum" | "procs" | "init" | "console" | "gdb");
+        if !known {
+            self.warn(Warning::UnknownOption(key));
+            return;
+        }
+        let Some(value) = value else {
+            self.warn(Warning::MissingValue(key));
+            return;
+        };
+
+        let ok = match key {
+            "loglevel" => LogLevel::parse(value).map(|level| self.loglevel = level).is_some(),
+            "sched" => match value {
+                "priority" | "minix" => Some(SchedPolicy::Priority),
+                "rr" | "round-robin" => Some(SchedPolicy::RoundRobin),
+                _ => None,
+            }
+            .map(|policy| self.sched = policy)
+            .is_some(),
+            "quantum" => match value.parse::<u8>() {
+                Ok(ticks) if ticks > 0 => {
+                    self.quantum = ticks;
+                    true
+                }
+                _ => false,
+            },
+            "procs" => match value.parse::<usize>() {
+                Ok(procs) if (BOOT_PROCESSES..=PROCESS_LIMIT).contains(&procs) => {
+                    self.procs = Some(procs);
+                    true
+                }
+                _ => false,
+            },
+            "init" => {
+                let ok = value.starts_with('/');
+                if ok {
+                    self.init = value;
+                }
+                ok
+            }
+            "console" => match value {
+                "serial" => Some(Console::Serial),
+                "vga" => Some(Console::Vga),
+                _ => None,
+            }
+            .map(|console| self.console = Some(console))
+            .is_some(),
+            "gdb" => match value {
+                "on" => Some(GdbMode::On),
+                "wait" => Some(GdbMode::Wait),
+                _ => None,
+            }
+            .map(|mode| self.gdb = Some(mode))
+            .is_some(),
+            _ => unreachable!(),
+        };
+        if !ok {
+            self.warn(Warning::InvalidValue { key, value });
+        }
+    }
+}
+
+/// コマンドラインを空白で区切る（`"` で囲んだ部分の空白は区切りにしない）
+struct Tokens<'a> {
+    rest: &'a str,
+}
+
+impl<'a> Iterator for Tokens<'a> {
+    type Item = &'a str;
+
+    fn next(&mut self) -> Option<&'a str> {
+        self.rest = self.rest.trim_start_matches(|c: char| c.is_ascii_whitespace());
+        if self.rest.is_empty() {
+            return None;
+        }
+        let mut in_quotes = false;
+        let end = self
+            .rest
+            .char_indices()
+            .find(|&(_, c)| {
+                if c == '"' {
+                    in_quotes = !in_quotes;
+                }
+                c.is_ascii_whitespace() && !in_quotes
+            })
+            .map_or(self.rest.len(), |(i, _)| i);
+        let (token, rest) = self.rest.split_at(end);
+        self.rest = rest;
+        Some(token)
+    }
+}
+
+/// コマンドラインを解析する
+pub fn parse(cmdline: &str) -> BootOptions<'_> {
+    let mut options = BootOptions::default();
+    for token in (Tokens { rest: cmdline }) {
+        let (key, value) = match token.split_once('=') {
+            Some((key, value)) => (key, Some(unquote(value))),
+            None => (token, None),
+        };
+        options.apply(key, value);
+    }
+    options
+}
+
+/// 値を囲む `"` を外す
+fn unquote(value: &str) -> &str {
+    value
+        .strip_prefix('"')
+        .and_then(|v| v.strip_suffix('"'))
+        .unwrap_or(value)
+}
+
+// ===== テスト =====
+#[cfg(all(test, not(target_os = "none")))]
+mod tests {
+    use super::*;
+
+    mod tokenizer_tests {
+        use super::*;
+
+        fn tokens(s: &str) -> Vec<&str> {
+            Tokens { rest: s }.collect()
+        }
+
+        #[test]
+        fn test_split_on_whitespace() {
+            assert_eq!(tokens("  a=1\tb=2  c "), ["a=1", "b=2", "c"], "連続した空白やタブも区切り");
+            assert!(tokens("").is_empty(), "空文字列はトークンなし");
+            assert!(tokens("   ").is_empty(), "空白だけでもトークンなし");
+        }
+
+        #[test]
+        fn test_quoted_value() {
+            assert_eq!(tokens("init=\"/bin/sh -i\" x"), ["init=\"/bin/sh -i\"", "x"], "引用符の中の空白は区切らない");
+            assert_eq!(unquote("\"/bin/sh -i\""), "/bin/sh -i", "引用符を外す");
+            assert_eq!(unquote("\"abc"), "\"abc", "閉じていなければそのまま");
+        }
+    }
+
+    mod parse_tests {
+        use super::*;
+
+        #[test]
+        fn test_defaults() {
+            let options = parse("");
+            assert_eq!(options.loglevel, LogLevel::Info, "デフォルトはinfo");
+            assert_eq!(options.sched, SchedPolicy::Priority, "デフォルトはMINIX式の優先度");
+            assert_eq!(options.quantum, Quantum::DEFAULT, "デフォルトはQuantum::DEFAULT");
+            assert_eq!(options.procs, None, "デフォルトはメモリ量から決める");
+            assert_eq!(options.init, DEFAULT_INIT);
+            assert_eq!(options.console, None, "デフォルトは両方に出力");
+            assert_eq!(options.gdb, None, "デフォルトはGDBスタブなし");
+            assert_eq!(options.warnings().count(), 0, "警告なし");
+        }
+
+        #[test]
+        fn test_all_options() {
+            let options = parse("loglevel=debug sched=rr quantum=4 procs=256 init=/bin/sh console=serial gdb=wait");
+            assert_eq!(options.loglevel, LogLevel::Debug);
+            assert_eq!(options.sched, SchedPolicy::RoundRobin);
+            assert_eq!(options.quantum, 4, "quantum= がデフォルトを上書きする");
+            assert_eq!(options.procs, Some(256));
+            assert_eq!(options.init, "/bin/sh");
+            assert_eq!(options.console, Some(Console::Serial));
+            assert_eq!(options.gdb, Some(GdbMode::Wait));
+            assert!(options.uses_console(Console::Serial));
+            assert!(!options.uses_console(Console::Vga), "console=serial ならVGAには出さない");
+            assert_eq!(options.warnings().count(), 0);
+        }
+
+        #[test]
+        fn test_numeric_loglevel() {
+            assert_eq!(parse("loglevel=1").loglevel, LogLevel::Error);
+            assert_eq!(parse("loglevel=5").loglevel, LogLevel::Trace);
+            assert!(LogLevel::Error < LogLevel::Trace, "数値が大きいほど詳細");
+        }
+
+        #[test]
+        fn test_last_one_wins() {
+            let options = parse("quantum=2 quantum=12");
+            assert_eq!(options.quantum, 12, "後に書いたものが有効");
+        }
+
+        #[test]
+        fn test_quoted_init() {
+            let options = parse("init=\"/sbin/init --single\"");
+            assert_eq!(options.init, "/sbin/init --single");
+        }
+    }
+
+    mod warning_tests {
+        use super::*;
+
+        #[test]
+        fn test_unknown_option() {
+            let options = parse("quiet foo=bar loglevel=warn");
+            let warnings: Vec<_> = options.warnings().copied().collect();
+            assert_eq!(warnings, [Warning::UnknownOption("quiet"), Warning::UnknownOption("foo")]);
+            assert_eq!(options.loglevel, LogLevel::Warn, "知らないオプションがあっても他は適用する");
+        }
+
+        #[test]
+        fn test_invalid_values_keep_defaults() {
+            let options = parse("quantum=0 quantum=300 sched=fifo console=lcd init=sbin/init loglevel=loud gdb=com2 procs=4");
+            assert_eq!(options.quantum, Quantum::DEFAULT, "0や範囲外は無視");
+            assert_eq!(options.procs, None, "起動用のスロットより少なくはできない");
+            assert_eq!(options.sched, SchedPolicy::Priority);
+            assert_eq!(options.console, None);
+            assert_eq!(options.init, DEFAULT_INIT, "絶対パスでなければ無視");
+            assert_eq!(options.loglevel, LogLevel::Info);
+            assert_eq!(options.gdb, None);
+            assert_eq!(options.warnings().count(), 8, "不正な値ごとに警告");
+            assert_eq!(options.dropped_warnings(), 0);
+            assert_eq!(
+                options.warnings().next(),
+                Some(&Warning::InvalidValue { key: "quantum", value: "0" })
+            );
+        }
+
+        #[test]
+        fn test_missing_value() {
+            let options = parse("loglevel");
+            assert_eq!(options.warnings().next(), Some(&Warning::MissingValue("loglevel")));
+        }
+
+        #[test]
+        fn test_too_many_warnings() {
+            let options = parse("a b c d e f g h i j");
+            assert_eq!(options.warnings().count(), MAX_WARNINGS, "記録できるのはMAX_WARNINGSまで");
+            assert_eq!(options.dropped_warnings(), 10 - MAX_WARNINGS, "残りは数だけ数える");
+        }
+
+        #[test]
+        fn test_warning_display() {
+            assert_eq!(Warning::UnknownOption("quiet").to_string(), "unknown option `quiet`");
+            assert_eq!(
+                Warning::InvalidValue { key: "sched", value: "fifo" }.to_string(),
+                "invalid value `fifo` for `sched`"
+            );
+        }
+    }
+}
@@ -0,0 +1,149 @@
+//! カーネルのコンソール出力
+//!
+//! `print!` / `println!` はシリアル（COM1）とVGAの両方に、
+//! `serial_print!` / `serial_println!` はシリアルだけに出力する。
+//! 出力先は起動オプション `console=serial|vga` で絞れる。
+//!
+//! どちらの出力も SpinLock で守っているので、割り込みハンドラから呼んでも
+//! 文字が混ざったりデッドロックしたりしない。
+//!
+//! MINIX 3: kernel/utility.c の kprintf()（カーネル内のprintf）
+//! MikanOS: main.cpp の printk()
+
+use core::fmt;
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use crate::vga::ColorCode;
+
+/// シリアルに出力するか
+static SERIAL_OUTPUT: AtomicBool = AtomicBool::new(true);
+/// VGAに出力するか
+static VGA_OUTPUT: AtomicBool = AtomicBool::new(true);
+
+/// `print!` の出力先を設定する
+pub fn set_outputs(serial: bool, vga: bool) {
+    SERIAL_OUTPUT.store(serial, Ordering::Relaxed);
+    VGA_OUTPUT.store(vga, Ordering::Relaxed);
+}
+
+/// VGAに出す文字の色を変える（シリアルには影響しない）
+pub fn set_color(color: ColorCode) {
+    if let Some(writer) = crate::vga::WRITER.lock().as_mut() {
+        writer.set_color(color);
+    }
+}
+
+/// `print!` の実体
+#[doc(hidden)]
+pub fn _print(args: fmt::Arguments) {
+    if SERIAL_OUTPUT.load(Ordering::Relaxed) {
+        _serial_print(args);
+    }
+    if VGA_OUTPUT.load(Ordering::Relaxed) {
+        _vga_print(args);
+    }
+}
+
+/// `serial_print!` の実体
+#[doc(hidden)]
+#[cfg(any(not(test), target_os = "none"))]
+pub fn _serial_print(args: fmt::Arguments) {
+    use core::fmt::Write;
+    if let Some(port) = crate::serial::SERIAL1.lock().as_mut() {
+        let _ = port.write_fmt(args);
+    }
+}
+
+/// ホストでのテスト時は標準出力に出す（cargo test の出力キャプチャに乗る）
+#[doc(hidden)]
+#[cfg(all(test, not(target_os = "none")))]
+pub fn _serial_print(args: fmt::Arguments) {
+    std::print!("{}", args);
+}
+
+/// パニック時の出力
+///
+/// パニックした処理がコンソールのロックを持ったままかもしれないので、待たずに
+/// 取れるロックだけを使う。COM1のロックが取れなければ、レジスタを直接叩く。
+/// 出力先の設定（console=）は無視して、出せるところすべてに出す。
+#[cfg(any(not(test), target_os = "none"))]
+pub fn _panic_print(args: fmt::Arguments) {
+    use crate::serial::{PortIo, SerialPort, COM1, SERIAL1};
+    use core::fmt::Write;
+
+    match SERIAL1.try_lock() {
+        Some(mut serial) => {
+            if let Some(port) = serial.as_mut() {
+                let _ = port.write_fmt(args);
+            }
+        }
+        None => {
+            let _ = SerialPort::new(unsafe { PortIo::new(COM1) }).write_fmt(args);
+        }
+    }
+    if let Some(mut vga) = crate::vga::WRITER.try_lock() {
+        if let Some(writer) = vga.as_mut() {
+            let _ = writer.write_fmt(args);
+        }
+    }
+}
+
+fn _vga_print(args: fmt::Arguments) {
+    use core::fmt::Write;
+    if let Some(writer) = crate::vga::WRITER.lock().as_mut() {
+        let _ = writer.write_fmt(args);
+    }
+}
+
+/// シリアルとVGAに出力する
+#[macro_export]
+macro_rules! print {
+    ($($arg:tt)*) => ($crate::console::_print(format_args!($($arg)*)));
+}
+
+/// シリアルとVGAに出力して改行する
+#[macro_export]
+macro_rules! println {
+    () => ($crate::print!("\n"));
+    ($($arg:tt)*) => ($crate::console::_print(format_args!("{}\n", format_args!($($arg)*))));
+}
+
+/// シリアルだけに出力する
+#[macro_export]
+macro_rules! serial_print {
+    ($($arg:tt)*) => ($crate::console::_serial_print(format_args!($($arg)*)));
+}
+
+/// シリアルだけに出力して改行する
+#[macro_export]
+macro_rules! serial_println {
+    () => ($crate::serial_print!("\n"));
+    ($($arg:tt)*) => ($crate::console::_serial_print(format_args!("{}\n", format_args!($($arg)*))));
+}
+
+// ===== テスト =====
+#[cfg(all(test, not(target_os = "none")))]
+mod tests {
+    use super::*;
+    use crate::vga::{Buffer, Writer, WRITER};
+
+    #[test]
+    fn test_print_reaches_vga_only_when_enabled() {
+        let buffer: &'static mut Buffer = Box::leak(Box::new(Buffer::new()));
+        *WRITER.lock() = Some(Writer::new(buffer));
+
+        set_outputs(true, false);
+        crate::print!("x");
+        let blank = WRITER.lock().as_ref().unwrap().char_at(0, 0);
+
+        set_outputs(true, true);
+        crate::println!("ok {}", 1);
+        let first = WRITER.lock().as_ref().unwrap().char_at(0, 0);
+        let position = WRITER.lock().as_ref().unwrap().position();
+        *WRITER.lock() = None;
+
+        assert_eq!(blank.ascii, b' ', "console=serial のときVGAには出さない");
+        assert_eq!(first.ascii, b'o', "VGAにも出力される");
+        assert_eq!(position, (1, 0), "println! は改行する");
+    }
+}
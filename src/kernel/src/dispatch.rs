@@ -0,0 +1,151 @@
+//! ディスパッチャ（次に動かすプロセスを選んで切り替える）
+//!
+//! 初期化を終えた起動スレッドは、ここで実行可能キュー（process::SCHEDULER）の先頭のプロセスを選び続ける。
+//! ユーザープロセスは `user::run()` で動かし、そこでアドレス空間（CR3）とカーネルスタック（TSSのRSP0と
+//! SYSCALLの入口）をそのプロセスのものに切り替える（`process::set_current()`）。プロセスが終わるか、
+//! IPCで止まるか、時間量子を使い切る（タイマー割り込みで `Exit::Preempted`）と、ここに戻ってくる。
+//! カーネルタスク（カーネルスタックを持たないプロセス）は、選ばれたら `KernelTask::step()` を呼ぶ。
+//! 実行可能なプロセスがなければ hlt で次の割り込みを待つ。
+//!
+//! MINIX 3: kernel/proc.c の switch_to_user()（pick_proc() で選んで restore_user_context() で戻る）と
+//!          sched()（時間量子を使い切ったプロセスはキューの最後に回す）
+//! Linux: kernel/sched/core.c の __schedule()
+
+use crate::process::{Process, Scheduler};
+use crate::user::Exit;
+
+/// 起動スレッドの上で動くカーネルタスク（MINIX 3のカーネルタスクに当たる）
+///
+/// カーネルタスクは横取りしないので、`step()` は少し仕事をしたらすぐに戻ること。
+#[cfg(any(not(test), target_os = "none"))]
+pub trait KernelTask {
+    /// プロセステーブルでのスロット番号
+    fn slot(&self) -> usize;
+
+    /// ディスパッチャに選ばれたときに呼ばれる
+    fn step(&mut self);
+}
+
+/// `user::run()` から戻ったプロセス `slot` を、戻った理由に合わせて並べ直す（終わったならtrue）
+///
+/// 時間量子を使い切ったら量子を戻して同じキューの最後に回し、終わったならキューから外す。
+/// IPCで止まったプロセスは ipc.rs がもうキューから外していて、待ちが解けたらそこで戻す。
+/// MINIX 3: proc.c の sched() と、RTS_SET() で実行可能でなくなったときの dequeue()
+pub fn requeue(scheduler: &mut Scheduler, slot: usize, process: &mut Process, exit: Option<Exit>) -> bool {
+    let priority = process.priority.value();
+    match exit {
+        Some(Exit::Preempted) => {
+            process.ticks_left = process.quantum_size;
+            scheduler.dequeue(slot, priority);
+            scheduler.enqueue(slot, priority);
+            false
+        }
+        Some(Exit::Blocked) => false,
+        // 実行可能でないのにキューに残っていた
+        None => {
+            scheduler.dequeue(slot, priority);
+            false
+        }
+        Some(Exit::Status(_) | Exit::Fault(_)) => {
+            scheduler.dequeue(slot, priority);
+            true
+        }
+    }
+}
+
+/// 実行可能キューから選んだプロセスを動かし続ける（戻らない）
+///
+/// `tasks` は起動スレッドの上で動かすカーネルタスク。終わったユーザープロセスは、IPCの待ちから外して
+/// アドレス空間とカーネルスタックを返し、スロットを空きにする。
+#[cfg(any(not(test), target_os = "none"))]
+pub fn run(tasks: &mut [&mut dyn KernelTask]) -> ! {
+    use crate::process::{self, ProcessFlags, PROCESS_TABLE, SCHEDULER};
+    use crate::user;
+
+    loop {
+        // MINIX 3: pick_proc()。キューが空なら idle() で割り込みを待つ
+        let Some(slot) = SCHEDULER.lock().pick_next() else {
+            crate::arch::halt();
+            continue;
+        };
+        if let Some(task) = tasks.iter_mut().find(|task| task.slot() == slot) {
+            process::set_current(Some(slot));
+            task.step();
+            process::set_current(None);
+            continue;
+        }
+        let exit = match PROCESS_TABLE.get_mut(slot) {
+            // カーネルスタックがなければユーザーモードでは動かせない
+            Some(process) if process.kernel_stack != 0 => user::run(slot),
+            _ => None,
+        };
+        let Some(process) = PROCESS_TABLE.get_mut(slot) else {
+            continue;
+        };
+        if !requeue(&mut SCHEDULER.lock(), slot, process, exit) {
+            continue;
+        }
+        if let Some(exit) = exit {
+            log::info!("{} (pid {}) {}", process.name_str(), process.pid, exit);
+        }
+        // 送受信の待ちから外し、このプロセスを待っていた相手はエラーで起こす
+        crate::ipc::cancel(&PROCESS_TABLE, &mut SCHEDULER.lock(), slot);
+        let Some(process) = PROCESS_TABLE.get_mut(slot) else {
+            continue;
+        };
+        // 安全性: 終わったプロセスはもう動いていない（set_current() は run() が戻してある）
+        unsafe { user::release(process) };
+        process.flags.set(ProcessFlags::SLOT_FREE);
+    }
+}
+
+// ===== テスト =====
+#[cfg(all(test, not(target_os = "none")))]
+mod tests {
+    use super::*;
+    use crate::process::{Priority, ProcessFlags};
+
+    fn scheduler() -> Scheduler {
+        let mut scheduler = Scheduler::new();
+        for slot in [1, 2, 3] {
+            scheduler.enqueue(slot, Priority::USER_Q);
+        }
+        scheduler
+    }
+
+    fn queue(scheduler: &Scheduler) -> Vec<usize> {
+        scheduler.queue(Priority::USER_Q.into()).collect()
+    }
+
+    #[test]
+    fn test_preempted_moves_to_tail() {
+        let mut scheduler = scheduler();
+        let mut process = Process::new(100);
+        process.set_quantum(4);
+        process.ticks_left = 0;
+        assert!(!requeue(&mut scheduler, 1, &mut process, Some(Exit::Preempted)));
+        assert_eq!(queue(&scheduler), [2, 3, 1], "同じ優先度の最後に回る");
+        assert_eq!(process.ticks_left, 4, "時間量子を戻す");
+    }
+
+    #[test]
+    fn test_blocked_is_left_to_ipc() {
+        let mut scheduler = scheduler();
+        let mut process = Process::new(100);
+        process.flags.set(ProcessFlags::RECEIVING);
+        scheduler.dequeue(2, Priority::USER_Q);
+        assert!(!requeue(&mut scheduler, 2, &mut process, Some(Exit::Blocked)));
+        assert_eq!(queue(&scheduler), [1, 3], "止まったときに ipc.rs が外している");
+        assert!(!requeue(&mut scheduler, 3, &mut process, None), "実行できないのに残っていたものは外す");
+        assert_eq!(queue(&scheduler), [1]);
+    }
+
+    #[test]
+    fn test_exited_leaves_queue() {
+        let mut scheduler = scheduler();
+        let mut process = Process::new(100);
+        assert!(requeue(&mut scheduler, 1, &mut process, Some(Exit::Status(0))));
+        assert!(requeue(&mut scheduler, 3, &mut process, Some(Exit::Fault(13))));
+        assert_eq!(queue(&scheduler), [2]);
+    }
+}
@@ -0,0 +1,412 @@
+//! ユーザープログラム（ELF64の実行ファイル）の読み込み
+//!
+//! ヘッダを検証し、PT_LOAD セグメントをその属性（読み取り専用・書き込み可・実行可）で
+//! プロセスのアドレス空間にマップする。ファイルにない部分（.bss）はゼロのまま残る。
+//! ユーザースタックには System V ABI の形で argc、argv、envp、補助ベクタ（auxv）を積む。
+//!
+//! ```text
+//! STACK_TOP   → 文字列（argv、envp の順に、NUL終端）
+//!               （16バイト境界にそろえる）
+//!               auxv: (AT_PHDR, ..) .. (AT_NULL, 0)
+//!               envp[0] .. NULL
+//!               argv[0] .. NULL
+//! rsp         → argc
+//! ```
+//!
+//! 静的リンクした ET_EXEC だけを扱う（動的リンカも再配置もない）。
+//! セグメントは仮想アドレスの順に並び、同じページを共有しないこと（`ld -z max-page-size=0x1000` の既定の配置）。
+//!
+//! MINIX 3: lib/libexec/exec_elf.c の libexec_load_elf() と、servers/pm の stack_fixup
+//! Linux: fs/binfmt_elf.c の load_elf_binary() と create_elf_tables()
+//! MikanOS: terminal.cpp の LoadELF() / MakeArgVector()
+
+use alloc::vec;
+use alloc::vec::Vec;
+use core::fmt;
+
+use crate::arch::MapFlags;
+use crate::memory::vm::{MAX_REGIONS, USER_END};
+use crate::memory::FRAME_SIZE;
+#[cfg(any(not(test), target_os = "none"))]
+use crate::memory::vm::{self, MemoryMap, Region};
+#[cfg(any(not(test), target_os = "none"))]
+use crate::process::SpawnError;
+#[cfg(any(not(test), target_os = "none"))]
+use crate::user::{STACK_SIZE, STACK_TOP};
+
+pub use elf64::{ProgramHeader, PF_W, PF_X};
+
+/// 補助ベクタの終わり
+pub const AT_NULL: u64 = 0;
+/// プログラムヘッダ表の仮想アドレス
+pub const AT_PHDR: u64 = 3;
+/// プログラムヘッダ1つの大きさ
+pub const AT_PHENT: u64 = 4;
+/// プログラムヘッダの数
+pub const AT_PHNUM: u64 = 5;
+/// ページの大きさ
+pub const AT_PAGESZ: u64 = 6;
+/// プログラムのエントリポイント
+pub const AT_ENTRY: u64 = 9;
+
+/// 読み込めるセグメントの数（スタックの領域を1つ残す）
+pub const MAX_SEGMENTS: usize = MAX_REGIONS - 1;
+
+/// ELFの読み込みエラー（最初の3つは elf64 クレートのヘッダの検証から）
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ElfError {
+    /// ファイルが短すぎる（ヘッダやセグメントがファイルの外を指す）
+    Truncated,
+    /// マジックナンバーが違う
+    BadMagic,
+    /// 64bitリトルエンディアンのx86_64実行ファイル（ET_EXEC）ではない
+    Unsupported,
+    /// セグメントの配置が不正（ユーザー空間の外、ページを共有する、filesz > memsz など）
+    BadSegment,
+    /// PT_LOAD セグメントが多すぎる（MAX_SEGMENTS 個まで）
+    TooManySegments,
+    /// エントリポイントが実行可能なセグメントの中にない
+    BadEntry,
+    /// 引数と環境変数がユーザースタックに収まらない
+    ArgumentsTooLong,
+}
+
+impl From<elf64::ElfError> for ElfError {
+    fn from(error: elf64::ElfError) -> Self {
+        match error {
+            elf64::ElfError::Truncated => Self::Truncated,
+            elf64::ElfError::BadMagic => Self::BadMagic,
+            elf64::ElfError::Unsupported => Self::Unsupported,
+        }
+    }
+}
+
+impl fmt::Display for ElfError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Truncated => write!(f, "truncated ELF file"),
+            Self::BadMagic => write!(f, "not an ELF file"),
+            Self::Unsupported => write!(f, "not a static x86_64 ELF64 executable"),
+            Self::BadSegment => write!(f, "bad PT_LOAD segment"),
+            Self::TooManySegments => write!(f, "too many PT_LOAD segments (max {})", MAX_SEGMENTS),
+            Self::BadEntry => write!(f, "entry point outside executable segments"),
+            Self::ArgumentsTooLong => write!(f, "argument list too long"),
+        }
+    }
+}
+
+/// ユーザー空間に置くセグメントとしての見方
+pub trait UserSegment {
+    /// セグメントを含むページの範囲 `[start, end)`
+    fn pages(&self) -> (u64, u64);
+
+    /// ページに付ける属性（読み取りはいつでもできる）
+    fn map_flags(&self) -> MapFlags;
+}
+
+impl UserSegment for ProgramHeader {
+    fn pages(&self) -> (u64, u64) {
+        let start = self.p_vaddr - self.p_vaddr % FRAME_SIZE;
+        let end = (self.p_vaddr + self.p_memsz).next_multiple_of(FRAME_SIZE);
+        (start, end)
+    }
+
+    fn map_flags(&self) -> MapFlags {
+        let mut flags = MapFlags::USER;
+        if self.p_flags & PF_W != 0 {
+            flags |= MapFlags::WRITABLE;
+        }
+        if self.p_flags & PF_X != 0 {
+            flags |= MapFlags::EXECUTABLE;
+        }
+        MapFlags::from_bits(flags)
+    }
+}
+
+/// 検証済みのユーザープログラム
+pub struct ElfFile<'a> {
+    elf: elf64::ElfFile<'a>,
+    /// エントリポイント（e_entry）
+    pub entry: u64,
+}
+
+impl<'a> ElfFile<'a> {
+    /// ELFヘッダとすべての PT_LOAD セグメントを検証する
+    ///
+    /// ここを通れば、セグメントはユーザー空間の中にあり、データはファイルの中にある。
+    pub fn parse(data: &'a [u8]) -> Result<Self, ElfError> {
+        let elf = elf64::ElfFile::parse(data)?;
+        let elf = Self { entry: elf.entry, elf };
+        elf.check_segments()?;
+        Ok(elf)
+    }
+
+    /// PT_LOAD セグメントの配置を確かめる
+    fn check_segments(&self) -> Result<(), ElfError> {
+        let mut count = 0;
+        let mut previous_end = FRAME_SIZE;
+        let mut entry_ok = false;
+        for ph in self.load_segments() {
+            self.elf.segment_data(&ph)?;
+            let mem_end = ph.p_vaddr.checked_add(ph.p_memsz).ok_or(ElfError::BadSegment)?;
+            // 0番のページはマップしない（ヌルポインタを #PF にする）
+            if ph.p_filesz > ph.p_memsz
+                || ph.p_memsz == 0
+                || mem_end > USER_END
+                || ph.p_vaddr % FRAME_SIZE != ph.p_offset % FRAME_SIZE
+                || ph.pages().0 < previous_end
+            {
+                return Err(ElfError::BadSegment);
+            }
+            previous_end = ph.pages().1;
+            entry_ok |= ph.p_flags & PF_X != 0 && ph.contains(self.entry);
+            count += 1;
+        }
+        if count > MAX_SEGMENTS {
+            return Err(ElfError::TooManySegments);
+        }
+        if !entry_ok {
+            return Err(ElfError::BadEntry);
+        }
+        Ok(())
+    }
+
+    /// PT_LOADセグメントを列挙する
+    pub fn load_segments(&self) -> impl Iterator<Item = ProgramHeader> + '_ {
+        self.elf.load_segments()
+    }
+
+    /// セグメントのファイル上のデータ（parse() で範囲を確かめてある）
+    pub fn segment_data(&self, ph: &ProgramHeader) -> &'a [u8] {
+        self.elf.segment_data(ph).unwrap_or_default()
+    }
+
+    /// プログラムヘッダ表を読み込む仮想アドレス（どのセグメントにも含まれなければNone）
+    pub fn phdr_address(&self) -> Option<u64> {
+        self.elf.phdr_address()
+    }
+
+    /// ユーザースタックに積む補助ベクタ（AT_NULL は含まない）
+    /// Linux: create_elf_tables() の NEW_AUX_ENT()
+    pub fn aux_vector(&self) -> Vec<(u64, u64)> {
+        let mut auxv = Vec::new();
+        if let Some(phdr) = self.phdr_address() {
+            auxv.push((AT_PHDR, phdr));
+        }
+        auxv.push((AT_PHENT, self.elf.phentsize() as u64));
+        auxv.push((AT_PHNUM, self.elf.phnum() as u64));
+        auxv.push((AT_PAGESZ, FRAME_SIZE));
+        auxv.push((AT_ENTRY, self.entry));
+        auxv
+    }
+}
+
+/// プロセスを始めるときのユーザースタックの中身
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InitialStack {
+    /// 最初の rsp（argc を指す、16バイト境界）
+    pub rsp: u64,
+    /// `rsp` からスタックの一番上までに書く内容
+    pub bytes: Vec<u8>,
+}
+
+/// 一番上が `top`、大きさ `size` のスタックに argc、argv、envp、補助ベクタを積んだ内容を作る
+pub fn initial_stack(top: u64, size: u64, argv: &[&str], envp: &[&str], auxv: &[(u64, u64)]) -> Result<InitialStack, ElfError> {
+    let strings: usize = argv.iter().chain(envp).map(|s| s.len() + 1).sum();
+    let words = 1 + (argv.len() + 1) + (envp.len() + 1) + 2 * (auxv.len() + 1);
+    let strings_start = top.checked_sub(strings as u64).ok_or(ElfError::ArgumentsTooLong)?;
+    let rsp = strings_start
+        .checked_sub(words as u64 * 8)
+        .map(|rsp| rsp & !0xf)
+        .filter(|&rsp| top - rsp <= size)
+        .ok_or(ElfError::ArgumentsTooLong)?;
+
+    let mut bytes = vec![0; (top - rsp) as usize];
+    let mut words_at = 0;
+    let mut push_word = |bytes: &mut [u8], value: u64| {
+        bytes[words_at..words_at + 8].copy_from_slice(&value.to_le_bytes());
+        words_at += 8;
+    };
+    let mut string_at = (strings_start - rsp) as usize;
+    push_word(&mut bytes, argv.len() as u64);
+    for list in [argv, envp] {
+        for s in list {
+            push_word(&mut bytes, rsp + string_at as u64);
+            bytes[string_at..string_at + s.len()].copy_from_slice(s.as_bytes());
+            string_at += s.len() + 1;
+        }
+        push_word(&mut bytes, 0);
+    }
+    for &(key, value) in auxv.iter().chain(&[(AT_NULL, 0)]) {
+        push_word(&mut bytes, key);
+        push_word(&mut bytes, value);
+    }
+    Ok(InitialStack { rsp, bytes })
+}
+
+/// ELFの実行ファイルをアドレス空間 `memory` に読み込み、ユーザースタックを用意する
+///
+/// 最初の rip（エントリポイント）と rsp を返す。失敗したときにそれまでに確保した領域は、
+/// 呼び出し側が `vm::destroy()` で返す。
+#[cfg(any(not(test), target_os = "none"))]
+pub fn load(memory: &mut MemoryMap, image: &[u8], argv: &[&str], envp: &[&str]) -> Result<(u64, u64), SpawnError> {
+    let elf = ElfFile::parse(image)?;
+    let stack = initial_stack(STACK_TOP, STACK_SIZE, argv, envp, &elf.aux_vector())?;
+    for ph in elf.load_segments() {
+        let (start, end) = ph.pages();
+        vm::allocate(memory, Region::new(start, end, ph.map_flags()))?;
+        vm::write(memory, ph.p_vaddr, elf.segment_data(&ph))?;
+    }
+    let flags = MapFlags::from_bits(MapFlags::USER | MapFlags::WRITABLE);
+    vm::allocate(memory, Region::new(STACK_TOP - STACK_SIZE, STACK_TOP, flags))?;
+    vm::write(memory, stack.rsp, &stack.bytes)?;
+    Ok((elf.entry, stack.rsp))
+}
+
+// ===== テスト =====
+#[cfg(all(test, not(target_os = "none")))]
+mod tests {
+    use super::*;
+    use elf64::{EHDR_SIZE, PHDR_SIZE};
+
+    /// testdata/hello.S を静的リンクしたもの
+    const HELLO: &[u8] = include_bytes!("../testdata/hello.elf");
+    /// 同じものを位置独立（ET_DYN）でリンクしたもの
+    const HELLO_PIE: &[u8] = include_bytes!("../testdata/hello-pie.elf");
+
+    fn word(stack: &InitialStack, addr: u64) -> u64 {
+        let at = (addr - stack.rsp) as usize;
+        u64::from_le_bytes(stack.bytes[at..at + 8].try_into().unwrap())
+    }
+
+    fn string(stack: &InitialStack, addr: u64) -> &str {
+        let bytes = &stack.bytes[(addr - stack.rsp) as usize..];
+        core::str::from_utf8(&bytes[..bytes.iter().position(|&b| b == 0).unwrap()]).unwrap()
+    }
+
+    #[test]
+    fn test_parse_sample() {
+        let elf = ElfFile::parse(HELLO).unwrap();
+        assert_eq!(elf.entry, 0x40_1000);
+        let segments: Vec<_> = elf.load_segments().collect();
+        assert_eq!(segments.len(), 4, "ヘッダ、.text、.rodata、.data+.bss");
+        let flags: Vec<_> = segments.iter().map(UserSegment::map_flags).collect();
+        let (user, writable, executable) = (MapFlags::USER, MapFlags::WRITABLE, MapFlags::EXECUTABLE);
+        let expected = [user, user | executable, user, user | writable].map(MapFlags::from_bits);
+        assert_eq!(flags, expected, "読み取り専用、実行可、書き込み可を分ける");
+
+        let text = &segments[1];
+        assert_eq!(text.pages(), (0x40_1000, 0x40_2000));
+        assert_eq!(elf.segment_data(text)[..5], [0xb8, 1, 0, 0, 0], "mov eax, 1");
+        let data = &segments[3];
+        assert!(data.p_memsz >= data.p_filesz + 8, ".bss はファイルにない");
+        assert_eq!(elf.segment_data(data), 100u64.to_le_bytes());
+        assert_eq!(elf.phdr_address(), Some(0x40_0040), "プログラムヘッダ表は最初のセグメントにある");
+    }
+
+    #[test]
+    fn test_reject_pie() {
+        assert_eq!(ElfFile::parse(HELLO_PIE).err(), Some(ElfError::Unsupported), "ET_DYN は再配置が要る");
+    }
+
+    #[test]
+    fn test_reject_bad_headers() {
+        assert_eq!(ElfFile::parse(&HELLO[..40]).err(), Some(ElfError::Truncated));
+        let mut image = HELLO.to_vec();
+        image[0] = 0;
+        assert_eq!(ElfFile::parse(&image).err(), Some(ElfError::BadMagic));
+        let mut image = HELLO.to_vec();
+        image[4] = 1; // ELFCLASS32
+        assert_eq!(ElfFile::parse(&image).err(), Some(ElfError::Unsupported));
+        let mut image = HELLO.to_vec();
+        image[18] = 0x28; // EM_ARM
+        assert_eq!(ElfFile::parse(&image).err(), Some(ElfError::Unsupported));
+    }
+
+    /// i番目のプログラムヘッダの `field`（ヘッダ内のオフセット）を書き換える
+    fn patch(field: usize, i: usize, value: u64) -> Vec<u8> {
+        let mut image = HELLO.to_vec();
+        let off = EHDR_SIZE + i * PHDR_SIZE + field;
+        image[off..off + 8].copy_from_slice(&value.to_le_bytes());
+        image
+    }
+
+    #[test]
+    fn test_reject_bad_segments() {
+        // p_offset は +8、p_vaddr は +16、p_filesz は +32、p_memsz は +40
+        let image = patch(8, 1, HELLO.len() as u64);
+        assert_eq!(ElfFile::parse(&image).err(), Some(ElfError::Truncated), "データがファイルの外");
+        let image = patch(16, 3, USER_END - 8);
+        assert_eq!(ElfFile::parse(&image).err(), Some(ElfError::BadSegment), "ユーザー空間の外");
+        let image = patch(16, 2, 0x40_1000);
+        assert_eq!(ElfFile::parse(&image).err(), Some(ElfError::BadSegment), ".text と同じページ");
+        let image = patch(40, 3, 4);
+        assert_eq!(ElfFile::parse(&image).err(), Some(ElfError::BadSegment), "filesz > memsz");
+        let image = patch(16, 0, 0);
+        assert_eq!(ElfFile::parse(&image).err(), Some(ElfError::BadSegment), "0番のページ");
+        let mut image = HELLO.to_vec();
+        image[24..32].copy_from_slice(&0x40_2000u64.to_le_bytes());
+        assert_eq!(ElfFile::parse(&image).err(), Some(ElfError::BadEntry), ".rodata は実行できない");
+    }
+
+    #[test]
+    fn test_aux_vector() {
+        let elf = ElfFile::parse(HELLO).unwrap();
+        let auxv = elf.aux_vector();
+        assert!(auxv.contains(&(AT_PHDR, 0x40_0040)));
+        assert!(auxv.contains(&(AT_PHNUM, 5)), "PT_GNU_STACK も数える");
+        assert!(auxv.contains(&(AT_ENTRY, 0x40_1000)));
+        assert!(auxv.contains(&(AT_PAGESZ, 4096)));
+    }
+
+    #[test]
+    fn test_initial_stack() {
+        let top = 0x8000_0000;
+        let stack = initial_stack(top, 0x4000, &["hello", "world"], &["HOME=/"], &[(AT_PAGESZ, 4096)]).unwrap();
+        assert_eq!(stack.rsp % 16, 0, "rsp は16バイト境界");
+        assert_eq!(stack.rsp + stack.bytes.len() as u64, top);
+
+        let mut at = stack.rsp;
+        let mut next = || {
+            let value = word(&stack, at);
+            at += 8;
+            value
+        };
+        assert_eq!(next(), 2, "argc");
+        let (argv0, argv1) = (next(), next());
+        assert_eq!((string(&stack, argv0), string(&stack, argv1)), ("hello", "world"));
+        assert_eq!(next(), 0, "argv の終わり");
+        assert_eq!(string(&stack, next()), "HOME=/");
+        assert_eq!(next(), 0, "envp の終わり");
+        assert_eq!((next(), next()), (AT_PAGESZ, 4096));
+        assert_eq!((next(), next()), (AT_NULL, 0));
+        assert!(at <= argv0, "文字列はポインタの上にある");
+    }
+
+    #[test]
+    fn test_initial_stack_too_long() {
+        let long = "x".repeat(0x1000);
+        let long = long.as_str();
+        assert!(initial_stack(0x8000_0000, 0x4000, &[long; 3], &[], &[]).is_ok());
+        assert_eq!(initial_stack(0x8000_0000, 0x4000, &[long; 4], &[], &[]), Err(ElfError::ArgumentsTooLong));
+    }
+}
+
+#[cfg(all(test, target_os = "none"))]
+mod kernel_tests {
+    use super::*;
+    use crate::process::{ProcessFlags, PROCESS_TABLE};
+    use crate::user::{self, Exit};
+
+    #[test_case]
+    fn test_run_sample_program() {
+        let image = include_bytes!("../testdata/hello.elf");
+        let slot = user::spawn_elf(100, "hello", image, &["hello", "world"], &[]).unwrap();
+        let process = PROCESS_TABLE.get_mut(slot).unwrap();
+        assert!(process.memory.contains_range(0x40_1000, 0x2e, MapFlags::USER | MapFlags::EXECUTABLE));
+        assert!(!process.memory.contains_range(0x40_2000, 1, MapFlags::WRITABLE), ".rodata は書き込めない");
+        assert_eq!(user::run(slot), Some(Exit::Status(102)), "argc + .data の 100 + .bss の 0");
+        unsafe { user::release(process) };
+        process.flags.set(ProcessFlags::SLOT_FREE);
+    }
+}
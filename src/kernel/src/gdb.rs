@@ -0,0 +1,938 @@
+//! GDBリモートスタブ
+//!
+//! 2つ目のシリアルポート（COM2）でGDBのリモートシリアルプロトコルを話し、
+//! カーネルを実機でもデバッグできるようにする。QEMUの `-s` と違って、
+//! カーネル自身が #BP（int3）と #DB（シングルステップ）の例外で止まり、
+//! GDBからのコマンドを処理してから実行を再開する。
+//!
+//! ```bash
+//! # COM1はコンソール、COM2をGDB用のTCPポートにする
+//! qemu-system-x86_64 -kernel kernel -append "gdb=wait" \
+//!     -serial stdio -serial tcp::1234,server,nowait
+//! gdb kernel -ex "target remote :1234"
+//! ```
+//!
+//! 対応するコマンド（プロトコルの一部だけ）:
+//! - `?` 停止理由、`g` / `G` / `p` / `P` レジスタの読み書き
+//! - `m` / `M` メモリの読み書き
+//! - `Z0` / `z0` ソフトウェアブレークポイント（命令の先頭を `int3` に書き換える）
+//! - `c` 継続、`s` シングルステップ（RFLAGSのTFを立てる）、`D` / `k` 切断
+//!
+//! それ以外のコマンドには空の応答を返す（GDBは「未対応」と解釈する）。
+//!
+//! MINIX 3: カーネル内のスタブはなく、QEMUやBochsのデバッガを使っていた
+//! Linux: kernel/debug/gdbstub.c（kgdb）と kgdboc= に相当
+
+use core::fmt;
+
+use crate::arch::StackFrame;
+use crate::serial::{SerialPort, UartIo};
+
+/// パケットの最大長（`$` と `#xx` を除くデータ部分）
+pub const PACKET_SIZE: usize = 1024;
+/// 同時に置けるブレークポイントの数
+pub const MAX_BREAKPOINTS: usize = 16;
+/// 停止理由として返すシグナル（SIGTRAP）
+const SIGTRAP: u8 = 5;
+/// int3 命令
+const INT3: u8 = 0xcc;
+/// RFLAGSのトラップフラグ（1命令ごとに #DB を起こす）
+const RFLAGS_TF: u64 = 1 << 8;
+/// 応答を送り直す回数の上限（GDBが `-` を返し続けるとき）
+const MAX_RETRIES: usize = 8;
+
+/// GDBのamd64のレジスタ番号（rax, rbx, rcx, rdx, rsi, rdi, rbp, rsp, r8〜r15 の後）
+const REG_RIP: usize = 16;
+const REG_EFLAGS: usize = 17;
+/// cs, ss, ds, es, fs, gs（StackFrameにはないので「不明」として返す）
+const REG_SEGMENTS: core::ops::Range<usize> = 18..24;
+
+/// エラー応答: EFAULT（読み書きできないアドレス）
+const ERROR_FAULT: &str = "E0e";
+/// エラー応答: ENOSPC（ブレークポイントの表がいっぱい）
+const ERROR_NO_SPACE: &str = "E1c";
+/// エラー応答: EINVAL（パケットの形式が不正）
+const ERROR_INVALID: &str = "E16";
+
+/// GDBとの通信路（1バイトずつ読み書きする）
+pub trait Connection {
+    /// 1バイト受信する（届くまで待つ）
+    fn read_byte(&mut self) -> u8;
+    fn write_byte(&mut self, byte: u8);
+}
+
+/// UARTはポーリングでGDBと話す（例外ハンドラの中なので割り込みは使えない）
+impl<I: UartIo> Connection for SerialPort<I> {
+    fn read_byte(&mut self) -> u8 {
+        loop {
+            if let Some(byte) = self.try_receive() {
+                return byte;
+            }
+            core::hint::spin_loop();
+        }
+    }
+
+    fn write_byte(&mut self, byte: u8) {
+        self.send(byte);
+    }
+}
+
+/// デバッグ対象のメモリ
+///
+/// 実機ではページテーブルを確かめてから直接読み書きし、テストでは配列を使う。
+pub trait Memory {
+    /// 読めないアドレスならNone
+    fn read(&mut self, address: u64) -> Option<u8>;
+    /// 書けないアドレスならfalse
+    fn write(&mut self, address: u64, value: u8) -> bool;
+}
+
+/// スタブに入った理由の例外
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Trap {
+    /// #BP（int3）
+    Breakpoint,
+    /// #DB（シングルステップ）
+    Debug,
+}
+
+/// GDBの指示で実行を再開する方法
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Resume {
+    /// 次のブレークポイントまで実行する
+    Continue,
+    /// 1命令だけ実行して #DB で戻ってくる
+    Step,
+    /// GDBが切断した（ブレークポイントはすべて外してある）
+    Detach,
+}
+
+/// 書き換えた命令の元のバイト
+#[derive(Debug, Clone, Copy)]
+struct Breakpoint {
+    address: u64,
+    original: u8,
+}
+
+/// 応答パケットのデータを組み立てるバッファ
+struct Reply {
+    data: [u8; PACKET_SIZE],
+    len: usize,
+}
+
+impl Reply {
+    const fn new() -> Self {
+        Self { data: [0; PACKET_SIZE], len: 0 }
+    }
+
+    fn as_bytes(&self) -> &[u8] {
+        &self.data[..self.len]
+    }
+
+    /// 入りきらない分は捨てる（mの長さは入る範囲に制限してある）
+    fn push(&mut self, byte: u8) {
+        if self.len < PACKET_SIZE {
+            self.data[self.len] = byte;
+            self.len += 1;
+        }
+    }
+
+    fn push_hex(&mut self, byte: u8) {
+        self.push(hex_digit(byte >> 4));
+        self.push(hex_digit(byte & 0xf));
+    }
+
+    /// `size` バイトのリトルエンディアン（GDBのレジスタの表現）
+    fn push_le(&mut self, value: u64, size: usize) {
+        for i in 0..size {
+            self.push_hex((value >> (8 * i)) as u8);
+        }
+    }
+}
+
+impl fmt::Write for Reply {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        s.bytes().for_each(|byte| self.push(byte));
+        Ok(())
+    }
+}
+
+/// GDBリモートプロトコルの状態
+pub struct GdbStub {
+    breakpoints: [Option<Breakpoint>; MAX_BREAKPOINTS],
+    /// GDBが `c` / `s` で再開させた（次に止まったら停止を知らせる）
+    attached: bool,
+}
+
+impl Default for GdbStub {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl GdbStub {
+    pub const fn new() -> Self {
+        Self { breakpoints: [None; MAX_BREAKPOINTS], attached: false }
+    }
+
+    /// 置いてあるブレークポイントの数
+    pub fn breakpoint_count(&self) -> usize {
+        self.breakpoints.iter().flatten().count()
+    }
+
+    fn breakpoint_at(&self, address: u64) -> Option<usize> {
+        self.breakpoints.iter().position(|bp| bp.is_some_and(|bp| bp.address == address))
+    }
+
+    /// 例外で止まったところから、GDBが再開を指示するまでコマンドを処理する
+    ///
+    /// `regs` は止まったときのレジスタで、GDBが書き換えた値はそのまま再開に使う。
+    pub fn handle<C: Connection, M: Memory>(
+        &mut self,
+        conn: &mut C,
+        memory: &mut M,
+        regs: &mut StackFrame,
+        trap: Trap,
+    ) -> Resume {
+        // シングルステップは1回ごと（続けるならGDBがまた `s` を送る）
+        regs.rflags &= !RFLAGS_TF;
+        // int3 は実行した後のアドレスで止まるので、自分が置いたものなら命令の先頭に戻す
+        if trap == Trap::Breakpoint && self.breakpoint_at(regs.rip.wrapping_sub(1)).is_some() {
+            regs.rip -= 1;
+        }
+
+        let mut packet = [0u8; PACKET_SIZE];
+        let mut reply = Reply::new();
+        if self.attached {
+            stop_reply(&mut reply);
+            send(conn, reply.as_bytes());
+        }
+        loop {
+            let len = receive(conn, &mut packet);
+            reply.len = 0;
+            if let Some(resume) = self.execute(&packet[..len], memory, regs, &mut reply) {
+                if resume == Resume::Detach && packet[0] == b'D' {
+                    send(conn, reply.as_bytes());
+                }
+                return resume;
+            }
+            send(conn, reply.as_bytes());
+        }
+    }
+
+    /// 1つのコマンドを実行する（再開するならSome、そうでなければ `reply` に応答を書く）
+    fn execute<M: Memory>(
+        &mut self,
+        packet: &[u8],
+        memory: &mut M,
+        regs: &mut StackFrame,
+        reply: &mut Reply,
+    ) -> Option<Resume> {
+        use fmt::Write;
+
+        let (&command, args) = packet.split_first()?;
+        let result = match command {
+            b'?' => {
+                stop_reply(reply);
+                Ok(())
+            }
+            b'g' => {
+                read_registers(regs, reply);
+                Ok(())
+            }
+            b'G' => write_registers(regs, args),
+            b'p' => parse_hex(args)
+                .and_then(|n| read_register(regs, n as usize, reply))
+                .ok_or(ERROR_INVALID),
+            b'P' => write_register(regs, args),
+            b'm' => read_memory(memory, args, reply),
+            b'M' => write_memory(memory, args),
+            b'Z' | b'z' => match args.strip_prefix(b"0,") {
+                Some(args) => self.set_breakpoint(memory, args, command == b'Z'),
+                // ハードウェアブレークポイントやウォッチポイントは未対応（GDBはZ0を使う）
+                None => return None,
+            },
+            b'c' | b's' => {
+                if !args.is_empty() {
+                    regs.rip = parse_hex(args)?;
+                }
+                self.attached = true;
+                if command == b's' {
+                    regs.rflags |= RFLAGS_TF;
+                    return Some(Resume::Step);
+                }
+                return Some(Resume::Continue);
+            }
+            b'D' | b'k' => {
+                self.remove_all_breakpoints(memory);
+                self.attached = false;
+                reply.push(b'O');
+                reply.push(b'K');
+                return Some(Resume::Detach);
+            }
+            b'H' => Ok(()),
+            b'q' if args.starts_with(b"Supported") => {
+                let _ = write!(reply, "PacketSize={:x}", PACKET_SIZE);
+                return None;
+            }
+            b'q' if args == b"Attached" => {
+                // 切断してもカーネルは動き続ける
+                reply.push(b'1');
+                return None;
+            }
+            _ => return None,
+        };
+        match result {
+            Ok(()) if reply.len == 0 => {
+                let _ = reply.write_str("OK");
+            }
+            Ok(()) => {}
+            Err(error) => {
+                reply.len = 0;
+                let _ = reply.write_str(error);
+            }
+        }
+        None
+    }
+
+    /// `Z0,addr,kind` / `z0,addr,kind`（`args` は `addr,kind`）
+    fn set_breakpoint<M: Memory>(&mut self, memory: &mut M, args: &[u8], insert: bool) -> Result<(), &'static str> {
+        let (address, _kind) = split(args, b',').ok_or(ERROR_INVALID)?;
+        let address = parse_hex(address).ok_or(ERROR_INVALID)?;
+        let existing = self.breakpoint_at(address);
+
+        if !insert {
+            if let Some(i) = existing {
+                let bp = self.breakpoints[i].take().unwrap();
+                memory.write(bp.address, bp.original);
+            }
+            return Ok(());
+        }
+        if existing.is_some() {
+            return Ok(());
+        }
+        let slot = self.breakpoints.iter().position(Option::is_none).ok_or(ERROR_NO_SPACE)?;
+        let original = memory.read(address).ok_or(ERROR_FAULT)?;
+        if !memory.write(address, INT3) {
+            return Err(ERROR_FAULT);
+        }
+        self.breakpoints[slot] = Some(Breakpoint { address, original });
+        Ok(())
+    }
+
+    fn remove_all_breakpoints<M: Memory>(&mut self, memory: &mut M) {
+        for bp in self.breakpoints.iter_mut().filter_map(Option::take) {
+            memory.write(bp.address, bp.original);
+        }
+    }
+}
+
+/// 停止理由（常にSIGTRAP）
+fn stop_reply(reply: &mut Reply) {
+    reply.push(b'S');
+    reply.push_hex(SIGTRAP);
+}
+
+/// GDBのレジスタ番号 `n` の値（eflagsは下位32ビット）
+fn register(regs: &mut StackFrame, n: usize) -> Option<&mut u64> {
+    Some(match n {
+        0 => &mut regs.rax,
+        1 => &mut regs.rbx,
+        2 => &mut regs.rcx,
+        3 => &mut regs.rdx,
+        4 => &mut regs.rsi,
+        5 => &mut regs.rdi,
+        6 => &mut regs.rbp,
+        7 => &mut regs.rsp,
+        8 => &mut regs.r8,
+        9 => &mut regs.r9,
+        10 => &mut regs.r10,
+        11 => &mut regs.r11,
+        12 => &mut regs.r12,
+        13 => &mut regs.r13,
+        14 => &mut regs.r14,
+        15 => &mut regs.r15,
+        REG_RIP => &mut regs.rip,
+        REG_EFLAGS => &mut regs.rflags,
+        _ => return None,
+    })
+}
+
+/// レジスタのバイト数
+fn register_size(n: usize) -> usize {
+    if n < REG_EFLAGS { 8 } else { 4 }
+}
+
+/// `p n`: 1つのレジスタ（範囲外ならNone）
+fn read_register(regs: &mut StackFrame, n: usize, reply: &mut Reply) -> Option<()> {
+    if REG_SEGMENTS.contains(&n) {
+        // 値がわからないレジスタは `x` で埋める
+        (0..8).for_each(|_| reply.push(b'x'));
+        return Some(());
+    }
+    let value = *register(regs, n)?;
+    reply.push_le(value, register_size(n));
+    Some(())
+}
+
+/// `g`: すべてのレジスタ
+fn read_registers(regs: &mut StackFrame, reply: &mut Reply) {
+    for n in 0..REG_SEGMENTS.end {
+        read_register(regs, n, reply);
+    }
+}
+
+/// `G data`: 汎用レジスタ・rip・eflagsを書き換える（セグメントは無視）
+fn write_registers(regs: &mut StackFrame, data: &[u8]) -> Result<(), &'static str> {
+    let mut offset = 0;
+    for n in 0..=REG_EFLAGS {
+        let size = register_size(n);
+        let hex = data.get(offset..offset + size * 2).ok_or(ERROR_INVALID)?;
+        set_register(regs, n, parse_le(hex).ok_or(ERROR_INVALID)?);
+        offset += size * 2;
+    }
+    Ok(())
+}
+
+/// `P n=value`
+fn write_register(regs: &mut StackFrame, args: &[u8]) -> Result<(), &'static str> {
+    let (n, value) = split(args, b'=').ok_or(ERROR_INVALID)?;
+    let n = parse_hex(n).ok_or(ERROR_INVALID)? as usize;
+    if REG_SEGMENTS.contains(&n) {
+        return Ok(());
+    }
+    if n > REG_EFLAGS || value.len() != register_size(n) * 2 {
+        return Err(ERROR_INVALID);
+    }
+    set_register(regs, n, parse_le(value).ok_or(ERROR_INVALID)?);
+    Ok(())
+}
+
+fn set_register(regs: &mut StackFrame, n: usize, value: u64) {
+    if let Some(register) = register(regs, n) {
+        *register = if n == REG_EFLAGS { (*register & !0xffff_ffff) | value } else { value };
+    }
+}
+
+/// `m addr,len`: 読めたところまで返す（先頭から読めなければエラー）
+fn read_memory<M: Memory>(memory: &mut M, args: &[u8], reply: &mut Reply) -> Result<(), &'static str> {
+    let (address, len) = parse_range(args)?;
+    let len = len.min(PACKET_SIZE as u64 / 2);
+    for i in 0..len {
+        match memory.read(address.wrapping_add(i)) {
+            Some(byte) => reply.push_hex(byte),
+            None if i == 0 => return Err(ERROR_FAULT),
+            None => break,
+        }
+    }
+    Ok(())
+}
+
+/// `M addr,len:data`
+fn write_memory<M: Memory>(memory: &mut M, args: &[u8]) -> Result<(), &'static str> {
+    let (range, data) = split(args, b':').ok_or(ERROR_INVALID)?;
+    let (address, len) = parse_range(range)?;
+    if len.checked_mul(2) != Some(data.len() as u64) {
+        return Err(ERROR_INVALID);
+    }
+    for (i, hex) in data.chunks(2).enumerate() {
+        let byte = parse_hex(hex).ok_or(ERROR_INVALID)? as u8;
+        if !memory.write(address.wrapping_add(i as u64), byte) {
+            return Err(ERROR_FAULT);
+        }
+    }
+    Ok(())
+}
+
+/// `addr,len`
+fn parse_range(args: &[u8]) -> Result<(u64, u64), &'static str> {
+    let (address, len) = split(args, b',').ok_or(ERROR_INVALID)?;
+    Ok((parse_hex(address).ok_or(ERROR_INVALID)?, parse_hex(len).ok_or(ERROR_INVALID)?))
+}
+
+/// `separator` の最初の位置で2つに分ける
+fn split(bytes: &[u8], separator: u8) -> Option<(&[u8], &[u8])> {
+    let i = bytes.iter().position(|&b| b == separator)?;
+    Some((&bytes[..i], &bytes[i + 1..]))
+}
+
+fn hex_digit(value: u8) -> u8 {
+    b"0123456789abcdef"[usize::from(value & 0xf)]
+}
+
+fn hex_value(digit: u8) -> Option<u8> {
+    (digit as char).to_digit(16).map(|v| v as u8)
+}
+
+/// 16進数（ビッグエンディアン、最大16桁）
+fn parse_hex(hex: &[u8]) -> Option<u64> {
+    if hex.is_empty() || hex.len() > 16 {
+        return None;
+    }
+    hex.iter().try_fold(0u64, |value, &digit| Some((value << 4) | u64::from(hex_value(digit)?)))
+}
+
+/// リトルエンディアンのバイト列の16進数（レジスタの値）
+fn parse_le(hex: &[u8]) -> Option<u64> {
+    if !hex.len().is_multiple_of(2) || hex.len() > 16 {
+        return None;
+    }
+    hex.chunks(2)
+        .enumerate()
+        .try_fold(0u64, |value, (i, byte)| Some(value | (parse_hex(byte)? << (8 * i))))
+}
+
+/// `$data#xx` のパケットを1つ受け取り、`+` を返す（チェックサムが違えば `-` で再送を頼む）
+fn receive<C: Connection>(conn: &mut C, packet: &mut [u8; PACKET_SIZE]) -> usize {
+    loop {
+        // 前の応答への `+` や Ctrl-C（0x03）は読み飛ばす
+        while conn.read_byte() != b'$' {}
+        let mut len = 0;
+        let mut sum = 0u8;
+        let mut overflow = false;
+        loop {
+            let byte = conn.read_byte();
+            if byte == b'#' {
+                break;
+            }
+            sum = sum.wrapping_add(byte);
+            if len < PACKET_SIZE {
+                packet[len] = byte;
+                len += 1;
+            } else {
+                overflow = true;
+            }
+        }
+        let checksum = [conn.read_byte(), conn.read_byte()];
+        if !overflow && len > 0 && parse_hex(&checksum) == Some(u64::from(sum)) {
+            conn.write_byte(b'+');
+            return len;
+        }
+        conn.write_byte(b'-');
+    }
+}
+
+/// `$data#xx` を送り、`+` が返るまで送り直す
+fn send<C: Connection>(conn: &mut C, data: &[u8]) {
+    let sum = data.iter().fold(0u8, |sum, &b| sum.wrapping_add(b));
+    for _ in 0..MAX_RETRIES {
+        conn.write_byte(b'$');
+        data.iter().for_each(|&b| conn.write_byte(b));
+        conn.write_byte(b'#');
+        conn.write_byte(hex_digit(sum >> 4));
+        conn.write_byte(hex_digit(sum & 0xf));
+        if conn.read_byte() == b'+' {
+            return;
+        }
+    }
+}
+
+// ===== 実機での接続 =====
+
+#[cfg(any(not(test), target_os = "none"))]
+use crate::arch::interrupts::InterruptFrame;
+#[cfg(any(not(test), target_os = "none"))]
+use crate::serial::{PortIo, SerialError};
+#[cfg(any(not(test), target_os = "none"))]
+use crate::sync::SpinLock;
+
+/// COM2とスタブ（init() の前はNone = デバッガなし）
+#[cfg(any(not(test), target_os = "none"))]
+static DEBUGGER: SpinLock<Option<(SerialPort<PortIo>, GdbStub)>> = SpinLock::new(None);
+
+/// COM2を初期化してスタブを有効にする
+///
+/// 以後の #BP / #DB はパニックではなくGDBに報告される。
+#[cfg(any(not(test), target_os = "none"))]
+pub fn init() -> Result<(), SerialError> {
+    let mut port = SerialPort::new(unsafe { PortIo::new(crate::serial::COM2) });
+    port.init(crate::serial::DEFAULT_BAUD)?;
+    *DEBUGGER.lock() = Some((port, GdbStub::new()));
+    Ok(())
+}
+
+/// ここで止まってGDBを待つ（`gdb=wait` のとき起動中に呼ぶ）
+#[cfg(any(not(test), target_os = "none"))]
+pub fn breakpoint() {
+    unsafe { core::arch::asm!("int3", options(nomem, nostack)) };
+}
+
+/// #BP / #DB の処理（割り込みの共通処理から呼ばれる）
+///
+/// スタブが無効なら何もせずfalseを返し、例外は通常どおりパニックになる。
+#[cfg(any(not(test), target_os = "none"))]
+pub fn handle_exception(frame: &mut InterruptFrame) -> bool {
+    let trap = match frame.vector {
+        1 => Trap::Debug,
+        3 => Trap::Breakpoint,
+        _ => return false,
+    };
+    // スタブの中で起きた例外（ロックを持ったまま）はパニックにする
+    let Some(mut debugger) = DEBUGGER.try_lock() else {
+        return false;
+    };
+    let Some((port, stub)) = debugger.as_mut() else {
+        return false;
+    };
+    let mut regs = StackFrame::from(&*frame);
+    stub.handle(port, &mut KernelMemory, &mut regs, trap);
+    frame.set_registers(&regs);
+    true
+}
+
+/// カーネルの仮想アドレス空間（ページテーブルを見て、マップされていなければ触らない）
+#[cfg(any(not(test), target_os = "none"))]
+struct KernelMemory;
+
+#[cfg(any(not(test), target_os = "none"))]
+impl Memory for KernelMemory {
+    fn read(&mut self, address: u64) -> Option<u8> {
+        crate::arch::is_mapped(address).then(|| unsafe { core::ptr::read_volatile(address as *const u8) })
+    }
+
+    fn write(&mut self, address: u64, value: u8) -> bool {
+        if !crate::arch::is_mapped(address) {
+            return false;
+        }
+        // .text は読み取り専用でマップされているので、CR0.WPを外して書く
+        unsafe { crate::arch::without_write_protect(|| core::ptr::write_volatile(address as *mut u8, value)) };
+        true
+    }
+}
+
+// ===== テスト =====
+#[cfg(all(test, not(target_os = "none")))]
+mod tests {
+    use super::*;
+    use std::collections::VecDeque;
+
+    /// GDB側の模擬: 送るバイト列を並べておき、スタブの出力を記録する
+    #[derive(Default)]
+    struct MockGdb {
+        input: VecDeque<u8>,
+        output: Vec<u8>,
+    }
+
+    impl MockGdb {
+        /// コマンドを送り、応答には `+` を返す
+        fn with_commands(commands: &[&str]) -> Self {
+            let mut gdb = Self::default();
+            for command in commands {
+                gdb.input.extend(packet(command).bytes());
+                gdb.input.push_back(b'+');
+            }
+            gdb
+        }
+
+        /// スタブが送った応答のデータ部分
+        fn replies(&self) -> Vec<String> {
+            let text = String::from_utf8(self.output.clone()).unwrap();
+            text.split('$')
+                .skip(1)
+                .map(|p| {
+                    let (data, checksum) = p.split_once('#').unwrap();
+                    assert_eq!(&checksum[..2], format!("{:02x}", checksum_of(data)), "応答のチェックサム");
+                    data.to_string()
+                })
+                .collect()
+        }
+    }
+
+    impl Connection for MockGdb {
+        fn read_byte(&mut self) -> u8 {
+            self.input.pop_front().expect("スタブが入力の終わりを越えて読もうとした")
+        }
+
+        fn write_byte(&mut self, byte: u8) {
+            self.output.push(byte);
+        }
+    }
+
+    fn checksum_of(data: &str) -> u8 {
+        data.bytes().fold(0u8, |sum, b| sum.wrapping_add(b))
+    }
+
+    fn packet(data: &str) -> String {
+        format!("${}#{:02x}", data, checksum_of(data))
+    }
+
+    /// `base` から始まる配列のメモリ
+    struct MockMemory {
+        base: u64,
+        bytes: Vec<u8>,
+    }
+
+    impl MockMemory {
+        fn new(base: u64, bytes: &[u8]) -> Self {
+            Self { base, bytes: bytes.to_vec() }
+        }
+
+        fn slot(&mut self, address: u64) -> Option<&mut u8> {
+            let offset = usize::try_from(address.checked_sub(self.base)?).ok()?;
+            self.bytes.get_mut(offset)
+        }
+    }
+
+    impl Memory for MockMemory {
+        fn read(&mut self, address: u64) -> Option<u8> {
+            self.slot(address).copied()
+        }
+
+        fn write(&mut self, address: u64, value: u8) -> bool {
+            self.slot(address).map(|slot| *slot = value).is_some()
+        }
+    }
+
+    /// コマンドを順に実行して、応答と再開方法を返す
+    fn run(stub: &mut GdbStub, memory: &mut MockMemory, regs: &mut StackFrame, trap: Trap, commands: &[&str]) -> (Vec<String>, Resume) {
+        let mut gdb = MockGdb::with_commands(commands);
+        if stub.attached {
+            // 最初に送られる停止の通知への `+`
+            gdb.input.push_front(b'+');
+        }
+        let resume = stub.handle(&mut gdb, memory, regs, trap);
+        (gdb.replies(), resume)
+    }
+
+    mod packet_tests {
+        use super::*;
+
+        #[test]
+        fn test_ack_and_checksum() {
+            let mut gdb = MockGdb::with_commands(&["?"]);
+            let mut buffer = [0u8; PACKET_SIZE];
+            let len = receive(&mut gdb, &mut buffer);
+            assert_eq!(&buffer[..len], b"?");
+            assert_eq!(gdb.output, b"+", "正しいパケットには + を返す");
+
+            send(&mut gdb, b"S05");
+            assert_eq!(&gdb.output[1..], b"$S05#b8", "`$data#チェックサム` の形で送る");
+        }
+
+        #[test]
+        fn test_bad_checksum_is_retransmitted() {
+            let mut gdb = MockGdb::default();
+            gdb.input.extend(b"+$?#00$?#3f");
+            let mut buffer = [0u8; PACKET_SIZE];
+            let len = receive(&mut gdb, &mut buffer);
+            assert_eq!(&buffer[..len], b"?", "送り直されたパケットを受け取る");
+            assert_eq!(gdb.output, b"-+", "チェックサムが違えば - で再送を頼む");
+        }
+
+        #[test]
+        fn test_nak_resends_reply() {
+            let mut gdb = MockGdb::default();
+            gdb.input.extend(b"-+");
+            send(&mut gdb, b"OK");
+            assert_eq!(gdb.output, b"$OK#9a$OK#9a", "- が返れば同じ応答を送り直す");
+        }
+
+        #[test]
+        fn test_hex() {
+            assert_eq!(parse_hex(b"ffffffff80100000"), Some(0xffff_ffff_8010_0000));
+            assert_eq!(parse_hex(b""), None);
+            assert_eq!(parse_hex(b"12g"), None);
+            assert_eq!(parse_le(b"78563412"), Some(0x1234_5678), "レジスタはリトルエンディアン");
+        }
+    }
+
+    mod register_tests {
+        use super::*;
+
+        #[test]
+        fn test_read_registers() {
+            let mut regs = StackFrame::new();
+            regs.rax = 0x1122_3344_5566_7788;
+            regs.rsp = 0xffff_8000_0001_0000;
+            regs.rip = 0xffff_ffff_8010_0000;
+            regs.rflags = 0x246;
+            let (replies, _) = run(&mut GdbStub::new(), &mut MockMemory::new(0, &[]), &mut regs, Trap::Breakpoint, &["g", "c"]);
+            let g = &replies[0];
+            assert_eq!(g.len(), 17 * 16 + 8 + 6 * 8, "16個の汎用レジスタ・rip・eflags・6個のセグメント");
+            assert_eq!(&g[..16], "8877665544332211", "raxは先頭にリトルエンディアンで");
+            assert_eq!(&g[7 * 16..8 * 16], "000001000080ffff", "rspは8番目（GDBの並び）");
+            assert_eq!(&g[16 * 16..17 * 16], "00001080ffffffff", "ripは16番");
+            assert_eq!(&g[17 * 16..17 * 16 + 8], "46020000", "eflagsは4バイト");
+            assert!(g.ends_with("xxxxxxxx"), "セグメントは不明として返す");
+        }
+
+        #[test]
+        fn test_write_registers() {
+            let mut regs = StackFrame::new();
+            regs.rflags = 0xdead_0000_0000_0002;
+            let mut data = String::new();
+            for n in 0..17u64 {
+                data += &format!("{:016x}", (n + 1).swap_bytes());
+            }
+            data += "02030000";
+            data += &"x".repeat(48);
+            let command = format!("G{}", data);
+            let (replies, _) = run(&mut GdbStub::new(), &mut MockMemory::new(0, &[]), &mut regs, Trap::Breakpoint, &[&command, "c"]);
+            assert_eq!(replies, ["OK"]);
+            assert_eq!((regs.rax, regs.rbx, regs.rbp, regs.rsp, regs.r15), (1, 2, 7, 8, 16));
+            assert_eq!(regs.rip, 17);
+            assert_eq!(regs.rflags, 0xdead_0000_0000_0302, "eflagsは下位32ビットだけ書き換える");
+        }
+
+        #[test]
+        fn test_single_register() {
+            let mut regs = StackFrame::new();
+            regs.r8 = 0xabcd;
+            let (replies, _) = run(
+                &mut GdbStub::new(),
+                &mut MockMemory::new(0, &[]),
+                &mut regs,
+                Trap::Breakpoint,
+                &["p8", "P10=0010000000000000", "p12", "p40", "c"],
+            );
+            assert_eq!(replies, ["cdab000000000000", "OK", "xxxxxxxx", "E16"], "p12 はcs（不明）、範囲外はエラー");
+            assert_eq!(regs.rip, 0x1000, "P10 はrip");
+        }
+    }
+
+    mod memory_tests {
+        use super::*;
+
+        #[test]
+        fn test_read_and_write_memory() {
+            let mut memory = MockMemory::new(0x1000, &[0x55, 0x48, 0x89, 0xe5]);
+            let (replies, _) = run(
+                &mut GdbStub::new(),
+                &mut memory,
+                &mut StackFrame::new(),
+                Trap::Breakpoint,
+                &["m1000,4", "M1001,2:9090", "m1000,8", "m0,4", "M2000,1:00", "c"],
+            );
+            assert_eq!(replies, ["554889e5", "OK", "559090e5", "E0e", "E0e"], "範囲外は読めたところまで、先頭から読めなければエラー");
+            assert_eq!(memory.bytes, [0x55, 0x90, 0x90, 0xe5]);
+        }
+
+        #[test]
+        fn test_malformed_write() {
+            let mut memory = MockMemory::new(0x1000, &[0; 4]);
+            let (replies, _) = run(&mut GdbStub::new(), &mut memory, &mut StackFrame::new(), Trap::Breakpoint, &["M1000,2:00", "c"]);
+            assert_eq!(replies, ["E16"], "長さとデータが合わない");
+            assert_eq!(memory.bytes, [0; 4]);
+        }
+
+        #[test]
+        fn test_write_length_overflow() {
+            let mut memory = MockMemory::new(0x1000, &[0; 4]);
+            let packets = ["M1000,8000000000000000:", "M1000,ffffffffffffffff:00", "c"];
+            let (replies, _) = run(&mut GdbStub::new(), &mut memory, &mut StackFrame::new(), Trap::Breakpoint, &packets);
+            assert_eq!(replies, ["E16", "E16"], "長さの2倍があふれてもパニックしない");
+            assert_eq!(memory.bytes, [0; 4]);
+        }
+    }
+
+    mod execution_tests {
+        use super::*;
+
+        #[test]
+        fn test_breakpoint_round_trip() {
+            let mut stub = GdbStub::new();
+            let mut memory = MockMemory::new(0x1000, &[0x55, 0x48, 0x89, 0xe5]);
+            let mut regs = StackFrame::new();
+
+            // 最初の停止: ブレークポイントを置いて継続
+            let (replies, resume) = run(&mut stub, &mut memory, &mut regs, Trap::Breakpoint, &["?", "Z0,1001,1", "c"]);
+            assert_eq!(replies, ["S05", "OK"]);
+            assert_eq!(resume, Resume::Continue);
+            assert_eq!(memory.bytes[1], INT3, "命令の先頭を int3 に書き換える");
+            assert_eq!(stub.breakpoint_count(), 1);
+
+            // int3 を実行した後（rip=0x1002）で止まる
+            regs.rip = 0x1002;
+            let (replies, _) = run(&mut stub, &mut memory, &mut regs, Trap::Breakpoint, &["z0,1001,1", "c"]);
+            assert_eq!(replies, ["S05", "OK"], "止まったことをすぐに知らせる");
+            assert_eq!(regs.rip, 0x1001, "自分のブレークポイントなら命令の先頭に戻す");
+            assert_eq!(memory.bytes[1], 0x48, "外すと元の命令に戻る");
+            assert_eq!(stub.breakpoint_count(), 0);
+        }
+
+        #[test]
+        fn test_foreign_int3_keeps_rip() {
+            let mut regs = StackFrame::new();
+            regs.rip = 0x2001;
+            run(&mut GdbStub::new(), &mut MockMemory::new(0x1000, &[0; 4]), &mut regs, Trap::Breakpoint, &["c"]);
+            assert_eq!(regs.rip, 0x2001, "コードに書かれた int3 はその次から続ける");
+        }
+
+        #[test]
+        fn test_breakpoint_errors() {
+            let mut stub = GdbStub::new();
+            let mut memory = MockMemory::new(0x1000, &[0; MAX_BREAKPOINTS + 1]);
+            let mut commands: Vec<String> = (0..=MAX_BREAKPOINTS).map(|i| format!("Z0,{:x},1", 0x1000 + i)).collect();
+            commands.push("Z0,9000,1".into());
+            commands.push("Z1,1000,1".into());
+            commands.push("c".into());
+            let commands: Vec<&str> = commands.iter().map(String::as_str).collect();
+            let (replies, _) = run(&mut stub, &mut memory, &mut StackFrame::new(), Trap::Breakpoint, &commands);
+            assert_eq!(replies[MAX_BREAKPOINTS], "E1c", "表がいっぱい");
+            assert_eq!(replies[MAX_BREAKPOINTS + 1], "E1c");
+            assert_eq!(replies[MAX_BREAKPOINTS + 2], "", "ハードウェアブレークポイントは未対応");
+            assert_eq!(stub.breakpoint_count(), MAX_BREAKPOINTS);
+        }
+
+        #[test]
+        fn test_single_step() {
+            let mut stub = GdbStub::new();
+            let mut memory = MockMemory::new(0, &[]);
+            let mut regs = StackFrame::new();
+            regs.rflags = 0x202;
+
+            let (_, resume) = run(&mut stub, &mut memory, &mut regs, Trap::Breakpoint, &["s"]);
+            assert_eq!(resume, Resume::Step);
+            assert_ne!(regs.rflags & RFLAGS_TF, 0, "TFを立てて1命令だけ実行する");
+
+            let (replies, resume) = run(&mut stub, &mut memory, &mut regs, Trap::Debug, &["c2000"]);
+            assert_eq!(replies, ["S05"]);
+            assert_eq!(resume, Resume::Continue);
+            assert_eq!(regs.rflags, 0x202, "止まったらTFを下ろす");
+            assert_eq!(regs.rip, 0x2000, "c にアドレスがあればそこから続ける");
+        }
+
+        #[test]
+        fn test_detach_removes_breakpoints() {
+            let mut stub = GdbStub::new();
+            let mut memory = MockMemory::new(0x1000, &[0x90; 2]);
+            let (replies, resume) = run(&mut stub, &mut memory, &mut StackFrame::new(), Trap::Breakpoint, &["Z0,1000,1", "Z0,1001,1", "D"]);
+            assert_eq!(replies, ["OK", "OK", "OK"]);
+            assert_eq!(resume, Resume::Detach);
+            assert_eq!(memory.bytes, [0x90; 2], "切断したら命令を元に戻す");
+            assert_eq!(stub.breakpoint_count(), 0);
+
+            // 切断した後に止まっても、GDBがつなぎ直すまでは何も送らない
+            let (replies, _) = run(&mut stub, &mut memory, &mut StackFrame::new(), Trap::Breakpoint, &["c"]);
+            assert!(replies.is_empty());
+        }
+
+        #[test]
+        fn test_queries() {
+            let (replies, _) = run(
+                &mut GdbStub::new(),
+                &mut MockMemory::new(0, &[]),
+                &mut StackFrame::new(),
+                Trap::Breakpoint,
+                &["qSupported:multiprocess+;swbreak+", "qAttached", "Hg0", "vMustReplyEmpty", "c"],
+            );
+            assert_eq!(replies, ["PacketSize=400", "1", "OK", ""], "知らないコマンドには空の応答");
+        }
+    }
+}
+
+// ===== カーネル内テスト =====
+#[cfg(all(test, target_os = "none"))]
+mod kernel_tests {
+    use super::*;
+
+    static TARGET: [u8; 4] = [0x12, 0x34, 0x56, 0x78];
+
+    #[test_case]
+    fn test_kernel_memory_checks_mapping() {
+        let address = TARGET.as_ptr() as u64;
+        assert_eq!(KernelMemory.read(address + 1), Some(0x34), "カーネルのデータは読める");
+        assert_eq!(KernelMemory.read(0), None, "NULLページはマップされていない");
+        assert_eq!(KernelMemory.read(0x0000_8000_0000_0000), None, "正規形でないアドレス");
+    }
+}
@@ -0,0 +1,391 @@
+//! 初期RAMディスク（initrd）
+//!
+//! ブートローダーがカーネルと一緒に読み込んだ cpio アーカイブ（最初のモジュール）を、
+//! 読み出し専用のファイルシステムとして使う。起動時に一度だけ解析してパスの表を作り、
+//! ファイルの中身はアーカイブのメモリを指したまま使う（コピーしない）。
+//! アーカイブはホストの `mkinitrd`（tools）でディレクトリから作る。
+//!
+//! newc 形式（`cpio -H newc` と同じ）:
+//! ```text
+//! "070701" と8桁の16進数13個（ino, mode, uid, gid, nlink, mtime, filesize,
+//!          devmajor, devminor, rdevmajor, rdevminor, namesize, check）  110バイト
+//! 名前（NUL終端で namesize バイト）           ヘッダと合わせて4バイト境界まで詰める
+//! 中身（filesize バイト）                     4バイト境界まで詰める
+//! ……名前が "TRAILER!!!" のエントリで終わり
+//! ```
+//!
+//! MINIX 3: ブートイメージのRAMディスク（drivers/memory の /dev/imgrd）をルートにしてから /sbin/init を起動する
+//! Linux: init/initramfs.c（newc の cpio を rootfs に展開する）
+
+use alloc::collections::BTreeMap;
+use alloc::string::String;
+#[cfg(any(not(test), target_os = "none"))]
+use alloc::vec::Vec;
+use core::fmt;
+use core::ops::Bound;
+
+#[cfg(any(not(test), target_os = "none"))]
+use boot_info::BootInfo;
+
+#[cfg(any(not(test), target_os = "none"))]
+use crate::process::{ProcessId, PROCESS_TABLE, SCHEDULER};
+use crate::sync::SpinLock;
+#[cfg(any(not(test), target_os = "none"))]
+use crate::user;
+
+/// newc 形式のマジック
+pub const NEWC_MAGIC: &[u8; 6] = b"070701";
+
+/// ヘッダの大きさ（マジックと16進数8桁×13）
+pub const HEADER_LEN: usize = 110;
+
+/// アーカイブの終わりを表すエントリの名前
+pub const TRAILER: &str = "TRAILER!!!";
+
+/// ファイルの種類を取り出すマスク（mode の上位ビット）
+pub const S_IFMT: u32 = 0o170_000;
+/// ディレクトリ
+pub const S_IFDIR: u32 = 0o040_000;
+/// 普通のファイル
+pub const S_IFREG: u32 = 0o100_000;
+
+/// 最初のユーザープロセス（init）のプロセス番号
+/// MINIX 3: INIT_PROC_NR（PMが最初に fork するプロセス）
+#[cfg(any(not(test), target_os = "none"))]
+pub const INIT_PID: ProcessId = 1;
+
+/// アーカイブを解析できなかった理由
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InitrdError {
+    /// ヘッダや中身の途中でアーカイブが終わっている（TRAILER!!! がない）
+    Truncated,
+    /// newc 形式のマジックではない
+    BadMagic,
+    /// ヘッダの数値が16進数でない
+    BadHeader,
+    /// 名前がNUL終端のUTF-8でないか、".." を含む
+    BadName,
+}
+
+impl fmt::Display for InitrdError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Truncated => write!(f, "archive truncated"),
+            Self::BadMagic => write!(f, "not a newc cpio archive"),
+            Self::BadHeader => write!(f, "malformed header"),
+            Self::BadName => write!(f, "invalid file name"),
+        }
+    }
+}
+
+/// アーカイブの1つのエントリ
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Entry<'a> {
+    /// 種類と許可（st_mode）
+    pub mode: u32,
+    /// 中身（ディレクトリなら空）
+    pub data: &'a [u8],
+}
+
+impl Entry<'_> {
+    pub fn is_dir(&self) -> bool {
+        self.mode & S_IFMT == S_IFDIR
+    }
+
+    pub fn is_file(&self) -> bool {
+        self.mode & S_IFMT == S_IFREG
+    }
+}
+
+/// 解析したアーカイブ（絶対パスからエントリを引く表）
+#[derive(Debug, Default)]
+pub struct Initrd<'a> {
+    entries: BTreeMap<String, Entry<'a>>,
+}
+
+impl<'a> Initrd<'a> {
+    /// newc 形式のアーカイブ `archive` を解析する
+    ///
+    /// 同じパスが2回出てきたら後のものを使う（cpio で展開したときと同じ）。
+    pub fn parse(archive: &'a [u8]) -> Result<Self, InitrdError> {
+        let mut entries = BTreeMap::new();
+        let mut offset = 0;
+        loop {
+            let header = archive.get(offset..offset + HEADER_LEN).ok_or(InitrdError::Truncated)?;
+            if &header[..6] != NEWC_MAGIC {
+                return Err(InitrdError::BadMagic);
+            }
+            let mode = field(header, 1)?;
+            let file_size = field(header, 6)? as usize;
+            let name_size = field(header, 11)? as usize;
+
+            let name_start = offset + HEADER_LEN;
+            let name = archive.get(name_start..name_start + name_size).ok_or(InitrdError::Truncated)?;
+            let name = match name.split_last() {
+                Some((0, name)) => core::str::from_utf8(name).map_err(|_| InitrdError::BadName)?,
+                _ => return Err(InitrdError::BadName),
+            };
+            let data_start = (name_start + name_size).next_multiple_of(4);
+            let data = archive.get(data_start..data_start + file_size).ok_or(InitrdError::Truncated)?;
+            offset = (data_start + file_size).next_multiple_of(4);
+
+            if name == TRAILER {
+                return Ok(Self { entries });
+            }
+            let path = normalize(name).ok_or(InitrdError::BadName)?;
+            entries.insert(path, Entry { mode, data });
+        }
+    }
+
+    /// エントリの数
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// `path` のエントリを探す（"/" 始まりの絶対パス。"//" や "." は無視する）
+    pub fn lookup(&self, path: &str) -> Option<&Entry<'a>> {
+        if !path.starts_with('/') {
+            return None;
+        }
+        self.entries.get(&normalize(path)?)
+    }
+
+    /// `path` が普通のファイルなら、その中身を返す
+    pub fn read(&self, path: &str) -> Option<&'a [u8]> {
+        self.lookup(path).filter(|entry| entry.is_file()).map(|entry| entry.data)
+    }
+
+    /// ディレクトリ `dir` の直下にあるエントリを、名前の順に返す
+    pub fn read_dir<'s>(&'s self, dir: &str) -> impl Iterator<Item = (&'s str, &'s Entry<'a>)> + 's {
+        let mut prefix = normalize(dir).unwrap_or_default();
+        if prefix != "/" {
+            prefix.push('/');
+        }
+        self.entries
+            .range::<str, _>((Bound::Included(prefix.as_str()), Bound::Unbounded))
+            .map_while(move |(path, entry)| Some((path.strip_prefix(prefix.as_str())?, entry)))
+            .filter(|(name, _)| !name.is_empty() && !name.contains('/'))
+    }
+}
+
+/// ヘッダの `index` 番目（0が ino）の数値（16進数8桁）
+fn field(header: &[u8], index: usize) -> Result<u32, InitrdError> {
+    let start = NEWC_MAGIC.len() + index * 8;
+    let digits = core::str::from_utf8(&header[start..start + 8]).map_err(|_| InitrdError::BadHeader)?;
+    u32::from_str_radix(digits, 16).map_err(|_| InitrdError::BadHeader)
+}
+
+/// アーカイブの名前やパスを "/" 始まりの形にそろえる（".." を含むならNone）
+///
+/// "sbin/init"、"./sbin/init"、"/sbin//init" はどれも "/sbin/init" になり、"." は "/" になる。
+fn normalize(name: &str) -> Option<String> {
+    let mut path = String::new();
+    for component in name.split('/') {
+        match component {
+            "" | "." => {}
+            ".." => return None,
+            component => {
+                path.push('/');
+                path.push_str(component);
+            }
+        }
+    }
+    if path.is_empty() {
+        path.push('/');
+    }
+    Some(path)
+}
+
+/// 起動時に読み込んだinitrd（なければNone）
+static INITRD: SpinLock<Option<Initrd<'static>>> = SpinLock::new(None);
+
+/// ブートローダーから渡された最初のモジュールをinitrdとして解析する（エントリの数を返す）
+///
+/// モジュールがなければ Ok(None)。モジュールの物理メモリはフレームアロケータが使わないので（memory::init）、
+/// 物理メモリマップ経由でそのまま参照し続けてよい。
+#[cfg(any(not(test), target_os = "none"))]
+pub fn init(boot_info: &BootInfo) -> Result<Option<usize>, InitrdError> {
+    let Some(module) = boot_info.modules().first() else {
+        return Ok(None);
+    };
+    // 安全性: モジュールはブートローダーが読み込んだ範囲で、上書きされない
+    let archive = unsafe {
+        core::slice::from_raw_parts(boot_info.phys_to_virt(module.start) as *const u8, module.len() as usize)
+    };
+    let initrd = Initrd::parse(archive)?;
+    let len = initrd.len();
+    *INITRD.lock() = Some(initrd);
+    Ok(Some(len))
+}
+
+/// initrdのファイル `path` の中身（initrdがないか、普通のファイルでなければNone）
+pub fn read(path: &str) -> Option<&'static [u8]> {
+    INITRD.lock().as_ref()?.read(path)
+}
+
+/// `command`（`init=` の値。最初の語がパスで、残りは引数）をinitrdから読み、最初のユーザープロセスとして作る
+///
+/// 作ったプロセスは実行可能キューに入れるだけで、動かすのはディスパッチャ（dispatch.rs）。スロット番号を返す。
+/// MINIX 3: PMが起動時に init を fork して /sbin/init を exec する（servers/pm/main.c）
+#[cfg(any(not(test), target_os = "none"))]
+pub fn start_init(command: &str) -> Option<usize> {
+    let argv: Vec<&str> = command.split_whitespace().collect();
+    let path = *argv.first()?;
+    let Some(image) = read(path) else {
+        log::warn!("init: {} not found in initrd", path);
+        return None;
+    };
+    let name = path.rsplit('/').next().unwrap_or(path);
+    let slot = match user::spawn_elf(INIT_PID, name, image, &argv, &[]) {
+        Ok(slot) => slot,
+        Err(error) => {
+            log::warn!("init: {}: {}", path, error);
+            return None;
+        }
+    };
+    let priority = PROCESS_TABLE.get_mut(slot)?.priority.value();
+    SCHEDULER.lock().enqueue(slot, priority);
+    log::info!("init: started {} (pid {})", path, INIT_PID);
+    Some(slot)
+}
+
+// ===== テスト =====
+#[cfg(all(test, not(target_os = "none")))]
+mod tests {
+    use super::*;
+
+    /// newc 形式のエントリを1つ書く（tools の cpio.rs と同じ形）
+    fn push_entry(archive: &mut Vec<u8>, name: &str, mode: u32, data: &[u8]) {
+        let fields = [0, mode, 0, 0, 1, 0, data.len() as u32, 0, 0, 0, 0, name.len() as u32 + 1, 0];
+        archive.extend_from_slice(NEWC_MAGIC);
+        for value in fields {
+            archive.extend_from_slice(format!("{:08X}", value).as_bytes());
+        }
+        archive.extend_from_slice(name.as_bytes());
+        archive.push(0);
+        archive.resize(archive.len().next_multiple_of(4), 0);
+        archive.extend_from_slice(data);
+        archive.resize(archive.len().next_multiple_of(4), 0);
+    }
+
+    fn sample() -> Vec<u8> {
+        let mut archive = Vec::new();
+        push_entry(&mut archive, ".", S_IFDIR | 0o755, &[]);
+        push_entry(&mut archive, "sbin", S_IFDIR | 0o755, &[]);
+        push_entry(&mut archive, "sbin/init", S_IFREG | 0o755, b"\x7fELF...");
+        push_entry(&mut archive, "./etc", S_IFDIR | 0o755, &[]);
+        push_entry(&mut archive, "etc/motd", S_IFREG | 0o644, b"hello\n");
+        push_entry(&mut archive, "etc/rc.d", S_IFDIR | 0o755, &[]);
+        push_entry(&mut archive, "etc/rc.d/net", S_IFREG | 0o644, b"");
+        push_entry(&mut archive, TRAILER, 0, &[]);
+        archive
+    }
+
+    #[test]
+    fn test_parse_and_lookup() {
+        let archive = sample();
+        let initrd = Initrd::parse(&archive).unwrap();
+        assert_eq!(initrd.len(), 7);
+        assert_eq!(initrd.read("/sbin/init"), Some(&b"\x7fELF..."[..]));
+        assert_eq!(initrd.read("/etc/motd"), Some(&b"hello\n"[..]), "\"./etc\" の下でも同じパスになる");
+        assert_eq!(initrd.read("//etc/./motd"), Some(&b"hello\n"[..]), "\"//\" と \".\" は無視");
+        assert_eq!(initrd.read("/etc/rc.d/net"), Some(&b""[..]), "空のファイル");
+        assert!(initrd.lookup("/").unwrap().is_dir(), "\".\" はルート");
+        assert!(initrd.lookup("/sbin").unwrap().is_dir());
+        assert_eq!(initrd.read("/sbin"), None, "ディレクトリは読めない");
+        assert_eq!(initrd.read("sbin/init"), None, "相対パスは探さない");
+        assert_eq!(initrd.read("/etc/../sbin/init"), None, "\"..\" は探さない");
+        assert_eq!(initrd.read("/bin/sh"), None);
+    }
+
+    #[test]
+    fn test_read_dir() {
+        let archive = sample();
+        let initrd = Initrd::parse(&archive).unwrap();
+        let names = |dir| initrd.read_dir(dir).map(|(name, _)| name).collect::<Vec<_>>();
+        assert_eq!(names("/"), ["etc", "sbin"], "直下だけを名前の順に");
+        assert_eq!(names("/etc"), ["motd", "rc.d"]);
+        assert_eq!(names("/etc/"), ["motd", "rc.d"]);
+        assert_eq!(names("/sbin/init"), Vec::<&str>::new());
+        assert!(initrd.read_dir("/etc").any(|(name, entry)| name == "rc.d" && entry.is_dir()));
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let archive = sample();
+        assert_eq!(Initrd::parse(&archive[..archive.len() - 4]).unwrap_err(), InitrdError::Truncated, "TRAILER!!! がない");
+        assert_eq!(Initrd::parse(&[]).unwrap_err(), InitrdError::Truncated);
+
+        let mut odc = archive.clone();
+        odc[..6].copy_from_slice(b"070707");
+        assert_eq!(Initrd::parse(&odc).unwrap_err(), InitrdError::BadMagic, "旧形式（odc）は読まない");
+
+        let mut bad = archive.clone();
+        bad[6 + 8] = b'g';
+        assert_eq!(Initrd::parse(&bad).unwrap_err(), InitrdError::BadHeader);
+
+        let mut escape = Vec::new();
+        push_entry(&mut escape, "../etc/passwd", S_IFREG | 0o644, b"root");
+        push_entry(&mut escape, TRAILER, 0, &[]);
+        assert_eq!(Initrd::parse(&escape).unwrap_err(), InitrdError::BadName, "外に出る名前は拒否");
+
+        let mut huge = Vec::new();
+        push_entry(&mut huge, "big", S_IFREG | 0o644, b"abcd");
+        huge[6 + 6 * 8..6 + 7 * 8].copy_from_slice(b"00010000");
+        assert_eq!(Initrd::parse(&huge).unwrap_err(), InitrdError::Truncated, "filesize がアーカイブを超える");
+    }
+
+    #[test]
+    fn test_later_entry_wins() {
+        let mut archive = Vec::new();
+        push_entry(&mut archive, "motd", S_IFREG | 0o644, b"old");
+        push_entry(&mut archive, "./motd", S_IFREG | 0o644, b"new");
+        push_entry(&mut archive, TRAILER, 0, &[]);
+        let initrd = Initrd::parse(&archive).unwrap();
+        assert_eq!(initrd.len(), 1);
+        assert_eq!(initrd.read("/motd"), Some(&b"new"[..]));
+    }
+
+    #[test]
+    fn test_normalize() {
+        assert_eq!(normalize(".").as_deref(), Some("/"));
+        assert_eq!(normalize("").as_deref(), Some("/"));
+        assert_eq!(normalize("./sbin/init").as_deref(), Some("/sbin/init"));
+        assert_eq!(normalize("/sbin//init/").as_deref(), Some("/sbin/init"));
+        assert_eq!(normalize("a/../b"), None);
+    }
+}
+
+// ===== カーネル内テスト（QEMU） =====
+#[cfg(all(test, target_os = "none"))]
+mod kernel_tests {
+    use super::*;
+    use crate::process::ProcessFlags;
+    use crate::user::Exit;
+
+    /// start_init() でキューに入れたinitを、ディスパッチャの代わりにここで動かして片付ける
+    fn run_init(command: &str) -> Option<Exit> {
+        let slot = start_init(command)?;
+        let process = PROCESS_TABLE.get_mut(slot)?;
+        assert!(SCHEDULER.lock().dequeue(slot, process.priority.value()), "キューに入っている");
+        let exit = user::run(slot);
+        unsafe { user::release(process) };
+        process.flags.set(ProcessFlags::SLOT_FREE);
+        exit
+    }
+
+    /// ktest は kernel/testdata/initrd をアーカイブにして -initrd で渡す（.cargo/config.toml）
+    #[test_case]
+    fn test_start_init_from_initrd() {
+        let image = read("/sbin/init").expect("ktest が -initrd を渡している");
+        assert_eq!(&image[..4], b"\x7fELF");
+        assert_eq!(run_init("/sbin/init"), Some(Exit::Status(101)), "argc 1 + .data の 100");
+        assert_eq!(run_init("/sbin/init one two"), Some(Exit::Status(103)), "残りの語は引数");
+        assert_eq!(start_init("/sbin/missing"), None);
+        assert_eq!(start_init(""), None);
+    }
+}
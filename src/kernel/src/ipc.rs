@@ -0,0 +1,599 @@
+//! プロセス間通信（IPC）
+//!
+//! MINIX 3ではプロセス同士（サーバー、ドライバ、ユーザープロセス）が固定長のメッセージを
+//! 送り合って仕事をする。メッセージの形式とトラップ番号はユーザー側と共有する libipc クレートにある。
+//!
+//! 送受信はランデブー方式で、相手の準備ができていなければ呼び出したプロセスが止まる
+//! （SENDING / RECEIVING）。送信を待つプロセスは、相手の `caller_q` から `q_link` でつないだ列に並ぶ。
+//! 止まったプロセスは実行可能キュー（process::Scheduler）から外し、待ちが解けたらキューの最後に戻すので、
+//! ディスパッチャ（dispatch.rs）がもう一度選んで続きから動かす。
+//! 届いたメッセージはいったん `deliver_message` に置き、そのプロセスがユーザーモードに戻るときに
+//! ユーザーのメモリに書く（止まっているプロセスのアドレス空間は有効になっていないため）。
+//!
+//! MINIX 3: kernel/proc.c の do_ipc()、mini_send()、mini_receive()、mini_notify()
+//!          include/minix/ipc.h の message（64バイト）
+
+use crate::arch::MapFlags;
+use crate::memory::slab::{KernelPages, ObjectCache, SlabPages};
+use crate::process::{self, ProcessFlags, ProcessId, ProcessTable, Scheduler, ANY};
+use crate::system;
+
+pub use libipc::{Error, Message, MESSAGE_LEN};
+use libipc::{NOTIFY, NOTIFY_MESSAGE, RECEIVE, SEND, SENDREC};
+
+/// 通知を送れるプロセスの数（スロット番号がこれより小さいプロセスだけが notify できる）
+/// MINIX 3: 通知を送れるのはシステムプロセス（NR_SYS_PROCS 個、priv構造体を持つもの）だけ
+pub const NR_NOTIFY_SOURCES: usize = u64::BITS as usize;
+
+/// 送信待ちのプロセスが送るメッセージを置くキャッシュ（受信側に渡したら返す）
+pub static MESSAGE_CACHE: ObjectCache<Message, KernelPages> = ObjectCache::new("message");
+
+/// 相手のエンドポイントのスロット番号（ANY と自分自身は不正、いなければ終了している）
+fn endpoint_slot<P: SlabPages + 'static>(table: &ProcessTable<P>, caller: usize, endpoint: ProcessId) -> Result<usize, Error> {
+    if endpoint == ANY {
+        return Err(Error::BadEndpoint);
+    }
+    match table.find(endpoint) {
+        Some(slot) if slot == caller => Err(Error::BadEndpoint),
+        Some(slot) => Ok(slot),
+        None => Err(Error::DeadEndpoint),
+    }
+}
+
+/// `receiver` が `sender` からのメッセージを今すぐ受け取れるか
+fn is_waiting_for(receiver: &process::Process, sender: ProcessId) -> bool {
+    receiver.flags.is_set(ProcessFlags::RECEIVING)
+        && !receiver.flags.is_set(ProcessFlags::SENDING)
+        && matches!(receiver.receive_from, Some(from) if from == ANY || from == sender)
+}
+
+/// 止まったプロセス `slot` を実行可能キューから外す
+/// MINIX 3: RTS_SET() は実行可能でなくなったプロセスを dequeue() する
+fn block(scheduler: &mut Scheduler, slot: usize, process: &process::Process) {
+    scheduler.dequeue(slot, process.priority.value());
+}
+
+/// 待ちが解けたプロセス `slot` が実行可能になったら、実行可能キューに戻す
+/// MINIX 3: RTS_UNSET() は実行可能になったプロセスを enqueue() する
+fn unblock(scheduler: &mut Scheduler, slot: usize, process: &process::Process) {
+    if process.is_runnable() {
+        scheduler.enqueue(slot, process.priority.value());
+    }
+}
+
+/// メッセージを渡して、受信待ちを解く（実行可能になればキューに戻す）
+fn deliver(scheduler: &mut Scheduler, slot: usize, receiver: &mut process::Process, message: &Message) {
+    receiver.deliver_message = *message;
+    receiver.delivery_pending = true;
+    receiver.flags.clear(ProcessFlags::RECEIVING);
+    receiver.receive_from = None;
+    unblock(scheduler, slot, receiver);
+}
+
+/// 通知のメッセージ
+fn notification(source: ProcessId) -> Message {
+    let mut message = Message::new(NOTIFY_MESSAGE);
+    message.source = source;
+    message
+}
+
+/// `caller` が `target` を待つと、待ちが一周して `caller` に戻ってくるか
+///
+/// 止まっているプロセスが待っている相手（SENDING なら送り先、RECEIVING なら受信元）を順にたどる。
+/// MINIX 3: proc.c の deadlock()
+fn deadlock<P: SlabPages + 'static>(table: &ProcessTable<P>, caller: usize, target: usize) -> bool {
+    let Some(caller_pid) = table.get_mut(caller).map(|process| process.pid) else {
+        return false;
+    };
+    let mut next = target;
+    for _ in 0..table.slot_count() {
+        let Some(process) = table.get_mut(next) else {
+            return false;
+        };
+        let waiting_for = if process.flags.is_set(ProcessFlags::SENDING) {
+            process.send_to
+        } else if process.flags.is_set(ProcessFlags::RECEIVING) {
+            process.receive_from
+        } else {
+            None
+        };
+        match waiting_for {
+            Some(pid) if pid == caller_pid => return true,
+            Some(pid) if pid != ANY => match table.find(pid) {
+                Some(slot) => next = slot,
+                None => return false,
+            },
+            _ => return false,
+        }
+    }
+    false
+}
+
+/// `receiver` の待ち行列の最後に `caller` を並べる
+///
+/// 待ち行列をたどるときは1つずつ `get_mut()` し、同じスロットの可変参照を2つ持たないようにする。
+fn enqueue_caller<P: SlabPages + 'static>(table: &ProcessTable<P>, receiver: usize, caller: usize) {
+    let Some(mut last) = table.get_mut(receiver).and_then(|process| process.caller_q) else {
+        if let Some(process) = table.get_mut(receiver) {
+            process.caller_q = Some(caller);
+        }
+        return;
+    };
+    while let Some(next) = table.get_mut(last).and_then(|process| process.q_link) {
+        last = next;
+    }
+    if let Some(process) = table.get_mut(last) {
+        process.q_link = Some(caller);
+    }
+}
+
+/// `receiver` の待ち行列から、`matches(スロット番号, プロセス)` を満たす最初のプロセスを外してスロット番号を返す
+fn dequeue_caller<P: SlabPages + 'static>(
+    table: &ProcessTable<P>,
+    receiver: usize,
+    matches: impl Fn(usize, &process::Process) -> bool,
+) -> Option<usize> {
+    let mut prev = None;
+    let mut next = table.get_mut(receiver)?.caller_q;
+    while let Some(slot) = next {
+        let queued = table.get_mut(slot)?;
+        if matches(slot, queued) {
+            let after = queued.q_link.take();
+            match prev {
+                None => table.get_mut(receiver)?.caller_q = after,
+                Some(prev) => table.get_mut(prev)?.q_link = after,
+            }
+            return Some(slot);
+        }
+        prev = Some(slot);
+        next = queued.q_link;
+    }
+    None
+}
+
+/// `caller` から `dest` にメッセージを送る
+///
+/// `dest` が `caller`（か ANY）から受信待ちならすぐに渡す。そうでなければ `caller` は SENDING で止まり、
+/// `dest` の待ち行列の最後に並ぶ（実行可能キューからは外れる）。
+/// MINIX 3: proc.c の mini_send()
+pub fn send<P: SlabPages + 'static>(
+    table: &ProcessTable<P>,
+    scheduler: &mut Scheduler,
+    caller: usize,
+    dest: ProcessId,
+    message: &Message,
+) -> Result<(), Error> {
+    let dest_slot = endpoint_slot(table, caller, dest)?;
+    let mut message = *message;
+    message.source = table.get_mut(caller).ok_or(Error::BadEndpoint)?.pid;
+    let receiver = table.get_mut(dest_slot).ok_or(Error::DeadEndpoint)?;
+    if is_waiting_for(receiver, message.source) {
+        deliver(scheduler, dest_slot, receiver, &message);
+        return Ok(());
+    }
+    if deadlock(table, caller, dest_slot) {
+        return Err(Error::Locked);
+    }
+    let queued = MESSAGE_CACHE.alloc(message).ok_or(Error::Other(system::ENOMEM))?;
+    let sender = table.get_mut(caller).ok_or(Error::BadEndpoint)?;
+    sender.send_message = Some(queued);
+    sender.flags.set(ProcessFlags::SENDING);
+    sender.send_to = Some(dest);
+    sender.q_link = None;
+    block(scheduler, caller, sender);
+    enqueue_caller(table, dest_slot, caller);
+    Ok(())
+}
+
+/// `caller` が `source`（ANY なら誰でも）からのメッセージを受信する
+///
+/// 保留中の通知、送信を待っているプロセスの順に探し、あればすぐに受け取る（`deliver_message` に入る）。
+/// なければ `caller` は RECEIVING で止まり（実行可能キューからは外れる）、届いたメッセージは `buffer` に書く。
+/// 受け取った相手の送信待ちが解ければ、その相手はキューに戻る。
+/// MINIX 3: proc.c の mini_receive()
+pub fn receive<P: SlabPages + 'static>(
+    table: &ProcessTable<P>,
+    scheduler: &mut Scheduler,
+    caller: usize,
+    source: ProcessId,
+    buffer: u64,
+) -> Result<(), Error> {
+    let source_slot = match source {
+        ANY => None,
+        source => Some(endpoint_slot(table, caller, source)?),
+    };
+    let receiver = table.get_mut(caller).ok_or(Error::BadEndpoint)?;
+    receiver.receive_buffer = buffer;
+
+    let mask = match source_slot {
+        None => u64::MAX,
+        Some(slot) if slot < NR_NOTIFY_SOURCES => 1 << slot,
+        Some(_) => 0,
+    };
+    let pending = receiver.notify_pending & mask;
+    if pending != 0 {
+        let slot = pending.trailing_zeros() as usize;
+        let notifier = table.get_mut(slot).map_or(0, |process| process.pid);
+        let receiver = table.get_mut(caller).ok_or(Error::BadEndpoint)?;
+        receiver.notify_pending &= !(1 << slot);
+        deliver(scheduler, caller, receiver, &notification(notifier));
+        return Ok(());
+    }
+
+    if let Some(slot) = dequeue_caller(table, caller, |_, sender| source == ANY || sender.pid == source) {
+        let sender = table.get_mut(slot).ok_or(Error::DeadEndpoint)?;
+        sender.flags.clear(ProcessFlags::SENDING);
+        sender.send_to = None;
+        let message = sender.send_message.take().ok_or(Error::DeadEndpoint)?;
+        // SENDREC の送り手は、返事を受け取るまで RECEIVING のまま
+        unblock(scheduler, slot, sender);
+        deliver(scheduler, caller, table.get_mut(caller).ok_or(Error::BadEndpoint)?, &message);
+        return Ok(());
+    }
+
+    if source_slot.is_some_and(|slot| deadlock(table, caller, slot)) {
+        return Err(Error::Locked);
+    }
+    let receiver = table.get_mut(caller).ok_or(Error::BadEndpoint)?;
+    receiver.flags.set(ProcessFlags::RECEIVING);
+    receiver.receive_from = Some(source);
+    block(scheduler, caller, receiver);
+    Ok(())
+}
+
+/// 送ってから、同じ相手からの返事を受信する（サーバーへの要求に使う）
+///
+/// 送信で止まったときは受信待ちも同時に立てておき、相手が受け取った後も返事が来るまで止まったままにする。
+/// MINIX 3: do_sync_ipc() の SENDREC（MF_REPLY_PEND）
+pub fn sendrec<P: SlabPages + 'static>(
+    table: &ProcessTable<P>,
+    scheduler: &mut Scheduler,
+    caller: usize,
+    endpoint: ProcessId,
+    message: &Message,
+    buffer: u64,
+) -> Result<(), Error> {
+    send(table, scheduler, caller, endpoint, message)?;
+    let process = table.get_mut(caller).ok_or(Error::BadEndpoint)?;
+    if !process.flags.is_set(ProcessFlags::SENDING) {
+        return receive(table, scheduler, caller, endpoint, buffer);
+    }
+    process.receive_buffer = buffer;
+    process.flags.set(ProcessFlags::RECEIVING);
+    process.receive_from = Some(endpoint);
+    Ok(())
+}
+
+/// `caller` から `dest` に通知する（待たない）
+///
+/// `dest` が受信待ちなら NOTIFY_MESSAGE を渡し、そうでなければ保留にしておく（同じ相手からの通知は1つにまとまる）。
+/// MINIX 3: proc.c の mini_notify()
+pub fn notify<P: SlabPages + 'static>(
+    table: &ProcessTable<P>,
+    scheduler: &mut Scheduler,
+    caller: usize,
+    dest: ProcessId,
+) -> Result<(), Error> {
+    let dest_slot = endpoint_slot(table, caller, dest)?;
+    if caller >= NR_NOTIFY_SOURCES {
+        return Err(Error::CallDenied);
+    }
+    let source = table.get_mut(caller).ok_or(Error::BadEndpoint)?.pid;
+    let receiver = table.get_mut(dest_slot).ok_or(Error::DeadEndpoint)?;
+    if is_waiting_for(receiver, source) {
+        deliver(scheduler, dest_slot, receiver, &notification(source));
+    } else {
+        receiver.notify_pending |= 1 << caller;
+    }
+    Ok(())
+}
+
+/// 終了するプロセス `slot` をIPCの待ちから外す（スロットを空きにする前に呼ぶ）
+///
+/// 自分が並んでいる待ち行列から抜け、自分を相手に止まっていたプロセスは EDEADSRCDST で起こす
+/// （実行可能キューに戻す）。`slot` 自身をキューから外すのは呼び出し側。
+/// MINIX 3: system/do_exit.c の clear_endpoint()
+pub fn cancel<P: SlabPages + 'static>(table: &ProcessTable<P>, scheduler: &mut Scheduler, slot: usize) {
+    let Some(process) = table.get_mut(slot) else {
+        return;
+    };
+    let pid = process.pid;
+    let dest = process.send_to.filter(|_| process.flags.is_set(ProcessFlags::SENDING));
+    if let Some(dest) = dest.and_then(|dest| table.find(dest)) {
+        dequeue_caller(table, dest, |queued, _| queued == slot);
+    }
+    let Some(process) = table.get_mut(slot) else {
+        return;
+    };
+    process.flags.clear(ProcessFlags::SENDING | ProcessFlags::RECEIVING);
+    process.send_to = None;
+    process.send_message = None;
+    process.receive_from = None;
+    process.caller_q = None;
+    process.q_link = None;
+
+    for other_slot in 0..table.slot_count() {
+        let Some(other) = table.get_mut(other_slot).filter(|_| other_slot != slot) else {
+            continue;
+        };
+        let sending = other.flags.is_set(ProcessFlags::SENDING) && other.send_to == Some(pid);
+        let receiving = other.flags.is_set(ProcessFlags::RECEIVING) && other.receive_from == Some(pid);
+        if sending || receiving {
+            other.flags.clear(ProcessFlags::SENDING | ProcessFlags::RECEIVING);
+            other.send_to = None;
+            other.receive_from = None;
+            other.q_link = None;
+            // 止まっていたトラップの戻り値
+            other.registers.rax = Error::DeadEndpoint.code() as u64;
+            unblock(scheduler, other_slot, other);
+        }
+        if slot < NR_NOTIFY_SOURCES {
+            other.notify_pending &= !(1 << slot);
+        }
+    }
+}
+
+/// IPCトラップ（`int 0x21` と `syscall` の共通の入口）
+///
+/// 実行中のプロセスを呼び出し元として、ユーザーのアドレス `addr` のメッセージを読む（受信なら書ける領域か確かめる）。
+/// 呼び出し元が止まったときも0を返す（エラーで起こされたときは cancel() が戻り値を書き換える）。
+/// MINIX 3: proc.c の do_ipc()
+pub fn do_ipc(call: u64, endpoint: ProcessId, addr: u64) -> i64 {
+    let Some(caller) = process::current_slot() else {
+        return system::ESRCH;
+    };
+    let table = &process::PROCESS_TABLE;
+    let scheduler = &mut *process::SCHEDULER.lock();
+    let result = match call {
+        SEND => read_message(addr).and_then(|message| send(table, scheduler, caller, endpoint, &message)),
+        RECEIVE => check_buffer(addr).and_then(|()| receive(table, scheduler, caller, endpoint, addr)),
+        SENDREC => check_buffer(addr)
+            .and_then(|()| read_message(addr))
+            .and_then(|message| sendrec(table, scheduler, caller, endpoint, &message, addr)),
+        NOTIFY => notify(table, scheduler, caller, endpoint),
+        _ => Err(Error::BadCall),
+    };
+    match result {
+        Ok(()) => 0,
+        Err(error) => error.code(),
+    }
+}
+
+/// ユーザーのメモリからメッセージを読む
+fn read_message(addr: u64) -> Result<Message, Error> {
+    let mut bytes = [0; MESSAGE_LEN];
+    system::copy_from_user(addr, &mut bytes).map_err(|_| Error::Fault)?;
+    Ok(Message::from_bytes(&bytes))
+}
+
+/// 受信したメッセージを書ける場所か確かめる
+fn check_buffer(addr: u64) -> Result<(), Error> {
+    system::check_user(addr, MESSAGE_LEN as u64, MapFlags::WRITABLE).map_err(|_| Error::Fault)
+}
+
+// ===== テスト =====
+#[cfg(all(test, not(target_os = "none")))]
+mod tests {
+    use super::*;
+    use crate::process::Priority;
+
+    /// スロット i にプロセス番号 pids[i] のプロセスを置いたテーブルと、それを全部並べた実行可能キュー
+    fn table(pids: &[ProcessId]) -> (ProcessTable, Scheduler) {
+        let table = ProcessTable::new();
+        let mut scheduler = Scheduler::new();
+        for (slot, &pid) in pids.iter().enumerate() {
+            *table.get_mut(slot).unwrap() = process::Process::new(pid);
+            scheduler.enqueue(slot, Priority::USER_Q);
+        }
+        (table, scheduler)
+    }
+
+    /// 実行可能キューに並んでいるスロット番号
+    fn ready(scheduler: &Scheduler) -> Vec<usize> {
+        scheduler.queue(Priority::USER_Q.into()).collect()
+    }
+
+    fn flags(table: &ProcessTable, slot: usize) -> String {
+        table.get_mut(slot).unwrap().flags.to_string()
+    }
+
+    /// 届いたメッセージを受け取る（ユーザーのメモリに書く代わり）
+    fn take_delivery(table: &ProcessTable, slot: usize) -> Option<Message> {
+        let process = table.get_mut(slot).unwrap();
+        core::mem::take(&mut process.delivery_pending).then_some(process.deliver_message)
+    }
+
+    #[test]
+    fn test_message_size() {
+        assert_eq!(core::mem::size_of::<Message>(), 64, "MINIXと同じ64バイト");
+        assert_eq!(MESSAGE_CACHE.stats().object_size, 64);
+    }
+
+    #[test]
+    fn test_send_to_waiting_receiver() {
+        let (table, mut scheduler) = table(&[10, 11]);
+        receive(&table, &mut scheduler, 1, ANY, 0x7000).unwrap();
+        assert_eq!(flags(&table, 1), "RECEIVING");
+
+        send(&table, &mut scheduler, 0, 11, &Message::new(42)).unwrap();
+        assert_eq!(flags(&table, 0), "-", "受信待ちの相手にはすぐ渡せる");
+        assert_eq!(flags(&table, 1), "-", "受信待ちが解ける");
+        let message = take_delivery(&table, 1).unwrap();
+        assert_eq!((message.source, message.m_type), (10, 42), "送信元はカーネルが書く");
+        assert_eq!(table.get_mut(1).unwrap().receive_buffer, 0x7000);
+    }
+
+    #[test]
+    fn test_senders_queue_in_order() {
+        let (table, mut scheduler) = table(&[10, 11, 12, 13]);
+        send(&table, &mut scheduler, 1, 10, &Message::new(1)).unwrap();
+        send(&table, &mut scheduler, 2, 10, &Message::new(2)).unwrap();
+        send(&table, &mut scheduler, 3, 10, &Message::new(3)).unwrap();
+        assert_eq!(flags(&table, 1), "SENDING", "相手が受信していなければ止まる");
+        assert_eq!(table.get_mut(1).unwrap().send_to, Some(10));
+        assert_eq!(table.get_mut(1).unwrap().send_message.as_deref(), Some(&Message { source: 11, ..Message::new(1) }), "メッセージは MESSAGE_CACHE に置く");
+
+        receive(&table, &mut scheduler, 0, 12, 0).unwrap();
+        assert_eq!(take_delivery(&table, 0).unwrap().m_type, 2, "指定した相手のものを受け取る");
+        receive(&table, &mut scheduler, 0, ANY, 0).unwrap();
+        assert_eq!(take_delivery(&table, 0).unwrap().m_type, 1, "ANY なら並んだ順");
+        receive(&table, &mut scheduler, 0, ANY, 0).unwrap();
+        assert_eq!(take_delivery(&table, 0).unwrap().m_type, 3);
+        assert_eq!([flags(&table, 1), flags(&table, 2), flags(&table, 3)], ["-", "-", "-"]);
+        assert!((1..4).all(|slot| table.get_mut(slot).unwrap().send_message.is_none()), "渡したらキャッシュに返す");
+
+        receive(&table, &mut scheduler, 0, ANY, 0).unwrap();
+        assert_eq!(take_delivery(&table, 0), None);
+        assert_eq!(flags(&table, 0), "RECEIVING", "誰も送っていなければ止まる");
+        assert_eq!(table.get_mut(0).unwrap().caller_q, None);
+    }
+
+    #[test]
+    fn test_blocking_leaves_ready_queue() {
+        let (table, mut scheduler) = table(&[10, 11, 12]);
+        receive(&table, &mut scheduler, 1, ANY, 0).unwrap();
+        assert_eq!(ready(&scheduler), [0, 2], "受信待ちになったらキューから外れる");
+        send(&table, &mut scheduler, 0, 11, &Message::new(1)).unwrap();
+        assert_eq!(ready(&scheduler), [0, 2, 1], "メッセージが届いたら最後に戻る");
+
+        // 忙しいサーバー（11）への SENDREC は、受け取られても返事が来るまで戻らない
+        sendrec(&table, &mut scheduler, 2, 11, &Message::new(2), 0).unwrap();
+        assert_eq!(ready(&scheduler), [0, 1]);
+        receive(&table, &mut scheduler, 1, ANY, 0).unwrap();
+        assert_eq!(take_delivery(&table, 1).unwrap().m_type, 2);
+        assert_eq!(ready(&scheduler), [0, 1], "送信待ちが解けても返事を待つ");
+        send(&table, &mut scheduler, 1, 12, &Message::new(3)).unwrap();
+        assert_eq!(ready(&scheduler), [0, 1, 2], "返事が届いたら戻る");
+
+        // 送った相手が受け取るまで送信側は外れる
+        send(&table, &mut scheduler, 0, 12, &Message::new(4)).unwrap();
+        assert_eq!(ready(&scheduler), [1, 2]);
+        receive(&table, &mut scheduler, 2, ANY, 0).unwrap();
+        assert_eq!(ready(&scheduler), [1, 2, 0], "受け取られたら戻る");
+    }
+
+    #[test]
+    fn test_receive_from_other_source_waits() {
+        let (table, mut scheduler) = table(&[10, 11, 12]);
+        receive(&table, &mut scheduler, 0, 12, 0).unwrap();
+        send(&table, &mut scheduler, 1, 10, &Message::new(5)).unwrap();
+        assert_eq!(flags(&table, 1), "SENDING", "別の相手を待っているので渡せない");
+        assert_eq!(table.get_mut(0).unwrap().caller_q, Some(1));
+        send(&table, &mut scheduler, 2, 10, &Message::new(6)).unwrap();
+        assert_eq!(take_delivery(&table, 0).unwrap().m_type, 6);
+    }
+
+    #[test]
+    fn test_sendrec() {
+        let (table, mut scheduler) = table(&[10, 11]);
+        // サーバー（11）が受信待ちなら、送った後は返事を待つ
+        receive(&table, &mut scheduler, 1, ANY, 0).unwrap();
+        sendrec(&table, &mut scheduler, 0, 11, &Message::new(7), 0x8000).unwrap();
+        assert_eq!(flags(&table, 0), "RECEIVING");
+        assert_eq!(take_delivery(&table, 1).unwrap().m_type, 7);
+        send(&table, &mut scheduler, 1, 10, &Message::new(8)).unwrap();
+        assert_eq!(take_delivery(&table, 0).unwrap().m_type, 8, "返事が届く");
+        assert_eq!(flags(&table, 0), "-");
+
+        // サーバーが忙しければ送信と受信の両方で待つ
+        sendrec(&table, &mut scheduler, 0, 11, &Message::new(9), 0x8000).unwrap();
+        assert_eq!(flags(&table, 0), "SENDING|RECEIVING");
+        receive(&table, &mut scheduler, 1, ANY, 0).unwrap();
+        assert_eq!(take_delivery(&table, 1).unwrap().m_type, 9);
+        assert_eq!(flags(&table, 0), "RECEIVING", "受け取られても返事を待つ");
+        assert_eq!(send(&table, &mut scheduler, 1, 10, &Message::new(10)), Ok(()));
+        assert_eq!(take_delivery(&table, 0).unwrap().m_type, 10);
+    }
+
+    #[test]
+    fn test_notify() {
+        let (table, mut scheduler) = table(&[10, 11, 12]);
+        notify(&table, &mut scheduler, 1, 10).unwrap();
+        notify(&table, &mut scheduler, 1, 10).unwrap();
+        notify(&table, &mut scheduler, 2, 10).unwrap();
+        assert_eq!(flags(&table, 1), "-", "通知では止まらない");
+
+        receive(&table, &mut scheduler, 0, 12, 0).unwrap();
+        let message = take_delivery(&table, 0).unwrap();
+        assert_eq!((message.source, message.m_type), (12, NOTIFY_MESSAGE));
+        receive(&table, &mut scheduler, 0, ANY, 0).unwrap();
+        assert_eq!(take_delivery(&table, 0).unwrap().source, 11);
+        receive(&table, &mut scheduler, 0, ANY, 0).unwrap();
+        assert_eq!(take_delivery(&table, 0), None, "同じ相手からの通知は1つにまとまる");
+
+        notify(&table, &mut scheduler, 2, 10).unwrap();
+        assert_eq!(take_delivery(&table, 0).unwrap().source, 12, "受信待ちならすぐ渡す");
+    }
+
+    #[test]
+    fn test_invalid_endpoints() {
+        let (table, mut scheduler) = table(&[10, 11]);
+        let message = Message::new(0);
+        assert_eq!(send(&table, &mut scheduler, 0, 10, &message), Err(Error::BadEndpoint), "自分自身");
+        assert_eq!(send(&table, &mut scheduler, 0, ANY, &message), Err(Error::BadEndpoint));
+        assert_eq!(send(&table, &mut scheduler, 0, 99, &message), Err(Error::DeadEndpoint), "いないプロセス");
+        assert_eq!(receive(&table, &mut scheduler, 0, 99, 0), Err(Error::DeadEndpoint));
+        assert_eq!(notify(&table, &mut scheduler, 0, 99), Err(Error::DeadEndpoint));
+        table.get_mut(1).unwrap().flags.set(ProcessFlags::SLOT_FREE);
+        assert_eq!(send(&table, &mut scheduler, 0, 11, &message), Err(Error::DeadEndpoint), "空きスロットは終了したプロセス");
+        assert_eq!(flags(&table, 0), "-", "エラーでは止まらない");
+    }
+
+    #[test]
+    fn test_deadlock() {
+        let (table, mut scheduler) = table(&[10, 11, 12]);
+        send(&table, &mut scheduler, 0, 11, &Message::new(0)).unwrap();
+        send(&table, &mut scheduler, 1, 12, &Message::new(0)).unwrap();
+        assert_eq!(send(&table, &mut scheduler, 2, 10, &Message::new(0)), Err(Error::Locked), "10 → 11 → 12 → 10");
+        assert_eq!(receive(&table, &mut scheduler, 2, 10, 0), Err(Error::Locked), "10 は 12 が受信するまで進めない");
+        assert_eq!(flags(&table, 2), "-");
+        assert!(receive(&table, &mut scheduler, 2, ANY, 0).is_ok(), "ANY なら 11 から受け取れる");
+    }
+
+    #[test]
+    fn test_cancel() {
+        let (table, mut scheduler) = table(&[10, 11, 12, 13]);
+        send(&table, &mut scheduler, 1, 10, &Message::new(1)).unwrap();
+        send(&table, &mut scheduler, 2, 10, &Message::new(2)).unwrap();
+        receive(&table, &mut scheduler, 3, 10, 0).unwrap();
+        notify(&table, &mut scheduler, 0, 12).unwrap();
+
+        // 2 が終了したら、10 の待ち行列から抜ける
+        cancel(&table, &mut scheduler, 2);
+        receive(&table, &mut scheduler, 0, ANY, 0).unwrap();
+        assert_eq!(take_delivery(&table, 0).unwrap().m_type, 1);
+        receive(&table, &mut scheduler, 0, ANY, 0).unwrap();
+        assert_eq!(take_delivery(&table, 0), None);
+
+        // 10 が終了したら、10 を待っていたプロセスはエラーで起きる
+        sendrec(&table, &mut scheduler, 1, 10, &Message::new(3), 0).unwrap();
+        assert_eq!(flags(&table, 1), "RECEIVING", "1 は 10 の返事を待つ");
+        cancel(&table, &mut scheduler, 0);
+        for slot in [1, 3] {
+            let process = table.get_mut(slot).unwrap();
+            assert_eq!(process.flags.to_string(), "-");
+            assert_eq!(process.registers.rax as i64, libipc::EDEADSRCDST);
+            assert!(scheduler.contains(slot), "起こしたプロセスはキューに戻る");
+        }
+        assert_eq!(table.get_mut(2).unwrap().notify_pending, 0, "保留中の通知も消える");
+    }
+
+    #[test]
+    fn test_cancel_middle_of_queue() {
+        let (table, mut scheduler) = table(&[10, 11, 12, 13]);
+        for slot in 1..4 {
+            send(&table, &mut scheduler, slot, 10, &Message::new(slot as i32)).unwrap();
+        }
+        cancel(&table, &mut scheduler, 2);
+        for expected in [1, 3] {
+            receive(&table, &mut scheduler, 0, ANY, 0).unwrap();
+            assert_eq!(take_delivery(&table, 0).unwrap().m_type, expected, "前後がつながる");
+        }
+        assert_eq!(table.get_mut(0).unwrap().caller_q, None);
+    }
+
+    #[test]
+    fn test_do_ipc_without_current_process() {
+        assert_eq!(do_ipc(SEND, 10, 0x40_0000), system::ESRCH);
+    }
+}
@@ -0,0 +1,362 @@
+//! カーネルログ（レベル付き、リングバッファに保存）
+//!
+//! `log` クレートのバックエンドとして登録するので、カーネル内では
+//! `log::info!("...")` のように書く。各メッセージにはティック数と、
+//! 出力したプロセスの番号・名前が付く。
+//!
+//! メッセージはコンソールに出すだけでなく、固定長のリングバッファにも残す。
+//! シリアルもVGAもない環境でも、あとから dmesg（SYS_DMESG）で読み出せる。
+//! バッファが一杯になったら古いものから上書きする。
+//!
+//! MINIX 3: kernel/utility.c の kputc() と kmess（カーネルメッセージバッファ）
+//! Linux:   printk() と dmesg
+
+use core::fmt::{self, Write};
+
+use log::{Level, LevelFilter, Log, Metadata, Record};
+
+use crate::cmdline::LogLevel;
+use crate::process::ProcessId;
+use crate::ring_buffer::RingBuffer;
+use crate::sync::SpinLock;
+
+/// バッファに残すメッセージ数
+pub const LOG_CAPACITY: usize = 128;
+/// 1メッセージの最大バイト数（超えた分は切り捨てる）
+pub const MESSAGE_LEN: usize = 120;
+/// dmesg形式の1行の最大バイト数（ティック数やプロセス名、改行を含む）
+pub const LINE_LEN: usize = MESSAGE_LEN + 64;
+/// プロセス名の最大バイト数（Process::name と同じ）
+const NAME_LEN: usize = 16;
+
+/// メッセージを出したプロセス
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Origin {
+    pub pid: ProcessId,
+    name: [u8; NAME_LEN],
+}
+
+impl Origin {
+    pub fn new(pid: ProcessId, name: &str) -> Self {
+        let mut buf = [0; NAME_LEN];
+        let len = truncate_len(name, NAME_LEN);
+        buf[..len].copy_from_slice(&name.as_bytes()[..len]);
+        Self { pid, name: buf }
+    }
+
+    pub fn name(&self) -> &str {
+        let end = self.name.iter().position(|&b| b == 0).unwrap_or(NAME_LEN);
+        core::str::from_utf8(&self.name[..end]).unwrap_or("")
+    }
+}
+
+/// バッファに残す1件分のメッセージ
+#[derive(Debug, Clone, Copy)]
+pub struct LogRecord {
+    /// 通し番号（上書きされても増え続ける）
+    pub seq: u64,
+    /// 出力時のティック数
+    pub ticks: u64,
+    pub level: Level,
+    /// カーネル自身（プロセス実行前や割り込み処理中）なら None
+    pub origin: Option<Origin>,
+    message: [u8; MESSAGE_LEN],
+    len: u8,
+}
+
+impl LogRecord {
+    pub fn message(&self) -> &str {
+        core::str::from_utf8(&self.message[..usize::from(self.len)]).unwrap_or("")
+    }
+}
+
+/// dmesg形式の1行（改行なし）
+/// 例: `[     123] WARN  init(1): cmdline: unknown option`
+impl fmt::Display for LogRecord {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "[{:>8}] {:<5} ", self.ticks, self.level)?;
+        match &self.origin {
+            Some(origin) => write!(f, "{}({}): ", origin.name(), origin.pid)?,
+            None => write!(f, "kernel: ")?,
+        }
+        f.write_str(self.message())
+    }
+}
+
+/// UTF-8の文字の途中で切らないように、`max` バイト以内に収まる長さを返す
+fn truncate_len(s: &str, max: usize) -> usize {
+    if s.len() <= max {
+        return s.len();
+    }
+    (0..=max).rev().find(|&i| s.is_char_boundary(i)).unwrap_or(0)
+}
+
+/// 固定長のバッファへの書き込み（あふれた分は捨てる）
+struct FixedWriter<'a> {
+    buf: &'a mut [u8],
+    len: usize,
+}
+
+impl<'a> FixedWriter<'a> {
+    fn new(buf: &'a mut [u8]) -> Self {
+        Self { buf, len: 0 }
+    }
+}
+
+impl Write for FixedWriter<'_> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        let room = self.buf.len() - self.len;
+        let n = truncate_len(s, room);
+        self.buf[self.len..self.len + n].copy_from_slice(&s.as_bytes()[..n]);
+        self.len += n;
+        Ok(())
+    }
+}
+
+/// メッセージのリングバッファ
+pub struct LogBuffer {
+    records: RingBuffer<LogRecord, LOG_CAPACITY>,
+    /// 次に割り当てる通し番号
+    next_seq: u64,
+}
+
+impl LogBuffer {
+    pub const fn new() -> Self {
+        Self { records: RingBuffer::new(), next_seq: 0 }
+    }
+
+    /// メッセージを追加する（一杯なら最も古いものを上書き）
+    pub fn push(&mut self, ticks: u64, level: Level, origin: Option<Origin>, args: fmt::Arguments) -> LogRecord {
+        let mut message = [0; MESSAGE_LEN];
+        let mut writer = FixedWriter::new(&mut message);
+        let _ = writer.write_fmt(args);
+        let len = writer.len as u8;
+
+        let record = LogRecord { seq: self.next_seq, ticks, level, origin, message, len };
+        self.next_seq += 1;
+        self.records.push_overwrite(record);
+        record
+    }
+
+    /// 残っているメッセージの数
+    pub fn len(&self) -> usize {
+        self.records.len()
+    }
+
+    /// 残っている中で最も古い通し番号
+    pub fn first_seq(&self) -> u64 {
+        self.next_seq - self.records.len() as u64
+    }
+
+    /// 次に書かれる通し番号
+    pub fn next_seq(&self) -> u64 {
+        self.next_seq
+    }
+
+    /// 通し番号 `from` 以降のメッセージを古い順に列挙する
+    /// （すでに上書きされていれば、残っている最も古いものから）
+    pub fn records_from(&self, from: u64) -> impl Iterator<Item = LogRecord> + '_ {
+        self.records.iter().filter(move |record| record.seq >= from)
+    }
+
+    /// dmesg: 通し番号 `from` 以降を1行ずつテキストにして `out` に詰める
+    ///
+    /// 行の途中では切らない（入りきらない行は次回に回す）。
+    /// 書いたバイト数と、次に読むべき通し番号を返す。
+    pub fn read(&self, from: u64, out: &mut [u8]) -> (usize, u64) {
+        let mut written = 0;
+        let mut next = from.max(self.first_seq());
+        for record in self.records_from(next) {
+            let mut line = [0; LINE_LEN];
+            let mut writer = FixedWriter::new(&mut line);
+            let _ = writeln!(writer, "{}", record);
+            let len = writer.len;
+            if written + len > out.len() {
+                break;
+            }
+            out[written..written + len].copy_from_slice(&line[..len]);
+            written += len;
+            next = record.seq + 1;
+        }
+        (written, next)
+    }
+}
+
+impl Default for LogBuffer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// カーネル全体のログバッファ
+pub static LOG_BUFFER: SpinLock<LogBuffer> = SpinLock::new(LogBuffer::new());
+
+/// 起動オプションのログレベルを `log` クレートのものに変換する
+pub fn level_filter(level: LogLevel) -> LevelFilter {
+    match level {
+        LogLevel::Error => LevelFilter::Error,
+        LogLevel::Warn => LevelFilter::Warn,
+        LogLevel::Info => LevelFilter::Info,
+        LogLevel::Debug => LevelFilter::Debug,
+        LogLevel::Trace => LevelFilter::Trace,
+    }
+}
+
+/// `log` クレートのバックエンド
+struct KernelLogger;
+
+impl Log for KernelLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= log::max_level()
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        let origin = crate::process::current().map(|process| Origin::new(process.pid, process.name_str()));
+        let ticks = crate::clock::ticks();
+
+        // コンソール出力中はバッファのロックを持たない
+        let entry = LOG_BUFFER.lock().push(ticks, record.level(), origin, *record.args());
+        print_record(&entry);
+    }
+
+    fn flush(&self) {}
+}
+
+/// コンソールに1件出す（VGAではレベルごとに色を変える）
+fn print_record(record: &LogRecord) {
+    use crate::vga::{Color, ColorCode, DEFAULT_COLOR};
+    let color = match record.level {
+        Level::Error => ColorCode::new(Color::LightRed, Color::Black),
+        Level::Warn => ColorCode::new(Color::Yellow, Color::Black),
+        Level::Info => DEFAULT_COLOR,
+        Level::Debug | Level::Trace => ColorCode::new(Color::DarkGray, Color::Black),
+    };
+    crate::console::set_color(color);
+    println!("{}", record);
+    crate::console::set_color(DEFAULT_COLOR);
+}
+
+static LOGGER: KernelLogger = KernelLogger;
+
+/// ロガーを登録し、出力するレベルを設定する
+pub fn init(level: LogLevel) {
+    // 二度目の登録は失敗するが、レベルの変更だけは反映する
+    let _ = log::set_logger(&LOGGER);
+    log::set_max_level(level_filter(level));
+}
+
+// ===== テスト =====
+#[cfg(all(test, not(target_os = "none")))]
+mod tests {
+    use super::*;
+
+    fn messages(buffer: &LogBuffer) -> Vec<String> {
+        buffer.records_from(0).map(|r| r.message().to_string()).collect()
+    }
+
+    mod record_tests {
+        use super::*;
+
+        #[test]
+        fn test_record_format() {
+            let mut buffer = LogBuffer::new();
+            buffer.push(42, Level::Warn, Some(Origin::new(3, "init")), format_args!("hello {}", 1));
+            buffer.push(7, Level::Info, None, format_args!("boot"));
+            let lines: Vec<_> = buffer.records_from(0).map(|r| r.to_string()).collect();
+            assert_eq!(lines[0], "[      42] WARN  init(3): hello 1");
+            assert_eq!(lines[1], "[       7] INFO  kernel: boot", "プロセスがなければkernel");
+        }
+
+        #[test]
+        fn test_long_message_is_truncated() {
+            let mut buffer = LogBuffer::new();
+            let long = "あ".repeat(100);
+            buffer.push(0, Level::Info, None, format_args!("{}", long));
+            let message = buffer.records_from(0).next().unwrap().message().to_string();
+            assert!(message.len() <= MESSAGE_LEN, "最大長で切り捨てる");
+            assert!(long.starts_with(&message), "文字の途中では切らない");
+            assert_eq!(message.len() % 3, 0);
+        }
+
+        #[test]
+        fn test_origin_name_truncated() {
+            let origin = Origin::new(1, "a-very-long-process-name");
+            assert_eq!(origin.name(), "a-very-long-proc", "名前は16バイトまで");
+        }
+
+        #[test]
+        fn test_level_filter() {
+            assert_eq!(level_filter(LogLevel::Error), LevelFilter::Error);
+            assert_eq!(level_filter(LogLevel::Trace), LevelFilter::Trace);
+        }
+    }
+
+    mod wraparound_tests {
+        use super::*;
+
+        #[test]
+        fn test_overwrites_oldest() {
+            let mut buffer = LogBuffer::new();
+            for i in 0..LOG_CAPACITY + 10 {
+                buffer.push(i as u64, Level::Info, None, format_args!("m{}", i));
+            }
+            assert_eq!(buffer.len(), LOG_CAPACITY, "容量を超えて増えない");
+            assert_eq!(buffer.first_seq(), 10, "古い10件は上書きされた");
+            assert_eq!(buffer.next_seq(), (LOG_CAPACITY + 10) as u64);
+            let messages = messages(&buffer);
+            assert_eq!(messages.first().unwrap(), "m10");
+            assert_eq!(messages.last().unwrap(), &format!("m{}", LOG_CAPACITY + 9), "最新のものが末尾");
+        }
+
+        #[test]
+        fn test_read_after_wraparound_starts_from_oldest() {
+            let mut buffer = LogBuffer::new();
+            for i in 0..LOG_CAPACITY * 3 {
+                buffer.push(0, Level::Info, None, format_args!("m{}", i));
+            }
+            let mut out = [0u8; 40];
+            let (len, next) = buffer.read(0, &mut out);
+            let text = core::str::from_utf8(&out[..len]).unwrap();
+            assert!(
+                text.starts_with(&format!("[       0] INFO  kernel: m{}\n", LOG_CAPACITY * 2)),
+                "上書きされた分は飛ばす: {}",
+                text
+            );
+            assert_eq!(next, (LOG_CAPACITY * 2 + 1) as u64, "1行だけ入った");
+        }
+
+        #[test]
+        fn test_read_resumes_without_loss() {
+            let mut buffer = LogBuffer::new();
+            for i in 0..20 {
+                buffer.push(i, Level::Debug, None, format_args!("line {}", i));
+            }
+            // 小さなバッファで何度も読んで、全行がそろうことを確かめる
+            let mut all = String::new();
+            let mut next = 0;
+            loop {
+                let mut out = [0u8; 100];
+                let (len, new_next) = buffer.read(next, &mut out);
+                if len == 0 {
+                    break;
+                }
+                all.push_str(core::str::from_utf8(&out[..len]).unwrap());
+                next = new_next;
+            }
+            assert_eq!(all.lines().count(), 20, "すべての行を読める");
+            assert!(all.lines().all(|line| line.contains("DEBUG kernel: line")));
+            assert_eq!(next, 20);
+        }
+
+        #[test]
+        fn test_read_does_not_split_lines() {
+            let mut buffer = LogBuffer::new();
+            buffer.push(0, Level::Info, None, format_args!("0123456789"));
+            let mut out = [0u8; 10];
+            assert_eq!(buffer.read(0, &mut out), (0, 0), "入りきらない行は書かない");
+        }
+    }
+}
@@ -2,57 +2,182 @@
 //! Phase 0: 最小限のカーネル - QEMUでブートして画面に文字を表示する
 //! Phase 1: プロセス管理 - MINIX 3から学んだ構造を実装
 
-// テスト時は標準ライブラリを使用
-#![cfg_attr(not(test), no_std)]
-#![cfg_attr(not(test), no_main)]
+// ホストでのテスト時は標準ライブラリを使用
+#![cfg_attr(any(not(test), target_os = "none"), no_std)]
+#![cfg_attr(any(not(test), target_os = "none"), no_main)]
+// カーネルのターゲットでの `cargo test` は、QEMU上で #[test_case] を実行する（testing.rs）
+#![cfg_attr(all(test, target_os = "none"), feature(custom_test_frameworks))]
+#![cfg_attr(all(test, target_os = "none"), test_runner(crate::testing::run))]
+#![cfg_attr(all(test, target_os = "none"), reexport_test_harness_main = "test_main")]
+// ヒープを確保できなかったときの処理（memory/heap.rs）を自分で決める
+#![cfg_attr(target_os = "none", feature(alloc_error_handler))]
 
+// Box, Vec, BTreeMap など（ヒープは memory::heap）
+extern crate alloc;
+
+#[macro_use]
+mod console;
+
+mod arch;
+mod boot;
+mod clock;
+mod cmdline;
+mod dispatch;
+mod elf;
+mod gdb;
+mod initrd;
+mod ipc;
+mod klog;
+mod memory;
+mod monitor;
+mod panic;
 mod process;
+mod ring_buffer;
+mod serial;
+mod symbols;
+mod sync;
+mod system;
+#[cfg(all(test, target_os = "none"))]
+mod testing;
+mod user;
+mod vga;
 
-#[cfg(not(test))]
-use core::panic::PanicInfo;
-#[cfg(not(test))]
-use process::{Process, ProcessFlags, ProcessTable, Priority, MAX_PROCESSES};
-
-/// パニックハンドラ
-/// パニックが発生した際に呼ばれる（現時点では無限ループ）
-/// テスト時は標準ライブラリのpanicハンドラを使用
-#[cfg(not(test))]
-#[panic_handler]
-fn panic(_info: &PanicInfo) -> ! {
-    loop {}
-}
+#[cfg(any(not(test), target_os = "none"))]
+use boot_info::BootInfo;
+#[cfg(any(not(test), target_os = "none"))]
+use arch::{CpuOps, X86_64};
+#[cfg(any(not(test), target_os = "none"))]
+use cmdline::{Console, GdbMode};
+#[cfg(any(not(test), target_os = "none"))]
+use process::{ProcessFlags, Quantum, BOOT_PROCESSES, PROCESS_TABLE, SCHEDULER};
+#[cfg(any(not(test), target_os = "none"))]
+use vga::{Color, ColorCode};
 
 /// カーネルのエントリポイント
-/// ブートローダーがこの関数を呼び出す
-/// テスト時は除外
-#[cfg(not(test))]
+/// UEFIブートローダーがこの関数を呼び出す
+/// 引数のBootInfoは物理メモリマップ経由の仮想アドレスで渡される
+/// （Multibootで起動した場合は boot::multiboot_main() から kernel_main() に入る）
+/// ホストでのテスト時は除外
+#[cfg(any(not(test), target_os = "none"))]
 #[no_mangle]
-pub extern "C" fn _start() -> ! {
-    // VGAテキストバッファのアドレス（0xB8000）
-    // 80x25のテキストモード画面
-    let vga_buffer = 0xb8000 as *mut u8;
-
-    // 画面をクリア（スペースで埋める）
-    for i in 0..80 * 25 {
-        unsafe {
-            // 文字
-            *vga_buffer.offset(i * 2) = b' ';
-            // 属性（ライトグレー on ブラック）
-            *vga_buffer.offset(i * 2 + 1) = 0x0f;
+pub extern "C" fn _start(boot_info: &'static BootInfo) -> ! {
+    kernel_main(boot_info)
+}
+
+/// ブート方法によらない共通のカーネル本体
+#[cfg(any(not(test), target_os = "none"))]
+pub fn kernel_main(boot_info: &'static BootInfo) -> ! {
+    // 別のブートローダーや壊れた引数で呼ばれた場合は先に進まない
+    if !boot_info.is_valid() {
+        loop {}
+    }
+
+    // ユーザーモードのセグメントとTSSを持つGDTに切り替えてから、割り込みの入口を用意する
+    // （PICのIRQはすべてマスクした状態）。システムコールの入口も登録しておく
+    // どちらの入口から来ても、カーネルの書き込みがコピーオンライトのページで #PF になるようにする
+    unsafe {
+        arch::enable_write_protect();
+        arch::gdt::init();
+        arch::interrupts::init();
+        arch::syscall::init();
+    }
+
+    // VGAテキスト画面（0xB8000、80x25）を物理メモリマップ経由で使う
+    let mut vga = unsafe { vga::Writer::from_address(boot_info.phys_to_virt(vga::VGA_BUFFER_PHYS)) };
+    vga.clear_screen();
+    *vga::WRITER.lock() = Some(vga);
+
+    // COM1（115200bps、8N1）。UARTがなくてもVGAだけで続ける
+    let serial = serial::init(serial::DEFAULT_BAUD);
+
+    // コマンドラインの起動オプションを適用
+    let options = cmdline::parse(boot_info.cmdline());
+    console::set_outputs(options.uses_console(Console::Serial), options.uses_console(Console::Vga));
+    klog::init(options.loglevel);
+    Quantum::set_default(options.quantum);
+    SCHEDULER.lock().set_policy(options.sched);
+    // メモリマップの使用可能な領域を物理フレームアロケータに渡す
+    let frames = memory::init(boot_info);
+    let heap = memory::heap::init();
+    // 今のページテーブル（ヒープもマップ済み）を、プロセスのアドレス空間のカーネルの半分として使う
+    memory::vm::init();
+    // MINIX 3: proc_init() と同じく、すべてのスロットを空きにしておく
+    for i in 0..BOOT_PROCESSES {
+        if let Some(process) = PROCESS_TABLE.get_mut(i) {
+            process.flags.set(ProcessFlags::SLOT_FREE);
+            process.reset_quantum();
         }
     }
+    // 起動用のスロットより後ろは、足りなくなったときにスラブから作る
+    let procs = options.procs.unwrap_or_else(|| process::default_capacity(frames as u64 * memory::FRAME_SIZE));
+    let procs = PROCESS_TABLE.set_capacity(procs);
+    SCHEDULER.lock().reserve(procs);
 
-    // "Hello, Learning OS!" を表示
-    let message = b"Hello, Learning OS!";
-    for (i, &byte) in message.iter().enumerate() {
-        unsafe {
-            // 文字
-            *vga_buffer.offset(i as isize * 2) = byte;
-            // 属性（ライトグレー on ブラック）
-            *vga_buffer.offset(i as isize * 2 + 1) = 0x0f;
+    // タイマー（HZ回/秒）を動かしてから割り込みを許可する
+    clock::init();
+    unsafe { X86_64::enable_interrupts() };
+
+    console::set_color(ColorCode::new(Color::White, Color::Black));
+    println!("Hello, Learning OS!");
+    console::set_color(vga::DEFAULT_COLOR);
+
+    // 解析できなかったオプションは警告として記録
+    if let Err(error) = serial {
+        log::warn!("serial: {}", error);
+    }
+    for warning in options.warnings() {
+        log::warn!("cmdline: {}", warning);
+    }
+    if options.dropped_warnings() > 0 {
+        log::warn!("cmdline: {} more warnings", options.dropped_warnings());
+    }
+    log::info!("timer running at {} Hz", clock::HZ);
+    log::info!("memory: {} MiB usable ({} frames)", (frames * memory::FRAME_SIZE as usize) >> 20, frames);
+    if let Err(error) = heap {
+        log::warn!("heap: {}", error);
+    }
+    log::info!("process table: {} slots ({} preallocated)", procs, BOOT_PROCESSES);
+
+    // gdb= ならCOM2でGDBスタブを動かす（wait なら、ここでGDBがつなぐのを待つ）
+    if let Some(mode) = options.gdb {
+        match gdb::init() {
+            Ok(()) if mode == GdbMode::Wait => {
+                log::info!("gdb: waiting for debugger on COM2");
+                gdb::breakpoint();
+            }
+            Ok(()) => log::info!("gdb: stub listening on COM2"),
+            Err(error) => log::warn!("gdb: {}", error),
         }
     }
 
-    // 無限ループ（OSは終了しない）
-    loop {}
-}
\ No newline at end of file
+    // 最初のモジュールをinitrd（読み出し専用のファイルシステム）として解析する
+    let initrd = match initrd::init(boot_info) {
+        Ok(Some(entries)) => {
+            log::info!("initrd: {} entries", entries);
+            true
+        }
+        Ok(None) => false,
+        Err(error) => {
+            log::warn!("initrd: {}", error);
+            false
+        }
+    };
+
+    // カーネル内テストのビルドでは、初期化が終わったところでテストを実行する（QEMUを終了させて戻らない）
+    #[cfg(test)]
+    test_main();
+
+    // モニタ（シリアルコンソールの対話シェル）をカーネルタスクとして登録する
+    let mut monitor = monitor::Task::start(boot_info.memory_regions());
+
+    // init= のプログラム（デフォルトは /sbin/init）をinitrdから読み、最初のユーザープロセスとしてキューに入れる
+    if initrd {
+        initrd::start_init(options.init);
+    }
+
+    // 起動スレッドはこのままディスパッチャになり、キューから選んだプロセスを動かし続ける（OSは終了しない）
+    match monitor.as_mut() {
+        Some(monitor) => dispatch::run(&mut [monitor]),
+        None => dispatch::run(&mut []),
+    }
+}
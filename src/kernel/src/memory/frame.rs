@@ -0,0 +1,561 @@
+//! 物理フレームアロケータ
+//!
+//! 物理メモリを4KiBのフレーム単位で管理する。
+//! - 空きフレームのビットマップ: 1フレーム1ビット。解放済みのフレームをもう一度
+//!   解放しようとした（二重解放）かをすぐに調べられる
+//! - 管理しているフレームのビットマップ: 使用可能な領域として追加したフレームだけが1。
+//!   MMIOやカーネルイメージなど、一度も渡していないフレームの解放を断る
+//! - バディアロケータ: 2^order 個の連続したフレームを確保する。オーダーごとに空きブロックの
+//!   ビットマップを持ち、解放したブロックの相方（バディ）も空いていれば1つ上のオーダーにまとめる
+//!
+//! ヒープがまだないので、管理用のデータはすべて固定長の配列に置く。
+//! 初期状態（すべて0）は「空きなし」なので、静的変数に置いても .bss に入る。
+//!
+//! MINIX 3: servers/vm/alloc.c の alloc_pages() / free_pages()（空きページのビットマップ）
+//! Linux: mm/page_alloc.c のバディアロケータ（free_area[order]）
+//! MikanOS: memory_manager.cpp の BitmapMemoryManager
+
+use core::fmt;
+
+use boot_info::{MemoryRegion, MemoryRegionKind};
+
+use super::{Frame, FRAME_SIZE};
+
+/// 最大のオーダー（2^10 フレーム = 4MiB のブロックまで）
+/// Linux: MAX_ORDER
+pub const MAX_ORDER: usize = 10;
+
+const WORD_BITS: usize = u64::BITS as usize;
+
+/// フレームを解放できなかった理由
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FreeError {
+    /// 管理している範囲の外
+    OutOfRange(Frame),
+    /// 先頭が 2^order フレームの境界にない、またはオーダーが大きすぎる
+    Misaligned(Frame, usize),
+    /// 確保されていないフレーム（二重解放）
+    NotAllocated(Frame),
+    /// 使用可能な領域として追加していないフレーム（MMIO、予約領域、カーネルイメージなど）
+    NotManaged(Frame),
+}
+
+impl fmt::Display for FreeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::OutOfRange(frame) => write!(f, "frame {:#x} is out of range", frame.start_address()),
+            Self::Misaligned(frame, order) => {
+                write!(f, "frame {:#x} is not aligned to order {}", frame.start_address(), order)
+            }
+            Self::NotAllocated(frame) => write!(f, "frame {:#x} is already free", frame.start_address()),
+            Self::NotManaged(frame) => write!(f, "frame {:#x} is not managed memory", frame.start_address()),
+        }
+    }
+}
+
+/// `WORDS * 64` 個のフレーム（物理アドレス0から）を管理するアロケータ
+pub struct FrameAllocator<const WORDS: usize> {
+    /// 空きフレーム（どれかのオーダーの空きブロックに含まれる）なら1
+    free_map: [u64; WORDS],
+    /// 使用可能な領域として追加したフレームなら1（これ以外は解放できない）
+    managed: [u64; WORDS],
+    /// オーダー0の空きブロック
+    free_order0: [u64; WORDS],
+    /// オーダー1以上の空きブロック（オーダーkは order_offset(k) ビット目から FRAMES >> k ビット）
+    free_higher: [u64; WORDS],
+    /// オーダーごとの空きブロック数
+    free_blocks: [usize; MAX_ORDER + 1],
+    /// オーダーごとに、これより前には空きブロックがないという位置（探索の開始位置）
+    hint: [usize; MAX_ORDER + 1],
+    /// 初期化で追加したフレーム数
+    total: usize,
+    /// 追加した最後のフレーム番号 + 1
+    limit: usize,
+}
+
+impl<const WORDS: usize> FrameAllocator<WORDS> {
+    /// 管理できるフレーム数
+    pub const FRAMES: usize = WORDS * WORD_BITS;
+
+    /// 空きフレームのないアロケータを作成
+    pub const fn new() -> Self {
+        Self {
+            free_map: [0; WORDS],
+            managed: [0; WORDS],
+            free_order0: [0; WORDS],
+            free_higher: [0; WORDS],
+            free_blocks: [0; MAX_ORDER + 1],
+            hint: [0; MAX_ORDER + 1],
+            total: 0,
+            limit: 0,
+        }
+    }
+
+    /// メモリマップの使用可能な領域を追加する
+    ///
+    /// `reserved` の範囲（カーネルイメージ、ブートモジュールなど）は、メモリマップで
+    /// 使用可能となっていても除く。フレーム0はヌルポインタと区別できないので使わない。
+    /// 管理できる範囲を超えたメモリは無視する。追加したフレーム数を返す。
+    ///
+    /// MINIX 3: servers/vm/main.c の init_vm() で free_pages を作るところ
+    pub fn init(&mut self, regions: &[MemoryRegion], reserved: impl Iterator<Item = (u64, u64)> + Clone) -> usize {
+        let before = self.total;
+        for region in regions.iter().filter(|r| r.kind == MemoryRegionKind::Usable) {
+            self.add_usable(region.start.max(FRAME_SIZE), region.end, reserved.clone());
+        }
+        self.total - before
+    }
+
+    /// 物理アドレス `[start, end)` を使用可能な領域として追加する（追加したフレーム数を返す）
+    ///
+    /// 初期化で除いた範囲のうち、もう使わなくなったもの（読み終えたブートデータなど）を
+    /// 空きに戻すときに使う。`free()` は追加していないフレームを受け付けない。
+    pub fn add_region(&mut self, start: u64, end: u64) -> usize {
+        let before = self.total;
+        self.add_usable(start.max(FRAME_SIZE), end, core::iter::empty());
+        self.total - before
+    }
+
+    /// `[start, end)` から `reserved` と重なる部分を除いて追加する
+    fn add_usable(&mut self, start: u64, end: u64, reserved: impl Iterator<Item = (u64, u64)> + Clone) {
+        if start >= end {
+            return;
+        }
+        // 重なる予約範囲があれば、その前後に分けて調べ直す
+        match reserved.clone().find(|&(r_start, r_end)| r_start < end && start < r_end) {
+            Some((r_start, r_end)) => {
+                self.add_usable(start, r_start, reserved.clone());
+                self.add_usable(r_end, end, reserved);
+            }
+            None => {
+                // 一部だけ使えるフレームは使わない（開始は切り上げ、終了は切り捨て）
+                let first = start.div_ceil(FRAME_SIZE);
+                let last = end / FRAME_SIZE;
+                self.add_frames(first as usize, (last as usize).min(Self::FRAMES));
+            }
+        }
+    }
+
+    /// フレーム番号 `[start, end)` を、そろえられる一番大きなブロックに分けて空きにする
+    /// （メモリマップの領域が重なっていても、すでに空きのフレームは数えない）
+    fn add_frames(&mut self, mut start: usize, end: usize) {
+        while start < end {
+            let mut order = (start.trailing_zeros() as usize).min(MAX_ORDER);
+            while order > 0 && (start + (1 << order) > end || !self.is_allocated(start, 1 << order)) {
+                order -= 1;
+            }
+            if self.is_allocated(start, 1 << order) {
+                for frame in start..start + (1 << order) {
+                    set_bit(&mut self.managed, frame, true);
+                }
+                self.release(start, order);
+                self.total += 1 << order;
+                self.limit = self.limit.max(start + (1 << order));
+            }
+            start += 1 << order;
+        }
+    }
+
+    /// 1フレーム確保する
+    pub fn alloc_frame(&mut self) -> Option<Frame> {
+        self.alloc(0)
+    }
+
+    /// 1フレーム解放する
+    pub fn free_frame(&mut self, frame: Frame) -> Result<(), FreeError> {
+        self.free(frame, 0)
+    }
+
+    /// 2^order 個の連続したフレームを確保する（先頭は 2^order フレームの境界にそろう）
+    ///
+    /// 足りるオーダーの空きブロックを探し、大きすぎれば半分に分けて残りを空きに戻す。
+    /// Linux: __rmqueue_smallest() と expand()
+    pub fn alloc(&mut self, order: usize) -> Option<Frame> {
+        let found = (order..=MAX_ORDER).find(|&k| self.free_blocks[k] > 0)?;
+        let mut index = self.find_free_block(found)?;
+        self.set_free_block(found, index, false);
+        for k in (order..found).rev() {
+            index <<= 1;
+            self.set_free_block(k, index + 1, true);
+        }
+        let start = index << order;
+        for frame in start..start + (1 << order) {
+            self.set_free_frame(frame, false);
+        }
+        Some(Frame::from_number(start as u64))
+    }
+
+    /// `alloc(order)` で確保したフレームを解放する
+    ///
+    /// 大きなブロックの一部をオーダー0で1フレームずつ返してもよい（全部そろえばまとまる）。
+    /// 初期化や `add_region()` で追加していないフレームは断る。
+    pub fn free(&mut self, frame: Frame, order: usize) -> Result<(), FreeError> {
+        let start = frame.number() as usize;
+        if order > MAX_ORDER || !start.is_multiple_of(1 << order) {
+            return Err(FreeError::Misaligned(frame, order));
+        }
+        if start + (1 << order) > Self::FRAMES {
+            return Err(FreeError::OutOfRange(frame));
+        }
+        if let Some(outside) = (start..start + (1 << order)).find(|&n| !test_bit(&self.managed, n)) {
+            return Err(FreeError::NotManaged(Frame::from_number(outside as u64)));
+        }
+        if let Some(free) = (start..start + (1 << order)).find(|&n| self.is_free_frame(n)) {
+            return Err(FreeError::NotAllocated(Frame::from_number(free as u64)));
+        }
+        self.release(start, order);
+        Ok(())
+    }
+
+    /// 空きにしてバディとまとめる
+    /// Linux: __free_one_page()
+    fn release(&mut self, start: usize, order: usize) {
+        for frame in start..start + (1 << order) {
+            self.set_free_frame(frame, true);
+        }
+        let mut order = order;
+        let mut index = start >> order;
+        while order < MAX_ORDER {
+            let buddy = index ^ 1;
+            if buddy >= Self::FRAMES >> order || !self.is_free_block(order, buddy) {
+                break;
+            }
+            self.set_free_block(order, buddy, false);
+            index >>= 1;
+            order += 1;
+        }
+        self.set_free_block(order, index, true);
+    }
+
+    /// 空きフレーム数
+    pub fn free_frames(&self) -> usize {
+        self.free_blocks.iter().enumerate().map(|(order, count)| count << order).sum()
+    }
+
+    /// 初期化で追加したフレーム数
+    pub fn total_frames(&self) -> usize {
+        self.total
+    }
+
+    /// 追加したフレームの番号はすべてこれより小さい（フレーム番号で引く表の大きさに使う）
+    pub fn frame_limit(&self) -> usize {
+        self.limit
+    }
+
+    /// オーダーごとの空きブロック数（Linuxの /proc/buddyinfo）
+    pub fn free_blocks(&self) -> &[usize; MAX_ORDER + 1] {
+        &self.free_blocks
+    }
+
+    fn is_free_frame(&self, frame: usize) -> bool {
+        test_bit(&self.free_map, frame)
+    }
+
+    fn is_allocated(&self, start: usize, count: usize) -> bool {
+        (start..start + count).all(|frame| !self.is_free_frame(frame))
+    }
+
+    fn set_free_frame(&mut self, frame: usize, free: bool) {
+        set_bit(&mut self.free_map, frame, free);
+    }
+
+    /// オーダー `order` のビットマップと、その先頭のビット位置
+    fn order_bitmap(&self, order: usize) -> (&[u64; WORDS], usize) {
+        match order {
+            0 => (&self.free_order0, 0),
+            _ => (&self.free_higher, Self::order_offset(order)),
+        }
+    }
+
+    fn order_offset(order: usize) -> usize {
+        (1..order).map(|k| Self::FRAMES >> k).sum()
+    }
+
+    fn is_free_block(&self, order: usize, index: usize) -> bool {
+        let (bitmap, offset) = self.order_bitmap(order);
+        test_bit(bitmap, offset + index)
+    }
+
+    fn set_free_block(&mut self, order: usize, index: usize, free: bool) {
+        let (bitmap, offset) = match order {
+            0 => (&mut self.free_order0, 0),
+            _ => (&mut self.free_higher, Self::order_offset(order)),
+        };
+        set_bit(bitmap, offset + index, free);
+        if free {
+            self.free_blocks[order] += 1;
+            self.hint[order] = self.hint[order].min(index);
+        } else {
+            self.free_blocks[order] -= 1;
+        }
+    }
+
+    /// オーダー `order` の空きブロックを先頭から探す
+    fn find_free_block(&mut self, order: usize) -> Option<usize> {
+        let (bitmap, offset) = self.order_bitmap(order);
+        let end = offset + (Self::FRAMES >> order);
+        let mut bit = offset + self.hint[order];
+        while bit < end {
+            let word = bitmap[bit / WORD_BITS] >> (bit % WORD_BITS);
+            if word == 0 {
+                bit = (bit / WORD_BITS + 1) * WORD_BITS;
+                continue;
+            }
+            let found = bit + word.trailing_zeros() as usize;
+            if found >= end {
+                break;
+            }
+            self.hint[order] = found - offset;
+            return Some(found - offset);
+        }
+        None
+    }
+}
+
+impl<const WORDS: usize> Default for FrameAllocator<WORDS> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn test_bit(bitmap: &[u64], bit: usize) -> bool {
+    bitmap[bit / WORD_BITS] & (1 << (bit % WORD_BITS)) != 0
+}
+
+fn set_bit(bitmap: &mut [u64], bit: usize, value: bool) {
+    if value {
+        bitmap[bit / WORD_BITS] |= 1 << (bit % WORD_BITS);
+    } else {
+        bitmap[bit / WORD_BITS] &= !(1 << (bit % WORD_BITS));
+    }
+}
+
+// ===== テスト =====
+#[cfg(all(test, not(target_os = "none")))]
+mod tests {
+    use super::*;
+
+    /// 4096フレーム（16MiB）を管理するアロケータ
+    type Allocator = FrameAllocator<64>;
+
+    const MIB: u64 = 1 << 20;
+
+    fn usable(start: u64, end: u64) -> MemoryRegion {
+        MemoryRegion::new(start, end, MemoryRegionKind::Usable)
+    }
+
+    fn no_reserved() -> core::iter::Empty<(u64, u64)> {
+        core::iter::empty()
+    }
+
+    /// 空きがなくなるまで1フレームずつ確保する
+    fn alloc_all(allocator: &mut Allocator) -> Vec<Frame> {
+        let mut frames = Vec::new();
+        while let Some(frame) = allocator.alloc_frame() {
+            frames.push(frame);
+        }
+        frames
+    }
+
+    mod init_tests {
+        use super::*;
+
+        #[test]
+        fn test_only_usable_regions() {
+            let regions = [
+                usable(0, 0x9_f000),
+                MemoryRegion::new(0x9_f000, MIB, MemoryRegionKind::Reserved),
+                usable(MIB, 2 * MIB),
+                MemoryRegion::new(2 * MIB, 3 * MIB, MemoryRegionKind::Kernel),
+                MemoryRegion::new(3 * MIB, 4 * MIB, MemoryRegionKind::Bootloader),
+                MemoryRegion::new(4 * MIB, 5 * MIB, MemoryRegionKind::AcpiReclaimable),
+                usable(5 * MIB, 8 * MIB),
+            ];
+            let mut allocator = Allocator::new();
+            let added = allocator.init(&regions, no_reserved());
+            // フレーム0は使わない
+            assert_eq!(added, 0x9e + 0x100 + 0x300, "使用可能な領域だけを数える");
+            assert_eq!(allocator.free_frames(), added);
+            assert_eq!(allocator.total_frames(), added);
+            assert_eq!(allocator.frame_limit(), (8 * MIB / FRAME_SIZE) as usize, "最後の使用可能な領域の終わり");
+
+            for frame in alloc_all(&mut allocator) {
+                let addr = frame.start_address();
+                assert_ne!(addr, 0, "フレーム0は返さない");
+                assert!(addr < 0x9_f000 || (MIB..2 * MIB).contains(&addr) || addr >= 5 * MIB, "{:#x}", addr);
+            }
+        }
+
+        #[test]
+        fn test_reserved_ranges_excluded() {
+            let regions = [usable(MIB, 8 * MIB)];
+            // メモリマップでは使用可能でも、カーネルとモジュールは除く（端数はフレーム単位に広げる）
+            let reserved = [(2 * MIB, 3 * MIB + 1), (5 * MIB - 1, 6 * MIB)];
+            let mut allocator = Allocator::new();
+            let added = allocator.init(&regions, reserved.iter().copied());
+            assert_eq!(added, 0x700 - 0x101 - 0x101);
+
+            for frame in alloc_all(&mut allocator) {
+                let addr = frame.start_address();
+                for &(start, end) in &reserved {
+                    assert!(addr + FRAME_SIZE <= start || end <= addr, "予約範囲のフレーム {:#x}", addr);
+                }
+            }
+        }
+
+        #[test]
+        fn test_partial_frames_and_overlap() {
+            // 端数のフレームは使わず、重なった領域は二重に数えない
+            let regions = [usable(0x1800, 0x5fff), usable(0x3000, 0x8000)];
+            let mut allocator = Allocator::new();
+            assert_eq!(allocator.init(&regions, no_reserved()), 6, "フレーム2〜7");
+            let mut numbers: Vec<_> = alloc_all(&mut allocator).iter().map(|f| f.number()).collect();
+            numbers.sort();
+            assert_eq!(numbers, [2, 3, 4, 5, 6, 7]);
+        }
+
+        #[test]
+        fn test_memory_above_capacity_ignored() {
+            let mut allocator = Allocator::new();
+            let added = allocator.init(&[usable(8 * MIB, 64 * MIB)], no_reserved());
+            assert_eq!(added, Allocator::FRAMES - 0x800, "16MiBより上は管理しない");
+        }
+    }
+
+    mod alloc_tests {
+        use super::*;
+
+        #[test]
+        fn test_alloc_all_without_overlap() {
+            let mut allocator = Allocator::new();
+            let added = allocator.init(&[usable(0x3000, 3 * MIB + 0x5000)], no_reserved());
+            let frames = alloc_all(&mut allocator);
+            assert_eq!(frames.len(), added, "追加したフレームをすべて確保できる");
+
+            let mut numbers: Vec<_> = frames.iter().map(|f| f.number()).collect();
+            numbers.sort();
+            numbers.dedup();
+            assert_eq!(numbers.len(), frames.len(), "同じフレームを二度返さない");
+            assert_eq!(allocator.free_frames(), 0);
+            assert_eq!(allocator.alloc_frame(), None, "空きがなければNone");
+        }
+
+        #[test]
+        fn test_free_merges_buddies() {
+            let mut allocator = Allocator::new();
+            allocator.init(&[usable(4 * MIB, 8 * MIB)], no_reserved());
+            assert_eq!(allocator.free_blocks()[MAX_ORDER], 1, "4MiBのブロック1つ");
+
+            let frames = alloc_all(&mut allocator);
+            for frame in frames.iter().rev() {
+                allocator.free_frame(*frame).unwrap();
+            }
+            assert_eq!(allocator.free_frames(), 1024);
+            assert_eq!(allocator.free_blocks()[MAX_ORDER], 1, "全部返せば元の大きさにまとまる");
+            assert_eq!(allocator.free_blocks()[..MAX_ORDER].iter().sum::<usize>(), 0);
+            assert_eq!(allocator.alloc(MAX_ORDER), Some(Frame::containing_address(4 * MIB)));
+        }
+
+        #[test]
+        fn test_contiguous_alloc() {
+            let mut allocator = Allocator::new();
+            allocator.init(&[usable(0x1000, 2 * MIB)], no_reserved());
+            let single = allocator.alloc_frame().unwrap();
+            let block = allocator.alloc(3).unwrap();
+            assert_eq!(block.number() % 8, 0, "8フレームの境界にそろう");
+
+            let blocked = block.number()..block.number() + 8;
+            assert!(!blocked.contains(&single.number()));
+            for frame in alloc_all(&mut allocator) {
+                assert!(!blocked.contains(&frame.number()), "確保中のブロックと重なる: {:?}", frame);
+            }
+        }
+
+        #[test]
+        fn test_split_block_freed_by_frames() {
+            let mut allocator = Allocator::new();
+            allocator.init(&[usable(MIB, 2 * MIB)], no_reserved());
+            let block = allocator.alloc(2).unwrap();
+            let before = allocator.free_frames();
+            for i in 0..4 {
+                allocator.free_frame(Frame::from_number(block.number() + i)).unwrap();
+            }
+            assert_eq!(allocator.free_frames(), before + 4, "1フレームずつ返してもよい");
+            assert_eq!(allocator.free_blocks()[8], 1, "1MiB全体に戻る");
+        }
+
+        #[test]
+        fn test_too_large_order() {
+            let mut allocator = Allocator::new();
+            allocator.init(&[usable(0x1000, 8 * MIB)], no_reserved());
+            assert_eq!(allocator.alloc(MAX_ORDER + 1), None);
+            // 0x1000から始まるので、4MiBにそろったブロックは4MiB〜8MiBの1つだけ
+            assert!(allocator.alloc(MAX_ORDER).is_some());
+            assert_eq!(allocator.alloc(MAX_ORDER), None);
+        }
+    }
+
+    mod free_tests {
+        use super::*;
+
+        #[test]
+        fn test_double_free() {
+            let mut allocator = Allocator::new();
+            allocator.init(&[usable(MIB, 2 * MIB)], no_reserved());
+            let frame = allocator.alloc_frame().unwrap();
+            assert_eq!(allocator.free_frame(frame), Ok(()));
+            assert_eq!(allocator.free_frame(frame), Err(FreeError::NotAllocated(frame)), "二重解放");
+            assert_eq!(allocator.free_frames(), 256, "二重解放しても空きは増えない");
+        }
+
+        #[test]
+        fn test_free_block_containing_free_frame() {
+            let mut allocator = Allocator::new();
+            allocator.init(&[usable(MIB, 2 * MIB)], no_reserved());
+            let block = allocator.alloc(1).unwrap();
+            let second = Frame::from_number(block.number() + 1);
+            allocator.free_frame(second).unwrap();
+            assert_eq!(allocator.free(block, 1), Err(FreeError::NotAllocated(second)), "一部がすでに空き");
+            allocator.free_frame(block).unwrap();
+        }
+
+        #[test]
+        fn test_invalid_free() {
+            let mut allocator = Allocator::new();
+            allocator.init(&[usable(MIB, 2 * MIB)], no_reserved());
+            let frame = Frame::from_number(0x101);
+            assert_eq!(allocator.free(frame, 1), Err(FreeError::Misaligned(frame, 1)));
+            assert_eq!(allocator.free(Frame::from_number(0), MAX_ORDER + 1), Err(FreeError::Misaligned(Frame::from_number(0), MAX_ORDER + 1)));
+            let outside = Frame::containing_address(16 * MIB);
+            assert_eq!(allocator.free_frame(outside), Err(FreeError::OutOfRange(outside)));
+            assert_eq!(allocator.free_frames(), 256, "失敗したら何も変えない");
+        }
+
+        #[test]
+        fn test_free_unmanaged_frame() {
+            let regions = [usable(MIB, 2 * MIB), MemoryRegion::new(2 * MIB, 3 * MIB, MemoryRegionKind::Bootloader)];
+            let mut allocator = Allocator::new();
+            allocator.init(&regions, [(MIB, MIB + 0x2000)].into_iter());
+            for frame in [Frame::containing_address(2 * MIB), Frame::containing_address(MIB), Frame::from_number(0)] {
+                assert_eq!(allocator.free_frame(frame), Err(FreeError::NotManaged(frame)), "渡していないフレーム");
+            }
+            let block = Frame::containing_address(MIB + 0x1000);
+            assert_eq!(allocator.free(Frame::containing_address(MIB), 1), Err(FreeError::NotManaged(Frame::containing_address(MIB))));
+            assert_eq!(allocator.free_frames(), 254, "失敗したら何も変えない");
+
+            // 読み終えたブートデータは add_region() で戻す
+            assert_eq!(allocator.add_region(2 * MIB, 3 * MIB), 256);
+            assert_eq!(allocator.add_region(0, MIB + 0x2000), 256 + 1, "フレーム0は使わない、追加済みのものは数えない");
+            assert_eq!(allocator.free_frame(block), Err(FreeError::NotAllocated(block)), "戻したあとは管理下");
+            assert_eq!(allocator.free_frames(), 254 + 256 + 257);
+        }
+
+        #[test]
+        fn test_error_display() {
+            let frame = Frame::containing_address(0x5000);
+            assert_eq!(FreeError::NotAllocated(frame).to_string(), "frame 0x5000 is already free");
+            assert_eq!(FreeError::Misaligned(frame, 2).to_string(), "frame 0x5000 is not aligned to order 2");
+            assert_eq!(FreeError::NotManaged(frame).to_string(), "frame 0x5000 is not managed memory");
+        }
+    }
+}
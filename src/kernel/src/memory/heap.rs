@@ -0,0 +1,463 @@
+//! カーネルヒープ
+//!
+//! `alloc` クレート（`Box`、`Vec`、`BTreeMap`）のための `#[global_allocator]`。
+//! 仮想アドレス `HEAP_START` からの領域に物理フレームをマップし、空き領域を
+//! アドレス順の連結リストで管理する。足りなくなったらページを足して広げる。
+//!
+//! 空きブロックのヘッダ（大きさと次のブロック）は空き領域そのものに書くので、
+//! 管理用の配列はいらない。隣り合う空きブロックは解放のときにまとめる。
+//!
+//! MINIX 3: lib/libc の malloc()（カーネルはヒープを持たず、すべて静的な配列だった）
+//! Linux: mm/slob.c（小さなシステム向けの、空きリストのファーストフィット）
+//! MikanOS: newlib_support.cpp の sbrk()（newlibのmallocにヒープを渡す）
+
+use core::alloc::Layout;
+use core::ptr::{self, NonNull};
+
+/// ヒープの仮想アドレス（PML4[400]、ブートローダーはマップしていない）
+pub const HEAP_START: u64 = 0xffff_c800_0000_0000;
+
+/// ヒープを広げられる上限
+pub const HEAP_MAX_SIZE: u64 = 1 << 30;
+
+/// 起動時にマップしておく大きさ
+pub const HEAP_INITIAL_SIZE: u64 = 1 << 20;
+
+/// ブロックの大きさと先頭のそろえ方の単位（空きブロックのヘッダが入る大きさ）
+const BLOCK_ALIGN: usize = core::mem::size_of::<FreeBlock>();
+
+/// 空きブロックのヘッダ
+struct FreeBlock {
+    /// ヘッダを含むブロックの大きさ
+    size: usize,
+    /// アドレスが次に大きい空きブロック
+    next: *mut FreeBlock,
+}
+
+/// 空きリストのヒープ（メモリのマップはしない。ホストでもテストできる）
+pub struct Heap {
+    /// アドレス順の空きリストの先頭
+    head: *mut FreeBlock,
+    /// 追加された領域の合計
+    total: usize,
+    /// 確保中のバイト数（ブロック単位に切り上げた値）
+    used: usize,
+}
+
+// 安全性: 空きリストは Heap が持つ領域の中だけを指す
+unsafe impl Send for Heap {}
+
+impl Heap {
+    /// 空のヒープを作成
+    pub const fn new() -> Self {
+        Self { head: ptr::null_mut(), total: 0, used: 0 }
+    }
+
+    /// `[start, start + size)` を空き領域として追加する（前後の空きブロックとつながればまとめる）
+    ///
+    /// # Safety
+    /// 領域は書き込めて、ほかのどこからも使われていないこと
+    pub unsafe fn add_region(&mut self, start: usize, size: usize) {
+        let aligned = start.next_multiple_of(BLOCK_ALIGN);
+        let size = size.saturating_sub(aligned - start) / BLOCK_ALIGN * BLOCK_ALIGN;
+        if size == 0 {
+            return;
+        }
+        self.total += size;
+        unsafe { self.insert(aligned, size) };
+    }
+
+    /// `layout` の大きさとそろえ方を満たすブロックを確保する（ファーストフィット）
+    pub fn alloc(&mut self, layout: Layout) -> Option<NonNull<u8>> {
+        let size = block_size(layout);
+        let align = layout.align().max(BLOCK_ALIGN);
+        let mut prev: *mut FreeBlock = ptr::null_mut();
+        let mut current = self.head;
+        while !current.is_null() {
+            let (start, block_size, next) = unsafe { (current as usize, (*current).size, (*current).next) };
+            let end = start + block_size;
+            // 前に余る部分はブロックの大きさの倍数なので、余ればそのまま空きブロックにできる
+            let aligned = start.next_multiple_of(align);
+            if aligned.checked_add(size).is_some_and(|alloc_end| alloc_end <= end) {
+                unsafe {
+                    self.unlink(prev, next);
+                    if aligned > start {
+                        self.insert(start, aligned - start);
+                    }
+                    if aligned + size < end {
+                        self.insert(aligned + size, end - aligned - size);
+                    }
+                }
+                self.used += size;
+                return NonNull::new(aligned as *mut u8);
+            }
+            prev = current;
+            current = next;
+        }
+        None
+    }
+
+    /// `alloc(layout)` で確保したブロックを返す
+    ///
+    /// # Safety
+    /// `ptr` は同じ `layout` でこのヒープから確保し、まだ返していないこと
+    pub unsafe fn dealloc(&mut self, ptr: NonNull<u8>, layout: Layout) {
+        let size = block_size(layout);
+        self.used -= size;
+        unsafe { self.insert(ptr.as_ptr() as usize, size) };
+    }
+
+    /// 追加された領域の合計（バイト）
+    pub fn total_bytes(&self) -> usize {
+        self.total
+    }
+
+    /// 確保中のバイト数
+    pub fn used_bytes(&self) -> usize {
+        self.used
+    }
+
+    /// 空きバイト数
+    pub fn free_bytes(&self) -> usize {
+        self.total - self.used
+    }
+
+    /// 空きブロックの数（断片化の目安）
+    pub fn free_blocks(&self) -> usize {
+        let mut count = 0;
+        let mut current = self.head;
+        while !current.is_null() {
+            count += 1;
+            current = unsafe { (*current).next };
+        }
+        count
+    }
+
+    /// 一番大きな空きブロック
+    pub fn largest_free_block(&self) -> usize {
+        let mut largest = 0;
+        let mut current = self.head;
+        while !current.is_null() {
+            unsafe {
+                largest = largest.max((*current).size);
+                current = (*current).next;
+            }
+        }
+        largest
+    }
+
+    /// `prev` の次（`prev` がヌルなら先頭）を `next` にする
+    unsafe fn unlink(&mut self, prev: *mut FreeBlock, next: *mut FreeBlock) {
+        if prev.is_null() {
+            self.head = next;
+        } else {
+            unsafe { (*prev).next = next };
+        }
+    }
+
+    /// アドレス順の位置に空きブロックを入れ、前後とつながればまとめる
+    unsafe fn insert(&mut self, start: usize, size: usize) {
+        let mut prev: *mut FreeBlock = ptr::null_mut();
+        let mut next = self.head;
+        while !next.is_null() && (next as usize) < start {
+            prev = next;
+            next = unsafe { (*next).next };
+        }
+
+        let block = start as *mut FreeBlock;
+        unsafe {
+            block.write(FreeBlock { size, next });
+            // 後ろのブロックとつながる
+            if !next.is_null() && start + size == next as usize {
+                (*block).size += (*next).size;
+                (*block).next = (*next).next;
+            }
+            // 前のブロックとつながる
+            if !prev.is_null() && prev as usize + (*prev).size == start {
+                (*prev).size += (*block).size;
+                (*prev).next = (*block).next;
+            } else {
+                self.unlink(prev, block);
+            }
+        }
+    }
+}
+
+impl Default for Heap {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// `layout` に使うブロックの大きさ（空きに戻したときにヘッダが入るように切り上げる）
+fn block_size(layout: Layout) -> usize {
+    layout.size().max(1).next_multiple_of(BLOCK_ALIGN)
+}
+
+/// ページをマップしながら広がるカーネルヒープ
+#[cfg(any(not(test), target_os = "none"))]
+pub struct KernelHeap {
+    inner: crate::sync::SpinLock<GrowableHeap>,
+}
+
+#[cfg(any(not(test), target_os = "none"))]
+struct GrowableHeap {
+    heap: Heap,
+    /// マップ済みの領域の終わり
+    end: u64,
+}
+
+#[cfg(any(not(test), target_os = "none"))]
+impl GrowableHeap {
+    /// 少なくとも `bytes` だけヒープの後ろにページを足す
+    fn grow(&mut self, bytes: u64) -> Result<(), crate::arch::MapError> {
+        use crate::arch::paging::{KernelFrames, Mapper};
+        use crate::arch::{AddressSpace, MapError, MapFlags, PageSize};
+
+        const PAGE: u64 = PageSize::Size4K.bytes();
+        let size = bytes.next_multiple_of(PAGE).max(64 * 1024);
+        if self.end + size > HEAP_START + HEAP_MAX_SIZE {
+            return Err(MapError::OutOfFrames);
+        }
+        let mut mapper = Mapper::active(KernelFrames);
+        let flags = MapFlags::from_bits(MapFlags::WRITABLE | MapFlags::GLOBAL);
+        let start = self.end;
+        while self.end < start + size {
+            let frame = super::alloc_frame().ok_or(MapError::OutOfFrames)?;
+            if let Err(error) = mapper.map(self.end, frame, PageSize::Size4K, flags) {
+                let _ = super::free_frame(frame);
+                return Err(error);
+            }
+            // マップできたページから順にヒープに入れる（途中で失敗しても無駄にしない）
+            unsafe { self.heap.add_region(self.end as usize, PAGE as usize) };
+            self.end += PAGE;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(any(not(test), target_os = "none"))]
+impl KernelHeap {
+    const fn new() -> Self {
+        Self { inner: crate::sync::SpinLock::new(GrowableHeap { heap: Heap::new(), end: HEAP_START }) }
+    }
+
+    /// (マップ済みのバイト数, 確保中のバイト数, 空きブロックの数)
+    pub fn stats(&self) -> (usize, usize, usize) {
+        let inner = self.inner.lock();
+        (inner.heap.total_bytes(), inner.heap.used_bytes(), inner.heap.free_blocks())
+    }
+}
+
+#[cfg(any(not(test), target_os = "none"))]
+unsafe impl core::alloc::GlobalAlloc for KernelHeap {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let mut inner = self.inner.lock();
+        if let Some(block) = inner.heap.alloc(layout) {
+            return block.as_ptr();
+        }
+        // 先頭をそろえるために余る分も見込んで広げる
+        let needed = (block_size(layout) + layout.align()) as u64;
+        if inner.grow(needed).is_err() {
+            return ptr::null_mut();
+        }
+        inner.heap.alloc(layout).map_or(ptr::null_mut(), |block| block.as_ptr())
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        if let Some(ptr) = NonNull::new(ptr) {
+            unsafe { self.inner.lock().heap.dealloc(ptr, layout) };
+        }
+    }
+}
+
+/// カーネルのヒープ
+#[cfg(any(not(test), target_os = "none"))]
+#[global_allocator]
+pub static HEAP: KernelHeap = KernelHeap::new();
+
+/// 起動時のヒープをマップする（フレームアロケータの初期化のあとで呼ぶ）
+#[cfg(any(not(test), target_os = "none"))]
+pub fn init() -> Result<(), crate::arch::MapError> {
+    HEAP.inner.lock().grow(HEAP_INITIAL_SIZE)
+}
+
+/// ヒープを確保できなかったときに呼ばれる
+#[cfg(target_os = "none")]
+#[alloc_error_handler]
+fn alloc_error(layout: Layout) -> ! {
+    panic!("out of kernel heap: {} bytes (align {})", layout.size(), layout.align())
+}
+
+// ===== テスト =====
+#[cfg(all(test, not(target_os = "none")))]
+mod tests {
+    use super::*;
+
+    /// テスト用のヒープ領域（4KiBにそろえた64KiB）
+    #[repr(C, align(4096))]
+    struct Arena([u8; 64 * 1024]);
+
+    fn heap_with_arena() -> (Heap, Box<Arena>) {
+        let mut arena = Box::new(Arena([0; 64 * 1024]));
+        let mut heap = Heap::new();
+        unsafe { heap.add_region(arena.0.as_mut_ptr() as usize, arena.0.len()) };
+        (heap, arena)
+    }
+
+    fn layout(size: usize, align: usize) -> Layout {
+        Layout::from_size_align(size, align).unwrap()
+    }
+
+    mod alloc_tests {
+        use super::*;
+
+        #[test]
+        fn test_alloc_and_dealloc() {
+            let (mut heap, _arena) = heap_with_arena();
+            assert_eq!(heap.total_bytes(), 64 * 1024);
+            let a = heap.alloc(layout(100, 8)).unwrap();
+            let b = heap.alloc(layout(1, 1)).unwrap();
+            assert_eq!(heap.used_bytes(), 112 + 16, "ブロックの大きさに切り上げる");
+            assert!(b.as_ptr() as usize >= a.as_ptr() as usize + 100, "重ならない");
+
+            unsafe {
+                heap.dealloc(a, layout(100, 8));
+                heap.dealloc(b, layout(1, 1));
+            }
+            assert_eq!(heap.used_bytes(), 0);
+            assert_eq!(heap.free_blocks(), 1, "全部返せば1つにまとまる");
+        }
+
+        #[test]
+        fn test_alignment() {
+            let (mut heap, _arena) = heap_with_arena();
+            let small = heap.alloc(layout(24, 8)).unwrap();
+            let page = heap.alloc(layout(4096, 4096)).unwrap();
+            assert_eq!(page.as_ptr() as usize % 4096, 0, "4KiBにそろう");
+            assert_eq!(small.as_ptr() as usize % BLOCK_ALIGN, 0);
+            // 前に余った部分は空きブロックとして残る
+            assert!(heap.alloc(layout(1024, 8)).unwrap().as_ptr() < page.as_ptr());
+        }
+
+        #[test]
+        fn test_exhaustion() {
+            let (mut heap, _arena) = heap_with_arena();
+            let all = heap.alloc(layout(64 * 1024, 16)).unwrap();
+            assert!(heap.alloc(layout(1, 1)).is_none(), "空きがなければNone");
+            unsafe { heap.dealloc(all, layout(64 * 1024, 16)) };
+            assert!(heap.alloc(layout(64 * 1024 + 1, 16)).is_none(), "領域より大きい");
+            assert!(heap.alloc(layout(32 * 1024, 32 * 1024)).is_some());
+        }
+
+        #[test]
+        fn test_adjacent_regions_merge() {
+            let mut arena = Box::new(Arena([0; 64 * 1024]));
+            let base = arena.0.as_mut_ptr() as usize;
+            let mut heap = Heap::new();
+            unsafe {
+                heap.add_region(base + 32 * 1024, 32 * 1024);
+                heap.add_region(base + 3, 16 * 1024 - 3);
+                heap.add_region(base + 16 * 1024, 16 * 1024);
+            }
+            assert_eq!(heap.total_bytes(), 64 * 1024 - 16, "そろわない先頭は使わない");
+            assert_eq!(heap.free_blocks(), 1, "後から足した領域も隣とつながる");
+            assert_eq!(heap.largest_free_block(), 64 * 1024 - 16);
+        }
+    }
+
+    mod stress_tests {
+        use super::*;
+
+        /// 線形合同法（テストを毎回同じ順序にする）
+        struct Lcg(u64);
+
+        impl Lcg {
+            fn next(&mut self) -> usize {
+                self.0 = self.0.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+                (self.0 >> 33) as usize
+            }
+        }
+
+        #[test]
+        fn test_random_alloc_and_free() {
+            let (mut heap, arena) = heap_with_arena();
+            let base = arena.0.as_ptr() as usize;
+            let mut rng = Lcg(42);
+            let mut live: Vec<(NonNull<u8>, Layout, u8)> = Vec::new();
+
+            for round in 0..20_000 {
+                if live.is_empty() || !rng.next().is_multiple_of(3) {
+                    let layout = layout(1 + rng.next() % 700, 1 << (rng.next() % 7));
+                    let Some(ptr) = heap.alloc(layout) else { continue };
+                    let start = ptr.as_ptr() as usize;
+                    assert!(start >= base && start + layout.size() <= base + arena.0.len(), "領域の外");
+                    assert_eq!(start % layout.align(), 0, "そろえ方");
+                    for (other, other_layout, _) in &live {
+                        let other = other.as_ptr() as usize;
+                        assert!(start + layout.size() <= other || other + other_layout.size() <= start, "重なった");
+                    }
+                    let fill = round as u8;
+                    unsafe { ptr::write_bytes(ptr.as_ptr(), fill, layout.size()) };
+                    live.push((ptr, layout, fill));
+                } else {
+                    let (ptr, layout, fill) = live.swap_remove(rng.next() % live.len());
+                    let data = unsafe { core::slice::from_raw_parts(ptr.as_ptr(), layout.size()) };
+                    assert!(data.iter().all(|&b| b == fill), "ほかの確保や空きリストに上書きされた");
+                    unsafe { heap.dealloc(ptr, layout) };
+                }
+            }
+
+            for (ptr, layout, _) in live.drain(..) {
+                unsafe { heap.dealloc(ptr, layout) };
+            }
+            assert_eq!(heap.used_bytes(), 0);
+            assert_eq!(heap.free_blocks(), 1, "断片がすべてまとまる");
+            assert_eq!(heap.largest_free_block(), 64 * 1024);
+        }
+    }
+}
+
+#[cfg(all(test, target_os = "none"))]
+mod kernel_tests {
+    use alloc::boxed::Box;
+    use alloc::collections::BTreeMap;
+    use alloc::vec::Vec;
+
+    use super::*;
+
+    #[test_case]
+    fn test_box_and_vec() {
+        let value = Box::new(0x1234_5678u64);
+        assert_eq!(*value, 0x1234_5678);
+        let numbers: Vec<u64> = (0..1000).collect();
+        assert_eq!(numbers.iter().sum::<u64>(), 499_500);
+    }
+
+    #[test_case]
+    fn test_heap_grows() {
+        // 起動時にマップした大きさより大きな確保は、ページを足して満たす
+        let (mapped, _, _) = HEAP.stats();
+        let big = alloc::vec![0xa5u8; HEAP_INITIAL_SIZE as usize * 2];
+        assert!(big.iter().all(|&b| b == 0xa5));
+        assert!(HEAP.stats().0 > mapped);
+    }
+
+    #[test_case]
+    fn test_btree_map_stress() {
+        let (_, used, _) = HEAP.stats();
+        {
+            let mut map = BTreeMap::new();
+            for i in 0..5000u64 {
+                map.insert(i.wrapping_mul(2_654_435_761) % 10_007, i);
+            }
+            for i in 0..2500u64 {
+                map.remove(&(i.wrapping_mul(2_654_435_761) % 10_007));
+            }
+            let boxes: Vec<Box<[u8]>> = (1..200).map(|n| alloc::vec![n as u8; n * 7].into_boxed_slice()).collect();
+            assert!(boxes.iter().enumerate().all(|(i, b)| b.len() == (i + 1) * 7 && b[0] == (i + 1) as u8));
+            assert_eq!(map.len(), 2500);
+        }
+        assert_eq!(HEAP.stats().1, used, "すべて返される");
+    }
+}
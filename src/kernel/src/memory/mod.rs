@@ -0,0 +1,120 @@
+//! メモリ管理
+//!
+//! Phase 3: 物理フレームの確保と解放（frame.rs）、カーネルヒープ（heap.rs）、
+//!          型ごとのオブジェクトキャッシュ（slab.rs）、プロセスのアドレス空間（vm.rs）
+//!
+//! MINIX 3: servers/vm（メモリ管理はユーザー空間のVMサーバーが担当する）
+//! MikanOS: memory_manager.cpp
+
+pub mod frame;
+pub mod heap;
+pub mod slab;
+pub mod vm;
+
+pub use frame::{FrameAllocator, FreeError};
+
+use crate::sync::SpinLock;
+
+/// フレーム（ページ）の大きさ
+pub const FRAME_SIZE: u64 = 4096;
+
+/// 管理する物理メモリの上限（これより上のメモリは使わない）
+/// MikanOS: kMaxPhysicalMemoryBytes
+pub const MAX_PHYSICAL_MEMORY: u64 = 16 << 30;
+
+/// ビットマップ1つあたりのワード数（16GiB / 4KiB / 64 = 64Ki）
+const BITMAP_WORDS: usize = (MAX_PHYSICAL_MEMORY / FRAME_SIZE) as usize / u64::BITS as usize;
+
+/// 物理フレーム（4KiB境界の物理メモリ）
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Frame(u64);
+
+impl Frame {
+    /// フレーム番号から作成
+    pub const fn from_number(number: u64) -> Self {
+        Self(number)
+    }
+
+    /// 物理アドレス `addr` を含むフレーム
+    pub const fn containing_address(addr: u64) -> Self {
+        Self(addr / FRAME_SIZE)
+    }
+
+    /// フレーム番号（物理アドレス / 4KiB）
+    pub const fn number(self) -> u64 {
+        self.0
+    }
+
+    /// 先頭の物理アドレス
+    pub const fn start_address(self) -> u64 {
+        self.0 * FRAME_SIZE
+    }
+}
+
+/// カーネル全体で使う物理フレームアロケータ
+pub static FRAME_ALLOCATOR: SpinLock<FrameAllocator<BITMAP_WORDS>> = SpinLock::new(FrameAllocator::new());
+
+/// 1フレーム確保する
+pub fn alloc_frame() -> Option<Frame> {
+    FRAME_ALLOCATOR.lock().alloc_frame()
+}
+
+/// 1フレーム解放する
+pub fn free_frame(frame: Frame) -> Result<(), FreeError> {
+    FRAME_ALLOCATOR.lock().free_frame(frame)
+}
+
+/// 2^order 個の連続したフレームを確保する（DMAバッファなど）
+pub fn alloc_frames(order: usize) -> Option<Frame> {
+    FRAME_ALLOCATOR.lock().alloc(order)
+}
+
+/// `alloc_frames(order)` で確保したフレームを解放する
+pub fn free_frames(frame: Frame, order: usize) -> Result<(), FreeError> {
+    FRAME_ALLOCATOR.lock().free(frame, order)
+}
+
+/// ブートローダーのメモリマップからフレームアロケータを作る
+///
+/// メモリマップで使用可能な領域だけを使い、カーネルイメージとブートモジュール（initrdなど）は
+/// メモリマップの記載にかかわらず除く。追加したフレーム数を返す。
+#[cfg(any(not(test), target_os = "none"))]
+pub fn init(boot_info: &boot_info::BootInfo) -> usize {
+    let kernel = core::iter::once((boot_info.kernel_addr, boot_info.kernel_addr + boot_info.kernel_size));
+    let modules = boot_info.modules().iter().map(|module| (module.start, module.end));
+    FRAME_ALLOCATOR.lock().init(boot_info.memory_regions(), kernel.chain(modules))
+}
+
+// ===== テスト =====
+#[cfg(all(test, not(target_os = "none")))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_frame_address() {
+        let frame = Frame::containing_address(0x1234_5678);
+        assert_eq!(frame.number(), 0x12345);
+        assert_eq!(frame.start_address(), 0x1234_5000, "4KiB境界に切り捨てる");
+        assert_eq!(Frame::from_number(0x12345), frame);
+    }
+}
+
+#[cfg(all(test, target_os = "none"))]
+mod kernel_tests {
+    use super::*;
+
+    #[test_case]
+    fn test_alloc_and_write_frame() {
+        let free = FRAME_ALLOCATOR.lock().free_frames();
+        let frame = alloc_frame().expect("起動後は空きフレームがある");
+        // 物理メモリマップ経由で読み書きできる（カーネルやブートデータと重なっていない）
+        let ptr = (boot_info::PHYSICAL_MEMORY_OFFSET + frame.start_address()) as *mut u64;
+        unsafe {
+            ptr.write_volatile(0x1234_5678_9abc_def0);
+            assert_eq!(ptr.read_volatile(), 0x1234_5678_9abc_def0);
+        }
+        free_frame(frame).unwrap();
+        assert_eq!(FRAME_ALLOCATOR.lock().free_frames(), free);
+        assert_eq!(free_frame(frame), Err(FreeError::NotAllocated(frame)));
+    }
+}
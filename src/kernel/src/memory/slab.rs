@@ -0,0 +1,625 @@
+//! スラブアロケータ
+//!
+//! 同じ型のオブジェクト（プロセス、IPCメッセージ、ページテーブル）を型ごとのキャッシュから確保する。
+//! キャッシュは 2^order フレームの「スラブ」単位で物理フレームアロケータからメモリをもらい、
+//! スラブをオブジェクトの大きさに区切って空きリストでつなぐ。
+//! - 確保と解放は空きリストの先頭を付け替えるだけ（O(1)）
+//! - 大きさがそろっているので断片化しない
+//! - 空きのあるスラブが残っていれば物理フレームアロケータを呼ばないので、`reserve()` で
+//!   前もって用意しておけば割り込みハンドラの中でも時間が読める
+//!
+//! スラブはその大きさの境界にそろっているので、オブジェクトのアドレスの下位ビットを
+//! 落とすだけでスラブの先頭（ヘッダ）がわかる。満杯のスラブはどのリストにもつながず、
+//! 1つ返されたときに空きのあるスラブのリストに戻す。
+//!
+//! ページテーブルのように 2^n フレームちょうどのオブジェクトは、ヘッダを置くと
+//! 1つ分がまるごと無駄になり、連続したフレームも要るので、ヘッダを持たせない。
+//! オブジェクト1つがそのままスラブで、空いたオブジェクトどうしを直接つなぐ。
+//!
+//! Linux: mm/slub.c（/proc/slabinfo）
+//! MINIX 3: なし（プロセスやメッセージは固定長の配列に置いていた）
+
+use core::fmt;
+use core::marker::PhantomData;
+use core::ops::{Deref, DerefMut};
+use core::ptr::{self, NonNull};
+
+use crate::sync::SpinLock;
+
+use super::{Frame, FRAME_SIZE};
+
+/// スラブの大きさの上限（2^3 フレーム = 32KiB）
+const MAX_SLAB_ORDER: usize = 3;
+
+/// 1つのスラブに入れたいオブジェクトの数（上限の大きさまでスラブを大きくする）
+const MIN_OBJECTS: usize = 8;
+
+/// スラブの先頭に置くヘッダ
+struct Slab {
+    /// 空きのあるスラブのリストで次のスラブ
+    next: *mut Slab,
+    /// このスラブの空きオブジェクトのリスト
+    free: *mut FreeObject,
+    /// 確保中のオブジェクト数
+    in_use: usize,
+}
+
+/// 空きオブジェクト（先頭に次の空きオブジェクトを書く）
+struct FreeObject {
+    next: *mut FreeObject,
+}
+
+/// スラブ用のメモリ（2^order フレーム、その大きさの境界にそろったもの）を用意する
+pub trait SlabPages {
+    /// 2^order フレームを確保して、書き込めるアドレスを返す
+    fn alloc(order: usize) -> Option<NonNull<u8>>;
+
+    /// `alloc(order)` で確保したメモリを返す
+    ///
+    /// # Safety
+    /// `ptr` は同じ `order` の `alloc()` で確保し、もう使っていないこと
+    unsafe fn free(ptr: NonNull<u8>, order: usize);
+}
+
+/// 物理フレームアロケータのフレームを物理メモリマップ経由で使う
+pub struct FramePages;
+
+impl SlabPages for FramePages {
+    fn alloc(order: usize) -> Option<NonNull<u8>> {
+        let frame = super::alloc_frames(order)?;
+        NonNull::new((boot_info::PHYSICAL_MEMORY_OFFSET + frame.start_address()) as *mut u8)
+    }
+
+    unsafe fn free(ptr: NonNull<u8>, order: usize) {
+        let frame = Frame::containing_address(ptr.as_ptr() as u64 - boot_info::PHYSICAL_MEMORY_OFFSET);
+        if let Err(error) = super::free_frames(frame, order) {
+            log::error!("slab: {}", error);
+        }
+    }
+}
+
+/// 標準ライブラリのアロケータからスラブの大きさにそろえて確保する（ホストのテスト用）
+#[cfg(all(test, not(target_os = "none")))]
+pub struct HostPages;
+
+#[cfg(all(test, not(target_os = "none")))]
+impl HostPages {
+    fn layout(order: usize) -> std::alloc::Layout {
+        let bytes = (FRAME_SIZE as usize) << order;
+        std::alloc::Layout::from_size_align(bytes, bytes).unwrap()
+    }
+}
+
+#[cfg(all(test, not(target_os = "none")))]
+impl SlabPages for HostPages {
+    fn alloc(order: usize) -> Option<NonNull<u8>> {
+        NonNull::new(unsafe { std::alloc::alloc_zeroed(Self::layout(order)) })
+    }
+
+    unsafe fn free(ptr: NonNull<u8>, order: usize) {
+        unsafe { std::alloc::dealloc(ptr.as_ptr(), Self::layout(order)) };
+    }
+}
+
+/// カーネルのキャッシュが使うメモリ（ホストのテストでは物理フレームアロケータがないのでヒープ）
+#[cfg(any(not(test), target_os = "none"))]
+pub type KernelPages = FramePages;
+#[cfg(all(test, not(target_os = "none")))]
+pub type KernelPages = HostPages;
+
+/// キャッシュの統計（Linuxの /proc/slabinfo の1行）
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CacheStats {
+    pub name: &'static str,
+    /// オブジェクト1つの大きさ（そろえたあとの値）
+    pub object_size: usize,
+    /// スラブ1つのオブジェクト数
+    pub objects_per_slab: usize,
+    /// スラブの数
+    pub slabs: usize,
+    /// 確保中のオブジェクト数
+    pub in_use: usize,
+    /// 空きオブジェクト数
+    pub free: usize,
+}
+
+impl CacheStats {
+    /// `Display` の列見出し
+    pub const HEADER: &'static str = "NAME           SIZE /SLAB  SLABS   IN USE     FREE";
+}
+
+impl fmt::Display for CacheStats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{:<12} {:>6} {:>5} {:>6} {:>8} {:>8}",
+            self.name, self.object_size, self.objects_per_slab, self.slabs, self.in_use, self.free
+        )
+    }
+}
+
+/// 型を持たないスラブキャッシュ（大きさとそろえ方だけを知っている）
+pub struct SlabCache {
+    name: &'static str,
+    /// オブジェクトの間隔
+    size: usize,
+    /// スラブは 2^order フレーム
+    order: usize,
+    /// スラブの先頭から最初のオブジェクトまで（ヘッダの分）
+    offset: usize,
+    /// スラブ1つのオブジェクト数
+    per_slab: usize,
+    /// 空きのあるスラブのリスト
+    partial: *mut Slab,
+    /// オブジェクト1つがスラブ全体か（ヘッダを持たず、空きオブジェクトを `pages` につなぐ）
+    whole_pages: bool,
+    /// 空いているスラブ（`whole_pages` のときだけ使う）
+    pages: *mut FreeObject,
+    slabs: usize,
+    in_use: usize,
+}
+
+// 安全性: スラブはキャッシュが持つメモリで、ロック（SpinLock）の中でだけ触る
+unsafe impl Send for SlabCache {}
+
+impl SlabCache {
+    /// `size` バイトで `align` にそろったオブジェクトのキャッシュを作成
+    ///
+    /// スラブに `MIN_OBJECTS` 個入る大きさ（最大 2^MAX_SLAB_ORDER フレーム）を選ぶ。
+    /// 2^n フレームちょうどのオブジェクトは、ヘッダなしでオブジェクト1つを1つのスラブにする。
+    /// 上限のスラブに1つも入らない大きさは、コンパイル時（静的変数の初期化）にエラーになる。
+    pub const fn new(name: &'static str, size: usize, align: usize) -> Self {
+        let align = max(align, core::mem::align_of::<Slab>());
+        let size = max(size, core::mem::size_of::<FreeObject>()).next_multiple_of(align);
+        let frame = FRAME_SIZE as usize;
+        let whole_pages = size >= frame && size.is_power_of_two();
+        let (order, offset) = if whole_pages {
+            ((size / frame).trailing_zeros() as usize, 0)
+        } else {
+            let offset = core::mem::size_of::<Slab>().next_multiple_of(align);
+            let mut order = 0;
+            while order < MAX_SLAB_ORDER && slab_objects(order, offset, size) < MIN_OBJECTS {
+                order += 1;
+            }
+            (order, offset)
+        };
+        let per_slab = slab_objects(order, offset, size);
+        assert!(order <= MAX_SLAB_ORDER && per_slab > 0, "object too large for a slab");
+        Self {
+            name,
+            size,
+            order,
+            offset,
+            per_slab,
+            partial: ptr::null_mut(),
+            whole_pages,
+            pages: ptr::null_mut(),
+            slabs: 0,
+            in_use: 0,
+        }
+    }
+
+    /// オブジェクトを1つ確保する（空きのあるスラブがなければ、スラブを1つ足す）
+    pub fn alloc<P: SlabPages>(&mut self) -> Option<NonNull<u8>> {
+        if self.whole_pages {
+            if self.pages.is_null() {
+                self.grow::<P>()?;
+            }
+            let object = self.pages;
+            self.pages = unsafe { (*object).next };
+            self.in_use += 1;
+            return NonNull::new(object as *mut u8);
+        }
+        if self.partial.is_null() {
+            self.grow::<P>()?;
+        }
+        let slab = self.partial;
+        unsafe {
+            let object = (*slab).free;
+            (*slab).free = (*object).next;
+            (*slab).in_use += 1;
+            // 満杯になったスラブはリストから外す
+            if (*slab).free.is_null() {
+                self.partial = (*slab).next;
+                (*slab).next = ptr::null_mut();
+            }
+            self.in_use += 1;
+            NonNull::new(object as *mut u8)
+        }
+    }
+
+    /// `alloc()` で確保したオブジェクトを返す
+    ///
+    /// # Safety
+    /// `ptr` はこのキャッシュから確保し、まだ返していないこと
+    pub unsafe fn free(&mut self, ptr: NonNull<u8>) {
+        if self.whole_pages {
+            let object = ptr.as_ptr() as *mut FreeObject;
+            unsafe { object.write(FreeObject { next: self.pages }) };
+            self.pages = object;
+            self.in_use -= 1;
+            return;
+        }
+        let slab = (ptr.as_ptr() as usize & !(self.slab_bytes() - 1)) as *mut Slab;
+        let object = ptr.as_ptr() as *mut FreeObject;
+        unsafe {
+            let was_full = (*slab).free.is_null();
+            object.write(FreeObject { next: (*slab).free });
+            (*slab).free = object;
+            (*slab).in_use -= 1;
+            if was_full {
+                (*slab).next = self.partial;
+                self.partial = slab;
+            }
+        }
+        self.in_use -= 1;
+    }
+
+    /// 空きオブジェクトが `objects` 個以上になるまでスラブを足す（足りなければfalse）
+    pub fn reserve<P: SlabPages>(&mut self, objects: usize) -> bool {
+        while self.free_objects() < objects {
+            if self.grow::<P>().is_none() {
+                return false;
+            }
+        }
+        true
+    }
+
+    /// 全部空いているスラブを返す。返したスラブの数を返す
+    pub fn shrink<P: SlabPages>(&mut self) -> usize {
+        let mut released = 0;
+        while let Some(page) = NonNull::new(self.pages) {
+            self.pages = unsafe { (*self.pages).next };
+            unsafe { P::free(page.cast(), self.order) };
+            self.slabs -= 1;
+            released += 1;
+        }
+        let mut prev: *mut Slab = ptr::null_mut();
+        let mut current = self.partial;
+        while !current.is_null() {
+            let next = unsafe { (*current).next };
+            if unsafe { (*current).in_use } == 0 {
+                if prev.is_null() {
+                    self.partial = next;
+                } else {
+                    unsafe { (*prev).next = next };
+                }
+                unsafe { P::free(NonNull::new_unchecked(current as *mut u8), self.order) };
+                self.slabs -= 1;
+                released += 1;
+            } else {
+                prev = current;
+            }
+            current = next;
+        }
+        released
+    }
+
+    /// 統計
+    pub fn stats(&self) -> CacheStats {
+        CacheStats {
+            name: self.name,
+            object_size: self.size,
+            objects_per_slab: self.per_slab,
+            slabs: self.slabs,
+            in_use: self.in_use,
+            free: self.free_objects(),
+        }
+    }
+
+    fn free_objects(&self) -> usize {
+        self.slabs * self.per_slab - self.in_use
+    }
+
+    fn slab_bytes(&self) -> usize {
+        (FRAME_SIZE as usize) << self.order
+    }
+
+    /// スラブを1つ確保し、オブジェクトを空きリストにつないで、空きのあるスラブのリストに入れる
+    fn grow<P: SlabPages>(&mut self) -> Option<()> {
+        let base = P::alloc(self.order)?.as_ptr();
+        debug_assert_eq!(base as usize % self.slab_bytes(), 0, "スラブはその大きさにそろえる");
+        if self.whole_pages {
+            let object = base as *mut FreeObject;
+            unsafe { object.write(FreeObject { next: self.pages }) };
+            self.pages = object;
+            self.slabs += 1;
+            return Some(());
+        }
+        let slab = base as *mut Slab;
+        unsafe {
+            // 後ろのオブジェクトから順に先頭へつなぐ（確保はアドレスの小さい順になる）
+            let mut free: *mut FreeObject = ptr::null_mut();
+            for i in (0..self.per_slab).rev() {
+                let object = base.add(self.offset + i * self.size) as *mut FreeObject;
+                object.write(FreeObject { next: free });
+                free = object;
+            }
+            slab.write(Slab { next: self.partial, free, in_use: 0 });
+        }
+        self.partial = slab;
+        self.slabs += 1;
+        Some(())
+    }
+}
+
+/// 2^order フレームのスラブに入るオブジェクトの数
+const fn slab_objects(order: usize, offset: usize, size: usize) -> usize {
+    ((FRAME_SIZE as usize) << order).saturating_sub(offset) / size
+}
+
+const fn max(a: usize, b: usize) -> usize {
+    if a > b {
+        a
+    } else {
+        b
+    }
+}
+
+/// 型 `T` のオブジェクトのキャッシュ（静的変数に置いて使う）
+///
+/// Linux: kmem_cache_create()
+pub struct ObjectCache<T, P: SlabPages = FramePages> {
+    cache: SpinLock<SlabCache>,
+    _marker: PhantomData<fn() -> (T, P)>,
+}
+
+impl<T, P: SlabPages> ObjectCache<T, P> {
+    /// キャッシュを作成（`name` は統計の表示に使う）
+    pub const fn new(name: &'static str) -> Self {
+        Self {
+            cache: SpinLock::new(SlabCache::new(name, core::mem::size_of::<T>(), core::mem::align_of::<T>())),
+            _marker: PhantomData,
+        }
+    }
+
+    /// `value` を置いたオブジェクトを確保する
+    /// Linux: kmem_cache_alloc()
+    pub fn alloc(&'static self, value: T) -> Option<SlabBox<T, P>> {
+        let ptr = self.alloc_uninit()?;
+        unsafe { ptr.write(value) };
+        Some(SlabBox { ptr, cache: self })
+    }
+
+    /// 初期化していないオブジェクトを確保する（`free_uninit()` で返す）
+    pub fn alloc_uninit(&self) -> Option<NonNull<T>> {
+        self.cache.lock().alloc::<P>().map(NonNull::cast)
+    }
+
+    /// `alloc_uninit()` で確保したオブジェクトを返す（中身のdropはしない）
+    ///
+    /// # Safety
+    /// `ptr` はこのキャッシュから確保し、まだ返していないこと
+    pub unsafe fn free_uninit(&self, ptr: NonNull<T>) {
+        unsafe { self.cache.lock().free(ptr.cast()) };
+    }
+
+    /// 空きオブジェクトが `objects` 個以上になるまでスラブを足す
+    pub fn reserve(&self, objects: usize) -> bool {
+        self.cache.lock().reserve::<P>(objects)
+    }
+
+    /// 全部空いているスラブを物理フレームアロケータに返す
+    pub fn shrink(&self) -> usize {
+        self.cache.lock().shrink::<P>()
+    }
+
+    /// 統計
+    pub fn stats(&self) -> CacheStats {
+        self.cache.lock().stats()
+    }
+}
+
+/// `ObjectCache` から確保したオブジェクト（dropするとキャッシュに返す）
+pub struct SlabBox<T: 'static, P: SlabPages + 'static = FramePages> {
+    ptr: NonNull<T>,
+    cache: &'static ObjectCache<T, P>,
+}
+
+// 安全性: SlabBox は中身を1人で持つ（Box<T> と同じ）
+unsafe impl<T: Send, P: SlabPages> Send for SlabBox<T, P> {}
+unsafe impl<T: Sync, P: SlabPages> Sync for SlabBox<T, P> {}
+
+impl<T, P: SlabPages> Deref for SlabBox<T, P> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        unsafe { self.ptr.as_ref() }
+    }
+}
+
+impl<T, P: SlabPages> DerefMut for SlabBox<T, P> {
+    fn deref_mut(&mut self) -> &mut T {
+        unsafe { self.ptr.as_mut() }
+    }
+}
+
+impl<T: fmt::Debug, P: SlabPages> fmt::Debug for SlabBox<T, P> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(&**self, f)
+    }
+}
+
+impl<T, P: SlabPages> Drop for SlabBox<T, P> {
+    fn drop(&mut self) {
+        unsafe {
+            ptr::drop_in_place(self.ptr.as_ptr());
+            self.cache.free_uninit(self.ptr);
+        }
+    }
+}
+
+// ===== テスト =====
+#[cfg(all(test, not(target_os = "none")))]
+mod tests {
+    use super::*;
+
+    /// スラブを1つも渡さない
+    struct NoPages;
+
+    impl SlabPages for NoPages {
+        fn alloc(_order: usize) -> Option<NonNull<u8>> {
+            None
+        }
+
+        unsafe fn free(_ptr: NonNull<u8>, _order: usize) {}
+    }
+
+    mod layout_tests {
+        use super::*;
+
+        #[test]
+        fn test_slab_layout() {
+            let small = SlabCache::new("small", 64, 8).stats();
+            assert_eq!((small.object_size, small.objects_per_slab), (64, 63), "ヘッダの分だけ減る");
+
+            let odd = SlabCache::new("odd", 1, 1).stats();
+            assert_eq!(odd.object_size, 8, "空きリストのポインタが入る大きさ");
+
+            let page = SlabCache::new("page", 4096, 4096);
+            assert!(page.whole_pages, "ページ大のオブジェクトはヘッダを持たない");
+            assert_eq!((page.order, page.offset, page.per_slab), (0, 0, 1), "1フレームに1つ");
+
+            let pages = SlabCache::new("pages", 8192, 8);
+            assert_eq!((pages.whole_pages, pages.order), (true, 1), "2フレームなら2フレームのスラブ");
+
+            let medium = SlabCache::new("medium", 1000, 8);
+            assert_eq!((medium.order, medium.per_slab), (1, 8), "8個入るまでスラブを大きくする");
+        }
+
+        #[test]
+        fn test_stats_display() {
+            let stats = CacheStats { name: "message", object_size: 64, objects_per_slab: 63, slabs: 2, in_use: 70, free: 56 };
+            assert_eq!(stats.to_string(), "message          64    63      2       70       56");
+            assert_eq!(CacheStats::HEADER.len(), stats.to_string().len(), "見出しと列がそろう");
+        }
+    }
+
+    mod cache_tests {
+        use super::*;
+
+        #[test]
+        fn test_alloc_fills_slabs_in_order() {
+            let mut cache = SlabCache::new("test", 64, 8);
+            let first = cache.alloc::<HostPages>().unwrap();
+            let second = cache.alloc::<HostPages>().unwrap();
+            assert_eq!(second.as_ptr() as usize - first.as_ptr() as usize, 64, "同じスラブの次のオブジェクト");
+            assert_eq!(first.as_ptr() as usize % 4096, 24, "ヘッダの後ろから");
+
+            let mut objects = vec![first, second];
+            for _ in 2..64 {
+                objects.push(cache.alloc::<HostPages>().unwrap());
+            }
+            let stats = cache.stats();
+            assert_eq!((stats.slabs, stats.in_use, stats.free), (2, 64, 62), "63個で満杯になり2つ目のスラブへ");
+
+            for object in objects {
+                unsafe { cache.free(object) };
+            }
+            assert_eq!((cache.stats().in_use, cache.stats().free), (0, 126));
+            assert_eq!(cache.shrink::<HostPages>(), 2, "空のスラブを返す");
+            assert_eq!(cache.stats().slabs, 0);
+        }
+
+        #[test]
+        fn test_full_slab_returns_to_partial_list() {
+            let mut cache = SlabCache::new("test", 512, 8);
+            let per_slab = cache.stats().objects_per_slab;
+            assert_eq!(per_slab, 15, "8個入るように2フレームのスラブになる");
+            let objects: Vec<_> = (0..per_slab).map(|_| cache.alloc::<HostPages>().unwrap()).collect();
+            assert!(cache.partial.is_null(), "満杯のスラブはリストにない");
+
+            unsafe { cache.free(objects[3]) };
+            assert_eq!(cache.alloc::<NoPages>(), Some(objects[3]), "返したオブジェクトを新しいスラブなしで使い直す");
+            assert_eq!(cache.alloc::<NoPages>(), None, "空きがなくスラブももらえない");
+            assert_eq!(cache.stats().slabs, 1);
+            for object in objects {
+                unsafe { cache.free(object) };
+            }
+            cache.shrink::<HostPages>();
+        }
+
+        #[test]
+        fn test_reserve() {
+            let mut cache = SlabCache::new("test", 256, 8);
+            assert!(cache.reserve::<HostPages>(20));
+            let stats = cache.stats();
+            assert_eq!((stats.slabs, stats.free), (2, 30), "15個入りのスラブを2つ");
+            assert!(!cache.reserve::<NoPages>(31));
+            // 用意した分はスラブを足さずに確保できる
+            let objects: Vec<_> = (0..30).map(|_| cache.alloc::<NoPages>().unwrap()).collect();
+            for object in objects {
+                unsafe { cache.free(object) };
+            }
+            assert_eq!(cache.shrink::<HostPages>(), 2);
+        }
+
+        #[test]
+        fn test_whole_page_objects() {
+            let mut cache = SlabCache::new("page", 4096, 4096);
+            let first = cache.alloc::<HostPages>().unwrap();
+            let second = cache.alloc::<HostPages>().unwrap();
+            assert_eq!(first.as_ptr() as usize % 4096, 0, "フレームの先頭から");
+            assert_eq!(cache.stats().slabs, 2, "1つずつフレームをもらう");
+
+            unsafe { cache.free(first) };
+            assert_eq!(cache.alloc::<NoPages>(), Some(first), "返したフレームを使い直す");
+            assert_eq!(cache.alloc::<NoPages>(), None);
+
+            assert!(cache.reserve::<HostPages>(3));
+            let stats = cache.stats();
+            assert_eq!((stats.slabs, stats.in_use, stats.free), (5, 2, 3));
+            unsafe {
+                cache.free(first);
+                cache.free(second);
+            }
+            assert_eq!(cache.shrink::<HostPages>(), 5, "空いたフレームをすべて返す");
+            assert_eq!(cache.stats().slabs, 0);
+        }
+
+        #[test]
+        fn test_shrink_keeps_used_slabs() {
+            let mut cache = SlabCache::new("test", 2048, 8);
+            let a = cache.alloc::<HostPages>().unwrap();
+            let objects: Vec<_> = (0..20).map(|_| cache.alloc::<HostPages>().unwrap()).collect();
+            for object in objects {
+                unsafe { cache.free(object) };
+            }
+            let slabs = cache.stats().slabs;
+            assert_eq!(cache.shrink::<HostPages>(), slabs - 1, "使用中のオブジェクトがあるスラブは残す");
+            unsafe { cache.free(a) };
+            assert_eq!(cache.shrink::<HostPages>(), 1);
+        }
+    }
+
+    mod object_cache_tests {
+        use super::*;
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        static DROPPED: AtomicUsize = AtomicUsize::new(0);
+
+        struct Tracked([u64; 6]);
+
+        impl Drop for Tracked {
+            fn drop(&mut self) {
+                DROPPED.fetch_add(1, Ordering::SeqCst);
+            }
+        }
+
+        static CACHE: ObjectCache<Tracked, HostPages> = ObjectCache::new("tracked");
+
+        #[test]
+        fn test_slab_box() {
+            let mut boxes: Vec<_> = (0..100u64).map(|i| CACHE.alloc(Tracked([i; 6])).unwrap()).collect();
+            boxes[5].0[0] = 500;
+            assert_eq!(CACHE.stats().in_use, 100);
+            assert!(boxes.iter().enumerate().all(|(i, b)| b.0[1] == i as u64), "ほかのオブジェクトと重ならない");
+            assert_eq!(boxes[5].0[0], 500);
+
+            drop(boxes);
+            assert_eq!(DROPPED.load(Ordering::SeqCst), 100, "dropで中身もdropする");
+            assert_eq!(CACHE.stats().in_use, 0, "dropでキャッシュに返す");
+            assert!(CACHE.shrink() > 0);
+        }
+    }
+}
@@ -0,0 +1,680 @@
+//! プロセスのアドレス空間
+//!
+//! ユーザープロセスはそれぞれ自分の最上位のページテーブル（x86_64ならPML4）を持つ。
+//! 上半分（カーネルの領域）のエントリは起動時のページテーブルからコピーして共有し、
+//! 下半分にプロセスの領域（テキスト、データ、スタックなど）をマップする。
+//! コンテキストスイッチでは最上位のページテーブルを切り替える（CR3に書く）。
+//!
+//! カーネルタスクは自分のページテーブルを持たず、起動時のページテーブルのまま動く。
+//!
+//! `fork()` はフレームをコピーせず、親と子の両方に書き込み禁止でマップして共有する（コピーオンライト）。
+//! どちらかが書き込むとページフォルトになり、`copy_on_write()` がそのページだけをコピーする。
+//!
+//! MINIX 3: kernel の p_seg.p_cr3（切り替えはカーネル、作るのはVMサーバー）と、
+//!          servers/vm の struct vmproc（vm_pt と領域の一覧 vm_regions_avl）
+//! Linux: struct mm_struct（pgd と vm_area_struct の一覧）
+
+use core::fmt;
+use core::sync::atomic::{AtomicPtr, AtomicU16, AtomicUsize, Ordering};
+
+use crate::arch::MapFlags;
+
+use super::{Frame, FRAME_SIZE};
+
+/// 1つのプロセスが持てる領域の数
+/// MINIX 3の古い版は text/data/stack の3つ（NR_LOCAL_SEGS）だった
+pub const MAX_REGIONS: usize = 8;
+
+/// ユーザー空間の上限（下半分、x86_64の正規形アドレスの境目）
+pub const USER_END: u64 = 0x0000_8000_0000_0000;
+
+/// アドレス空間の中の連続した領域（ページ境界にそろえる）
+/// Linux: struct vm_area_struct
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Region {
+    /// 先頭の仮想アドレス
+    pub start: u64,
+    /// 末尾の次の仮想アドレス
+    pub end: u64,
+    /// ページに付ける属性
+    pub flags: MapFlags,
+}
+
+impl Region {
+    pub const fn new(start: u64, end: u64, flags: MapFlags) -> Self {
+        Self { start, end, flags }
+    }
+
+    /// 大きさ（バイト）
+    pub fn size(&self) -> u64 {
+        self.end - self.start
+    }
+
+    /// `addr` を含むか
+    pub fn contains(&self, addr: u64) -> bool {
+        (self.start..self.end).contains(&addr)
+    }
+
+    fn overlaps(&self, other: &Region) -> bool {
+        self.start < other.end && other.start < self.end
+    }
+}
+
+/// 領域の追加に失敗した理由
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RegionError {
+    /// ページ境界にそろっていない、空、またはユーザー空間の外
+    Invalid(Region),
+    /// ほかの領域と重なる
+    Overlap(Region),
+    /// MAX_REGIONS 個を超える
+    TooMany,
+}
+
+impl fmt::Display for RegionError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Invalid(region) => write!(f, "invalid region {:#x}-{:#x}", region.start, region.end),
+            Self::Overlap(region) => write!(f, "region {:#x}-{:#x} overlaps another", region.start, region.end),
+            Self::TooMany => write!(f, "too many regions (max {})", MAX_REGIONS),
+        }
+    }
+}
+
+/// プロセスのアドレス空間（最上位のページテーブルと領域の一覧）
+///
+/// `root` が None ならカーネルのページテーブルを使う（カーネルタスク）。
+/// ページテーブルを作るのも壊すのも `create()` / `destroy()` で明示的に行う
+/// （プロセステーブルのスロットは上書きで使い回すので、dropでは解放しない）。
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MemoryMap {
+    root: Option<Frame>,
+    regions: [Option<Region>; MAX_REGIONS],
+}
+
+impl MemoryMap {
+    /// カーネルのページテーブルを使う、領域のないアドレス空間
+    pub const fn new() -> Self {
+        Self { root: None, regions: [None; MAX_REGIONS] }
+    }
+
+    /// 最上位のページテーブルが `root` にあるアドレス空間
+    pub const fn with_root(root: Frame) -> Self {
+        Self { root: Some(root), regions: [None; MAX_REGIONS] }
+    }
+
+    /// 最上位のページテーブル（カーネルのものを使うならNone）
+    pub fn root(&self) -> Option<Frame> {
+        self.root
+    }
+
+    /// 領域を追加する（ページテーブルへのマップは呼び出し側で行う）
+    pub fn add_region(&mut self, region: Region) -> Result<(), RegionError> {
+        let aligned = region.start.is_multiple_of(FRAME_SIZE) && region.end.is_multiple_of(FRAME_SIZE);
+        if !aligned || region.start >= region.end || region.end > USER_END {
+            return Err(RegionError::Invalid(region));
+        }
+        if self.regions().any(|r| r.overlaps(&region)) {
+            return Err(RegionError::Overlap(region));
+        }
+        let slot = self.regions.iter_mut().find(|r| r.is_none()).ok_or(RegionError::TooMany)?;
+        *slot = Some(region);
+        Ok(())
+    }
+
+    /// `start` から始まる領域を一覧から外して返す
+    pub fn remove_region(&mut self, start: u64) -> Option<Region> {
+        self.regions.iter_mut().find(|r| r.is_some_and(|r| r.start == start))?.take()
+    }
+
+    /// `addr` を含む領域（ページフォルトの処理で使う）
+    pub fn find(&self, addr: u64) -> Option<&Region> {
+        self.regions().find(|r| r.contains(addr))
+    }
+
+    /// `addr` から `len` バイトがすべて領域の中にあり、どの領域にも `flags` が付いているか
+    ///
+    /// システムコールでユーザーが渡したポインタを使う前に確かめる。隣り合う領域にまたがってもよい。
+    /// MINIX 3: umap_local() / vm_check_range()
+    pub fn contains_range(&self, addr: u64, len: u64, flags: u8) -> bool {
+        let Some(end) = addr.checked_add(len) else {
+            return false;
+        };
+        let mut next = addr;
+        while next < end {
+            match self.find(next) {
+                Some(region) if region.flags.contains(flags) => next = region.end,
+                _ => return false,
+            }
+        }
+        true
+    }
+
+    /// すべての領域（追加した順とは限らない）
+    pub fn regions(&self) -> impl Iterator<Item = &Region> {
+        self.regions.iter().flatten()
+    }
+}
+
+impl Default for MemoryMap {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// 2つ以上のアドレス空間にマップしているフレームの参照数（コピーオンライトで使う）
+///
+/// フレーム番号で引く配列で、起動時に物理メモリの大きさに合わせて一度だけ確保する。
+/// ページフォルトの中ではロックもヒープも使わない。値はほかに参照しているアドレス空間の数なので、
+/// forkしないプロセスのフレームは0のまま（参照数は1）。
+/// MINIX 3: servers/vm の struct phys_block の refcount
+/// Linux: struct page の _mapcount
+#[derive(Debug, Default)]
+pub struct FrameRefs {
+    /// フレーム番号 → ほかに参照しているアドレス空間の数（`init()` までは空）
+    counts: AtomicPtr<AtomicU16>,
+    /// `counts` の長さ
+    len: AtomicUsize,
+}
+
+impl FrameRefs {
+    pub const fn new() -> Self {
+        Self { counts: AtomicPtr::new(core::ptr::null_mut()), len: AtomicUsize::new(0) }
+    }
+
+    /// フレーム番号が `counts.len()` より小さいフレームを数えられるようにする（起動時に一度呼ぶ）
+    pub fn init(&self, counts: &'static [AtomicU16]) {
+        self.counts.store(counts.as_ptr().cast_mut(), Ordering::Relaxed);
+        self.len.store(counts.len(), Ordering::Release);
+    }
+
+    /// `frame` の参照数（`init()` で渡した範囲の外ならNone）
+    fn entry(&self, frame: Frame) -> Option<&AtomicU16> {
+        let len = self.len.load(Ordering::Acquire);
+        let counts = self.counts.load(Ordering::Relaxed);
+        if counts.is_null() {
+            return None;
+        }
+        // 安全性: init() で渡した 'static な配列
+        unsafe { core::slice::from_raw_parts(counts, len) }.get(frame.number() as usize)
+    }
+
+    /// もう1つのアドレス空間から `frame` を参照する（範囲外か、数えきれなければfalse）
+    pub fn share(&self, frame: Frame) -> bool {
+        self.entry(frame)
+            .is_some_and(|count| count.fetch_update(Ordering::Relaxed, Ordering::Relaxed, |n| n.checked_add(1)).is_ok())
+    }
+
+    /// `frame` をマップしているアドレス空間の数
+    pub fn count(&self, frame: Frame) -> u32 {
+        self.entry(frame).map_or(1, |count| u32::from(count.load(Ordering::Relaxed)) + 1)
+    }
+
+    /// `frame` の参照を1つ減らす。最後の参照だったらtrue（呼び出し側がフレームを解放する）
+    pub fn release(&self, frame: Frame) -> bool {
+        self.entry(frame)
+            .is_none_or(|count| count.fetch_update(Ordering::Relaxed, Ordering::Relaxed, |n| n.checked_sub(1)).is_err())
+    }
+
+    /// 共有しているフレームの数
+    pub fn shared_frames(&self) -> usize {
+        (0..self.len.load(Ordering::Acquire) as u64).filter(|&n| self.count(Frame::from_number(n)) > 1).count()
+    }
+}
+
+/// 全アドレス空間で共有するフレームの参照数
+#[cfg(any(not(test), target_os = "none"))]
+pub static FRAME_REFS: FrameRefs = FrameRefs::new();
+
+/// カーネルの半分だけをマップした、新しいアドレス空間を作る
+#[cfg(any(not(test), target_os = "none"))]
+pub fn create() -> Result<MemoryMap, crate::arch::MapError> {
+    use crate::arch::paging::{KernelFrames, Mapper};
+
+    let mapper = Mapper::with_kernel_half(KernelFrames, kernel_root())?;
+    Ok(MemoryMap::with_root(mapper.pml4()))
+}
+
+/// 領域を追加し、ゼロで埋めたフレームを確保してすべてのページをマップする
+///
+/// 途中でフレームが足りなくなったら、それまでにマップしたページは `destroy()` で返る。
+/// MINIX 3: servers/vm/region.c の map_page_region()
+#[cfg(any(not(test), target_os = "none"))]
+pub fn allocate(map: &mut MemoryMap, region: Region) -> Result<(), crate::arch::MapError> {
+    use crate::arch::paging::{KernelFrames, Mapper};
+    use crate::arch::{AddressSpace, MapError, PageSize};
+
+    let root = map.root.ok_or(MapError::NotMapped)?;
+    map.add_region(region).map_err(|_| MapError::AlreadyMapped)?;
+    let mut mapper = Mapper::new(root, KernelFrames);
+    for page in (region.start..region.end).step_by(FRAME_SIZE as usize) {
+        let frame = super::alloc_frame().ok_or(MapError::OutOfFrames)?;
+        // 前に使っていた内容をほかのプロセスに見せない
+        unsafe { core::ptr::write_bytes(physical(frame.start_address()), 0, FRAME_SIZE as usize) };
+        if let Err(error) = mapper.map(page, frame, PageSize::Size4K, region.flags) {
+            let _ = super::free_frame(frame);
+            return Err(error);
+        }
+    }
+    Ok(())
+}
+
+/// `parent` の領域をすべて共有する、新しいアドレス空間を作る
+///
+/// フレームはコピーせず、子には書き込み禁止でマップして参照数を増やす。
+/// 親の書ける領域も書き込み禁止にする（`copy_on_write()` で書けるように戻す）。
+/// 途中で失敗したら子のアドレス空間を壊して参照数を戻し、親のページも書けるように戻す。
+/// MINIX 3: servers/vm/fork.c の do_fork() → map_proc_copy()
+/// Linux: dup_mm() → copy_page_range()
+#[cfg(any(not(test), target_os = "none"))]
+pub fn fork(parent: &MemoryMap) -> Result<MemoryMap, crate::arch::MapError> {
+    use crate::arch::MapError;
+
+    let parent_root = parent.root.ok_or(MapError::NotMapped)?;
+    let mut child = create()?;
+    if let Err(error) = share_regions(parent_root, parent, &mut child) {
+        // 安全性: 作ったばかりの子のページテーブルはどこでも使っていない
+        unsafe { destroy(&mut child) };
+        restore_writable(parent_root, parent);
+        return Err(error);
+    }
+    Ok(child)
+}
+
+/// `parent` の領域とそのページを `child` にコピーする（フレームは共有する）
+#[cfg(any(not(test), target_os = "none"))]
+fn share_regions(parent_root: Frame, parent: &MemoryMap, child: &mut MemoryMap) -> Result<(), crate::arch::MapError> {
+    use crate::arch::{AddressSpace, MapError, PageSize};
+
+    let mut parent_mapper = mapper(parent_root);
+    let mut child_mapper = mapper(child.root.ok_or(MapError::NotMapped)?);
+    for region in parent.regions() {
+        child.add_region(*region).map_err(|_| MapError::AlreadyMapped)?;
+        let read_only = region.flags.without(MapFlags::WRITABLE);
+        for page in (region.start..region.end).step_by(FRAME_SIZE as usize) {
+            // allocate() が途中で失敗した領域は、後ろがマップされていない
+            let Some(phys) = parent_mapper.translate(page) else {
+                continue;
+            };
+            // 子にマップしたページの参照は、失敗したときに destroy() が減らす
+            let frame = Frame::containing_address(phys);
+            if !FRAME_REFS.share(frame) {
+                return Err(MapError::OutOfFrames);
+            }
+            if let Err(error) = child_mapper.map(page, frame, PageSize::Size4K, read_only) {
+                FRAME_REFS.release(frame);
+                return Err(error);
+            }
+            if region.flags.is_set(MapFlags::WRITABLE) {
+                parent_mapper.protect(page, read_only)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// forkに失敗したときに、もう共有していない親の書ける領域のページを書けるように戻す
+#[cfg(any(not(test), target_os = "none"))]
+fn restore_writable(parent_root: Frame, parent: &MemoryMap) {
+    use crate::arch::AddressSpace;
+
+    let mut mapper = mapper(parent_root);
+    for region in parent.regions().filter(|region| region.flags.is_set(MapFlags::WRITABLE)) {
+        for page in (region.start..region.end).step_by(FRAME_SIZE as usize) {
+            let Some(phys) = mapper.translate(page) else {
+                continue;
+            };
+            if FRAME_REFS.count(Frame::containing_address(phys)) == 1 {
+                let _ = mapper.protect(page, region.flags);
+            }
+        }
+    }
+}
+
+/// 書き込みでページフォルトになった `addr` が、コピーオンライトのページなら書けるようにする
+///
+/// ほかのアドレス空間と共有しているフレームならコピーしてから、最後の1つなら属性を戻すだけ。
+/// 直せたらtrue（フォルトした命令をもう一度実行すればよい）。書けない領域への書き込みならfalse。
+/// MINIX 3: servers/vm/pagefaults.c の do_pagefaults() → map_pf()
+/// Linux: do_wp_page()
+#[cfg(any(not(test), target_os = "none"))]
+pub fn copy_on_write(map: &MemoryMap, addr: u64) -> bool {
+    use crate::arch::paging::PageTableEntry;
+    use crate::arch::PageSize;
+
+    let (Some(root), Some(region)) = (map.root, map.find(addr)) else {
+        return false;
+    };
+    if !region.flags.is_set(MapFlags::WRITABLE) {
+        return false;
+    }
+    let page = addr & !(FRAME_SIZE - 1);
+    let mut mapper = mapper(root);
+    // もう書けるページでのフォルトをtrueにすると、同じフォルトを繰り返す
+    match mapper.lookup(page) {
+        Some((entry, PageSize::Size4K)) if !entry.is_set(PageTableEntry::WRITABLE) => {}
+        _ => return false,
+    }
+    unshare(&mut mapper, page, region.flags).is_ok()
+}
+
+/// `page` を自分だけのフレームにして、属性を `flags` にする
+#[cfg(any(not(test), target_os = "none"))]
+fn unshare(
+    mapper: &mut crate::arch::paging::Mapper<crate::arch::paging::KernelFrames>,
+    page: u64,
+    flags: MapFlags,
+) -> Result<(), crate::arch::MapError> {
+    use crate::arch::{AddressSpace, MapError, PageSize};
+
+    let frame = Frame::containing_address(mapper.translate(page).ok_or(MapError::NotMapped)?);
+    if FRAME_REFS.count(frame) == 1 {
+        return mapper.protect(page, flags);
+    }
+    let copy = super::alloc_frame().ok_or(MapError::OutOfFrames)?;
+    unsafe { core::ptr::copy_nonoverlapping(physical(frame.start_address()), physical(copy.start_address()), FRAME_SIZE as usize) };
+    mapper.unmap(page)?;
+    // 下の段のテーブルは残っているので、同じ場所へのマップは失敗しない
+    mapper.map(page, copy, PageSize::Size4K, flags)?;
+    // コピーしている間にほかの参照がなくなっていれば、元のフレームはもう誰も使わない
+    if FRAME_REFS.release(frame) {
+        let _ = super::free_frame(frame);
+    }
+    Ok(())
+}
+
+/// `root` のページテーブルを操作する（今のCR3なら、書き換えたページをTLBから消す）
+#[cfg(any(not(test), target_os = "none"))]
+fn mapper(root: Frame) -> crate::arch::paging::Mapper<crate::arch::paging::KernelFrames> {
+    use crate::arch::paging::{read_cr3, KernelFrames, Mapper};
+
+    if read_cr3() == root.start_address() {
+        Mapper::active(KernelFrames)
+    } else {
+        Mapper::new(root, KernelFrames)
+    }
+}
+
+/// アドレス空間の `addr` に `bytes` を書く（プログラムを読み込むときなど、今のCR3とは別のアドレス空間でもよい）
+///
+/// ほかのアドレス空間と共有しているページは、書く前にコピーする。
+/// MINIX 3: sys_vircopy()
+#[cfg(any(not(test), target_os = "none"))]
+pub fn write(map: &MemoryMap, addr: u64, bytes: &[u8]) -> Result<(), crate::arch::MapError> {
+    use crate::arch::{AddressSpace, MapError};
+
+    let mut mapper = mapper(map.root.ok_or(MapError::NotMapped)?);
+    let mut done = 0;
+    while done < bytes.len() {
+        let virt = addr + done as u64;
+        let phys = mapper.translate(virt).ok_or(MapError::NotMapped)?;
+        let phys = if FRAME_REFS.count(Frame::containing_address(phys)) > 1 {
+            let region = map.find(virt).ok_or(MapError::NotMapped)?;
+            unshare(&mut mapper, virt & !(FRAME_SIZE - 1), region.flags)?;
+            mapper.translate(virt).ok_or(MapError::NotMapped)?
+        } else {
+            phys
+        };
+        let len = (FRAME_SIZE - virt % FRAME_SIZE).min((bytes.len() - done) as u64) as usize;
+        unsafe { core::ptr::copy_nonoverlapping(bytes[done..].as_ptr(), physical(phys), len) };
+        done += len;
+    }
+    Ok(())
+}
+
+/// 物理アドレスを、物理メモリマップ経由で読み書きできるポインタにする
+#[cfg(any(not(test), target_os = "none"))]
+fn physical(addr: u64) -> *mut u8 {
+    (boot_info::PHYSICAL_MEMORY_OFFSET + addr) as *mut u8
+}
+
+/// 領域にマップしていたフレームとページテーブルを解放する
+///
+/// # Safety
+/// `map` のページテーブルを今使っていない（CR3に入っていない）こと
+#[cfg(any(not(test), target_os = "none"))]
+pub unsafe fn destroy(map: &mut MemoryMap) {
+    use crate::arch::paging::{KernelFrames, Mapper};
+    use crate::arch::AddressSpace;
+
+    if let Some(root) = map.root.take() {
+        let mut mapper = Mapper::new(root, KernelFrames);
+        for region in map.regions() {
+            for page in (region.start..region.end).step_by(FRAME_SIZE as usize) {
+                // allocate() が途中で失敗した領域は、後ろがマップされていない
+                // ほかのアドレス空間と共有しているフレームは、最後の参照がなくなるまで返さない
+                if let Ok((frame, _)) = mapper.unmap(page) {
+                    if FRAME_REFS.release(frame) {
+                        let _ = super::free_frame(frame);
+                    }
+                }
+            }
+        }
+        mapper.destroy();
+    }
+    map.regions = [None; MAX_REGIONS];
+}
+
+/// `map` を `new` に取り替えて、古いアドレス空間を壊す（exec）
+///
+/// 古いページテーブルを今使っていれば（exec を呼んだプロセス自身）、先に新しいほうに切り替える。
+#[cfg(any(not(test), target_os = "none"))]
+pub fn replace(map: &mut MemoryMap, new: MemoryMap) {
+    let mut old = core::mem::replace(map, new);
+    if old.root.is_some_and(|root| root.start_address() == crate::arch::paging::read_cr3()) {
+        activate(map.root);
+    }
+    // 安全性: 古いページテーブルはもうCR3に入っていない
+    unsafe { destroy(&mut old) };
+}
+
+/// 起動時のページテーブル（カーネルタスクとカーネルの半分のコピー元）
+#[cfg(any(not(test), target_os = "none"))]
+static KERNEL_ROOT: core::sync::atomic::AtomicU64 = core::sync::atomic::AtomicU64::new(0);
+
+/// 今のページテーブルをカーネルのページテーブルとして覚え、フレームの参照数の表を作る（ヒープを作った後に一度呼ぶ）
+#[cfg(any(not(test), target_os = "none"))]
+pub fn init() {
+    KERNEL_ROOT.store(crate::arch::paging::read_cr3(), Ordering::Relaxed);
+    // フレームの参照数はページフォルトの中でも使うので、ここで全フレーム分を確保しておく
+    let frames = super::FRAME_ALLOCATOR.lock().frame_limit();
+    let counts: alloc::vec::Vec<AtomicU16> = (0..frames).map(|_| AtomicU16::new(0)).collect();
+    FRAME_REFS.init(counts.leak());
+}
+
+/// カーネルのページテーブル
+#[cfg(any(not(test), target_os = "none"))]
+pub fn kernel_root() -> Frame {
+    Frame::containing_address(KERNEL_ROOT.load(Ordering::Relaxed))
+}
+
+/// `root` のページテーブルに切り替える（Noneならカーネルのページテーブル）
+///
+/// コンテキストスイッチで呼ぶ。同じページテーブルのままならCR3に書かない（TLBを消さない）。
+#[cfg(any(not(test), target_os = "none"))]
+pub fn activate(root: Option<Frame>) {
+    let root = root.unwrap_or_else(kernel_root);
+    unsafe { crate::arch::paging::switch_pml4(root) };
+}
+
+// ===== テスト =====
+#[cfg(all(test, not(target_os = "none")))]
+mod tests {
+    use super::*;
+
+    const DATA: MapFlags = MapFlags::from_bits(MapFlags::WRITABLE);
+
+    #[test]
+    fn test_new_map_uses_kernel_root() {
+        let map = MemoryMap::new();
+        assert_eq!(map.root(), None, "カーネルのページテーブルを使う");
+        assert_eq!(map.regions().count(), 0);
+        assert_eq!(MemoryMap::with_root(Frame::from_number(5)).root(), Some(Frame::from_number(5)));
+    }
+
+    #[test]
+    fn test_add_and_find_regions() {
+        let mut map = MemoryMap::new();
+        map.add_region(Region::new(0x40_0000, 0x40_3000, MapFlags::from_bits(MapFlags::EXECUTABLE))).unwrap();
+        map.add_region(Region::new(0x7fff_0000, 0x8000_0000, DATA)).unwrap();
+
+        assert_eq!(map.find(0x40_2fff).map(|r| r.start), Some(0x40_0000));
+        assert_eq!(map.find(0x40_3000), None, "末尾は含まない");
+        assert_eq!(map.find(0x7fff_8000).map(Region::size), Some(0x1_0000));
+
+        assert_eq!(map.remove_region(0x40_0000).map(|r| r.end), Some(0x40_3000));
+        assert_eq!(map.remove_region(0x40_0000), None, "2回は外せない");
+        assert_eq!(map.regions().count(), 1);
+    }
+
+    #[test]
+    fn test_region_errors() {
+        let mut map = MemoryMap::new();
+        map.add_region(Region::new(0x1000, 0x3000, DATA)).unwrap();
+
+        let overlap = Region::new(0x2000, 0x4000, DATA);
+        assert_eq!(map.add_region(overlap), Err(RegionError::Overlap(overlap)));
+        let misaligned = Region::new(0x4000, 0x4800, DATA);
+        assert_eq!(map.add_region(misaligned), Err(RegionError::Invalid(misaligned)));
+        let kernel = Region::new(0xffff_8000_0000_0000, 0xffff_8000_0000_1000, DATA);
+        assert_eq!(map.add_region(kernel), Err(RegionError::Invalid(kernel)), "カーネルの半分には置けない");
+        assert_eq!(map.add_region(Region::new(0x5000, 0x5000, DATA)), Err(RegionError::Invalid(Region::new(0x5000, 0x5000, DATA))));
+        assert!(map.add_region(Region::new(0x3000, 0x4000, DATA)).is_ok(), "隣り合うのはよい");
+
+        for i in 2..MAX_REGIONS as u64 {
+            map.add_region(Region::new(i << 20, (i << 20) + 0x1000, DATA)).unwrap();
+        }
+        assert_eq!(map.add_region(Region::new(0x10_0000_0000, 0x10_0000_1000, DATA)), Err(RegionError::TooMany));
+        assert_eq!(RegionError::TooMany.to_string(), "too many regions (max 8)");
+    }
+
+    #[test]
+    fn test_contains_range() {
+        let mut map = MemoryMap::new();
+        map.add_region(Region::new(0x40_0000, 0x40_2000, MapFlags::from_bits(MapFlags::USER))).unwrap();
+        map.add_region(Region::new(0x40_2000, 0x40_3000, MapFlags::from_bits(MapFlags::USER | MapFlags::WRITABLE))).unwrap();
+
+        assert!(map.contains_range(0x40_0ff0, 0x20, MapFlags::USER), "ページをまたいでもよい");
+        assert!(map.contains_range(0x40_1ff0, 0x20, MapFlags::USER), "隣り合う領域にまたがってもよい");
+        assert!(!map.contains_range(0x40_1ff0, 0x20, MapFlags::WRITABLE), "書けない領域が混ざる");
+        assert!(map.contains_range(0x40_2000, 0x1000, MapFlags::WRITABLE));
+        assert!(!map.contains_range(0x40_2ff0, 0x20, MapFlags::USER), "末尾を越える");
+        assert!(!map.contains_range(0x3f_fff0, 0x20, MapFlags::USER), "先頭より前から始まる");
+        assert!(!map.contains_range(u64::MAX - 4, 16, MapFlags::USER), "桁あふれ");
+        assert!(map.contains_range(0x10, 0, MapFlags::USER), "0バイトは常によい");
+    }
+
+    #[test]
+    fn test_frame_refs() {
+        let refs = FrameRefs::new();
+        let frame = Frame::from_number(7);
+        assert!(!refs.share(frame), "init() の前は数えられない");
+        refs.init((0..16).map(|_| AtomicU16::new(0)).collect::<Vec<_>>().leak());
+        assert_eq!(refs.count(frame), 1, "記録がなければ1つのアドレス空間だけ");
+        assert!(refs.release(frame), "共有していなければすぐ返す");
+
+        assert!(refs.share(frame));
+        assert!(refs.share(frame));
+        assert_eq!(refs.count(frame), 3);
+        assert_eq!(refs.count(Frame::from_number(8)), 1);
+        assert_eq!(refs.shared_frames(), 1);
+        assert!(!refs.release(frame));
+        assert!(!refs.release(frame));
+        assert_eq!(refs.count(frame), 1);
+        assert_eq!(refs.shared_frames(), 0);
+        assert!(refs.release(frame), "最後の参照");
+        assert_eq!(refs.count(frame), 1, "0より下には減らない");
+
+        assert!(!refs.share(Frame::from_number(16)), "配列の外のフレームは共有できない");
+        assert!(refs.release(Frame::from_number(16)));
+    }
+}
+
+#[cfg(all(test, target_os = "none"))]
+mod kernel_tests {
+    use super::*;
+    use crate::arch::paging::{KernelFrames, Mapper};
+    use crate::arch::{AddressSpace, PageSize};
+
+    #[test_case]
+    fn test_switch_address_space() {
+        static MARKER: u64 = 0x5a5a;
+        const PAGE: u64 = 0x40_0000;
+
+        let mut map = create().unwrap();
+        let frame = crate::memory::alloc_frame().unwrap();
+        let user = MapFlags::from_bits(MapFlags::USER | MapFlags::WRITABLE);
+        let mut mapper = Mapper::new(map.root().unwrap(), KernelFrames);
+        mapper.map(PAGE, frame, PageSize::Size4K, user).unwrap();
+        map.add_region(Region::new(PAGE, PAGE + FRAME_SIZE, user)).unwrap();
+
+        activate(map.root());
+        // カーネルの半分は共有しているので、切り替えてもカーネルはそのまま動く
+        assert_eq!(unsafe { core::ptr::read_volatile(&MARKER) }, 0x5a5a);
+        unsafe { core::ptr::write_volatile(PAGE as *mut u64, 42) };
+        activate(None);
+        assert!(!crate::arch::is_mapped(PAGE), "カーネルのページテーブルには下半分のマップがない");
+
+        let phys = (boot_info::PHYSICAL_MEMORY_OFFSET + frame.start_address()) as *const u64;
+        assert_eq!(unsafe { core::ptr::read_volatile(phys) }, 42);
+        unsafe { destroy(&mut map) };
+        assert_eq!(map, MemoryMap::new());
+        assert!(crate::memory::free_frame(frame).is_err(), "領域のフレームは destroy() で返る");
+    }
+
+    #[test_case]
+    fn test_allocate_and_write() {
+        const TEXT: u64 = 0x40_0000;
+
+        let mut map = create().unwrap();
+        let text = MapFlags::from_bits(MapFlags::USER | MapFlags::EXECUTABLE);
+        allocate(&mut map, Region::new(TEXT, TEXT + 2 * FRAME_SIZE, text)).unwrap();
+        // ページの境目をまたいで書く
+        write(&map, TEXT + FRAME_SIZE - 2, b"ring").unwrap();
+        assert_eq!(write(&map, TEXT + 2 * FRAME_SIZE, b"x"), Err(crate::arch::MapError::NotMapped));
+
+        activate(map.root());
+        let bytes = unsafe { core::slice::from_raw_parts((TEXT + FRAME_SIZE - 4) as *const u8, 8) };
+        assert_eq!(bytes, b"\0\0ring\0\0", "残りはゼロで埋まっている");
+        activate(None);
+        unsafe { destroy(&mut map) };
+        assert_eq!(map.regions().count(), 0);
+    }
+
+    #[test_case]
+    fn test_fork_copy_on_write() {
+        const DATA: u64 = 0x60_0000;
+
+        let mut parent = create().unwrap();
+        let data = MapFlags::from_bits(MapFlags::USER | MapFlags::WRITABLE);
+        allocate(&mut parent, Region::new(DATA, DATA + 2 * FRAME_SIZE, data)).unwrap();
+        write(&parent, DATA, b"parent").unwrap();
+
+        let mut child = fork(&parent).unwrap();
+        let (parent_mapper, child_mapper) = (mapper(parent.root().unwrap()), mapper(child.root().unwrap()));
+        assert_eq!(child.regions().copied().next(), parent.regions().copied().next(), "領域も同じ");
+        assert_eq!(parent_mapper.translate(DATA), child_mapper.translate(DATA), "フレームを共有する");
+        for (mapper, name) in [(&parent_mapper, "親"), (&child_mapper, "子")] {
+            let (entry, _) = mapper.lookup(DATA).unwrap();
+            assert!(!entry.is_set(crate::arch::paging::PageTableEntry::WRITABLE), "{}も書き込み禁止", name);
+        }
+
+        write(&child, DATA, b"child").unwrap();
+        let (parent_phys, child_phys) = (parent_mapper.translate(DATA).unwrap(), child_mapper.translate(DATA).unwrap());
+        assert_ne!(parent_phys, child_phys, "書いたページだけコピーする");
+        assert_eq!(parent_mapper.translate(DATA + FRAME_SIZE), child_mapper.translate(DATA + FRAME_SIZE));
+        let read = |phys: u64| unsafe { core::slice::from_raw_parts(physical(phys), 6) };
+        assert_eq!(read(parent_phys), b"parent", "親は変わらない");
+        assert_eq!(read(child_phys), b"child\0");
+
+        // 親のページはもう共有していないので、フォルトでは属性を戻すだけ
+        assert!(copy_on_write(&parent, DATA + 8));
+        assert_eq!(parent_mapper.translate(DATA), Some(parent_phys));
+        assert!(!copy_on_write(&parent, DATA + 8), "もう書けるページ");
+        assert!(!copy_on_write(&parent, DATA + 2 * FRAME_SIZE), "領域の外");
+
+        unsafe { destroy(&mut child) };
+        assert_eq!(FRAME_REFS.count(Frame::containing_address(parent_mapper.translate(DATA + FRAME_SIZE).unwrap())), 1);
+        unsafe { destroy(&mut parent) };
+    }
+}
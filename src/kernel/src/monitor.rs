@@ -0,0 +1,700 @@
+//! カーネルモニタ（シリアルコンソールの対話シェル）
+//!
+//! デバッグ用に、COM1から打ったコマンドでカーネルの状態を見たり変えたりする。
+//! モニタはアイドルのすぐ上の優先度（MONITOR_PRIORITY）のカーネルタスクで、実行可能キューに並んで
+//! ディスパッチャ（dispatch.rs）に選ばれたときだけ動く。実行可能なユーザープロセスがある間は後回しになる。
+//! 選ばれるとシリアルドライバの受信バッファ（serial::read_byte()）に届いたキー入力を処理し、
+//! なくなったらキューから外れて眠る（serial::wait_for_input()）。次のキー入力の割り込み（IRQ 4）でキューに戻る。
+//!
+//! ```text
+//! monitor> rq
+//! policy: Priority
+//! q14: 0
+//! ```
+//!
+//! MINIX 3: IS（情報サーバー）が F1〜F12 キーでプロセステーブルや
+//!          スケジューリングキューをダンプしていた（servers/is/dmp_kernel.c）
+//! MikanOS: terminal.cpp の Terminal::ExecuteLine()
+
+use core::fmt::{self, Write};
+
+use boot_info::MemoryRegion;
+
+use crate::arch::interrupts::{irq_stats, IrqStat};
+#[cfg(any(not(test), target_os = "none"))]
+use crate::dispatch::KernelTask;
+use crate::klog::LogBuffer;
+use crate::memory::slab::CacheStats;
+use crate::process::{Priority, Process, ProcessFlags, ProcessId, ProcessTable, Scheduler, NR_SCHED_QUEUES};
+use crate::sync::SpinLock;
+
+/// プロンプト
+pub const PROMPT: &str = "monitor> ";
+/// 1行の最大文字数
+pub const LINE_LEN: usize = 80;
+/// モニタのプロセス番号（負の番号はカーネルタスク、MINIX 3と同じ）
+pub const MONITOR_PID: ProcessId = -1;
+/// モニタの優先度（アイドルのすぐ上。ユーザープロセスが動ける間は選ばれない）
+pub const MONITOR_PRIORITY: u8 = Priority::IDLE_Q - 1;
+
+/// コマンドの一覧（help の表示順）
+const COMMANDS: [(&str, &str); 9] = [
+    ("ps", "list processes"),
+    ("rq", "show scheduler ready queues"),
+    ("mem", "show the physical memory map"),
+    ("irq", "show interrupt counts per IRQ"),
+    ("slab", "show slab cache statistics"),
+    ("kill <pid>", "stop a process"),
+    ("nice <pid> <prio>", "change the priority of a process (0-15)"),
+    ("log [n]", "show the last n kernel log messages"),
+    ("help", "show this message"),
+];
+
+/// 1行分の入力（エコーと行編集）
+pub struct LineEditor {
+    buf: [u8; LINE_LEN],
+    len: usize,
+    /// 直前が CR だった（CR LF を1回の改行として扱う）
+    after_cr: bool,
+}
+
+impl Default for LineEditor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl LineEditor {
+    pub const fn new() -> Self {
+        Self { buf: [0; LINE_LEN], len: 0, after_cr: false }
+    }
+
+    /// 入力中の行
+    pub fn as_str(&self) -> &str {
+        // 印字可能なASCIIしか入れないので必ずUTF-8
+        core::str::from_utf8(&self.buf[..self.len]).unwrap_or("")
+    }
+
+    /// 行を空にする
+    pub fn clear(&mut self) {
+        self.len = 0;
+    }
+
+    /// 1文字を処理して端末にエコーする。改行なら true（行は clear() するまで残る）
+    ///
+    /// Backspace / DEL で1文字、Ctrl-U で行全体を消す。制御文字とASCII以外は無視する。
+    pub fn feed(&mut self, byte: u8, echo: &mut impl Write) -> bool {
+        let after_cr = core::mem::replace(&mut self.after_cr, byte == b'\r');
+        match byte {
+            b'\n' if after_cr => false,
+            b'\r' | b'\n' => {
+                let _ = echo.write_str("\n");
+                true
+            }
+            0x08 | 0x7f => {
+                if self.len > 0 {
+                    self.len -= 1;
+                    let _ = echo.write_str("\x08 \x08");
+                }
+                false
+            }
+            0x15 => {
+                for _ in 0..self.len {
+                    let _ = echo.write_str("\x08 \x08");
+                }
+                self.len = 0;
+                false
+            }
+            0x20..=0x7e if self.len < LINE_LEN => {
+                self.buf[self.len] = byte;
+                self.len += 1;
+                let _ = echo.write_char(byte as char);
+                false
+            }
+            _ => false,
+        }
+    }
+}
+
+/// モニタのコマンド
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Command {
+    Help,
+    Ps,
+    Rq,
+    Mem,
+    Irq,
+    Slab,
+    Kill(ProcessId),
+    Nice(ProcessId, u8),
+    /// 最後の n 件（None ならバッファにあるすべて）
+    Log(Option<usize>),
+}
+
+/// コマンドの解析エラー
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ParseError<'a> {
+    /// 知らないコマンド
+    Unknown(&'a str),
+    /// 引数が違う（正しい使い方）
+    Usage(&'static str),
+}
+
+impl fmt::Display for ParseError<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ParseError::Unknown(name) => write!(f, "unknown command `{}` (try `help`)", name),
+            ParseError::Usage(usage) => write!(f, "usage: {}", usage),
+        }
+    }
+}
+
+impl Command {
+    /// 1行を解析する（空行ならNone）
+    pub fn parse(line: &str) -> Result<Option<Self>, ParseError<'_>> {
+        let mut words = line.split_ascii_whitespace();
+        let Some(name) = words.next() else {
+            return Ok(None);
+        };
+        let args: [Option<&str>; 3] = [words.next(), words.next(), words.next()];
+        let command = match (name, args) {
+            ("help" | "?", [None, ..]) => Command::Help,
+            ("ps", [None, ..]) => Command::Ps,
+            ("rq", [None, ..]) => Command::Rq,
+            ("mem", [None, ..]) => Command::Mem,
+            ("irq", [None, ..]) => Command::Irq,
+            ("slab", [None, ..]) => Command::Slab,
+            ("kill", [Some(pid), None, _]) => Command::Kill(pid.parse().map_err(|_| usage("kill"))?),
+            ("nice", [Some(pid), Some(priority), None]) => Command::Nice(
+                pid.parse().map_err(|_| usage("nice"))?,
+                priority.parse().map_err(|_| usage("nice"))?,
+            ),
+            ("log", [None, ..]) => Command::Log(None),
+            ("log", [Some(n), None, _]) => Command::Log(Some(n.parse().map_err(|_| usage("log"))?)),
+            (name, _) => return Err(usage(name)),
+        };
+        Ok(Some(command))
+    }
+}
+
+/// `name` の正しい使い方（知らないコマンドならUnknown）
+fn usage(name: &str) -> ParseError<'_> {
+    COMMANDS
+        .iter()
+        .find(|(usage, _)| usage.split(' ').next() == Some(name))
+        .map_or(ParseError::Unknown(name), |(usage, _)| ParseError::Usage(usage))
+}
+
+/// モニタの状態と、コマンドが触るカーネルのデータ
+pub struct Monitor<'a> {
+    line: LineEditor,
+    table: &'a ProcessTable,
+    scheduler: &'a SpinLock<Scheduler>,
+    log: &'a SpinLock<LogBuffer>,
+    memory: &'a [MemoryRegion],
+}
+
+impl<'a> Monitor<'a> {
+    pub fn new(
+        table: &'a ProcessTable,
+        scheduler: &'a SpinLock<Scheduler>,
+        log: &'a SpinLock<LogBuffer>,
+        memory: &'a [MemoryRegion],
+    ) -> Self {
+        Self { line: LineEditor::new(), table, scheduler, log, memory }
+    }
+
+    pub fn prompt(&self, out: &mut impl Write) {
+        let _ = out.write_str(PROMPT);
+    }
+
+    /// 1文字を処理する（改行ならコマンドを実行して、次のプロンプトを出す）
+    pub fn feed(&mut self, byte: u8, out: &mut impl Write) {
+        if !self.line.feed(byte, out) {
+            return;
+        }
+        let _ = match Command::parse(self.line.as_str()) {
+            Ok(Some(command)) => self.execute(command, out),
+            Ok(None) => Ok(()),
+            Err(error) => writeln!(out, "{}", error),
+        };
+        self.line.clear();
+        self.prompt(out);
+    }
+
+    /// コマンドを実行して結果を `out` に書く
+    pub fn execute(&self, command: Command, out: &mut impl Write) -> fmt::Result {
+        match command {
+            Command::Help => {
+                for (usage, description) in COMMANDS {
+                    writeln!(out, "  {:<18} {}", usage, description)?;
+                }
+                Ok(())
+            }
+            Command::Ps => writeln!(out, "{}", self.table),
+            Command::Rq => {
+                // ロックを持ったまま文字列にして、出力はロックを放してから
+                let dump = alloc::format!("{}", *self.scheduler.lock());
+                writeln!(out, "{}", dump)
+            }
+            Command::Mem => mem(self.memory, out),
+            Command::Irq => irq(&irq_stats(), out),
+            Command::Slab => slab(&slab_stats(), out),
+            Command::Kill(pid) => match kill(self.table, &mut self.scheduler.lock(), pid) {
+                Ok(slot) => writeln!(out, "killed {} ({})", pid, self.table.get_mut(slot).map_or("", |p| p.name_str())),
+                Err(error) => writeln!(out, "kill: {}", error),
+            },
+            Command::Nice(pid, priority) => match nice(self.table, &mut self.scheduler.lock(), pid, priority) {
+                Ok(old) => writeln!(out, "{}: priority {} -> {}", pid, old, priority),
+                Err(error) => writeln!(out, "nice: {}", error),
+            },
+            Command::Log(count) => log(self.log, count, out),
+        }
+    }
+}
+
+/// `kill` / `nice` のエラー
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ControlError {
+    /// そのプロセス番号のプロセスはない
+    NoSuchProcess(ProcessId),
+    /// カーネルタスク（負のプロセス番号）は止められない
+    KernelTask(ProcessId),
+    /// 優先度が範囲外
+    InvalidPriority(u8),
+    /// 最大優先度より高くはできない
+    AboveMaxPriority { max: u8 },
+}
+
+impl fmt::Display for ControlError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ControlError::NoSuchProcess(pid) => write!(f, "no process with pid {}", pid),
+            ControlError::KernelTask(pid) => write!(f, "cannot kill kernel task {}", pid),
+            ControlError::InvalidPriority(priority) => {
+                write!(f, "priority {} out of range 0-{}", priority, NR_SCHED_QUEUES - 1)
+            }
+            ControlError::AboveMaxPriority { max } => write!(f, "cannot raise priority above {}", max),
+        }
+    }
+}
+
+/// モニタ自身をカーネルタスクとしてプロセステーブルに登録し、実行可能キューに入れる（スロット番号を返す）
+pub fn spawn(table: &ProcessTable, scheduler: &mut Scheduler) -> Option<usize> {
+    let slot = table.find_free_slot()?;
+    let process = table.get_mut(slot)?;
+    *process = Process::new(MONITOR_PID);
+    process.set_name("monitor");
+    process.priority = Priority::new(MONITOR_PRIORITY);
+    process.max_priority = Priority::new(MONITOR_PRIORITY);
+    process.reset_quantum();
+    scheduler.enqueue(slot, MONITOR_PRIORITY);
+    Some(slot)
+}
+
+/// `mem`: ブートローダーから受け取った物理メモリマップ
+pub fn mem(regions: &[MemoryRegion], out: &mut impl Write) -> fmt::Result {
+    writeln!(out, "START              END                        SIZE TYPE")?;
+    let mut usable = 0;
+    let mut total = 0;
+    for region in regions {
+        writeln!(out, "{:#018x} {:#018x} {:>8} KiB {:?}", region.start, region.end, region.len() / 1024, region.kind)?;
+        total += region.len();
+        if region.kind == boot_info::MemoryRegionKind::Usable {
+            usable += region.len();
+        }
+    }
+    writeln!(out, "usable: {} KiB of {} KiB", usable / 1024, total / 1024)
+}
+
+/// `irq`: IRQごとの回数
+pub fn irq(stats: &[IrqStat], out: &mut impl Write) -> fmt::Result {
+    writeln!(out, "IRQ        COUNT HANDLER")?;
+    for (irq, stat) in stats.iter().enumerate() {
+        writeln!(out, "{:>3} {:>12} {}", irq, stat.count, if stat.has_handler { "yes" } else { "-" })?;
+    }
+    Ok(())
+}
+
+/// カーネルのスラブキャッシュの統計
+fn slab_stats() -> [CacheStats; 3] {
+    [
+        crate::process::PROCESS_CACHE.stats(),
+        crate::ipc::MESSAGE_CACHE.stats(),
+        crate::arch::paging::PAGE_TABLE_CACHE.stats(),
+    ]
+}
+
+/// `slab`: キャッシュごとの使用状況（Linuxの slabtop に近い）
+pub fn slab(stats: &[CacheStats], out: &mut impl Write) -> fmt::Result {
+    writeln!(out, "{}", CacheStats::HEADER)?;
+    for stat in stats {
+        writeln!(out, "{}", stat)?;
+    }
+    Ok(())
+}
+
+/// `kill`: キューから外してスロットを空きにする（止めたプロセスのスロット番号を返す）
+///
+/// MINIX 3: PMの sys_kill() → SYSTEMの do_kill() だが、ここではその場で消す。
+pub fn kill(table: &ProcessTable, scheduler: &mut Scheduler, pid: ProcessId) -> Result<usize, ControlError> {
+    if pid < 0 {
+        return Err(ControlError::KernelTask(pid));
+    }
+    let slot = table.find(pid).ok_or(ControlError::NoSuchProcess(pid))?;
+    let process = table.get_mut(slot).ok_or(ControlError::NoSuchProcess(pid))?;
+    scheduler.dequeue(slot, process.priority.value());
+    // 送受信の待ちから外し、このプロセスを待っていた相手はエラーで起こす
+    crate::ipc::cancel(table, scheduler, slot);
+    process.flags.set(ProcessFlags::SLOT_FREE);
+    // 止めたプロセスは実行中ではない（モニタが動いている）ので、アドレス空間とカーネルスタックを返してよい
+    #[cfg(any(not(test), target_os = "none"))]
+    unsafe {
+        crate::user::release(process)
+    };
+    Ok(slot)
+}
+
+/// `nice`: 優先度を変えて、キューに入っていれば入れ直す（元の優先度を返す）
+///
+/// MINIX 3: do_nice()。max_priority より高い優先度（小さい数）にはできない。
+pub fn nice(table: &ProcessTable, scheduler: &mut Scheduler, pid: ProcessId, priority: u8) -> Result<u8, ControlError> {
+    if usize::from(priority) >= NR_SCHED_QUEUES {
+        return Err(ControlError::InvalidPriority(priority));
+    }
+    let slot = table.find(pid).ok_or(ControlError::NoSuchProcess(pid))?;
+    let process = table.get_mut(slot).ok_or(ControlError::NoSuchProcess(pid))?;
+    let max = process.max_priority.value();
+    if priority < max {
+        return Err(ControlError::AboveMaxPriority { max });
+    }
+    let old = process.priority.value();
+    let queued = scheduler.dequeue(slot, old);
+    process.priority = Priority::new(priority);
+    if queued {
+        scheduler.enqueue(slot, priority);
+    }
+    Ok(old)
+}
+
+/// `log`: カーネルログの最後の `count` 件
+///
+/// シリアルへの出力は遅いので、ログバッファのロック（割り込み禁止）は1件ずつ取る。
+pub fn log(buffer: &SpinLock<LogBuffer>, count: Option<usize>, out: &mut impl Write) -> fmt::Result {
+    let mut seq = {
+        let buffer = buffer.lock();
+        let skip = count.map_or(0, |count| buffer.len().saturating_sub(count));
+        buffer.first_seq() + skip as u64
+    };
+    while let Some(record) = buffer.lock().records_from(seq).next() {
+        writeln!(out, "{}", record)?;
+        seq = record.seq + 1;
+    }
+    Ok(())
+}
+
+/// シリアル（COM1）への出力
+#[cfg(any(not(test), target_os = "none"))]
+struct SerialConsole;
+
+#[cfg(any(not(test), target_os = "none"))]
+impl Write for SerialConsole {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        crate::console::_serial_print(format_args!("{}", s));
+        Ok(())
+    }
+}
+
+/// ディスパッチャから呼ばれるモニタ（カーネルタスク）
+#[cfg(any(not(test), target_os = "none"))]
+pub struct Task {
+    slot: usize,
+    monitor: Monitor<'static>,
+    out: SerialConsole,
+}
+
+#[cfg(any(not(test), target_os = "none"))]
+impl Task {
+    /// モニタをプロセステーブルに登録して、最初のプロンプトを出す（テーブルに空きがなければNone）
+    pub fn start(memory: &'static [MemoryRegion]) -> Option<Self> {
+        use crate::process::{PROCESS_TABLE, SCHEDULER};
+
+        let slot = spawn(&PROCESS_TABLE, &mut SCHEDULER.lock())?;
+        let monitor = Monitor::new(&PROCESS_TABLE, &SCHEDULER, &crate::klog::LOG_BUFFER, memory);
+        let mut out = SerialConsole;
+        serial_println!("kernel monitor: type `help` for commands");
+        monitor.prompt(&mut out);
+        Some(Self { slot, monitor, out })
+    }
+}
+
+#[cfg(any(not(test), target_os = "none"))]
+impl KernelTask for Task {
+    fn slot(&self) -> usize {
+        self.slot
+    }
+
+    /// 届いているキー入力を処理して、次のキー入力（IRQ 4）まで実行可能キューから外れる
+    fn step(&mut self) {
+        while let Some(byte) = crate::serial::read_byte() {
+            self.monitor.feed(byte, &mut self.out);
+        }
+        crate::serial::wait_for_input(self.slot);
+    }
+}
+
+// ===== テスト =====
+#[cfg(all(test, not(target_os = "none")))]
+mod tests {
+    use super::*;
+    use boot_info::MemoryRegionKind;
+    use log::Level;
+
+    /// プロセスが1つもないテーブル（起動時と同じくすべて空き）
+    fn empty_table() -> ProcessTable {
+        let table = ProcessTable::new();
+        for slot in 0..crate::process::BOOT_PROCESSES {
+            table.get_mut(slot).unwrap().flags.set(ProcessFlags::SLOT_FREE);
+        }
+        table
+    }
+
+    /// `slot` にユーザープロセスを作って優先度 `priority` のキューに入れる
+    fn add_process(table: &ProcessTable, scheduler: &mut Scheduler, slot: usize, pid: ProcessId, name: &str, priority: u8) {
+        let process = table.get_mut(slot).unwrap();
+        *process = Process::new(pid);
+        process.set_name(name);
+        process.priority = Priority::new(priority);
+        scheduler.enqueue(slot, priority);
+    }
+
+    mod line_editor_tests {
+        use super::*;
+
+        fn type_keys(editor: &mut LineEditor, keys: &[u8]) -> (String, usize) {
+            let mut echo = String::new();
+            let lines = keys.iter().filter(|&&key| editor.feed(key, &mut echo)).count();
+            (echo, lines)
+        }
+
+        #[test]
+        fn test_echo_and_enter() {
+            let mut editor = LineEditor::new();
+            let (echo, lines) = type_keys(&mut editor, b"ps\r");
+            assert_eq!(echo, "ps\n", "打った文字をエコーし、CRで改行する");
+            assert_eq!(lines, 1);
+            assert_eq!(editor.as_str(), "ps", "行は clear() するまで残る");
+        }
+
+        #[test]
+        fn test_crlf_is_one_newline() {
+            let mut editor = LineEditor::new();
+            let (_, lines) = type_keys(&mut editor, b"rq\r\n\n");
+            assert_eq!(lines, 2, "CR LFで1回、続くLFでもう1回");
+        }
+
+        #[test]
+        fn test_backspace_and_kill_line() {
+            let mut editor = LineEditor::new();
+            let (echo, _) = type_keys(&mut editor, b"pz\x7fs");
+            assert_eq!(editor.as_str(), "ps", "DELで1文字消す");
+            assert_eq!(echo, "pz\x08 \x08s");
+
+            type_keys(&mut editor, b"\x15");
+            assert_eq!(editor.as_str(), "", "Ctrl-Uで行全体を消す");
+            let (echo, _) = type_keys(&mut editor, b"\x08");
+            assert_eq!(echo, "", "空の行ではBackspaceは何もしない");
+        }
+
+        #[test]
+        fn test_ignores_control_and_overflow() {
+            let mut editor = LineEditor::new();
+            type_keys(&mut editor, b"\x1b[A");
+            assert_eq!(editor.as_str(), "[A", "ESCなどの制御文字は無視");
+            editor.clear();
+            type_keys(&mut editor, &[b'x'; LINE_LEN + 5]);
+            assert_eq!(editor.as_str().len(), LINE_LEN, "LINE_LENを超えた分は捨てる");
+        }
+    }
+
+    mod parse_tests {
+        use super::*;
+
+        #[test]
+        fn test_commands() {
+            assert_eq!(Command::parse("  ps "), Ok(Some(Command::Ps)));
+            assert_eq!(Command::parse("kill 3"), Ok(Some(Command::Kill(3))));
+            assert_eq!(Command::parse("nice 3 10"), Ok(Some(Command::Nice(3, 10))));
+            assert_eq!(Command::parse("log"), Ok(Some(Command::Log(None))));
+            assert_eq!(Command::parse("log 5"), Ok(Some(Command::Log(Some(5)))));
+            assert_eq!(Command::parse(""), Ok(None), "空行は何もしない");
+        }
+
+        #[test]
+        fn test_errors() {
+            assert_eq!(Command::parse("reboot"), Err(ParseError::Unknown("reboot")));
+            assert_eq!(Command::parse("kill"), Err(ParseError::Usage("kill <pid>")));
+            assert_eq!(Command::parse("kill x"), Err(ParseError::Usage("kill <pid>")));
+            assert_eq!(Command::parse("nice 3"), Err(ParseError::Usage("nice <pid> <prio>")));
+            assert_eq!(Command::parse("ps -a"), Err(ParseError::Usage("ps")), "引数を取らないコマンド");
+            assert_eq!(ParseError::Usage("kill <pid>").to_string(), "usage: kill <pid>");
+            assert_eq!(ParseError::Unknown("x").to_string(), "unknown command `x` (try `help`)");
+        }
+    }
+
+    mod command_tests {
+        use super::*;
+
+        #[test]
+        fn test_spawn() {
+            let table = empty_table();
+            let mut scheduler = Scheduler::new();
+            let slot = spawn(&table, &mut scheduler).unwrap();
+            let monitor = table.get_mut(slot).unwrap();
+            assert_eq!((monitor.pid, monitor.name_str()), (MONITOR_PID, "monitor"));
+            assert!(monitor.is_runnable(), "SLOT_FREEは外れている");
+            assert_eq!(monitor.max_priority.value(), MONITOR_PRIORITY, "nice で上げられない");
+            assert_eq!(scheduler.head(usize::from(MONITOR_PRIORITY)), Some(slot), "モニタはアイドルのすぐ上のキュー");
+        }
+
+        #[test]
+        fn test_ps_and_rq_use_dumps() {
+            let table = empty_table();
+            let scheduler = SpinLock::new(Scheduler::new());
+            add_process(&table, &mut scheduler.lock(), 4, 12, "init", Priority::USER_Q);
+            let log_buffer = SpinLock::new(LogBuffer::new());
+            let monitor = Monitor::new(&table, &scheduler, &log_buffer, &[]);
+
+            let mut out = String::new();
+            monitor.execute(Command::Ps, &mut out).unwrap();
+            assert_eq!(out, format!("{}\n", table), "ps はプロセステーブルのダンプ");
+            assert!(out.contains("   4    12 init "), "{}", out);
+
+            out.clear();
+            monitor.execute(Command::Rq, &mut out).unwrap();
+            assert_eq!(out, "policy: Priority\nq7: 4\n", "rq はスケジューラのダンプ");
+        }
+
+        #[test]
+        fn test_kill() {
+            let table = empty_table();
+            let mut scheduler = Scheduler::new();
+            spawn(&table, &mut scheduler);
+            add_process(&table, &mut scheduler, 3, 7, "loop", Priority::USER_Q);
+
+            assert_eq!(kill(&table, &mut scheduler, 7), Ok(3));
+            assert!(table.get_mut(3).unwrap().flags.is_set(ProcessFlags::SLOT_FREE), "スロットは空きになる");
+            assert_eq!(scheduler.head(usize::from(Priority::USER_Q)), None, "キューからも外す");
+            assert_eq!(kill(&table, &mut scheduler, 7), Err(ControlError::NoSuchProcess(7)), "もういない");
+            assert_eq!(kill(&table, &mut scheduler, MONITOR_PID), Err(ControlError::KernelTask(MONITOR_PID)));
+        }
+
+        #[test]
+        fn test_nice() {
+            let table = empty_table();
+            let mut scheduler = Scheduler::new();
+            add_process(&table, &mut scheduler, 1, 3, "job", Priority::USER_Q);
+
+            assert_eq!(nice(&table, &mut scheduler, 3, 12), Ok(Priority::USER_Q));
+            assert_eq!(table.get_mut(1).unwrap().priority.value(), 12);
+            assert_eq!(scheduler.head(12), Some(1), "新しい優先度のキューに入れ直す");
+            assert_eq!(scheduler.head(usize::from(Priority::USER_Q)), None);
+
+            assert_eq!(nice(&table, &mut scheduler, 3, 2), Err(ControlError::AboveMaxPriority { max: Priority::USER_Q }));
+            assert_eq!(nice(&table, &mut scheduler, 3, 16), Err(ControlError::InvalidPriority(16)));
+            assert_eq!(nice(&table, &mut scheduler, 4, 9), Err(ControlError::NoSuchProcess(4)));
+        }
+
+        #[test]
+        fn test_mem() {
+            let regions = [
+                MemoryRegion::new(0, 0x9f000, MemoryRegionKind::Usable),
+                MemoryRegion::new(0x10_0000, 0x20_0000, MemoryRegionKind::Kernel),
+            ];
+            let mut out = String::new();
+            mem(&regions, &mut out).unwrap();
+            let lines: Vec<_> = out.lines().collect();
+            assert_eq!(lines[1], "0x0000000000000000 0x000000000009f000      636 KiB Usable");
+            assert_eq!(lines[3], "usable: 636 KiB of 1660 KiB");
+        }
+
+        #[test]
+        fn test_irq() {
+            let mut stats = [IrqStat::default(); 16];
+            stats[0] = IrqStat { count: 1234, has_handler: true };
+            let mut out = String::new();
+            irq(&stats, &mut out).unwrap();
+            let lines: Vec<_> = out.lines().collect();
+            assert_eq!(lines.len(), 17);
+            assert_eq!(lines[1], "  0         1234 yes");
+            assert_eq!(lines[2], "  1            0 -");
+        }
+
+        #[test]
+        fn test_slab() {
+            assert_eq!(Command::parse("slab"), Ok(Some(Command::Slab)));
+            let stats = [CacheStats { name: "process", object_size: 128, objects_per_slab: 31, slabs: 2, in_use: 40, free: 22 }];
+            let mut out = String::new();
+            slab(&stats, &mut out).unwrap();
+            let lines: Vec<_> = out.lines().collect();
+            assert_eq!(lines[0], CacheStats::HEADER);
+            assert_eq!(lines[1], "process         128    31      2       40       22");
+
+            // 実際のキャッシュの名前がそろっている
+            let names: Vec<_> = slab_stats().iter().map(|s| s.name).collect();
+            assert_eq!(names, ["process", "message", "page_table"]);
+        }
+
+        #[test]
+        fn test_log() {
+            let buffer = SpinLock::new(LogBuffer::new());
+            for i in 0..5 {
+                buffer.lock().push(i, Level::Info, None, format_args!("message {}", i));
+            }
+            let mut out = String::new();
+            log(&buffer, Some(2), &mut out).unwrap();
+            assert_eq!(out, "[       3] INFO  kernel: message 3\n[       4] INFO  kernel: message 4\n", "最後の2件");
+            out.clear();
+            log(&buffer, None, &mut out).unwrap();
+            assert_eq!(out.lines().count(), 5, "引数がなければすべて");
+        }
+    }
+
+    mod monitor_tests {
+        use super::*;
+
+        #[test]
+        fn test_session() {
+            let table = empty_table();
+            let scheduler = SpinLock::new(Scheduler::new());
+            add_process(&table, &mut scheduler.lock(), 2, 9, "victim", Priority::USER_Q);
+            let log_buffer = SpinLock::new(LogBuffer::new());
+            let mut monitor = Monitor::new(&table, &scheduler, &log_buffer, &[]);
+
+            let mut out = String::new();
+            for &byte in b"kill 9\rfoo\r\r" {
+                monitor.feed(byte, &mut out);
+            }
+            let lines: Vec<_> = out.split('\n').collect();
+            assert_eq!(lines[0], "kill 9", "エコー");
+            assert_eq!(lines[1], "killed 9 (victim)");
+            assert_eq!(lines[2], "monitor> foo");
+            assert_eq!(lines[3], "unknown command `foo` (try `help`)");
+            assert_eq!(lines[4], "monitor> ", "空行ではプロンプトだけ");
+            assert_eq!(lines[5], "monitor> ");
+        }
+
+        #[test]
+        fn test_help_lists_every_command() {
+            let table = empty_table();
+            let scheduler = SpinLock::new(Scheduler::new());
+            let log_buffer = SpinLock::new(LogBuffer::new());
+            let monitor = Monitor::new(&table, &scheduler, &log_buffer, &[]);
+            let mut out = String::new();
+            monitor.execute(Command::Help, &mut out).unwrap();
+            for (usage, _) in COMMANDS {
+                assert!(out.contains(usage), "{} がない", usage);
+            }
+        }
+    }
+}
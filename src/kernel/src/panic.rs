@@ -0,0 +1,281 @@
+//! パニックハンドラ
+//!
+//! 割り込みを止めてから、メッセージと発生場所、実行中のプロセス、
+//! レジスタ、バックトレースをシリアルとVGAに出して停止する。
+//! テストビルド（QEMU上のカーネル内テスト）では、テスト中のパニックは testing::on_panic() に任せ、
+//! それ以外はQEMUを失敗の終了コードで終わらせる。
+//!
+//! MINIX 3: kernel/utility.c の panic() と、proc_stacktrace() によるスタックトレース
+//! MikanOS: なし（Haltするだけだった）
+
+use core::fmt::{self, Write};
+use core::panic::Location;
+
+use ksyms::SymbolTable;
+
+use crate::arch::StackFrame;
+use crate::process::{ProcessId, ProcessTable, Scheduler};
+
+/// パニック時に表示するプロセスの情報
+pub struct ProcessDump<'a> {
+    pub pid: ProcessId,
+    pub name: &'a str,
+    /// 最後に保存されたレジスタ
+    pub registers: &'a StackFrame,
+}
+
+/// パニックの報告を書き出す
+///
+/// ハードウェアに依存しない部分だけを分けておき、ホストでテストできるようにしている。
+/// バックトレースのアドレスは `symbols` で関数名に直す。例外のときは最初のアドレスが
+/// 例外を起こした命令（rip）、それ以外は戻りアドレス。
+pub fn write_report(
+    out: &mut impl Write,
+    message: &dyn fmt::Display,
+    location: Option<&Location>,
+    process: Option<&ProcessDump>,
+    exception: Option<&StackFrame>,
+    backtrace: impl Iterator<Item = u64>,
+    symbols: &SymbolTable,
+) -> fmt::Result {
+    writeln!(out)?;
+    writeln!(out, "KERNEL PANIC: {}", message)?;
+    match location {
+        Some(location) => writeln!(out, "  at {}:{}:{}", location.file(), location.line(), location.column())?,
+        None => writeln!(out, "  at <unknown location>")?,
+    }
+
+    match process {
+        Some(process) => {
+            writeln!(out, "process: {} (pid {})", process.name, process.pid)?;
+            writeln!(out, "saved registers:")?;
+            write_indented(out, process.registers)?;
+        }
+        None => writeln!(out, "process: none (kernel)")?,
+    }
+
+    if let Some(registers) = exception {
+        writeln!(out, "exception registers:")?;
+        write_indented(out, registers)?;
+    }
+
+    writeln!(out, "backtrace:")?;
+    let mut empty = true;
+    for (i, address) in backtrace.enumerate() {
+        write!(out, "  #{:<2} {:#018x}", i, address)?;
+        let symbol = if i == 0 && exception.is_some() {
+            symbols.lookup(address)
+        } else {
+            symbols.lookup_return_address(address)
+        };
+        match symbol {
+            Some(symbol) => writeln!(out, " <{}>", symbol)?,
+            None => writeln!(out)?,
+        }
+        empty = false;
+    }
+    if empty {
+        writeln!(out, "  <none>")?;
+    }
+    Ok(())
+}
+
+/// パニック時点のプロセステーブルと実行可能キューを書き出す
+///
+/// スケジューラのロックを持ったままパニックしたときは `scheduler` が None になり、
+/// キューの代わりにそのことを書く。
+///
+/// MINIX 3: kernel/debug.c の print_proc() / print_proc_recursive()
+pub fn write_state(out: &mut impl Write, table: &ProcessTable, scheduler: Option<&Scheduler>) -> fmt::Result {
+    writeln!(out, "process table:")?;
+    write_indented(out, table)?;
+    writeln!(out, "ready queues:")?;
+    match scheduler {
+        Some(scheduler) => write_indented(out, scheduler),
+        None => writeln!(out, "  <scheduler locked>"),
+    }
+}
+
+/// 複数行の値を2文字下げて書く
+fn write_indented(out: &mut impl Write, value: &dyn fmt::Display) -> fmt::Result {
+    struct Indent<'a, W: Write> {
+        out: &'a mut W,
+        line_start: bool,
+    }
+    impl<W: Write> Write for Indent<'_, W> {
+        fn write_str(&mut self, s: &str) -> fmt::Result {
+            for c in s.chars() {
+                if self.line_start {
+                    self.out.write_str("  ")?;
+                }
+                self.out.write_char(c)?;
+                self.line_start = c == '\n';
+            }
+            Ok(())
+        }
+    }
+    let mut indent = Indent { out, line_start: true };
+    writeln!(indent, "{}", value)
+}
+
+/// カーネルのパニックハンドラ（ホストでの cargo test では標準ライブラリのものを使う）
+#[cfg(any(not(test), target_os = "none"))]
+#[panic_handler]
+fn panic(info: &core::panic::PanicInfo) -> ! {
+    use core::sync::atomic::{AtomicBool, Ordering};
+
+    use crate::arch::{backtrace, interrupts, CpuOps, X86_64};
+    use crate::vga::{Color, ColorCode};
+
+    /// パニックの報告中にもう一度パニックしたか（ダンプ中のページフォルトなど）
+    static PANICKING: AtomicBool = AtomicBool::new(false);
+
+    unsafe { X86_64::disable_interrupts() };
+
+    // カーネル内テストの実行中なら、結果を記録して次のテストへ進む（戻ってこない）
+    #[cfg(test)]
+    crate::testing::on_panic(info);
+
+    if PANICKING.swap(true, Ordering::SeqCst) {
+        crate::console::_panic_print(format_args!("\nKERNEL PANIC while panicking: {}\n", info.message()));
+        finish();
+    }
+
+    if let Some(mut vga) = crate::vga::WRITER.try_lock() {
+        if let Some(writer) = vga.as_mut() {
+            writer.set_color(ColorCode::new(Color::LightRed, Color::Black));
+        }
+    }
+
+    let current = crate::process::current();
+    let process = current.map(|process| ProcessDump {
+        pid: process.pid,
+        name: process.name_str(),
+        registers: &process.registers,
+    });
+
+    // CPU例外から来たなら、例外が起きた場所からたどる
+    let exception = interrupts::exception_frame().map(|frame| StackFrame::from(&frame));
+    let (first, rbp) = match &exception {
+        Some(registers) => (Some(registers.rip), registers.rbp),
+        None => (None, backtrace::current_rbp()),
+    };
+    let trace = first.into_iter().chain(unsafe { backtrace::from_rbp(rbp) });
+
+    let _ = write_report(
+        &mut PanicWriter,
+        &info.message(),
+        info.location(),
+        process.as_ref(),
+        exception.as_ref(),
+        trace,
+        &crate::symbols::table(),
+    );
+    // パニックしたのがスケジューラのロック中なら、キューは読まない（止まってしまうので）
+    let scheduler = crate::process::SCHEDULER.try_lock();
+    let _ = write_state(&mut PanicWriter, &crate::process::PROCESS_TABLE, scheduler.as_deref());
+    finish()
+}
+
+/// console::_panic_print() に流す Write
+#[cfg(any(not(test), target_os = "none"))]
+struct PanicWriter;
+
+#[cfg(any(not(test), target_os = "none"))]
+impl Write for PanicWriter {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        crate::console::_panic_print(format_args!("{}", s));
+        Ok(())
+    }
+}
+
+/// 停止する（テストビルドではQEMUを失敗として終了させる）
+#[cfg(any(not(test), target_os = "none"))]
+fn finish() -> ! {
+    #[cfg(test)]
+    crate::arch::qemu::exit(crate::arch::qemu::QemuExitCode::Failed);
+    crate::arch::halt_forever()
+}
+
+// ===== テスト =====
+#[cfg(all(test, not(target_os = "none")))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_report_without_process() {
+        let mut out = String::new();
+        let location = Location::caller();
+        let table = SymbolTable::EMPTY;
+        write_report(&mut out, &"oops", Some(location), None, None, [0x1000u64, 0x2000].into_iter(), &table).unwrap();
+        let lines: Vec<_> = out.lines().collect();
+        assert_eq!(lines[1], "KERNEL PANIC: oops", "メッセージ");
+        assert!(lines[2].starts_with("  at kernel/src/panic.rs:"), "発生場所: {}", lines[2]);
+        assert_eq!(lines[3], "process: none (kernel)");
+        assert_eq!(lines[4], "backtrace:");
+        assert_eq!(lines[5], "  #0  0x0000000000001000");
+        assert_eq!(lines[6], "  #1  0x0000000000002000");
+    }
+
+    #[test]
+    fn test_report_with_process_and_exception() {
+        let mut registers = StackFrame::new();
+        registers.rip = 0xffff_ffff_8010_1234;
+        let process = ProcessDump { pid: 7, name: "init", registers: &registers };
+        let mut out = String::new();
+        let table = SymbolTable::EMPTY;
+        write_report(&mut out, &"page fault", None, Some(&process), Some(&registers), core::iter::empty(), &table)
+            .unwrap();
+
+        assert!(out.contains("  at <unknown location>\n"));
+        assert!(out.contains("process: init (pid 7)\nsaved registers:\n  rax="), "プロセスとレジスタ: {}", out);
+        assert!(out.contains("exception registers:\n"));
+        assert!(out.contains("  rip=ffffffff80101234"), "各行を字下げする");
+        assert!(out.ends_with("backtrace:\n  <none>\n"), "たどれなければnone");
+    }
+
+    #[test]
+    fn test_report_symbolizes_backtrace() {
+        let mut symbols = [
+            ksyms::RawSymbol { address: 0x1000, size: 0x100, name: "kernel::fault" },
+            ksyms::RawSymbol { address: 0x2000, size: 0x40, name: "kernel::caller" },
+        ];
+        let mut data = vec![0; ksyms::table_size(&symbols)];
+        ksyms::write_table(&mut symbols, &mut data).unwrap();
+        let table = SymbolTable::parse(&data).unwrap();
+
+        let registers = StackFrame::new();
+        let trace = [0x1000u64, 0x2040, 0x9000].into_iter();
+        let mut out = String::new();
+        write_report(&mut out, &"fault", None, None, Some(&registers), trace, &table).unwrap();
+
+        assert!(out.contains("  #0  0x0000000000001000 <kernel::fault+0x0>\n"), "例外のripはそのまま: {}", out);
+        assert!(out.contains("  #1  0x0000000000002040 <kernel::caller+0x40>\n"), "戻りアドレスは呼び出し元の関数");
+        assert!(out.ends_with("  #2  0x0000000000009000\n"), "わからなければアドレスだけ");
+    }
+
+    #[test]
+    fn test_state_dump() {
+        use crate::process::{Process, ProcessFlags, BOOT_PROCESSES};
+
+        let processes = ProcessTable::new();
+        for slot in 0..BOOT_PROCESSES {
+            processes.get_mut(slot).unwrap().flags.set(ProcessFlags::SLOT_FREE);
+        }
+        let init = processes.get_mut(0).unwrap();
+        *init = Process::new(1);
+        init.set_name("init");
+        let mut scheduler = Scheduler::new();
+        scheduler.enqueue(0, 7);
+
+        let mut out = String::new();
+        write_state(&mut out, &processes, Some(&scheduler)).unwrap();
+        assert!(out.starts_with("process table:\n  SLOT   PID NAME"), "ヘッダも字下げする: {}", out);
+        assert!(out.contains("\n     0     1 init "), "{}", out);
+        assert!(out.ends_with("ready queues:\n  policy: Priority\n  q7: 0\n"), "{}", out);
+
+        out.clear();
+        write_state(&mut out, &processes, None).unwrap();
+        assert!(out.ends_with("ready queues:\n  <scheduler locked>\n"), "ロック中ならキューは読まない");
+    }
+}
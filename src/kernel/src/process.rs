@@ -1,14 +1,55 @@
 //! プロセス管理モジュール
 //! MINIX 3の proc.h から学んだ構造をRustで実装
 
+use alloc::vec::Vec;
+use core::alloc::Layout;
 use core::cell::UnsafeCell;
+use core::fmt;
+use core::ptr::{self, NonNull};
+use core::sync::atomic::{AtomicPtr, AtomicU8, AtomicUsize, Ordering};
+
+use crate::memory::slab::{FramePages, KernelPages, ObjectCache, SlabBox, SlabPages};
+use crate::arch::MapError;
+use crate::elf::ElfError;
+use crate::ipc::Message;
+use crate::memory::vm::MemoryMap;
+use crate::sync::SpinLock;
+
+/// レジスタ保存領域はアーキテクチャ依存なので arch に置く
+pub use crate::arch::StackFrame;
 
 /// プロセス番号の型
 /// MINIX 3では負の値はカーネルタスク、0以上はユーザープロセス
 pub type ProcessId = i32;
 
-/// プロセスの最大数
-pub const MAX_PROCESSES: usize = 16;
+/// 起動直後から使えるスロット数（静的な配列に置くので、ヒープやスラブがなくても使える）
+pub const BOOT_PROCESSES: usize = 16;
+
+/// ディレクトリの1チャンクに入るスロット数（ポインタ512個で4KiB）
+const CHUNK_SLOTS: usize = 512;
+
+/// ディレクトリのチャンク数
+const DIRECTORY_CHUNKS: usize = 128;
+
+/// スロット数の上限（ディレクトリに入る数。実際には起動時に物理メモリ量か `procs=` で決めた容量まで）
+pub const PROCESS_LIMIT: usize = BOOT_PROCESSES + CHUNK_SLOTS * DIRECTORY_CHUNKS;
+
+/// ディレクトリの1チャンク
+type Chunk = [AtomicPtr<Process>; CHUNK_SLOTS];
+
+/// 物理メモリがこれだけあるごとに、プロセスを1つ作れるようにする
+/// Linux: max_threads（totalram_pages / (8 * THREAD_SIZE / PAGE_SIZE)）と同じ考え方
+pub const MEMORY_PER_PROCESS: u64 = 256 * 1024;
+
+/// 物理メモリ量から決めたスロット数（`procs=` で指定しなかったときに使う）
+pub fn default_capacity(memory_bytes: u64) -> usize {
+    let procs = usize::try_from(memory_bytes / MEMORY_PER_PROCESS).unwrap_or(PROCESS_LIMIT);
+    procs.clamp(BOOT_PROCESSES, PROCESS_LIMIT)
+}
+
+/// 誰からでも受信する（受信待ちの相手として使う）
+/// MINIX 3: ANY（0x7ace）
+pub const ANY: ProcessId = libipc::ANY;
 
 /// プロセスの実行状態フラグ
 /// MINIX 3の p_rts_flags に相当
@@ -25,8 +66,8 @@ impl ProcessFlags {
     pub const RECEIVING: u8 = 0x08;
     
     // ===== Phase 3: メモリ管理で使用 =====
-    // /// ビット1: メモリマップ未設定（fork直後の子プロセス）
-    // pub const NO_MAP: u8 = 0x02;
+    /// ビット1: メモリマップ未設定（fork直後の子プロセス）
+    pub const NO_MAP: u8 = 0x02;
     
     // ===== Phase 4: ファイルシステムで使用 =====
     // /// ビット4: シグナル受信
@@ -60,12 +101,58 @@ impl ProcessFlags {
         (self.0 & flag) != 0
     }
     
+    /// フラグの生の値
+    pub fn bits(&self) -> u8 {
+        self.0
+    }
+
     /// 実行可能かどうか（フラグが0なら実行可能）
     pub fn is_runnable(&self) -> bool {
         self.0 == 0
     }
 }
 
+/// `SENDING|RECEIVING` のようにフラグ名を並べる（実行可能なら `-`）
+/// MINIX 3: rtsflagstr()（IS のプロセスダンプ）
+///
+/// 幅指定（`{:<20}`）が効くように、一度バッファに組み立ててから `pad` する。
+impl fmt::Display for ProcessFlags {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        const NAMES: [(u8, &str); 4] = [
+            (ProcessFlags::SLOT_FREE, "SLOT_FREE"),
+            (ProcessFlags::NO_MAP, "NO_MAP"),
+            (ProcessFlags::SENDING, "SENDING"),
+            (ProcessFlags::RECEIVING, "RECEIVING"),
+        ];
+        if self.0 == 0 {
+            return f.pad("-");
+        }
+        let mut buf = [0u8; 40];
+        let mut len = 0;
+        let mut rest = self.0;
+        let mut push = |text: &[u8]| {
+            if len > 0 {
+                buf[len] = b'|';
+                len += 1;
+            }
+            buf[len..len + text.len()].copy_from_slice(text);
+            len += text.len();
+        };
+        for (flag, name) in NAMES {
+            if rest & flag != 0 {
+                push(name.as_bytes());
+                rest &= !flag;
+            }
+        }
+        // 名前のないビット（まだ使っていないフラグ）は16進数で
+        if rest != 0 {
+            const HEX: &[u8; 16] = b"0123456789abcdef";
+            push(&[b'0', b'x', HEX[usize::from(rest >> 4)], HEX[usize::from(rest & 0xf)]]);
+        }
+        f.pad(core::str::from_utf8(&buf[..len]).unwrap_or("?"))
+    }
+}
+
 /// スケジューリング優先度
 /// MINIX 3では 0 が最高優先度、数値が大きいほど低優先度
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
@@ -94,6 +181,9 @@ impl Priority {
 #[derive(Debug, Clone, Copy)]
 pub struct Quantum(u8);
 
+/// 起動時に決まるデフォルトの時間量子（コマンドラインの quantum= で上書きされる）
+static BOOT_QUANTUM: AtomicU8 = AtomicU8::new(Quantum::DEFAULT);
+
 impl Quantum {
     /// デフォルトの時間量子
     pub const DEFAULT: u8 = 8;
@@ -105,53 +195,15 @@ impl Quantum {
     pub fn value(&self) -> u8 {
         self.0
     }
-}
-
-/// コンテキストスイッチ用のレジスタ保存領域
-/// x86_64の呼び出し規約に従って保存するレジスタ
-#[derive(Debug, Clone, Copy)]
-#[repr(C)]
-pub struct StackFrame {
-    /// 汎用レジスタ
-    pub rax: u64,
-    pub rbx: u64,
-    pub rcx: u64,
-    pub rdx: u64,
-    pub rsi: u64,
-    pub rdi: u64,
-    pub rbp: u64,
-    pub r8: u64,
-    pub r9: u64,
-    pub r10: u64,
-    pub r11: u64,
-    pub r12: u64,
-    pub r13: u64,
-    pub r14: u64,
-    pub r15: u64,
-    /// 割り込み発生時のRFLAGS
-    pub rflags: u64,
-    /// 割り込み発生時のRIP（リターンアドレス）
-    pub rip: u64,
-    /// 割り込み発生時のRSP
-    pub rsp: u64,
-}
 
-impl StackFrame {
-    /// ゼロ初期化されたStackFrameを作成（const fn対応）
-    pub const fn new() -> Self {
-        Self {
-            rax: 0, rbx: 0, rcx: 0, rdx: 0,
-            rsi: 0, rdi: 0, rbp: 0,
-            r8: 0, r9: 0, r10: 0, r11: 0,
-            r12: 0, r13: 0, r14: 0, r15: 0,
-            rflags: 0, rip: 0, rsp: 0,
-        }
+    /// デフォルトの時間量子を変更する（起動時に一度だけ呼ぶ）
+    pub fn set_default(ticks: u8) {
+        BOOT_QUANTUM.store(ticks, Ordering::Relaxed);
     }
-}
 
-impl Default for StackFrame {
-    fn default() -> Self {
-        Self::new()
+    /// 現在のデフォルトの時間量子
+    pub fn current_default() -> u8 {
+        BOOT_QUANTUM.load(Ordering::Relaxed)
     }
 }
 
@@ -182,6 +234,50 @@ pub struct Process {
     
     /// プロセス名
     pub name: [u8; 16],
+
+    /// 送信待ちの相手（SENDING のとき）
+    /// MINIX 3: p_sendto_e
+    pub send_to: Option<ProcessId>,
+
+    /// 受信待ちの相手（RECEIVING のとき、ANY なら誰からでも）
+    /// MINIX 3: p_getfrom_e
+    pub receive_from: Option<ProcessId>,
+
+    /// アドレス空間（カーネルタスクはカーネルのページテーブルを使う）
+    /// MINIX 3: p_seg.p_cr3
+    pub memory: MemoryMap,
+
+    /// ring 3 から入ったときに使うカーネルスタックの一番上（0ならカーネルタスクで、起動時のスタックのまま）
+    /// Linux: thread.sp0
+    pub kernel_stack: u64,
+
+    /// 送信待ちの間、送るメッセージを置いておく（`ipc::MESSAGE_CACHE` から確保し、渡したら返す）
+    /// MINIX 3: p_sendmsg
+    pub send_message: Option<SlabBox<Message, KernelPages>>,
+
+    /// 届いたメッセージ（次にユーザーモードに戻るときに `receive_buffer` に書く）
+    /// MINIX 3: p_delivermsg
+    pub deliver_message: Message,
+
+    /// `deliver_message` をまだユーザーのメモリに書いていない
+    /// MINIX 3: p_misc_flags の MF_DELIVERMSG
+    pub delivery_pending: bool,
+
+    /// 受信したメッセージを書くユーザーのアドレス
+    /// MINIX 3: p_delivermsg_vir
+    pub receive_buffer: u64,
+
+    /// 通知を送ってきたプロセスのスロット番号のビットマップ（`ipc::NR_NOTIFY_SOURCES` 個まで）
+    /// MINIX 3: priv の s_notify_pending
+    pub notify_pending: u64,
+
+    /// このプロセスに送ろうとして待っている最初のプロセス（スロット番号）
+    /// MINIX 3: p_caller_q
+    pub caller_q: Option<usize>,
+
+    /// 同じ相手に送ろうとして待っている次のプロセス（スロット番号）
+    /// MINIX 3: p_q_link
+    pub q_link: Option<usize>,
 }
 
 impl Process {
@@ -196,6 +292,17 @@ impl Process {
             ticks_left: Quantum::DEFAULT,
             quantum_size: Quantum::DEFAULT,
             name: [0; 16],
+            send_to: None,
+            receive_from: None,
+            memory: MemoryMap::new(),
+            kernel_stack: 0,
+            send_message: None,
+            deliver_message: Message::new(0),
+            delivery_pending: false,
+            receive_buffer: 0,
+            notify_pending: 0,
+            caller_q: None,
+            q_link: None,
         }
     }
     
@@ -203,7 +310,36 @@ impl Process {
     pub fn is_runnable(&self) -> bool {
         self.flags.is_runnable()
     }
+
+    /// 時間量子を現在のデフォルト値に戻す
+    /// （const fnの new() では Quantum::DEFAULT しか使えないため）
+    pub fn reset_quantum(&mut self) {
+        self.set_quantum(Quantum::current_default());
+    }
+
+    /// 時間量子を `ticks` にして、残り時間も揃える
+    pub fn set_quantum(&mut self, ticks: u8) {
+        self.quantum_size = ticks;
+        self.ticks_left = ticks;
+    }
     
+    /// 用意ができたアドレス空間を設定して、NO_MAP を外す
+    /// MINIX 3: VMサーバーがマップを作り終えると RTS_UNSET(rp, RTS_VMINHIBIT) する
+    pub fn set_memory(&mut self, memory: MemoryMap) {
+        self.memory = memory;
+        self.flags.clear(ProcessFlags::NO_MAP);
+    }
+
+    /// 別のプログラムに取り替えた後の状態にする（exec、アドレス空間は呼び出し側が取り替える）
+    ///
+    /// レジスタは `registers` から始め直し、名前も変える。プロセス番号、優先度とその上限（特権）、
+    /// 時間量子、IPCの状態はそのまま残す。
+    /// MINIX 3: kernel/system/do_exec.c（arch_proc_init() でレジスタを作り直し、p_name を変える）
+    pub fn exec(&mut self, name: &str, registers: StackFrame) {
+        self.registers = registers;
+        self.set_name(name);
+    }
+
     /// 名前を設定
     pub fn set_name(&mut self, name: &str) {
         let bytes = name.as_bytes();
@@ -221,55 +357,392 @@ impl Process {
 
 /// プロセステーブル
 /// MINIX 3の proc[] 配列に相当
-pub struct ProcessTable {
-    processes: [UnsafeCell<Process>; MAX_PROCESSES],
+///
+/// 先頭の `BOOT_PROCESSES` 個は静的な配列に置き、それより後ろのスロットは必要になったときに
+/// 1つずつ `ObjectCache` から確保する。スロット番号は一度作ったら変わらない（スケジューラの
+/// キューはスロット番号でつなぐ）。空いたスロットはメモリを返さずに使い回す。
+///
+/// 後ろのスロットへのポインタは2段のディレクトリに置く。`CHUNK_SLOTS` 個ずつのチャンクは
+/// そこまでスロットが増えたときにヒープから確保するので、使わない容量の分のメモリは要らない。
+///
+/// MINIX 3: NR_PROCS はコンパイル時に決まる（include/minix/config.h）
+/// Linux: pid_max / threads-max は起動時やsysctlで変えられる
+pub struct ProcessTable<P: SlabPages + 'static = FramePages> {
+    processes: [UnsafeCell<Process>; BOOT_PROCESSES],
+    /// `BOOT_PROCESSES` 番以降のスロットのチャンク（確保していなければnull）
+    extra: [AtomicPtr<Chunk>; DIRECTORY_CHUNKS],
+    /// 作ったスロットの数（`extra` はこの手前まで埋まっている）
+    len: AtomicUsize,
+    /// 作ってよいスロットの数
+    capacity: AtomicUsize,
+    /// 後ろのスロットを確保するキャッシュ
+    cache: &'static ObjectCache<Process, P>,
+    /// スロットを増やす処理を1つずつにする
+    grow_lock: SpinLock<()>,
 }
 
 impl ProcessTable {
-    /// 新しいプロセステーブルを作成
+    /// 新しいプロセステーブルを作成（容量は `BOOT_PROCESSES`、増やすときは `PROCESS_CACHE` から）
     pub const fn new() -> Self {
+        Self::with_cache(&PROCESS_CACHE)
+    }
+}
+
+impl<P: SlabPages + 'static> ProcessTable<P> {
+    /// 後ろのスロットを `cache` から確保するプロセステーブルを作成
+    pub const fn with_cache(cache: &'static ObjectCache<Process, P>) -> Self {
         // 配列の初期化（const fn で配列を初期化するためのパターン）
         const EMPTY_PROCESS: UnsafeCell<Process> = UnsafeCell::new(Process::new(0));
         Self {
-            processes: [EMPTY_PROCESS; MAX_PROCESSES],
+            processes: [EMPTY_PROCESS; BOOT_PROCESSES],
+            extra: [const { AtomicPtr::new(ptr::null_mut()) }; DIRECTORY_CHUNKS],
+            len: AtomicUsize::new(BOOT_PROCESSES),
+            capacity: AtomicUsize::new(BOOT_PROCESSES),
+            cache,
+            grow_lock: SpinLock::new(()),
         }
     }
-    
+
+    /// 作ってよいスロットの数を変える（起動時に一度呼ぶ）
+    ///
+    /// `BOOT_PROCESSES` から `PROCESS_LIMIT` の範囲にそろえ、作ったスロットより少なくはしない。
+    /// 実際に使う容量を返す。
+    pub fn set_capacity(&self, capacity: usize) -> usize {
+        let _guard = self.grow_lock.lock();
+        let capacity = capacity.clamp(self.slot_count(), PROCESS_LIMIT);
+        self.capacity.store(capacity, Ordering::Relaxed);
+        capacity
+    }
+
+    /// 作ってよいスロットの数
+    pub fn capacity(&self) -> usize {
+        self.capacity.load(Ordering::Relaxed)
+    }
+
+    /// 作ったスロットの数（空きも含む）
+    pub fn slot_count(&self) -> usize {
+        self.len.load(Ordering::Acquire)
+    }
+
     /// プロセスを取得（可変参照）
     pub fn get_mut(&self, index: usize) -> Option<&mut Process> {
-        if index < MAX_PROCESSES {
+        if index < BOOT_PROCESSES {
             // フラグを確認してスロットが使用中かチェック
             unsafe { Some(&mut *self.processes[index].get()) }
+        } else if index < self.slot_count() {
+            let (chunk, offset) = Self::locate(index);
+            let chunk = unsafe { self.extra[chunk].load(Ordering::Acquire).as_ref()? };
+            unsafe { chunk[offset].load(Ordering::Acquire).as_mut() }
         } else {
             None
         }
     }
-    
-    /// 空きスロットを探す
+
+    /// すべてのスロット（空きも含む）をスロット番号と一緒に列挙する
+    /// MINIX 3: for (rp = BEG_PROC_ADDR; rp < END_PROC_ADDR; ++rp)
+    pub fn slots(&self) -> impl Iterator<Item = (usize, &Process)> {
+        (0..self.slot_count()).filter_map(|i| self.get_mut(i).map(|process| (i, &*process)))
+    }
+
+    /// 使用中のスロットからプロセス番号で探す
+    pub fn find(&self, pid: ProcessId) -> Option<usize> {
+        self.slots()
+            .find(|(_, process)| !process.flags.is_set(ProcessFlags::SLOT_FREE) && process.pid == pid)
+            .map(|(slot, _)| slot)
+    }
+
+    /// 空きスロットを探す（なければ容量の範囲でスロットを1つ増やす）
     pub fn find_free_slot(&self) -> Option<usize> {
-        for (i, cell) in self.processes.iter().enumerate() {
-            unsafe {
-                let proc = &*cell.get();
-                if proc.flags.is_set(ProcessFlags::SLOT_FREE) || proc.pid == 0 && i > 0 {
-                    return Some(i);
+        let free = self
+            .slots()
+            .find(|&(i, proc)| proc.flags.is_set(ProcessFlags::SLOT_FREE) || proc.pid == 0 && i > 0)
+            .map(|(i, _)| i);
+        free.or_else(|| self.grow())
+    }
+
+    /// `BOOT_PROCESSES` 番以降のスロット `index` のチャンク番号とチャンク内の位置
+    fn locate(index: usize) -> (usize, usize) {
+        let index = index - BOOT_PROCESSES;
+        (index / CHUNK_SLOTS, index % CHUNK_SLOTS)
+    }
+
+    /// スロットを1つ増やして、その番号を返す（新しいスロットは空き）
+    fn grow(&self) -> Option<usize> {
+        let _guard = self.grow_lock.lock();
+        let index = self.slot_count();
+        if index >= self.capacity() {
+            return None;
+        }
+        let (chunk, offset) = Self::locate(index);
+        let mut slots = self.extra[chunk].load(Ordering::Acquire);
+        if slots.is_null() {
+            // すべてnull（0）のポインタで埋まったチャンク
+            slots = unsafe { alloc::alloc::alloc_zeroed(Layout::new::<Chunk>()) }.cast::<Chunk>();
+            if slots.is_null() {
+                return None;
+            }
+            self.extra[chunk].store(slots, Ordering::Release);
+        }
+        let process = self.cache.alloc_uninit()?;
+        let mut empty = Process::new(0);
+        empty.flags.set(ProcessFlags::SLOT_FREE);
+        empty.reset_quantum();
+        unsafe {
+            process.write(empty);
+            (*slots)[offset].store(process.as_ptr(), Ordering::Release);
+        }
+        self.len.store(index + 1, Ordering::Release);
+        Some(index)
+    }
+}
+
+/// 増やしたスロットのプロセスをdropしてキャッシュに返し、チャンクをヒープに返す（静的なテーブルはdropしない）
+impl<P: SlabPages + 'static> Drop for ProcessTable<P> {
+    fn drop(&mut self) {
+        for chunk in &self.extra {
+            let Some(slots) = NonNull::new(chunk.load(Ordering::Relaxed)) else {
+                continue;
+            };
+            for process in unsafe { slots.as_ref() } {
+                if let Some(process) = NonNull::new(process.load(Ordering::Relaxed)) {
+                    unsafe {
+                        ptr::drop_in_place(process.as_ptr());
+                        self.cache.free_uninit(process);
+                    }
                 }
             }
+            unsafe { alloc::alloc::dealloc(slots.as_ptr().cast(), Layout::new::<Chunk>()) };
         }
-        None
     }
 }
 
 // 安全性: ProcessTableはシングルスレッド環境でのみ使用される
-unsafe impl Sync for ProcessTable {}
+unsafe impl<P: SlabPages + 'static> Sync for ProcessTable<P> {}
+
+/// プロセステーブルのダンプ（使用中のスロットを1行ずつ、空きスロットは最後にまとめる）
+/// MINIX 3: IS の proctab_dmp()（F1キー）
+///
+/// ```text
+/// SLOT   PID NAME             FLAGS                 PRI  MAX  USED QUANT IPC
+///    0    -1 monitor          -                      14   14     0     8
+///    2     5 sh               RECEIVING               7    7     3     8 <- ANY
+/// free slots: 1, 3-15
+/// ```
+impl<P: SlabPages + 'static> fmt::Display for ProcessTable<P> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{:>4} {:>5} {:<16} {:<20} {:>4} {:>4} {:>5} {:>5} IPC",
+            "SLOT", "PID", "NAME", "FLAGS", "PRI", "MAX", "USED", "QUANT"
+        )?;
+        for (slot, process) in self.slots().filter(|(_, p)| !p.flags.is_set(ProcessFlags::SLOT_FREE)) {
+            write!(
+                f,
+                "\n{:>4} {:>5} {:<16} {:<20} {:>4} {:>4} {:>5} {:>5}",
+                slot,
+                process.pid,
+                process.name_str(),
+                process.flags,
+                process.priority.value(),
+                process.max_priority.value(),
+                process.quantum_size.saturating_sub(process.ticks_left),
+                process.quantum_size
+            )?;
+            if let (true, Some(to)) = (process.flags.is_set(ProcessFlags::SENDING), process.send_to) {
+                write!(f, " -> {}", to)?;
+            }
+            if let (true, Some(from)) = (process.flags.is_set(ProcessFlags::RECEIVING), process.receive_from) {
+                match from {
+                    ANY => write!(f, " <- ANY")?,
+                    from => write!(f, " <- {}", from)?,
+                }
+            }
+        }
+
+        // 連続した空きスロットは `3-15` のようにまとめる
+        let mut free = self.slots().filter(|(_, p)| p.flags.is_set(ProcessFlags::SLOT_FREE)).map(|(slot, _)| slot).peekable();
+        let mut first = true;
+        while let Some(start) = free.next() {
+            let mut end = start;
+            while free.next_if_eq(&(end + 1)).is_some() {
+                end += 1;
+            }
+            f.write_str(if first { "\nfree slots: " } else { ", " })?;
+            if start == end {
+                write!(f, "{}", start)?;
+            } else {
+                write!(f, "{}-{}", start, end)?;
+            }
+            first = false;
+        }
+        Ok(())
+    }
+}
+
+/// `spawn()` / `fork()` の失敗
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SpawnError {
+    /// 空きスロットがない（容量まで使っている）
+    TableFull,
+    /// プロセス番号が0やANY、またはほかのプロセスが使っている
+    BadPid(ProcessId),
+    /// アドレス空間を作れなかった
+    Map(MapError),
+    /// プログラムを読み込めなかった
+    Elf(ElfError),
+}
+
+impl From<MapError> for SpawnError {
+    fn from(error: MapError) -> Self {
+        Self::Map(error)
+    }
+}
+
+impl From<ElfError> for SpawnError {
+    fn from(error: ElfError) -> Self {
+        Self::Elf(error)
+    }
+}
+
+impl fmt::Display for SpawnError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::TableFull => write!(f, "process table full"),
+            Self::BadPid(pid) => write!(f, "pid {} is invalid or already in use", pid),
+            Self::Map(error) => write!(f, "cannot create address space: {}", error),
+            Self::Elf(error) => write!(f, "cannot load program: {}", error),
+        }
+    }
+}
+
+/// 空きスロットにユーザープロセスを作る（スロット番号を返す）
+///
+/// アドレス空間は `create_map` で作る（カーネルでは `memory::vm::create`、カーネルの半分を共有する）。
+/// 作っている間は NO_MAP を立てておくので、先にスケジューラに入れられても実行可能にならない。
+/// MINIX 3: PMの fork → VMの vm_fork()（できるまで子は RTS_VMINHIBIT）
+pub fn spawn<P: SlabPages + 'static>(
+    table: &ProcessTable<P>,
+    pid: ProcessId,
+    name: &str,
+    create_map: impl FnOnce() -> Result<MemoryMap, MapError>,
+) -> Result<usize, SpawnError> {
+    let slot = table.find_free_slot().ok_or(SpawnError::TableFull)?;
+    let process = table.get_mut(slot).ok_or(SpawnError::TableFull)?;
+    *process = Process::new(pid);
+    process.set_name(name);
+    process.reset_quantum();
+    process.flags.set(ProcessFlags::NO_MAP);
+    match create_map() {
+        Ok(memory) => {
+            process.set_memory(memory);
+            Ok(slot)
+        }
+        Err(error) => {
+            process.flags.set(ProcessFlags::SLOT_FREE);
+            Err(SpawnError::Map(error))
+        }
+    }
+}
+
+/// `parent` を複製した子プロセスを空きスロットに作る（スロット番号を返す）
+///
+/// レジスタ、優先度とその上限（特権）、時間量子、名前を引き継ぐ。IPCの状態（送受信待ち、
+/// 届いたメッセージ、通知）は引き継がない。アドレス空間は `fork_map` で作る
+/// （カーネルでは `memory::vm::fork`、ページをコピーオンライトで共有する）。
+/// 子のレジスタは親と同じなので、fork の戻り値を変えるのは呼び出し側。
+/// MINIX 3: kernel/system/do_fork.c（PMが子のプロセス番号を決めて SYS_FORK を呼ぶ）
+pub fn fork<P: SlabPages + 'static>(
+    table: &ProcessTable<P>,
+    parent: &Process,
+    pid: ProcessId,
+    fork_map: impl FnOnce(&MemoryMap) -> Result<MemoryMap, MapError>,
+) -> Result<usize, SpawnError> {
+    if pid <= 0 || pid == ANY || table.find(pid).is_some() {
+        return Err(SpawnError::BadPid(pid));
+    }
+    let slot = table.find_free_slot().ok_or(SpawnError::TableFull)?;
+    let child = table.get_mut(slot).ok_or(SpawnError::TableFull)?;
+    *child = Process::new(pid);
+    child.registers = parent.registers;
+    child.priority = parent.priority;
+    child.max_priority = parent.max_priority;
+    child.quantum_size = parent.quantum_size;
+    child.ticks_left = parent.ticks_left;
+    child.name = parent.name;
+    child.flags.set(ProcessFlags::NO_MAP);
+    match fork_map(&parent.memory) {
+        Ok(memory) => {
+            child.set_memory(memory);
+            Ok(slot)
+        }
+        Err(error) => {
+            child.flags.set(ProcessFlags::SLOT_FREE);
+            Err(SpawnError::Map(error))
+        }
+    }
+}
 
 // グローバルプロセステーブル
 #[no_mangle]
 pub static PROCESS_TABLE: ProcessTable = ProcessTable::new();
 
+/// 実行中のプロセスがないことを表す値
+const NO_CURRENT: usize = usize::MAX;
+
+/// 実行中のプロセスのスロット番号
+/// MINIX 3: proc_ptr（現在実行中のプロセスへのポインタ）
+static CURRENT: AtomicUsize = AtomicUsize::new(NO_CURRENT);
+
+/// 実行中のプロセスを設定する（None はカーネル自身が動いている状態）
+///
+/// コンテキストスイッチの入口で、次のプロセスのアドレス空間とカーネルスタックに切り替える。
+/// MINIX 3: switch_to_user() の前の switch_address_space()
+pub fn set_current(index: Option<usize>) {
+    CURRENT.store(index.unwrap_or(NO_CURRENT), Ordering::Relaxed);
+    #[cfg(any(not(test), target_os = "none"))]
+    crate::memory::vm::activate(current().and_then(|process| process.memory.root()));
+    if let Some(process) = current().filter(|process| process.kernel_stack != 0) {
+        crate::arch::set_kernel_stack(process.kernel_stack);
+    }
+}
+
+/// 実行中のプロセスのスロット番号
+pub fn current_slot() -> Option<usize> {
+    match CURRENT.load(Ordering::Relaxed) {
+        NO_CURRENT => None,
+        index => Some(index),
+    }
+}
+
+/// 実行中のプロセス
+pub fn current() -> Option<&'static Process> {
+    current_slot().and_then(|index| PROCESS_TABLE.get_mut(index)).map(|process| &*process)
+}
+
+/// スケジューラの状態（実行可能キュー）
+/// MINIX 3: rdy_head[] / rdy_tail[] はグローバル変数だった
+pub static SCHEDULER: SpinLock<Scheduler> = SpinLock::new(Scheduler::new());
+
+/// プロセス構造体のキャッシュ（`BOOT_PROCESSES` を超えるスロットに使う）
+pub static PROCESS_CACHE: ObjectCache<Process> = ObjectCache::new("process");
+
 /// スケジューリングキューの数
 /// MINIX 3: NR_SCHED_QUEUES = 16
 pub const NR_SCHED_QUEUES: usize = 16;
 
+/// `next_ready` で次がないことを表す値
+const NO_NEXT: u32 = u32::MAX;
+const _: () = assert!(PROCESS_LIMIT <= NO_NEXT as usize, "slot numbers must fit in u32");
+
+/// スケジューリング方式（コマンドラインの sched= で選ぶ）
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SchedPolicy {
+    /// MINIX 3と同じ多段階優先度キュー（デフォルト）
+    Priority,
+    /// 優先度を無視して全プロセスをUSER_Qで順番に回す（比較用）
+    RoundRobin,
+}
+
 /// スケジューラ
 /// MINIX 3の proc.c の enqueue, dequeue, pick_proc, sched を再現
 /// 
@@ -277,10 +750,25 @@ pub const NR_SCHED_QUEUES: usize = 16;
 /// - rdy_head[q]: 優先度qのキューの先頭
 /// - rdy_tail[q]: 優先度qのキューの末尾
 /// - pick_proc(): 優先度0から順にキューをチェック
+///
+/// 大きいのでコピーしない（ダンプはロックを持ったまま書く）。
 pub struct Scheduler {
     /// 各優先度のキューの先頭プロセスインデックス
     /// MINIX 3: struct proc *rdy_head[NR_SCHED_QUEUES]
     rdy_head: [Option<usize>; NR_SCHED_QUEUES],
+
+    /// 各優先度のキューの末尾（追加はここから）
+    /// MINIX 3: struct proc *rdy_tail[NR_SCHED_QUEUES]
+    rdy_tail: [Option<usize>; NR_SCHED_QUEUES],
+
+    /// 同じキューで次に並んでいるプロセス（なければ NO_NEXT）
+    /// MINIX 3: struct proc の p_nextready
+    ///
+    /// スロット番号で引く。プロセステーブルの容量に合わせて `reserve()` で伸ばしておく。
+    next_ready: Vec<u32>,
+
+    /// スケジューリング方式
+    policy: SchedPolicy,
 }
 
 impl Scheduler {
@@ -289,35 +777,122 @@ impl Scheduler {
     pub const fn new() -> Self {
         Self {
             rdy_head: [None; NR_SCHED_QUEUES],
+            rdy_tail: [None; NR_SCHED_QUEUES],
+            next_ready: Vec::new(),
+            policy: SchedPolicy::Priority,
+        }
+    }
+
+    /// スケジューリング方式を設定
+    pub fn set_policy(&mut self, policy: SchedPolicy) {
+        self.policy = policy;
+    }
+
+    /// 現在のスケジューリング方式
+    pub fn policy(&self) -> SchedPolicy {
+        self.policy
+    }
+
+    /// スロット番号 `slots` 個分までのプロセスを並べられるようにする（起動時にテーブルの容量で呼ぶ）
+    ///
+    /// `enqueue()` でも足りなければ伸ばすが、割り込みハンドラからヒープを使わずに済むよう前もって確保しておく。
+    pub fn reserve(&mut self, slots: usize) {
+        let slots = slots.min(PROCESS_LIMIT);
+        if self.next_ready.len() < slots {
+            self.next_ready.resize(slots, NO_NEXT);
+        }
+    }
+
+    /// 同じキューで `index` の次に並んでいるプロセス
+    fn next(&self, index: usize) -> Option<usize> {
+        match self.next_ready[index] {
+            NO_NEXT => None,
+            next => Some(next as usize),
+        }
+    }
+
+    fn set_next(&mut self, index: usize, next: Option<usize>) {
+        // PROCESS_LIMIT は u32 に収まる（enqueue() で範囲を確かめている）
+        self.next_ready[index] = next.map_or(NO_NEXT, |next| next as u32);
+    }
+
+    /// 優先度から実際に使うキューを決める
+    fn queue_of(&self, priority: u8) -> usize {
+        match self.policy {
+            SchedPolicy::Priority => priority as usize,
+            SchedPolicy::RoundRobin => Priority::USER_Q as usize,
         }
     }
     
-    /// プロセスを実行可能キューに追加
+    /// プロセスを実行可能キューの末尾に追加
     /// MINIX 3: enqueue() - proc.c
     /// 
     /// # 引数
     /// - `process_index`: プロセステーブル内のインデックス
     /// - `priority`: 優先度（0=最高、15=最低）
+    ///
+    /// すでにどこかのキューに入っていれば何もしない。
     pub fn enqueue(&mut self, process_index: usize, priority: u8) {
-        let q = priority as usize;
-        if q < NR_SCHED_QUEUES {
-            // MINIX 3: rdy_head[q] = rp
-            self.rdy_head[q] = Some(process_index);
+        let q = self.queue_of(priority);
+        if q >= NR_SCHED_QUEUES || process_index >= PROCESS_LIMIT || self.contains(process_index) {
+            return;
+        }
+        self.reserve(process_index + 1);
+        // MINIX 3: rdy_tail[q]->p_nextready = rp; rdy_tail[q] = rp
+        self.set_next(process_index, None);
+        match self.rdy_tail[q] {
+            Some(tail) => self.set_next(tail, Some(process_index)),
+            None => self.rdy_head[q] = Some(process_index),
         }
+        self.rdy_tail[q] = Some(process_index);
     }
     
-    /// プロセスをキューから削除
+    /// プロセスをキューから削除（入っていなければfalse）
     /// MINIX 3: dequeue() - proc.c
     /// 
     /// # 引数
     /// - `process_index`: 削除するプロセスのインデックス
     /// - `priority`: 優先度
-    pub fn dequeue(&mut self, _process_index: usize, priority: u8) {
-        let q = priority as usize;
-        if q < NR_SCHED_QUEUES {
-            // MINIX 3: キューから削除
-            self.rdy_head[q] = None;
+    pub fn dequeue(&mut self, process_index: usize, priority: u8) -> bool {
+        let q = self.queue_of(priority);
+        if q >= NR_SCHED_QUEUES {
+            return false;
+        }
+        // MINIX 3: xpp = &rdy_head[q] から p_nextready をたどって外す
+        let mut prev = None;
+        let mut cursor = self.rdy_head[q];
+        while let Some(index) = cursor {
+            if index == process_index {
+                let next = self.next(index);
+                self.set_next(index, None);
+                match prev {
+                    Some(prev) => self.set_next(prev, next),
+                    None => self.rdy_head[q] = next,
+                }
+                if self.rdy_tail[q] == Some(index) {
+                    self.rdy_tail[q] = prev;
+                }
+                return true;
+            }
+            prev = cursor;
+            cursor = self.next(index);
         }
+        false
+    }
+
+    /// 優先度 `q` のキューの先頭
+    pub fn head(&self, q: usize) -> Option<usize> {
+        self.rdy_head.get(q).copied().flatten()
+    }
+
+    /// 優先度 `q` のキューに並んでいるプロセスを先頭から順に
+    pub fn queue(&self, q: usize) -> impl Iterator<Item = usize> + '_ {
+        core::iter::successors(self.head(q), |&index| self.next(index))
+    }
+
+    /// どこかのキューに入っているか
+    pub fn contains(&self, process_index: usize) -> bool {
+        (0..NR_SCHED_QUEUES).any(|q| self.queue(q).any(|index| index == process_index))
     }
     
     /// 次に実行するプロセスを選択
@@ -346,8 +921,34 @@ impl Scheduler {
     }
 }
 
+/// 実行可能キューのダンプ（空でないキューだけ、並んでいるスロット番号を先頭から）
+/// MINIX 3: IS の sched_dmp()
+///
+/// ```text
+/// policy: Priority
+/// q7: 3 5 1
+/// q14: 0
+/// ```
+impl fmt::Display for Scheduler {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "policy: {:?}", self.policy)?;
+        let mut empty = true;
+        for q in (0..NR_SCHED_QUEUES).filter(|&q| self.head(q).is_some()) {
+            write!(f, "\nq{}:", q)?;
+            for index in self.queue(q) {
+                write!(f, " {}", index)?;
+            }
+            empty = false;
+        }
+        if empty {
+            f.write_str("\n(no runnable processes)")?;
+        }
+        Ok(())
+    }
+}
+
 // ===== テスト =====
-#[cfg(test)]
+#[cfg(all(test, not(target_os = "none")))]
 mod tests {
     use super::*;
 
@@ -405,6 +1006,19 @@ mod tests {
             assert!(flags.is_runnable(), "クリア後は実行可能であるべき");
         }
 
+        #[test]
+        fn test_flags_display() {
+            let mut flags = ProcessFlags::new();
+            assert_eq!(flags.to_string(), "-", "実行可能");
+            flags.set(ProcessFlags::SENDING);
+            flags.set(ProcessFlags::RECEIVING);
+            assert_eq!(flags.to_string(), "SENDING|RECEIVING", "MINIX 3のsendrec()の途中");
+            flags.set(0x40);
+            assert_eq!(flags.to_string(), "SENDING|RECEIVING|0x40", "名前のないビットは16進数");
+            assert_eq!(format!("[{:<10}]", ProcessFlags(ProcessFlags::SLOT_FREE)), "[SLOT_FREE ]", "幅指定が効く");
+            assert_eq!(ProcessFlags(ProcessFlags::NO_MAP).to_string(), "NO_MAP");
+        }
+
         #[test]
         fn test_slot_free_flag() {
             // SLOT_FREEフラグのテスト
@@ -480,6 +1094,30 @@ mod tests {
             assert!(process.name_str().len() <= 15, "名前は15文字以下であるべき");
         }
 
+        #[test]
+        fn test_set_quantum() {
+            // reset_quantum() は quantum= で変更されたデフォルト値でこれを呼ぶ
+            // （他のテストと並行に走るので、ここでは BOOT_QUANTUM を書き換えない）
+            let mut process = Process::new(1);
+            assert_eq!(process.quantum_size, Quantum::DEFAULT, "new()はコンパイル時のデフォルト");
+
+            process.set_quantum(3);
+
+            assert_eq!(process.quantum_size, 3, "指定した時間量子に変わるべき");
+            assert_eq!(process.ticks_left, 3, "残り時間も揃えるべき");
+        }
+
+        #[test]
+        fn test_set_memory_clears_no_map() {
+            let mut process = Process::new(1);
+            process.flags.set(ProcessFlags::NO_MAP);
+            assert!(!process.is_runnable(), "マップができるまでは動かない");
+
+            process.set_memory(MemoryMap::with_root(crate::memory::Frame::from_number(9)));
+            assert!(process.is_runnable());
+            assert_eq!(process.memory.root(), Some(crate::memory::Frame::from_number(9)));
+        }
+
         #[test]
         fn test_process_flags_blocking() {
             let mut process = Process::new(1);
@@ -506,8 +1144,8 @@ mod tests {
         fn test_get_mut_out_of_bounds() {
             let table = ProcessTable::new();
             
-            assert!(table.get_mut(MAX_PROCESSES).is_none(), "範囲外はNoneであるべき");
-            assert!(table.get_mut(MAX_PROCESSES + 1).is_none(), "範囲外はNoneであるべき");
+            assert!(table.get_mut(BOOT_PROCESSES).is_none(), "範囲外はNoneであるべき");
+            assert!(table.get_mut(BOOT_PROCESSES + 1).is_none(), "範囲外はNoneであるべき");
         }
 
         #[test]
@@ -519,6 +1157,97 @@ mod tests {
             assert!(slot.is_some(), "空きスロットが見つかるべき");
         }
 
+        #[test]
+        fn test_slots() {
+            let table = ProcessTable::new();
+            table.get_mut(3).unwrap().pid = 7;
+            assert_eq!(table.slots().count(), BOOT_PROCESSES, "空きスロットも含めて列挙する");
+            assert_eq!(table.slots().find(|(_, p)| p.pid == 7).map(|(i, _)| i), Some(3));
+        }
+
+        #[test]
+        fn test_spawn() {
+            let table = ProcessTable::new();
+            let root = crate::memory::Frame::from_number(0x40);
+            let slot = spawn(&table, 10, "init", || {
+                // アドレス空間を作っている間は NO_MAP
+                assert!(table.get_mut(1).unwrap().flags.is_set(ProcessFlags::NO_MAP));
+                Ok(MemoryMap::with_root(root))
+            });
+            assert_eq!(slot, Ok(1), "スロット0は使用中とみなす");
+            let process = table.get_mut(1).unwrap();
+            assert_eq!((process.pid, process.name_str()), (10, "init"));
+            assert!(process.is_runnable(), "マップができたら NO_MAP を外す");
+            assert_eq!(process.memory.root(), Some(root));
+
+            assert_eq!(spawn(&table, 11, "sh", || Err(MapError::OutOfFrames)), Err(SpawnError::Map(MapError::OutOfFrames)));
+            assert!(table.get_mut(2).unwrap().flags.is_set(ProcessFlags::SLOT_FREE), "失敗したらスロットを空ける");
+            assert_eq!(
+                SpawnError::Map(MapError::OutOfFrames).to_string(),
+                "cannot create address space: out of page table frames"
+            );
+        }
+
+        #[test]
+        fn test_fork() {
+            let table = ProcessTable::new();
+            let parent_root = crate::memory::Frame::from_number(0x40);
+            let parent_slot = spawn(&table, 10, "sh", || Ok(MemoryMap::with_root(parent_root))).unwrap();
+            let parent = table.get_mut(parent_slot).unwrap();
+            parent.registers.rip = 0x40_1000;
+            parent.max_priority = Priority::new(Priority::USER_Q - 2);
+            parent.priority = Priority::new(Priority::USER_Q - 1);
+            parent.flags.set(ProcessFlags::RECEIVING);
+            parent.receive_from = Some(ANY);
+
+            let child_root = crate::memory::Frame::from_number(0x80);
+            let slot = fork(&table, parent, 11, |memory| {
+                assert_eq!(memory.root(), Some(parent_root), "親のアドレス空間から作る");
+                Ok(MemoryMap::with_root(child_root))
+            })
+            .unwrap();
+            let child = table.get_mut(slot).unwrap();
+            assert_ne!(slot, parent_slot);
+            assert_eq!((child.pid, child.name_str(), child.registers.rip), (11, "sh", 0x40_1000));
+            assert_eq!((child.priority, child.max_priority), (parent.priority, parent.max_priority), "特権も引き継ぐ");
+            assert_eq!(child.memory.root(), Some(child_root));
+            assert!(child.is_runnable(), "IPCの待ちは引き継がない");
+            assert_eq!(child.receive_from, None);
+        }
+
+        #[test]
+        fn test_exec_keeps_pid_and_privileges() {
+            let mut process = Process::new(10);
+            process.set_name("long-program-name");
+            process.max_priority = Priority::new(Priority::USER_Q - 2);
+            process.priority = Priority::new(Priority::USER_Q - 1);
+            process.registers.rax = 42;
+            let memory = process.memory;
+
+            let mut registers = StackFrame::new();
+            registers.rip = 0x40_1000;
+            process.exec("sh", registers);
+            assert_eq!(process.name_str(), "sh", "前の長い名前は残らない");
+            assert_eq!((process.registers.rip, process.registers.rax), (0x40_1000, 0), "レジスタは始め直す");
+            assert_eq!(process.pid, 10);
+            assert_eq!(process.max_priority, Priority::new(Priority::USER_Q - 2), "特権はそのまま");
+            assert_eq!(process.priority, Priority::new(Priority::USER_Q - 1));
+            assert_eq!(process.memory, memory, "アドレス空間は取り替えない");
+        }
+
+        #[test]
+        fn test_fork_errors() {
+            let table = ProcessTable::new();
+            let slot = spawn(&table, 10, "sh", || Ok(MemoryMap::new())).unwrap();
+            let parent = table.get_mut(slot).unwrap();
+            for pid in [0, -1, ANY, 10] {
+                assert_eq!(fork(&table, parent, pid, |_| Ok(MemoryMap::new())), Err(SpawnError::BadPid(pid)));
+            }
+            assert_eq!(fork(&table, parent, 11, |_| Err(MapError::OutOfFrames)), Err(SpawnError::Map(MapError::OutOfFrames)));
+            assert_eq!(table.find(11), None, "失敗したらスロットを空ける");
+            assert_eq!(SpawnError::BadPid(10).to_string(), "pid 10 is invalid or already in use");
+        }
+
         #[test]
         fn test_process_table_modify() {
             let table = ProcessTable::new();
@@ -537,6 +1266,156 @@ mod tests {
         }
     }
 
+    /// スロットを増やすテスト
+    mod growth_tests {
+        use super::*;
+        use crate::memory::slab::HostPages;
+
+        static CACHE: ObjectCache<Process, HostPages> = ObjectCache::new("process");
+
+        /// 起動時と同じく、起動用のスロットをすべて空きにしたテーブル
+        fn table(capacity: usize) -> ProcessTable<HostPages> {
+            let table = ProcessTable::with_cache(&CACHE);
+            for slot in 0..BOOT_PROCESSES {
+                table.get_mut(slot).unwrap().flags.set(ProcessFlags::SLOT_FREE);
+            }
+            assert_eq!(table.set_capacity(capacity), capacity);
+            table
+        }
+
+        fn spawn(table: &ProcessTable<HostPages>, pid: ProcessId) -> Option<usize> {
+            let slot = table.find_free_slot()?;
+            *table.get_mut(slot)? = Process::new(pid);
+            Some(slot)
+        }
+
+        #[test]
+        fn test_capacity() {
+            let table = ProcessTable::with_cache(&CACHE);
+            assert_eq!(table.capacity(), BOOT_PROCESSES, "最初は起動用のスロットだけ");
+            assert_eq!(table.set_capacity(1), BOOT_PROCESSES, "起動用のスロットより少なくはできない");
+            assert_eq!(table.set_capacity(usize::MAX), PROCESS_LIMIT, "上限で止める");
+
+            assert_eq!(default_capacity(0), BOOT_PROCESSES);
+            assert_eq!(default_capacity(128 << 20), 512, "128MiBなら512個");
+            assert_eq!(default_capacity(16 << 30), 65536, "16GiBでも物理メモリ量で決まる");
+            assert_eq!(default_capacity(u64::MAX), PROCESS_LIMIT);
+        }
+
+        #[test]
+        fn test_boot_table_does_not_grow() {
+            let table = table(BOOT_PROCESSES);
+            for pid in 0..BOOT_PROCESSES as ProcessId {
+                assert_eq!(spawn(&table, pid + 1), Some(pid as usize));
+            }
+            assert_eq!(table.find_free_slot(), None, "容量を増やさなければ起動用のスロットだけ");
+            assert_eq!(table.slot_count(), BOOT_PROCESSES);
+        }
+
+        #[test]
+        fn test_grow_to_capacity() {
+            // チャンクをいくつもまたぐ大きさ
+            const CAPACITY: usize = 4096;
+            let table = table(CAPACITY);
+            let mut scheduler = Scheduler::new();
+            for pid in 1..=CAPACITY as ProcessId {
+                let slot = spawn(&table, pid).expect("容量まではスロットを増やせる");
+                assert_eq!(slot, pid as usize - 1, "空きがなければ末尾に足す");
+                scheduler.enqueue(slot, Priority::USER_Q);
+            }
+            assert_eq!(table.slot_count(), CAPACITY);
+            assert_eq!(table.find_free_slot(), None, "容量を超えては増やさない");
+            assert!(CACHE.stats().in_use >= CAPACITY - BOOT_PROCESSES, "後ろのスロットはキャッシュから");
+
+            // スロット番号は変わらない（スケジューラのキューもそのまま使える）
+            assert_eq!(table.get_mut(4000).unwrap().pid, 4001);
+            assert_eq!(table.slots().last().map(|(slot, p)| (slot, p.pid)), Some((CAPACITY - 1, CAPACITY as ProcessId)));
+            assert_eq!(scheduler.queue(Priority::USER_Q as usize).count(), CAPACITY);
+            assert!(scheduler.dequeue(4000, Priority::USER_Q));
+            assert_eq!(scheduler.queue(Priority::USER_Q as usize).nth(4000), Some(4001), "大きなスロット番号もつなげる");
+        }
+
+        #[test]
+        fn test_spawn_and_reap_thousands() {
+            let table = table(1024);
+            let mut live = std::collections::VecDeque::new();
+            for pid in 1..=20_000 {
+                // 最大500個まで生かしておき、古いものから回収する
+                if live.len() == 500 {
+                    let slot: usize = live.pop_front().unwrap();
+                    table.get_mut(slot).unwrap().flags.set(ProcessFlags::SLOT_FREE);
+                }
+                let slot = spawn(&table, pid).expect("回収したスロットを使い回す");
+                assert!(!live.contains(&slot), "使用中のスロットは渡さない");
+                live.push_back(slot);
+            }
+            assert_eq!(table.slot_count(), 500, "同時に生きていた数までしか増えない");
+            let alive = table.slots().filter(|(_, p)| !p.flags.is_set(ProcessFlags::SLOT_FREE)).count();
+            assert_eq!(alive, 500);
+            assert!(table.to_string().lines().count() > 500, "増やしたスロットもダンプする");
+        }
+    }
+
+    /// ダンプ（Display）のテスト
+    mod dump_tests {
+        use super::*;
+
+        #[test]
+        fn test_process_table_dump() {
+            let table = ProcessTable::new();
+            for slot in (0..BOOT_PROCESSES).filter(|&slot| slot != 0 && slot != 2) {
+                table.get_mut(slot).unwrap().flags.set(ProcessFlags::SLOT_FREE);
+            }
+            let monitor = table.get_mut(0).unwrap();
+            *monitor = Process::new(-1);
+            monitor.set_name("monitor");
+            monitor.priority = Priority::new(14);
+            monitor.max_priority = Priority::new(14);
+            (monitor.ticks_left, monitor.quantum_size) = (8, 8);
+
+            let sh = table.get_mut(2).unwrap();
+            *sh = Process::new(5);
+            sh.set_name("sh");
+            sh.flags.set(ProcessFlags::RECEIVING);
+            sh.receive_from = Some(ANY);
+            (sh.ticks_left, sh.quantum_size) = (5, 8);
+
+            let dump = table.to_string();
+            let lines: Vec<_> = dump.lines().collect();
+            assert_eq!(lines[0], "SLOT   PID NAME             FLAGS                 PRI  MAX  USED QUANT IPC");
+            assert_eq!(lines[1], "   0    -1 monitor          -                      14   14     0     8", "IPCで待っていなければ空");
+            assert_eq!(lines[2], "   2     5 sh               RECEIVING               7    7     3     8 <- ANY");
+            assert_eq!(lines[3], format!("free slots: 1, 3-{}", BOOT_PROCESSES - 1), "連続した空きはまとめる");
+            assert_eq!(lines.len(), 4);
+        }
+
+        #[test]
+        fn test_sending_target() {
+            let table = ProcessTable::new();
+            let process = table.get_mut(1).unwrap();
+            process.pid = 6;
+            process.flags.set(ProcessFlags::SENDING);
+            process.send_to = Some(5);
+            // SENDINGでなければ send_to は表示しない
+            table.get_mut(3).unwrap().send_to = Some(9);
+            let dump = table.to_string();
+            assert!(dump.lines().nth(2).unwrap().ends_with("SENDING                 7    7     0     8 -> 5"), "{}", dump);
+            assert!(!dump.contains("-> 9"));
+            assert!(!dump.contains("free slots"), "空きスロットがなければ書かない");
+        }
+
+        #[test]
+        fn test_scheduler_dump() {
+            let mut scheduler = Scheduler::new();
+            assert_eq!(scheduler.to_string(), "policy: Priority\n(no runnable processes)");
+            scheduler.enqueue(3, Priority::USER_Q);
+            scheduler.enqueue(5, Priority::USER_Q);
+            scheduler.enqueue(1, Priority::USER_Q);
+            scheduler.enqueue(0, 14);
+            assert_eq!(scheduler.to_string(), "policy: Priority\nq7: 3 5 1\nq14: 0", "キューに並んだ順");
+        }
+    }
+
     /// StackFrameのテスト
     mod stack_frame_tests {
         use super::*;
@@ -611,6 +1490,35 @@ mod tests {
             assert!(scheduler.pick_next().is_none(), "デキュー後は空であるべき");
         }
 
+        #[test]
+        fn test_scheduler_fifo_order() {
+            // MINIX 3: 同じ優先度では enqueue() した順に並ぶ
+            let mut scheduler = Scheduler::new();
+            scheduler.enqueue(4, Priority::USER_Q);
+            scheduler.enqueue(2, Priority::USER_Q);
+            scheduler.enqueue(9, Priority::USER_Q);
+            scheduler.enqueue(2, Priority::USER_Q);
+            assert_eq!(scheduler.queue(Priority::USER_Q as usize).collect::<Vec<_>>(), [4, 2, 9], "2回入れても1回だけ並ぶ");
+            assert_eq!(scheduler.pick_next(), Some(4), "先頭から選ぶ");
+
+            assert!(scheduler.dequeue(2, Priority::USER_Q), "真ん中を外す");
+            assert_eq!(scheduler.queue(Priority::USER_Q as usize).collect::<Vec<_>>(), [4, 9]);
+            assert!(scheduler.dequeue(9, Priority::USER_Q), "末尾を外す");
+            scheduler.enqueue(7, Priority::USER_Q);
+            assert_eq!(scheduler.queue(Priority::USER_Q as usize).collect::<Vec<_>>(), [4, 7], "末尾を直してから追加する");
+            assert!(scheduler.contains(7));
+            assert!(!scheduler.contains(9));
+        }
+
+        #[test]
+        fn test_scheduler_dequeue_other_process() {
+            let mut scheduler = Scheduler::new();
+            scheduler.enqueue(1, Priority::USER_Q);
+            assert!(!scheduler.dequeue(2, Priority::USER_Q), "入っていないプロセス");
+            assert_eq!(scheduler.head(Priority::USER_Q as usize), Some(1), "入っていないプロセスを外しても先頭は変わらない");
+            assert_eq!(scheduler.head(NR_SCHED_QUEUES), None, "範囲外のキューは空");
+        }
+
         #[test]
         fn test_scheduler_idle_is_lowest() {
             // MINIX 3: IDLE_Q = 15 は最低優先度
@@ -629,5 +1537,20 @@ mod tests {
             
             assert_eq!(scheduler.pick_next().unwrap(), 1, "IDLE_Qが最後");
         }
+
+        #[test]
+        fn test_scheduler_round_robin_ignores_priority() {
+            // sched=rr では全員が同じキューに入る
+            let mut scheduler = Scheduler::new();
+            scheduler.set_policy(SchedPolicy::RoundRobin);
+            assert_eq!(scheduler.policy(), SchedPolicy::RoundRobin);
+
+            scheduler.enqueue(1, Priority::TASK_Q);
+            assert_eq!(scheduler.pick_next(), Some(1), "エンキューしたプロセスが選ばれる");
+
+            // USER_Qから外すとTASK_Qで入れたプロセスも消える（同じキュー）
+            scheduler.dequeue(1, Priority::USER_Q);
+            assert!(scheduler.pick_next().is_none(), "同じキューに入っているべき");
+        }
     }
 }
@@ -0,0 +1,150 @@
+//! 固定長のリングバッファ
+//!
+//! ヒープを使わずに、割り込みハンドラとカーネル本体の間でデータを受け渡す。
+//! シリアルの受信バッファなどで使う。
+//!
+//! MINIX 3: drivers/tty/tty.h の tty_inbuf（tty_inhead / tty_intail）に相当
+
+/// 容量 `N` のリングバッファ
+pub struct RingBuffer<T: Copy, const N: usize> {
+    data: [Option<T>; N],
+    /// 次に読み出す位置
+    head: usize,
+    /// 格納されている要素数
+    len: usize,
+}
+
+impl<T: Copy, const N: usize> RingBuffer<T, N> {
+    pub const fn new() -> Self {
+        Self { data: [None; N], head: 0, len: 0 }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.len == N
+    }
+
+    pub const fn capacity(&self) -> usize {
+        N
+    }
+
+    /// 末尾に追加する。満杯なら追加せずに `false` を返す（新しいデータを捨てる）
+    pub fn push(&mut self, value: T) -> bool {
+        if self.is_full() {
+            return false;
+        }
+        self.data[(self.head + self.len) % N] = Some(value);
+        self.len += 1;
+        true
+    }
+
+    /// 末尾に追加する。満杯なら最も古い要素を上書きし、それを返す
+    pub fn push_overwrite(&mut self, value: T) -> Option<T> {
+        if N == 0 {
+            return Some(value);
+        }
+        if self.is_full() {
+            let oldest = self.data[self.head].replace(value);
+            self.head = (self.head + 1) % N;
+            oldest
+        } else {
+            self.push(value);
+            None
+        }
+    }
+
+    /// 先頭（最も古い要素）を取り出す
+    pub fn pop(&mut self) -> Option<T> {
+        if self.is_empty() {
+            return None;
+        }
+        let value = self.data[self.head].take();
+        self.head = (self.head + 1) % N;
+        self.len -= 1;
+        value
+    }
+
+    /// 古い順に列挙する（取り出さない）
+    pub fn iter(&self) -> impl Iterator<Item = T> + '_ {
+        (0..self.len).filter_map(move |i| self.data[(self.head + i) % N])
+    }
+
+    pub fn clear(&mut self) {
+        while self.pop().is_some() {}
+    }
+}
+
+impl<T: Copy, const N: usize> Default for RingBuffer<T, N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// ===== テスト =====
+#[cfg(all(test, not(target_os = "none")))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_push_pop_fifo() {
+        let mut buffer: RingBuffer<u8, 4> = RingBuffer::new();
+        assert!(buffer.is_empty(), "最初は空");
+        buffer.push(1);
+        buffer.push(2);
+        assert_eq!(buffer.len(), 2);
+        assert_eq!(buffer.pop(), Some(1), "古い順に取り出す");
+        assert_eq!(buffer.pop(), Some(2));
+        assert_eq!(buffer.pop(), None, "空ならNone");
+    }
+
+    #[test]
+    fn test_push_when_full_drops_new() {
+        let mut buffer: RingBuffer<u8, 2> = RingBuffer::new();
+        assert!(buffer.push(1));
+        assert!(buffer.push(2));
+        assert!(!buffer.push(3), "満杯なら追加できない");
+        assert_eq!(buffer.iter().collect::<Vec<_>>(), [1, 2], "古いデータは残る");
+    }
+
+    #[test]
+    fn test_push_overwrite_wraps() {
+        let mut buffer: RingBuffer<u32, 3> = RingBuffer::new();
+        for i in 0..3 {
+            assert_eq!(buffer.push_overwrite(i), None, "空きがあれば上書きしない");
+        }
+        assert_eq!(buffer.push_overwrite(3), Some(0), "最も古い要素を上書きする");
+        assert_eq!(buffer.push_overwrite(4), Some(1));
+        assert_eq!(buffer.iter().collect::<Vec<_>>(), [2, 3, 4], "一周しても古い順");
+        assert!(buffer.is_full());
+    }
+
+    #[test]
+    fn test_wraparound_many_times() {
+        let mut buffer: RingBuffer<usize, 5> = RingBuffer::new();
+        for i in 0..1000 {
+            buffer.push_overwrite(i);
+            if i % 3 == 0 {
+                buffer.pop();
+            }
+        }
+        let items: Vec<_> = buffer.iter().collect();
+        assert!(items.windows(2).all(|w| w[0] + 1 == w[1]), "連続した値が古い順に並ぶ");
+        assert_eq!(*items.last().unwrap(), 999, "最後に追加した値が末尾");
+    }
+
+    #[test]
+    fn test_clear() {
+        let mut buffer: RingBuffer<u8, 4> = RingBuffer::new();
+        buffer.push(1);
+        buffer.clear();
+        assert!(buffer.is_empty());
+        assert_eq!(buffer.capacity(), 4);
+    }
+}
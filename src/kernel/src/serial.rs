@@ -0,0 +1,484 @@
+//! 16550 UART シリアルポート
+//!
+//! QEMUの `-serial stdio` やCIのようにディスプレイがない環境でも使えるコンソール。
+//! 送信はLSRを見て待つポーリング方式、受信はIRQ 4 の割り込みでリングバッファに貯める。
+//! 入力を待つカーネルタスク（モニタ）は `wait_for_input()` で実行可能キューから外れ、受信割り込みで戻る。
+//!
+//! MINIX 3: drivers/tty/rs232.c の rs_init() / rs232_handler()
+//! MikanOS: シリアル出力はなく、QEMUのログはフレームバッファ経由だった
+
+use core::fmt;
+#[cfg(any(not(test), target_os = "none"))]
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::ring_buffer::RingBuffer;
+use crate::sync::SpinLock;
+
+/// COM1のI/Oポート
+pub const COM1: u16 = 0x3f8;
+/// COM1のIRQ
+pub const COM1_IRQ: u8 = 4;
+/// COM2のI/Oポート（GDBスタブが使う）
+pub const COM2: u16 = 0x2f8;
+/// 起動時のボーレート
+pub const DEFAULT_BAUD: u32 = 115_200;
+/// UARTの基準クロック（divisor=1 のときのボーレート）
+const BASE_BAUD: u32 = 115_200;
+/// 受信バッファのサイズ
+const RX_BUFFER_SIZE: usize = 256;
+/// 送信可能になるまで待つ回数の上限（UARTがないときに止まらないように）
+const TX_SPIN_LIMIT: u32 = 100_000;
+
+/// レジスタのオフセット
+mod reg {
+    /// 送受信データ（DLAB=0）/ divisor下位（DLAB=1）
+    pub const DATA: u16 = 0;
+    /// 割り込み許可（DLAB=0）/ divisor上位（DLAB=1）
+    pub const IER: u16 = 1;
+    /// FIFO制御（書き込み）
+    pub const FCR: u16 = 2;
+    /// ライン制御
+    pub const LCR: u16 = 3;
+    /// モデム制御
+    pub const MCR: u16 = 4;
+    /// ラインステータス
+    pub const LSR: u16 = 5;
+}
+
+/// LCRのビット
+mod lcr {
+    /// divisorにアクセスする
+    pub const DLAB: u8 = 0x80;
+    /// 8ビット、パリティなし、ストップビット1
+    pub const EIGHT_N_ONE: u8 = 0x03;
+}
+
+/// LSRのビット
+mod lsr {
+    /// 受信データあり
+    pub const DATA_READY: u8 = 0x01;
+    /// 送信保持レジスタが空
+    pub const THR_EMPTY: u8 = 0x20;
+}
+
+/// MCRのビット
+mod mcr {
+    pub const DTR: u8 = 0x01;
+    pub const RTS: u8 = 0x02;
+    /// PCではIRQ線の出力を有効にする
+    pub const OUT2: u8 = 0x08;
+    /// ループバック（自己診断）
+    pub const LOOPBACK: u8 = 0x10;
+}
+
+/// FCR: FIFO有効、送受信FIFOクリア、14バイトで割り込み
+const FCR_ENABLE_CLEAR_14: u8 = 0xc7;
+/// IER: 受信データありで割り込み
+const IER_RX_AVAILABLE: u8 = 0x01;
+/// ループバック診断で送る値
+const LOOPBACK_TEST_BYTE: u8 = 0xae;
+
+/// シリアルポートの初期化エラー
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SerialError {
+    /// 基準クロックを割り切れないボーレート
+    InvalidBaudRate(u32),
+    /// ループバック診断に失敗した（UARTがない）
+    NotPresent,
+}
+
+impl fmt::Display for SerialError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::InvalidBaudRate(baud) => write!(f, "unsupported baud rate {}", baud),
+            Self::NotPresent => write!(f, "UART not present"),
+        }
+    }
+}
+
+/// ボーレートから divisor を計算する
+pub fn divisor(baud: u32) -> Result<u16, SerialError> {
+    if baud == 0 || baud > BASE_BAUD || !BASE_BAUD.is_multiple_of(baud) {
+        return Err(SerialError::InvalidBaudRate(baud));
+    }
+    Ok((BASE_BAUD / baud) as u16)
+}
+
+/// UARTのレジスタへのアクセス
+///
+/// 実機ではI/Oポート、テストでは模擬デバイスを使う。
+pub trait UartIo {
+    fn read(&mut self, offset: u16) -> u8;
+    fn write(&mut self, offset: u16, value: u8);
+}
+
+/// I/Oポート経由のアクセス
+pub struct PortIo {
+    base: u16,
+}
+
+impl PortIo {
+    /// # Safety
+    /// `base` に16550互換のUARTがあること（なければ無関係なデバイスに書き込む）
+    pub const unsafe fn new(base: u16) -> Self {
+        Self { base }
+    }
+}
+
+impl UartIo for PortIo {
+    fn read(&mut self, offset: u16) -> u8 {
+        unsafe { crate::arch::port::inb(self.base + offset) }
+    }
+
+    fn write(&mut self, offset: u16, value: u8) {
+        unsafe { crate::arch::port::outb(self.base + offset, value) }
+    }
+}
+
+/// 16550 UART
+pub struct SerialPort<I: UartIo> {
+    io: I,
+}
+
+impl<I: UartIo> SerialPort<I> {
+    pub const fn new(io: I) -> Self {
+        Self { io }
+    }
+
+    /// ボーレート・8N1・FIFOを設定し、ループバックで動作を確認する
+    /// 割り込みは無効のまま（enable_rx_interrupt() で有効にする）
+    pub fn init(&mut self, baud: u32) -> Result<(), SerialError> {
+        let divisor = divisor(baud)?;
+
+        self.io.write(reg::IER, 0);
+        self.io.write(reg::LCR, lcr::DLAB);
+        self.io.write(reg::DATA, divisor as u8);
+        self.io.write(reg::IER, (divisor >> 8) as u8);
+        self.io.write(reg::LCR, lcr::EIGHT_N_ONE);
+        self.io.write(reg::FCR, FCR_ENABLE_CLEAR_14);
+
+        // ループバックで送った値が読めなければUARTはない
+        self.io.write(reg::MCR, mcr::LOOPBACK | mcr::RTS | mcr::OUT2);
+        self.io.write(reg::DATA, LOOPBACK_TEST_BYTE);
+        if self.io.read(reg::DATA) != LOOPBACK_TEST_BYTE {
+            return Err(SerialError::NotPresent);
+        }
+
+        self.io.write(reg::MCR, mcr::DTR | mcr::RTS | mcr::OUT2);
+        Ok(())
+    }
+
+    /// 受信割り込みを有効にする
+    pub fn enable_rx_interrupt(&mut self) {
+        self.io.write(reg::IER, IER_RX_AVAILABLE);
+    }
+
+    /// 1バイト送信する（送信保持レジスタが空くまで待つ）
+    pub fn send(&mut self, byte: u8) {
+        for _ in 0..TX_SPIN_LIMIT {
+            if self.io.read(reg::LSR) & lsr::THR_EMPTY != 0 {
+                break;
+            }
+            core::hint::spin_loop();
+        }
+        self.io.write(reg::DATA, byte);
+    }
+
+    /// 受信データがあれば1バイト読む
+    pub fn try_receive(&mut self) -> Option<u8> {
+        if self.io.read(reg::LSR) & lsr::DATA_READY != 0 {
+            Some(self.io.read(reg::DATA))
+        } else {
+            None
+        }
+    }
+
+    /// 受信割り込みの処理: FIFOに溜まったデータをすべてバッファに移す
+    /// バッファがあふれた分は捨てる。移したバイト数を返す
+    pub fn drain_into<const N: usize>(&mut self, buffer: &mut RingBuffer<u8, N>) -> usize {
+        let mut count = 0;
+        while let Some(byte) = self.try_receive() {
+            buffer.push(byte);
+            count += 1;
+        }
+        count
+    }
+}
+
+impl<I: UartIo> fmt::Write for SerialPort<I> {
+    /// 改行は端末向けに CR LF にして送る
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        for byte in s.bytes() {
+            if byte == b'\n' {
+                self.send(b'\r');
+            }
+            self.send(byte);
+        }
+        Ok(())
+    }
+}
+
+/// COM1（初期化前はNone）
+pub static SERIAL1: SpinLock<Option<SerialPort<PortIo>>> = SpinLock::new(None);
+
+/// COM1の受信バッファ（割り込みハンドラが書き、read_byte() が読む）
+static RX_BUFFER: SpinLock<RingBuffer<u8, RX_BUFFER_SIZE>> = SpinLock::new(RingBuffer::new());
+
+/// 受信割り込みで起こすカーネルタスクがいないことを表す値
+#[cfg(any(not(test), target_os = "none"))]
+const NO_WAITER: usize = usize::MAX;
+
+/// 受信割り込みで実行可能キューに戻すカーネルタスクのスロット番号（`wait_for_input()`）
+#[cfg(any(not(test), target_os = "none"))]
+static RX_WAITER: AtomicUsize = AtomicUsize::new(NO_WAITER);
+
+/// COM1を初期化し、受信割り込みを登録する
+#[cfg(any(not(test), target_os = "none"))]
+pub fn init(baud: u32) -> Result<(), SerialError> {
+    use crate::arch::interrupts::{set_irq_handler, PIC};
+    use crate::arch::InterruptController;
+
+    let mut port = SerialPort::new(unsafe { PortIo::new(COM1) });
+    port.init(baud)?;
+    port.enable_rx_interrupt();
+    *SERIAL1.lock() = Some(port);
+
+    set_irq_handler(COM1_IRQ, com1_interrupt);
+    PIC.lock().enable(u32::from(COM1_IRQ));
+    Ok(())
+}
+
+/// IRQ 4 のハンドラ
+#[cfg(any(not(test), target_os = "none"))]
+fn com1_interrupt() {
+    use crate::process::{PROCESS_TABLE, SCHEDULER};
+
+    if let Some(port) = SERIAL1.lock().as_mut() {
+        port.drain_into(&mut RX_BUFFER.lock());
+    }
+    if RX_BUFFER.lock().is_empty() {
+        return;
+    }
+    // 入力を待っていたカーネルタスクをキューに戻す（NO_WAITER ならテーブルにない）
+    let waiter = RX_WAITER.swap(NO_WAITER, Ordering::Relaxed);
+    if let Some(process) = PROCESS_TABLE.get_mut(waiter) {
+        SCHEDULER.lock().enqueue(waiter, process.priority.value());
+    }
+}
+
+/// 受信バッファが空なら、カーネルタスク `slot` を実行可能キューから外す（外したらtrue）
+///
+/// 次に文字が届くと、受信割り込みがキューの最後に戻す。確かめてから外すまでスケジューラのロックを持つ
+/// （割り込み禁止）ので、その間に届いた文字で起こし損ねることはない。
+/// MINIX 3: TTYドライバは受信割り込みの通知（HARDWARE からの notify）が来るまで receive で止まる
+#[cfg(any(not(test), target_os = "none"))]
+pub fn wait_for_input(slot: usize) -> bool {
+    use crate::process::{PROCESS_TABLE, SCHEDULER};
+
+    let Some(process) = PROCESS_TABLE.get_mut(slot) else {
+        return false;
+    };
+    let mut scheduler = SCHEDULER.lock();
+    if !RX_BUFFER.lock().is_empty() {
+        return false;
+    }
+    RX_WAITER.store(slot, Ordering::Relaxed);
+    scheduler.dequeue(slot, process.priority.value())
+}
+
+/// 受信バッファから1バイト取り出す（なければNone）
+pub fn read_byte() -> Option<u8> {
+    RX_BUFFER.lock().pop()
+}
+
+// ===== テスト =====
+#[cfg(all(test, not(target_os = "none")))]
+mod tests {
+    use super::*;
+    use core::fmt::Write;
+    use std::collections::VecDeque;
+
+    /// 模擬UART: 書き込みを記録し、LSRとループバックを再現する
+    #[derive(Default)]
+    struct MockUart {
+        writes: Vec<(u16, u8)>,
+        sent: Vec<u8>,
+        rx: VecDeque<u8>,
+        mcr: u8,
+        lcr: u8,
+        loopback: Option<u8>,
+        /// THRが空になるまでにLSRを読む回数
+        busy_reads: u32,
+        lsr_reads: u32,
+        /// ループバックが壊れている（UARTがない）
+        broken: bool,
+    }
+
+    impl UartIo for MockUart {
+        fn read(&mut self, offset: u16) -> u8 {
+            match offset {
+                reg::LSR => {
+                    self.lsr_reads += 1;
+                    let thr = if self.busy_reads > 0 {
+                        self.busy_reads -= 1;
+                        0
+                    } else {
+                        lsr::THR_EMPTY
+                    };
+                    thr | if self.rx.is_empty() { 0 } else { lsr::DATA_READY }
+                }
+                reg::DATA if self.mcr & mcr::LOOPBACK != 0 => {
+                    if self.broken { 0xff } else { self.loopback.take().unwrap_or(0) }
+                }
+                reg::DATA => self.rx.pop_front().unwrap_or(0),
+                _ => 0,
+            }
+        }
+
+        fn write(&mut self, offset: u16, value: u8) {
+            self.writes.push((offset, value));
+            match offset {
+                reg::MCR => self.mcr = value,
+                reg::LCR => self.lcr = value,
+                reg::DATA if self.lcr & lcr::DLAB != 0 => {}
+                reg::DATA if self.mcr & mcr::LOOPBACK != 0 => self.loopback = Some(value),
+                reg::DATA => self.sent.push(value),
+                _ => {}
+            }
+        }
+    }
+
+    mod divisor_tests {
+        use super::*;
+
+        #[test]
+        fn test_divisor() {
+            assert_eq!(divisor(115_200), Ok(1), "最大速度は divisor=1");
+            assert_eq!(divisor(38_400), Ok(3));
+            assert_eq!(divisor(9_600), Ok(12));
+        }
+
+        #[test]
+        fn test_invalid_baud() {
+            assert_eq!(divisor(0), Err(SerialError::InvalidBaudRate(0)));
+            assert_eq!(divisor(230_400), Err(SerialError::InvalidBaudRate(230_400)), "基準クロックより速くはできない");
+            assert_eq!(divisor(100_000), Err(SerialError::InvalidBaudRate(100_000)), "割り切れない値");
+        }
+    }
+
+    mod init_tests {
+        use super::*;
+
+        #[test]
+        fn test_init_sequence() {
+            let mut port = SerialPort::new(MockUart::default());
+            assert_eq!(port.init(9_600), Ok(()));
+            let writes = &port.io.writes;
+            assert_eq!(writes[0], (reg::IER, 0), "まず割り込みを止める");
+            assert_eq!(writes[1], (reg::LCR, lcr::DLAB));
+            assert_eq!(writes[2], (reg::DATA, 12), "divisor下位");
+            assert_eq!(writes[3], (reg::IER, 0), "divisor上位");
+            assert_eq!(writes[4], (reg::LCR, lcr::EIGHT_N_ONE), "8N1でDLABを戻す");
+            assert_eq!(writes[5], (reg::FCR, FCR_ENABLE_CLEAR_14));
+            assert_eq!(
+                *writes.last().unwrap(),
+                (reg::MCR, mcr::DTR | mcr::RTS | mcr::OUT2),
+                "最後に通常モードに戻す"
+            );
+            assert!(port.io.sent.is_empty(), "ループバック中の送信は外に出ない");
+        }
+
+        #[test]
+        fn test_init_detects_missing_uart() {
+            let mut port = SerialPort::new(MockUart { broken: true, ..Default::default() });
+            assert_eq!(port.init(DEFAULT_BAUD), Err(SerialError::NotPresent));
+        }
+
+        #[test]
+        fn test_init_rejects_bad_baud() {
+            let mut port = SerialPort::new(MockUart::default());
+            assert_eq!(port.init(12_345), Err(SerialError::InvalidBaudRate(12_345)));
+            assert!(port.io.writes.is_empty(), "エラー時はレジスタに触らない");
+        }
+
+        #[test]
+        fn test_enable_rx_interrupt() {
+            let mut port = SerialPort::new(MockUart::default());
+            port.enable_rx_interrupt();
+            assert_eq!(port.io.writes, [(reg::IER, IER_RX_AVAILABLE)]);
+        }
+    }
+
+    mod transfer_tests {
+        use super::*;
+
+        #[test]
+        fn test_send_waits_for_thr_empty() {
+            let mut port = SerialPort::new(MockUart { busy_reads: 3, ..Default::default() });
+            port.send(b'A');
+            assert_eq!(port.io.lsr_reads, 4, "THRが空くまでLSRを読む");
+            assert_eq!(port.io.sent, b"A");
+        }
+
+        #[test]
+        fn test_send_gives_up_without_uart() {
+            let mut port = SerialPort::new(MockUart { busy_reads: u32::MAX, ..Default::default() });
+            port.send(b'A');
+            assert_eq!(port.io.lsr_reads, TX_SPIN_LIMIT, "上限まで待ったら諦めて書く");
+        }
+
+        #[test]
+        fn test_write_translates_newline() {
+            let mut port = SerialPort::new(MockUart::default());
+            write!(port, "ok\n{}", 1).unwrap();
+            assert_eq!(port.io.sent, b"ok\r\n1", "改行はCR LFにする");
+        }
+
+        #[test]
+        fn test_drain_into_buffer() {
+            let mut port = SerialPort::new(MockUart::default());
+            port.io.rx.extend(b"hello");
+            let mut buffer: RingBuffer<u8, 4> = RingBuffer::new();
+            assert_eq!(port.drain_into(&mut buffer), 5, "FIFOは空になるまで読む");
+            assert_eq!(buffer.iter().collect::<Vec<_>>(), b"hell", "あふれた分は捨てる");
+            assert_eq!(port.try_receive(), None);
+        }
+    }
+}
+
+// ===== カーネル内テスト =====
+#[cfg(all(test, target_os = "none"))]
+mod kernel_tests {
+    use super::*;
+    use core::fmt::Write;
+
+    #[test_case]
+    fn test_com1_is_initialized() {
+        let mut serial = SERIAL1.lock();
+        let port = serial.as_mut().expect("QEMUにはCOM1がある");
+        port.write_str("").unwrap();
+    }
+
+    #[test_case]
+    fn test_wait_for_input_until_rx_interrupt() {
+        use crate::process::{Process, ProcessFlags, PROCESS_TABLE, SCHEDULER};
+
+        let slot = PROCESS_TABLE.find_free_slot().unwrap();
+        *PROCESS_TABLE.get_mut(slot).unwrap() = Process::new(-2);
+        let priority = PROCESS_TABLE.get_mut(slot).unwrap().priority.value();
+        SCHEDULER.lock().enqueue(slot, priority);
+        RX_BUFFER.lock().clear();
+        assert!(wait_for_input(slot), "受信バッファが空ならキューから外れる");
+        assert!(!SCHEDULER.lock().contains(slot));
+
+        // 受信割り込みの代わりに、バッファに1文字入れてからハンドラを呼ぶ
+        RX_BUFFER.lock().push(b'x');
+        com1_interrupt();
+        assert!(SCHEDULER.lock().contains(slot), "文字が届いたらキューに戻る");
+        assert!(!wait_for_input(slot), "読むまではキューに残る");
+        assert_eq!(read_byte(), Some(b'x'));
+
+        SCHEDULER.lock().dequeue(slot, priority);
+        PROCESS_TABLE.get_mut(slot).unwrap().flags.set(ProcessFlags::SLOT_FREE);
+    }
+}
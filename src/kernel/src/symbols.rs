@@ -0,0 +1,89 @@
+//! カーネルのシンボル表
+//!
+//! バックトレースや例外のアドレスを `kernel::kernel_main+0x1f` の形で表示するための表。
+//! カーネルには中身が0の `.ksyms` セクションを予約しておき、リンク後にホストの
+//! `ksyms` コマンド（または mkimage）がデマングルした関数名の表をそこへ書き込む。
+//! 書き込まれていなければ表は空で、アドレスだけを表示する。
+//!
+//! MINIX 3: なし（アドレスだけを表示し、ホストの nm で調べていた）
+//! Linux: kernel/kallsyms.c（2回リンクして表を埋め込む）
+
+use core::fmt;
+
+use ksyms::SymbolTable;
+
+/// 予約する大きさ（デバッグビルドのシンボルが余裕をもって入る）
+#[cfg(any(not(test), target_os = "none"))]
+const RESERVED_SIZE: usize = 256 * 1024;
+
+/// 表を書き込む場所。リンカスクリプトで `.ksyms` セクションにまとめる
+#[cfg(any(not(test), target_os = "none"))]
+#[used]
+#[link_section = ".ksyms"]
+static RESERVED: [u8; RESERVED_SIZE] = [0; RESERVED_SIZE];
+
+/// 埋め込まれたシンボル表
+#[cfg(any(not(test), target_os = "none"))]
+pub fn table() -> SymbolTable<'static> {
+    extern "C" {
+        /// linker.ld で定義（.ksyms セクションの先頭と終わり）
+        static __ksyms_start: u8;
+        static __ksyms_end: u8;
+    }
+    // RESERVED を直接読むと、中身が0だとコンパイラに知られているので
+    // リンク後に書き込んだ表が読めない。リンカのシンボルから読む
+    let start = core::ptr::addr_of!(__ksyms_start);
+    let len = core::ptr::addr_of!(__ksyms_end) as usize - start as usize;
+    // 安全性: .ksyms はカーネルイメージの一部で、起動後に書き換えない
+    let data = unsafe { core::slice::from_raw_parts(start, len) };
+    SymbolTable::parse(data).unwrap_or(SymbolTable::EMPTY)
+}
+
+/// ホストでのテストではカーネルの表はない
+#[cfg(all(test, not(target_os = "none")))]
+pub fn table() -> SymbolTable<'static> {
+    SymbolTable::EMPTY
+}
+
+/// アドレスと、わかれば関数名を表示する（`0xffffffff80101234 <kernel::foo+0x14>`）
+pub struct Symbolized(pub u64);
+
+impl fmt::Display for Symbolized {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:#x}", self.0)?;
+        if let Some(symbol) = table().lookup(self.0) {
+            write!(f, " <{}>", symbol)?;
+        }
+        Ok(())
+    }
+}
+
+// ===== テスト =====
+#[cfg(all(test, not(target_os = "none")))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_symbolized_without_table() {
+        assert_eq!(Symbolized(0xffff_ffff_8010_1234).to_string(), "0xffffffff80101234", "表がなければアドレスだけ");
+    }
+}
+
+// ===== カーネル内テスト =====
+#[cfg(all(test, target_os = "none"))]
+mod kernel_tests {
+    use super::*;
+
+    #[test_case]
+    fn test_lookup_kernel_function() {
+        let symbols = table();
+        if symbols.is_empty() {
+            // シンボル表を埋め込まずに起動した
+            return;
+        }
+        let address = table as usize as u64;
+        let symbol = symbols.lookup(address + 1).expect("カーネルの関数が見つかる");
+        assert_eq!(symbol.name, "kernel::symbols::table");
+        assert_eq!(symbol.offset, 1);
+    }
+}
@@ -0,0 +1,151 @@
+//! 同期プリミティブ
+//!
+//! 割り込みハンドラからも使うデータ（コンソール、受信バッファ）を守るロック。
+//! ロックを持っている間は割り込みを禁止するので、同じCPUで割り込みハンドラが
+//! 同じロックを取りに来てデッドロックすることはない。
+//!
+//! MINIX 3: lock() / unlock()（割り込み禁止で排他制御していた）
+//! MikanOS: __asm__("cli") と __asm__("sti") で囲んでいた部分
+
+use core::cell::UnsafeCell;
+use core::ops::{Deref, DerefMut};
+use core::sync::atomic::{AtomicBool, Ordering};
+
+/// 割り込み禁止付きのスピンロック
+pub struct SpinLock<T> {
+    locked: AtomicBool,
+    data: UnsafeCell<T>,
+}
+
+// 安全性: ロックで排他制御するので、T が Send なら共有してよい
+unsafe impl<T: Send> Sync for SpinLock<T> {}
+unsafe impl<T: Send> Send for SpinLock<T> {}
+
+impl<T> SpinLock<T> {
+    pub const fn new(data: T) -> Self {
+        Self { locked: AtomicBool::new(false), data: UnsafeCell::new(data) }
+    }
+
+    /// ロックを取得する（取得できるまで待つ）
+    pub fn lock(&self) -> SpinLockGuard<'_, T> {
+        let interrupts = irq_save();
+        while self
+            .locked
+            .compare_exchange_weak(false, true, Ordering::Acquire, Ordering::Relaxed)
+            .is_err()
+        {
+            core::hint::spin_loop();
+        }
+        SpinLockGuard { lock: self, interrupts }
+    }
+
+    /// ロックを取得できなければすぐに None を返す
+    /// （パニック時など、ロックを持ったまま止まった可能性がある場面で使う）
+    pub fn try_lock(&self) -> Option<SpinLockGuard<'_, T>> {
+        let interrupts = irq_save();
+        if self.locked.compare_exchange(false, true, Ordering::Acquire, Ordering::Relaxed).is_ok() {
+            Some(SpinLockGuard { lock: self, interrupts })
+        } else {
+            irq_restore(interrupts);
+            None
+        }
+    }
+}
+
+/// ロック中を表すガード（スコープを抜けると解放し、割り込み状態を戻す）
+pub struct SpinLockGuard<'a, T> {
+    lock: &'a SpinLock<T>,
+    /// ロック前に割り込みが有効だったか
+    interrupts: bool,
+}
+
+impl<T> Deref for SpinLockGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        unsafe { &*self.lock.data.get() }
+    }
+}
+
+impl<T> DerefMut for SpinLockGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        unsafe { &mut *self.lock.data.get() }
+    }
+}
+
+impl<T> Drop for SpinLockGuard<'_, T> {
+    fn drop(&mut self) {
+        self.lock.locked.store(false, Ordering::Release);
+        irq_restore(self.interrupts);
+    }
+}
+
+/// 割り込みを禁止し、それまで有効だったかを返す
+/// ホストでのテスト時（ユーザーモード）はcliを実行できないので何もしない
+#[cfg(any(not(test), target_os = "none"))]
+fn irq_save() -> bool {
+    use crate::arch::{CpuOps, X86_64};
+    let enabled = crate::arch::interrupts_enabled();
+    if enabled {
+        unsafe { X86_64::disable_interrupts() };
+    }
+    enabled
+}
+
+#[cfg(all(test, not(target_os = "none")))]
+fn irq_save() -> bool {
+    false
+}
+
+/// irq_save() の前の状態に戻す
+fn irq_restore(enabled: bool) {
+    #[cfg(any(not(test), target_os = "none"))]
+    if enabled {
+        use crate::arch::{CpuOps, X86_64};
+        unsafe { X86_64::enable_interrupts() };
+    }
+    #[cfg(all(test, not(target_os = "none")))]
+    let _ = enabled;
+}
+
+// ===== テスト =====
+#[cfg(all(test, not(target_os = "none")))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lock_and_modify() {
+        let lock = SpinLock::new(0);
+        *lock.lock() += 1;
+        assert_eq!(*lock.lock(), 1, "ガード経由で変更できる");
+    }
+
+    #[test]
+    fn test_try_lock_while_locked() {
+        let lock = SpinLock::new(());
+        let guard = lock.lock();
+        assert!(lock.try_lock().is_none(), "ロック中は取得できない");
+        drop(guard);
+        assert!(lock.try_lock().is_some(), "解放後は取得できる");
+    }
+
+    #[test]
+    fn test_shared_between_threads() {
+        use std::sync::Arc;
+        let lock = Arc::new(SpinLock::new(0usize));
+        let threads: Vec<_> = (0..4)
+            .map(|_| {
+                let lock = Arc::clone(&lock);
+                std::thread::spawn(move || {
+                    for _ in 0..1000 {
+                        *lock.lock() += 1;
+                    }
+                })
+            })
+            .collect();
+        for thread in threads {
+            thread.join().unwrap();
+        }
+        assert_eq!(*lock.lock(), 4000, "排他制御されていれば数が合う");
+    }
+}
@@ -0,0 +1,305 @@
+//! カーネルコール
+//!
+//! ユーザー空間（主にシステムサーバー）がカーネルに頼む処理。
+//! 番号ごとに do_*() を用意しておき、システムコールの入口（arch の syscall.rs）から
+//! `dispatch()` が `CALL_VEC` を引いて呼び出す。
+//!
+//! 戻り値は0以上なら成功、負ならエラー番号（MINIX 3 と同じく errno を負にした値）。
+//! `IPC_CALL_BASE` 以上の番号はIPCトラップで、ipc.rs の `do_ipc()` に回す。
+//!
+//! MINIX 3: kernel/system.c の call_vec[] と kernel/system/do_*.c
+//!          （例: SYS_GETINFO は do_getinfo()）
+
+use alloc::string::String;
+use alloc::vec::Vec;
+
+use libipc::IPC_CALL_BASE;
+
+use crate::arch::MapFlags;
+use crate::initrd;
+use crate::ipc;
+use crate::klog;
+use crate::process::{self, SpawnError};
+#[cfg(any(not(test), target_os = "none"))]
+use crate::user::{exec, exit, fork};
+
+/// カーネルログを読む（dmesg）
+pub const SYS_DMESG: usize = 0;
+/// 文字列をコンソールに出す
+/// MINIX 3: SYS_DIAGCTL の DIAGCTL_CODE_DIAG（サーバーやドライバの診断メッセージ）
+pub const SYS_DIAGCTL: usize = 1;
+/// 呼び出したプロセスを終了する
+/// MINIX 3: SYS_EXIT
+pub const SYS_EXIT: usize = 2;
+/// 呼び出したプロセスを複製する（子のプロセス番号は呼び出し側が決める）
+/// MINIX 3: SYS_FORK
+pub const SYS_FORK: usize = 3;
+/// 呼び出したプロセスのプログラムを、initrdの実行ファイルに取り替える
+/// MINIX 3: SYS_EXEC
+pub const SYS_EXEC: usize = 4;
+
+/// カーネルコールの数
+pub const NR_SYS_CALLS: usize = 5;
+
+/// ファイルがない
+pub const ENOENT: i64 = -2;
+/// 呼び出したプロセスが見つからない
+pub const ESRCH: i64 = -3;
+/// 実行ファイルの形式が不正
+pub const ENOEXEC: i64 = -8;
+/// プロセステーブルに空きがない
+pub const EAGAIN: i64 = -11;
+/// メモリが足りない
+pub const ENOMEM: i64 = -12;
+/// ユーザーのアドレスが不正
+pub const EFAULT: i64 = -14;
+/// 引数が不正
+pub const EINVAL: i64 = -22;
+/// その番号のカーネルコールはない
+pub const ENOSYS: i64 = -71;
+
+/// カーネルコールの引数（rdi, rsi, rdx, r10, r8, r9 の順）
+pub type Args = [u64; 6];
+
+/// 番号ごとの処理
+/// MINIX 3: call_vec[]（map(SYS_FORK, do_fork) などで埋める）
+static CALL_VEC: [fn(&Args) -> i64; NR_SYS_CALLS] = [sys_dmesg, sys_diagctl, sys_exit, sys_fork, sys_exec];
+
+/// 番号 `number` のカーネルコール（IPC_CALL_BASE 以上ならIPCトラップ）を実行する
+pub fn dispatch(number: u64, args: Args) -> i64 {
+    if number >= IPC_CALL_BASE {
+        return ipc::do_ipc(number - IPC_CALL_BASE, args[0] as i32, args[1]);
+    }
+    match CALL_VEC.get(number as usize) {
+        Some(call) => call(&args),
+        None => ENOSYS,
+    }
+}
+
+/// dmesg で一度に読む最大バイト数（バッファのログを全部読める大きさ）
+const DMESG_MAX: usize = klog::LOG_CAPACITY * klog::LINE_LEN;
+
+/// 実行中のプロセスの `addr` から `len` バイトが、`flags` の付いたユーザーの領域に収まるか確かめる
+pub fn check_user(addr: u64, len: u64, flags: u8) -> Result<(), i64> {
+    let process = process::current().ok_or(ESRCH)?;
+    if !process.memory.contains_range(addr, len, MapFlags::USER | flags) {
+        return Err(EFAULT);
+    }
+    Ok(())
+}
+
+/// 実行中のプロセスの `addr` から `out.len()` バイトを、カーネルのバッファにコピーする
+///
+/// システムコールの間は呼び出したプロセスのページテーブルのままなので、確かめれば直接読める。
+/// MINIX 3: data_copy()
+pub fn copy_from_user(addr: u64, out: &mut [u8]) -> Result<(), i64> {
+    check_user(addr, out.len() as u64, 0)?;
+    if !out.is_empty() {
+        // 安全性: 範囲はすべて今のアドレス空間のユーザーの領域にマップされている
+        unsafe { core::ptr::copy_nonoverlapping(addr as *const u8, out.as_mut_ptr(), out.len()) };
+    }
+    Ok(())
+}
+
+/// カーネルのバッファ `data` を、実行中のプロセスの `addr` にコピーする
+///
+/// 共有している（コピーオンライトの）ページは、書いたときのページフォルトで複製される。
+pub fn copy_to_user(addr: u64, data: &[u8]) -> Result<(), i64> {
+    check_user(addr, data.len() as u64, MapFlags::WRITABLE)?;
+    if !data.is_empty() {
+        // 安全性: 範囲はすべて今のアドレス空間のユーザーの書ける領域にマップされている
+        unsafe { core::ptr::copy_nonoverlapping(data.as_ptr(), addr as *mut u8, data.len()) };
+    }
+    Ok(())
+}
+
+/// 実行中のプロセスの `addr` から `len` バイトを、新しく確保したカーネルのバッファにコピーする
+fn copy_in(addr: u64, len: u64) -> Result<Vec<u8>, i64> {
+    check_user(addr, len, 0)?;
+    let mut bytes = Vec::new();
+    bytes.try_reserve_exact(len as usize).map_err(|_| ENOMEM)?;
+    bytes.resize(len as usize, 0);
+    copy_from_user(addr, &mut bytes)?;
+    Ok(bytes)
+}
+
+/// ユーザーのアドレス範囲 `[a, a + a_len)` と `[b, b + b_len)` が重なるか
+fn overlaps(a: u64, a_len: u64, b: u64, b_len: u64) -> bool {
+    a_len > 0 && b_len > 0 && a < b.saturating_add(b_len) && b < a.saturating_add(a_len)
+}
+
+/// SYS_DMESG: 通し番号 `from` 以降のカーネルログを `out` にテキストで詰める
+///
+/// 書いたバイト数と、次の呼び出しで渡す通し番号を返す。
+/// 古いログが上書きされていれば、残っている最も古いものから返す。
+pub fn do_dmesg(from: u64, out: &mut [u8]) -> (usize, u64) {
+    klog::LOG_BUFFER.lock().read(from, out)
+}
+
+/// SYS_DMESG(seq, buf, len): `*seq` 以降のログを `buf` に読み、`*seq` を次の通し番号にする
+///
+/// ログはいったんカーネルのバッファに読み、ロックを放してからユーザーにコピーする。
+/// `seq` と `buf` が重なっていれば EINVAL。
+fn sys_dmesg(args: &Args) -> i64 {
+    let [seq, buf, len, ..] = *args;
+    if overlaps(seq, 8, buf, len) {
+        return EINVAL;
+    }
+    let mut from = [0; 8];
+    let checked = copy_from_user(seq, &mut from)
+        .and_then(|()| check_user(seq, 8, MapFlags::WRITABLE))
+        .and_then(|()| check_user(buf, len, MapFlags::WRITABLE));
+    if let Err(error) = checked {
+        return error;
+    }
+    let mut out = Vec::new();
+    if out.try_reserve_exact((len as usize).min(DMESG_MAX)).is_err() {
+        return ENOMEM;
+    }
+    out.resize((len as usize).min(DMESG_MAX), 0);
+    let (written, next) = do_dmesg(u64::from_ne_bytes(from), &mut out);
+    match copy_to_user(buf, &out[..written]).and_then(|()| copy_to_user(seq, &next.to_ne_bytes())) {
+        Ok(()) => written as i64,
+        Err(error) => error,
+    }
+}
+
+/// SYS_DIAGCTL(buf, len): UTF-8 の文字列をコンソールに出し、バイト数を返す
+fn sys_diagctl(args: &Args) -> i64 {
+    let [buf, len, ..] = *args;
+    let bytes = match copy_in(buf, len) {
+        Ok(bytes) => bytes,
+        Err(error) => return error,
+    };
+    match core::str::from_utf8(&bytes) {
+        Ok(text) => {
+            print!("{}", text);
+            len as i64
+        }
+        Err(_) => EINVAL,
+    }
+}
+
+/// SYS_EXIT(status): 呼び出したプロセスを終了する（成功すれば戻らない）
+fn sys_exit(args: &Args) -> i64 {
+    match process::current() {
+        Some(_) => exit(args[0] as i32),
+        None => ESRCH,
+    }
+}
+
+/// SYS_FORK(pid): 呼び出したプロセスを複製して子のプロセス番号 `pid` を返す（子には0が返る）
+fn sys_fork(args: &Args) -> i64 {
+    let Some(parent) = process::current() else {
+        return ESRCH;
+    };
+    match fork(parent, args[0] as process::ProcessId) {
+        Ok(_) => args[0] as process::ProcessId as i64,
+        Err(error) => spawn_errno(error),
+    }
+}
+
+/// SYS_EXEC(cmd, len): 呼び出したプロセスのプログラムを、initrdの実行ファイルに取り替える
+///
+/// `cmd` は `init=` と同じ形（最初の語がパスで、残りは引数）。成功すると新しいプログラムが
+/// エントリポイントから動く（rax は0）。失敗したらエラー番号を返し、元のプログラムが続きから動く。
+fn sys_exec(args: &Args) -> i64 {
+    let [cmd, len, ..] = *args;
+    let Some(slot) = process::current_slot() else {
+        return ESRCH;
+    };
+    // 古いアドレス空間は取り替えたときに壊れるので、カーネルにコピーしておく
+    let command = match copy_in(cmd, len) {
+        Ok(bytes) => bytes,
+        Err(error) => return error,
+    };
+    let Ok(command) = String::from_utf8(command) else {
+        return EINVAL;
+    };
+    let argv: Vec<&str> = command.split_whitespace().collect();
+    let Some(&path) = argv.first() else {
+        return EINVAL;
+    };
+    let Some(image) = initrd::read(path) else {
+        return ENOENT;
+    };
+    let name = path.rsplit('/').next().unwrap_or(path);
+    match exec(slot, name, image, &argv, &[]) {
+        Ok(()) => 0,
+        Err(error) => spawn_errno(error),
+    }
+}
+
+/// プロセスを作れなかった理由をエラー番号にする
+fn spawn_errno(error: SpawnError) -> i64 {
+    match error {
+        SpawnError::TableFull => EAGAIN,
+        SpawnError::BadPid(_) => EINVAL,
+        SpawnError::Map(_) => ENOMEM,
+        SpawnError::Elf(_) => ENOEXEC,
+    }
+}
+
+/// ホストのテストには抜けるユーザーモードがない
+#[cfg(all(test, not(target_os = "none")))]
+fn exit(_status: i32) -> i64 {
+    EINVAL
+}
+
+/// ホストのテストにはユーザープロセスがない
+#[cfg(all(test, not(target_os = "none")))]
+fn exec(_slot: usize, _name: &str, _image: &[u8], _argv: &[&str], _envp: &[&str]) -> Result<(), SpawnError> {
+    Err(SpawnError::TableFull)
+}
+
+/// ホストのテストにはユーザープロセスがない
+#[cfg(all(test, not(target_os = "none")))]
+fn fork(_parent: &process::Process, _pid: process::ProcessId) -> Result<usize, SpawnError> {
+    Err(SpawnError::TableFull)
+}
+
+// ===== テスト =====
+#[cfg(all(test, not(target_os = "none")))]
+mod tests {
+    use super::*;
+    use log::Level;
+
+    #[test]
+    fn test_do_dmesg_reads_kernel_log() {
+        let record = klog::LOG_BUFFER.lock().push(5, Level::Error, None, format_args!("disk on fire"));
+        let mut out = [0u8; 256];
+        let (len, next) = do_dmesg(record.seq, &mut out);
+        let text = core::str::from_utf8(&out[..len]).unwrap();
+        assert!(text.starts_with("[       5] ERROR kernel: disk on fire\n"), "dmesg形式で読める: {}", text);
+        assert!(next > record.seq, "次の通し番号に進む");
+    }
+
+    #[test]
+    fn test_dispatch() {
+        assert_eq!(dispatch(NR_SYS_CALLS as u64, [0; 6]), ENOSYS, "範囲外の番号");
+        assert_eq!(dispatch(IPC_CALL_BASE - 1, [0; 6]), ENOSYS);
+        assert_eq!(dispatch(u64::MAX, [0; 6]), ESRCH, "IPC_CALL_BASE 以上はIPCトラップ");
+        // ホストのテストでは実行中のユーザープロセスがない
+        assert_eq!(dispatch(SYS_DIAGCTL as u64, [0x40_0000, 5, 0, 0, 0, 0]), ESRCH);
+        assert_eq!(dispatch(SYS_EXIT as u64, [0; 6]), ESRCH);
+        assert_eq!(dispatch(SYS_FORK as u64, [101, 0, 0, 0, 0, 0]), ESRCH);
+        assert_eq!(dispatch(SYS_EXEC as u64, [0x40_0000, 10, 0, 0, 0, 0]), ESRCH);
+    }
+
+    #[test]
+    fn test_overlaps() {
+        assert!(overlaps(0x1000, 8, 0x1004, 64), "seq の後ろ半分に buf が重なる");
+        assert!(overlaps(0x1000, 8, 0xff8, 16));
+        assert!(!overlaps(0x1000, 8, 0x1008, 64), "隣り合うだけなら重ならない");
+        assert!(!overlaps(0x1000, 8, 0xfc0, 64));
+        assert!(!overlaps(0x1000, 8, 0x1004, 0), "長さ0の buf はどこにも重ならない");
+        assert!(overlaps(0x1000, 8, 0, u64::MAX), "終わりがあふれても重なる");
+    }
+
+    #[test]
+    fn test_spawn_errno() {
+        assert_eq!(spawn_errno(SpawnError::TableFull), EAGAIN);
+        assert_eq!(spawn_errno(SpawnError::BadPid(0)), EINVAL);
+        assert_eq!(spawn_errno(SpawnError::Map(crate::arch::MapError::OutOfFrames)), ENOMEM);
+    }
+}
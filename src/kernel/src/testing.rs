@@ -0,0 +1,193 @@
+//! カーネル内テスト（QEMU上で実行する）
+//!
+//! ホストでの `cargo test` はカーネルのロジックを普通のプログラムとして試すだけで、
+//! 実際に起動したカーネル（IDT、PIC、タイマー、シリアル）は通らない。
+//! カーネルのターゲットで `cargo test` すると custom_test_frameworks が `#[test_case]` を
+//! 付けた関数を集め、kernel_main() が初期化を終えたところで run() を呼ぶ。
+//! 結果はシリアルに出し、isa-debug-exit でQEMUの終了コードとして返す。
+//!
+//! ```bash
+//! cd kernel
+//! cargo test --target x86_64-learning-os.json -Z build-std=core,compiler_builtins -Z build-std-features=compiler-builtins-mem
+//! ```
+//!
+//! # パニックを期待するテスト
+//! 戻り値の型を `ShouldPanic` にしたテストは、パニックすれば成功、戻ってきたら失敗になる。
+//!
+//! ```ignore
+//! #[test_case]
+//! fn test_breakpoint_panics() -> ShouldPanic {
+//!     unsafe { core::arch::asm!("int3") };
+//!     ShouldPanic
+//! }
+//! ```
+//!
+//! panic=abort なのでパニックから戻る方法はない。パニックハンドラから on_panic() が呼ばれたら、
+//! パニックしたテストのスタックは捨てて、専用のスタックで次のテストから続ける。
+//! パニックしたテストが持っていたロックは解放されないので、テストの中では
+//! 長くロックを持たないこと。
+//!
+//! MINIX 3: なし（テストはユーザー空間の test/ にあるプログラムで行う）
+//! MikanOS: なし
+
+use core::cell::UnsafeCell;
+use core::panic::PanicInfo;
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+use crate::arch::qemu::{self, QemuExitCode};
+use crate::arch::{CpuOps, X86_64};
+use crate::sync::SpinLock;
+
+/// パニックすることを期待するテストの戻り値
+pub struct ShouldPanic;
+
+/// テストの戻り値（パニックを期待するかどうか）
+pub trait Outcome {
+    const SHOULD_PANIC: bool;
+}
+
+impl Outcome for () {
+    const SHOULD_PANIC: bool = false;
+}
+
+impl Outcome for ShouldPanic {
+    const SHOULD_PANIC: bool = true;
+}
+
+/// `#[test_case]` を付けられるもの
+pub trait Testable: Sync {
+    /// `clock::kernel_tests::test_timer_ticks` のような名前
+    fn name(&self) -> &'static str;
+    fn should_panic(&self) -> bool;
+    fn run(&self);
+}
+
+impl<F: Fn() -> R + Sync, R: Outcome> Testable for F {
+    fn name(&self) -> &'static str {
+        let name = core::any::type_name::<F>();
+        // クレート名（kernel::）は省く
+        name.split_once("::").map_or(name, |(_, path)| path)
+    }
+
+    fn should_panic(&self) -> bool {
+        R::SHOULD_PANIC
+    }
+
+    fn run(&self) {
+        self();
+    }
+}
+
+/// 実行中のテストがないことを表す CURRENT の値
+const NO_TEST: usize = usize::MAX;
+
+/// すべてのテスト（パニックの後に続きから再開するため覚えておく）
+static TESTS: SpinLock<&'static [&'static dyn Testable]> = SpinLock::new(&[]);
+/// 実行中のテストの番号
+static CURRENT: AtomicUsize = AtomicUsize::new(NO_TEST);
+static PASSED: AtomicUsize = AtomicUsize::new(0);
+static FAILED: AtomicUsize = AtomicUsize::new(0);
+/// テストを始めたときに割り込みが有効だったか（パニックハンドラが止めるので再開時に戻す）
+static INTERRUPTS: AtomicBool = AtomicBool::new(false);
+
+/// パニックの後に使うスタックの大きさ
+const RESUME_STACK_SIZE: usize = 64 * 1024;
+
+/// パニックの後に使うスタック
+#[repr(C, align(16))]
+struct ResumeStack(UnsafeCell<[u8; RESUME_STACK_SIZE]>);
+
+// 安全性: テストは1つのCPUで順に実行し、パニックした後にだけ使う
+unsafe impl Sync for ResumeStack {}
+
+static RESUME_STACK: ResumeStack = ResumeStack(UnsafeCell::new([0; RESUME_STACK_SIZE]));
+
+/// テストランナー（custom_test_frameworks の test_runner）
+pub fn run(tests: &'static [&'static dyn Testable]) {
+    serial_println!();
+    serial_println!("running {} tests", tests.len());
+    *TESTS.lock() = tests;
+    INTERRUPTS.store(crate::arch::interrupts_enabled(), Ordering::SeqCst);
+    run_from(0)
+}
+
+/// `first` 番目のテストから最後まで実行して、QEMUを終了させる
+fn run_from(first: usize) -> ! {
+    let tests = *TESTS.lock();
+    for (i, test) in tests.iter().enumerate().skip(first) {
+        CURRENT.store(i, Ordering::SeqCst);
+        serial_print!("test {} ... ", test.name());
+        test.run();
+        if test.should_panic() {
+            serial_println!("FAILED");
+            serial_println!("  note: test did not panic as expected");
+            FAILED.fetch_add(1, Ordering::SeqCst);
+        } else {
+            serial_println!("ok");
+            PASSED.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+    CURRENT.store(NO_TEST, Ordering::SeqCst);
+
+    let passed = PASSED.load(Ordering::SeqCst);
+    let failed = FAILED.load(Ordering::SeqCst);
+    let result = if failed == 0 { "ok" } else { "FAILED" };
+    serial_println!();
+    serial_println!("test result: {}. {} passed; {} failed", result, passed, failed);
+    qemu::exit(if failed == 0 { QemuExitCode::Success } else { QemuExitCode::Failed });
+    crate::arch::halt_forever()
+}
+
+/// パニックハンドラから最初に呼ばれる
+///
+/// テストの実行中なら結果を記録して次のテストから再開する（戻らない）。
+/// テストの外（初期化中など）のパニックなら何もせずに戻り、通常のパニック処理に任せる。
+pub fn on_panic(info: &PanicInfo) {
+    let current = CURRENT.load(Ordering::SeqCst);
+    if current == NO_TEST {
+        return;
+    }
+    let tests = match TESTS.try_lock() {
+        Some(tests) => *tests,
+        None => return,
+    };
+    if tests[current].should_panic() {
+        crate::console::_panic_print(format_args!("ok\n"));
+        PASSED.fetch_add(1, Ordering::SeqCst);
+    } else {
+        crate::console::_panic_print(format_args!("FAILED\n  panicked: {}\n", info.message()));
+        if let Some(location) = info.location() {
+            crate::console::_panic_print(format_args!("  at {}:{}:{}\n", location.file(), location.line(), location.column()));
+        }
+        FAILED.fetch_add(1, Ordering::SeqCst);
+    }
+    crate::arch::interrupts::clear_exception_frame();
+    unsafe { resume(current + 1) }
+}
+
+/// スタックを RESUME_STACK に切り替えて `next` 番目のテストから続ける
+///
+/// # Safety
+/// 元のスタックには二度と戻らないこと（パニックハンドラからだけ呼ぶ）
+unsafe fn resume(next: usize) -> ! {
+    let top = RESUME_STACK.0.get() as usize + RESUME_STACK_SIZE;
+    unsafe {
+        core::arch::asm!(
+            "mov rsp, {top}",
+            // バックトレースがここで止まるように
+            "xor ebp, ebp",
+            "call {entry}",
+            top = in(reg) top,
+            entry = sym resume_entry,
+            in("rdi") next,
+            options(noreturn)
+        )
+    }
+}
+
+extern "C" fn resume_entry(next: usize) -> ! {
+    if INTERRUPTS.load(Ordering::SeqCst) {
+        unsafe { X86_64::enable_interrupts() };
+    }
+    run_from(next)
+}
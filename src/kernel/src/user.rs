@@ -0,0 +1,690 @@
+//! ユーザープロセス（ring 3）
+//!
+//! プロセスごとにアドレス空間（memory/vm.rs）とカーネルスタックを用意し、
+//! プログラムを読み込んで ring 3 で実行する。プロセスがシステムコールや割り込みで
+//! カーネルに入ると、CPUはそのプロセスのカーネルスタックに切り替える（TSSのRSP0とSYSCALLの入口）。
+//!
+//! `run()` はプロセスが終わる（SYS_EXIT か例外）か、IPCで止まるか、時間量子を使い切るまで戻らない。
+//! 次にどのプロセスを `run()` するかはディスパッチャ（dispatch.rs）が実行可能キューから選ぶ。
+//! 止まったプロセスはレジスタを registers に保存しておき、もう一度 `run()` すると続きから動く。
+//!
+//! ```text
+//! 0x0000_0000_0040_0000  テキスト（TEXT_START から。ELFならセグメントごとに、リンクしたアドレスへ）
+//! 0x0000_0000_7fff_c000  ユーザースタック（STACK_SIZE）
+//! 0x0000_0000_8000_0000  STACK_TOP
+//! ```
+//!
+//! MINIX 3: kernel/arch/i386/mpx.S の restore_user_context（iretでユーザーに戻る）
+//! MikanOS: task.cpp と CallApp()（ring 3のアプリを呼び出し、終わったら戻る）
+
+use core::fmt;
+
+#[cfg(any(not(test), target_os = "none"))]
+use core::sync::atomic::{AtomicU64, Ordering};
+
+#[cfg(any(not(test), target_os = "none"))]
+use crate::arch::interrupts::{exception_name, InterruptFrame};
+#[cfg(any(not(test), target_os = "none"))]
+use crate::arch::syscall::USER_RFLAGS;
+#[cfg(any(not(test), target_os = "none"))]
+use crate::arch::{MapError, MapFlags, StackFrame};
+#[cfg(any(not(test), target_os = "none"))]
+use crate::elf;
+#[cfg(any(not(test), target_os = "none"))]
+use crate::memory::vm::{self, MemoryMap, Region};
+#[cfg(any(not(test), target_os = "none"))]
+use crate::memory::{self, Frame, FRAME_SIZE};
+#[cfg(any(not(test), target_os = "none"))]
+use crate::process::{self, Process, ProcessId, SpawnError, PROCESS_TABLE};
+
+/// プログラムを読み込む仮想アドレス
+pub const TEXT_START: u64 = 0x40_0000;
+/// ユーザースタックの一番上
+pub const STACK_TOP: u64 = 0x8000_0000;
+/// ユーザースタックの大きさ
+pub const STACK_SIZE: u64 = 16 * 1024;
+/// カーネルスタックの大きさ（2^order フレーム、16KiB）
+pub const KERNEL_STACK_ORDER: usize = 2;
+
+/// ユーザープロセスが終わった理由
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Exit {
+    /// SYS_EXIT で終わった（終了ステータス）
+    Status(i32),
+    /// CPU例外で止めた（ベクタ番号）
+    Fault(u8),
+    /// IPCの送受信で止まった（終わってはいない）
+    Blocked,
+    /// 時間量子を使い切った（まだ実行可能）
+    Preempted,
+}
+
+impl Exit {
+    /// 例外で止めたことを表すビット（下位32ビットがベクタ番号）
+    const FAULT: u64 = 1 << 32;
+    /// IPCで止まったことを表すビット
+    const BLOCKED: u64 = 1 << 33;
+    /// 時間量子を使い切ったことを表すビット
+    const PREEMPTED: u64 = 1 << 34;
+
+    /// `__leave_user()` で返す値にする
+    pub fn encode(self) -> u64 {
+        match self {
+            Self::Status(status) => u64::from(status as u32),
+            Self::Fault(vector) => Self::FAULT | u64::from(vector),
+            Self::Blocked => Self::BLOCKED,
+            Self::Preempted => Self::PREEMPTED,
+        }
+    }
+
+    /// `__enter_user()` が返した値から戻す
+    pub fn decode(value: u64) -> Self {
+        if value & Self::PREEMPTED != 0 {
+            Self::Preempted
+        } else if value & Self::BLOCKED != 0 {
+            Self::Blocked
+        } else if value & Self::FAULT != 0 {
+            Self::Fault(value as u8)
+        } else {
+            Self::Status(value as u32 as i32)
+        }
+    }
+}
+
+impl fmt::Display for Exit {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Status(status) => write!(f, "exited with status {}", status),
+            Self::Fault(vector) => write!(f, "killed by exception {}", vector),
+            Self::Blocked => write!(f, "blocked in IPC"),
+            Self::Preempted => write!(f, "used up its quantum"),
+        }
+    }
+}
+
+/// `run()` の呼び出し元のスタック（`exit()` と `fault()` はここに戻る）
+#[cfg(any(not(test), target_os = "none"))]
+static RETURN_RSP: AtomicU64 = AtomicU64::new(0);
+
+/// ユーザープロセスを作り、`code` を TEXT_START に読み込む（スロット番号を返す）
+///
+/// テキストとスタックの領域、カーネルスタックを確保し、最初のrip/rspを registers に書いておく。
+/// MINIX 3: PMの do_fork() と VMの do_exec_newmem()
+#[cfg(any(not(test), target_os = "none"))]
+pub fn spawn(pid: ProcessId, name: &str, code: &[u8]) -> Result<usize, SpawnError> {
+    spawn_with(pid, name, |memory| load_code(memory, code))
+}
+
+/// ユーザープロセスを作り、ELFの実行ファイル `image` を読み込む（スロット番号を返す）
+///
+/// ユーザースタックには `argv` と `envp` を積んでおく（elf.rs）。
+/// MINIX 3: PMの do_exec() → VMの exec_newmem と libexec_load_elf()
+#[cfg(any(not(test), target_os = "none"))]
+pub fn spawn_elf(pid: ProcessId, name: &str, image: &[u8], argv: &[&str], envp: &[&str]) -> Result<usize, SpawnError> {
+    spawn_with(pid, name, |memory| elf::load(memory, image, argv, envp))
+}
+
+/// `load` でアドレス空間を用意し、返された rip/rsp から始まるプロセスを作る
+#[cfg(any(not(test), target_os = "none"))]
+fn spawn_with(
+    pid: ProcessId,
+    name: &str,
+    load: impl FnOnce(&mut MemoryMap) -> Result<(u64, u64), SpawnError>,
+) -> Result<usize, SpawnError> {
+    let slot = process::spawn(&PROCESS_TABLE, pid, name, vm::create)?;
+    let process = PROCESS_TABLE.get_mut(slot).ok_or(SpawnError::TableFull)?;
+    if let Err(error) = load(&mut process.memory).and_then(|(rip, rsp)| prepare(process, rip, rsp)) {
+        // 安全性: 作ったばかりのプロセスはまだ動いていない
+        unsafe { release(process) };
+        process.flags.set(process::ProcessFlags::SLOT_FREE);
+        return Err(error);
+    }
+    Ok(slot)
+}
+
+/// `code` をそのまま TEXT_START に置き、空のスタックを用意する
+#[cfg(any(not(test), target_os = "none"))]
+fn load_code(memory: &mut MemoryMap, code: &[u8]) -> Result<(u64, u64), SpawnError> {
+    let text_end = TEXT_START + (code.len() as u64).next_multiple_of(FRAME_SIZE).max(FRAME_SIZE);
+    let text = MapFlags::from_bits(MapFlags::USER | MapFlags::EXECUTABLE);
+    vm::allocate(memory, Region::new(TEXT_START, text_end, text))?;
+    vm::write(memory, TEXT_START, code)?;
+    let stack = MapFlags::from_bits(MapFlags::USER | MapFlags::WRITABLE);
+    vm::allocate(memory, Region::new(STACK_TOP - STACK_SIZE, STACK_TOP, stack))?;
+    Ok((TEXT_START, STACK_TOP))
+}
+
+/// 実行中のプロセス `parent` を複製した子プロセス `pid` を作る（スロット番号を返す）
+///
+/// 子のアドレス空間は親のページをコピーオンライトで共有する（`vm::fork()`）。
+/// 子は実行可能キューに入り、システムコールから戻ったところから動き出す。rax（forkの戻り値）だけが0になる。
+/// MINIX 3: kernel/system/do_fork.c（子の戻り値は PM が reply で0にする）
+/// Linux: copy_thread() の childregs->ax = 0
+#[cfg(any(not(test), target_os = "none"))]
+pub fn fork(parent: &Process, pid: ProcessId) -> Result<usize, SpawnError> {
+    let slot = process::fork(&PROCESS_TABLE, parent, pid, vm::fork)?;
+    let child = PROCESS_TABLE.get_mut(slot).ok_or(SpawnError::TableFull)?;
+    child.registers = StackFrame::from(trap_frame(parent));
+    child.registers.rax = 0;
+    if let Err(error) = alloc_kernel_stack(child) {
+        // 安全性: 作ったばかりのプロセスはまだ動いていない
+        unsafe { release(child) };
+        child.flags.set(process::ProcessFlags::SLOT_FREE);
+        return Err(error);
+    }
+    // 子は親と同じ優先度のキューの最後に並び、ディスパッチャに選ばれたら動き出す
+    process::SCHEDULER.lock().enqueue(slot, child.priority.value());
+    Ok(slot)
+}
+
+/// スロット `slot` のプロセスのプログラムを、ELFの実行ファイル `image` に取り替える
+///
+/// 新しいアドレス空間を別に作って読み込み、できてから古いものと取り替える（Linuxの point of no return）。
+/// そこまでに失敗したら、元のプログラムには何も触らずにエラーを返す。
+/// プロセス番号と優先度（特権）はそのままで、名前は `name` になる。実行中のプロセス（SYS_EXEC）なら、
+/// システムコールから戻ったところで新しいプログラムのエントリポイントから動き出す。
+/// MINIX 3: PMの do_exec() → VMの exec_newmem() → カーネルの SYS_EXEC（do_exec.c）
+/// Linux: execve() の begin_new_exec()
+#[cfg(any(not(test), target_os = "none"))]
+pub fn exec(slot: usize, name: &str, image: &[u8], argv: &[&str], envp: &[&str]) -> Result<(), SpawnError> {
+    let process = PROCESS_TABLE.get_mut(slot).ok_or(SpawnError::TableFull)?;
+    let mut memory = vm::create()?;
+    let (rip, rsp) = match elf::load(&mut memory, image, argv, envp) {
+        Ok(start) => start,
+        Err(error) => {
+            // 安全性: 作ったばかりのアドレス空間はまだ使っていない
+            unsafe { vm::destroy(&mut memory) };
+            return Err(error);
+        }
+    };
+    let mut registers = StackFrame::new();
+    registers.rip = rip;
+    registers.rsp = rsp;
+    registers.rflags = USER_RFLAGS;
+    // 名前は呼び出したプロセスのメモリを指しているかもしれないので、古いアドレス空間を壊す前にコピーする
+    process.exec(name, registers);
+    vm::replace(&mut process.memory, memory);
+    if process::current_slot() == Some(slot) {
+        trap_frame_mut(process).set_registers(&registers);
+    }
+    Ok(())
+}
+
+/// カーネルに入ったときに保存した、ユーザーモードのレジスタ
+///
+/// システムコールでも割り込みでも、カーネルスタックの一番上に `InterruptFrame` を積む。
+/// Linux: task_pt_regs()
+#[cfg(any(not(test), target_os = "none"))]
+fn trap_frame(process: &Process) -> &InterruptFrame {
+    let frame = process.kernel_stack - core::mem::size_of::<InterruptFrame>() as u64;
+    unsafe { &*(frame as *const InterruptFrame) }
+}
+
+/// `trap_frame()` の書き換えられる版（戻り先を変える）
+#[cfg(any(not(test), target_os = "none"))]
+fn trap_frame_mut(process: &mut Process) -> &mut InterruptFrame {
+    let frame = process.kernel_stack - core::mem::size_of::<InterruptFrame>() as u64;
+    unsafe { &mut *(frame as *mut InterruptFrame) }
+}
+
+/// カーネルスタックを確保し、最初のレジスタを registers に書く
+#[cfg(any(not(test), target_os = "none"))]
+fn prepare(process: &mut Process, rip: u64, rsp: u64) -> Result<(), SpawnError> {
+    alloc_kernel_stack(process)?;
+    process.registers.rip = rip;
+    process.registers.rsp = rsp;
+    process.registers.rflags = USER_RFLAGS;
+    Ok(())
+}
+
+/// ring 3 から入ったときに使うカーネルスタックを確保する
+#[cfg(any(not(test), target_os = "none"))]
+fn alloc_kernel_stack(process: &mut Process) -> Result<(), SpawnError> {
+    let kernel_stack = memory::alloc_frames(KERNEL_STACK_ORDER).ok_or(MapError::OutOfFrames)?;
+    process.kernel_stack =
+        boot_info::PHYSICAL_MEMORY_OFFSET + kernel_stack.start_address() + (FRAME_SIZE << KERNEL_STACK_ORDER);
+    Ok(())
+}
+
+/// プロセスのアドレス空間とカーネルスタックを返す（スロットを空きにするのは呼び出し側）
+///
+/// # Safety
+/// `process` が実行中でない（そのページテーブルとカーネルスタックを使っていない）こと
+#[cfg(any(not(test), target_os = "none"))]
+pub unsafe fn release(process: &mut Process) {
+    unsafe { vm::destroy(&mut process.memory) };
+    if process.kernel_stack != 0 {
+        let bottom = process.kernel_stack - boot_info::PHYSICAL_MEMORY_OFFSET - (FRAME_SIZE << KERNEL_STACK_ORDER);
+        let _ = memory::free_frames(Frame::containing_address(bottom), KERNEL_STACK_ORDER);
+        process.kernel_stack = 0;
+    }
+}
+
+/// スロット `slot` のプロセスを ring 3 で実行し、終わるか止まるまで待つ（実行可能でなければNone）
+///
+/// アドレス空間とカーネルスタックは `process::set_current()` でこのプロセスのものに切り替える。
+/// 呼び出し元のレジスタは __enter_user() がカーネルスタックに残しておき、プロセスが `exit()` するか
+/// 例外を起こすかIPCで止まるか時間量子を使い切ると、そこに戻ってくる。
+/// 止まっている間に届いたメッセージは、ここでユーザーのメモリに書いてから再開する。
+#[cfg(any(not(test), target_os = "none"))]
+pub fn run(slot: usize) -> Option<Exit> {
+    use crate::arch::syscall::__enter_user;
+
+    let process = PROCESS_TABLE.get_mut(slot).filter(|process| process.is_runnable())?;
+    if deliver(process).is_err() {
+        process.registers.rax = libipc::EFAULT as u64;
+    }
+    let registers: *const StackFrame = &process.registers;
+    let previous = process::current_slot();
+    process::set_current(Some(slot));
+    let value = unsafe { __enter_user(registers, RETURN_RSP.as_ptr()) };
+    process::set_current(previous);
+    Some(Exit::decode(value))
+}
+
+/// 届いたメッセージを受信バッファに書く（なければ何もしない）
+/// MINIX 3: proc.c の delivermsg()（MF_DELIVERMSG が立っていれば、ユーザーに戻る前に書く）
+#[cfg(any(not(test), target_os = "none"))]
+fn deliver(process: &mut Process) -> Result<(), MapError> {
+    if !core::mem::take(&mut process.delivery_pending) {
+        return Ok(());
+    }
+    vm::write(&process.memory, process.receive_buffer, &process.deliver_message.to_bytes())
+}
+
+/// トラップ（システムコールとIPC）からユーザーモードに戻る前の処理
+///
+/// 戻り値を rax に書き、呼び出したプロセスがまだ実行可能なら届いたメッセージを書いてそのまま戻る。
+/// IPCで止まったなら、レジスタを保存して `run()` の呼び出し元に戻る。
+/// MINIX 3: switch_to_user()（実行可能でなければ別のプロセスを選ぶ）
+#[cfg(any(not(test), target_os = "none"))]
+pub fn finish_trap(frame: &mut InterruptFrame, result: i64) {
+    frame.rax = result as u64;
+    let Some(process) = process::current_slot().and_then(|slot| PROCESS_TABLE.get_mut(slot)) else {
+        return;
+    };
+    if process.is_runnable() {
+        if deliver(process).is_err() {
+            frame.rax = libipc::EFAULT as u64;
+        }
+        return;
+    }
+    process.registers = StackFrame::from(&*frame);
+    leave(Exit::Blocked)
+}
+
+/// ユーザーモードでタイマー割り込みが来たときの処理
+///
+/// 実行中のプロセスの残り時間を減らし、使い切ったらレジスタを保存して `run()` の呼び出し元に戻る
+/// （ディスパッチャが同じキューの最後に回す）。割り込みの終わり（EOI）を送ってから呼ぶこと。
+/// MINIX 3: clock.c の clock_handler()（p_ticks_left を減らし、0になったら RTS_NO_QUANTUM）
+#[cfg(any(not(test), target_os = "none"))]
+pub fn tick(frame: &InterruptFrame) {
+    let Some(process) = process::current_slot().and_then(|slot| PROCESS_TABLE.get_mut(slot)) else {
+        return;
+    };
+    process.ticks_left = process.ticks_left.saturating_sub(1);
+    if process.ticks_left > 0 {
+        return;
+    }
+    process.registers = StackFrame::from(frame);
+    leave(Exit::Preempted)
+}
+
+/// 実行中のユーザープロセスを終了して、`run()` の呼び出し元に戻る（SYS_EXIT）
+#[cfg(any(not(test), target_os = "none"))]
+pub fn exit(status: i32) -> ! {
+    leave(Exit::Status(status))
+}
+
+/// 書き込みのページフォルトが、実行中のプロセスのコピーオンライトのページなら直す
+///
+/// 直せたらtrue（フォルトした命令から再開する）。ユーザーモードでもカーネルモード（システムコールで
+/// ユーザーのバッファに書いたとき）でも呼ぶ。
+/// MINIX 3: カーネルはページフォルトをVMサーバーに送り（VM_PAGEFAULT）、VMが直してから再開させる
+#[cfg(any(not(test), target_os = "none"))]
+pub fn copy_on_write(error_code: u64, addr: u64) -> bool {
+    use crate::arch::interrupts::{PF_PRESENT, PF_WRITE};
+
+    if error_code & (PF_PRESENT | PF_WRITE) != PF_PRESENT | PF_WRITE {
+        return false;
+    }
+    process::current().is_some_and(|process| vm::copy_on_write(&process.memory, addr))
+}
+
+/// ユーザーモードで起きたCPU例外で、そのプロセスを止める
+///
+/// MINIX 3: exception_handler() はユーザープロセスの例外をシグナル（SIGSEGVなど）にしてPMに送る
+#[cfg(any(not(test), target_os = "none"))]
+pub fn fault(frame: &InterruptFrame) -> ! {
+    let vector = frame.vector as u8;
+    if let Some(process) = process::current() {
+        log::warn!(
+            "{} (pid {}): {} at {:#x} error={:#x}, killed",
+            process.name_str(),
+            process.pid,
+            exception_name(vector),
+            frame.rip,
+            frame.error_code
+        );
+    }
+    leave(Exit::Fault(vector))
+}
+
+#[cfg(any(not(test), target_os = "none"))]
+fn leave(exit: Exit) -> ! {
+    unsafe { crate::arch::syscall::__leave_user(RETURN_RSP.load(Ordering::Relaxed), exit.encode()) }
+}
+
+// ===== テスト =====
+#[cfg(all(test, not(target_os = "none")))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_exit_encoding() {
+        let exits = [Exit::Status(0), Exit::Status(14), Exit::Status(-14), Exit::Status(i32::MIN), Exit::Fault(14)];
+        for exit in exits.into_iter().chain([Exit::Blocked, Exit::Preempted]) {
+            assert_eq!(Exit::decode(exit.encode()), exit, "{:?} を戻せる", exit);
+        }
+        assert_ne!(Exit::Status(-1).encode(), Exit::Fault(255).encode(), "負のステータスと例外を区別する");
+        assert_eq!(Exit::Status(3).to_string(), "exited with status 3");
+        assert_eq!(Exit::Fault(6).to_string(), "killed by exception 6");
+        assert_eq!(Exit::Blocked.to_string(), "blocked in IPC");
+        assert_eq!(Exit::Preempted.to_string(), "used up its quantum");
+    }
+}
+
+#[cfg(all(test, target_os = "none"))]
+mod kernel_tests {
+    use super::*;
+    use crate::system::{EFAULT, ENOENT, SYS_DIAGCTL, SYS_DMESG, SYS_EXEC, SYS_EXIT, SYS_FORK};
+    use libipc::{ANY, EDEADSRCDST, IPC_CALL_BASE, IPC_VECTOR, RECEIVE, SEND};
+
+    // SYS_DIAGCTL で文字列を出し、その戻り値（バイト数）を終了ステータスにする
+    core::arch::global_asm!(
+        ".section .rodata",
+        "__user_hello:",
+        "    mov eax, {diagctl}",
+        "    lea rdi, [rip + .Lhello_message]",
+        "    mov esi, 14",
+        "    syscall",
+        "    mov rdi, rax",
+        "    mov eax, {exit}",
+        "    syscall",
+        "    ud2",
+        ".Lhello_message:",
+        "    .ascii \"hello, ring 3\\n\"",
+        "__user_hello_end:",
+        // マップしていないアドレスを渡すと EFAULT が返る
+        "__user_bad_pointer:",
+        "    mov eax, {diagctl}",
+        "    mov edi, 0x1000",
+        "    mov esi, 4",
+        "    syscall",
+        "    mov rdi, rax",
+        "    mov eax, {exit}",
+        "    syscall",
+        "__user_bad_pointer_end:",
+        // ring 3 では特権命令を実行できない
+        "__user_privileged:",
+        "    cli",
+        "__user_privileged_end:",
+        // int 0x21 で誰かからのメッセージを受信し、その m_type を終了ステータスにする
+        "__user_receiver:",
+        "    sub rsp, 64",
+        "    mov eax, {receive}",
+        "    mov edi, {any}",
+        "    mov rsi, rsp",
+        "    int {ipc_vector}",
+        "    mov edi, [rsp + 4]",
+        "    test rax, rax",
+        "    cmovnz rdi, rax",
+        "    mov eax, {exit}",
+        "    syscall",
+        "__user_receiver_end:",
+        // syscall でプロセス100に m_type 42 のメッセージを送り、その結果を終了ステータスにする
+        "__user_sender:",
+        "    sub rsp, 64",
+        "    mov dword ptr [rsp + 4], 42",
+        "    mov eax, {send}",
+        "    mov edi, 100",
+        "    mov rsi, rsp",
+        "    syscall",
+        "    mov rdi, rax",
+        "    mov eax, {exit}",
+        "    syscall",
+        "__user_sender_end:",
+        // スタックに1を書いてから fork（子は101）し、親は2、子は3を同じ場所に書く
+        // 親は子のプロセス番号、子は fork 前に見えた値 * 10 + 自分で書いた値を終了ステータスにする
+        "__user_fork:",
+        "    mov qword ptr [rsp - 8], 1",
+        "    mov eax, {fork}",
+        "    mov edi, 101",
+        "    syscall",
+        "    test rax, rax",
+        "    jz .Lfork_child",
+        "    mov qword ptr [rsp - 8], 2",
+        "    mov rdi, rax",
+        "    mov eax, {exit}",
+        "    syscall",
+        ".Lfork_child:",
+        "    imul rdi, qword ptr [rsp - 8], 10",
+        "    mov qword ptr [rsp - 8], 3",
+        "    add rdi, qword ptr [rsp - 8]",
+        "    mov eax, {exit}",
+        "    syscall",
+        "__user_fork_end:",
+        // スタックの通し番号を0にしてから fork し、子だけが SYS_DMESG でそこに次の通し番号を書かせる
+        // 親は子のプロセス番号、子は読んだバイト数を終了ステータスにする
+        "__user_fork_dmesg:",
+        "    mov qword ptr [rsp - 8], 0",
+        "    mov eax, {fork}",
+        "    mov edi, 101",
+        "    syscall",
+        "    test rax, rax",
+        "    jz .Lfork_dmesg_child",
+        "    mov rdi, rax",
+        "    mov eax, {exit}",
+        "    syscall",
+        ".Lfork_dmesg_child:",
+        "    mov eax, {dmesg}",
+        "    lea rdi, [rsp - 8]",
+        "    lea rsi, [rsp - 72]",
+        "    mov edx, 64",
+        "    syscall",
+        "    mov rdi, rax",
+        "    mov eax, {exit}",
+        "    syscall",
+        "__user_fork_dmesg_end:",
+        // initrdの /sbin/init（testdata/hello.elf、100 + argc で終わる）に取り替える
+        // 戻ってきたら失敗なので、そのエラー番号を終了ステータスにする
+        "__user_exec:",
+        "    mov eax, {exec}",
+        "    lea rdi, [rip + .Lexec_command]",
+        "    mov esi, 18",
+        "    syscall",
+        "    mov rdi, rax",
+        "    mov eax, {exit}",
+        "    syscall",
+        ".Lexec_command:",
+        "    .ascii \"/sbin/init one two\"",
+        "__user_exec_end:",
+        "__user_exec_missing:",
+        "    mov eax, {exec}",
+        "    lea rdi, [rip + .Lexec_missing]",
+        "    mov esi, 13",
+        "    syscall",
+        "    mov rdi, rax",
+        "    mov eax, {exit}",
+        "    syscall",
+        ".Lexec_missing:",
+        "    .ascii \"/sbin/missing\"",
+        "__user_exec_missing_end:",
+        ".section .text",
+        dmesg = const SYS_DMESG,
+        diagctl = const SYS_DIAGCTL,
+        exit = const SYS_EXIT,
+        fork = const SYS_FORK,
+        exec = const SYS_EXEC,
+        receive = const RECEIVE,
+        send = const IPC_CALL_BASE + SEND,
+        any = const ANY,
+        ipc_vector = const IPC_VECTOR,
+    );
+
+    extern "C" {
+        static __user_hello: u8;
+        static __user_hello_end: u8;
+        static __user_bad_pointer: u8;
+        static __user_bad_pointer_end: u8;
+        static __user_privileged: u8;
+        static __user_privileged_end: u8;
+        static __user_receiver: u8;
+        static __user_receiver_end: u8;
+        static __user_sender: u8;
+        static __user_sender_end: u8;
+        static __user_fork: u8;
+        static __user_fork_end: u8;
+        static __user_fork_dmesg: u8;
+        static __user_fork_dmesg_end: u8;
+        static __user_exec: u8;
+        static __user_exec_end: u8;
+        static __user_exec_missing: u8;
+        static __user_exec_missing_end: u8;
+    }
+
+    fn program(start: &'static u8, end: &'static u8) -> &'static [u8] {
+        let start = start as *const u8;
+        unsafe { core::slice::from_raw_parts(start, end as *const u8 as usize - start as usize) }
+    }
+
+    /// IPCで起こされるとキューに入るので、そこからも外す
+    fn free(slot: usize) {
+        let process = PROCESS_TABLE.get_mut(slot).unwrap();
+        process::SCHEDULER.lock().dequeue(slot, process.priority.value());
+        unsafe { release(process) };
+        process.flags.set(process::ProcessFlags::SLOT_FREE);
+    }
+
+    fn spawn_and_run(name: &str, code: &[u8]) -> Exit {
+        let slot = spawn(100, name, code).unwrap();
+        let exit = run(slot).unwrap();
+        free(slot);
+        exit
+    }
+
+    #[test_case]
+    fn test_user_program_makes_syscall() {
+        let code = unsafe { program(&__user_hello, &__user_hello_end) };
+        assert_eq!(spawn_and_run("hello", code), Exit::Status(14), "SYS_DIAGCTL は書いたバイト数を返す");
+        assert_eq!(process::current_slot(), None, "終わったら元に戻る");
+    }
+
+    #[test_case]
+    fn test_bad_pointer_returns_efault() {
+        let code = unsafe { program(&__user_bad_pointer, &__user_bad_pointer_end) };
+        assert_eq!(spawn_and_run("bad-pointer", code), Exit::Status(EFAULT as i32));
+    }
+
+    #[test_case]
+    fn test_privileged_instruction_kills_process() {
+        let code = unsafe { program(&__user_privileged, &__user_privileged_end) };
+        assert_eq!(spawn_and_run("privileged", code), Exit::Fault(13), "cli は #GP");
+        assert!(crate::arch::interrupts_enabled(), "カーネルの割り込み状態に戻る");
+    }
+
+    #[test_case]
+    fn test_ipc_blocks_until_message_arrives() {
+        let receiver = spawn(100, "receiver", unsafe { program(&__user_receiver, &__user_receiver_end) }).unwrap();
+        let sender = spawn(101, "sender", unsafe { program(&__user_sender, &__user_sender_end) }).unwrap();
+        assert_eq!(run(receiver), Some(Exit::Blocked), "誰も送っていないので止まる");
+        assert_eq!(run(receiver), None, "受信待ちの間は実行できない");
+        assert_eq!(run(sender), Some(Exit::Status(0)), "受信待ちの相手にはすぐ渡せる");
+        assert!(process::SCHEDULER.lock().contains(receiver), "メッセージが届いたらキューに戻る");
+        assert_eq!(run(receiver), Some(Exit::Status(42)), "届いたメッセージを受け取って続きから動く");
+        free(sender);
+        free(receiver);
+    }
+
+    #[test_case]
+    fn test_send_to_missing_process_fails() {
+        // 送り先のプロセス100がいない
+        let sender = spawn(101, "sender", unsafe { program(&__user_sender, &__user_sender_end) }).unwrap();
+        assert_eq!(run(sender), Some(Exit::Status(EDEADSRCDST as i32)));
+        free(sender);
+    }
+
+    #[test_case]
+    fn test_fork_isolates_writes() {
+        use crate::arch::paging::{KernelFrames, Mapper};
+        use crate::arch::AddressSpace;
+
+        let parent = spawn(100, "fork", unsafe { program(&__user_fork, &__user_fork_end) }).unwrap();
+        assert_eq!(run(parent), Some(Exit::Status(101)), "親には子のプロセス番号が返る");
+        let child = PROCESS_TABLE.find(101).expect("子ができている");
+        assert_eq!(PROCESS_TABLE.get_mut(child).unwrap().name_str(), "fork");
+        assert!(process::SCHEDULER.lock().contains(child), "子は実行可能キューに並ぶ");
+        assert_eq!(run(child), Some(Exit::Status(13)), "子には0が返り、親が後から書いた値は見えない");
+
+        let mapper = |slot: usize| Mapper::new(PROCESS_TABLE.get_mut(slot).unwrap().memory.root().unwrap(), KernelFrames);
+        let (parent_map, child_map) = (mapper(parent), mapper(child));
+        let peek = |phys: u64| unsafe { *((boot_info::PHYSICAL_MEMORY_OFFSET + phys) as *const u64) };
+        assert_eq!(peek(parent_map.translate(STACK_TOP - 8).unwrap()), 2);
+        assert_eq!(peek(child_map.translate(STACK_TOP - 8).unwrap()), 3);
+        assert_ne!(parent_map.translate(STACK_TOP - 8), child_map.translate(STACK_TOP - 8), "書いたページはコピーした");
+        assert_eq!(parent_map.translate(TEXT_START), child_map.translate(TEXT_START), "テキストは共有したまま");
+        free(parent);
+        free(child);
+    }
+
+    #[test_case]
+    fn test_kernel_write_copies_shared_page() {
+        use crate::arch::paging::{KernelFrames, Mapper};
+        use crate::arch::AddressSpace;
+
+        let parent = spawn(100, "fork-dmesg", unsafe { program(&__user_fork_dmesg, &__user_fork_dmesg_end) }).unwrap();
+        assert_eq!(run(parent), Some(Exit::Status(101)));
+        let child = PROCESS_TABLE.find(101).expect("子ができている");
+        let exit = run(child);
+        assert!(matches!(exit, Some(Exit::Status(written)) if written > 0), "子はログを読める: {:?}", exit);
+
+        let mapper = |slot: usize| Mapper::new(PROCESS_TABLE.get_mut(slot).unwrap().memory.root().unwrap(), KernelFrames);
+        let (parent_map, child_map) = (mapper(parent), mapper(child));
+        let peek = |phys: u64| unsafe { *((boot_info::PHYSICAL_MEMORY_OFFSET + phys) as *const u64) };
+        assert_eq!(peek(parent_map.translate(STACK_TOP - 8).unwrap()), 0, "カーネルが子に書いた値は親に見えない");
+        assert_ne!(peek(child_map.translate(STACK_TOP - 8).unwrap()), 0, "子には次の通し番号が書かれる");
+        assert_ne!(parent_map.translate(STACK_TOP - 8), child_map.translate(STACK_TOP - 8), "カーネルの書き込みでもコピーした");
+        free(parent);
+        free(child);
+    }
+
+    #[test_case]
+    fn test_exec_replaces_image() {
+        let slot = spawn(100, "exec", unsafe { program(&__user_exec, &__user_exec_end) }).unwrap();
+        let old_root = PROCESS_TABLE.get_mut(slot).unwrap().memory.root();
+        assert_eq!(run(slot), Some(Exit::Status(103)), "新しいプログラムが引数を受け取って動く");
+        let process = PROCESS_TABLE.get_mut(slot).unwrap();
+        assert_eq!((process.pid, process.name_str()), (100, "init"), "プロセス番号はそのまま、名前は変わる");
+        assert_ne!(process.memory.root(), old_root, "アドレス空間は作り直した");
+        free(slot);
+    }
+
+    #[test_case]
+    fn test_exec_missing_file_keeps_image() {
+        let code = unsafe { program(&__user_exec_missing, &__user_exec_missing_end) };
+        assert_eq!(spawn_and_run("exec-missing", code), Exit::Status(ENOENT as i32), "元のプログラムに戻る");
+    }
+
+    #[test_case]
+    fn test_exec_bad_image_keeps_image() {
+        let slot = spawn(100, "hello", unsafe { program(&__user_hello, &__user_hello_end) }).unwrap();
+        let before = PROCESS_TABLE.get_mut(slot).unwrap().memory;
+        let result = exec(slot, "bad", b"#!/bin/sh\nexit 0\n", &["bad"], &[]);
+        assert!(matches!(result, Err(SpawnError::Elf(_))), "ELFではない: {:?}", result);
+        let process = PROCESS_TABLE.get_mut(slot).unwrap();
+        assert_eq!((process.memory, process.name_str()), (before, "hello"), "元のまま");
+        assert_eq!(run(slot), Some(Exit::Status(14)), "元のプログラムが動く");
+        free(slot);
+    }
+}
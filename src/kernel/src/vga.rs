@@ -0,0 +1,444 @@
+//! VGAテキストモードのコンソール
+//!
+//! 物理アドレス 0xB8000 からの80x25の画面バッファに、
+//! 1文字あたり2バイト（文字コード + 色属性）で書き込む。
+//!
+//! MINIX 3: drivers/tty/console.c（cons_write, scroll_screen, set_6845）
+//! MikanOS: console.cpp の Console（ただしMikanOSはフレームバッファに自前で描画していた）
+
+use core::fmt;
+use core::ptr::{read_volatile, write_volatile};
+
+use crate::arch::port::outb;
+use crate::sync::SpinLock;
+
+/// VGAテキストバッファの物理アドレス
+pub const VGA_BUFFER_PHYS: u64 = 0xb8000;
+/// 画面の幅（文字数）
+pub const BUFFER_WIDTH: usize = 80;
+/// 画面の高さ（行数）
+pub const BUFFER_HEIGHT: usize = 25;
+/// タブの幅
+pub const TAB_WIDTH: usize = 8;
+
+/// 表示できない文字の代わりに出す文字（■）
+const REPLACEMENT: u8 = 0xfe;
+
+/// CRTC（6845互換）のポート
+/// MINIX 3: set_6845() で VID_INDEX / VID_DATA に書いていた
+mod crtc {
+    pub const INDEX: u16 = 0x3d4;
+    pub const DATA: u16 = 0x3d5;
+    /// カーソル開始スキャンライン
+    pub const CURSOR_START: u8 = 0x0a;
+    /// カーソル終了スキャンライン
+    pub const CURSOR_END: u8 = 0x0b;
+    /// カーソル位置（上位8bit）
+    pub const CURSOR_HIGH: u8 = 0x0e;
+    /// カーソル位置（下位8bit）
+    pub const CURSOR_LOW: u8 = 0x0f;
+}
+
+/// 16色パレット
+#[allow(dead_code)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum Color {
+    Black = 0,
+    Blue = 1,
+    Green = 2,
+    Cyan = 3,
+    Red = 4,
+    Magenta = 5,
+    Brown = 6,
+    LightGray = 7,
+    DarkGray = 8,
+    LightBlue = 9,
+    LightGreen = 10,
+    LightCyan = 11,
+    LightRed = 12,
+    Pink = 13,
+    Yellow = 14,
+    White = 15,
+}
+
+/// 色属性（上位4bit: 背景色、下位4bit: 文字色）
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(transparent)]
+pub struct ColorCode(u8);
+
+impl ColorCode {
+    pub const fn new(foreground: Color, background: Color) -> Self {
+        Self(((background as u8) << 4) | (foreground as u8))
+    }
+
+    pub fn value(&self) -> u8 {
+        self.0
+    }
+}
+
+/// デフォルトの色（ライトグレー on ブラック）
+pub const DEFAULT_COLOR: ColorCode = ColorCode::new(Color::LightGray, Color::Black);
+
+/// 画面上の1文字
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(C)]
+pub struct ScreenChar {
+    pub ascii: u8,
+    pub color: ColorCode,
+}
+
+impl ScreenChar {
+    const fn blank(color: ColorCode) -> Self {
+        Self { ascii: b' ', color }
+    }
+}
+
+/// 画面バッファ全体（0xB8000のメモリ配置そのもの）
+#[repr(transparent)]
+pub struct Buffer {
+    chars: [[ScreenChar; BUFFER_WIDTH]; BUFFER_HEIGHT],
+}
+
+impl Buffer {
+    /// 空白で埋めたバッファ（テスト用のメモリ上の画面）
+    #[cfg(all(test, not(target_os = "none")))]
+    pub fn new() -> Self {
+        Self { chars: [[ScreenChar::blank(DEFAULT_COLOR); BUFFER_WIDTH]; BUFFER_HEIGHT] }
+    }
+
+    // 画面バッファはMMIOなので、最適化で読み書きが消されないようにvolatileでアクセスする
+    fn read(&self, row: usize, column: usize) -> ScreenChar {
+        unsafe { read_volatile(&self.chars[row][column]) }
+    }
+
+    fn write(&mut self, row: usize, column: usize, c: ScreenChar) {
+        unsafe { write_volatile(&mut self.chars[row][column], c) }
+    }
+}
+
+/// VGAテキスト画面への書き込み
+///
+/// カーソル位置を覚えておき、改行・スクロール・タブ・バックスペースを処理する。
+pub struct Writer<'a> {
+    buffer: &'a mut Buffer,
+    row: usize,
+    column: usize,
+    color: ColorCode,
+    /// CRTCのハードウェアカーソルを動かすか（メモリ上のバッファでは動かさない）
+    hardware_cursor: bool,
+}
+
+impl<'a> Writer<'a> {
+    /// 任意のバッファに書き込むWriter（ハードウェアカーソルは動かさない）
+    pub fn new(buffer: &'a mut Buffer) -> Self {
+        Self { buffer, row: 0, column: 0, color: DEFAULT_COLOR, hardware_cursor: false }
+    }
+
+    /// 実際のVGAテキストバッファに書き込むWriter
+    ///
+    /// # Safety
+    /// `address` はVGAテキストバッファ（物理 0xB8000）にマップされた仮想アドレスで、
+    /// 他に同時に書き込むWriterがないこと
+    pub unsafe fn from_address(address: u64) -> Writer<'static> {
+        let mut writer = Writer::new(&mut *(address as *mut Buffer));
+        writer.hardware_cursor = true;
+        writer.enable_cursor();
+        writer
+    }
+
+    /// 現在の色
+    pub fn color(&self) -> ColorCode {
+        self.color
+    }
+
+    /// 以降の文字の色を変える
+    pub fn set_color(&mut self, color: ColorCode) {
+        self.color = color;
+    }
+
+    /// カーソル位置 (行, 列)
+    pub fn position(&self) -> (usize, usize) {
+        (self.row, self.column)
+    }
+
+    /// カーソルを移動する（画面外は端に丸める）
+    pub fn set_position(&mut self, row: usize, column: usize) {
+        self.row = row.min(BUFFER_HEIGHT - 1);
+        self.column = column.min(BUFFER_WIDTH - 1);
+        self.update_cursor();
+    }
+
+    /// 指定位置の文字（テスト・デバッグ用）
+    pub fn char_at(&self, row: usize, column: usize) -> ScreenChar {
+        self.buffer.read(row, column)
+    }
+
+    /// 画面全体を現在の色の空白で埋め、カーソルを左上に戻す
+    pub fn clear_screen(&mut self) {
+        for row in 0..BUFFER_HEIGHT {
+            self.clear_row(row);
+        }
+        self.row = 0;
+        self.column = 0;
+        self.update_cursor();
+    }
+
+    /// 1バイト書き込む
+    pub fn write_byte(&mut self, byte: u8) {
+        self.put_byte(byte);
+        self.update_cursor();
+    }
+
+    /// 文字列を書き込む（ASCII以外は■で表示）
+    pub fn write_string(&mut self, s: &str) {
+        for c in s.chars() {
+            self.put_byte(if c.is_ascii() { c as u8 } else { REPLACEMENT });
+        }
+        self.update_cursor();
+    }
+
+    /// カーソルを更新せずに1バイト書く（文字列ではまとめて最後に更新する）
+    fn put_byte(&mut self, byte: u8) {
+        match byte {
+            b'\n' => self.new_line(),
+            b'\r' => self.column = 0,
+            b'\t' => {
+                let next = (self.column / TAB_WIDTH + 1) * TAB_WIDTH;
+                while self.column < next.min(BUFFER_WIDTH) {
+                    self.put_char(b' ');
+                }
+                if next >= BUFFER_WIDTH {
+                    self.new_line();
+                }
+            }
+            // バックスペース: 1つ戻って消す（行頭では何もしない）
+            0x08 => {
+                if self.column > 0 {
+                    self.column -= 1;
+                    self.buffer.write(self.row, self.column, ScreenChar::blank(self.color));
+                }
+            }
+            0x20..=0x7e => self.put_char(byte),
+            _ => self.put_char(REPLACEMENT),
+        }
+    }
+
+    /// 表示できる文字を書いてカーソルを進める（行末なら折り返す）
+    fn put_char(&mut self, ascii: u8) {
+        if self.column >= BUFFER_WIDTH {
+            self.new_line();
+        }
+        self.buffer.write(self.row, self.column, ScreenChar { ascii, color: self.color });
+        self.column += 1;
+    }
+
+    /// 改行（最下行ならスクロール）
+    fn new_line(&mut self) {
+        self.column = 0;
+        if self.row + 1 < BUFFER_HEIGHT {
+            self.row += 1;
+        } else {
+            self.scroll_up();
+        }
+    }
+
+    /// 1行上にスクロールし、最下行を空ける
+    /// MINIX 3: scroll_screen()
+    fn scroll_up(&mut self) {
+        for row in 1..BUFFER_HEIGHT {
+            for column in 0..BUFFER_WIDTH {
+                let c = self.buffer.read(row, column);
+                self.buffer.write(row - 1, column, c);
+            }
+        }
+        self.clear_row(BUFFER_HEIGHT - 1);
+    }
+
+    fn clear_row(&mut self, row: usize) {
+        for column in 0..BUFFER_WIDTH {
+            self.buffer.write(row, column, ScreenChar::blank(self.color));
+        }
+    }
+
+    /// ハードウェアカーソルを表示する（下2ライン分の下線カーソル）
+    fn enable_cursor(&self) {
+        if self.hardware_cursor {
+            unsafe {
+                outb(crtc::INDEX, crtc::CURSOR_START);
+                outb(crtc::DATA, 14);
+                outb(crtc::INDEX, crtc::CURSOR_END);
+                outb(crtc::DATA, 15);
+            }
+        }
+    }
+
+    /// ハードウェアカーソルを現在位置に動かす
+    /// MINIX 3: set_6845(CURSOR, ...)
+    fn update_cursor(&self) {
+        if self.hardware_cursor {
+            // 行末まで書いた直後は、次の行の先頭に表示する
+            let position = (self.row * BUFFER_WIDTH + self.column).min(BUFFER_WIDTH * BUFFER_HEIGHT - 1) as u16;
+            unsafe {
+                outb(crtc::INDEX, crtc::CURSOR_HIGH);
+                outb(crtc::DATA, (position >> 8) as u8);
+                outb(crtc::INDEX, crtc::CURSOR_LOW);
+                outb(crtc::DATA, position as u8);
+            }
+        }
+    }
+}
+
+impl fmt::Write for Writer<'_> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.write_string(s);
+        Ok(())
+    }
+}
+
+/// カーネル全体で共有する画面（kernel_main() で設定するまではNone）
+pub static WRITER: SpinLock<Option<Writer<'static>>> = SpinLock::new(None);
+
+// ===== テスト =====
+#[cfg(all(test, not(target_os = "none")))]
+mod tests {
+    use super::*;
+    use core::fmt::Write;
+
+    /// 行の内容を文字列として取り出す（末尾の空白は除く）
+    fn row_text(writer: &Writer, row: usize) -> String {
+        let text: String = (0..BUFFER_WIDTH).map(|c| writer.char_at(row, c).ascii as char).collect();
+        text.trim_end().to_string()
+    }
+
+    mod color_tests {
+        use super::*;
+
+        #[test]
+        fn test_color_code() {
+            assert_eq!(ColorCode::new(Color::White, Color::Black).value(), 0x0f, "白 on 黒");
+            assert_eq!(ColorCode::new(Color::Yellow, Color::Blue).value(), 0x1e, "上位4bitが背景色");
+            assert_eq!(DEFAULT_COLOR.value(), 0x07);
+        }
+
+        #[test]
+        fn test_screen_char_layout() {
+            // 0xB8000のメモリ配置: 文字、属性の順に2バイト
+            assert_eq!(core::mem::size_of::<ScreenChar>(), 2);
+            assert_eq!(core::mem::size_of::<Buffer>(), BUFFER_WIDTH * BUFFER_HEIGHT * 2);
+        }
+
+        #[test]
+        fn test_set_color() {
+            let mut buffer = Buffer::new();
+            let mut writer = Writer::new(&mut buffer);
+            let red = ColorCode::new(Color::LightRed, Color::Black);
+            writer.set_color(red);
+            writer.write_string("E");
+            assert_eq!(writer.char_at(0, 0).color, red, "設定した色で書かれる");
+            assert_eq!(writer.color(), red);
+        }
+    }
+
+    mod writer_tests {
+        use super::*;
+
+        #[test]
+        fn test_write_string() {
+            let mut buffer = Buffer::new();
+            let mut writer = Writer::new(&mut buffer);
+            writer.write_string("Hello");
+            assert_eq!(row_text(&writer, 0), "Hello");
+            assert_eq!(writer.position(), (0, 5), "カーソルは文字の後ろ");
+        }
+
+        #[test]
+        fn test_newline_and_carriage_return() {
+            let mut buffer = Buffer::new();
+            let mut writer = Writer::new(&mut buffer);
+            writer.write_string("abc\ndef\rX");
+            assert_eq!(row_text(&writer, 0), "abc");
+            assert_eq!(row_text(&writer, 1), "Xef", "\\rは行頭に戻るだけ");
+            assert_eq!(writer.position(), (1, 1));
+        }
+
+        #[test]
+        fn test_line_wrap() {
+            let mut buffer = Buffer::new();
+            let mut writer = Writer::new(&mut buffer);
+            let line = "x".repeat(BUFFER_WIDTH);
+            writer.write_string(&line);
+            assert_eq!(writer.position(), (0, BUFFER_WIDTH), "行末ではまだ折り返さない");
+            writer.write_string("y");
+            assert_eq!(row_text(&writer, 1), "y", "次の文字で折り返す");
+        }
+
+        #[test]
+        fn test_scroll() {
+            let mut buffer = Buffer::new();
+            let mut writer = Writer::new(&mut buffer);
+            for i in 0..BUFFER_HEIGHT + 2 {
+                writeln!(writer, "line {i}").unwrap();
+            }
+            // 27行書いて最後に改行したので、最下行は空、その上が "line 26"
+            assert_eq!(row_text(&writer, 0), "line 3", "先頭の3行は押し出される");
+            assert_eq!(row_text(&writer, BUFFER_HEIGHT - 2), "line 26");
+            assert_eq!(row_text(&writer, BUFFER_HEIGHT - 1), "", "最下行は空白");
+            assert_eq!(writer.position(), (BUFFER_HEIGHT - 1, 0));
+        }
+
+        #[test]
+        fn test_tab() {
+            let mut buffer = Buffer::new();
+            let mut writer = Writer::new(&mut buffer);
+            writer.write_string("ab\tc");
+            assert_eq!(row_text(&writer, 0), "ab      c", "次のタブ位置（8の倍数）まで進む");
+            assert_eq!(writer.position(), (0, TAB_WIDTH + 1));
+
+            writer.set_position(0, BUFFER_WIDTH - 3);
+            writer.write_string("\t");
+            assert_eq!(writer.position(), (1, 0), "行末を越えるタブは改行になる");
+        }
+
+        #[test]
+        fn test_backspace() {
+            let mut buffer = Buffer::new();
+            let mut writer = Writer::new(&mut buffer);
+            writer.write_string("abc\x08\x08d");
+            assert_eq!(row_text(&writer, 0), "ad", "1文字戻って消す");
+
+            writer.write_string("\n\x08");
+            assert_eq!(writer.position(), (1, 0), "行頭では何もしない");
+        }
+
+        #[test]
+        fn test_non_ascii() {
+            let mut buffer = Buffer::new();
+            let mut writer = Writer::new(&mut buffer);
+            writer.write_string("あa\x01");
+            assert_eq!(writer.char_at(0, 0).ascii, REPLACEMENT, "ASCII以外は1文字の■");
+            assert_eq!(writer.char_at(0, 1).ascii, b'a');
+            assert_eq!(writer.char_at(0, 2).ascii, REPLACEMENT, "制御文字も■");
+        }
+
+        #[test]
+        fn test_clear_screen() {
+            let mut buffer = Buffer::new();
+            let mut writer = Writer::new(&mut buffer);
+            writer.write_string("garbage\nmore");
+            let blue = ColorCode::new(Color::White, Color::Blue);
+            writer.set_color(blue);
+            writer.clear_screen();
+            assert_eq!(writer.position(), (0, 0));
+            assert_eq!(writer.char_at(1, 2), ScreenChar { ascii: b' ', color: blue }, "現在の色の空白で埋める");
+        }
+
+        #[test]
+        fn test_fmt_write() {
+            let mut buffer = Buffer::new();
+            let mut writer = Writer::new(&mut buffer);
+            write!(writer, "pid={} name=init", 42).unwrap();
+            assert_eq!(row_text(&writer, 0), "pid=42 name=init");
+        }
+    }
+}
@@ -0,0 +1,634 @@
+//! Rustのシンボル名のデマングル
+//!
+//! rustc は2種類のマングリングを使う。
+//! - legacy: `_ZN4core3fmt5Write10write_char17h0123456789abcdefE`（C++のItanium形式に似せたもの）
+//! - v0:     `_RNvNtCs1234_4core3fmt10write_char`（RFC 2603。ビルド済みの core はこちら）
+//!
+//! どちらも、末尾のハッシュやクレートの識別子を落として
+//! `core::fmt::Write::write_char` のような読める形にする。
+//! 解釈できない名前（Cの関数やアセンブリのラベル）はそのまま表示する。
+
+use core::fmt::{self, Write};
+
+/// 再帰の深さの上限（壊れた名前や後方参照のループで止まらないように）
+const MAX_DEPTH: u32 = 64;
+
+/// デマングルした名前（Display で書き出す）
+pub struct Demangle<'a> {
+    name: &'a str,
+}
+
+/// シンボル名をデマングルする
+pub fn demangle(name: &str) -> Demangle<'_> {
+    Demangle { name }
+}
+
+/// 解釈できなかった
+#[derive(Debug)]
+struct Invalid;
+
+impl From<fmt::Error> for Invalid {
+    fn from(_: fmt::Error) -> Self {
+        Invalid
+    }
+}
+
+/// 何も書かない Write（形式が正しいかだけを先に確かめる）
+struct Sink;
+
+impl Write for Sink {
+    fn write_str(&mut self, _: &str) -> fmt::Result {
+        Ok(())
+    }
+}
+
+fn print(name: &str, out: &mut dyn Write) -> Result<(), Invalid> {
+    if let Some(rest) = name.strip_prefix("_R") {
+        let mut printer = V0 { sym: rest.as_bytes(), pos: 0, depth: 0, silent: 0, out };
+        printer.symbol()
+    } else if let Some(rest) = name.strip_prefix("_ZN").or_else(|| name.strip_prefix("__ZN")) {
+        legacy(rest, out)
+    } else {
+        Err(Invalid)
+    }
+}
+
+impl fmt::Display for Demangle<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        // 途中で失敗して半端な名前を出さないよう、先に空書きで確かめる
+        if print(self.name, &mut Sink).is_ok() {
+            print(self.name, f).map_err(|_| fmt::Error)
+        } else {
+            f.write_str(self.name)
+        }
+    }
+}
+
+// ===== legacy =====
+
+/// `<長さ><名前>` の並びを `::` でつなぐ。最後の `h<16桁の16進数>` はハッシュなので落とす
+fn legacy(mut rest: &str, out: &mut dyn Write) -> Result<(), Invalid> {
+    let mut first = true;
+    loop {
+        if rest.starts_with('E') {
+            return if first { Err(Invalid) } else { Ok(()) };
+        }
+        let digits = rest.bytes().take_while(u8::is_ascii_digit).count();
+        let len: usize = rest[..digits].parse().map_err(|_| Invalid)?;
+        let ident = rest.get(digits..digits + len).ok_or(Invalid)?;
+        rest = &rest[digits + len..];
+
+        if rest.starts_with('E') && is_legacy_hash(ident) {
+            continue;
+        }
+        if !first {
+            out.write_str("::")?;
+        }
+        first = false;
+        legacy_ident(ident, out)?;
+    }
+}
+
+fn is_legacy_hash(ident: &str) -> bool {
+    ident.len() == 17 && ident.starts_with('h') && ident[1..].bytes().all(|b| b.is_ascii_hexdigit())
+}
+
+/// `$LT$` などのエスケープを戻す
+fn legacy_ident(ident: &str, out: &mut dyn Write) -> Result<(), Invalid> {
+    let mut rest = ident.strip_prefix("_$").map_or(ident, |r| &ident[ident.len() - r.len() - 1..]);
+    while !rest.is_empty() {
+        if let Some(r) = rest.strip_prefix("..") {
+            out.write_str("::")?;
+            rest = r;
+        } else if let Some(r) = rest.strip_prefix('$') {
+            let end = r.find('$').ok_or(Invalid)?;
+            let escape = &r[..end];
+            let c = match escape {
+                "SP" => '@',
+                "BP" => '*',
+                "RF" => '&',
+                "LT" => '<',
+                "GT" => '>',
+                "LP" => '(',
+                "RP" => ')',
+                "C" => ',',
+                _ => {
+                    let hex = escape.strip_prefix('u').ok_or(Invalid)?;
+                    let code = u32::from_str_radix(hex, 16).map_err(|_| Invalid)?;
+                    char::from_u32(code).ok_or(Invalid)?
+                }
+            };
+            out.write_char(c)?;
+            rest = &r[end + 1..];
+        } else {
+            let end = rest[1..].find(['$', '.']).map_or(rest.len(), |i| i + 1);
+            out.write_str(&rest[..end])?;
+            rest = &rest[end..];
+        }
+    }
+    Ok(())
+}
+
+// ===== v0 =====
+
+/// v0形式の読み取りと書き出しを同時に行う
+struct V0<'a, 'o> {
+    /// `_R` より後ろ（後方参照の位置はここからのオフセット）
+    sym: &'a [u8],
+    pos: usize,
+    depth: u32,
+    /// 0より大きい間は何も書かない（impl のパスを読み飛ばすとき）
+    silent: u32,
+    out: &'o mut dyn Write,
+}
+
+impl<'a> V0<'a, '_> {
+    fn peek(&self) -> Option<u8> {
+        self.sym.get(self.pos).copied()
+    }
+
+    fn eat(&mut self, b: u8) -> bool {
+        if self.peek() == Some(b) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn next(&mut self) -> Result<u8, Invalid> {
+        let b = self.peek().ok_or(Invalid)?;
+        self.pos += 1;
+        Ok(b)
+    }
+
+    fn write(&mut self, s: &str) -> Result<(), Invalid> {
+        if self.silent > 0 {
+            return Ok(());
+        }
+        self.out.write_str(s).map_err(Invalid::from)
+    }
+
+    fn print(&mut self, args: fmt::Arguments) -> Result<(), Invalid> {
+        if self.silent > 0 {
+            return Ok(());
+        }
+        self.out.write_fmt(args).map_err(Invalid::from)
+    }
+
+    fn symbol(&mut self) -> Result<(), Invalid> {
+        // エンコーディングのバージョン（省略可）
+        while self.peek().is_some_and(|b| b.is_ascii_digit()) {
+            self.pos += 1;
+        }
+        self.path(true)
+        // 後ろに続くインスタンス化したクレートとベンダー接尾辞は表示しない
+    }
+
+    /// `<0-9a-zA-Z>* _`（`_` だけなら0、それ以外は値+1）
+    fn base62(&mut self) -> Result<u64, Invalid> {
+        if self.eat(b'_') {
+            return Ok(0);
+        }
+        let mut value: u64 = 0;
+        loop {
+            let b = self.next()?;
+            let digit = match b {
+                b'0'..=b'9' => b - b'0',
+                b'a'..=b'z' => b - b'a' + 10,
+                b'A'..=b'Z' => b - b'A' + 36,
+                b'_' => return value.checked_add(1).ok_or(Invalid),
+                _ => return Err(Invalid),
+            };
+            value = value.checked_mul(62).and_then(|v| v.checked_add(u64::from(digit))).ok_or(Invalid)?;
+        }
+    }
+
+    fn decimal(&mut self) -> Result<usize, Invalid> {
+        let start = self.pos;
+        while self.peek().is_some_and(|b| b.is_ascii_digit()) {
+            self.pos += 1;
+        }
+        let digits = core::str::from_utf8(&self.sym[start..self.pos]).map_err(|_| Invalid)?;
+        if digits.is_empty() || (digits.len() > 1 && digits.starts_with('0')) {
+            return Err(Invalid);
+        }
+        digits.parse().map_err(|_| Invalid)
+    }
+
+    /// `s<base62>`（省略されていれば0）
+    fn disambiguator(&mut self) -> Result<u64, Invalid> {
+        if self.eat(b's') {
+            Ok(self.base62()? + 1)
+        } else {
+            Ok(0)
+        }
+    }
+
+    /// `[u] <decimal> [_] <bytes>` を読む
+    fn ident(&mut self) -> Result<&'a str, Invalid> {
+        let punycode = self.eat(b'u');
+        let len = self.decimal()?;
+        self.eat(b'_');
+        let bytes = self.sym.get(self.pos..self.pos + len).ok_or(Invalid)?;
+        self.pos += len;
+        if punycode {
+            // Unicodeの識別子は元に戻さず、エンコードされたまま表示する
+        }
+        core::str::from_utf8(bytes).map_err(|_| Invalid)
+    }
+
+    /// 後方参照の先を同じ方法で書き出す
+    fn backref(&mut self, f: impl FnOnce(&mut Self) -> Result<(), Invalid>) -> Result<(), Invalid> {
+        let start = self.pos - 1;
+        let target = self.base62()? as usize;
+        if target >= start || self.depth >= MAX_DEPTH {
+            return Err(Invalid);
+        }
+        let saved = self.pos;
+        self.pos = target;
+        self.depth += 1;
+        let result = f(self);
+        self.depth -= 1;
+        self.pos = saved;
+        result
+    }
+
+    fn enter(&mut self) -> Result<(), Invalid> {
+        self.depth += 1;
+        if self.depth > MAX_DEPTH {
+            Err(Invalid)
+        } else {
+            Ok(())
+        }
+    }
+
+    /// パス。`in_value` なら型引数を `::<...>` で書く
+    fn path(&mut self, in_value: bool) -> Result<(), Invalid> {
+        self.enter()?;
+        let result = self.path_inner(in_value);
+        self.depth -= 1;
+        result
+    }
+
+    fn path_inner(&mut self, in_value: bool) -> Result<(), Invalid> {
+        match self.next()? {
+            b'C' => {
+                self.disambiguator()?;
+                let name = self.ident()?;
+                self.write(name)?;
+            }
+            b'N' => {
+                let namespace = self.next()?;
+                if !namespace.is_ascii_alphabetic() {
+                    return Err(Invalid);
+                }
+                self.path(in_value)?;
+                let disambiguator = self.disambiguator()?;
+                let name = self.ident()?;
+                if namespace.is_ascii_uppercase() {
+                    let kind = match namespace {
+                        b'C' => "closure",
+                        b'S' => "shim",
+                        _ => "",
+                    };
+                    if name.is_empty() {
+                        self.print(format_args!("::{{{}#{}}}", kind, disambiguator))?;
+                    } else {
+                        self.print(format_args!("::{{{}:{}#{}}}", kind, name, disambiguator))?;
+                    }
+                } else if !name.is_empty() {
+                    self.write("::")?;
+                    self.write(name)?;
+                }
+            }
+            b'M' => {
+                self.impl_path()?;
+                self.write("<")?;
+                self.ty()?;
+                self.write(">")?;
+            }
+            b'X' => {
+                self.impl_path()?;
+                self.write("<")?;
+                self.ty()?;
+                self.write(" as ")?;
+                self.path(false)?;
+                self.write(">")?;
+            }
+            b'Y' => {
+                self.write("<")?;
+                self.ty()?;
+                self.write(" as ")?;
+                self.path(false)?;
+                self.write(">")?;
+            }
+            b'I' => {
+                self.path(in_value)?;
+                self.write(if in_value { "::<" } else { "<" })?;
+                self.list(b'E', ", ", Self::generic_arg)?;
+                self.write(">")?;
+            }
+            b'B' => self.backref(|p| p.path(in_value))?,
+            _ => return Err(Invalid),
+        }
+        Ok(())
+    }
+
+    /// impl のパスは表示しない（`<T>` や `<T as Trait>` だけにする）
+    fn impl_path(&mut self) -> Result<(), Invalid> {
+        self.disambiguator()?;
+        self.silent += 1;
+        let result = self.path(false);
+        self.silent -= 1;
+        result
+    }
+
+    /// `end` が来るまで `item` を `separator` 区切りで書く
+    fn list(&mut self, end: u8, separator: &str, item: fn(&mut Self) -> Result<(), Invalid>) -> Result<usize, Invalid> {
+        let mut count = 0;
+        while !self.eat(end) {
+            if count > 0 {
+                self.write(separator)?;
+            }
+            item(self)?;
+            count += 1;
+        }
+        Ok(count)
+    }
+
+    fn generic_arg(&mut self) -> Result<(), Invalid> {
+        if self.eat(b'L') {
+            self.base62()?;
+            self.write("'_")
+        } else if self.eat(b'K') {
+            self.constant()
+        } else {
+            self.ty()
+        }
+    }
+
+    fn ty(&mut self) -> Result<(), Invalid> {
+        self.enter()?;
+        let result = self.ty_inner();
+        self.depth -= 1;
+        result
+    }
+
+    fn ty_inner(&mut self) -> Result<(), Invalid> {
+        let b = self.peek().ok_or(Invalid)?;
+        if let Some(name) = basic_type(b) {
+            self.pos += 1;
+            return self.write(name);
+        }
+        self.pos += 1;
+        match b {
+            b'R' | b'Q' => {
+                if self.eat(b'L') {
+                    self.base62()?;
+                }
+                self.write(if b == b'R' { "&" } else { "&mut " })?;
+                self.ty()?;
+            }
+            b'P' => {
+                self.write("*const ")?;
+                self.ty()?;
+            }
+            b'O' => {
+                self.write("*mut ")?;
+                self.ty()?;
+            }
+            b'A' => {
+                self.write("[")?;
+                self.ty()?;
+                self.write("; ")?;
+                self.constant()?;
+                self.write("]")?;
+            }
+            b'S' => {
+                self.write("[")?;
+                self.ty()?;
+                self.write("]")?;
+            }
+            b'T' => {
+                self.write("(")?;
+                let count = self.list(b'E', ", ", Self::ty)?;
+                if count == 1 {
+                    self.write(",")?;
+                }
+                self.write(")")?;
+            }
+            b'F' => {
+                if self.eat(b'G') {
+                    self.base62()?;
+                }
+                if self.eat(b'U') {
+                    self.write("unsafe ")?;
+                }
+                if self.eat(b'K') {
+                    if self.eat(b'C') {
+                        self.write("extern \"C\" ")?;
+                    } else {
+                        let abi = self.ident()?;
+                        self.print(format_args!("extern \"{}\" ", abi))?;
+                    }
+                }
+                self.write("fn(")?;
+                self.list(b'E', ", ", Self::ty)?;
+                self.write(")")?;
+                if self.eat(b'u') {
+                    // 戻り値が () なら省略
+                } else {
+                    self.write(" -> ")?;
+                    self.ty()?;
+                }
+            }
+            b'D' => {
+                if self.eat(b'G') {
+                    self.base62()?;
+                }
+                self.write("dyn ")?;
+                self.list(b'E', " + ", Self::dyn_trait)?;
+                if !self.eat(b'L') {
+                    return Err(Invalid);
+                }
+                self.base62()?;
+            }
+            b'B' => self.backref(Self::ty)?,
+            _ => {
+                // パスで表される型（構造体など）
+                self.pos -= 1;
+                self.path(false)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn dyn_trait(&mut self) -> Result<(), Invalid> {
+        self.path(false)?;
+        while self.eat(b'p') {
+            let name = self.ident()?;
+            self.print(format_args!("<{} = ", name))?;
+            self.ty()?;
+            self.write(">")?;
+        }
+        Ok(())
+    }
+
+    fn constant(&mut self) -> Result<(), Invalid> {
+        if self.eat(b'p') {
+            return self.write("_");
+        }
+        if self.eat(b'B') {
+            return self.backref(Self::constant);
+        }
+        let ty = self.next()?;
+        let negative = self.eat(b'n');
+        let start = self.pos;
+        while self.peek().is_some_and(|b| b.is_ascii_hexdigit()) {
+            self.pos += 1;
+        }
+        let hex = core::str::from_utf8(&self.sym[start..self.pos]).map_err(|_| Invalid)?;
+        if !self.eat(b'_') {
+            return Err(Invalid);
+        }
+        let value = u64::from_str_radix(if hex.is_empty() { "0" } else { hex }, 16).ok();
+        match (ty, value) {
+            (b'b', Some(0)) => self.write("false"),
+            (b'b', Some(1)) => self.write("true"),
+            (b'c', Some(v)) => {
+                let c = char::from_u32(v as u32).ok_or(Invalid)?;
+                self.print(format_args!("{:?}", c))
+            }
+            (_, Some(v)) => self.print(format_args!("{}{}", if negative { "-" } else { "" }, v)),
+            (_, None) => self.print(format_args!("0x{}", hex)),
+        }
+    }
+}
+
+/// 1文字で表される基本型
+fn basic_type(b: u8) -> Option<&'static str> {
+    Some(match b {
+        b'a' => "i8",
+        b'b' => "bool",
+        b'c' => "char",
+        b'd' => "f64",
+        b'e' => "str",
+        b'f' => "f32",
+        b'h' => "u8",
+        b'i' => "isize",
+        b'j' => "usize",
+        b'l' => "i32",
+        b'm' => "u32",
+        b'n' => "i128",
+        b'o' => "u128",
+        b's' => "i16",
+        b't' => "u16",
+        b'u' => "()",
+        b'v' => "...",
+        b'x' => "i64",
+        b'y' => "u64",
+        b'z' => "!",
+        b'p' => "_",
+        _ => return None,
+    })
+}
+
+// ===== テスト =====
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn d(name: &str) -> String {
+        demangle(name).to_string()
+    }
+
+    mod legacy_tests {
+        use super::*;
+
+        #[test]
+        fn test_simple_path() {
+            assert_eq!(d("_ZN4core3fmt5Write10write_char17h5967f7981c518676E"), "core::fmt::Write::write_char");
+            assert_eq!(d("_ZN6kernel11kernel_main17h0123456789abcdefE"), "kernel::kernel_main", "ハッシュは落とす");
+        }
+
+        #[test]
+        fn test_escapes() {
+            assert_eq!(
+                d("_ZN44_$LT$$RF$T$u20$as$u20$core..fmt..Display$GT$3fmt17haf5e797e8474c873E"),
+                "<&T as core::fmt::Display>::fmt"
+            );
+            assert_eq!(
+                d("_ZN4core3ptr75drop_in_place$LT$kernel..sync..SpinLockGuard$LT$kernel..vga..Writer$GT$$GT$17h1111111111111111E"),
+                "core::ptr::drop_in_place<kernel::sync::SpinLockGuard<kernel::vga::Writer>>"
+            );
+            assert_eq!(d("_ZN6kernel4main28_$u7b$$u7b$closure$u7d$$u7d$17h2222222222222222E"), "kernel::main::{{closure}}");
+        }
+
+        #[test]
+        fn test_without_hash() {
+            assert_eq!(d("_ZN3foo3barE"), "foo::bar", "ハッシュがなくてもよい");
+        }
+
+        #[test]
+        fn test_invalid_kept_as_is() {
+            assert_eq!(d("_ZN3fooE_broken"), "foo", "Eより後ろ（.llvm.など）は無視する");
+            assert_eq!(d("_ZN99tooshortE"), "_ZN99tooshortE", "長さが合わなければそのまま");
+            assert_eq!(d("_ZNE"), "_ZNE");
+        }
+    }
+
+    mod v0_tests {
+        use super::*;
+
+        #[test]
+        fn test_trait_impl_with_backrefs() {
+            assert_eq!(
+                d("_RNvXs1_NtNtCsgEmfK2I1SDS_4core5panic10panic_infoNtB5_12PanicMessageNtNtB9_3fmt7Display3fmt"),
+                "<core::panic::panic_info::PanicMessage as core::fmt::Display>::fmt"
+            );
+        }
+
+        #[test]
+        fn test_nested_path() {
+            assert_eq!(d("_RNvNtCs1234_7mycrate3foo3bar"), "mycrate::foo::bar");
+            assert_eq!(d("_RNvCs1234_7mycrate4main"), "mycrate::main");
+        }
+
+        #[test]
+        fn test_generics_and_types() {
+            // mycrate::foo::<u8, &str>
+            assert_eq!(d("_RINvCs1234_7mycrate3foohReE"), "mycrate::foo::<u8, &str>");
+            // mycrate::foo::<[u32; 4]>
+            assert_eq!(d("_RINvCs1234_7mycrate3fooAmj4_E"), "mycrate::foo::<[u32; 4]>");
+            // mycrate::foo::<(i32,)>
+            assert_eq!(d("_RINvCs1234_7mycrate3fooTlEE"), "mycrate::foo::<(i32,)>");
+        }
+
+        #[test]
+        fn test_closure() {
+            assert_eq!(d("_RNCNvCs1234_7mycrate4main0"), "mycrate::main::{closure#0}");
+            assert_eq!(d("_RNCNvCs1234_7mycrate4mains0_0"), "mycrate::main::{closure#2}");
+        }
+
+        #[test]
+        fn test_inherent_impl() {
+            // <mycrate::Foo>::new
+            assert_eq!(d("_RNvMNtCs1234_7mycrate3fooNtB2_3Foo3new"), "<mycrate::foo::Foo>::new");
+        }
+
+        #[test]
+        fn test_invalid_v0() {
+            assert_eq!(d("_RNvB9999_3foo"), "_RNvB9999_3foo", "前方を指す後方参照は不正");
+            assert_eq!(d("_R"), "_R");
+            assert_eq!(d("_RNvC"), "_RNvC");
+        }
+    }
+
+    #[test]
+    fn test_non_rust_names() {
+        assert_eq!(d("__isr_common"), "__isr_common");
+        assert_eq!(d("memcpy"), "memcpy");
+        assert_eq!(d("_start"), "_start");
+    }
+}
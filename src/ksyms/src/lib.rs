@@ -0,0 +1,335 @@
+//! カーネルのシンボルテーブル
+//!
+//! バックトレースのアドレスを `関数名+オフセット` で表示するための表。
+//! ビルド後にホストの `ksyms` コマンドがカーネルELFの .symtab を読み、
+//! デマングルした名前をアドレス順に並べて、カーネル内の `.ksyms` セクションに書き込む。
+//! カーネルは起動後にその領域を読むだけなので、リンクを2回やり直す必要がない。
+//!
+//! # 形式（すべてリトルエンディアン）
+//! ```text
+//! ヘッダ（16バイト）: magic "KSYM" | version u16 | 予約 u16 | count u32 | strings_len u32
+//! エントリ × count（16バイト、アドレス順）: address u64 | size u32 | name_offset u32
+//! 文字列領域: NUL終端の名前を並べたもの
+//! ```
+//!
+//! # MINIX 3との比較
+//! MINIX 3はカーネル内に名前を持たず、アドレスだけを表示して
+//! ホストの nm や addr2line で調べていた。
+
+// テスト時は標準ライブラリを使用
+#![cfg_attr(not(test), no_std)]
+
+pub mod demangle;
+
+pub use demangle::demangle;
+
+/// ヘッダの先頭
+pub const MAGIC: [u8; 4] = *b"KSYM";
+/// 形式のバージョン
+pub const VERSION: u16 = 1;
+/// ヘッダのサイズ
+pub const HEADER_SIZE: usize = 16;
+/// エントリ1つのサイズ
+pub const ENTRY_SIZE: usize = 16;
+
+/// 表の書き込みエラー
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Error {
+    /// 出力先に入りきらない（必要なバイト数）
+    TooLarge(usize),
+}
+
+impl core::fmt::Display for Error {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        match self {
+            Self::TooLarge(needed) => write!(f, "symbol table needs {} bytes", needed),
+        }
+    }
+}
+
+/// 表に入れる前のシンボル
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RawSymbol<'a> {
+    pub address: u64,
+    /// 関数の大きさ（不明なら0）
+    pub size: u32,
+    pub name: &'a str,
+}
+
+/// 表に必要なバイト数
+pub fn table_size(symbols: &[RawSymbol]) -> usize {
+    HEADER_SIZE + symbols.iter().map(|s| ENTRY_SIZE + s.name.len() + 1).sum::<usize>()
+}
+
+/// シンボルをアドレス順に並べ替えて `out` に表を書く。書いたバイト数を返す
+///
+/// 同じアドレスのシンボルは名前順で最初の1つだけを残す。
+pub fn write_table(symbols: &mut [RawSymbol], out: &mut [u8]) -> Result<usize, Error> {
+    symbols.sort_unstable_by_key(|s| (s.address, s.name));
+    let mut count = 0;
+    let mut last = None;
+    for i in 0..symbols.len() {
+        if last != Some(symbols[i].address) {
+            last = Some(symbols[i].address);
+            symbols[count] = symbols[i];
+            count += 1;
+        }
+    }
+    let symbols = &symbols[..count];
+
+    let needed = table_size(symbols);
+    if needed > out.len() {
+        return Err(Error::TooLarge(needed));
+    }
+
+    let strings_start = HEADER_SIZE + ENTRY_SIZE * count;
+    out[0..4].copy_from_slice(&MAGIC);
+    out[4..6].copy_from_slice(&VERSION.to_le_bytes());
+    out[6..8].copy_from_slice(&[0, 0]);
+    out[8..12].copy_from_slice(&(count as u32).to_le_bytes());
+    out[12..16].copy_from_slice(&((needed - strings_start) as u32).to_le_bytes());
+
+    let mut name_offset = 0;
+    for (i, symbol) in symbols.iter().enumerate() {
+        let entry = &mut out[HEADER_SIZE + ENTRY_SIZE * i..][..ENTRY_SIZE];
+        entry[0..8].copy_from_slice(&symbol.address.to_le_bytes());
+        entry[8..12].copy_from_slice(&symbol.size.to_le_bytes());
+        entry[12..16].copy_from_slice(&(name_offset as u32).to_le_bytes());
+
+        let name = &mut out[strings_start + name_offset..][..symbol.name.len() + 1];
+        name[..symbol.name.len()].copy_from_slice(symbol.name.as_bytes());
+        name[symbol.name.len()] = 0;
+        name_offset += symbol.name.len() + 1;
+    }
+    Ok(needed)
+}
+
+/// アドレスを解決した結果
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Symbol<'a> {
+    pub name: &'a str,
+    /// シンボルの先頭からのオフセット
+    pub offset: u64,
+}
+
+impl core::fmt::Display for Symbol<'_> {
+    /// `name+0x1f` の形
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        write!(f, "{}+{:#x}", self.name, self.offset)
+    }
+}
+
+/// 書き込み済みの表の読み出し
+#[derive(Debug, Clone, Copy)]
+pub struct SymbolTable<'a> {
+    entries: &'a [u8],
+    strings: &'a [u8],
+}
+
+impl<'a> SymbolTable<'a> {
+    /// 空の表（シンボルが埋め込まれていないとき）
+    pub const EMPTY: SymbolTable<'static> = SymbolTable { entries: &[], strings: &[] };
+
+    /// 表を読む。マジックやサイズが合わなければ None（まだ埋め込まれていない）
+    pub fn parse(data: &'a [u8]) -> Option<Self> {
+        if data.len() < HEADER_SIZE || data[0..4] != MAGIC || read_u16(data, 4) != VERSION {
+            return None;
+        }
+        let count = read_u32(data, 8) as usize;
+        let strings_len = read_u32(data, 12) as usize;
+        let strings_start = HEADER_SIZE.checked_add(count.checked_mul(ENTRY_SIZE)?)?;
+        let end = strings_start.checked_add(strings_len)?;
+        if end > data.len() {
+            return None;
+        }
+        Some(Self { entries: &data[HEADER_SIZE..strings_start], strings: &data[strings_start..end] })
+    }
+
+    /// シンボルの数
+    pub fn len(&self) -> usize {
+        self.entries.len() / ENTRY_SIZE
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn address(&self, index: usize) -> u64 {
+        read_u64(self.entries, index * ENTRY_SIZE)
+    }
+
+    fn name(&self, index: usize) -> &'a str {
+        let offset = read_u32(self.entries, index * ENTRY_SIZE + 12) as usize;
+        let bytes = self.strings.get(offset..).unwrap_or(&[]);
+        let end = bytes.iter().position(|&b| b == 0).unwrap_or(bytes.len());
+        core::str::from_utf8(&bytes[..end]).unwrap_or("?")
+    }
+
+    /// `address` を含む関数を探す
+    ///
+    /// 大きさがわかっている関数の範囲外（関数の間の詰め物など）なら None。
+    pub fn lookup(&self, address: u64) -> Option<Symbol<'a>> {
+        // address 以下で最大の開始アドレスを二分探索する
+        let (mut low, mut high) = (0, self.len());
+        while low < high {
+            let mid = (low + high) / 2;
+            if self.address(mid) <= address {
+                low = mid + 1;
+            } else {
+                high = mid;
+            }
+        }
+        let index = low.checked_sub(1)?;
+        let start = self.address(index);
+        let size = u64::from(read_u32(self.entries, index * ENTRY_SIZE + 8));
+        let offset = address - start;
+        if size != 0 && offset >= size {
+            return None;
+        }
+        Some(Symbol { name: self.name(index), offset })
+    }
+
+    /// 戻りアドレスを含む関数を探す
+    ///
+    /// 戻りアドレスは call 命令の次を指すので、関数の最後の命令が call だと
+    /// 次の関数を指してしまう。1つ前のアドレスで探してからオフセットを戻す。
+    pub fn lookup_return_address(&self, address: u64) -> Option<Symbol<'a>> {
+        let symbol = self.lookup(address.checked_sub(1)?)?;
+        Some(Symbol { offset: symbol.offset + 1, ..symbol })
+    }
+
+    /// すべてのシンボルをアドレス順に列挙する
+    pub fn iter(&self) -> impl Iterator<Item = (u64, &'a str)> + '_ {
+        (0..self.len()).map(|i| (self.address(i), self.name(i)))
+    }
+}
+
+fn read_u16(data: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes([data[offset], data[offset + 1]])
+}
+
+fn read_u32(data: &[u8], offset: usize) -> u32 {
+    let mut bytes = [0; 4];
+    bytes.copy_from_slice(&data[offset..offset + 4]);
+    u32::from_le_bytes(bytes)
+}
+
+fn read_u64(data: &[u8], offset: usize) -> u64 {
+    let mut bytes = [0; 8];
+    bytes.copy_from_slice(&data[offset..offset + 8]);
+    u64::from_le_bytes(bytes)
+}
+
+// ===== テスト =====
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn build(symbols: &mut [RawSymbol]) -> Vec<u8> {
+        let mut out = vec![0; 4096];
+        let len = write_table(symbols, &mut out).unwrap();
+        out.truncate(len);
+        out
+    }
+
+    mod table_tests {
+        use super::*;
+
+        #[test]
+        fn test_roundtrip_sorted() {
+            let data = build(&mut [
+                RawSymbol { address: 0x3000, size: 0x10, name: "c" },
+                RawSymbol { address: 0x1000, size: 0x100, name: "kernel::kernel_main" },
+                RawSymbol { address: 0x2000, size: 0, name: "__isr_common" },
+            ]);
+            let table = SymbolTable::parse(&data).expect("書いた表は読める");
+            assert_eq!(table.len(), 3);
+            let names: Vec<_> = table.iter().map(|(_, name)| name).collect();
+            assert_eq!(names, ["kernel::kernel_main", "__isr_common", "c"], "アドレス順に並ぶ");
+        }
+
+        #[test]
+        fn test_duplicate_addresses_are_merged() {
+            let data = build(&mut [
+                RawSymbol { address: 0x1000, size: 4, name: "first" },
+                RawSymbol { address: 0x1000, size: 4, name: "alias" },
+            ]);
+            let table = SymbolTable::parse(&data).unwrap();
+            assert_eq!(table.len(), 1, "同じアドレスは1つにまとめる");
+        }
+
+        #[test]
+        fn test_too_large() {
+            let mut symbols = [RawSymbol { address: 0, size: 0, name: "a_long_function_name" }];
+            let mut out = [0; 20];
+            assert_eq!(write_table(&mut symbols, &mut out), Err(Error::TooLarge(HEADER_SIZE + ENTRY_SIZE + 21)));
+        }
+
+        #[test]
+        fn test_parse_rejects_empty_area() {
+            assert!(SymbolTable::parse(&[0; 64]).is_none(), "埋め込まれていない領域は読まない");
+            let mut data = build(&mut [RawSymbol { address: 0, size: 0, name: "x" }]);
+            data.truncate(data.len() - 1);
+            assert!(SymbolTable::parse(&data).is_none(), "途中で切れた表は読まない");
+        }
+    }
+
+    mod lookup_tests {
+        use super::*;
+
+        #[test]
+        fn test_lookup_offset() {
+            let data = build(&mut [
+                RawSymbol { address: 0x1000, size: 0x80, name: "foo" },
+                RawSymbol { address: 0x2000, size: 0, name: "bar" },
+            ]);
+            let table = SymbolTable::parse(&data).unwrap();
+            assert_eq!(table.lookup(0x1000), Some(Symbol { name: "foo", offset: 0 }));
+            assert_eq!(table.lookup(0x101f).unwrap().to_string(), "foo+0x1f", "関数名+オフセット");
+            assert_eq!(table.lookup(0x1080), None, "大きさがわかっていれば範囲外は解決しない");
+            assert_eq!(table.lookup(0x2345).unwrap().to_string(), "bar+0x345", "大きさ0なら次のシンボルまで");
+            assert_eq!(table.lookup(0xfff), None, "最初のシンボルより前");
+        }
+
+        #[test]
+        fn test_lookup_return_address() {
+            let data = build(&mut [
+                RawSymbol { address: 0x1000, size: 0x10, name: "calls_panic" },
+                RawSymbol { address: 0x1010, size: 0x10, name: "next" },
+            ]);
+            let table = SymbolTable::parse(&data).unwrap();
+            assert_eq!(table.lookup(0x1010).unwrap().name, "next");
+            assert_eq!(
+                table.lookup_return_address(0x1010),
+                Some(Symbol { name: "calls_panic", offset: 0x10 }),
+                "最後の call の戻りアドレスは呼び出した関数のもの"
+            );
+            assert_eq!(table.lookup_return_address(0), None);
+        }
+
+        #[test]
+        fn test_lookup_many() {
+            let names: Vec<String> = (0..500).map(|i| format!("f{}", i)).collect();
+            let mut symbols: Vec<_> = names
+                .iter()
+                .enumerate()
+                .rev()
+                .map(|(i, name)| RawSymbol { address: 0x1000 + i as u64 * 0x40, size: 0x40, name })
+                .collect();
+            let mut out = vec![0; table_size(&symbols)];
+            write_table(&mut symbols, &mut out).unwrap();
+            let table = SymbolTable::parse(&out).unwrap();
+            for i in (0..500).step_by(37) {
+                let symbol = table.lookup(0x1000 + i as u64 * 0x40 + 5).unwrap();
+                assert_eq!(symbol.name, format!("f{}", i));
+                assert_eq!(symbol.offset, 5);
+            }
+        }
+
+        #[test]
+        fn test_empty_table() {
+            assert_eq!(SymbolTable::EMPTY.lookup(0x1000), None);
+            assert!(SymbolTable::EMPTY.is_empty());
+        }
+    }
+}
@@ -0,0 +1,283 @@
+//! ユーザープロセスのためのIPCライブラリ
+//!
+//! MINIX 3 のプロセスは、固定長（64バイト）のメッセージをカーネル経由で送り合って仕事をする。
+//! このクレートはメッセージの形式、トラップ番号、エラー番号と、それを呼ぶ安全な関数をまとめたもの。
+//! カーネルも同じ定義を使うので、ユーザーとカーネルでメッセージの解釈がずれない。
+//!
+//! # 呼び出し方（x86_64）
+//! ```text
+//! rax: トラップ番号（SEND など）、rdi: 相手のエンドポイント、rsi: メッセージのアドレス
+//! rax: 結果（0なら成功、負ならエラー番号）
+//! ```
+//! `int 0x21`（IPC_VECTOR）ならトラップ番号そのまま、`syscall` なら IPC_CALL_BASE を足した番号で呼ぶ。
+//!
+//! # MINIX 3との比較
+//! MINIX 3 の libc は `_ipc_send_intr()`（int IPCVEC）と `_ipc_send_syscall()` などを用意し、
+//! 起動時にCPUに合わせて使う方を選ぶ（lib/libc/arch/i386/sys/_ipc.S、ipc.h）。
+
+// テスト時は標準ライブラリを使用
+#![cfg_attr(not(test), no_std)]
+
+use core::fmt;
+
+/// エンドポイント（相手のプロセス番号）
+/// MINIX 3: endpoint_t
+pub type Endpoint = i32;
+
+/// 誰からでも受信する（receive の相手として使う）
+/// MINIX 3: ANY（0x7ace）
+pub const ANY: Endpoint = 0x7ace;
+
+/// 送る（相手が受信するまで待つ）
+pub const SEND: u64 = 1;
+/// 受信する（メッセージが届くまで待つ）
+pub const RECEIVE: u64 = 2;
+/// 送ってから、同じ相手からの返事を受信する
+pub const SENDREC: u64 = 3;
+/// 通知する（待たない。相手が受信していなければ保留しておく）
+pub const NOTIFY: u64 = 4;
+
+/// IPCトラップの割り込みベクタ（`int 0x21`）
+/// MINIX 3: IPCVEC（33）
+pub const IPC_VECTOR: u8 = 0x21;
+
+/// `syscall` でIPCを呼ぶときに、トラップ番号に足す値（これより小さい番号はカーネルコール）
+pub const IPC_CALL_BASE: u64 = 0x100;
+
+/// 通知として届くメッセージの種類（送信元は `source` に入る）
+/// MINIX 3: NOTIFY_MESSAGE
+pub const NOTIFY_MESSAGE: i32 = 0x1000;
+
+/// メッセージの本体の大きさ
+pub const PAYLOAD_LEN: usize = 56;
+
+/// メッセージ全体の大きさ
+pub const MESSAGE_LEN: usize = 64;
+
+/// 固定長（64バイト）のメッセージ
+/// MINIX 3: message（m_source, m_type, 残りは用途ごとの共用体）
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(C)]
+pub struct Message {
+    /// 送信元（カーネルが書き込む）
+    pub source: Endpoint,
+    /// メッセージの種類（要求の番号や返答のコード）
+    pub m_type: i32,
+    /// 本体
+    pub payload: [u8; PAYLOAD_LEN],
+}
+
+impl Message {
+    /// 種類だけを持つ空のメッセージ
+    pub const fn new(m_type: i32) -> Self {
+        Self { source: 0, m_type, payload: [0; PAYLOAD_LEN] }
+    }
+
+    /// メモリ上の表現（ユーザーのメモリに書くとき）
+    pub fn to_bytes(&self) -> [u8; MESSAGE_LEN] {
+        let mut bytes = [0; MESSAGE_LEN];
+        bytes[0..4].copy_from_slice(&self.source.to_ne_bytes());
+        bytes[4..8].copy_from_slice(&self.m_type.to_ne_bytes());
+        bytes[8..].copy_from_slice(&self.payload);
+        bytes
+    }
+
+    /// メモリ上の表現から作る（ユーザーのメモリから読むとき）
+    pub fn from_bytes(bytes: &[u8; MESSAGE_LEN]) -> Self {
+        let mut payload = [0; PAYLOAD_LEN];
+        payload.copy_from_slice(&bytes[8..]);
+        Self {
+            source: i32::from_ne_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]),
+            m_type: i32::from_ne_bytes([bytes[4], bytes[5], bytes[6], bytes[7]]),
+            payload,
+        }
+    }
+}
+
+/// 循環待ち（デッドロック）になるので送受信できない
+pub const ELOCKED: i64 = -101;
+/// トラップ番号が不正
+pub const EBADCALL: i64 = -102;
+/// 相手のエンドポイントが不正（自分自身や、範囲外の番号）
+pub const EBADSRCDST: i64 = -103;
+/// その呼び出しは許されていない
+pub const ECALLDENIED: i64 = -104;
+/// 相手のプロセスが存在しない（終了した）
+pub const EDEADSRCDST: i64 = -105;
+/// メッセージのアドレスが不正
+pub const EFAULT: i64 = -14;
+
+/// IPCのエラー
+/// MINIX 3: include/sys/errno.h の ELOCKED〜EDEADSRCDST（負の値で返る）
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Error {
+    Locked,
+    BadCall,
+    BadEndpoint,
+    CallDenied,
+    DeadEndpoint,
+    Fault,
+    /// IPC以外のエラー番号
+    Other(i64),
+}
+
+impl Error {
+    /// エラー番号（負の値）
+    pub fn code(self) -> i64 {
+        match self {
+            Self::Locked => ELOCKED,
+            Self::BadCall => EBADCALL,
+            Self::BadEndpoint => EBADSRCDST,
+            Self::CallDenied => ECALLDENIED,
+            Self::DeadEndpoint => EDEADSRCDST,
+            Self::Fault => EFAULT,
+            Self::Other(code) => code,
+        }
+    }
+
+    /// エラー番号から作る
+    pub fn from_code(code: i64) -> Self {
+        match code {
+            ELOCKED => Self::Locked,
+            EBADCALL => Self::BadCall,
+            EBADSRCDST => Self::BadEndpoint,
+            ECALLDENIED => Self::CallDenied,
+            EDEADSRCDST => Self::DeadEndpoint,
+            EFAULT => Self::Fault,
+            code => Self::Other(code),
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Locked => write!(f, "can't send message due to deadlock"),
+            Self::BadCall => write!(f, "illegal IPC call"),
+            Self::BadEndpoint => write!(f, "bad source or destination process"),
+            Self::CallDenied => write!(f, "no permission for IPC call"),
+            Self::DeadEndpoint => write!(f, "source or destination is not alive"),
+            Self::Fault => write!(f, "bad message address"),
+            Self::Other(code) => write!(f, "error {}", code),
+        }
+    }
+}
+
+/// トラップの戻り値を Result にする
+pub fn check(result: i64) -> Result<(), Error> {
+    if result < 0 {
+        Err(Error::from_code(result))
+    } else {
+        Ok(())
+    }
+}
+
+/// トラップを直接呼ぶ関数（戻り値はカーネルが返した値のまま）
+#[cfg(target_arch = "x86_64")]
+pub mod raw {
+    use super::{Endpoint, Message, IPC_CALL_BASE};
+
+    /// `syscall` 命令でIPCを呼ぶ
+    ///
+    /// # Safety
+    /// ring 3 のユーザープロセスから呼ぶこと。`message` はトラップ番号に応じて読み書きされる
+    pub unsafe fn syscall(call: u64, endpoint: Endpoint, message: *mut Message) -> i64 {
+        let result: i64;
+        unsafe {
+            core::arch::asm!(
+                "syscall",
+                inlateout("rax") IPC_CALL_BASE + call => result,
+                in("rdi") i64::from(endpoint),
+                in("rsi") message,
+                // CPUが rip と rflags を入れる
+                lateout("rcx") _,
+                lateout("r11") _,
+                options(nostack),
+            );
+        }
+        result
+    }
+
+    /// `int 0x21` でIPCを呼ぶ
+    ///
+    /// # Safety
+    /// ring 3 のユーザープロセスから呼ぶこと。`message` はトラップ番号に応じて読み書きされる
+    pub unsafe fn interrupt(call: u64, endpoint: Endpoint, message: *mut Message) -> i64 {
+        let result: i64;
+        unsafe {
+            core::arch::asm!(
+                "int {vector}",
+                vector = const super::IPC_VECTOR,
+                inlateout("rax") call => result,
+                in("rdi") i64::from(endpoint),
+                in("rsi") message,
+            );
+        }
+        result
+    }
+}
+
+/// `dest` にメッセージを送る（相手が受信するまで待つ）
+#[cfg(target_arch = "x86_64")]
+pub fn send(dest: Endpoint, message: &Message) -> Result<(), Error> {
+    let mut message = *message;
+    check(unsafe { raw::syscall(SEND, dest, &mut message) })
+}
+
+/// `source`（ANY なら誰でも）からのメッセージを受信する
+#[cfg(target_arch = "x86_64")]
+pub fn receive(source: Endpoint) -> Result<Message, Error> {
+    let mut message = Message::new(0);
+    check(unsafe { raw::syscall(RECEIVE, source, &mut message) })?;
+    Ok(message)
+}
+
+/// `endpoint` にメッセージを送り、返事で `message` を上書きする
+#[cfg(target_arch = "x86_64")]
+pub fn sendrec(endpoint: Endpoint, message: &mut Message) -> Result<(), Error> {
+    check(unsafe { raw::syscall(SENDREC, endpoint, message) })
+}
+
+/// `dest` に通知する（待たない）
+#[cfg(target_arch = "x86_64")]
+pub fn notify(dest: Endpoint) -> Result<(), Error> {
+    check(unsafe { raw::syscall(NOTIFY, dest, core::ptr::null_mut()) })
+}
+
+// ===== テスト =====
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_message_layout() {
+        assert_eq!(core::mem::size_of::<Message>(), MESSAGE_LEN, "MINIXと同じ64バイト");
+        assert_eq!(core::mem::offset_of!(Message, m_type), 4);
+        assert_eq!(core::mem::offset_of!(Message, payload), 8);
+    }
+
+    #[test]
+    fn test_message_bytes() {
+        let mut message = Message::new(42);
+        message.source = -2;
+        message.payload[0] = 7;
+        message.payload[PAYLOAD_LEN - 1] = 9;
+        let bytes = message.to_bytes();
+        // repr(C) のメモリ上の表現と同じ
+        let raw = unsafe { core::mem::transmute::<Message, [u8; MESSAGE_LEN]>(message) };
+        assert_eq!(bytes, raw);
+        assert_eq!(Message::from_bytes(&bytes), message);
+    }
+
+    #[test]
+    fn test_error_codes() {
+        for error in [Error::Locked, Error::BadCall, Error::BadEndpoint, Error::CallDenied, Error::DeadEndpoint, Error::Fault] {
+            assert_eq!(Error::from_code(error.code()), error);
+            assert!(error.code() < 0, "エラー番号は負");
+        }
+        assert_eq!(Error::from_code(-22), Error::Other(-22));
+        assert_eq!(check(0), Ok(()));
+        assert_eq!(check(EDEADSRCDST), Err(Error::DeadEndpoint));
+        assert_eq!(Error::BadEndpoint.to_string(), "bad source or destination process");
+    }
+}
@@ -0,0 +1,81 @@
+//! カーネルELFにシンボル表を埋め込む
+//!
+//! リンクしたカーネルの `.ksyms` セクションに、デマングルした関数名の表を書き込む。
+//! パニック時のバックトレースが `kernel::kernel_main+0x1f` のように表示されるようになる。
+//! mkimage はカーネルに `.ksyms` セクションがあれば同じ処理を自動で行う。
+//!
+//! 使い方:
+//!
+//! ```bash
+//! cargo run -p tools --bin ksyms -- target/x86_64-learning-os/debug/kernel
+//! cargo run -p tools --bin ksyms -- --list target/x86_64-learning-os/debug/kernel
+//! ```
+
+use std::path::PathBuf;
+use std::process::ExitCode;
+
+use tools::symtab;
+
+const USAGE: &str = "usage: ksyms [--list] <kernel.elf>
+
+  writes the demangled function symbols into the kernel's .ksyms section (in place)
+  --list   print the embedded table instead of writing it";
+
+/// コマンドライン引数
+struct Args {
+    kernel: PathBuf,
+    list: bool,
+}
+
+fn parse_args(args: impl Iterator<Item = String>) -> Result<Args, String> {
+    let mut kernel = None;
+    let mut list = false;
+    for arg in args {
+        match arg.as_str() {
+            "--list" => list = true,
+            "--help" | "-h" => return Err(USAGE.to_string()),
+            other if other.starts_with('-') => return Err(format!("unknown option: {}\n{}", other, USAGE)),
+            path if kernel.is_none() => kernel = Some(PathBuf::from(path)),
+            _ => return Err(USAGE.to_string()),
+        }
+    }
+    Ok(Args { kernel: kernel.ok_or_else(|| USAGE.to_string())?, list })
+}
+
+fn run(args: Args) -> Result<(), String> {
+    let mut elf = std::fs::read(&args.kernel).map_err(|e| format!("{}: {}", args.kernel.display(), e))?;
+
+    if args.list {
+        let section = symtab::sections(&elf)?
+            .into_iter()
+            .find(|s| s.name == symtab::SECTION_NAME)
+            .ok_or_else(|| format!("kernel has no {} section", symtab::SECTION_NAME))?;
+        let table = ksyms::SymbolTable::parse(&elf[section.offset..section.offset + section.size])
+            .ok_or("no symbol table has been embedded yet")?;
+        for (address, name) in table.iter() {
+            println!("{:016x} {}", address, name);
+        }
+        return Ok(());
+    }
+
+    let embedded = symtab::embed(&mut elf)?;
+    std::fs::write(&args.kernel, &elf).map_err(|e| format!("{}: {}", args.kernel.display(), e))?;
+    println!(
+        "embedded {} symbols into {} ({} of {} bytes)",
+        embedded.count,
+        args.kernel.display(),
+        embedded.used,
+        embedded.capacity
+    );
+    Ok(())
+}
+
+fn main() -> ExitCode {
+    match parse_args(std::env::args().skip(1)).and_then(run) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(message) => {
+            eprintln!("ksyms: {}", message);
+            ExitCode::FAILURE
+        }
+    }
+}
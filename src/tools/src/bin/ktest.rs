@@ -0,0 +1,86 @@
+//! カーネル内テストのランナー
+//!
+//! `cargo test --target x86_64-learning-os.json` がビルドしたテスト用カーネルを受け取り、
+//! シンボル表を埋め込んでからQEMUで起動する。`--initrd` にディレクトリを渡すと、
+//! それを cpio アーカイブにして `-initrd`（最初のモジュール）として一緒に渡す。テストの結果はQEMUの終了コード
+//! （isa-debug-exit）で受け取り、cargo にわかる終了コード（成功なら0）で返す。
+//!
+//! `kernel/.cargo/config.toml` の runner に指定してあるので、先にビルドしておく:
+//!
+//! ```bash
+//! cargo build -p tools --bin ktest
+//! ```
+
+use std::path::PathBuf;
+use std::process::ExitCode;
+use std::time::Duration;
+
+use tools::cpio;
+use tools::qemu::KernelTest;
+use tools::symtab;
+
+const USAGE: &str = "usage: ktest [--timeout <seconds>] [--initrd <directory>] <kernel.elf> [test args...]
+
+  boots a kernel test binary in QEMU and reports the result of its #[test_case] functions";
+
+/// コマンドライン引数
+struct Args {
+    kernel: PathBuf,
+    timeout: Option<u64>,
+    initrd: Option<PathBuf>,
+}
+
+fn parse_args(mut args: impl Iterator<Item = String>) -> Result<Args, String> {
+    let mut timeout = None;
+    let mut initrd = None;
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--timeout" => {
+                let value = args.next().ok_or("--timeout requires a value")?;
+                timeout = Some(value.parse().map_err(|_| "--timeout must be seconds".to_string())?);
+            }
+            "--initrd" => initrd = Some(PathBuf::from(args.next().ok_or("--initrd requires a value")?)),
+            "--help" | "-h" => return Err(USAGE.to_string()),
+            // cargo が後ろに付けるテストの引数（フィルタなど）は使わない
+            path => return Ok(Args { kernel: PathBuf::from(path), timeout, initrd }),
+        }
+    }
+    Err(USAGE.to_string())
+}
+
+fn run(args: Args) -> Result<(), String> {
+    let mut elf = std::fs::read(&args.kernel).map_err(|e| format!("{}: {}", args.kernel.display(), e))?;
+    // cargo の成果物は書き換えず、一時ファイルに埋め込んでから起動する
+    if symtab::has_section(&elf) {
+        symtab::embed(&mut elf)?;
+    }
+    let kernel = std::env::temp_dir().join(format!("learning-os-ktest-{}.elf", std::process::id()));
+    std::fs::write(&kernel, &elf).map_err(|e| format!("{}: {}", kernel.display(), e))?;
+
+    let mut test = KernelTest::new();
+    if let Some(timeout) = args.timeout {
+        test.timeout = Duration::from_secs(timeout);
+    }
+    if let Some(root) = &args.initrd {
+        let archive = cpio::from_directory(root).map_err(|e| format!("{}: {}", root.display(), e))?;
+        let initrd = std::env::temp_dir().join(format!("learning-os-ktest-{}.cpio", std::process::id()));
+        std::fs::write(&initrd, archive).map_err(|e| format!("{}: {}", initrd.display(), e))?;
+        test.initrd = Some(initrd);
+    }
+    let result = test.run(&kernel);
+    let _ = std::fs::remove_file(&kernel);
+    if let Some(initrd) = &test.initrd {
+        let _ = std::fs::remove_file(initrd);
+    }
+    result
+}
+
+fn main() -> ExitCode {
+    match parse_args(std::env::args().skip(1)).and_then(run) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(message) => {
+            eprintln!("ktest: {}", message);
+            ExitCode::FAILURE
+        }
+    }
+}
@@ -0,0 +1,161 @@
+//! USBメモリに `dd` で書き込める起動イメージを作る
+//!
+//! GPTディスクにFAT32のESPを1つ作り、以下のファイルを置く:
+//!
+//! ```text
+//! /EFI/BOOT/BOOTX64.EFI   UEFIブートローダー
+//! /kernel.elf             カーネル（.ksyms があればシンボル表を埋め込んだもの）
+//! /initrd                 初期RAMディスク（指定した場合）
+//! ```
+//!
+//! 使い方:
+//!
+//! ```bash
+//! cargo run -p tools --bin mkimage -- \
+//!     --loader target/x86_64-unknown-uefi/debug/bootloader.efi \
+//!     --kernel target/x86_64-learning-os/debug/kernel \
+//!     --output learning-os.img --boot-test
+//! ```
+
+use std::path::PathBuf;
+use std::process::ExitCode;
+use std::time::Duration;
+
+use tools::fat32::{self, Directory};
+use tools::gpt::{self, Guid, Layout};
+use tools::qemu::BootTest;
+use tools::symtab;
+use tools::{SplitMix64, SECTOR_SIZE};
+
+/// デフォルトのイメージサイズ（MiB）
+const DEFAULT_SIZE_MIB: u64 = 64;
+
+const USAGE: &str = "usage: mkimage --loader <BOOTX64.EFI> --kernel <kernel.elf> [--initrd <file>]
+               --output <image.img> [--size <MiB>]
+               [--boot-test] [--ovmf <dir>] [--timeout <seconds>]
+
+  --boot-test   boot the image in QEMU with OVMF and check the serial output
+  --ovmf        directory containing OVMF_CODE.fd and OVMF_VARS.fd (default: ovmf)";
+
+/// コマンドライン引数
+struct Args {
+    loader: PathBuf,
+    kernel: PathBuf,
+    initrd: Option<PathBuf>,
+    output: PathBuf,
+    size_mib: u64,
+    boot_test: bool,
+    ovmf_dir: PathBuf,
+    timeout: Option<u64>,
+}
+
+fn parse_args(mut args: impl Iterator<Item = String>) -> Result<Args, String> {
+    let mut loader = None;
+    let mut kernel = None;
+    let mut initrd = None;
+    let mut output = None;
+    let mut size_mib = DEFAULT_SIZE_MIB;
+    let mut boot_test = false;
+    let mut ovmf_dir = PathBuf::from("ovmf");
+    let mut timeout = None;
+
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().ok_or_else(|| format!("{} requires a value", arg));
+        match arg.as_str() {
+            "--loader" => loader = Some(PathBuf::from(value()?)),
+            "--kernel" => kernel = Some(PathBuf::from(value()?)),
+            "--initrd" => initrd = Some(PathBuf::from(value()?)),
+            "--output" | "-o" => output = Some(PathBuf::from(value()?)),
+            "--size" => size_mib = value()?.parse().map_err(|_| "--size must be a number of MiB".to_string())?,
+            "--boot-test" => boot_test = true,
+            "--ovmf" => ovmf_dir = PathBuf::from(value()?),
+            "--timeout" => timeout = Some(value()?.parse().map_err(|_| "--timeout must be seconds".to_string())?),
+            "--help" | "-h" => return Err(USAGE.to_string()),
+            other => return Err(format!("unknown option: {}\n{}", other, USAGE)),
+        }
+    }
+
+    Ok(Args {
+        loader: loader.ok_or_else(|| format!("--loader is required\n{}", USAGE))?,
+        kernel: kernel.ok_or_else(|| format!("--kernel is required\n{}", USAGE))?,
+        initrd,
+        output: output.ok_or_else(|| format!("--output is required\n{}", USAGE))?,
+        size_mib,
+        boot_test,
+        ovmf_dir,
+        timeout,
+    })
+}
+
+fn read(path: &PathBuf) -> Result<Vec<u8>, String> {
+    std::fs::read(path).map_err(|e| format!("{}: {}", path.display(), e))
+}
+
+fn run(args: Args) -> Result<(), String> {
+    let loader = read(&args.loader)?;
+    let mut kernel = read(&args.kernel)?;
+    let initrd = args.initrd.as_ref().map(read).transpose()?;
+
+    // バックトレース用のシンボル表を埋め込む（イメージに入れるコピーだけを書き換える）
+    if symtab::has_section(&kernel) {
+        let embedded = symtab::embed(&mut kernel)?;
+        println!("embedded {} kernel symbols ({} of {} bytes)", embedded.count, embedded.used, embedded.capacity);
+    }
+
+    // GUIDとボリュームIDは中身から決める（同じ入力なら同じイメージになる）
+    let mut rng = SplitMix64::from_bytes([&loader[..], &kernel[..], initrd.as_deref().unwrap_or(&[])]);
+
+    let mut root = Directory::new();
+    root.add_file("/EFI/BOOT/BOOTX64.EFI", loader.clone()).map_err(|e| e.to_string())?;
+    root.add_file("/kernel.elf", kernel.clone()).map_err(|e| e.to_string())?;
+    if let Some(initrd) = &initrd {
+        root.add_file("/initrd", initrd.clone()).map_err(|e| e.to_string())?;
+    }
+
+    let total_sectors = args.size_mib * 1024 * 1024 / SECTOR_SIZE as u64;
+    let layout = Layout::new(total_sectors).map_err(|e| e.to_string())?;
+    let mut image = vec![0u8; total_sectors as usize * SECTOR_SIZE];
+
+    let volume = &mut image[layout.partition_range()];
+    fat32::write(volume, layout.partition_start as u32, rng.next_u64() as u32, "LEARNING OS", &root)
+        .map_err(|e| format!("{} (try a larger --size)", e))?;
+    gpt::write(&mut image, &layout, Guid::random(&mut rng), Guid::random(&mut rng));
+
+    // 書いたものを読み戻して確認する
+    let (start, end) = gpt::verify(&image)?;
+    let volume = &image[start as usize * SECTOR_SIZE..(end as usize + 1) * SECTOR_SIZE];
+    if fat32::read_file(volume, "/EFI/BOOT/BOOTX64.EFI").as_ref() != Some(&loader)
+        || fat32::read_file(volume, "/kernel.elf").as_ref() != Some(&kernel)
+    {
+        return Err("verification of the written image failed".to_string());
+    }
+
+    std::fs::write(&args.output, &image).map_err(|e| format!("{}: {}", args.output.display(), e))?;
+    println!(
+        "wrote {} ({} MiB, ESP at LBA {}..={})",
+        args.output.display(),
+        args.size_mib,
+        layout.partition_start,
+        layout.partition_end
+    );
+
+    if args.boot_test {
+        let mut test = BootTest::new(args.ovmf_dir);
+        if let Some(timeout) = args.timeout {
+            test.timeout = Duration::from_secs(timeout);
+        }
+        test.run(&args.output)?;
+        println!("boot test passed");
+    }
+    Ok(())
+}
+
+fn main() -> ExitCode {
+    match parse_args(std::env::args().skip(1)).and_then(run) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(message) => {
+            eprintln!("mkimage: {}", message);
+            ExitCode::FAILURE
+        }
+    }
+}
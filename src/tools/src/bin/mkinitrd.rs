@@ -0,0 +1,58 @@
+//! ディレクトリから初期RAMディスク（newc 形式の cpio アーカイブ）を作る
+//!
+//! カーネルは最初のモジュールをinitrdとして読み、`init=`（デフォルトは /sbin/init）を起動する。
+//!
+//! ```bash
+//! cargo run -p tools --bin mkinitrd -- --output initrd.cpio rootfs/
+//! cargo run -p tools --bin mkimage -- --loader ... --kernel ... --initrd initrd.cpio --output learning-os.img
+//! ```
+
+use std::path::PathBuf;
+use std::process::ExitCode;
+
+use tools::cpio;
+
+const USAGE: &str = "usage: mkinitrd --output <initrd.cpio> <directory>
+
+  packs <directory> into a newc cpio archive for the kernel's initrd";
+
+/// コマンドライン引数
+struct Args {
+    root: PathBuf,
+    output: PathBuf,
+}
+
+fn parse_args(mut args: impl Iterator<Item = String>) -> Result<Args, String> {
+    let mut root = None;
+    let mut output = None;
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--output" | "-o" => output = Some(PathBuf::from(args.next().ok_or("--output requires a value")?)),
+            "--help" | "-h" => return Err(USAGE.to_string()),
+            other if other.starts_with('-') => return Err(format!("unknown option: {}\n{}", other, USAGE)),
+            path if root.is_none() => root = Some(PathBuf::from(path)),
+            _ => return Err(USAGE.to_string()),
+        }
+    }
+    Ok(Args {
+        root: root.ok_or_else(|| format!("a directory is required\n{}", USAGE))?,
+        output: output.ok_or_else(|| format!("--output is required\n{}", USAGE))?,
+    })
+}
+
+fn run(args: Args) -> Result<(), String> {
+    let archive = cpio::from_directory(&args.root).map_err(|e| format!("{}: {}", args.root.display(), e))?;
+    std::fs::write(&args.output, &archive).map_err(|e| format!("{}: {}", args.output.display(), e))?;
+    println!("wrote {} ({} bytes)", args.output.display(), archive.len());
+    Ok(())
+}
+
+fn main() -> ExitCode {
+    match parse_args(std::env::args().skip(1)).and_then(run) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(message) => {
+            eprintln!("mkinitrd: {}", message);
+            ExitCode::FAILURE
+        }
+    }
+}
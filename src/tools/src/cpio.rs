@@ -0,0 +1,183 @@
+//! 初期RAMディスク（initrd）用の cpio アーカイブ（newc 形式）
+//!
+//! カーネルの initrd.rs が読む形式で書く。Linuxの initramfs と同じなので、
+//! `cpio -t -H newc < initrd` で中身を確かめられる。
+//!
+//! 同じディレクトリからは同じアーカイブができるように、名前の順に並べ、
+//! 所有者と更新時刻は0にする（mkimage の再現可能なビルドに合わせる）。
+
+use std::fs;
+use std::io;
+use std::path::Path;
+
+/// newc 形式のマジック
+pub const NEWC_MAGIC: &str = "070701";
+
+/// アーカイブの終わりを表すエントリの名前
+pub const TRAILER: &str = "TRAILER!!!";
+
+/// ディレクトリ（st_mode の種類）
+pub const S_IFDIR: u32 = 0o040_000;
+/// 普通のファイル
+pub const S_IFREG: u32 = 0o100_000;
+
+/// newc 形式のアーカイブを組み立てる
+#[derive(Debug, Default)]
+pub struct Archive {
+    bytes: Vec<u8>,
+    /// 次のエントリの inode 番号（cpio はハードリンクの判定にだけ使う）
+    next_ino: u32,
+}
+
+impl Archive {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// ディレクトリ `name`（"sbin" のような相対パス）を加える
+    pub fn add_dir(&mut self, name: &str, permissions: u32) {
+        self.push(name, S_IFDIR | permissions, 2, &[]);
+    }
+
+    /// ファイル `name` を加える
+    pub fn add_file(&mut self, name: &str, permissions: u32, data: &[u8]) {
+        self.push(name, S_IFREG | permissions, 1, data);
+    }
+
+    /// 終わりのエントリを書いて、アーカイブのバイト列を返す
+    pub fn finish(mut self) -> Vec<u8> {
+        self.push(TRAILER, 0, 1, &[]);
+        self.bytes
+    }
+
+    fn push(&mut self, name: &str, mode: u32, nlink: u32, data: &[u8]) {
+        self.next_ino += 1;
+        let ino = if name == TRAILER { 0 } else { self.next_ino };
+        // ino, mode, uid, gid, nlink, mtime, filesize, devmajor, devminor, rdevmajor, rdevminor, namesize, check
+        let fields = [ino, mode, 0, 0, nlink, 0, data.len() as u32, 0, 0, 0, 0, name.len() as u32 + 1, 0];
+        self.bytes.extend_from_slice(NEWC_MAGIC.as_bytes());
+        for value in fields {
+            self.bytes.extend_from_slice(format!("{:08X}", value).as_bytes());
+        }
+        self.bytes.extend_from_slice(name.as_bytes());
+        self.bytes.push(0);
+        self.pad();
+        self.bytes.extend_from_slice(data);
+        self.pad();
+    }
+
+    /// 4バイト境界まで0で詰める
+    fn pad(&mut self) {
+        self.bytes.resize(self.bytes.len().next_multiple_of(4), 0);
+    }
+}
+
+/// ディレクトリ `root` の中身をアーカイブにする（`root` 自身は "."）
+///
+/// シンボリックリンクはたどって、リンク先のファイルとして入れる。
+pub fn from_directory(root: &Path) -> io::Result<Vec<u8>> {
+    let mut archive = Archive::new();
+    archive.add_dir(".", permissions(&fs::metadata(root)?));
+    add_directory(&mut archive, root, "")?;
+    Ok(archive.finish())
+}
+
+fn add_directory(archive: &mut Archive, dir: &Path, prefix: &str) -> io::Result<()> {
+    let mut entries = fs::read_dir(dir)?.collect::<io::Result<Vec<_>>>()?;
+    entries.sort_by_key(|entry| entry.file_name());
+    for entry in entries {
+        let file_name = entry.file_name();
+        let file_name = file_name
+            .to_str()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, format!("{}: not UTF-8", entry.path().display())))?;
+        let name = format!("{}{}", prefix, file_name);
+        let metadata = fs::metadata(entry.path())?;
+        if metadata.is_dir() {
+            archive.add_dir(&name, permissions(&metadata));
+            add_directory(archive, &entry.path(), &format!("{}/", name))?;
+        } else if metadata.is_file() {
+            archive.add_file(&name, permissions(&metadata), &fs::read(entry.path())?);
+        }
+    }
+    Ok(())
+}
+
+/// 許可ビット（rwxrwxrwx）
+#[cfg(unix)]
+fn permissions(metadata: &fs::Metadata) -> u32 {
+    use std::os::unix::fs::PermissionsExt;
+    metadata.permissions().mode() & 0o7777
+}
+
+#[cfg(not(unix))]
+fn permissions(metadata: &fs::Metadata) -> u32 {
+    if metadata.is_dir() {
+        0o755
+    } else {
+        0o644
+    }
+}
+
+// ===== テスト =====
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// (名前, mode, 中身) の一覧に読み戻す
+    fn entries(archive: &[u8]) -> Vec<(String, u32, Vec<u8>)> {
+        let field = |header: &[u8], index: usize| {
+            let start = 6 + index * 8;
+            u32::from_str_radix(std::str::from_utf8(&header[start..start + 8]).unwrap(), 16).unwrap() as usize
+        };
+        let mut list = Vec::new();
+        let mut offset = 0;
+        loop {
+            let header = &archive[offset..offset + 110];
+            assert_eq!(&header[..6], NEWC_MAGIC.as_bytes());
+            let (size, name_size) = (field(header, 6), field(header, 11));
+            let name = std::str::from_utf8(&archive[offset + 110..offset + 110 + name_size - 1]).unwrap().to_string();
+            let data_start = (offset + 110 + name_size).next_multiple_of(4);
+            let data = archive[data_start..data_start + size].to_vec();
+            offset = (data_start + size).next_multiple_of(4);
+            if name == TRAILER {
+                assert_eq!(offset, archive.len(), "TRAILER!!! で終わる");
+                return list;
+            }
+            list.push((name, field(header, 1) as u32, data));
+        }
+    }
+
+    #[test]
+    fn test_archive_layout() {
+        let mut archive = Archive::new();
+        archive.add_dir("sbin", 0o755);
+        archive.add_file("sbin/init", 0o755, b"\x7fELF");
+        let bytes = archive.finish();
+        assert_eq!(&bytes[..6], b"070701");
+        assert_eq!(&bytes[6..14], b"00000001", "ino は1から");
+        assert_eq!(&bytes[14..22], b"000041ED", "mode は大文字の16進数");
+        assert_eq!(&bytes[110..115], b"sbin\0");
+        assert_eq!(bytes.len() % 4, 0, "4バイト境界で終わる");
+        assert_eq!(
+            entries(&bytes),
+            [("sbin".to_string(), S_IFDIR | 0o755, vec![]), ("sbin/init".to_string(), S_IFREG | 0o755, b"\x7fELF".to_vec())]
+        );
+    }
+
+    #[test]
+    fn test_from_directory() {
+        let root = std::env::temp_dir().join(format!("learning-os-cpio-test-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(root.join("sbin")).unwrap();
+        fs::create_dir_all(root.join("etc")).unwrap();
+        fs::write(root.join("sbin/init"), b"init").unwrap();
+        fs::write(root.join("etc/motd"), b"hello\n").unwrap();
+
+        let bytes = from_directory(&root).unwrap();
+        let names: Vec<_> = entries(&bytes).into_iter().map(|(name, _, _)| name).collect();
+        assert_eq!(names, [".", "etc", "etc/motd", "sbin", "sbin/init"], "名前の順で親が先");
+        assert_eq!(entries(&bytes)[4].2, b"init");
+        assert_eq!(from_directory(&root).unwrap(), bytes, "同じディレクトリからは同じアーカイブ");
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -0,0 +1,46 @@
+//! CRC32（IEEE 802.3、多項式 0xEDB88320）
+//!
+//! GPTヘッダとパーティションエントリ配列の検証に使う。
+
+/// 反転した生成多項式
+const POLYNOMIAL: u32 = 0xedb8_8320;
+
+/// 1バイトごとのCRC値の表（コンパイル時に計算）
+const TABLE: [u32; 256] = make_table();
+
+const fn make_table() -> [u32; 256] {
+    let mut table = [0u32; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 != 0 { (crc >> 1) ^ POLYNOMIAL } else { crc >> 1 };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+}
+
+/// バイト列のCRC32を計算する
+pub fn crc32(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &byte in data {
+        crc = TABLE[((crc ^ byte as u32) & 0xff) as usize] ^ (crc >> 8);
+    }
+    !crc
+}
+
+// ===== テスト =====
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_known_values() {
+        assert_eq!(crc32(b""), 0, "空データは0");
+        assert_eq!(crc32(b"123456789"), 0xcbf4_3926, "標準のチェック値");
+    }
+}
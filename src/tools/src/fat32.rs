@@ -0,0 +1,567 @@
+//! FAT32ファイルシステムの作成
+//!
+//! ESPの中身（ブートローダー、カーネル、initrd）をFAT32として書き込む。
+//! mkfs.fat を使わずに、ボリュームのバイト列を直接組み立てる。
+//!
+//! ```text
+//! セクタ 0           ブートセクタ（BPB）
+//! セクタ 1           FSInfo
+//! セクタ 6, 7        ブートセクタとFSInfoのバックアップ
+//! セクタ 32..        FAT #1, FAT #2
+//! その後             データ領域（クラスタ2 = ルートディレクトリ）
+//! ```
+//!
+//! ファイル名は8.3形式だけに対応する（長いファイル名のエントリは書かない）。
+//! UEFIのFATドライバは大文字小文字を区別しないので `\kernel.elf` で開ける。
+
+use crate::{get_u16, get_u32, put_u16, put_u32, ImageError, SECTOR_SIZE};
+
+/// 予約セクタ数（ブートセクタ、FSInfo、バックアップを含む）
+const RESERVED_SECTORS: u32 = 32;
+/// FATの数
+const NUM_FATS: u32 = 2;
+/// FSInfoのセクタ番号
+const FSINFO_SECTOR: u32 = 1;
+/// バックアップブートセクタのセクタ番号
+const BACKUP_BOOT_SECTOR: u32 = 6;
+/// ルートディレクトリのクラスタ番号
+const ROOT_CLUSTER: u32 = 2;
+/// FAT32と判定されるのに必要な最小クラスタ数
+const MIN_CLUSTERS: u32 = 65525;
+/// クラスタチェーンの終端
+const END_OF_CHAIN: u32 = 0x0fff_ffff;
+/// ディレクトリエントリのサイズ
+const DIR_ENTRY_SIZE: usize = 32;
+
+/// ディレクトリエントリの属性
+mod attr {
+    pub const VOLUME_ID: u8 = 0x08;
+    pub const DIRECTORY: u8 = 0x10;
+    pub const ARCHIVE: u8 = 0x20;
+}
+
+/// NTResフィールド: 名前/拡張子を小文字で表示する（Windows NT以降の拡張）
+mod nt_flags {
+    pub const LOWER_BASE: u8 = 0x08;
+    pub const LOWER_EXT: u8 = 0x10;
+}
+
+/// 全エントリに付けるタイムスタンプ（1980-01-01 00:00）
+/// 入力が同じなら同じイメージになるように、現在時刻は使わない
+const FIXED_DATE: u16 = (1 << 5) | 1;
+
+/// 8.3形式のファイル名
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ShortName {
+    /// 大文字に変換し、空白で埋めた名前8文字 + 拡張子3文字
+    name: [u8; 11],
+    /// 小文字で表示するかどうか（nt_flags）
+    case: u8,
+}
+
+impl ShortName {
+    /// `"bootx64.efi"` のような名前を8.3形式に変換する
+    ///
+    /// 名前と拡張子はそれぞれ全部大文字か全部小文字であること（混在は長いファイル名が必要）。
+    pub fn parse(name: &str) -> Result<Self, ImageError> {
+        let invalid = || ImageError::InvalidName(name.to_string());
+        let (base, ext) = match name.rfind('.') {
+            Some(dot) => (&name[..dot], &name[dot + 1..]),
+            None => (name, ""),
+        };
+        if base.is_empty() || base.len() > 8 || ext.len() > 3 || (name.ends_with('.')) {
+            return Err(invalid());
+        }
+
+        let mut short = ShortName { name: [b' '; 11], case: 0 };
+        for (part, range, lower_flag) in [(base, 0..8, nt_flags::LOWER_BASE), (ext, 8..11, nt_flags::LOWER_EXT)] {
+            let has_lower = part.bytes().any(|b| b.is_ascii_lowercase());
+            let has_upper = part.bytes().any(|b| b.is_ascii_uppercase());
+            if has_lower && has_upper {
+                return Err(invalid());
+            }
+            if has_lower {
+                short.case |= lower_flag;
+            }
+            for (dst, b) in short.name[range].iter_mut().zip(part.bytes()) {
+                if !(b.is_ascii_alphanumeric() || b"!#$%&'()-@^_`{}~".contains(&b)) {
+                    return Err(invalid());
+                }
+                *dst = b.to_ascii_uppercase();
+            }
+        }
+        Ok(short)
+    }
+
+    /// ディレクトリエントリに書く11バイト
+    pub fn as_bytes(&self) -> &[u8; 11] {
+        &self.name
+    }
+}
+
+/// イメージに書き込むディレクトリツリー
+#[derive(Debug, Default, Clone)]
+pub struct Directory {
+    entries: Vec<(ShortName, Node)>,
+}
+
+#[derive(Debug, Clone)]
+enum Node {
+    File(Vec<u8>),
+    Dir(Directory),
+}
+
+impl Directory {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// `/` 区切りのパスにファイルを追加する（途中のディレクトリは自動で作る）
+    pub fn add_file(&mut self, path: &str, data: Vec<u8>) -> Result<(), ImageError> {
+        let mut components = path.split('/').filter(|c| !c.is_empty()).peekable();
+        let mut dir = self;
+        while let Some(component) = components.next() {
+            let name = ShortName::parse(component)?;
+            let existing = dir.entries.iter().position(|(n, _)| n.name == name.name);
+            if components.peek().is_none() {
+                if existing.is_some() {
+                    return Err(ImageError::DuplicateName(path.to_string()));
+                }
+                dir.entries.push((name, Node::File(data)));
+                return Ok(());
+            }
+            let index = match existing {
+                Some(index) => index,
+                None => {
+                    dir.entries.push((name, Node::Dir(Directory::new())));
+                    dir.entries.len() - 1
+                }
+            };
+            dir = match &mut dir.entries[index].1 {
+                Node::Dir(sub) => sub,
+                Node::File(_) => return Err(ImageError::DuplicateName(path.to_string())),
+            };
+        }
+        Err(ImageError::InvalidName(path.to_string()))
+    }
+
+    /// このディレクトリのエントリ領域のバイト数（"." と ".."、またはボリュームラベルを含む）
+    fn byte_len(&self) -> usize {
+        (self.entries.len() + 2) * DIR_ENTRY_SIZE
+    }
+}
+
+/// ボリュームの大きさから決まるFAT32のパラメータ
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Geometry {
+    pub total_sectors: u32,
+    pub sectors_per_cluster: u32,
+    /// FAT1つあたりのセクタ数
+    pub fat_sectors: u32,
+    /// データ領域のクラスタ数
+    pub cluster_count: u32,
+}
+
+impl Geometry {
+    /// Microsoftの仕様書（fatgen103）の表と計算式に従ってパラメータを決める
+    pub fn new(total_sectors: u64) -> Result<Self, ImageError> {
+        let total_sectors: u32 = total_sectors.try_into().map_err(|_| ImageError::DiskFull)?;
+        let sectors_per_cluster = match total_sectors as u64 * SECTOR_SIZE as u64 {
+            size if size <= 260 << 20 => 1,
+            size if size <= 8 << 30 => 8,
+            size if size <= 16 << 30 => 16,
+            size if size <= 32 << 30 => 32,
+            _ => 64,
+        };
+        let data_and_fats = total_sectors.checked_sub(RESERVED_SECTORS).ok_or(ImageError::TooSmall)?;
+        let per_fat_sector = (256 * sectors_per_cluster + NUM_FATS) / 2;
+        let fat_sectors = data_and_fats.div_ceil(per_fat_sector);
+        let data_sectors = data_and_fats.checked_sub(NUM_FATS * fat_sectors).ok_or(ImageError::TooSmall)?;
+        let cluster_count = data_sectors / sectors_per_cluster;
+        if cluster_count < MIN_CLUSTERS {
+            return Err(ImageError::TooSmall);
+        }
+        Ok(Self { total_sectors, sectors_per_cluster, fat_sectors, cluster_count })
+    }
+
+    pub fn cluster_size(&self) -> usize {
+        self.sectors_per_cluster as usize * SECTOR_SIZE
+    }
+
+    /// データ領域の先頭セクタ
+    fn data_start(&self) -> u32 {
+        RESERVED_SECTORS + NUM_FATS * self.fat_sectors
+    }
+
+    /// クラスタのバイトオフセット
+    fn cluster_offset(&self, cluster: u32) -> usize {
+        (self.data_start() as usize + (cluster - 2) as usize * self.sectors_per_cluster as usize) * SECTOR_SIZE
+    }
+}
+
+/// ボリューム（ゼロクリア済みのパーティション領域）にFAT32を作り、ファイルを書き込む
+///
+/// # 引数
+/// - `hidden_sectors`: ディスク先頭からパーティションまでのセクタ数
+/// - `volume_id`: ボリュームのシリアル番号
+/// - `label`: ボリュームラベル（最大11文字）
+pub fn write(volume: &mut [u8], hidden_sectors: u32, volume_id: u32, label: &str, root: &Directory) -> Result<Geometry, ImageError> {
+    let geometry = Geometry::new((volume.len() / SECTOR_SIZE) as u64)?;
+    let mut label_bytes = [b' '; 11];
+    for (dst, b) in label_bytes.iter_mut().zip(label.bytes()) {
+        *dst = b.to_ascii_uppercase();
+    }
+
+    let mut writer = Writer {
+        volume,
+        geometry,
+        fat: vec![0; geometry.cluster_count as usize + 2],
+        next_cluster: ROOT_CLUSTER,
+    };
+    writer.fat[0] = 0x0fff_fff8; // メディア記述子（0xF8）
+    writer.fat[1] = END_OF_CHAIN;
+
+    let root_cluster = writer.allocate(root.byte_len())?;
+    debug_assert_eq!(root_cluster, ROOT_CLUSTER);
+    writer.write_directory(root, root_cluster, None, &label_bytes)?;
+    writer.finish(hidden_sectors, volume_id, &label_bytes);
+    Ok(geometry)
+}
+
+struct Writer<'a> {
+    volume: &'a mut [u8],
+    geometry: Geometry,
+    fat: Vec<u32>,
+    next_cluster: u32,
+}
+
+impl Writer<'_> {
+    /// 連続したクラスタを確保してチェーンをつなぎ、先頭クラスタを返す（0バイトなら0）
+    fn allocate(&mut self, bytes: usize) -> Result<u32, ImageError> {
+        let count = bytes.div_ceil(self.geometry.cluster_size()) as u32;
+        if count == 0 {
+            return Ok(0);
+        }
+        let first = self.next_cluster;
+        let end = first + count;
+        if end > self.geometry.cluster_count + 2 {
+            return Err(ImageError::DiskFull);
+        }
+        for cluster in first..end - 1 {
+            self.fat[cluster as usize] = cluster + 1;
+        }
+        self.fat[end as usize - 1] = END_OF_CHAIN;
+        self.next_cluster = end;
+        Ok(first)
+    }
+
+    /// 確保済みの連続クラスタにデータを書く
+    fn write_clusters(&mut self, first: u32, data: &[u8]) {
+        if first != 0 {
+            let offset = self.geometry.cluster_offset(first);
+            self.volume[offset..offset + data.len()].copy_from_slice(data);
+        }
+    }
+
+    /// ディレクトリとその中身を書く
+    ///
+    /// `parent` はルートならNone、ルート直下のディレクトリなら Some(0)（仕様で ".." は0を指す）
+    fn write_directory(&mut self, dir: &Directory, cluster: u32, parent: Option<u32>, label: &[u8; 11]) -> Result<(), ImageError> {
+        let mut entries = vec![0u8; dir.byte_len()];
+        let mut slots = entries.chunks_mut(DIR_ENTRY_SIZE);
+        match parent {
+            None => dir_entry(slots.next().unwrap(), label, 0, attr::VOLUME_ID, 0, 0),
+            Some(parent) => {
+                dir_entry(slots.next().unwrap(), b".          ", 0, attr::DIRECTORY, cluster, 0);
+                dir_entry(slots.next().unwrap(), b"..         ", 0, attr::DIRECTORY, parent, 0);
+            }
+        }
+
+        let this = if parent.is_none() { 0 } else { cluster };
+        for (name, node) in &dir.entries {
+            let slot = slots.next().unwrap();
+            match node {
+                Node::File(data) => {
+                    let first = self.allocate(data.len())?;
+                    self.write_clusters(first, data);
+                    dir_entry(slot, name.as_bytes(), name.case, attr::ARCHIVE, first, data.len() as u32);
+                }
+                Node::Dir(sub) => {
+                    let first = self.allocate(sub.byte_len())?;
+                    dir_entry(slot, name.as_bytes(), name.case, attr::DIRECTORY, first, 0);
+                    self.write_directory(sub, first, Some(this), label)?;
+                }
+            }
+        }
+        self.write_clusters(cluster, &entries);
+        Ok(())
+    }
+
+    /// ブートセクタ、FSInfo、FATを書く
+    fn finish(self, hidden_sectors: u32, volume_id: u32, label: &[u8; 11]) {
+        let geometry = self.geometry;
+        let boot = boot_sector(&geometry, hidden_sectors, volume_id, label);
+        let free = geometry.cluster_count + 2 - self.next_cluster;
+        let fsinfo = fsinfo_sector(free, self.next_cluster);
+
+        for base in [0, BACKUP_BOOT_SECTOR] {
+            let offset = base as usize * SECTOR_SIZE;
+            self.volume[offset..offset + SECTOR_SIZE].copy_from_slice(&boot);
+            let offset = (base + FSINFO_SECTOR) as usize * SECTOR_SIZE;
+            self.volume[offset..offset + SECTOR_SIZE].copy_from_slice(&fsinfo);
+        }
+
+        for i in 0..NUM_FATS {
+            let offset = (RESERVED_SECTORS + i * geometry.fat_sectors) as usize * SECTOR_SIZE;
+            for (j, &entry) in self.fat.iter().enumerate() {
+                put_u32(self.volume, offset + j * 4, entry);
+            }
+        }
+    }
+}
+
+/// 32バイトのディレクトリエントリを書く
+fn dir_entry(slot: &mut [u8], name: &[u8; 11], case: u8, attributes: u8, cluster: u32, size: u32) {
+    slot[0..11].copy_from_slice(name);
+    slot[11] = attributes;
+    slot[12] = case;
+    put_u16(slot, 16, FIXED_DATE); // 作成日
+    put_u16(slot, 18, FIXED_DATE); // 最終アクセス日
+    put_u16(slot, 20, (cluster >> 16) as u16);
+    put_u16(slot, 24, FIXED_DATE); // 更新日
+    put_u16(slot, 26, cluster as u16);
+    put_u32(slot, 28, size);
+}
+
+/// ブートセクタ（BIOS Parameter Block）
+fn boot_sector(geometry: &Geometry, hidden_sectors: u32, volume_id: u32, label: &[u8; 11]) -> [u8; SECTOR_SIZE] {
+    let mut sector = [0u8; SECTOR_SIZE];
+    sector[0..3].copy_from_slice(&[0xeb, 0x58, 0x90]); // jmp short 0x5a; nop
+    sector[3..11].copy_from_slice(b"LRNOS1.0");
+    put_u16(&mut sector, 11, SECTOR_SIZE as u16);
+    sector[13] = geometry.sectors_per_cluster as u8;
+    put_u16(&mut sector, 14, RESERVED_SECTORS as u16);
+    sector[16] = NUM_FATS as u8;
+    // 17: ルートエントリ数, 19: 16bit総セクタ数（FAT32では0）
+    sector[21] = 0xf8; // 固定ディスク
+    // 22: 16bitのFATサイズ（FAT32では0）
+    put_u16(&mut sector, 24, 63); // トラックあたりセクタ数
+    put_u16(&mut sector, 26, 255); // ヘッド数
+    put_u32(&mut sector, 28, hidden_sectors);
+    put_u32(&mut sector, 32, geometry.total_sectors);
+    put_u32(&mut sector, 36, geometry.fat_sectors);
+    // 40: 拡張フラグ（全FATをミラー）, 42: バージョン0.0
+    put_u32(&mut sector, 44, ROOT_CLUSTER);
+    put_u16(&mut sector, 48, FSINFO_SECTOR as u16);
+    put_u16(&mut sector, 50, BACKUP_BOOT_SECTOR as u16);
+    sector[64] = 0x80; // ドライブ番号
+    sector[66] = 0x29; // 拡張ブートシグネチャ
+    put_u32(&mut sector, 67, volume_id);
+    sector[71..82].copy_from_slice(label);
+    sector[82..90].copy_from_slice(b"FAT32   ");
+    // 起動可能ではないので、ブートコードは停止ループだけ
+    sector[90..93].copy_from_slice(&[0xf4, 0xeb, 0xfd]); // hlt; jmp $-1
+    sector[510] = 0x55;
+    sector[511] = 0xaa;
+    sector
+}
+
+/// FSInfoセクタ（空きクラスタ数のヒント）
+fn fsinfo_sector(free_clusters: u32, next_free: u32) -> [u8; SECTOR_SIZE] {
+    let mut sector = [0u8; SECTOR_SIZE];
+    put_u32(&mut sector, 0, 0x4161_5252);
+    put_u32(&mut sector, 484, 0x6141_7272);
+    put_u32(&mut sector, 488, free_clusters);
+    put_u32(&mut sector, 492, next_free);
+    put_u32(&mut sector, 508, 0xaa55_0000);
+    sector
+}
+
+/// 書き込んだボリュームからファイルを読み出す（mkimageの自己検証とテスト用）
+pub fn read_file(volume: &[u8], path: &str) -> Option<Vec<u8>> {
+    let sector_size = get_u16(volume, 11) as usize;
+    let sectors_per_cluster = volume[13] as usize;
+    let reserved = get_u16(volume, 14) as usize;
+    let num_fats = volume[16] as usize;
+    let fat_sectors = get_u32(volume, 36) as usize;
+    let cluster_size = sector_size * sectors_per_cluster;
+    let fat = &volume[reserved * sector_size..(reserved + fat_sectors) * sector_size];
+    let data_start = (reserved + num_fats * fat_sectors) * sector_size;
+
+    // クラスタチェーンをたどって内容を連結する
+    let read_chain = |first: u32, size: Option<usize>| -> Option<Vec<u8>> {
+        let mut data = Vec::new();
+        let mut cluster = first;
+        while (2..0x0fff_fff8).contains(&cluster) {
+            let offset = data_start + (cluster as usize - 2) * cluster_size;
+            data.extend_from_slice(volume.get(offset..offset + cluster_size)?);
+            cluster = get_u32(fat, cluster as usize * 4) & 0x0fff_ffff;
+        }
+        if let Some(size) = size {
+            data.truncate(size);
+        }
+        Some(data)
+    };
+
+    let mut dir = read_chain(get_u32(volume, 44), None)?;
+    let mut components = path.split('/').filter(|c| !c.is_empty()).peekable();
+    while let Some(component) = components.next() {
+        let name = ShortName::parse(&component.to_ascii_uppercase()).ok()?;
+        let entry = dir
+            .chunks(DIR_ENTRY_SIZE)
+            .take_while(|e| e[0] != 0)
+            .find(|e| e[11] & attr::VOLUME_ID == 0 && &e[0..11] == name.as_bytes())?;
+        let cluster = (get_u16(entry, 20) as u32) << 16 | get_u16(entry, 26) as u32;
+        let is_dir = entry[11] & attr::DIRECTORY != 0;
+        if components.peek().is_none() {
+            return if is_dir { None } else { read_chain(cluster, Some(get_u32(entry, 28) as usize)) };
+        }
+        if !is_dir {
+            return None;
+        }
+        dir = read_chain(cluster, None)?;
+    }
+    None
+}
+
+// ===== テスト =====
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 63MiBのボリューム（64MiBのディスクのESPと同じ大きさ）
+    const SECTORS: usize = 63 * 1024 * 1024 / SECTOR_SIZE;
+
+    fn format(root: &Directory) -> Vec<u8> {
+        let mut volume = vec![0u8; SECTORS * SECTOR_SIZE];
+        write(&mut volume, 2048, 0x1234_5678, "LEARNING OS", root).unwrap();
+        volume
+    }
+
+    mod short_name_tests {
+        use super::*;
+
+        #[test]
+        fn test_parse_upper_and_lower() {
+            let name = ShortName::parse("BOOTX64.EFI").unwrap();
+            assert_eq!(name.as_bytes(), b"BOOTX64 EFI", "空白で埋める");
+            assert_eq!(name.case, 0);
+
+            let name = ShortName::parse("kernel.elf").unwrap();
+            assert_eq!(name.as_bytes(), b"KERNEL  ELF", "大文字で保存する");
+            assert_eq!(name.case, nt_flags::LOWER_BASE | nt_flags::LOWER_EXT, "小文字で表示するフラグ");
+
+            assert_eq!(ShortName::parse("initrd").unwrap().as_bytes(), b"INITRD     ", "拡張子なし");
+        }
+
+        #[test]
+        fn test_parse_invalid() {
+            for name in ["", "toolongname.efi", "a.long", "Kernel.elf", "a b", "a.", ".efi"] {
+                assert!(ShortName::parse(name).is_err(), "{:?} は8.3形式で表せない", name);
+            }
+        }
+    }
+
+    mod directory_tests {
+        use super::*;
+
+        #[test]
+        fn test_add_file_creates_directories() {
+            let mut root = Directory::new();
+            root.add_file("/EFI/BOOT/BOOTX64.EFI", vec![1]).unwrap();
+            root.add_file("/EFI/BOOT/STARTUP.NSH", vec![2]).unwrap();
+            assert_eq!(root.entries.len(), 1, "EFIディレクトリは1つだけ作る");
+        }
+
+        #[test]
+        fn test_duplicate_name() {
+            let mut root = Directory::new();
+            root.add_file("kernel.elf", vec![1]).unwrap();
+            assert_eq!(
+                root.add_file("KERNEL.ELF", vec![2]),
+                Err(ImageError::DuplicateName("KERNEL.ELF".to_string())),
+                "大文字小文字だけ違う名前は同じファイル"
+            );
+            assert!(root.add_file("kernel.elf/x", vec![]).is_err(), "ファイルの下には作れない");
+        }
+    }
+
+    mod geometry_tests {
+        use super::*;
+
+        #[test]
+        fn test_geometry_fits() {
+            let geometry = Geometry::new(SECTORS as u64).unwrap();
+            assert_eq!(geometry.sectors_per_cluster, 1, "260MiB以下は512バイトクラスタ");
+            assert!(geometry.cluster_count >= MIN_CLUSTERS, "FAT32と判定されるクラスタ数");
+            let fat_entries = geometry.fat_sectors as usize * SECTOR_SIZE / 4;
+            assert!(fat_entries >= geometry.cluster_count as usize + 2, "FATに全クラスタが載る");
+            let used = geometry.data_start() as usize + geometry.cluster_count as usize * geometry.sectors_per_cluster as usize;
+            assert!(used <= SECTORS, "ボリュームからはみ出さない");
+        }
+
+        #[test]
+        fn test_too_small_for_fat32() {
+            assert_eq!(Geometry::new(16 * 1024 * 1024 / SECTOR_SIZE as u64), Err(ImageError::TooSmall), "16MiBでは足りない");
+        }
+    }
+
+    mod write_tests {
+        use super::*;
+
+        #[test]
+        fn test_roundtrip() {
+            let loader: Vec<u8> = (0..3000).map(|i| i as u8).collect();
+            let kernel: Vec<u8> = (0..100_000).map(|i| (i * 7) as u8).collect();
+            let mut root = Directory::new();
+            root.add_file("/EFI/BOOT/BOOTX64.EFI", loader.clone()).unwrap();
+            root.add_file("/kernel.elf", kernel.clone()).unwrap();
+            root.add_file("/empty", Vec::new()).unwrap();
+            let volume = format(&root);
+
+            assert_eq!(read_file(&volume, "/EFI/BOOT/BOOTX64.EFI"), Some(loader), "ブートローダーを読み戻せる");
+            assert_eq!(read_file(&volume, "/kernel.elf"), Some(kernel), "複数クラスタのファイル");
+            assert_eq!(read_file(&volume, "/KERNEL.ELF").map(|d| d.len()), Some(100_000), "大文字小文字を区別しない");
+            assert_eq!(read_file(&volume, "/empty"), Some(Vec::new()), "空のファイル");
+            assert_eq!(read_file(&volume, "/missing"), None);
+            assert_eq!(read_file(&volume, "/EFI"), None, "ディレクトリはファイルとして読めない");
+        }
+
+        #[test]
+        fn test_boot_sector_and_fsinfo() {
+            let volume = format(&Directory::new());
+            assert_eq!(&volume[510..512], &[0x55, 0xaa], "ブートシグネチャ");
+            assert_eq!(&volume[82..90], b"FAT32   ");
+            assert_eq!(get_u32(&volume, 28), 2048, "隠しセクタ = パーティション開始LBA");
+            assert_eq!(&volume[..SECTOR_SIZE], &volume[6 * SECTOR_SIZE..7 * SECTOR_SIZE], "バックアップブートセクタ");
+            let fsinfo = &volume[SECTOR_SIZE..2 * SECTOR_SIZE];
+            assert_eq!(get_u32(fsinfo, 0), 0x4161_5252, "FSInfoのシグネチャ");
+            assert_eq!(get_u32(fsinfo, 492), 3, "ルートディレクトリの次が空き");
+        }
+
+        #[test]
+        fn test_subdirectory_dot_entries() {
+            let mut root = Directory::new();
+            root.add_file("/EFI/BOOT/BOOTX64.EFI", vec![0xaa]).unwrap();
+            let volume = format(&root);
+            let geometry = Geometry::new(SECTORS as u64).unwrap();
+
+            // ルート → EFI（クラスタ3）→ BOOT（クラスタ4）の順に確保される
+            let efi = &volume[geometry.cluster_offset(3)..];
+            assert_eq!(&efi[0..11], b".          ");
+            assert_eq!(get_u16(efi, 26), 3, "\".\" は自分自身");
+            assert_eq!(&efi[32..43], b"..         ");
+            assert_eq!(get_u16(efi, 32 + 26), 0, "ルート直下の \"..\" は0");
+            let boot = &volume[geometry.cluster_offset(4)..];
+            assert_eq!(get_u16(boot, 32 + 26), 3, "BOOTの \"..\" はEFI");
+        }
+
+        #[test]
+        fn test_disk_full() {
+            let mut root = Directory::new();
+            root.add_file("big", vec![0; SECTORS * SECTOR_SIZE]).unwrap();
+            let mut volume = vec![0u8; SECTORS * SECTOR_SIZE];
+            assert_eq!(write(&mut volume, 0, 0, "", &root), Err(ImageError::DiskFull));
+        }
+    }
+}
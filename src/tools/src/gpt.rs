@@ -0,0 +1,335 @@
+//! GPT（GUIDパーティションテーブル）
+//!
+//! UEFIファームウェアはGPTディスクのEFIシステムパーティション（ESP）から
+//! `EFI/BOOT/BOOTX64.EFI` を探して起動する。ディスクの構成:
+//!
+//! ```text
+//! LBA 0              保護MBR（GPTを知らないツールにディスク全体が使用中だと見せる）
+//! LBA 1              プライマリGPTヘッダ
+//! LBA 2..=33         パーティションエントリ配列（128エントリ x 128バイト）
+//! LBA 2048..         ESP（1MiB境界に揃える）
+//! LBA -33..=-2       バックアップのパーティションエントリ配列
+//! LBA -1             バックアップGPTヘッダ
+//! ```
+
+use std::fmt;
+
+use crate::crc32::crc32;
+use crate::{get_u32, get_u64, put_u16, put_u32, put_u64, ImageError, SplitMix64, SECTOR_SIZE};
+
+/// パーティションエントリの数（仕様上の最小値）
+pub const ENTRY_COUNT: usize = 128;
+/// パーティションエントリ1つのサイズ
+pub const ENTRY_SIZE: usize = 128;
+/// パーティションエントリ配列が占めるセクタ数
+pub const ENTRY_SECTORS: u64 = (ENTRY_COUNT * ENTRY_SIZE / SECTOR_SIZE) as u64;
+/// パーティションの開始位置の揃え（2048セクタ = 1MiB）
+pub const PARTITION_ALIGN: u64 = 2048;
+
+/// GPTヘッダのシグネチャ
+const SIGNATURE: &[u8; 8] = b"EFI PART";
+/// GPTヘッダのリビジョン（1.0）
+const REVISION: u32 = 0x0001_0000;
+/// GPTヘッダのサイズ
+const HEADER_SIZE: u32 = 92;
+
+/// GUID（ディスク上の並び: 先頭3フィールドはリトルエンディアン）
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Guid([u8; 16]);
+
+impl Guid {
+    /// EFIシステムパーティションの種別GUID（C12A7328-F81F-11D2-BA4B-00A0C93EC93B）
+    pub const EFI_SYSTEM_PARTITION: Guid =
+        Guid::from_fields(0xc12a_7328, 0xf81f, 0x11d2, [0xba, 0x4b, 0x00, 0xa0, 0xc9, 0x3e, 0xc9, 0x3b]);
+
+    pub const fn from_fields(d1: u32, d2: u16, d3: u16, d4: [u8; 8]) -> Self {
+        let a = d1.to_le_bytes();
+        let b = d2.to_le_bytes();
+        let c = d3.to_le_bytes();
+        Guid([
+            a[0], a[1], a[2], a[3], b[0], b[1], c[0], c[1], d4[0], d4[1], d4[2], d4[3], d4[4], d4[5], d4[6], d4[7],
+        ])
+    }
+
+    /// 乱数からバージョン4のGUIDを作る
+    pub fn random(rng: &mut SplitMix64) -> Self {
+        let mut bytes = [0u8; 16];
+        bytes[..8].copy_from_slice(&rng.next_u64().to_le_bytes());
+        bytes[8..].copy_from_slice(&rng.next_u64().to_le_bytes());
+        // d3の上位4bitがバージョン、d4[0]の上位2bitがバリアント
+        bytes[7] = (bytes[7] & 0x0f) | 0x40;
+        bytes[8] = (bytes[8] & 0x3f) | 0x80;
+        Guid(bytes)
+    }
+
+    pub fn as_bytes(&self) -> &[u8; 16] {
+        &self.0
+    }
+}
+
+impl fmt::Display for Guid {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let b = &self.0;
+        write!(
+            f,
+            "{:08X}-{:04X}-{:04X}-{:02X}{:02X}-",
+            u32::from_le_bytes([b[0], b[1], b[2], b[3]]),
+            u16::from_le_bytes([b[4], b[5]]),
+            u16::from_le_bytes([b[6], b[7]]),
+            b[8],
+            b[9]
+        )?;
+        for byte in &b[10..] {
+            write!(f, "{:02X}", byte)?;
+        }
+        Ok(())
+    }
+}
+
+/// ディスク全体とESPの配置
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Layout {
+    /// ディスク全体のセクタ数
+    pub total_sectors: u64,
+    /// ESPの最初のLBA
+    pub partition_start: u64,
+    /// ESPの最後のLBA（この値を含む）
+    pub partition_end: u64,
+}
+
+impl Layout {
+    /// ディスクのセクタ数から、1MiB境界に揃えたESPの配置を決める
+    pub fn new(total_sectors: u64) -> Result<Self, ImageError> {
+        let last_usable = total_sectors.checked_sub(ENTRY_SECTORS + 2).ok_or(ImageError::TooSmall)?;
+        let partition_start = PARTITION_ALIGN;
+        let partition_end = ((last_usable + 1) / PARTITION_ALIGN * PARTITION_ALIGN).saturating_sub(1);
+        if partition_end < partition_start {
+            return Err(ImageError::TooSmall);
+        }
+        Ok(Self { total_sectors, partition_start, partition_end })
+    }
+
+    /// ESPのセクタ数
+    pub fn partition_sectors(&self) -> u64 {
+        self.partition_end - self.partition_start + 1
+    }
+
+    /// ESPのバイト範囲
+    pub fn partition_range(&self) -> std::ops::Range<usize> {
+        let start = self.partition_start as usize * SECTOR_SIZE;
+        start..start + self.partition_sectors() as usize * SECTOR_SIZE
+    }
+
+    fn last_usable(&self) -> u64 {
+        self.total_sectors - ENTRY_SECTORS - 2
+    }
+}
+
+/// 保護MBR・GPTヘッダ・パーティションエントリ配列をイメージに書き込む
+///
+/// `image` の長さは `layout.total_sectors * SECTOR_SIZE` であること。
+pub fn write(image: &mut [u8], layout: &Layout, disk_guid: Guid, partition_guid: Guid) {
+    assert_eq!(image.len() as u64, layout.total_sectors * SECTOR_SIZE as u64, "イメージサイズとレイアウトの不一致");
+
+    write_protective_mbr(&mut image[..SECTOR_SIZE], layout.total_sectors);
+
+    let entries = partition_entries(layout, partition_guid);
+    let entries_crc = crc32(&entries);
+    let backup_entries_lba = layout.total_sectors - 1 - ENTRY_SECTORS;
+
+    let primary = header(layout, 1, layout.total_sectors - 1, 2, disk_guid, entries_crc);
+    let backup = header(layout, layout.total_sectors - 1, 1, backup_entries_lba, disk_guid, entries_crc);
+
+    image[SECTOR_SIZE..2 * SECTOR_SIZE].copy_from_slice(&primary);
+    image[2 * SECTOR_SIZE..2 * SECTOR_SIZE + entries.len()].copy_from_slice(&entries);
+    let backup_entries = backup_entries_lba as usize * SECTOR_SIZE;
+    image[backup_entries..backup_entries + entries.len()].copy_from_slice(&entries);
+    let last = (layout.total_sectors as usize - 1) * SECTOR_SIZE;
+    image[last..last + SECTOR_SIZE].copy_from_slice(&backup);
+}
+
+/// ディスク全体を1つの種別0xEEパーティションとして見せる保護MBR
+fn write_protective_mbr(sector: &mut [u8], total_sectors: u64) {
+    let entry = &mut sector[446..462];
+    entry[0] = 0x00; // 起動不可
+    entry[1..4].copy_from_slice(&[0x00, 0x02, 0x00]); // 開始CHS
+    entry[4] = 0xee; // GPT保護
+    entry[5..8].copy_from_slice(&[0xff, 0xff, 0xff]); // 終了CHS
+    put_u32(entry, 8, 1);
+    put_u32(entry, 12, (total_sectors - 1).min(u32::MAX as u64) as u32);
+    sector[510] = 0x55;
+    sector[511] = 0xaa;
+}
+
+/// ESPだけを含むパーティションエントリ配列
+fn partition_entries(layout: &Layout, partition_guid: Guid) -> Vec<u8> {
+    let mut entries = vec![0u8; ENTRY_COUNT * ENTRY_SIZE];
+    let entry = &mut entries[..ENTRY_SIZE];
+    entry[0..16].copy_from_slice(Guid::EFI_SYSTEM_PARTITION.as_bytes());
+    entry[16..32].copy_from_slice(partition_guid.as_bytes());
+    put_u64(entry, 32, layout.partition_start);
+    put_u64(entry, 40, layout.partition_end);
+    put_u64(entry, 48, 0); // 属性
+    // パーティション名（UTF-16LE、最大36文字）
+    for (i, unit) in "EFI System Partition".encode_utf16().take(36).enumerate() {
+        put_u16(entry, 56 + i * 2, unit);
+    }
+    entries
+}
+
+/// GPTヘッダ（1セクタ分）
+fn header(layout: &Layout, my_lba: u64, alternate_lba: u64, entries_lba: u64, disk_guid: Guid, entries_crc: u32) -> [u8; SECTOR_SIZE] {
+    let mut sector = [0u8; SECTOR_SIZE];
+    sector[0..8].copy_from_slice(SIGNATURE);
+    put_u32(&mut sector, 8, REVISION);
+    put_u32(&mut sector, 12, HEADER_SIZE);
+    // 16: ヘッダのCRC32（計算時は0）, 20: 予約
+    put_u64(&mut sector, 24, my_lba);
+    put_u64(&mut sector, 32, alternate_lba);
+    put_u64(&mut sector, 40, ENTRY_SECTORS + 2);
+    put_u64(&mut sector, 48, layout.last_usable());
+    sector[56..72].copy_from_slice(disk_guid.as_bytes());
+    put_u64(&mut sector, 72, entries_lba);
+    put_u32(&mut sector, 80, ENTRY_COUNT as u32);
+    put_u32(&mut sector, 84, ENTRY_SIZE as u32);
+    put_u32(&mut sector, 88, entries_crc);
+    let crc = crc32(&sector[..HEADER_SIZE as usize]);
+    put_u32(&mut sector, 16, crc);
+    sector
+}
+
+/// イメージのGPTを検証し、最初のESPの (開始LBA, 終了LBA) を返す
+///
+/// プライマリとバックアップの両方のヘッダ・エントリ配列のCRCを確認する。
+pub fn verify(image: &[u8]) -> Result<(u64, u64), &'static str> {
+    if image.len() < 3 * SECTOR_SIZE || !image.len().is_multiple_of(SECTOR_SIZE) {
+        return Err("image size is not a multiple of the sector size");
+    }
+    if image[510] != 0x55 || image[511] != 0xaa || image[446 + 4] != 0xee {
+        return Err("missing protective MBR");
+    }
+    let total_sectors = (image.len() / SECTOR_SIZE) as u64;
+    let primary = &image[SECTOR_SIZE..2 * SECTOR_SIZE];
+    let backup_lba = get_u64(primary, 32);
+    if backup_lba != total_sectors - 1 {
+        return Err("backup header is not at the last LBA");
+    }
+    let backup = &image[backup_lba as usize * SECTOR_SIZE..];
+
+    let mut esp = None;
+    for (my_lba, header) in [(1, primary), (backup_lba, backup)] {
+        if &header[0..8] != SIGNATURE {
+            return Err("bad GPT signature");
+        }
+        if get_u64(header, 24) != my_lba {
+            return Err("header LBA mismatch");
+        }
+        let mut copy = header[..HEADER_SIZE as usize].to_vec();
+        copy[16..20].fill(0);
+        if crc32(&copy) != get_u32(header, 16) {
+            return Err("bad header CRC32");
+        }
+        let entries_lba = get_u64(header, 72) as usize;
+        let count = get_u32(header, 80) as usize;
+        let size = get_u32(header, 84) as usize;
+        let start = entries_lba * SECTOR_SIZE;
+        let entries = image.get(start..start + count * size).ok_or("entry array out of range")?;
+        if crc32(entries) != get_u32(header, 88) {
+            return Err("bad partition entry array CRC32");
+        }
+        for entry in entries.chunks(size) {
+            if &entry[0..16] == Guid::EFI_SYSTEM_PARTITION.as_bytes() {
+                esp.get_or_insert((get_u64(entry, 32), get_u64(entry, 40)));
+                break;
+            }
+        }
+    }
+    esp.ok_or("no EFI system partition")
+}
+
+// ===== テスト =====
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 64MiBのディスク
+    const TOTAL: u64 = 64 * 1024 * 1024 / SECTOR_SIZE as u64;
+
+    fn build() -> (Vec<u8>, Layout) {
+        let layout = Layout::new(TOTAL).unwrap();
+        let mut image = vec![0u8; TOTAL as usize * SECTOR_SIZE];
+        let mut rng = SplitMix64::new(1);
+        write(&mut image, &layout, Guid::random(&mut rng), Guid::random(&mut rng));
+        (image, layout)
+    }
+
+    mod guid_tests {
+        use super::*;
+
+        #[test]
+        fn test_esp_type_guid_layout() {
+            let bytes = Guid::EFI_SYSTEM_PARTITION.as_bytes();
+            assert_eq!(&bytes[..4], &[0x28, 0x73, 0x2a, 0xc1], "先頭フィールドはリトルエンディアン");
+            assert_eq!(&bytes[8..], &[0xba, 0x4b, 0x00, 0xa0, 0xc9, 0x3e, 0xc9, 0x3b], "末尾はそのまま");
+            assert_eq!(Guid::EFI_SYSTEM_PARTITION.to_string(), "C12A7328-F81F-11D2-BA4B-00A0C93EC93B");
+        }
+
+        #[test]
+        fn test_random_guid_version() {
+            let guid = Guid::random(&mut SplitMix64::new(42));
+            let text = guid.to_string();
+            assert_eq!(&text[14..15], "4", "バージョン4");
+            assert!("89AB".contains(&text[19..20]), "RFC 4122のバリアント");
+        }
+    }
+
+    mod layout_tests {
+        use super::*;
+
+        #[test]
+        fn test_partition_aligned() {
+            let layout = Layout::new(TOTAL).unwrap();
+            assert_eq!(layout.partition_start, PARTITION_ALIGN, "1MiBから始まる");
+            assert!((layout.partition_end + 1).is_multiple_of(PARTITION_ALIGN), "終わりも1MiB境界");
+            assert!(layout.partition_end <= TOTAL - ENTRY_SECTORS - 2, "バックアップ領域と重ならない");
+        }
+
+        #[test]
+        fn test_too_small() {
+            assert_eq!(Layout::new(2048), Err(ImageError::TooSmall), "パーティションを置けない");
+            assert_eq!(Layout::new(10), Err(ImageError::TooSmall));
+        }
+    }
+
+    mod write_tests {
+        use super::*;
+
+        #[test]
+        fn test_verify_roundtrip() {
+            let (image, layout) = build();
+            assert_eq!(verify(&image), Ok((layout.partition_start, layout.partition_end)), "書いたESPが読める");
+        }
+
+        #[test]
+        fn test_protective_mbr() {
+            let (image, _) = build();
+            assert_eq!(image[446 + 4], 0xee, "種別0xEE");
+            assert_eq!(get_u32(&image, 446 + 8), 1, "LBA 1から");
+            assert_eq!(get_u32(&image, 446 + 12), (TOTAL - 1) as u32, "ディスクの終わりまで");
+        }
+
+        #[test]
+        fn test_corruption_detected() {
+            let (mut image, layout) = build();
+            // エントリ配列の1バイトを壊す
+            image[2 * SECTOR_SIZE + 40] ^= 0xff;
+            assert_eq!(verify(&image), Err("bad partition entry array CRC32"));
+
+            let (mut image, _) = build();
+            // バックアップヘッダのディスクGUIDを壊す
+            let last = (layout.total_sectors as usize - 1) * SECTOR_SIZE;
+            image[last + 60] ^= 0xff;
+            assert_eq!(verify(&image), Err("bad header CRC32"));
+        }
+    }
+}
@@ -0,0 +1,135 @@
+//! 開発用ホストツール
+//!
+//! READMEの最終目標である「USBメモリから起動できるOSイメージ」を作るためのツール群。
+//! mkfsやループデバイスに頼らず、ディスクイメージのバイト列をRustだけで組み立てる。
+//!
+//! - `cpio`: 初期RAMディスク（initrd）の cpio アーカイブ（newc 形式）
+//! - `gpt`: GPTパーティションテーブル（保護MBR、プライマリ/バックアップヘッダ）
+//! - `fat32`: EFIシステムパーティション（ESP）用のFAT32ファイルシステム
+//! - `qemu`: 作ったイメージをQEMU + OVMFで起動して確認する（カーネル内テストの実行も）
+//! - `symtab`: カーネルELFにバックトレース用のシンボル表を埋め込む
+//!
+//! バイナリ `mkimage` がこれらを組み合わせて `.img` ファイルを書き出す。
+//! バイナリ `ksyms` はシンボル表の埋め込みだけを行う。
+//! バイナリ `mkinitrd` はディレクトリからinitrdを作る。
+//! バイナリ `ktest` はカーネル内テストをQEMUで実行する（cargo の runner）。
+
+pub mod cpio;
+pub mod crc32;
+pub mod fat32;
+pub mod gpt;
+pub mod qemu;
+pub mod symtab;
+
+use std::fmt;
+
+/// セクタサイズ（USBメモリもQEMUも512バイト）
+pub const SECTOR_SIZE: usize = 512;
+
+/// イメージ作成時のエラー
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ImageError {
+    /// ディスクが小さすぎる（FAT32として必要なクラスタ数が確保できない等）
+    TooSmall,
+    /// ファイルがパーティションに収まらない
+    DiskFull,
+    /// 8.3形式で表せないファイル名
+    InvalidName(String),
+    /// 同じディレクトリに同じ名前がある
+    DuplicateName(String),
+}
+
+impl fmt::Display for ImageError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ImageError::TooSmall => write!(f, "disk image is too small for FAT32"),
+            ImageError::DiskFull => write!(f, "files do not fit in the partition"),
+            ImageError::InvalidName(name) => write!(f, "invalid 8.3 file name: {}", name),
+            ImageError::DuplicateName(name) => write!(f, "duplicate file name: {}", name),
+        }
+    }
+}
+
+impl std::error::Error for ImageError {}
+
+/// リトルエンディアンでの書き込み（ディスク上の構造はすべてリトルエンディアン）
+pub(crate) fn put_u16(buf: &mut [u8], off: usize, value: u16) {
+    buf[off..off + 2].copy_from_slice(&value.to_le_bytes());
+}
+
+pub(crate) fn put_u32(buf: &mut [u8], off: usize, value: u32) {
+    buf[off..off + 4].copy_from_slice(&value.to_le_bytes());
+}
+
+pub(crate) fn put_u64(buf: &mut [u8], off: usize, value: u64) {
+    buf[off..off + 8].copy_from_slice(&value.to_le_bytes());
+}
+
+pub(crate) fn get_u16(buf: &[u8], off: usize) -> u16 {
+    u16::from_le_bytes([buf[off], buf[off + 1]])
+}
+
+pub(crate) fn get_u32(buf: &[u8], off: usize) -> u32 {
+    u32::from_le_bytes([buf[off], buf[off + 1], buf[off + 2], buf[off + 3]])
+}
+
+pub(crate) fn get_u64(buf: &[u8], off: usize) -> u64 {
+    get_u32(buf, off) as u64 | (get_u32(buf, off + 4) as u64) << 32
+}
+
+/// イメージの内容から決まる疑似乱数（splitmix64）
+///
+/// GUIDやボリュームIDを作るのに使う。入力が同じなら同じイメージになる（再現可能なビルド）。
+pub struct SplitMix64(u64);
+
+impl SplitMix64 {
+    pub fn new(seed: u64) -> Self {
+        Self(seed)
+    }
+
+    /// 任意のバイト列からシードを作る（FNV-1a）
+    pub fn from_bytes<'a>(chunks: impl IntoIterator<Item = &'a [u8]>) -> Self {
+        let mut hash = 0xcbf2_9ce4_8422_2325u64;
+        for chunk in chunks {
+            for &byte in chunk {
+                hash = (hash ^ byte as u64).wrapping_mul(0x0000_0100_0000_01b3);
+            }
+        }
+        Self(hash)
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+}
+
+// ===== テスト =====
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_little_endian_roundtrip() {
+        let mut buf = [0u8; 16];
+        put_u16(&mut buf, 0, 0x1234);
+        put_u32(&mut buf, 2, 0xdead_beef);
+        put_u64(&mut buf, 6, 0x0102_0304_0506_0708);
+        assert_eq!(buf[0], 0x34, "下位バイトが先");
+        assert_eq!(get_u16(&buf, 0), 0x1234);
+        assert_eq!(get_u32(&buf, 2), 0xdead_beef);
+        assert_eq!(get_u64(&buf, 6), 0x0102_0304_0506_0708);
+    }
+
+    #[test]
+    fn test_seed_is_deterministic() {
+        let a = SplitMix64::from_bytes([&b"kernel"[..], &b"loader"[..]]).next_u64();
+        let b = SplitMix64::from_bytes([&b"kernel"[..], &b"loader"[..]]).next_u64();
+        let c = SplitMix64::from_bytes([&b"kernel2"[..]]).next_u64();
+        assert_eq!(a, b, "同じ入力なら同じ値");
+        assert_ne!(a, c, "入力が違えば違う値");
+    }
+}
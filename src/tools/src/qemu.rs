@@ -0,0 +1,211 @@
+//! 作ったディスクイメージをQEMU + OVMFで起動して確認する
+//!
+//! OVMFはコンソール出力をシリアルポートにも流すので、`-serial stdio` の出力に
+//! ブートローダーのメッセージが現れるかどうかで起動できたかを判定する。
+//! OVMFのファイルはネットワークから取得せず、ローカルに置いたものを使う。
+//!
+//! カーネル内テスト（`KernelTest`）はOVMFを使わず、`-kernel` でMultiboot起動する。
+//! 結果は isa-debug-exit デバイスを通したQEMUの終了コードで受け取る。
+
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// ブートローダーが出力するメッセージ（`bootloader/src/main.rs`）
+pub const DEFAULT_MARKERS: &[&str] = &["Learning OS bootloader", "exiting boot services"];
+
+/// 起動確認の設定
+#[derive(Debug, Clone)]
+pub struct BootTest {
+    /// OVMF_CODE.fd と OVMF_VARS.fd を置いたディレクトリ
+    pub ovmf_dir: PathBuf,
+    /// これだけ待っても見つからなければ失敗
+    pub timeout: Duration,
+    /// シリアル出力に順に現れるべき文字列
+    pub markers: Vec<String>,
+}
+
+impl BootTest {
+    pub fn new(ovmf_dir: PathBuf) -> Self {
+        Self {
+            ovmf_dir,
+            timeout: Duration::from_secs(30),
+            markers: DEFAULT_MARKERS.iter().map(|s| s.to_string()).collect(),
+        }
+    }
+
+    /// イメージを起動し、すべてのマーカーが出力されたらシリアル出力を返す
+    pub fn run(&self, image: &Path) -> Result<String, String> {
+        let code = self.ovmf_dir.join("OVMF_CODE.fd");
+        if !code.exists() {
+            return Err(format!("{} not found", code.display()));
+        }
+        // OVMF_VARS.fd は書き込まれるので、元のファイルを汚さないようにコピーして使う
+        let vars = std::env::temp_dir().join(format!("learning-os-ovmf-vars-{}.fd", std::process::id()));
+        std::fs::copy(self.ovmf_dir.join("OVMF_VARS.fd"), &vars).map_err(|e| format!("OVMF_VARS.fd: {}", e))?;
+
+        let mut child = Command::new("qemu-system-x86_64")
+            .args(["-machine", "q35", "-m", "256M", "-display", "none", "-serial", "stdio", "-no-reboot"])
+            .arg("-drive")
+            .arg(format!("if=pflash,format=raw,readonly=on,file={}", code.display()))
+            .arg("-drive")
+            .arg(format!("if=pflash,format=raw,file={}", vars.display()))
+            .arg("-drive")
+            .arg(format!("format=raw,file={}", image.display()))
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::inherit())
+            .spawn()
+            .map_err(|e| format!("failed to start qemu-system-x86_64: {}", e))?;
+
+        // 出力は別スレッドで読み、ここではマーカーが揃うのを待つ
+        let output = Arc::new(Mutex::new(Vec::new()));
+        let mut stdout = child.stdout.take().unwrap();
+        let reader = {
+            let output = Arc::clone(&output);
+            thread::spawn(move || {
+                let mut buf = [0u8; 4096];
+                while let Ok(n) = stdout.read(&mut buf) {
+                    if n == 0 {
+                        break;
+                    }
+                    output.lock().unwrap().extend_from_slice(&buf[..n]);
+                }
+            })
+        };
+
+        let start = Instant::now();
+        let result = loop {
+            let text = String::from_utf8_lossy(&output.lock().unwrap()).into_owned();
+            if markers_found(&text, &self.markers) {
+                break Ok(text);
+            }
+            if let Ok(Some(status)) = child.try_wait() {
+                break Err(format!("qemu exited ({}) before boot completed:\n{}", status, text));
+            }
+            if start.elapsed() > self.timeout {
+                break Err(format!("timed out after {:?}:\n{}", self.timeout, text));
+            }
+            thread::sleep(Duration::from_millis(100));
+        };
+
+        let _ = child.kill();
+        let _ = child.wait();
+        let _ = reader.join();
+        let _ = std::fs::remove_file(&vars);
+        result
+    }
+}
+
+/// カーネル内テストがすべて成功したときのQEMUの終了コード
+/// （`kernel/src/arch/x86_64/qemu.rs` の QemuExitCode::Success を (v << 1) | 1 したもの）
+pub const TEST_SUCCESS_STATUS: i32 = 0x21;
+/// カーネル内テストが失敗したときのQEMUの終了コード
+pub const TEST_FAILURE_STATUS: i32 = 0x23;
+
+/// カーネル内テストの実行の設定
+#[derive(Debug, Clone)]
+pub struct KernelTest {
+    /// これだけ待っても終わらなければ失敗
+    pub timeout: Duration,
+    /// `-initrd` で渡すアーカイブ（Multibootの最初のモジュールになる）
+    pub initrd: Option<PathBuf>,
+}
+
+impl KernelTest {
+    pub fn new() -> Self {
+        Self { timeout: Duration::from_secs(60), initrd: None }
+    }
+
+    /// テスト用のカーネルを起動して、終わるのを待つ（シリアル出力はそのまま標準出力に流す）
+    pub fn run(&self, kernel: &Path) -> Result<(), String> {
+        let mut command = Command::new("qemu-system-x86_64");
+        command
+            .args(["-m", "256M", "-display", "none", "-serial", "stdio", "-no-reboot"])
+            .args(["-device", "isa-debug-exit,iobase=0xf4,iosize=0x04"])
+            .arg("-kernel")
+            .arg(kernel);
+        if let Some(initrd) = &self.initrd {
+            command.arg("-initrd").arg(initrd);
+        }
+        let mut child = command
+            .stdin(Stdio::null())
+            .spawn()
+            .map_err(|e| format!("failed to start qemu-system-x86_64: {}", e))?;
+
+        let start = Instant::now();
+        let status = loop {
+            if let Some(status) = child.try_wait().map_err(|e| e.to_string())? {
+                break status;
+            }
+            if start.elapsed() > self.timeout {
+                let _ = child.kill();
+                let _ = child.wait();
+                return Err(format!("timed out after {:?}", self.timeout));
+            }
+            thread::sleep(Duration::from_millis(100));
+        };
+        test_result(status.code())
+    }
+}
+
+impl Default for KernelTest {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// QEMUの終了コードをテストの結果に直す
+pub fn test_result(status: Option<i32>) -> Result<(), String> {
+    match status {
+        Some(TEST_SUCCESS_STATUS) => Ok(()),
+        Some(TEST_FAILURE_STATUS) => Err("kernel tests failed".to_string()),
+        Some(code) => Err(format!("qemu exited with status {} (did the kernel reach the test runner?)", code)),
+        None => Err("qemu was killed by a signal".to_string()),
+    }
+}
+
+/// すべてのマーカーがこの順番で出力に含まれているか
+pub fn markers_found(output: &str, markers: &[String]) -> bool {
+    let mut rest = output;
+    for marker in markers {
+        match rest.find(marker.as_str()) {
+            Some(pos) => rest = &rest[pos + marker.len()..],
+            None => return false,
+        }
+    }
+    true
+}
+
+// ===== テスト =====
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn markers(list: &[&str]) -> Vec<String> {
+        list.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn test_markers_in_order() {
+        let output = "BdsDxe: loading Boot0001\r\nLearning OS bootloader\r\nkernel: 4 segments\r\nexiting boot services\r\n";
+        assert!(markers_found(output, &markers(DEFAULT_MARKERS)), "順番どおりに出力された");
+        assert!(
+            !markers_found(output, &markers(&["exiting boot services", "Learning OS bootloader"])),
+            "順番が逆なら見つからない"
+        );
+        assert!(!markers_found("Learning OS bootloader\r\n", &markers(DEFAULT_MARKERS)), "途中で止まった");
+        assert!(markers_found("", &[]), "マーカーなしは常に成功");
+    }
+
+    #[test]
+    fn test_kernel_test_status() {
+        assert_eq!(test_result(Some(TEST_SUCCESS_STATUS)), Ok(()));
+        assert!(test_result(Some(TEST_FAILURE_STATUS)).unwrap_err().contains("failed"));
+        assert!(test_result(Some(0)).is_err(), "テストランナーを通らずに終わったら失敗");
+        assert!(test_result(None).is_err());
+    }
+}
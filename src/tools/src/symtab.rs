@@ -0,0 +1,310 @@
+//! カーネルELFのシンボルを `.ksyms` セクションに埋め込む
+//!
+//! リンク済みのカーネルの .symtab から関数のシンボルを集め、デマングルして
+//! `ksyms` クレートの形式の表にし、カーネル内に予約された `.ksyms` セクションの
+//! ファイル上の領域へ直接書き込む。アドレスは変わらないので、リンクをやり直す必要がない。
+//!
+//! Linux: scripts/kallsyms.c（こちらは2回リンクして表を .rodata に入れる）
+
+use ksyms::RawSymbol;
+
+use crate::{get_u16, get_u32, get_u64};
+
+/// シンボルを書き込むセクションの名前
+pub const SECTION_NAME: &str = ".ksyms";
+
+/// ELF64ヘッダのサイズ
+const EHDR_SIZE: usize = 64;
+/// セクションヘッダ1つのサイズ
+const SHDR_SIZE: usize = 64;
+/// シンボル1つのサイズ（Elf64_Sym）
+const SYM_SIZE: usize = 24;
+
+/// SHT_SYMTAB
+const SHT_SYMTAB: u32 = 2;
+/// SHT_NOBITS（.bss のようにファイル上に中身がない）
+const SHT_NOBITS: u32 = 8;
+/// SHF_EXECINSTR
+const SHF_EXECINSTR: u64 = 0x4;
+/// STT_NOTYPE（アセンブリで定義したラベル）
+const STT_NOTYPE: u8 = 0;
+/// STT_FUNC
+const STT_FUNC: u8 = 2;
+
+/// セクションヘッダ（必要な項目だけ）
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Section {
+    pub name: String,
+    pub kind: u32,
+    pub flags: u64,
+    pub addr: u64,
+    pub offset: usize,
+    pub size: usize,
+    pub link: u32,
+}
+
+impl Section {
+    fn file_range(&self) -> std::ops::Range<usize> {
+        self.offset..self.offset + self.size
+    }
+}
+
+/// 関数のシンボル（名前はデマングル済み）
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FunctionSymbol {
+    pub address: u64,
+    pub size: u32,
+    pub name: String,
+}
+
+/// 埋め込みの結果
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Embedded {
+    /// 表に入れたシンボルの数
+    pub count: usize,
+    /// 表のバイト数
+    pub used: usize,
+    /// `.ksyms` セクションの大きさ
+    pub capacity: usize,
+}
+
+fn check_range(elf: &[u8], start: usize, len: usize) -> Result<(), String> {
+    match start.checked_add(len) {
+        Some(end) if end <= elf.len() => Ok(()),
+        _ => Err("ELF file is truncated".to_string()),
+    }
+}
+
+/// セクションヘッダをすべて読む
+pub fn sections(elf: &[u8]) -> Result<Vec<Section>, String> {
+    if elf.len() < EHDR_SIZE || elf[0..4] != [0x7f, b'E', b'L', b'F'] {
+        return Err("not an ELF file".to_string());
+    }
+    if elf[4] != 2 || elf[5] != 1 {
+        return Err("not a 64-bit little-endian ELF file".to_string());
+    }
+    let shoff = get_u64(elf, 40) as usize;
+    let shentsize = get_u16(elf, 58) as usize;
+    let shnum = get_u16(elf, 60) as usize;
+    let shstrndx = get_u16(elf, 62) as usize;
+    if shnum == 0 {
+        return Err("ELF file has no section headers".to_string());
+    }
+    if shentsize < SHDR_SIZE {
+        return Err("unsupported section header size".to_string());
+    }
+    check_range(elf, shoff, shnum * shentsize)?;
+
+    let mut sections = Vec::with_capacity(shnum);
+    let mut name_offsets = Vec::with_capacity(shnum);
+    for i in 0..shnum {
+        let off = shoff + i * shentsize;
+        let section = Section {
+            name: String::new(),
+            kind: get_u32(elf, off + 4),
+            flags: get_u64(elf, off + 8),
+            addr: get_u64(elf, off + 16),
+            offset: get_u64(elf, off + 24) as usize,
+            size: get_u64(elf, off + 32) as usize,
+            link: get_u32(elf, off + 40),
+        };
+        if section.kind != SHT_NOBITS {
+            check_range(elf, section.offset, section.size)?;
+        }
+        name_offsets.push(get_u32(elf, off) as usize);
+        sections.push(section);
+    }
+
+    let shstrtab = sections.get(shstrndx).ok_or("bad section name table index")?.file_range();
+    let names = &elf[shstrtab];
+    for (section, offset) in sections.iter_mut().zip(name_offsets) {
+        section.name = c_string(names, offset).to_string();
+    }
+    Ok(sections)
+}
+
+/// 文字列表の `offset` からNUL終端の文字列を取り出す
+fn c_string(table: &[u8], offset: usize) -> &str {
+    let bytes = table.get(offset..).unwrap_or(&[]);
+    let end = bytes.iter().position(|&b| b == 0).unwrap_or(bytes.len());
+    std::str::from_utf8(&bytes[..end]).unwrap_or("")
+}
+
+/// 実行可能なセクションにある関数とラベルを集める
+///
+/// コンパイラが作るローカルラベル（`.L` で始まるもの）は除く。
+pub fn function_symbols(elf: &[u8]) -> Result<Vec<FunctionSymbol>, String> {
+    let sections = sections(elf)?;
+    let symtab = sections
+        .iter()
+        .find(|s| s.kind == SHT_SYMTAB)
+        .ok_or("ELF file has no .symtab (was it stripped?)")?;
+    let strtab = sections.get(symtab.link as usize).ok_or("bad string table index")?;
+    let strings = &elf[strtab.file_range()];
+
+    let mut symbols = Vec::new();
+    for entry in elf[symtab.file_range()].chunks_exact(SYM_SIZE) {
+        let kind = entry[4] & 0xf;
+        let shndx = get_u16(entry, 6) as usize;
+        let address = get_u64(entry, 8);
+        let size = get_u64(entry, 16);
+        if kind != STT_FUNC && kind != STT_NOTYPE {
+            continue;
+        }
+        let executable = sections.get(shndx).is_some_and(|s| s.flags & SHF_EXECINSTR != 0);
+        let name = c_string(strings, get_u32(entry, 0) as usize);
+        if !executable || name.is_empty() || name.starts_with(".L") || name.starts_with('$') {
+            continue;
+        }
+        symbols.push(FunctionSymbol {
+            address,
+            size: u32::try_from(size).unwrap_or(0),
+            name: ksyms::demangle(name).to_string(),
+        });
+    }
+    Ok(symbols)
+}
+
+/// `.ksyms` セクションがあるか（シンボルを埋め込むカーネルか）
+pub fn has_section(elf: &[u8]) -> bool {
+    sections(elf).is_ok_and(|sections| sections.iter().any(|s| s.name == SECTION_NAME))
+}
+
+/// シンボル表を作って `.ksyms` セクションに書き込む
+///
+/// 何度実行しても同じ結果になる（前の表は消してから書く）。
+pub fn embed(elf: &mut [u8]) -> Result<Embedded, String> {
+    let symbols = function_symbols(elf)?;
+    let section = sections(elf)?
+        .into_iter()
+        .find(|s| s.name == SECTION_NAME)
+        .ok_or_else(|| format!("kernel has no {} section", SECTION_NAME))?;
+    if section.kind == SHT_NOBITS {
+        return Err(format!("{} section has no file contents", SECTION_NAME));
+    }
+
+    let mut raw: Vec<RawSymbol> =
+        symbols.iter().map(|s| RawSymbol { address: s.address, size: s.size, name: &s.name }).collect();
+    let area = &mut elf[section.file_range()];
+    area.fill(0);
+    let used = ksyms::write_table(&mut raw, area)
+        .map_err(|e| format!("{} ({} section is {} bytes)", e, SECTION_NAME, section.size))?;
+    let count = ksyms::SymbolTable::parse(area).map_or(0, |table| table.len());
+    Ok(Embedded { count, used, capacity: section.size })
+}
+
+// ===== テスト =====
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{put_u16, put_u32, put_u64};
+
+    /// テスト用のELFを組み立てる
+    ///
+    /// セクション: なし / .text / .ksyms / .data / .symtab / .strtab / .shstrtab
+    /// symbols: (名前, アドレス, 大きさ, 種類, セクション番号)
+    fn build_elf(ksyms_size: usize, symbols: &[(&str, u64, u64, u8, u16)]) -> Vec<u8> {
+        let mut strtab = vec![0u8];
+        let mut symtab = vec![0u8; SYM_SIZE];
+        for &(name, address, size, kind, shndx) in symbols {
+            let mut entry = [0u8; SYM_SIZE];
+            put_u32(&mut entry, 0, strtab.len() as u32);
+            entry[4] = 0x10 | kind; // STB_GLOBAL
+            put_u16(&mut entry, 6, shndx);
+            put_u64(&mut entry, 8, address);
+            put_u64(&mut entry, 16, size);
+            symtab.extend_from_slice(&entry);
+            strtab.extend_from_slice(name.as_bytes());
+            strtab.push(0);
+        }
+        let mut shstrtab = vec![0u8];
+        let mut name = |s: &str| {
+            let offset = shstrtab.len() as u32;
+            shstrtab.extend_from_slice(s.as_bytes());
+            shstrtab.push(0);
+            offset
+        };
+        // (名前, 種類, フラグ, アドレス, 中身, link)
+        let headers = [
+            (name(".text"), 1, 0x6, 0x1000, vec![0xc3; 0x40], 0),
+            (name(SECTION_NAME), 1, 0x2, 0x2000, vec![0; ksyms_size], 0),
+            (name(".data"), 1, 0x3, 0x3000, vec![0; 16], 0),
+            (name(".symtab"), SHT_SYMTAB, 0, 0, symtab, 5),
+            (name(".strtab"), 3, 0, 0, strtab, 0),
+            (name(".shstrtab"), 3, 0, 0, Vec::new(), 0),
+        ];
+
+        let mut elf = vec![0u8; EHDR_SIZE];
+        elf[0..4].copy_from_slice(&[0x7f, b'E', b'L', b'F']);
+        elf[4] = 2;
+        elf[5] = 1;
+        let mut placed = Vec::new();
+        for (i, (_, _, _, _, data, _)) in headers.iter().enumerate() {
+            let data = if i == headers.len() - 1 { &shstrtab } else { data };
+            placed.push((elf.len(), data.len()));
+            elf.extend_from_slice(data);
+        }
+        let shoff = elf.len();
+        elf.extend_from_slice(&[0u8; SHDR_SIZE]);
+        for ((name, kind, flags, addr, _, link), (offset, size)) in headers.iter().zip(placed) {
+            let mut header = [0u8; SHDR_SIZE];
+            put_u32(&mut header, 0, *name);
+            put_u32(&mut header, 4, *kind);
+            put_u64(&mut header, 8, *flags);
+            put_u64(&mut header, 16, *addr);
+            put_u64(&mut header, 24, offset as u64);
+            put_u64(&mut header, 32, size as u64);
+            put_u32(&mut header, 40, *link);
+            elf.extend_from_slice(&header);
+        }
+        put_u64(&mut elf, 40, shoff as u64);
+        put_u16(&mut elf, 58, SHDR_SIZE as u16);
+        put_u16(&mut elf, 60, headers.len() as u16 + 1);
+        put_u16(&mut elf, 62, headers.len() as u16);
+        elf
+    }
+
+    fn kernel_symbols() -> Vec<(&'static str, u64, u64, u8, u16)> {
+        vec![
+            ("_ZN6kernel11kernel_main17h0123456789abcdefE", 0x1000, 0x20, STT_FUNC, 1),
+            ("__isr_common", 0x1020, 0, STT_NOTYPE, 1),
+            (".Ltmp0", 0x1008, 0, STT_NOTYPE, 1),
+            ("_ZN6kernel7STARTED17h0123456789abcdefE", 0x3000, 1, 1, 3), // STT_OBJECT
+            ("_ZN6kernel4data17h0123456789abcdefE", 0x3008, 0, STT_NOTYPE, 3),
+        ]
+    }
+
+    #[test]
+    fn test_collects_functions_only() {
+        let elf = build_elf(256, &kernel_symbols());
+        let symbols = function_symbols(&elf).unwrap();
+        let names: Vec<_> = symbols.iter().map(|s| s.name.as_str()).collect();
+        assert_eq!(names, ["kernel::kernel_main", "__isr_common"], "実行可能セクションの関数とラベルだけ");
+        assert_eq!(symbols[0].size, 0x20);
+    }
+
+    #[test]
+    fn test_embed_writes_table_into_section() {
+        let mut elf = build_elf(256, &kernel_symbols());
+        assert!(has_section(&elf));
+        let embedded = embed(&mut elf).unwrap();
+        assert_eq!(embedded.count, 2);
+        assert_eq!(embedded.capacity, 256);
+
+        let section = sections(&elf).unwrap().into_iter().find(|s| s.name == SECTION_NAME).unwrap();
+        let table = ksyms::SymbolTable::parse(&elf[section.file_range()]).expect("書き込んだ表が読める");
+        assert_eq!(table.lookup(0x1010).unwrap().to_string(), "kernel::kernel_main+0x10");
+        assert_eq!(table.lookup(0x1024).unwrap().to_string(), "__isr_common+0x4");
+
+        let again = elf.clone();
+        embed(&mut elf).unwrap();
+        assert_eq!(elf, again, "もう一度実行しても同じ");
+    }
+
+    #[test]
+    fn test_embed_errors() {
+        let mut small = build_elf(16, &kernel_symbols());
+        assert!(embed(&mut small).unwrap_err().contains("needs"), "入りきらなければエラー");
+        assert!(sections(b"not an elf").is_err());
+    }
+}